@@ -9,6 +9,9 @@
     .globl _start
 # 目前 _start 的功能：将预留的栈空间写入 $sp，然后跳转至 rust_main
 _start:
+    # 保存 SBI 传入的设备树地址
+    la t0, BOOT_DTB
+    sd a1, 0(t0)
     la sp, boot_stack_top
     call rust_main
 
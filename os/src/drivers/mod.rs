@@ -0,0 +1,72 @@
+//! Device drivers
+
+use crate::zircon_object::ZxResult;
+use alloc::{boxed::Box, format, string::String, sync::Arc, vec::Vec};
+use lazy_static::lazy_static;
+use spin::RwLock;
+
+pub mod virtio;
+
+/// A network card sending and receiving ethernet frames
+pub trait NetDevice: Send + Sync {
+    /// MAC address
+    fn mac(&self) -> [u8; 6];
+    /// maximum transmission unit, excluding the ethernet header
+    fn mtu(&self) -> usize;
+    /// send an ethernet frame
+    fn transmit(&self, frame: &[u8]) -> ZxResult;
+    /// take a received ethernet frame
+    fn receive(&self) -> Option<Vec<u8>>;
+    /// set the function called from the interrupt handler when frames may have arrived
+    fn set_rx_callback(&self, callback: Box<dyn Fn() + Send + Sync>);
+    /// interrupt number of the card
+    fn irq(&self) -> u32;
+}
+
+/// Size of a block of [`BlockDevice`]
+pub const BLOCK_SIZE: usize = 512;
+
+/// A disk read and written in blocks of [`BLOCK_SIZE`] bytes
+pub trait BlockDevice: Send + Sync {
+    /// number of blocks
+    fn num_blocks(&self) -> u64;
+    /// whether writes are rejected
+    fn read_only(&self) -> bool;
+    /// read blocks from `block_id`, the length of `buf` must be a multiple of the block size
+    fn read_blocks(&self, block_id: u64, buf: &mut [u8]) -> ZxResult;
+    /// write blocks from `block_id`, the length of `buf` must be a multiple of the block size
+    fn write_blocks(&self, block_id: u64, buf: &[u8]) -> ZxResult;
+    /// make the written blocks persistent
+    fn flush(&self) -> ZxResult;
+}
+
+lazy_static! {
+    static ref NET_DEVICES: RwLock<Vec<Arc<dyn NetDevice>>> = RwLock::new(Vec::new());
+    static ref BLOCK_DEVICES: RwLock<Vec<(String, Arc<dyn BlockDevice>)>> = RwLock::new(Vec::new());
+}
+
+fn add_net_device(device: Arc<dyn NetDevice>) {
+    NET_DEVICES.write().push(device);
+}
+
+/// Get all network cards found.
+pub fn net_devices() -> Vec<Arc<dyn NetDevice>> {
+    NET_DEVICES.read().clone()
+}
+
+/// Add a disk, named `vda`, `vdb`, ... in the order found.
+fn add_block_device(device: Arc<dyn BlockDevice>) {
+    let mut devices = BLOCK_DEVICES.write();
+    let name = format!("vd{}", (b'a' + devices.len() as u8) as char);
+    devices.push((name, device));
+}
+
+/// Get all disks found, with their names.
+pub fn block_devices() -> Vec<(String, Arc<dyn BlockDevice>)> {
+    BLOCK_DEVICES.read().clone()
+}
+
+/// Probe and initialize devices.
+pub fn init() {
+    virtio::init();
+}
@@ -0,0 +1,320 @@
+//! Virtio block device
+
+use super::queue::{Buffer, VirtQueue};
+use super::{DmaRegion, MmioTransport};
+use crate::drivers::{BlockDevice, BLOCK_SIZE};
+use crate::kernel_hal::InterruptManager;
+use crate::zircon_object::dev::BusTransactionInitiator;
+use crate::zircon_object::vm::PAGE_SIZE;
+use crate::zircon_object::{ZxError, ZxResult};
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use spin::Mutex;
+
+/// Feature bit: the device is read-only
+const VIRTIO_BLK_F_RO: u32 = 5;
+/// Feature bit: the device supports cache flush
+const VIRTIO_BLK_F_FLUSH: u32 = 9;
+
+const VIRTIO_BLK_T_IN: u32 = 0;
+const VIRTIO_BLK_T_OUT: u32 = 1;
+const VIRTIO_BLK_T_FLUSH: u32 = 4;
+
+const VIRTIO_BLK_S_OK: u8 = 0;
+const VIRTIO_BLK_S_IOERR: u8 = 1;
+
+const QUEUE_REQUEST: u16 = 0;
+/// Max descriptors of the request queue, rounded down to a power of two by
+/// [`VirtQueue`], so 42 requests can be in flight
+const QUEUE_SIZE: u16 = 128;
+/// Size of the request header, followed by the status byte
+const HEADER_LEN: usize = 16;
+/// Space reserved for the header and status of each request
+const HEADER_STRIDE: usize = 32;
+/// Max data of each request
+const DATA_LEN: usize = PAGE_SIZE;
+
+/// A virtio block device
+///
+/// Requests are queued in slots, each with its own header and a page of
+/// data, so several threads can have requests in flight at once. Completed
+/// slots are collected by the interrupt handler; a thread waiting for a slot
+/// or a completion sleeps until the next interrupt instead of polling.
+pub struct VirtioBlk {
+    transport: MmioTransport,
+    capacity: u64,
+    read_only: bool,
+    flush: bool,
+    inner: Mutex<BlkInner>,
+    _bti: Arc<BusTransactionInitiator>,
+}
+
+struct BlkInner {
+    queue: VirtQueue,
+    /// request headers and status bytes, one per slot
+    headers: DmaRegion,
+    /// data buffers, one page per slot
+    data: DmaRegion,
+    /// free slots
+    free: Vec<usize>,
+    /// completed slots and their status
+    done: BTreeMap<usize, u8>,
+    /// the device returned a bad used ring and was reset, requests fail
+    failed: bool,
+}
+
+impl VirtioBlk {
+    /// Initialize the device and enable its interrupt.
+    pub fn new(
+        transport: MmioTransport,
+        irq: u32,
+        bti: Arc<BusTransactionInitiator>,
+    ) -> ZxResult<Arc<Self>> {
+        let features = transport.begin_init((1 << VIRTIO_BLK_F_RO) | (1 << VIRTIO_BLK_F_FLUSH))?;
+        // capacity in 512-byte sectors, whatever the block size
+        let capacity = transport.config_u64(0);
+        let queue = VirtQueue::new(&transport, QUEUE_REQUEST, &bti, QUEUE_SIZE)?;
+        let slots = request_slots(queue.size());
+        if slots == 0 {
+            return Err(ZxError::NO_RESOURCES);
+        }
+        let headers = DmaRegion::new(&bti, HEADER_STRIDE * slots)?;
+        let data = DmaRegion::new(&bti, DATA_LEN * slots)?;
+        let blk = Arc::new(VirtioBlk {
+            transport,
+            capacity,
+            read_only: features & (1 << VIRTIO_BLK_F_RO) != 0,
+            flush: features & (1 << VIRTIO_BLK_F_FLUSH) != 0,
+            inner: Mutex::new(BlkInner {
+                queue,
+                headers,
+                data,
+                free: (0..slots).collect(),
+                done: BTreeMap::new(),
+                failed: false,
+            }),
+            _bti: bti,
+        });
+        blk.transport.finish_init();
+
+        let weak = Arc::downgrade(&blk);
+        let handle = Box::new(move || {
+            if let Some(blk) = Weak::upgrade(&weak) {
+                blk.handle_irq();
+            }
+        });
+        if InterruptManager::set_ioapic_handle(irq, handle).is_none() {
+            warn!("virtio-blk: irq {} is in use", irq);
+            return Err(ZxError::ALREADY_BOUND);
+        }
+        InterruptManager::enable(irq);
+        info!(
+            "virtio-blk: {} sectors{}",
+            capacity,
+            if blk.read_only { ", read-only" } else { "" }
+        );
+        Ok(blk)
+    }
+
+    fn handle_irq(&self) {
+        self.transport.ack_interrupt();
+        // the submitter collects the completions itself if it holds the lock
+        if let Some(mut inner) = self.inner.try_lock() {
+            // an error disables the device, the waiting threads see it
+            let _ = self.harvest(&mut inner);
+        }
+    }
+
+    /// Collect the completed requests.
+    ///
+    /// A bad used ring can't be recovered from: the device is reset and all
+    /// requests fail from then on.
+    fn harvest(&self, inner: &mut BlkInner) -> ZxResult {
+        if inner.failed {
+            return Err(ZxError::BAD_STATE);
+        }
+        if let Err(err) = inner.harvest() {
+            warn!("virtio-blk: bad used ring, disabling the device: {:?}", err);
+            self.transport.fail();
+            inner.failed = true;
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    /// Queue a request on a free slot, return the slot.
+    ///
+    /// For writes, `data` is copied to the slot before the request is queued.
+    fn submit(&self, type_: u32, sector: u64, data: &[u8], len: usize) -> ZxResult<usize> {
+        let mut inner = self.inner.lock();
+        let slot = loop {
+            self.harvest(&mut inner)?;
+            if let Some(slot) = inner.free.pop() {
+                break slot;
+            }
+            // all slots are in flight, wait for one of them to complete
+            drop(inner);
+            InterruptManager::wait();
+            inner = self.inner.lock();
+        };
+        if let Err(err) = Self::queue_request(&mut inner, slot, type_, sector, data, len) {
+            inner.free.push(slot);
+            return Err(err);
+        }
+        inner.queue.notify(&self.transport);
+        Ok(slot)
+    }
+
+    /// Fill the header and data of `slot` and add it to the queue.
+    fn queue_request(
+        inner: &mut BlkInner,
+        slot: usize,
+        type_: u32,
+        sector: u64,
+        data: &[u8],
+        len: usize,
+    ) -> ZxResult {
+        inner
+            .headers
+            .write(slot * HEADER_STRIDE, &request_header(type_, sector))?;
+        if type_ == VIRTIO_BLK_T_OUT {
+            inner.data.write(slot * DATA_LEN, data)?;
+        }
+        let mut bufs = Vec::with_capacity(3);
+        bufs.push(Buffer {
+            addr: inner.headers.addr(slot * HEADER_STRIDE),
+            len: HEADER_LEN,
+            device_writable: false,
+        });
+        if len > 0 {
+            bufs.push(Buffer {
+                addr: inner.data.addr(slot * DATA_LEN),
+                len,
+                device_writable: type_ == VIRTIO_BLK_T_IN,
+            });
+        }
+        bufs.push(Buffer {
+            addr: inner.headers.addr(slot * HEADER_STRIDE + HEADER_LEN),
+            len: 1,
+            device_writable: true,
+        });
+        inner.queue.add(&bufs, slot)
+    }
+
+    /// Wait for the request on `slot` to complete and free the slot.
+    ///
+    /// For reads, the data of the slot is copied to `buf`.
+    fn complete(&self, slot: usize, buf: &mut [u8]) -> ZxResult {
+        let mut inner = self.inner.lock();
+        let status = loop {
+            if let Err(err) = self.harvest(&mut inner) {
+                // the device was reset, so it no longer uses the slot
+                inner.done.remove(&slot);
+                inner.free.push(slot);
+                return Err(err);
+            }
+            if let Some(status) = inner.done.remove(&slot) {
+                break status;
+            }
+            // the interrupt is left pending until claimed, so it can't be
+            // missed between dropping the lock and waiting
+            drop(inner);
+            InterruptManager::wait();
+            inner = self.inner.lock();
+        };
+        let res = match status {
+            VIRTIO_BLK_S_OK => inner.data.read(slot * DATA_LEN, buf),
+            VIRTIO_BLK_S_IOERR => Err(ZxError::IO),
+            _ => Err(ZxError::NOT_SUPPORTED),
+        };
+        inner.free.push(slot);
+        res
+    }
+}
+
+/// Number of request slots of a queue with `queue_size` descriptors.
+///
+/// A request takes up to 3 descriptors: header, data and status.
+pub(crate) fn request_slots(queue_size: u16) -> usize {
+    queue_size as usize / 3
+}
+
+/// Build the header of a request, with the status byte following it.
+pub(crate) fn request_header(type_: u32, sector: u64) -> [u8; HEADER_STRIDE] {
+    let mut header = [0u8; HEADER_STRIDE];
+    header[0..4].copy_from_slice(&type_.to_le_bytes());
+    header[8..16].copy_from_slice(&sector.to_le_bytes());
+    // the device overwrites the status on completion
+    header[HEADER_LEN] = 0xff;
+    header
+}
+
+/// Check that `len` bytes from `block_id` are whole blocks within `capacity`.
+pub(crate) fn check_range(capacity: u64, block_id: u64, len: usize) -> ZxResult {
+    if len % BLOCK_SIZE != 0 {
+        return Err(ZxError::INVALID_ARGS);
+    }
+    let end = block_id
+        .checked_add((len / BLOCK_SIZE) as u64)
+        .ok_or(ZxError::OUT_OF_RANGE)?;
+    if end > capacity {
+        return Err(ZxError::OUT_OF_RANGE);
+    }
+    Ok(())
+}
+
+impl BlkInner {
+    /// Record the status of the requests the device is done with.
+    fn harvest(&mut self) -> ZxResult {
+        while let Some((slot, _)) = self.queue.pop_used()? {
+            let mut status = [0u8; 1];
+            self.headers
+                .read(slot * HEADER_STRIDE + HEADER_LEN, &mut status)?;
+            self.done.insert(slot, status[0]);
+        }
+        Ok(())
+    }
+}
+
+impl BlockDevice for VirtioBlk {
+    fn num_blocks(&self) -> u64 {
+        self.capacity
+    }
+
+    fn read_only(&self) -> bool {
+        self.read_only
+    }
+
+    fn read_blocks(&self, block_id: u64, buf: &mut [u8]) -> ZxResult {
+        check_range(self.capacity, block_id, buf.len())?;
+        for (i, chunk) in buf.chunks_mut(DATA_LEN).enumerate() {
+            let sector = block_id + (i * DATA_LEN / BLOCK_SIZE) as u64;
+            let slot = self.submit(VIRTIO_BLK_T_IN, sector, &[], chunk.len())?;
+            self.complete(slot, chunk)?;
+        }
+        Ok(())
+    }
+
+    fn write_blocks(&self, block_id: u64, buf: &[u8]) -> ZxResult {
+        if self.read_only {
+            return Err(ZxError::ACCESS_DENIED);
+        }
+        check_range(self.capacity, block_id, buf.len())?;
+        for (i, chunk) in buf.chunks(DATA_LEN).enumerate() {
+            let sector = block_id + (i * DATA_LEN / BLOCK_SIZE) as u64;
+            let slot = self.submit(VIRTIO_BLK_T_OUT, sector, chunk, chunk.len())?;
+            self.complete(slot, &mut [])?;
+        }
+        Ok(())
+    }
+
+    fn flush(&self) -> ZxResult {
+        if !self.flush {
+            return Ok(());
+        }
+        let slot = self.submit(VIRTIO_BLK_T_FLUSH, 0, &[], 0)?;
+        self.complete(slot, &mut [])
+    }
+}
@@ -0,0 +1,304 @@
+//! Virtio devices over the MMIO transport
+//!
+//! Both the legacy (version 1) and the modern (version 2) register layouts
+//! are supported. Queue memory and buffers are contiguous VMOs pinned through
+//! a [`BusTransactionInitiator`], the same way a userspace driver gets the
+//! device addresses of its buffers.
+
+use crate::zircon_object::dev::{BusTransactionInitiator, Iommu, IommuPerms, PinnedMemoryToken};
+use crate::zircon_object::vm::{pages, VmObject, PAGE_SIZE, PAGE_SIZE_LOG2};
+use crate::zircon_object::{ZxError, ZxResult};
+use alloc::sync::Arc;
+use bitflags::bitflags;
+use core::ptr::{read_volatile, write_volatile};
+
+pub use self::blk::VirtioBlk;
+pub(crate) use self::blk::{check_range, request_header, request_slots};
+pub use self::net::VirtioNet;
+pub use self::queue::VirtQueue;
+
+mod blk;
+mod net;
+mod queue;
+
+/// MMIO base of the first virtio slot on QEMU virt
+const VIRTIO_MMIO_BASE: usize = 0x1000_1000;
+/// Size of each virtio slot
+const VIRTIO_MMIO_SIZE: usize = 0x1000;
+/// Number of virtio slots
+const VIRTIO_MMIO_COUNT: usize = 8;
+/// IRQ of the first virtio slot
+const VIRTIO_IRQ_BASE: u32 = 1;
+
+/// Magic value of the MMIO header, "virt"
+const MAGIC: u32 = 0x7472_6976;
+
+/// Device type
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum DeviceType {
+    /// Network card
+    Network = 1,
+    /// Block device
+    Block = 2,
+}
+
+bitflags! {
+    /// Device status
+    struct DeviceStatus: u32 {
+        const ACKNOWLEDGE = 1;
+        const DRIVER = 2;
+        const DRIVER_OK = 4;
+        const FEATURES_OK = 8;
+        const FAILED = 128;
+    }
+}
+
+/// Feature bit: the device conforms to virtio 1.0
+pub const VIRTIO_F_VERSION_1: u32 = 32;
+
+/// Register offsets of the MMIO header
+mod reg {
+    pub const MAGIC: usize = 0x000;
+    pub const VERSION: usize = 0x004;
+    pub const DEVICE_ID: usize = 0x008;
+    pub const DEVICE_FEATURES: usize = 0x010;
+    pub const DEVICE_FEATURES_SEL: usize = 0x014;
+    pub const DRIVER_FEATURES: usize = 0x020;
+    pub const DRIVER_FEATURES_SEL: usize = 0x024;
+    pub const GUEST_PAGE_SIZE: usize = 0x028;
+    pub const QUEUE_SEL: usize = 0x030;
+    pub const QUEUE_NUM_MAX: usize = 0x034;
+    pub const QUEUE_NUM: usize = 0x038;
+    pub const QUEUE_ALIGN: usize = 0x03c;
+    pub const QUEUE_PFN: usize = 0x040;
+    pub const QUEUE_READY: usize = 0x044;
+    pub const QUEUE_NOTIFY: usize = 0x050;
+    pub const INTERRUPT_STATUS: usize = 0x060;
+    pub const INTERRUPT_ACK: usize = 0x064;
+    pub const STATUS: usize = 0x070;
+    pub const QUEUE_DESC_LOW: usize = 0x080;
+    pub const QUEUE_DESC_HIGH: usize = 0x084;
+    pub const QUEUE_DRIVER_LOW: usize = 0x090;
+    pub const QUEUE_DRIVER_HIGH: usize = 0x094;
+    pub const QUEUE_DEVICE_LOW: usize = 0x0a0;
+    pub const QUEUE_DEVICE_HIGH: usize = 0x0a4;
+    pub const CONFIG: usize = 0x100;
+}
+
+/// Registers of a virtio-mmio device
+pub struct MmioTransport {
+    /// identity-mapped base address
+    base: usize,
+    version: u32,
+}
+
+impl MmioTransport {
+    /// Probe the slot at `base`, return `None` if no device is there.
+    fn probe(base: usize) -> Option<(Self, DeviceType)> {
+        let transport = MmioTransport { base, version: 0 };
+        if transport.read(reg::MAGIC) != MAGIC {
+            return None;
+        }
+        let version = transport.read(reg::VERSION);
+        if version != 1 && version != 2 {
+            warn!("virtio: unknown version {} at {:#x}", version, base);
+            return None;
+        }
+        let device_type = match transport.read(reg::DEVICE_ID) {
+            1 => DeviceType::Network,
+            2 => DeviceType::Block,
+            // no device in this slot
+            0 => return None,
+            id => {
+                info!("virtio: unsupported device type {} at {:#x}", id, base);
+                return None;
+            }
+        };
+        Some((MmioTransport { base, version }, device_type))
+    }
+
+    fn read(&self, offset: usize) -> u32 {
+        unsafe { read_volatile((self.base + offset) as *const u32) }
+    }
+
+    fn write(&self, offset: usize, value: u32) {
+        unsafe { write_volatile((self.base + offset) as *mut u32, value) }
+    }
+
+    /// Whether the device uses the legacy interface
+    pub fn is_legacy(&self) -> bool {
+        self.version == 1
+    }
+
+    /// Reset the device and negotiate features.
+    ///
+    /// `supported` is the set of features the driver understands, the
+    /// accepted subset is returned.
+    pub fn begin_init(&self, supported: u64) -> ZxResult<u64> {
+        self.write(reg::STATUS, 0);
+        self.set_status(DeviceStatus::ACKNOWLEDGE | DeviceStatus::DRIVER);
+        self.write(reg::DEVICE_FEATURES_SEL, 0);
+        let mut features = self.read(reg::DEVICE_FEATURES) as u64;
+        self.write(reg::DEVICE_FEATURES_SEL, 1);
+        features |= (self.read(reg::DEVICE_FEATURES) as u64) << 32;
+        let mut accepted = features & supported;
+        if self.is_legacy() {
+            accepted &= !(1 << VIRTIO_F_VERSION_1);
+        } else {
+            accepted |= features & (1 << VIRTIO_F_VERSION_1);
+        }
+        self.write(reg::DRIVER_FEATURES_SEL, 0);
+        self.write(reg::DRIVER_FEATURES, accepted as u32);
+        self.write(reg::DRIVER_FEATURES_SEL, 1);
+        self.write(reg::DRIVER_FEATURES, (accepted >> 32) as u32);
+        if self.is_legacy() {
+            self.write(reg::GUEST_PAGE_SIZE, PAGE_SIZE as u32);
+        } else {
+            self.set_status(DeviceStatus::FEATURES_OK);
+            if !self.status().contains(DeviceStatus::FEATURES_OK) {
+                self.set_status(DeviceStatus::FAILED);
+                return Err(ZxError::NOT_SUPPORTED);
+            }
+        }
+        Ok(accepted)
+    }
+
+    /// Tell the device the driver is ready.
+    pub fn finish_init(&self) {
+        self.set_status(DeviceStatus::DRIVER_OK);
+    }
+
+    /// Reset the device after an unrecoverable error and mark it as failed,
+    /// so it stops using the queues.
+    pub fn fail(&self) {
+        self.write(reg::STATUS, 0);
+        self.set_status(DeviceStatus::FAILED);
+    }
+
+    fn status(&self) -> DeviceStatus {
+        DeviceStatus::from_bits_truncate(self.read(reg::STATUS))
+    }
+
+    fn set_status(&self, status: DeviceStatus) {
+        self.write(reg::STATUS, (self.status() | status).bits());
+    }
+
+    /// Max size of queue `idx`, 0 if the queue is not available.
+    pub fn max_queue_size(&self, idx: u16) -> u16 {
+        self.write(reg::QUEUE_SEL, idx as u32);
+        self.read(reg::QUEUE_NUM_MAX) as u16
+    }
+
+    /// Tell the device where the rings of queue `idx` are.
+    pub fn setup_queue(&self, idx: u16, size: u16, desc: usize, driver: usize, device: usize) {
+        self.write(reg::QUEUE_SEL, idx as u32);
+        self.write(reg::QUEUE_NUM, size as u32);
+        if self.is_legacy() {
+            // the legacy layout is fixed: descriptors, driver ring, then the
+            // device ring at the next page
+            self.write(reg::QUEUE_ALIGN, PAGE_SIZE as u32);
+            self.write(reg::QUEUE_PFN, (desc / PAGE_SIZE) as u32);
+        } else {
+            self.write(reg::QUEUE_DESC_LOW, desc as u32);
+            self.write(reg::QUEUE_DESC_HIGH, (desc as u64 >> 32) as u32);
+            self.write(reg::QUEUE_DRIVER_LOW, driver as u32);
+            self.write(reg::QUEUE_DRIVER_HIGH, (driver as u64 >> 32) as u32);
+            self.write(reg::QUEUE_DEVICE_LOW, device as u32);
+            self.write(reg::QUEUE_DEVICE_HIGH, (device as u64 >> 32) as u32);
+            self.write(reg::QUEUE_READY, 1);
+        }
+    }
+
+    /// Notify the device of new buffers in queue `idx`.
+    pub fn notify(&self, idx: u16) {
+        self.write(reg::QUEUE_NOTIFY, idx as u32);
+    }
+
+    /// Acknowledge the interrupt, return the interrupt status.
+    pub fn ack_interrupt(&self) -> u32 {
+        let status = self.read(reg::INTERRUPT_STATUS);
+        self.write(reg::INTERRUPT_ACK, status);
+        status
+    }
+
+    /// Read a byte of the device-specific configuration space.
+    pub fn config_u8(&self, offset: usize) -> u8 {
+        unsafe { read_volatile((self.base + reg::CONFIG + offset) as *const u8) }
+    }
+
+    /// Read a little-endian `u64` of the device-specific configuration space.
+    pub fn config_u64(&self, offset: usize) -> u64 {
+        let mut bytes = [0u8; 8];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = self.config_u8(offset + i);
+        }
+        u64::from_le_bytes(bytes)
+    }
+}
+
+/// A contiguous buffer pinned for DMA
+pub struct DmaRegion {
+    vmo: Arc<VmObject>,
+    _pmt: Arc<PinnedMemoryToken>,
+    /// device address of the first byte
+    addr: usize,
+}
+
+impl DmaRegion {
+    /// Allocate a zeroed region of at least `size` bytes and pin it to `bti`.
+    pub fn new(bti: &Arc<BusTransactionInitiator>, size: usize) -> ZxResult<Self> {
+        let size = pages(size) * PAGE_SIZE;
+        let vmo = VmObject::new_contiguous(size, PAGE_SIZE_LOG2)?;
+        let perms = IommuPerms::PERM_READ | IommuPerms::PERM_WRITE;
+        let pmt = bti.pin(vmo.clone(), 0, size, perms)?;
+        let addr = pmt.encode_addrs(false, true)?[0];
+        Ok(DmaRegion {
+            vmo,
+            _pmt: pmt,
+            addr,
+        })
+    }
+
+    /// Device address of `offset` in the region
+    pub fn addr(&self, offset: usize) -> usize {
+        self.addr + offset
+    }
+
+    /// Read from the region at `offset`.
+    pub fn read(&self, offset: usize, buf: &mut [u8]) -> ZxResult {
+        self.vmo.read(offset, buf)
+    }
+
+    /// Write to the region at `offset`.
+    pub fn write(&self, offset: usize, buf: &[u8]) -> ZxResult {
+        self.vmo.write(offset, buf)
+    }
+}
+
+/// Create a BTI for a device, backed by the dummy IOMMU.
+fn create_bti(bti_id: u64) -> Arc<BusTransactionInitiator> {
+    BusTransactionInitiator::create(Iommu::create(), bti_id)
+}
+
+/// Probe the virtio-mmio slots of QEMU virt and initialize the devices found.
+pub fn init() {
+    for i in 0..VIRTIO_MMIO_COUNT {
+        let base = VIRTIO_MMIO_BASE + i * VIRTIO_MMIO_SIZE;
+        let irq = VIRTIO_IRQ_BASE + i as u32;
+        let (transport, device_type) = match MmioTransport::probe(base) {
+            Some(device) => device,
+            None => continue,
+        };
+        info!("virtio: found {:?} device at {:#x}, irq {}", device_type, base, irq);
+        match device_type {
+            DeviceType::Network => match VirtioNet::new(transport, irq, create_bti(i as u64)) {
+                Ok(net) => super::add_net_device(net),
+                Err(err) => warn!("virtio: failed to init network device: {:?}", err),
+            },
+            DeviceType::Block => match VirtioBlk::new(transport, irq, create_bti(i as u64)) {
+                Ok(blk) => super::add_block_device(blk),
+                Err(err) => warn!("virtio: failed to init block device: {:?}", err),
+            },
+        }
+    }
+}
@@ -0,0 +1,245 @@
+//! Virtio network device
+
+use super::queue::{Buffer, VirtQueue};
+use super::{DmaRegion, MmioTransport, VIRTIO_F_VERSION_1};
+use crate::drivers::NetDevice;
+use crate::kernel_hal::InterruptManager;
+use crate::zircon_object::dev::BusTransactionInitiator;
+use crate::zircon_object::{ZxError, ZxResult};
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use spin::Mutex;
+
+/// Feature bit: the device has a MAC address in its configuration space
+const VIRTIO_NET_F_MAC: u32 = 5;
+
+const QUEUE_RECEIVE: u16 = 0;
+const QUEUE_TRANSMIT: u16 = 1;
+/// Max descriptors of each queue
+const QUEUE_SIZE: u16 = 16;
+/// Size of each RX/TX buffer, enough for a header and a full frame
+const BUF_SIZE: usize = 2048;
+/// Max size of an ethernet frame without FCS
+const MAX_FRAME_LEN: usize = 1514;
+/// Max frames waiting for a free transmit buffer
+const TX_PENDING_MAX: usize = 64;
+
+/// A virtio network card
+pub struct VirtioNet {
+    transport: MmioTransport,
+    irq: u32,
+    mac: [u8; 6],
+    /// size of `virtio_net_hdr` in front of each frame
+    header_len: usize,
+    inner: Mutex<NetInner>,
+    rx_callback: Mutex<Option<Box<dyn Fn() + Send + Sync>>>,
+    _bti: Arc<BusTransactionInitiator>,
+}
+
+struct NetInner {
+    rx: VirtQueue,
+    tx: VirtQueue,
+    /// receive buffers, one per RX descriptor
+    rx_buf: DmaRegion,
+    /// transmit buffers, one per TX descriptor
+    tx_buf: DmaRegion,
+    /// free transmit buffers
+    tx_free: Vec<usize>,
+    /// frames waiting for a free transmit buffer, in order
+    tx_pending: VecDeque<Vec<u8>>,
+}
+
+impl VirtioNet {
+    /// Initialize the device and enable its interrupt.
+    pub fn new(
+        transport: MmioTransport,
+        irq: u32,
+        bti: Arc<BusTransactionInitiator>,
+    ) -> ZxResult<Arc<Self>> {
+        let features = transport.begin_init(1 << VIRTIO_NET_F_MAC)?;
+        // the legacy header has no `num_buffers` field
+        let header_len = if features & (1 << VIRTIO_F_VERSION_1) != 0 {
+            12
+        } else {
+            10
+        };
+        let mut mac = [0u8; 6];
+        if features & (1 << VIRTIO_NET_F_MAC) != 0 {
+            for (i, byte) in mac.iter_mut().enumerate() {
+                *byte = transport.config_u8(i);
+            }
+        } else {
+            // locally administered address
+            mac = [0x52, 0x54, 0x00, 0x12, 0x34, 0x56];
+        }
+        let mut rx = VirtQueue::new(&transport, QUEUE_RECEIVE, &bti, QUEUE_SIZE)?;
+        let tx = VirtQueue::new(&transport, QUEUE_TRANSMIT, &bti, QUEUE_SIZE)?;
+        let rx_buf = DmaRegion::new(&bti, BUF_SIZE * rx.size() as usize)?;
+        let tx_buf = DmaRegion::new(&bti, BUF_SIZE * tx.size() as usize)?;
+        for i in 0..rx.size() as usize {
+            rx.add(&[rx_buffer(&rx_buf, i)], i)?;
+        }
+        let tx_free = (0..tx.size() as usize).collect();
+        let net = Arc::new(VirtioNet {
+            transport,
+            irq,
+            mac,
+            header_len,
+            inner: Mutex::new(NetInner {
+                rx,
+                tx,
+                rx_buf,
+                tx_buf,
+                tx_free,
+                tx_pending: VecDeque::new(),
+            }),
+            rx_callback: Mutex::new(None),
+            _bti: bti,
+        });
+        net.transport.finish_init();
+        net.inner.lock().rx.notify(&net.transport);
+
+        let weak = Arc::downgrade(&net);
+        let handle = Box::new(move || {
+            if let Some(net) = Weak::upgrade(&weak) {
+                net.handle_irq();
+            }
+        });
+        if InterruptManager::set_ioapic_handle(irq, handle).is_none() {
+            warn!("virtio-net: irq {} is in use", irq);
+            return Err(ZxError::ALREADY_BOUND);
+        }
+        InterruptManager::enable(irq);
+        info!("virtio-net: mac {:02x?}", net.mac);
+        Ok(net)
+    }
+
+    fn handle_irq(&self) {
+        self.transport.ack_interrupt();
+        // the device may be done with some transmit buffers
+        if let Err(err) = self.flush_tx(&mut self.inner.lock()) {
+            warn!("virtio-net: failed to transmit: {:?}", err);
+        }
+        if let Some(callback) = self.rx_callback.lock().as_ref() {
+            callback();
+        }
+    }
+
+    /// Reclaim the transmit buffers the device is done with.
+    fn reclaim_tx(inner: &mut NetInner) -> ZxResult {
+        while let Some((slot, _)) = inner.tx.pop_used()? {
+            inner.tx_free.push(slot);
+        }
+        Ok(())
+    }
+
+    /// Hand the pending frames to the device, as far as buffers are free.
+    fn flush_tx(&self, inner: &mut NetInner) -> ZxResult {
+        Self::reclaim_tx(inner)?;
+        let mut added = false;
+        let result = loop {
+            if inner.tx_pending.is_empty() {
+                break Ok(());
+            }
+            let slot = match inner.tx_free.pop() {
+                Some(slot) => slot,
+                None => break Ok(()),
+            };
+            let frame = inner.tx_pending.pop_front().unwrap();
+            if let Err(err) = self.add_tx(inner, slot, &frame) {
+                inner.tx_free.push(slot);
+                inner.tx_pending.push_front(frame);
+                break Err(err);
+            }
+            added = true;
+        };
+        if added {
+            inner.tx.notify(&self.transport);
+        }
+        result
+    }
+
+    /// Copy `frame` to the transmit buffer `slot` and add it to the queue.
+    fn add_tx(&self, inner: &mut NetInner, slot: usize, frame: &[u8]) -> ZxResult {
+        let offset = slot * BUF_SIZE;
+        // an all-zero header: no checksum offload, no segmentation
+        let mut buf = vec![0u8; self.header_len + frame.len()];
+        buf[self.header_len..].copy_from_slice(frame);
+        inner.tx_buf.write(offset, &buf)?;
+        let desc = Buffer {
+            addr: inner.tx_buf.addr(offset),
+            len: buf.len(),
+            device_writable: false,
+        };
+        inner.tx.add(&[desc], slot)
+    }
+}
+
+fn rx_buffer(region: &DmaRegion, slot: usize) -> Buffer {
+    Buffer {
+        addr: region.addr(slot * BUF_SIZE),
+        len: BUF_SIZE,
+        device_writable: true,
+    }
+}
+
+impl NetDevice for VirtioNet {
+    fn mac(&self) -> [u8; 6] {
+        self.mac
+    }
+
+    fn mtu(&self) -> usize {
+        1500
+    }
+
+    fn transmit(&self, frame: &[u8]) -> ZxResult {
+        if frame.len() > MAX_FRAME_LEN {
+            return Err(ZxError::INVALID_ARGS);
+        }
+        let mut inner = self.inner.lock();
+        // frames wait for buffers the device gives back, behind the ones
+        // already waiting
+        if inner.tx_pending.len() >= TX_PENDING_MAX {
+            self.flush_tx(&mut inner)?;
+            if inner.tx_pending.len() >= TX_PENDING_MAX {
+                return Err(ZxError::SHOULD_WAIT);
+            }
+        }
+        inner.tx_pending.push_back(frame.to_vec());
+        self.flush_tx(&mut inner)
+    }
+
+    fn receive(&self) -> Option<Vec<u8>> {
+        let mut inner = self.inner.lock();
+        let inner = &mut *inner;
+        let (slot, len) = match inner.rx.pop_used() {
+            Ok(Some(used)) => used,
+            Ok(None) => return None,
+            Err(err) => {
+                warn!("virtio-net: bad used ring: {:?}", err);
+                return None;
+            }
+        };
+        let len = len.min(BUF_SIZE).max(self.header_len);
+        let mut frame = vec![0u8; len - self.header_len];
+        let read = inner
+            .rx_buf
+            .read(slot * BUF_SIZE + self.header_len, &mut frame);
+        // give the buffer back to the device
+        if let Err(err) = inner.rx.add(&[rx_buffer(&inner.rx_buf, slot)], slot) {
+            warn!("virtio-net: failed to recycle rx buffer: {:?}", err);
+        }
+        inner.rx.notify(&self.transport);
+        read.ok().map(|_| frame)
+    }
+
+    fn set_rx_callback(&self, callback: Box<dyn Fn() + Send + Sync>) {
+        *self.rx_callback.lock() = Some(callback);
+    }
+
+    fn irq(&self) -> u32 {
+        self.irq
+    }
+}
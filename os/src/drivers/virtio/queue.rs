@@ -0,0 +1,194 @@
+//! Split virtqueue
+
+use super::{DmaRegion, MmioTransport};
+use crate::zircon_object::dev::BusTransactionInitiator;
+use crate::zircon_object::vm::PAGE_SIZE;
+use crate::zircon_object::{ZxError, ZxResult};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use bitflags::bitflags;
+use core::sync::atomic::{fence, Ordering};
+
+/// Size of a descriptor
+const DESC_SIZE: usize = 16;
+/// Size of an element of the device ring
+const USED_ELEM_SIZE: usize = 8;
+
+bitflags! {
+    struct DescFlags: u16 {
+        const NEXT = 1;
+        const WRITE = 2;
+    }
+}
+
+/// A buffer in a descriptor chain
+#[derive(Debug, Copy, Clone)]
+pub struct Buffer {
+    /// device address
+    pub addr: usize,
+    /// length in bytes
+    pub len: usize,
+    /// written by the device
+    pub device_writable: bool,
+}
+
+/// A split virtqueue
+///
+/// The descriptor table, driver ring and device ring share one DMA region
+/// laid out as the legacy interface requires.
+pub struct VirtQueue {
+    idx: u16,
+    size: u16,
+    region: DmaRegion,
+    /// offset of the driver (available) ring
+    avail_offset: usize,
+    /// offset of the device (used) ring
+    used_offset: usize,
+    /// free descriptors
+    free: Vec<u16>,
+    /// next descriptor of each chain, shadowing the device-visible table
+    next: Vec<u16>,
+    /// caller token of each chain in flight, by head descriptor
+    tokens: Vec<Option<usize>>,
+    avail_idx: u16,
+    last_used_idx: u16,
+}
+
+impl VirtQueue {
+    /// Create queue `idx` with at most `max_size` descriptors and hand it to the device.
+    pub fn new(
+        transport: &MmioTransport,
+        idx: u16,
+        bti: &Arc<BusTransactionInitiator>,
+        max_size: u16,
+    ) -> ZxResult<Self> {
+        let device_max = transport.max_queue_size(idx);
+        if device_max == 0 {
+            return Err(ZxError::NOT_FOUND);
+        }
+        // queue size must be a power of 2
+        let mut size = max_size.min(device_max);
+        while !size.is_power_of_two() {
+            size &= size - 1;
+        }
+        let n = size as usize;
+        let avail_offset = DESC_SIZE * n;
+        let used_offset = align_up(avail_offset + 6 + 2 * n);
+        let total = used_offset + align_up(6 + USED_ELEM_SIZE * n);
+        let region = DmaRegion::new(bti, total)?;
+        transport.setup_queue(
+            idx,
+            size,
+            region.addr(0),
+            region.addr(avail_offset),
+            region.addr(used_offset),
+        );
+        Ok(VirtQueue {
+            idx,
+            size,
+            region,
+            avail_offset,
+            used_offset,
+            free: (0..size).rev().collect(),
+            next: vec![0; n],
+            tokens: vec![None; n],
+            avail_idx: 0,
+            last_used_idx: 0,
+        })
+    }
+
+    /// Number of descriptors
+    pub fn size(&self) -> u16 {
+        self.size
+    }
+
+    /// Number of free descriptors
+    pub fn available(&self) -> usize {
+        self.free.len()
+    }
+
+    /// Add a descriptor chain of `bufs` to the driver ring.
+    ///
+    /// `token` is returned by [`pop_used`](Self::pop_used) when the device is
+    /// done with the chain. The device is not notified.
+    pub fn add(&mut self, bufs: &[Buffer], token: usize) -> ZxResult {
+        if bufs.is_empty() {
+            return Err(ZxError::INVALID_ARGS);
+        }
+        if self.free.len() < bufs.len() {
+            return Err(ZxError::SHOULD_WAIT);
+        }
+        let ids: Vec<u16> = (0..bufs.len()).map(|_| self.free.pop().unwrap()).collect();
+        for (i, buf) in bufs.iter().enumerate() {
+            let mut flags = DescFlags::empty();
+            if buf.device_writable {
+                flags |= DescFlags::WRITE;
+            }
+            let next = ids.get(i + 1).cloned().unwrap_or(0);
+            if i + 1 < ids.len() {
+                flags |= DescFlags::NEXT;
+            }
+            self.next[ids[i] as usize] = next;
+            let mut desc = [0u8; DESC_SIZE];
+            desc[0..8].copy_from_slice(&(buf.addr as u64).to_le_bytes());
+            desc[8..12].copy_from_slice(&(buf.len as u32).to_le_bytes());
+            desc[12..14].copy_from_slice(&flags.bits().to_le_bytes());
+            desc[14..16].copy_from_slice(&next.to_le_bytes());
+            self.region.write(ids[i] as usize * DESC_SIZE, &desc)?;
+        }
+        let head = ids[0];
+        self.tokens[head as usize] = Some(token);
+        let slot = (self.avail_idx % self.size) as usize;
+        self.region
+            .write(self.avail_offset + 4 + 2 * slot, &head.to_le_bytes())?;
+        // the ring entry must be visible before the index
+        fence(Ordering::SeqCst);
+        self.avail_idx = self.avail_idx.wrapping_add(1);
+        self.region
+            .write(self.avail_offset + 2, &self.avail_idx.to_le_bytes())?;
+        Ok(())
+    }
+
+    /// Notify the device of new buffers.
+    pub fn notify(&self, transport: &MmioTransport) {
+        fence(Ordering::SeqCst);
+        transport.notify(self.idx);
+    }
+
+    /// Take a chain the device is done with, return its token and the
+    /// number of bytes written by the device.
+    pub fn pop_used(&mut self) -> ZxResult<Option<(usize, usize)>> {
+        let mut idx = [0u8; 2];
+        self.region.read(self.used_offset + 2, &mut idx)?;
+        if u16::from_le_bytes(idx) == self.last_used_idx {
+            return Ok(None);
+        }
+        // read the element after the index
+        fence(Ordering::SeqCst);
+        let slot = (self.last_used_idx % self.size) as usize;
+        let mut elem = [0u8; USED_ELEM_SIZE];
+        self.region
+            .read(self.used_offset + 4 + USED_ELEM_SIZE * slot, &mut elem)?;
+        self.last_used_idx = self.last_used_idx.wrapping_add(1);
+        let head = u32::from_le_bytes([elem[0], elem[1], elem[2], elem[3]]) as u16;
+        let len = u32::from_le_bytes([elem[4], elem[5], elem[6], elem[7]]) as usize;
+        let token = self.tokens[head as usize].take().ok_or(ZxError::IO)?;
+        // free the chain
+        let mut id = head;
+        loop {
+            self.free.push(id);
+            let mut flags = [0u8; 2];
+            self.region.read(id as usize * DESC_SIZE + 12, &mut flags)?;
+            let flags = DescFlags::from_bits_truncate(u16::from_le_bytes(flags));
+            if !flags.contains(DescFlags::NEXT) {
+                break;
+            }
+            id = self.next[id as usize];
+        }
+        Ok(Some((token, len)))
+    }
+}
+
+fn align_up(size: usize) -> usize {
+    (size + PAGE_SIZE - 1) & !(PAGE_SIZE - 1)
+}
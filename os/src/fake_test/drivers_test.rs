@@ -0,0 +1,100 @@
+use crate::drivers::virtio::{check_range, request_header, request_slots};
+use crate::drivers::{BlockDevice, BLOCK_SIZE};
+use crate::linux_object::fs::BlockDev;
+use crate::zircon_object::{ZxError, ZxResult};
+use crate::{print, println};
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use rcore_fs::dev::Device;
+use spin::Mutex;
+
+/// A disk in memory.
+struct RamDisk {
+    data: Mutex<Vec<u8>>,
+    read_only: bool,
+}
+
+impl RamDisk {
+    fn new(blocks: usize, read_only: bool) -> Self {
+        RamDisk {
+            data: Mutex::new(vec![0; blocks * BLOCK_SIZE]),
+            read_only,
+        }
+    }
+}
+
+impl BlockDevice for RamDisk {
+    fn num_blocks(&self) -> u64 {
+        (self.data.lock().len() / BLOCK_SIZE) as u64
+    }
+
+    fn read_only(&self) -> bool {
+        self.read_only
+    }
+
+    fn read_blocks(&self, block_id: u64, buf: &mut [u8]) -> ZxResult {
+        check_range(self.num_blocks(), block_id, buf.len())?;
+        let begin = block_id as usize * BLOCK_SIZE;
+        buf.copy_from_slice(&self.data.lock()[begin..begin + buf.len()]);
+        Ok(())
+    }
+
+    fn write_blocks(&self, block_id: u64, buf: &[u8]) -> ZxResult {
+        check_range(self.num_blocks(), block_id, buf.len())?;
+        let begin = block_id as usize * BLOCK_SIZE;
+        self.data.lock()[begin..begin + buf.len()].copy_from_slice(buf);
+        Ok(())
+    }
+
+    fn flush(&self) -> ZxResult {
+        Ok(())
+    }
+}
+
+pub fn virtio_blk_test() {
+    // the queue is rounded down to 128 descriptors
+    assert_eq!(request_slots(128), 42);
+    assert_eq!(request_slots(2), 0);
+
+    let header = request_header(1, 0x1234);
+    assert_eq!(header[0..4], 1u32.to_le_bytes());
+    assert_eq!(header[4..8], [0; 4]);
+    assert_eq!(header[8..16], 0x1234u64.to_le_bytes());
+    assert_eq!(header[16], 0xff);
+
+    assert!(check_range(4, 0, 4 * BLOCK_SIZE).is_ok());
+    assert!(check_range(4, 4, 0).is_ok());
+    assert_eq!(check_range(4, 0, 100), Err(ZxError::INVALID_ARGS));
+    assert_eq!(
+        check_range(4, 3, 2 * BLOCK_SIZE),
+        Err(ZxError::OUT_OF_RANGE)
+    );
+    assert_eq!(
+        check_range(4, u64::MAX, BLOCK_SIZE),
+        Err(ZxError::OUT_OF_RANGE)
+    );
+    println!("virtio_blk_test pass");
+}
+
+pub fn block_dev_test() {
+    let dev = BlockDev::new(Arc::new(RamDisk::new(4, false)), 0);
+    // a write across a block boundary keeps the rest of both blocks
+    let data: Vec<u8> = (0..600).map(|i| i as u8).collect();
+    assert_eq!(dev.write_at(500, &data).unwrap(), 600);
+    let mut buf = vec![0xaa; 4 * BLOCK_SIZE];
+    assert_eq!(dev.read_at(0, &mut buf).unwrap(), 4 * BLOCK_SIZE);
+    assert!(buf[..500].iter().all(|&b| b == 0));
+    assert_eq!(buf[500..1100], data[..]);
+    assert!(buf[1100..].iter().all(|&b| b == 0));
+
+    // accesses are cut at the end of the disk
+    let mut buf = [0u8; 16];
+    assert_eq!(dev.read_at(4 * BLOCK_SIZE - 8, &mut buf).unwrap(), 8);
+    assert_eq!(dev.read_at(4 * BLOCK_SIZE, &mut buf).unwrap(), 0);
+    assert_eq!(dev.write_at(4 * BLOCK_SIZE - 4, &buf).unwrap(), 4);
+
+    let dev = BlockDev::new(Arc::new(RamDisk::new(1, true)), 1);
+    assert!(dev.write_at(0, &[1]).is_err());
+    println!("block_dev_test pass");
+}
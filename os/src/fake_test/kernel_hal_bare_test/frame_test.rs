@@ -1,4 +1,5 @@
 use crate::kernel_hal_bare::Frame;
+use crate::kernel_hal::{frame_stats, PhysFrame, PAGE_SIZE};
 use alloc::vec::Vec;
 use crate::{print, println};
 
@@ -22,6 +23,27 @@ pub fn frame_test() {
     println!("frame_test pass");
 }
 
+pub fn contiguous_frame_test() {
+    let frames = PhysFrame::alloc_contiguous(4, 2);
+    assert_eq!(frames.len(), 4);
+    assert_eq!(frames[0].addr() % (4 * PAGE_SIZE), 0);
+    for i in 1..4 {
+        assert_eq!(frames[i].addr(), frames[0].addr() + i * PAGE_SIZE);
+    }
+    drop(frames);
+    println!("contiguous_frame_test pass");
+}
+
+pub fn frame_stats_test() {
+    let (total, free) = frame_stats();
+    assert!(free <= total);
+    let frame = PhysFrame::alloc().unwrap();
+    assert_eq!(frame_stats().1, free - 1);
+    drop(frame);
+    assert_eq!(frame_stats().1, free);
+    println!("frame_stats_test pass");
+}
+
 /* #[no_mangle]
 pub extern "C" fn hal_frame_alloc() -> Option<usize> {
     println!("running in hal_frame_alloc()");
@@ -1,6 +1,8 @@
 mod frame_test;
 mod pmem_test;
 mod page_table_test;
+mod random_test;
 pub use frame_test::*;
 pub use pmem_test::*;
 pub use page_table_test::*;
+pub use random_test::*;
@@ -0,0 +1,20 @@
+use crate::kernel_hal_bare::random::chacha20_block;
+use crate::{print, println};
+
+pub fn chacha20_test() {
+    // RFC 8439, section 2.3.2
+    let mut key = [0u8; 32];
+    for (i, x) in key.iter_mut().enumerate() {
+        *x = i as u8;
+    }
+    let nonce = [0, 0, 0, 0x09, 0, 0, 0, 0x4a, 0, 0, 0, 0];
+    let expected: [u8; 64] = [
+        0x10, 0xf1, 0xe7, 0xe4, 0xd1, 0x3b, 0x59, 0x15, 0x50, 0x0f, 0xdd, 0x1f, 0xa3, 0x20, 0x71,
+        0xc4, 0xc7, 0xd1, 0xf4, 0xc7, 0x33, 0xc0, 0x68, 0x03, 0x04, 0x22, 0xaa, 0x9a, 0xc3, 0xd4,
+        0x6c, 0x4e, 0xd2, 0x82, 0x64, 0x46, 0x07, 0x9f, 0xaa, 0x09, 0x14, 0xc2, 0xd7, 0x05, 0xd9,
+        0x8b, 0x02, 0xa2, 0xb5, 0x12, 0x9c, 0xd1, 0xde, 0x16, 0x4e, 0xb9, 0xcb, 0xd0, 0x83, 0xe8,
+        0xa2, 0x50, 0x3c, 0x4e,
+    ];
+    assert_eq!(chacha20_block(&key, 1, &nonce)[..], expected[..]);
+    println!("chacha20_test pass");
+}
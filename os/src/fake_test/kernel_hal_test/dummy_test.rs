@@ -1,5 +1,5 @@
 use alloc::vec::Vec;
-use crate::kernel_hal::fill_random;
+use crate::kernel_hal::{add_entropy, fill_random};
 use crate::{print, println};
 
 pub fn fill_random_test() {
@@ -8,5 +8,17 @@ pub fn fill_random_test() {
         buffer.push(i);
     }
     fill_random(&mut buffer);
+
+    let mut first = [0u8; 64];
+    let mut second = [0u8; 64];
+    fill_random(&mut first);
+    fill_random(&mut second);
+    assert!(first.iter().any(|&x| x != 0));
+    assert_ne!(first[..], second[..]);
+
+    add_entropy(&[0u8; 300]);
+    let mut third = [0u8; 64];
+    fill_random(&mut third);
+    assert_ne!(second[..], third[..]);
     println!("fill_random_test pass");
 }
\ No newline at end of file
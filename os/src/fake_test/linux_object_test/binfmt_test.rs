@@ -0,0 +1,127 @@
+use crate::linux_object::cred::Credentials;
+use crate::linux_object::error::LxError;
+use crate::linux_object::loader::{parse_script, LinuxElfLoader};
+use crate::zircon_object::vm::VmAddressRegion;
+use crate::{print, println};
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use rcore_fs::vfs::{FileSystem, FileType};
+use rcore_fs_ramfs::RamFS;
+
+pub fn test_parse_script() {
+    assert_eq!(parse_script(b"#!/bin/sh\necho").unwrap(), ("/bin/sh", ""));
+    // the argument is the rest of the line
+    assert_eq!(
+        parse_script(b"#! /usr/bin/env  python3 -u \n").unwrap(),
+        ("/usr/bin/env", "python3 -u")
+    );
+    assert_eq!(
+        parse_script(b"#!\t/bin/sh\t-e\r\n").unwrap(),
+        ("/bin/sh", "-e")
+    );
+    // without a newline the file ends the line
+    assert_eq!(parse_script(b"#!/bin/sh").unwrap(), ("/bin/sh", ""));
+    assert!(matches!(parse_script(b"#!  \n"), Err(LxError::ENOEXEC)));
+    assert!(matches!(parse_script(b"/bin/sh"), Err(LxError::ENOEXEC)));
+
+    // a line filling the limit might have been cut
+    let mut long = Vec::from(&b"#!/bin/"[..]);
+    long.resize(300, b'a');
+    assert!(matches!(parse_script(&long), Err(LxError::ENOEXEC)));
+    long[200] = b'\n';
+    assert_eq!(parse_script(&long).unwrap().0.len(), 198);
+    println!("test_parse_script pass");
+}
+
+pub fn test_script_interpreter() {
+    let fs = RamFS::new();
+    let root = fs.root_inode();
+    let loader = LinuxElfLoader {
+        syscall_entry: 0,
+        stack_pages: 8,
+        root_inode: root.clone(),
+        cred: Credentials::default(),
+        caller_cred: Credentials::default(),
+    };
+    let vmar = VmAddressRegion::new_root();
+    let exec = |data: &[u8]| {
+        let args = vec![String::from("script")];
+        loader.prepare(&vmar, data.into(), args, vec![], "/script".into())
+    };
+
+    // the interpreter must be an executable file
+    root.create("noexec", FileType::File, 0o644).unwrap();
+    assert!(matches!(exec(b"#!/noexec\n"), Err(LxError::EACCES)));
+    root.create("dir", FileType::Dir, 0o755).unwrap();
+    assert!(matches!(exec(b"#!/dir\n"), Err(LxError::EACCES)));
+    assert!(matches!(exec(b"#!/none\n"), Err(LxError::ENOENT)));
+
+    // a script interpreting itself ends at the depth limit
+    let file = root.create("loop", FileType::File, 0o755).unwrap();
+    file.write_at(0, b"#!/loop\n").unwrap();
+    assert!(matches!(exec(b"#!/loop\n"), Err(LxError::ELOOP)));
+
+    // a file in no known format, which the shell runs as a script itself
+    assert!(matches!(exec(b"echo hello\n"), Err(LxError::ENOEXEC)));
+    // nothing is mapped before the program is known to be loadable
+    assert_eq!(vmar.count(), 0);
+    println!("test_script_interpreter pass");
+}
+
+/// Build an ELF image with one LOAD segment of `filesz` bytes at `offset` in
+/// the file, mapped at `vaddr`.
+fn elf_image(type_: u16, offset: u64, vaddr: u64, filesz: u64) -> Vec<u8> {
+    let mut data = Vec::from(&b"\x7fELF\x02\x01\x01"[..]);
+    data.resize(16, 0);
+    data.extend_from_slice(&type_.to_le_bytes());
+    data.extend_from_slice(&0xf3u16.to_le_bytes()); // EM_RISCV
+    data.extend_from_slice(&1u32.to_le_bytes());
+    data.extend_from_slice(&vaddr.to_le_bytes()); // entry
+    data.extend_from_slice(&64u64.to_le_bytes()); // program headers
+    data.extend_from_slice(&0u64.to_le_bytes()); // section headers
+    data.extend_from_slice(&0u32.to_le_bytes());
+    for &n in [64u16, 56, 1, 64, 0, 0].iter() {
+        data.extend_from_slice(&n.to_le_bytes());
+    }
+    data.extend_from_slice(&1u32.to_le_bytes()); // PT_LOAD
+    data.extend_from_slice(&5u32.to_le_bytes()); // R-X
+    for &n in [offset, vaddr, vaddr, filesz, filesz, 0x1000].iter() {
+        data.extend_from_slice(&n.to_le_bytes());
+    }
+    data.resize(0x200, 0);
+    data
+}
+
+pub fn test_check_segments() {
+    let fs = RamFS::new();
+    let loader = LinuxElfLoader {
+        syscall_entry: 0,
+        stack_pages: 8,
+        root_inode: fs.root_inode(),
+        cred: Credentials::default(),
+        caller_cred: Credentials::default(),
+    };
+    let vmar = VmAddressRegion::new_root();
+    let exec = |data: Vec<u8>| {
+        let args = vec![String::from("prog")];
+        loader.prepare(&vmar, data, args, vec![], "/prog".into())
+    };
+    const ET_EXEC: u16 = 2;
+    const ET_DYN: u16 = 3;
+
+    assert!(exec(elf_image(ET_DYN, 0, 0, 0x200)).is_ok());
+    // the segment is not in the file
+    let image = elf_image(ET_DYN, 0x100, 0, 0x200);
+    assert!(matches!(exec(image), Err(LxError::ENOEXEC)));
+    let image = elf_image(ET_DYN, u64::MAX, 0, 0x200);
+    assert!(matches!(exec(image), Err(LxError::ENOEXEC)));
+    // the executable is linked out of the address space
+    let image = elf_image(ET_EXEC, 0, 0x1000, 0x200);
+    assert!(matches!(exec(image), Err(LxError::ENOEXEC)));
+    let image = elf_image(ET_EXEC, 0, vmar.addr() as u64 + 0x1000, 0x200);
+    assert!(exec(image).is_ok());
+    // nothing is mapped before the program is known to be loadable
+    assert_eq!(vmar.count(), 0);
+    println!("test_check_segments pass");
+}
@@ -0,0 +1,172 @@
+use crate::linux_object::cred::*;
+use crate::linux_object::error::LxError;
+use crate::{print, println};
+use rcore_fs::vfs::{FileType, Metadata, Timespec};
+
+fn metadata(type_: FileType, mode: u16, uid: usize, gid: usize) -> Metadata {
+    Metadata {
+        dev: 0,
+        inode: 0,
+        size: 0,
+        blk_size: 0,
+        blocks: 0,
+        atime: Timespec { sec: 0, nsec: 0 },
+        mtime: Timespec { sec: 0, nsec: 0 },
+        ctime: Timespec { sec: 0, nsec: 0 },
+        type_,
+        mode,
+        nlinks: 1,
+        uid,
+        gid,
+        rdev: 0,
+    }
+}
+
+fn user(uid: u32, gid: u32) -> Credentials {
+    let ids = |id| IdSet {
+        real: id,
+        effective: id,
+        saved: id,
+        fs: id,
+    };
+    Credentials {
+        user: ids(uid),
+        group: ids(gid),
+        groups: vec![],
+    }
+}
+
+pub fn test_id_set() {
+    let mut ids = IdSet {
+        real: 1000,
+        effective: 0,
+        saved: 0,
+        fs: 0,
+    };
+    // unprivileged: only to the real or saved ID
+    assert!(matches!(ids.set(2000, false), Err(LxError::EPERM)));
+    assert!(ids.set(1000, false).is_ok());
+    assert_eq!(
+        (ids.real, ids.effective, ids.saved, ids.fs),
+        (1000, 1000, 0, 1000)
+    );
+    assert!(ids.set(0, false).is_ok());
+    // privileged: all of them
+    assert!(ids.set(2000, true).is_ok());
+    assert_eq!(
+        (ids.real, ids.effective, ids.saved, ids.fs),
+        (2000, 2000, 2000, 2000)
+    );
+
+    let mut ids = IdSet {
+        real: 1000,
+        effective: 1000,
+        saved: 1000,
+        fs: 1000,
+    };
+    assert!(matches!(
+        ids.set_re(Some(0), None, false),
+        Err(LxError::EPERM)
+    ));
+    assert!(matches!(
+        ids.set_res(None, Some(0), None, false),
+        Err(LxError::EPERM)
+    ));
+    assert!(ids.set_res(Some(0), Some(1), Some(2), true).is_ok());
+    assert_eq!((ids.real, ids.effective, ids.saved, ids.fs), (0, 1, 2, 1));
+    // swapping the real and effective IDs saves the new effective one
+    assert!(ids.set_re(Some(1), Some(0), false).is_ok());
+    assert_eq!((ids.real, ids.effective, ids.saved), (1, 0, 0));
+
+    // an ID which is not allowed leaves the filesystem ID unchanged
+    assert_eq!(ids.set_fs(3000, false), 0);
+    assert_eq!(ids.fs, 0);
+    assert_eq!(ids.set_fs(1, false), 0);
+    assert_eq!(ids.fs, 1);
+    println!("test_id_set pass");
+}
+
+pub fn test_check_access() {
+    let file = metadata(FileType::File, 0o640, 1000, 100);
+    let owner = user(1000, 1000);
+    let mut member = user(2000, 2000);
+    member.groups.push(100);
+    let other = user(3000, 3000);
+    let root = user(0, 0);
+
+    assert!(owner
+        .check_access(&file, Access::READ | Access::WRITE)
+        .is_ok());
+    assert!(matches!(
+        owner.check_access(&file, Access::EXEC),
+        Err(LxError::EACCES)
+    ));
+    assert!(member.check_access(&file, Access::READ).is_ok());
+    assert!(matches!(
+        member.check_access(&file, Access::WRITE),
+        Err(LxError::EACCES)
+    ));
+    assert!(matches!(
+        other.check_access(&file, Access::READ),
+        Err(LxError::EACCES)
+    ));
+    // root reads and writes anything, but executes only executable files
+    assert!(root
+        .check_access(&file, Access::READ | Access::WRITE)
+        .is_ok());
+    assert!(matches!(
+        root.check_access(&file, Access::EXEC),
+        Err(LxError::EACCES)
+    ));
+    let script = metadata(FileType::File, 0o700, 1000, 1000);
+    assert!(root.check_access(&script, Access::EXEC).is_ok());
+    let dir = metadata(FileType::Dir, 0o000, 1000, 1000);
+    assert!(root.check_access(&dir, Access::EXEC).is_ok());
+    // the owner class applies even if it grants less than the others
+    let odd = metadata(FileType::File, 0o047, 1000, 1000);
+    assert!(matches!(
+        owner.check_access(&odd, Access::READ),
+        Err(LxError::EACCES)
+    ));
+    assert!(other.check_access(&odd, Access::READ).is_ok());
+    println!("test_check_access pass");
+}
+
+pub fn test_exec_credentials() {
+    let setuid = metadata(FileType::File, 0o4755 | 0o2010, 0, 50);
+    let mut cred = user(1000, 1000);
+    cred.exec(&setuid, true);
+    assert_eq!(
+        (cred.user.real, cred.user.effective, cred.user.saved),
+        (1000, 0, 0)
+    );
+    assert_eq!((cred.group.real, cred.group.effective), (1000, 50));
+    assert!(cred.secure_exec());
+
+    // ignored under an unprivileged tracer
+    let mut cred = user(1000, 1000);
+    cred.exec(&setuid, false);
+    assert_eq!(cred.user.effective, 1000);
+    assert_eq!(cred.group.effective, 1000);
+    assert!(!cred.secure_exec());
+
+    // set-group-ID without group execute is mandatory locking
+    let locking = metadata(FileType::File, 0o2644, 0, 50);
+    let mut cred = user(1000, 1000);
+    cred.exec(&locking, true);
+    assert_eq!(cred.group.effective, 1000);
+    println!("test_exec_credentials pass");
+}
+
+pub fn test_may_trace() {
+    let tracer = user(1000, 1000);
+    assert!(tracer.may_trace(&user(1000, 1000)));
+    assert!(!tracer.may_trace(&user(2000, 1000)));
+    // a process which was set-user-ID keeps other saved IDs
+    let mut setuid = user(1000, 1000);
+    setuid.user.effective = 0;
+    setuid.user.saved = 0;
+    assert!(!tracer.may_trace(&setuid));
+    assert!(user(0, 0).may_trace(&setuid));
+    println!("test_may_trace pass");
+}
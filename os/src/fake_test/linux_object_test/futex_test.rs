@@ -0,0 +1,72 @@
+use super::poll_once;
+use crate::linux_object::futex::get_futex;
+use crate::linux_object::process::ProcessExt;
+use crate::zircon_object::signal::Futex;
+use crate::zircon_object::task::{Job, Process};
+use crate::zircon_object::vm::{MMUFlags, VmObject, PAGE_SIZE};
+use crate::zircon_object::ZxError;
+use crate::{print, println};
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use core::sync::atomic::AtomicI32;
+use core::task::Poll;
+use rcore_fs_ramfs::RamFS;
+
+pub fn test_futex_bitset() {
+    static VALUE: AtomicI32 = AtomicI32::new(1);
+    let futex = Futex::new(&VALUE);
+    let mut wait1 = Box::pin(futex.wait_bitset(1, None, None, 0b01));
+    let mut wait2 = Box::pin(futex.wait_bitset(1, None, None, 0b10));
+    // like FUTEX_WAIT, with all bits
+    let mut wait3 = Box::pin(futex.wait(1));
+    assert!(poll_once(wait1.as_mut()).is_pending());
+    assert!(poll_once(wait2.as_mut()).is_pending());
+    assert!(poll_once(wait3.as_mut()).is_pending());
+
+    // only the waiters with a bit in common are woken up
+    assert_eq!(futex.wake_bitset(2, 0b10), 2);
+    assert!(poll_once(wait1.as_mut()).is_pending());
+    assert!(matches!(poll_once(wait2.as_mut()), Poll::Ready(Ok(()))));
+    assert!(matches!(poll_once(wait3.as_mut()), Poll::Ready(Ok(()))));
+    assert_eq!(futex.wake_bitset(1, 0b100), 0);
+    assert!(poll_once(wait1.as_mut()).is_pending());
+
+    // like FUTEX_WAKE
+    assert_eq!(futex.wake(2), 1);
+    assert!(matches!(poll_once(wait1.as_mut()), Poll::Ready(Ok(()))));
+    println!("test_futex_bitset pass");
+}
+
+pub fn test_futex_shared() {
+    let parent = Process::create_linux(&Job::root(), RamFS::new()).unwrap();
+    let vmo = VmObject::new_paged(1);
+    let flags = MMUFlags::READ | MMUFlags::WRITE | MMUFlags::USER;
+    let addr = parent
+        .vmar()
+        .map_shared(None, vmo.clone(), 0, PAGE_SIZE, flags, false)
+        .unwrap();
+    let child = Process::fork_from(&parent, false).unwrap();
+
+    // the same futex for all processes mapping the page, on the word in the VMO
+    let futex = get_futex(&parent, addr + 8, true).unwrap();
+    assert!(Arc::ptr_eq(&futex, &get_futex(&child, addr + 8, true).unwrap()));
+    assert!(!Arc::ptr_eq(&futex, &get_futex(&parent, addr + 12, true).unwrap()));
+    vmo.write(8, &5i32.to_ne_bytes()).unwrap();
+    let mut wait = Box::pin(futex.wait(4));
+    assert!(matches!(poll_once(wait.as_mut()), Poll::Ready(Err(ZxError::BAD_STATE))));
+    let mut wait = Box::pin(futex.wait(5));
+    assert!(poll_once(wait.as_mut()).is_pending());
+    assert_eq!(futex.wake(1), 1);
+    assert!(matches!(poll_once(wait.as_mut()), Poll::Ready(Ok(()))));
+    drop(wait);
+
+    // the page is kept while the futex lives, even when it is unmapped
+    parent.vmar().unmap(addr, PAGE_SIZE).unwrap();
+    child.vmar().unmap(addr, PAGE_SIZE).unwrap();
+    assert_eq!(vmo.decommit(0, PAGE_SIZE), Err(ZxError::BAD_STATE));
+    drop(futex);
+    vmo.decommit(0, PAGE_SIZE).unwrap();
+    child.exit(0);
+    parent.exit(0);
+    println!("test_futex_shared pass");
+}
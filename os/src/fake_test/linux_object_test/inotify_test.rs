@@ -0,0 +1,161 @@
+use crate::linux_object::error::LxError;
+use crate::linux_object::fs::inotify::*;
+use crate::linux_object::fs::vfs::{FileSystem, FileType, FsError, INode};
+use crate::{print, println};
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use rcore_fs_ramfs::RamFS;
+
+/// An event read from an instance: (wd, mask, cookie, name)
+type Event = (i32, InotifyMask, u32, String);
+
+/// Read all the queued events of `inotify`.
+fn read_events(inotify: &Inotify) -> Vec<Event> {
+    let mut events = Vec::new();
+    let mut buf = [0u8; 4096];
+    while let Ok(len) = inotify.read_at(0, &mut buf) {
+        let mut record = &buf[..len];
+        while !record.is_empty() {
+            let field = |i: usize| {
+                let mut bytes = [0u8; 4];
+                bytes.copy_from_slice(&record[i * 4..i * 4 + 4]);
+                u32::from_ne_bytes(bytes)
+            };
+            let name_len = field(3) as usize;
+            let name = &record[16..16 + name_len];
+            let name = name.split(|&b| b == 0).next().unwrap();
+            events.push((
+                field(0) as i32,
+                InotifyMask::from_bits_truncate(field(1)),
+                field(2),
+                String::from(core::str::from_utf8(name).unwrap()),
+            ));
+            record = &record[16 + name_len..];
+        }
+    }
+    events
+}
+
+fn event(wd: i32, mask: InotifyMask, name: &str) -> Event {
+    (wd, mask, 0, String::from(name))
+}
+
+pub fn test_inotify_events() {
+    let root = RamFS::new().root_inode();
+    let file = root.create("file", FileType::File, 0o644).unwrap();
+    let inotify = Inotify::default();
+    let mask = InotifyMask::CREATE | InotifyMask::DELETE | InotifyMask::MODIFY;
+    let wd = inotify.add_watch(&root, mask).unwrap();
+    assert!(read_events(&inotify).is_empty());
+
+    // events on entries are reported to the directory with their names
+    notify_entry(&root, "new", false, InotifyMask::CREATE, 0);
+    notify_entry(&root, "dir", true, InotifyMask::CREATE, 0);
+    let entry = (root.clone(), String::from("file"));
+    notify_file(&file, Some(&entry), InotifyMask::MODIFY);
+    notify_entry(&root, "new", false, InotifyMask::DELETE, 0);
+    // events not in the mask are not reported
+    notify_entry(&root, "new", false, InotifyMask::ATTRIB, 0);
+    assert_eq!(
+        read_events(&inotify),
+        [
+            event(wd, InotifyMask::CREATE, "new"),
+            event(wd, InotifyMask::CREATE | InotifyMask::ISDIR, "dir"),
+            event(wd, InotifyMask::MODIFY, "file"),
+            event(wd, InotifyMask::DELETE, "new"),
+        ]
+    );
+
+    // an event the same as the last one queued is dropped
+    notify_file(&file, Some(&entry), InotifyMask::MODIFY);
+    notify_file(&file, Some(&entry), InotifyMask::MODIFY);
+    notify_entry(&root, "new", false, InotifyMask::CREATE, 0);
+    notify_file(&file, Some(&entry), InotifyMask::MODIFY);
+    assert_eq!(
+        read_events(&inotify),
+        [
+            event(wd, InotifyMask::MODIFY, "file"),
+            event(wd, InotifyMask::CREATE, "new"),
+            event(wd, InotifyMask::MODIFY, "file"),
+        ]
+    );
+
+    // removing the watch queues IGNORED
+    inotify.rm_watch(wd).unwrap();
+    assert!(matches!(inotify.rm_watch(wd), Err(LxError::EINVAL)));
+    notify_entry(&root, "new", false, InotifyMask::CREATE, 0);
+    assert_eq!(read_events(&inotify), [event(wd, InotifyMask::IGNORED, "")]);
+    println!("test_inotify_events pass");
+}
+
+pub fn test_inotify_watch_flags() {
+    let root = RamFS::new().root_inode();
+    let file = root.create("file", FileType::File, 0o644).unwrap();
+    let inotify = Inotify::default();
+
+    // MASK_ADD adds to the mask, without it the mask is replaced
+    let wd = inotify.add_watch(&file, InotifyMask::MODIFY).unwrap();
+    let mask = InotifyMask::ATTRIB | InotifyMask::MASK_ADD;
+    assert_eq!(inotify.add_watch(&file, mask).unwrap(), wd);
+    notify_inode(&file, InotifyMask::MODIFY);
+    notify_inode(&file, InotifyMask::ATTRIB);
+    assert_eq!(inotify.add_watch(&file, InotifyMask::OPEN).unwrap(), wd);
+    notify_inode(&file, InotifyMask::MODIFY);
+    notify_inode(&file, InotifyMask::OPEN);
+    let mask = InotifyMask::OPEN | InotifyMask::MASK_CREATE;
+    assert!(matches!(inotify.add_watch(&file, mask), Err(LxError::EEXIST)));
+    assert_eq!(
+        read_events(&inotify),
+        [
+            event(wd, InotifyMask::MODIFY, ""),
+            event(wd, InotifyMask::ATTRIB, ""),
+            event(wd, InotifyMask::OPEN, ""),
+        ]
+    );
+
+    // ONESHOT removes the watch after the first event
+    let mask = InotifyMask::MODIFY | InotifyMask::ONESHOT;
+    let wd = inotify.add_watch(&root, mask).unwrap();
+    let entry = (root.clone(), String::from("file"));
+    notify_file(&file, Some(&entry), InotifyMask::MODIFY);
+    notify_entry(&root, "file", false, InotifyMask::MODIFY, 0);
+    assert_eq!(
+        read_events(&inotify),
+        [
+            event(wd, InotifyMask::MODIFY, "file"),
+            event(wd, InotifyMask::IGNORED, ""),
+        ]
+    );
+    assert!(matches!(inotify.rm_watch(wd), Err(LxError::EINVAL)));
+    println!("test_inotify_watch_flags pass");
+}
+
+pub fn test_inotify_queue() {
+    let root = RamFS::new().root_inode();
+    let inotify = Inotify::default();
+    let wd = inotify.add_watch(&root, InotifyMask::CREATE).unwrap();
+
+    // a buffer too small for the first event
+    notify_entry(&root, "a_long_name", false, InotifyMask::CREATE, 0);
+    let mut buf = [0u8; 20];
+    assert!(matches!(
+        inotify.read_at(0, &mut buf),
+        Err(FsError::InvalidParam)
+    ));
+    assert_eq!(
+        read_events(&inotify),
+        [event(wd, InotifyMask::CREATE, "a_long_name")]
+    );
+
+    // events beyond the queue limit are replaced by one overflow event
+    let max_events = 16384;
+    for i in 0..max_events + 10 {
+        notify_entry(&root, &format!("{}", i), false, InotifyMask::CREATE, 0);
+    }
+    let events = read_events(&inotify);
+    assert_eq!(events.len(), max_events + 1);
+    assert_eq!(events[max_events - 1], event(wd, InotifyMask::CREATE, "16383"));
+    assert_eq!(events[max_events], event(-1, InotifyMask::Q_OVERFLOW, ""));
+    println!("test_inotify_queue pass");
+}
@@ -0,0 +1,115 @@
+use crate::linux_object::cred::{Access, Credentials, IdSet};
+use crate::linux_object::error::LxError;
+use crate::linux_object::ipc::{select_message, IpcPerm, IpcTable};
+use crate::{print, println};
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+
+const IPC_CREAT: usize = 0o1000;
+const IPC_EXCL: usize = 0o2000;
+
+fn user(uid: u32, gid: u32, groups: Vec<u32>) -> Credentials {
+    let ids = |id| IdSet {
+        real: id,
+        effective: id,
+        saved: id,
+        fs: id,
+    };
+    Credentials {
+        user: ids(uid),
+        group: ids(gid),
+        groups,
+    }
+}
+
+pub fn test_ipc_table() {
+    let mut table = IpcTable::default();
+    let create = |key| Ok(Arc::new(key));
+    assert!(matches!(
+        table.get_or_create(1, 0, create),
+        Err(LxError::ENOENT)
+    ));
+    let (id, object, created) = table.get_or_create(1, IPC_CREAT, create).unwrap();
+    assert!(created);
+    assert_eq!(*object, 1);
+    // the same key gives the same ID, which is valid for everyone
+    let (id2, _, created) = table.get_or_create(1, IPC_CREAT, create).unwrap();
+    assert_eq!((id2, created), (id, false));
+    assert!(matches!(
+        table.get_or_create(1, IPC_CREAT | IPC_EXCL, create),
+        Err(LxError::EEXIST)
+    ));
+    assert_eq!(*table.get(id).unwrap(), 1);
+
+    // private objects get their own IDs and are not found by key
+    let (private, _, _) = table.get_or_create(0, 0, create).unwrap();
+    let (private2, _, _) = table.get_or_create(0, 0, create).unwrap();
+    assert!(private != id && private2 != id && private != private2);
+
+    // the key is free once the object is removed
+    assert!(table.remove(id).is_some());
+    assert!(table.remove(id).is_none());
+    assert!(table.get(id).is_none());
+    assert!(matches!(
+        table.get_or_create(1, 0, create),
+        Err(LxError::ENOENT)
+    ));
+    assert!(table.get(private).is_some());
+    println!("test_ipc_table pass");
+}
+
+pub fn test_ipc_perm_check() {
+    let owner = user(1000, 100, vec![]);
+    let perm = IpcPerm::new(42, IPC_CREAT | 0o640, &owner);
+    assert_eq!(perm.mode, 0o640);
+    assert!(perm.check(&owner, Access::READ | Access::WRITE).is_ok());
+    assert!(matches!(
+        perm.check(&owner, Access::EXEC),
+        Err(LxError::EACCES)
+    ));
+
+    // the group by the effective or the supplementary group IDs
+    let member = user(1001, 100, vec![]);
+    assert!(perm.check(&member, Access::READ).is_ok());
+    assert!(matches!(
+        perm.check(&member, Access::WRITE),
+        Err(LxError::EACCES)
+    ));
+    assert!(perm
+        .check(&user(1002, 200, vec![100]), Access::READ)
+        .is_ok());
+
+    // others, and root which is never denied
+    assert!(matches!(
+        perm.check(&user(1003, 300, vec![]), Access::READ),
+        Err(LxError::EACCES)
+    ));
+    assert!(perm.check(&user(0, 0, vec![]), Access::WRITE).is_ok());
+    assert!(perm.is_owner(&owner));
+    assert!(!perm.is_owner(&member));
+    assert_eq!(IpcPerm::requested(0o600), Access::READ | Access::WRITE);
+    println!("test_ipc_perm_check pass");
+}
+
+pub fn test_msg_select() {
+    let types = [3, 1, 2, 1, 5];
+    // the first message
+    assert_eq!(select_message(types.iter().cloned(), 0, false), Some(0));
+    assert_eq!(select_message(None, 0, false), None);
+    // the first message of the type, or not of the type with MSG_EXCEPT
+    assert_eq!(select_message(types.iter().cloned(), 1, false), Some(1));
+    assert_eq!(select_message(types.iter().cloned(), 4, false), None);
+    assert_eq!(select_message(types.iter().cloned(), 3, true), Some(1));
+    assert_eq!(select_message(vec![2, 2], 2, true), None);
+    // the first message of the lowest type up to the absolute value
+    assert_eq!(select_message(types.iter().cloned(), -2, false), Some(1));
+    assert_eq!(select_message(types.iter().cloned(), -5, false), Some(1));
+    assert_eq!(select_message(vec![4, 3, 3], -3, false), Some(1));
+    assert_eq!(
+        select_message(types.iter().cloned(), -0x7fff, false),
+        Some(1)
+    );
+    assert_eq!(select_message(vec![4, 5], -3, false), None);
+    println!("test_msg_select pass");
+}
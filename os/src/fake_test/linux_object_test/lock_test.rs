@@ -0,0 +1,209 @@
+use super::{block_on, poll_once};
+use crate::linux_object::error::{LxError, LxResult};
+use crate::linux_object::fs::LockKind::{Exclusive, Shared};
+use crate::linux_object::fs::LockOwner::Process;
+use crate::linux_object::fs::*;
+use crate::{print, println};
+use alloc::boxed::Box;
+use core::future::Future;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use core::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+
+/// a key of no INode, so the tests do not meet the locks of files
+fn key(n: usize) -> LockKey {
+    (usize::MAX, n)
+}
+
+fn set(key: LockKey, owner: LockOwner, kind: Option<LockKind>, start: u64, end: u64) -> LxResult {
+    block_on(set_record_lock(key, owner, kind, start, end, false))
+}
+
+fn range(lock: Option<RecordLock>) -> Option<(u64, u64)> {
+    lock.map(|lock| (lock.start, lock.end))
+}
+
+/// number of the wakers of `poll_counted` alive
+static LIVE_WAKERS: AtomicUsize = AtomicUsize::new(0);
+
+/// Poll `future` once, with a waker counted in `LIVE_WAKERS`.
+fn poll_counted<F: Future + ?Sized>(future: Pin<&mut F>) -> Poll<F::Output> {
+    fn clone(_: *const ()) -> RawWaker {
+        LIVE_WAKERS.fetch_add(1, Ordering::SeqCst);
+        RawWaker::new(core::ptr::null(), &VTABLE)
+    }
+    fn release(_: *const ()) {
+        LIVE_WAKERS.fetch_sub(1, Ordering::SeqCst);
+    }
+    fn noop(_: *const ()) {}
+    static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, release, noop, release);
+
+    #[allow(unsafe_code)]
+    let waker = unsafe { Waker::from_raw(clone(core::ptr::null())) };
+    future.poll(&mut Context::from_waker(&waker))
+}
+
+pub fn test_record_lock_replace() {
+    let key = key(1);
+    set(key, Process(1), Some(Exclusive), 0, 100).unwrap();
+    // unlocking the middle splits the lock
+    set(key, Process(1), None, 20, 30).unwrap();
+    assert!(test_record_lock(key, Process(2), Shared, 20, 30).is_none());
+    assert_eq!(
+        range(test_record_lock(key, Process(2), Shared, 10, 25)),
+        Some((0, 20))
+    );
+    assert_eq!(
+        range(test_record_lock(key, Process(2), Shared, 50, 60)),
+        Some((30, 100))
+    );
+
+    // a lock of another kind in the middle replaces that part
+    set(key, Process(1), Some(Shared), 40, 50).unwrap();
+    assert!(test_record_lock(key, Process(2), Shared, 40, 50).is_none());
+    let lock = test_record_lock(key, Process(2), Exclusive, 40, 50).unwrap();
+    assert_eq!((lock.kind, lock.start, lock.end), (Shared, 40, 50));
+    assert_eq!(
+        range(test_record_lock(key, Process(2), Shared, 50, 51)),
+        Some((50, 100))
+    );
+
+    // locks up to the end of the file
+    set(key, Process(1), Some(Exclusive), 200, u64::MAX).unwrap();
+    assert!(test_record_lock(key, Process(2), Shared, 1 << 40, 1 << 41).is_some());
+
+    release_record_locks(key, Process(1));
+    assert!(test_record_lock(key, Process(2), Exclusive, 0, u64::MAX).is_none());
+
+    // adjacent and overlapping locks of the same kind are merged
+    set(key, Process(1), Some(Shared), 0, 10).unwrap();
+    set(key, Process(1), Some(Shared), 20, 30).unwrap();
+    set(key, Process(1), Some(Shared), 10, 20).unwrap();
+    set(key, Process(1), Some(Shared), 25, 40).unwrap();
+    assert_eq!(
+        range(test_record_lock(key, Process(2), Exclusive, 35, 36)),
+        Some((0, 40))
+    );
+    set(key, Process(1), Some(Exclusive), 40, 50).unwrap();
+    assert_eq!(
+        range(test_record_lock(key, Process(2), Shared, 45, 46)),
+        Some((40, 50))
+    );
+    release_record_locks(key, Process(1));
+    println!("test_record_lock_replace pass");
+}
+
+pub fn test_record_lock_conflict() {
+    let key = key(2);
+    set(key, Process(1), Some(Shared), 0, 10).unwrap();
+    set(key, Process(2), Some(Shared), 5, 15).unwrap();
+    let err = set(key, Process(3), Some(Exclusive), 0, 1);
+    assert!(matches!(err, Err(LxError::EAGAIN)));
+    let err = set(key, Process(3), Some(Exclusive), 10, 20);
+    assert!(matches!(err, Err(LxError::EAGAIN)));
+    set(key, Process(3), Some(Exclusive), 15, 20).unwrap();
+
+    // the locks of the owner do not conflict with its new lock
+    set(key, Process(1), Some(Exclusive), 0, 5).unwrap();
+    // an OFD lock is owned by the file, not the process
+    let err = set(key, LockOwner::File(100), Some(Shared), 0, 1);
+    assert!(matches!(err, Err(LxError::EAGAIN)));
+
+    for pid in 1..=3 {
+        release_all_locks(Process(pid));
+    }
+    set(key, LockOwner::File(100), Some(Exclusive), 0, 20).unwrap();
+    release_all_locks(LockOwner::File(100));
+    println!("test_record_lock_conflict pass");
+}
+
+pub fn test_flock() {
+    let key = key(3);
+    block_on(flock(key, 1, Shared, true)).unwrap();
+    block_on(flock(key, 2, Shared, true)).unwrap();
+    let err = block_on(flock(key, 3, Exclusive, true));
+    assert!(matches!(err, Err(LxError::EAGAIN)));
+    // record locks do not interact with `flock` ones
+    set(key, Process(1), Some(Exclusive), 0, u64::MAX).unwrap();
+
+    funlock(key, 1);
+    let err = block_on(flock(key, 3, Exclusive, true));
+    assert!(matches!(err, Err(LxError::EAGAIN)));
+    // closing the file releases its `flock` lock
+    release_all_locks(LockOwner::File(2));
+    block_on(flock(key, 3, Exclusive, true)).unwrap();
+
+    funlock(key, 3);
+    release_all_locks(Process(1));
+    println!("test_flock pass");
+}
+
+pub fn test_lock_deadlock() {
+    let (key1, key2) = (key(4), key(5));
+    set(key1, Process(1), Some(Exclusive), 0, 1).unwrap();
+    set(key2, Process(2), Some(Exclusive), 0, 1).unwrap();
+
+    // 2 waits for 1, so 1 can not wait for 2
+    let mut wait = Box::pin(set_record_lock(
+        key1,
+        Process(2),
+        Some(Exclusive),
+        0,
+        1,
+        true,
+    ));
+    assert!(poll_once(wait.as_mut()).is_pending());
+    let err = block_on(set_record_lock(
+        key2,
+        Process(1),
+        Some(Exclusive),
+        0,
+        1,
+        true,
+    ));
+    assert!(matches!(err, Err(LxError::EDEADLK)));
+
+    // the waiter gets the lock once it is released
+    set(key1, Process(1), None, 0, 1).unwrap();
+    assert!(matches!(poll_once(wait.as_mut()), Poll::Ready(Ok(()))));
+    drop(wait);
+    // and no longer waits, so 1 can wait for 2 again
+    let mut wait = Box::pin(set_record_lock(
+        key2,
+        Process(1),
+        Some(Exclusive),
+        0,
+        1,
+        true,
+    ));
+    assert!(poll_once(wait.as_mut()).is_pending());
+    drop(wait);
+
+    release_all_locks(Process(1));
+    release_all_locks(Process(2));
+    println!("test_lock_deadlock pass");
+}
+
+pub fn test_lock_wait_dropped() {
+    let key = key(6);
+    set(key, Process(1), Some(Exclusive), 0, 10).unwrap();
+    let mut wait = Box::pin(set_record_lock(key, Process(2), Some(Shared), 0, 10, true));
+    assert!(poll_counted(wait.as_mut()).is_pending());
+    assert_eq!(LIVE_WAKERS.load(Ordering::SeqCst), 1);
+    // polled again, the waker is replaced rather than added
+    assert!(poll_counted(wait.as_mut()).is_pending());
+    assert_eq!(LIVE_WAKERS.load(Ordering::SeqCst), 1);
+    // an interrupted wait leaves no waker behind
+    drop(wait);
+    assert_eq!(LIVE_WAKERS.load(Ordering::SeqCst), 0);
+
+    // nor does a wait which got its lock
+    let mut wait = Box::pin(set_record_lock(key, Process(2), Some(Shared), 0, 10, true));
+    assert!(poll_counted(wait.as_mut()).is_pending());
+    release_all_locks(Process(1));
+    assert_eq!(LIVE_WAKERS.load(Ordering::SeqCst), 0);
+    assert!(matches!(poll_counted(wait.as_mut()), Poll::Ready(Ok(()))));
+    drop(wait);
+    release_all_locks(Process(2));
+    println!("test_lock_wait_dropped pass");
+}
@@ -0,0 +1,121 @@
+pub mod binfmt_test;
+pub mod cred_test;
+pub mod futex_test;
+pub mod inotify_test;
+pub mod ipc_test;
+pub mod lock_test;
+pub mod mount_test;
+pub mod pipe_test;
+pub mod procfs_test;
+pub mod statfs_test;
+pub mod task_test;
+pub mod tcp_test;
+pub mod tty_test;
+pub mod unix_test;
+pub mod walk_test;
+pub mod xattr_test;
+
+use crate::{print, println};
+use alloc::boxed::Box;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+
+use binfmt_test::*;
+use cred_test::*;
+use futex_test::*;
+use inotify_test::*;
+use ipc_test::*;
+use lock_test::*;
+use mount_test::*;
+use pipe_test::*;
+use procfs_test::*;
+use statfs_test::*;
+use task_test::*;
+use tcp_test::*;
+use tty_test::*;
+use unix_test::*;
+use walk_test::*;
+use xattr_test::*;
+
+pub fn test_all_in_linux_object_test() {
+    test_parse_script();
+    test_script_interpreter();
+    test_check_segments();
+    test_id_set();
+    test_check_access();
+    test_exec_credentials();
+    test_may_trace();
+    test_futex_bitset();
+    test_futex_shared();
+    test_inotify_events();
+    test_inotify_watch_flags();
+    test_inotify_queue();
+    test_ipc_table();
+    test_ipc_perm_check();
+    test_msg_select();
+    test_record_lock_replace();
+    test_record_lock_conflict();
+    test_flock();
+    test_lock_deadlock();
+    test_lock_wait_dropped();
+    test_pipe_pages();
+    test_pipe_tee();
+    test_splice_file();
+    test_vmsplice_alias();
+    test_procfs_self();
+    test_procfs_shared_fs();
+    test_procfs_process();
+    test_wait_stop_continue();
+    test_wait_exit();
+    test_fault_signal();
+    test_rusage();
+    test_clone_flags();
+    test_clone_settid();
+    test_clone_settls();
+    test_tcp_reassembly();
+    test_tcp_reuse_addr();
+    test_tty_canonical();
+    test_tty_erase();
+    test_tty_noncanonical();
+    test_tty_isig();
+    test_unix_stream_rights();
+    test_unix_dgram_bind();
+    test_unix_sockaddr();
+    test_unix_connect();
+    test_unix_bind_inode();
+    test_walk_trailing_slash();
+    test_xattr_encode();
+    test_xattr_flags();
+    test_xattr_on_disk();
+    // replaces the mount table, so last
+    test_mount_lookup();
+    test_umount_busy();
+    test_mount_dev();
+    test_mount_move();
+    test_mount_restrictions();
+    test_statfs();
+    test_xattr_hidden();
+    println!("all test in linux_object_test pass");
+}
+
+/// Poll `future` once, with a waker doing nothing.
+pub fn poll_once<F: Future + ?Sized>(future: Pin<&mut F>) -> Poll<F::Output> {
+    fn clone(_: *const ()) -> RawWaker {
+        RawWaker::new(core::ptr::null(), &VTABLE)
+    }
+    fn noop(_: *const ()) {}
+    static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+
+    #[allow(unsafe_code)]
+    let waker = unsafe { Waker::from_raw(clone(core::ptr::null())) };
+    future.poll(&mut Context::from_waker(&waker))
+}
+
+/// Run `future`, which must complete without waiting.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    match poll_once(Box::pin(future).as_mut()) {
+        Poll::Ready(output) => output,
+        Poll::Pending => panic!("the future should not wait"),
+    }
+}
@@ -0,0 +1,141 @@
+use crate::linux_object::error::LxError;
+use crate::linux_object::fs::mount::{self, MountFlags, UmountFlags};
+use crate::{print, println};
+use alloc::string::String;
+use alloc::sync::Arc;
+use rcore_fs::vfs::{FileSystem, FileType, INode};
+use rcore_fs_ramfs::RamFS;
+
+/// Create the directory `name` in `dir` and mount a RamFS holding `file` on it.
+fn mount_ramfs(dir: &Arc<dyn INode>, name: &str, path: &str, file: &str) {
+    let target = dir.create(name, FileType::Dir, 0o755).unwrap();
+    let fs = RamFS::new();
+    fs.root_inode().create(file, FileType::File, 0o644).unwrap();
+    let path = String::from(path);
+    mount::mount(fs, "tmpfs", "tmpfs", &target, path, MountFlags::empty()).unwrap();
+}
+
+pub fn test_mount_lookup() {
+    let root = mount::mount_root(RamFS::new());
+    mount_ramfs(&root, "a", "/a", "x");
+    let a = root.find("a").unwrap();
+    assert!(a.find("x").is_ok());
+    // `..` at the root of the mount goes back to the directory it is on
+    assert!(a.find("..").unwrap().find("a").is_ok());
+
+    // procfs can be mounted
+    let proc = root.create("proc", FileType::Dir, 0o555).unwrap();
+    let fs = mount::create_fs("proc", None).unwrap();
+    let path = String::from("/proc");
+    mount::mount(fs, "proc", "proc", &proc, path, MountFlags::empty()).unwrap();
+    assert!(root.find("proc").unwrap().find("self").is_ok());
+    assert!(mount::mounts_info().contains("proc /proc proc rw 0 0\n"));
+    println!("test_mount_lookup pass");
+}
+
+pub fn test_umount_busy() {
+    let root = mount::mount_root(RamFS::new());
+    mount_ramfs(&root, "a", "/a", "x");
+    // an INode in use, such as the working directory of a process
+    let x = root.find("a").unwrap().find("x").unwrap();
+    let result = mount::umount(root.find("a").unwrap(), UmountFlags::empty());
+    assert!(matches!(result, Err(LxError::EBUSY)));
+    drop(x);
+
+    // a filesystem mounted under it
+    mount_ramfs(&root.find("a").unwrap(), "b", "/a/b", "y");
+    let result = mount::umount(root.find("a").unwrap(), UmountFlags::empty());
+    assert!(matches!(result, Err(LxError::EBUSY)));
+    let b = root.find("a").unwrap().find("b").unwrap();
+    mount::umount(b, UmountFlags::empty()).unwrap();
+    mount::umount(root.find("a").unwrap(), UmountFlags::empty()).unwrap();
+    assert!(root.find("a").unwrap().find("x").is_err());
+
+    // the root can not be unmounted
+    let result = mount::umount(root.clone(), UmountFlags::empty());
+    assert!(matches!(result, Err(LxError::EBUSY)));
+    println!("test_umount_busy pass");
+}
+
+pub fn test_mount_dev() {
+    let root = mount::mount_root(RamFS::new());
+    mount_ramfs(&root, "a", "/a", "x");
+    let x = root.find("a").unwrap().find("x").unwrap();
+    let key = mount::inode_key(&x).unwrap();
+    drop(x);
+
+    // a bind mount is the same filesystem, but busy on its own
+    let target = root.create("b", FileType::Dir, 0o755).unwrap();
+    let a = root.find("a").unwrap();
+    mount::bind(&a, "/a", &target, String::from("/b"), MountFlags::empty()).unwrap();
+    drop((a, target));
+    let x = root.find("b").unwrap().find("x").unwrap();
+    assert_eq!(mount::inode_key(&x).unwrap(), key);
+    let result = mount::umount(root.find("b").unwrap(), UmountFlags::empty());
+    assert!(matches!(result, Err(LxError::EBUSY)));
+    mount::umount(root.find("a").unwrap(), UmountFlags::empty()).unwrap();
+    drop(x);
+    mount::umount(root.find("b").unwrap(), UmountFlags::empty()).unwrap();
+
+    // the ID of an unmounted filesystem is not given to a new one
+    mount_ramfs(&root, "c", "/c", "x");
+    let x = root.find("c").unwrap().find("x").unwrap();
+    assert_ne!(mount::inode_key(&x).unwrap(), key);
+    println!("test_mount_dev pass");
+}
+
+pub fn test_mount_move() {
+    let root = mount::mount_root(RamFS::new());
+    mount_ramfs(&root, "a", "/a", "x");
+    let a = root.find("a").unwrap();
+    mount_ramfs(&a, "b", "/a/b", "y");
+    let c = root.create("c", FileType::Dir, 0o755).unwrap();
+
+    // a mount can not be moved under itself
+    let b = a.find("b").unwrap();
+    let result = mount::move_mount(&a, &b, String::from("/a/b"));
+    assert!(matches!(result, Err(LxError::ELOOP)));
+    drop(b);
+
+    mount::move_mount(&a, &c, String::from("/c")).unwrap();
+    assert!(root.find("a").unwrap().find("x").is_err());
+    let c = root.find("c").unwrap();
+    assert!(c.find("x").is_ok());
+    // with the mounts under it
+    assert!(c.find("b").unwrap().find("y").is_ok());
+    let info = mount::mounts_info();
+    assert!(info.contains("tmpfs /c tmpfs"));
+    assert!(info.contains("tmpfs /c/b tmpfs"));
+    assert!(!info.contains("/a"));
+
+    // only the root of a mount can be moved
+    let result = mount::move_mount(&root.find("a").unwrap(), &c, String::from("/c"));
+    assert!(matches!(result, Err(LxError::EINVAL)));
+    println!("test_mount_move pass");
+}
+
+pub fn test_mount_restrictions() {
+    let root = mount::mount_root(RamFS::new());
+    let target = root.create("mnt", FileType::Dir, 0o755).unwrap();
+    let fs = RamFS::new();
+    fs.root_inode().create("prog", FileType::File, 0o4755).unwrap();
+    fs.root_inode().create("null", FileType::CharDevice, 0o666).unwrap();
+    let flags = MountFlags::NOSUID | MountFlags::NODEV | MountFlags::NOEXEC;
+    let path = String::from("/mnt");
+    mount::mount(fs, "tmpfs", "tmpfs", &target, path, flags).unwrap();
+    let mnt = root.find("mnt").unwrap();
+    let prog = mnt.find("prog").unwrap();
+    let null = mnt.find("null").unwrap();
+    assert!(matches!(mount::check_executable(&prog), Err(LxError::EACCES)));
+    assert!(mount::ignores_set_ids(&prog));
+    assert!(matches!(mount::check_device(&null), Err(LxError::EACCES)));
+    // only device special files are refused
+    assert!(mount::check_device(&prog).is_ok());
+
+    // the restrictions go away with the flags
+    mount::remount(&mnt, MountFlags::empty()).unwrap();
+    assert!(mount::check_executable(&prog).is_ok());
+    assert!(!mount::ignores_set_ids(&prog));
+    assert!(mount::check_device(&null).is_ok());
+    println!("test_mount_restrictions pass");
+}
@@ -0,0 +1,154 @@
+use super::block_on;
+use crate::linux_object::error::LxError;
+use crate::linux_object::fs::vfs::{FileSystem, FileType};
+use crate::linux_object::fs::*;
+use crate::zircon_object::vm::{VmObject, PAGE_SIZE};
+use crate::{print, println};
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use rcore_fs_ramfs::RamFS;
+
+fn options(read: bool, write: bool) -> OpenOptions {
+    OpenOptions {
+        read,
+        write,
+        append: false,
+        nonblock: true,
+        fd_cloexec: false,
+        path_only: false,
+    }
+}
+
+/// The read and write ends of a new nonblocking pipe
+fn pipe() -> (Arc<File>, Arc<File>) {
+    let (read, write) = Pipe::create_pair();
+    (
+        File::new(Arc::new(read), options(true, false), String::from("pipe_r:[]")),
+        File::new(Arc::new(write), options(false, true), String::from("pipe_w:[]")),
+    )
+}
+
+/// Read all there is in the pipe `file`.
+fn read_all(file: &File) -> Vec<u8> {
+    let mut data = Vec::new();
+    let mut buf = [0u8; 777];
+    while let Ok(len) = block_on(file.read(&mut buf)) {
+        data.extend_from_slice(&buf[..len]);
+    }
+    data
+}
+
+pub fn test_pipe_pages() {
+    let (read, write) = pipe();
+    assert!(matches!(block_on(read.read(&mut [0u8; 1])), Err(LxError::EAGAIN)));
+    // writes fill the last page before taking a new one
+    let data: Vec<u8> = (0..PIPE_SIZE + 100).map(|i| (i % 251) as u8).collect();
+    for chunk in data.chunks(1000) {
+        assert_eq!(write.write(chunk).unwrap(), chunk.len());
+    }
+    assert_eq!(read_all(&read), data);
+
+    // a page shared with another pipe is not appended to
+    let (read2, write2) = pipe();
+    write.write(b"abc").unwrap();
+    let (from, to) = (read.as_pipe().unwrap(), write2.as_pipe().unwrap());
+    assert_eq!(from.splice_to(to, 2).unwrap(), 2);
+    write2.write(b"xy").unwrap();
+    write.write(b"de").unwrap();
+    assert_eq!(read_all(&read), b"cde");
+    assert_eq!(read_all(&read2), b"abxy");
+    println!("test_pipe_pages pass");
+}
+
+pub fn test_pipe_tee() {
+    let (read, write) = pipe();
+    let (read2, write2) = pipe();
+    assert!(matches!(
+        block_on(tee(&read, &write2, 10, true)),
+        Err(LxError::EAGAIN)
+    ));
+    write.write(b"hello").unwrap();
+    assert_eq!(block_on(tee(&read, &write2, 3, true)).unwrap(), 3);
+    assert_eq!(block_on(tee(&read, &write2, 10, true)).unwrap(), 5);
+    assert_eq!(read_all(&read2), b"helhello");
+    assert_eq!(read_all(&read), b"hello");
+    // between two pipes only
+    assert!(matches!(block_on(tee(&read, &write, 1, true)), Err(LxError::EINVAL)));
+    assert!(matches!(block_on(tee(&write, &write2, 1, true)), Err(LxError::EBADF)));
+    println!("test_pipe_tee pass");
+}
+
+pub fn test_splice_file() {
+    let root = RamFS::new().root_inode();
+    let inode = root.create("file", FileType::File, 0o644).unwrap();
+    let file = File::new(inode, options(true, true), String::from("/file"));
+    let (read, write) = pipe();
+
+    // to a file, at the file offset or at the given one
+    write.write(b"hello world").unwrap();
+    assert_eq!(block_on(splice(&read, None, &file, None, 5, true)).unwrap(), 5);
+    assert_eq!(file.seek(SeekFrom::Current(0)).unwrap(), 5);
+    let mut offset = 100;
+    let len = block_on(splice(&read, None, &file, Some(&mut offset), 100, true)).unwrap();
+    assert_eq!((len, offset), (6, 106));
+    assert_eq!(file.seek(SeekFrom::Current(0)).unwrap(), 5);
+    let mut buf = [0u8; 6];
+    assert_eq!(block_on(file.read_at(100, &mut buf)).unwrap(), 6);
+    assert_eq!(&buf, b" world");
+
+    // from a file, up to its end
+    let mut offset = 1;
+    let len = block_on(splice(&file, Some(&mut offset), &write, None, 4, true)).unwrap();
+    assert_eq!((len, offset), (4, 5));
+    assert_eq!(file.seek(SeekFrom::Current(0)).unwrap(), 5);
+    file.seek(SeekFrom::Start(100)).unwrap();
+    assert_eq!(block_on(splice(&file, None, &write, None, 100, true)).unwrap(), 6);
+    assert_eq!(file.seek(SeekFrom::Current(0)).unwrap(), 106);
+    assert_eq!(read_all(&read), b"ello world");
+
+    // offsets are for files, one end must be a pipe and both are not the same
+    let (read2, write2) = pipe();
+    let mut offset = 0;
+    let result = block_on(splice(&read, Some(&mut offset), &file, None, 1, true));
+    assert!(matches!(result, Err(LxError::ESPIPE)));
+    let result = block_on(splice(&file, None, &write, Some(&mut offset), 1, true));
+    assert!(matches!(result, Err(LxError::ESPIPE)));
+    let result = block_on(splice(&read, None, &write2, Some(&mut offset), 1, true));
+    assert!(matches!(result, Err(LxError::ESPIPE)));
+    let result = block_on(splice(&file, None, &file, None, 1, true));
+    assert!(matches!(result, Err(LxError::EINVAL)));
+    write.write(b"x").unwrap();
+    let result = block_on(splice(&read, None, &write, None, 1, true));
+    assert!(matches!(result, Err(LxError::EINVAL)));
+    let result = block_on(splice(&write, None, &write2, None, 1, true));
+    assert!(matches!(result, Err(LxError::EBADF)));
+    let append = File::new(
+        file.inode(),
+        OpenOptions {
+            append: true,
+            ..options(false, true)
+        },
+        String::from("/file"),
+    );
+    let result = block_on(splice(&read, None, &append, Some(&mut offset), 1, true));
+    assert!(matches!(result, Err(LxError::EINVAL)));
+    assert_eq!(block_on(splice(&read, None, &write2, None, 10, true)).unwrap(), 1);
+    assert_eq!(read_all(&read2), b"x");
+    println!("test_splice_file pass");
+}
+
+pub fn test_vmsplice_alias() {
+    let (read, write) = pipe();
+    let vmo = VmObject::new_paged(2);
+    vmo.write(PAGE_SIZE - 2, b"hello").unwrap();
+    let pipe = write.as_pipe().unwrap();
+    // the bytes must be in one page
+    assert!(pipe.write_vmo(vmo.clone(), PAGE_SIZE - 2, 5).is_err());
+    assert_eq!(pipe.write_vmo(vmo.clone(), PAGE_SIZE - 2, 2).unwrap(), 2);
+    assert_eq!(pipe.write_vmo(vmo.clone(), PAGE_SIZE, 3).unwrap(), 3);
+    // the pipe reads the memory as it is when read
+    vmo.write(PAGE_SIZE - 1, b"EL").unwrap();
+    assert_eq!(read_all(&read), b"hELlo");
+    println!("test_vmsplice_alias pass");
+}
@@ -0,0 +1,78 @@
+use crate::linux_object::fs::{is_self_link, read_link, ProcFS};
+use crate::linux_object::process::ProcessExt;
+use crate::zircon_object::object::KernelObject;
+use crate::zircon_object::task::{Job, Process};
+use crate::{print, println};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use alloc::{format, vec};
+use rcore_fs::vfs::{FileSystem, FileType, INode};
+use rcore_fs_ramfs::RamFS;
+
+/// Get the inode numbers of the entries of `dir`, and of the entries of the
+/// directories among them.
+fn tree_inos(dir: &Arc<dyn INode>) -> Vec<usize> {
+    let mut inos = vec![dir.metadata().unwrap().inode];
+    let mut i = 2;
+    while let Ok(name) = dir.get_entry(i) {
+        let inode = dir.find(&name).unwrap();
+        if inode.metadata().unwrap().type_ == FileType::Dir {
+            inos.extend(tree_inos(&inode));
+        } else {
+            inos.push(inode.metadata().unwrap().inode);
+        }
+        i += 1;
+    }
+    inos
+}
+
+pub fn test_procfs_self() {
+    let fs = ProcFS::new();
+    let root = fs.root_inode();
+    let link = root.find("self").unwrap();
+    assert_eq!(link.metadata().unwrap().type_, FileType::SymLink);
+    assert!(is_self_link(&link));
+    assert!(!is_self_link(&root));
+    // the target depends on the process reading it
+    assert_eq!(read_link(&link, Some(5)).unwrap(), b"/proc/5");
+    assert_eq!(read_link(&link, None).unwrap(), b"");
+    assert!((2..)
+        .map(|i| root.get_entry(i))
+        .any(|name| name.unwrap() == "self"));
+    println!("test_procfs_self pass");
+}
+
+pub fn test_procfs_shared_fs() {
+    let fs = ProcFS::new();
+    let root = fs.root_inode();
+    let cpuinfo = root.find("cpuinfo").unwrap();
+    // every INode refers to the filesystem it was found in
+    let ptr = |fs: Arc<dyn FileSystem>| Arc::as_ptr(&fs) as *const ();
+    assert_eq!(ptr(root.fs()), Arc::as_ptr(&fs) as *const ());
+    assert_eq!(ptr(cpuinfo.fs()), Arc::as_ptr(&fs) as *const ());
+    assert_ne!(
+        ptr(ProcFS::new().root_inode().fs()),
+        Arc::as_ptr(&fs) as *const ()
+    );
+    println!("test_procfs_shared_fs pass");
+}
+
+pub fn test_procfs_process() {
+    let proc = Process::create_linux(&Job::root(), RamFS::new()).unwrap();
+    let fs = ProcFS::new();
+    let dir = fs.root_inode().find(&format!("{}", proc.id())).unwrap();
+    // the environment can only be read by its owner
+    let cred = proc.linux().credentials();
+    let environ = dir.find("environ").unwrap().metadata().unwrap();
+    assert_eq!(environ.mode, 0o400);
+    assert_eq!(environ.uid, cred.user.effective as usize);
+    assert_eq!(dir.find("cmdline").unwrap().metadata().unwrap().mode, 0o444);
+
+    // the files and open files of a process have inode numbers of their own
+    let mut inos = tree_inos(&dir);
+    let len = inos.len();
+    inos.sort_unstable();
+    inos.dedup();
+    assert_eq!(inos.len(), len);
+    println!("test_procfs_process pass");
+}
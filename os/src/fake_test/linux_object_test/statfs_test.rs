@@ -0,0 +1,67 @@
+use crate::linux_object::fs::mount::{self, MountFlags};
+use crate::linux_object::fs::{File, FileLike, MemBuf, OpenOptions, Pipe};
+use crate::linux_object::net::{SocketType, UnixSocket};
+use crate::{print, println};
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use rcore_fs::vfs::{FileSystem, FileType, INode};
+use rcore_fs_ramfs::RamFS;
+use rcore_fs_sfs::SimpleFileSystem;
+
+const TMPFS_MAGIC: usize = 0x0102_1994;
+const SFS_MAGIC: usize = 0x2f8d_be2a;
+const PIPEFS_MAGIC: usize = 0x5049_5045;
+const SOCKFS_MAGIC: usize = 0x534f_434b;
+
+fn open(inode: Arc<dyn INode>) -> Arc<dyn FileLike> {
+    let options = OpenOptions {
+        read: true,
+        write: false,
+        append: false,
+        nonblock: false,
+        fd_cloexec: false,
+        path_only: false,
+    };
+    File::new(inode, options, String::from("test"))
+}
+
+pub fn test_statfs() {
+    // an SFS on a disk of 64 blocks
+    let disk = Box::leak(vec![0u8; 64 * 4096].into_boxed_slice());
+    let sfs = SimpleFileSystem::create(Arc::new(MemBuf::new(disk)), 64 * 4096).unwrap();
+    let root = mount::mount_root(sfs);
+    let stat = mount::fs_stat(&root).unwrap();
+    assert_eq!(stat.magic, SFS_MAGIC);
+    assert_eq!(stat.info.blocks, 64);
+    let bfree = stat.info.bfree;
+    assert!(bfree > 0 && bfree < 64);
+    let file = root.create("file", FileType::File, 0o644).unwrap();
+    file.write_at(0, &[1; 4096]).unwrap();
+    assert!(mount::fs_stat(&file).unwrap().info.bfree < bfree);
+
+    // a tmpfs, remounted with restrictions
+    let target = root.create("tmp", FileType::Dir, 0o755).unwrap();
+    let path = String::from("/tmp");
+    mount::mount(RamFS::new(), "tmpfs", "tmpfs", &target, path, MountFlags::empty()).unwrap();
+    let tmp = root.find("tmp").unwrap();
+    let stat = mount::fs_stat(&tmp).unwrap();
+    assert_eq!(stat.magic, TMPFS_MAGIC);
+    assert!(stat.flags.is_empty());
+    mount::remount(&tmp, MountFlags::RDONLY | MountFlags::NOSUID).unwrap();
+    let stat = mount::file_fs_stat(open(tmp)).unwrap();
+    assert_eq!(stat.flags, MountFlags::RDONLY | MountFlags::NOSUID);
+    // the root is not changed
+    assert!(mount::fs_stat(&root).unwrap().flags.is_empty());
+
+    // pipes and sockets are on filesystems of their own
+    let (read, _) = Pipe::create_pair();
+    let stat = mount::file_fs_stat(open(Arc::new(read))).unwrap();
+    assert_eq!(stat.magic, PIPEFS_MAGIC);
+    let socket: Arc<dyn FileLike> = UnixSocket::new(SocketType::Stream, false);
+    let stat = mount::file_fs_stat(socket).unwrap();
+    assert_eq!(stat.magic, SOCKFS_MAGIC);
+    assert_eq!(stat.info.blocks, 0);
+    println!("test_statfs pass");
+}
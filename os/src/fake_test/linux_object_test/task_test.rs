@@ -0,0 +1,250 @@
+use super::block_on;
+use crate::linux_object::error::{LxError, LxResult};
+use crate::kernel_hal::user::UserOutPtr;
+use crate::kernel_hal::UserContext;
+use crate::linux_object::process::{
+    fault_process, signal_process, store_clone_tid, wait_child, ChildEvent, CloneFlags, ProcessExt,
+    WaitOptions, WaitResult, WaitTarget,
+};
+use crate::linux_object::signal::{Signal as LinuxSignal, SignalAction, SignalCode, SIG_IGN};
+use crate::linux_object::thread::clone_context;
+use crate::linux_object::time::{clock_ticks, RUsage};
+use crate::zircon_object::object::KernelObject;
+use crate::zircon_object::task::{Job, Process};
+use crate::zircon_object::vm::{MMUFlags, VmObject, PAGE_SIZE};
+use crate::{print, println};
+use alloc::sync::Arc;
+use rcore_fs_ramfs::RamFS;
+
+/// Wait for a child of `parent` without blocking.
+fn wait(
+    parent: &Arc<Process>,
+    target: WaitTarget,
+    options: WaitOptions,
+) -> LxResult<Option<WaitResult>> {
+    block_on(wait_child(parent, target, options | WaitOptions::NOHANG))
+}
+
+pub fn test_wait_stop_continue() {
+    let parent = Process::create_linux(&Job::root(), RamFS::new()).unwrap();
+    let child = Process::fork_from(&parent, false).unwrap();
+    let target = WaitTarget::Pid(child.id());
+    let all = WaitOptions::EXITED | WaitOptions::UNTRACED | WaitOptions::CONTINUED;
+    assert!(wait(&parent, target, all).unwrap().is_none());
+
+    // a stop is only reported with WUNTRACED
+    signal_process(&child, LinuxSignal::SIGSTOP);
+    assert!(wait(&parent, target, WaitOptions::EXITED).unwrap().is_none());
+    let options = WaitOptions::UNTRACED | WaitOptions::NOWAIT;
+    let result = wait(&parent, target, options).unwrap().unwrap();
+    assert_eq!(result.pid, child.id());
+    assert_eq!(result.event, ChildEvent::Stopped(LinuxSignal::SIGSTOP));
+    assert_eq!(result.event.wstatus(), 0x137f);
+    let (code, status) = result.event.siginfo();
+    assert_eq!(code as i32, SignalCode::CLD_STOPPED as i32);
+    assert_eq!(status, LinuxSignal::SIGSTOP as i32);
+    // WNOWAIT leaves the stop to be reported again, once
+    let result = wait(&parent, target, WaitOptions::UNTRACED).unwrap().unwrap();
+    assert_eq!(result.event, ChildEvent::Stopped(LinuxSignal::SIGSTOP));
+    assert!(wait(&parent, target, WaitOptions::UNTRACED).unwrap().is_none());
+
+    // resuming is only reported with WCONTINUED
+    signal_process(&child, LinuxSignal::SIGCONT);
+    assert!(wait(&parent, target, WaitOptions::UNTRACED).unwrap().is_none());
+    let result = wait(&parent, target, WaitOptions::CONTINUED).unwrap().unwrap();
+    assert_eq!(result.event, ChildEvent::Continued);
+    assert_eq!(result.event.wstatus(), 0xffff);
+    let (code, status) = result.event.siginfo();
+    assert_eq!(code as i32, SignalCode::CLD_CONTINUED as i32);
+    assert_eq!(status, LinuxSignal::SIGCONT as i32);
+    assert!(wait(&parent, target, all).unwrap().is_none());
+
+    child.exit(0);
+    assert!(wait(&parent, target, all).unwrap().is_some());
+    parent.exit(0);
+    println!("test_wait_stop_continue pass");
+}
+
+pub fn test_wait_exit() {
+    let parent = Process::create_linux(&Job::root(), RamFS::new()).unwrap();
+    let a = Process::fork_from(&parent, false).unwrap();
+    let b = Process::fork_from(&parent, false).unwrap();
+    let exited = WaitOptions::EXITED;
+
+    // only children can be waited for
+    let result = wait(&parent, WaitTarget::Pid(parent.id()), exited);
+    assert!(matches!(result, Err(LxError::ECHILD)));
+
+    a.exit(3);
+    assert!(wait(&parent, WaitTarget::Pid(b.id()), exited).unwrap().is_none());
+    let result = wait(&parent, WaitTarget::Any, exited).unwrap().unwrap();
+    assert_eq!(result.pid, a.id());
+    assert_eq!(result.event, ChildEvent::Exited(3));
+    assert_eq!(result.event.wstatus(), 0x300);
+    let (code, status) = result.event.siginfo();
+    assert_eq!(code as i32, SignalCode::CLD_EXITED as i32);
+    assert_eq!(status, 3);
+    // the child is reaped
+    let result = wait(&parent, WaitTarget::Pid(a.id()), exited);
+    assert!(matches!(result, Err(LxError::ECHILD)));
+    assert!(wait(&parent, WaitTarget::Any, exited).unwrap().is_none());
+
+    // children in the process group of the caller
+    signal_process(&b, LinuxSignal::SIGKILL);
+    let group = WaitTarget::Group(parent.linux().pgid());
+    let result = wait(&parent, group, exited).unwrap().unwrap();
+    assert_eq!(result.pid, b.id());
+    assert_eq!(result.event, ChildEvent::Killed(LinuxSignal::SIGKILL));
+    assert_eq!(result.event.wstatus(), LinuxSignal::SIGKILL as i32);
+    let (code, _) = result.event.siginfo();
+    assert_eq!(code as i32, SignalCode::CLD_KILLED as i32);
+    let result = wait(&parent, WaitTarget::Any, exited);
+    assert!(matches!(result, Err(LxError::ECHILD)));
+    parent.exit(0);
+    println!("test_wait_exit pass");
+}
+
+pub fn test_fault_signal() {
+    let parent = Process::create_linux(&Job::root(), RamFS::new()).unwrap();
+    let child = Process::fork_from(&parent, false).unwrap();
+    let ignore = SignalAction {
+        handler: SIG_IGN,
+        ..SignalAction::default()
+    };
+    child.linux().set_signal_action(LinuxSignal::SIGSEGV, ignore);
+    signal_process(&child, LinuxSignal::SIGSEGV);
+    let target = WaitTarget::Pid(child.id());
+    assert!(wait(&parent, target, WaitOptions::EXITED).unwrap().is_none());
+
+    // the fault would come again, so it can not be ignored
+    fault_process(&child, LinuxSignal::SIGSEGV);
+    let result = wait(&parent, target, WaitOptions::EXITED).unwrap().unwrap();
+    assert_eq!(result.event, ChildEvent::Killed(LinuxSignal::SIGSEGV));
+    parent.exit(0);
+    println!("test_fault_signal pass");
+}
+
+pub fn test_rusage() {
+    let rusage = RUsage::from_cpu_time(1_500_000_000);
+    assert_eq!((rusage.utime.sec, rusage.utime.usec), (1, 500_000));
+    assert_eq!((rusage.stime.sec, rusage.stime.usec), (0, 0));
+    assert_eq!(clock_ticks(1_500_000_000), 150);
+    println!("test_rusage pass");
+}
+
+pub fn test_clone_flags() {
+    let check = |flags: CloneFlags| CloneFlags::check(flags.bits());
+    let thread = CloneFlags::THREAD
+        | CloneFlags::VM
+        | CloneFlags::FS
+        | CloneFlags::FILES
+        | CloneFlags::SIGHAND;
+    assert_eq!(check(thread).unwrap(), thread);
+    let flags = thread | CloneFlags::SETTLS | CloneFlags::PARENT_SETTID;
+    assert_eq!(check(flags).unwrap(), flags);
+    // the exit signal is in the low byte
+    assert!(CloneFlags::check(17).is_ok());
+    assert!(CloneFlags::check((CloneFlags::VM | CloneFlags::VFORK).bits() | 17).is_ok());
+
+    // threads share everything with the caller
+    let result = check(CloneFlags::THREAD);
+    assert!(matches!(result, Err(LxError::EINVAL)));
+    let result = check(thread - CloneFlags::VM);
+    assert!(matches!(result, Err(LxError::EINVAL)));
+    let result = check(thread - CloneFlags::SIGHAND);
+    assert!(matches!(result, Err(LxError::EINVAL)));
+    let result = check(thread - CloneFlags::FILES);
+    assert!(matches!(result, Err(LxError::EINVAL)));
+    let result = check(thread | CloneFlags::VFORK);
+    assert!(matches!(result, Err(LxError::EINVAL)));
+    let result = check(CloneFlags::SIGHAND);
+    assert!(matches!(result, Err(LxError::EINVAL)));
+
+    // unknown and unsupported flags
+    let result = CloneFlags::check(1 << 12);
+    assert!(matches!(result, Err(LxError::EINVAL)));
+    let result = CloneFlags::check(thread.bits() | 1 << 12);
+    assert!(matches!(result, Err(LxError::EINVAL)));
+    let result = check(CloneFlags::NEWNS);
+    assert!(matches!(result, Err(LxError::EINVAL)));
+    let result = check(CloneFlags::PTRACE);
+    assert!(matches!(result, Err(LxError::EINVAL)));
+    println!("test_clone_flags pass");
+}
+
+pub fn test_clone_settid() {
+    let parent = Process::create_linux(&Job::root(), RamFS::new()).unwrap();
+    let vmo = VmObject::new_paged(1);
+    let flags = MMUFlags::READ | MMUFlags::WRITE | MMUFlags::USER;
+    let addr = parent.vmar().map(None, vmo, 0, PAGE_SIZE, flags).unwrap();
+    let child = Process::fork_from(&parent, false).unwrap();
+    let read_tid = |proc: &Arc<Process>, offset: usize| {
+        let mut buf = [0u8; 4];
+        proc.vmar().read_memory(addr + offset, &mut buf).unwrap();
+        i32::from_ne_bytes(buf)
+    };
+    let parent_tid = UserOutPtr::<i32>::from(addr);
+    let child_tid = UserOutPtr::<i32>::from(addr + 4);
+
+    // nothing is stored without the flags
+    store_clone_tid(
+        CloneFlags::empty(),
+        child.id(),
+        &parent,
+        &child,
+        &parent_tid,
+        &child_tid,
+    );
+    assert_eq!(read_tid(&parent, 0), 0);
+    assert_eq!(read_tid(&child, 4), 0);
+
+    // PARENT_SETTID in the memory of the caller, CHILD_SETTID in the child's
+    let flags = CloneFlags::PARENT_SETTID | CloneFlags::CHILD_SETTID;
+    store_clone_tid(flags, child.id(), &parent, &child, &parent_tid, &child_tid);
+    assert_eq!(read_tid(&parent, 0), child.id() as i32);
+    assert_eq!(read_tid(&child, 0), 0);
+    assert_eq!(read_tid(&child, 4), child.id() as i32);
+    assert_eq!(read_tid(&parent, 4), 0);
+
+    // a thread stores it in the memory of its own process
+    let flags = CloneFlags::VM | CloneFlags::CHILD_SETTID;
+    store_clone_tid(flags, 42, &parent, &child, &parent_tid, &child_tid);
+    assert_eq!(read_tid(&parent, 4), 42);
+
+    // a bad pointer is ignored, and the child is still waited for
+    let unmapped = UserOutPtr::<i32>::from(0x10);
+    let flags = CloneFlags::PARENT_SETTID | CloneFlags::CHILD_SETTID;
+    store_clone_tid(flags, child.id(), &parent, &child, &unmapped, &unmapped);
+    child.exit(0);
+    let target = WaitTarget::Pid(child.id());
+    let result = wait(&parent, target, WaitOptions::EXITED).unwrap().unwrap();
+    assert_eq!(result.pid, child.id());
+    assert_eq!(result.event, ChildEvent::Exited(0));
+    parent.exit(0);
+    println!("test_clone_settid pass");
+}
+
+pub fn test_clone_settls() {
+    let mut context = UserContext::default();
+    context.general.a0 = 220;
+    context.general.sp = 0x8000;
+    context.general.tp = 0x1000;
+    let thread = CloneFlags::THREAD
+        | CloneFlags::VM
+        | CloneFlags::FS
+        | CloneFlags::FILES
+        | CloneFlags::SIGHAND;
+
+    // the thread pointer is only set with SETTLS
+    let new = clone_context(&context, thread, 0x4000, 0x2000);
+    assert_eq!(new.general.a0, 0);
+    assert_eq!(new.general.sp, 0x4000);
+    assert_eq!(new.general.tp, 0x1000);
+    let new = clone_context(&context, thread | CloneFlags::SETTLS, 0, 0x2000);
+    assert_eq!(new.general.a0, 0);
+    assert_eq!(new.general.sp, 0x8000);
+    assert_eq!(new.general.tp, 0x2000);
+    // the caller is not changed
+    assert_eq!(context.general.tp, 0x1000);
+    println!("test_clone_settls pass");
+}
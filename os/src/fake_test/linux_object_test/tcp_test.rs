@@ -0,0 +1,74 @@
+use crate::linux_object::error::LxError;
+use crate::linux_object::net::*;
+use crate::{print, println};
+
+pub fn test_tcp_reassembly() {
+    let mut assembler = Assembler::default();
+    assembler.insert(110, b"klm");
+    assembler.insert(105, b"fghij");
+    // the gap before 105 is not filled
+    assert_eq!(assembler.take(100), None);
+    assert_eq!(assembler.take(105).unwrap(), b"fghij");
+    assert_eq!(assembler.take(110).unwrap(), b"klm");
+    assert!(assembler.is_empty());
+
+    // overlapping and duplicate segments, the longest is kept
+    assembler.insert(200, b"ab");
+    assembler.insert(200, b"abcdef");
+    assembler.insert(200, b"abc");
+    assert_eq!(assembler.take(203).unwrap(), b"def");
+    assert!(assembler.is_empty());
+
+    // segments which were received already are dropped
+    assembler.insert(300, b"xy");
+    assert_eq!(assembler.take(302), None);
+    assert!(assembler.is_empty());
+
+    // sequence numbers wrap around
+    assembler.insert(0xffff_fffe, b"abcd");
+    assert_eq!(assembler.take(0xffff_ffff).unwrap(), b"bcd");
+    println!("test_tcp_reassembly pass");
+}
+
+fn endpoint(port: u16) -> SockAddr {
+    SockAddr::Inet(IpEndpoint::new(IpAddress::UNSPECIFIED_V4, port))
+}
+
+fn reuse_addr(socket: &TcpSocket) {
+    let value = 1u32.to_ne_bytes();
+    socket
+        .setsockopt(SOL_SOCKET, sockopt::SO_REUSEADDR, &value)
+        .unwrap();
+}
+
+pub fn test_tcp_reuse_addr() {
+    let listener = TcpSocket::new(AddressFamily::Inet, true);
+    listener.bind(endpoint(5000)).unwrap();
+    listener.listen(16).unwrap();
+
+    // SO_REUSEADDR does not take over a live listener
+    let other = TcpSocket::new(AddressFamily::Inet, true);
+    assert!(matches!(
+        other.bind(endpoint(5000)),
+        Err(LxError::EADDRINUSE)
+    ));
+    reuse_addr(&other);
+    assert!(matches!(
+        other.bind(endpoint(5000)),
+        Err(LxError::EADDRINUSE)
+    ));
+
+    // nor a bound socket
+    let bound = TcpSocket::new(AddressFamily::Inet, true);
+    bound.bind(endpoint(5001)).unwrap();
+    assert!(matches!(
+        other.bind(endpoint(5001)),
+        Err(LxError::EADDRINUSE)
+    ));
+
+    // the port is free once the socket is closed
+    drop(listener);
+    other.bind(endpoint(5000)).unwrap();
+    other.listen(16).unwrap();
+    println!("test_tcp_reuse_addr pass");
+}
@@ -0,0 +1,105 @@
+use crate::linux_object::fs::Tty;
+use crate::{print, println};
+use alloc::{boxed::Box, sync::Arc, vec::Vec};
+use rcore_fs::vfs::{FsError, INode};
+use spin::Mutex;
+
+const ISIG: u32 = 0o1;
+const ICANON: u32 = 0o2;
+
+/// Create a terminal and the buffer its output goes to.
+fn tty() -> (Arc<Tty>, Arc<Mutex<Vec<u8>>>) {
+    let output = Arc::new(Mutex::new(Vec::new()));
+    let tty = Tty::new(0, {
+        let output = output.clone();
+        Box::new(move |data| output.lock().extend_from_slice(data))
+    });
+    (tty, output)
+}
+
+fn push(tty: &Tty, input: &[u8]) {
+    for &c in input {
+        tty.push(c);
+    }
+}
+
+fn read(tty: &Tty) -> Result<Vec<u8>, FsError> {
+    let mut buf = [0u8; 64];
+    let len = tty.read_at(0, &mut buf)?;
+    Ok(buf[..len].to_vec())
+}
+
+fn set_lflag(tty: &Tty, set: u32, clear: u32) {
+    let mut termios = tty.termios();
+    termios.lflag = (termios.lflag | set) & !clear;
+    tty.set_termios(termios, false);
+}
+
+pub fn test_tty_canonical() {
+    let (tty, output) = tty();
+    push(&tty, b"ab");
+    assert!(matches!(read(&tty), Err(FsError::Again)));
+    push(&tty, b"\rcd\n");
+    // a read returns one line, with the carriage return mapped to a newline
+    assert_eq!(read(&tty).unwrap(), b"ab\n");
+    assert_eq!(read(&tty).unwrap(), b"cd\n");
+    assert_eq!(output.lock().as_slice(), b"ab\r\ncd\r\n");
+    assert_eq!(tty.input_len(), 0);
+
+    // end of file on an empty line
+    push(&tty, &[4]);
+    assert_eq!(read(&tty).unwrap(), b"");
+    println!("test_tty_canonical pass");
+}
+
+pub fn test_tty_erase() {
+    let (tty, output) = tty();
+    push(&tty, b"abc\x7fd\n");
+    assert_eq!(read(&tty).unwrap(), b"abd\n");
+    assert_eq!(output.lock().as_slice(), b"abc\x08 \x08d\r\n");
+
+    // erasing an empty line does nothing
+    push(&tty, b"\x7f\x7fe\n");
+    assert_eq!(read(&tty).unwrap(), b"e\n");
+
+    // ^U kills the line, ^W erases a word
+    push(&tty, b"xy\x15z\n");
+    assert_eq!(read(&tty).unwrap(), b"z\n");
+    push(&tty, b"foo bar \x17baz\n");
+    assert_eq!(read(&tty).unwrap(), b"foo baz\n");
+
+    // ^V takes the next character literally
+    push(&tty, b"a\x16\x7f\n");
+    assert_eq!(read(&tty).unwrap(), b"a\x7f\n");
+    println!("test_tty_erase pass");
+}
+
+pub fn test_tty_noncanonical() {
+    let (tty, _output) = tty();
+    push(&tty, b"ab");
+    // the partial line becomes input when leaving canonical mode
+    set_lflag(&tty, 0, ICANON);
+    push(&tty, b"c\x7f");
+    assert_eq!(tty.input_len(), 4);
+    assert_eq!(read(&tty).unwrap(), b"abc\x7f");
+    assert!(matches!(read(&tty), Err(FsError::Again)));
+    println!("test_tty_noncanonical pass");
+}
+
+pub fn test_tty_isig() {
+    let (tty, output) = tty();
+    // ^C discards the pending input and is echoed
+    push(&tty, b"ab\x03");
+    push(&tty, b"c\n");
+    assert_eq!(read(&tty).unwrap(), b"c\n");
+    assert_eq!(output.lock().as_slice(), b"ab^Cc\r\n");
+
+    // without ISIG it is an ordinary character
+    set_lflag(&tty, 0, ISIG);
+    push(&tty, b"a\x03\n");
+    assert_eq!(read(&tty).unwrap(), b"a\x03\n");
+    set_lflag(&tty, ISIG, 0);
+    push(&tty, b"\x1c\n");
+    assert_eq!(read(&tty).unwrap(), b"\n");
+    println!("test_tty_isig pass");
+}
@@ -0,0 +1,180 @@
+use super::{block_on, poll_once};
+use crate::linux_object::error::LxError;
+use crate::linux_object::fs::{absolute_path, FileLike};
+use crate::linux_object::net::*;
+use crate::{print, println};
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use core::task::Poll;
+use alloc::vec;
+
+fn abstract_addr(name: &[u8]) -> SockAddr {
+    SockAddr::Unix(UnixAddr::Abstract(name.to_vec()))
+}
+
+pub fn test_unix_stream_rights() {
+    let (socket0, socket1) = UnixSocket::new_pair(SocketType::Stream, true);
+    let mut buf = [0u8; 16];
+    assert!(matches!(
+        block_on(socket1.recv(&mut buf, MsgFlags::empty())),
+        Err(LxError::EAGAIN)
+    ));
+
+    // the files are delivered with the first byte of the data sent with them
+    let file = UnixSocket::new(SocketType::Stream, false) as Arc<dyn FileLike>;
+    let sent = block_on(socket0.send(b"hello", None, vec![file.clone()], MsgFlags::empty()));
+    assert_eq!(sent.unwrap(), 5);
+    let msg = block_on(socket1.recv(&mut buf[..2], MsgFlags::empty())).unwrap();
+    assert_eq!(&buf[..msg.len], b"he");
+    assert_eq!(msg.rights.len(), 1);
+    assert_eq!(
+        Arc::as_ptr(&msg.rights[0]) as *const (),
+        Arc::as_ptr(&file) as *const ()
+    );
+    let msg = block_on(socket1.recv(&mut buf, MsgFlags::empty())).unwrap();
+    assert_eq!(&buf[..msg.len], b"llo");
+    assert!(msg.rights.is_empty());
+
+    // a stream socket is connected already
+    assert!(matches!(
+        block_on(socket0.send(b"x", Some(abstract_addr(b"x")), vec![], MsgFlags::empty())),
+        Err(LxError::EISCONN)
+    ));
+    println!("test_unix_stream_rights pass");
+}
+
+pub fn test_unix_dgram_bind() {
+    let server = UnixSocket::new(SocketType::Datagram, true);
+    server.bind(abstract_addr(b"test_unix_dgram_bind")).unwrap();
+    // a socket is bound once, and a name to one socket
+    assert!(matches!(
+        server.bind(abstract_addr(b"other")),
+        Err(LxError::EINVAL)
+    ));
+    let other = UnixSocket::new(SocketType::Datagram, true);
+    assert!(matches!(
+        other.bind(abstract_addr(b"test_unix_dgram_bind")),
+        Err(LxError::EADDRINUSE)
+    ));
+
+    // an unnamed sender is reported as such
+    let to = Some(abstract_addr(b"test_unix_dgram_bind"));
+    assert_eq!(
+        block_on(other.send(b"ping", to, vec![], MsgFlags::empty())).unwrap(),
+        4
+    );
+    let mut buf = [0u8; 2];
+    let msg = block_on(server.recv(&mut buf, MsgFlags::empty())).unwrap();
+    assert_eq!(&buf, b"pi");
+    assert!(msg.flags.contains(MsgFlags::TRUNC));
+    assert_eq!(msg.addr, Some(SockAddr::Unix(UnixAddr::Unnamed)));
+
+    // the name is released when the socket is closed
+    drop(server);
+    other.bind(abstract_addr(b"test_unix_dgram_bind")).unwrap();
+    assert!(matches!(
+        block_on(other.send(
+            b"x",
+            Some(abstract_addr(b"none")),
+            vec![],
+            MsgFlags::empty()
+        )),
+        Err(LxError::ECONNREFUSED)
+    ));
+    println!("test_unix_dgram_bind pass");
+}
+
+pub fn test_unix_sockaddr() {
+    let path = SockAddr::Unix(UnixAddr::Path("/tmp/sock".into()));
+    assert_eq!(SockAddr::from_bytes(&path.to_bytes()).unwrap(), path);
+    let name = abstract_addr(b"\0name");
+    assert_eq!(SockAddr::from_bytes(&name.to_bytes()).unwrap(), name);
+    assert_eq!(
+        SockAddr::from_bytes(&1u16.to_ne_bytes()).unwrap(),
+        SockAddr::Unix(UnixAddr::Unnamed)
+    );
+
+    // relative pathnames are bound from the working directory
+    assert_eq!(absolute_path("/home/user", "sock"), "/home/user/sock");
+    assert_eq!(
+        absolute_path("/home/user", "../.././tmp//sock"),
+        "/tmp/sock"
+    );
+    assert_eq!(absolute_path("/home", "/run/sock"), "/run/sock");
+    assert_eq!(absolute_path("/", ".."), "/");
+    println!("test_unix_sockaddr pass");
+}
+
+pub fn test_unix_connect() {
+    let name = abstract_addr(b"test_unix_connect");
+    // a socket connecting to itself is refused, not deadlocked
+    let socket = UnixSocket::new(SocketType::Stream, true);
+    socket.bind(name.clone()).unwrap();
+    assert!(matches!(
+        block_on(socket.connect(name.clone())),
+        Err(LxError::ECONNREFUSED)
+    ));
+    drop(socket);
+
+    let listener = UnixSocket::new(SocketType::Stream, true);
+    listener.bind(name.clone()).unwrap();
+    listener.listen(0).unwrap();
+    let client0 = UnixSocket::new(SocketType::Stream, true);
+    block_on(client0.connect(name.clone())).unwrap();
+    // the backlog is full: a nonblocking connect fails, a blocking one waits
+    let client1 = UnixSocket::new(SocketType::Stream, true);
+    assert!(matches!(
+        block_on(client1.connect(name.clone())),
+        Err(LxError::EAGAIN)
+    ));
+    let client2 = UnixSocket::new(SocketType::Stream, false);
+    let mut connect = Box::pin(client2.connect(name.clone()));
+    assert!(poll_once(connect.as_mut()).is_pending());
+    let (_server0, _) = block_on(listener.accept()).unwrap();
+    assert!(matches!(poll_once(connect.as_mut()), Poll::Ready(Ok(()))));
+    drop(connect);
+
+    // a waiting connect fails when the listener is closed
+    let client3 = UnixSocket::new(SocketType::Stream, false);
+    let mut connect = Box::pin(client3.connect(name));
+    assert!(poll_once(connect.as_mut()).is_pending());
+    drop(listener);
+    assert!(matches!(
+        poll_once(connect.as_mut()),
+        Poll::Ready(Err(LxError::ECONNREFUSED))
+    ));
+    println!("test_unix_connect pass");
+}
+
+pub fn test_unix_bind_inode() {
+    let path = SockAddr::Unix(UnixAddr::Path("/tmp/sock".into()));
+    let inode = (usize::MAX, 42);
+    // a pathname is bound by the socket inode made for it
+    let listener = UnixSocket::new(SocketType::Stream, true);
+    assert!(matches!(listener.bind(path.clone()), Err(LxError::EINVAL)));
+    listener.bind_inode(path.clone(), inode).unwrap();
+    listener.listen(1).unwrap();
+    let client = UnixSocket::new(SocketType::Stream, true);
+    block_on(client.connect_inode(path.clone(), inode)).unwrap();
+    assert_eq!(client.peer_addr().unwrap(), path);
+    let other = UnixSocket::new(SocketType::Stream, true);
+    assert!(matches!(
+        block_on(other.connect_inode(path.clone(), (usize::MAX, 43))),
+        Err(LxError::ECONNREFUSED)
+    ));
+
+    // a new socket inode with the key of an unlinked one replaces its binding
+    let datagram = UnixSocket::new(SocketType::Datagram, true);
+    datagram.bind_inode(path.clone(), inode).unwrap();
+    assert!(matches!(
+        other.send_to_inode(b"x", inode, vec![]),
+        Err(LxError::EISCONN)
+    ));
+    let sender = UnixSocket::new(SocketType::Datagram, true);
+    assert_eq!(sender.send_to_inode(b"ping", inode, vec![]).unwrap(), 4);
+    drop(listener);
+    assert_eq!(sender.send_to_inode(b"ping", inode, vec![]).unwrap(), 4);
+    let mut buf = [0u8; 4];
+    assert_eq!(block_on(datagram.recv(&mut buf, MsgFlags::empty())).unwrap().len, 4);
+    println!("test_unix_bind_inode pass");
+}
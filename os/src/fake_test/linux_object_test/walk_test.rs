@@ -0,0 +1,26 @@
+use crate::linux_object::error::LxError;
+use crate::linux_object::fs::walk;
+use crate::{print, println};
+use rcore_fs::vfs::{FileSystem, FileType};
+use rcore_fs_ramfs::RamFS;
+
+pub fn test_walk_trailing_slash() {
+    let fs = RamFS::new();
+    let root = fs.root_inode();
+    root.create("dir", FileType::Dir, 0o755).unwrap();
+    root.create("file", FileType::File, 0o644).unwrap();
+    let link = root.create("link", FileType::SymLink, 0o777).unwrap();
+    link.write_at(0, b"dir").unwrap();
+
+    let lookup = |path| walk(&root, root.clone(), path, false, None, None);
+    assert!(lookup("dir/").is_ok());
+    assert!(lookup("/").is_ok());
+    assert!(lookup("file").is_ok());
+    assert!(matches!(lookup("file/"), Err(LxError::ENOTDIR)));
+    // the slash follows a link at the end
+    let dir = lookup("link/").unwrap();
+    assert_eq!(dir.metadata().unwrap().type_, FileType::Dir);
+    let link = lookup("link").unwrap();
+    assert_eq!(link.metadata().unwrap().type_, FileType::SymLink);
+    println!("test_walk_trailing_slash pass");
+}
@@ -0,0 +1,124 @@
+use crate::linux_object::error::LxError;
+use crate::linux_object::fs::mount::{self, MountFlags};
+use crate::linux_object::fs::xattr::*;
+use crate::linux_object::fs::MemBuf;
+use crate::{print, println};
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use rcore_fs::vfs::{FileSystem, FileType, FsError, INode};
+use rcore_fs_ramfs::RamFS;
+use rcore_fs_sfs::SimpleFileSystem;
+
+pub fn test_xattr_encode() {
+    let mut attrs = BTreeMap::new();
+    attrs.insert(String::from("user.a"), vec![]);
+    attrs.insert(String::from("security.b"), vec![1, 2, 3]);
+    let data = encode(&attrs);
+    assert_eq!(decode(&data).unwrap(), attrs);
+    assert!(decode(&[]).unwrap().is_empty());
+    // truncated data is rejected
+    assert!(matches!(decode(&data[..data.len() - 1]), Err(LxError::EIO)));
+    assert!(matches!(decode(&data[..3]), Err(LxError::EIO)));
+    println!("test_xattr_encode pass");
+}
+
+pub fn test_xattr_flags() {
+    let table = XattrTable::default();
+    assert!(matches!(table.get(1, "user.a"), Err(LxError::ENODATA)));
+    assert!(matches!(
+        table.set(1, "user.a", b"x", XattrFlags::REPLACE),
+        Err(LxError::ENODATA)
+    ));
+    table.set(1, "user.a", b"x", XattrFlags::CREATE).unwrap();
+    assert!(matches!(
+        table.set(1, "user.a", b"y", XattrFlags::CREATE),
+        Err(LxError::EEXIST)
+    ));
+    table.set(1, "user.a", b"y", XattrFlags::REPLACE).unwrap();
+    table.set(1, "user.b", b"", XattrFlags::empty()).unwrap();
+    assert_eq!(table.get(1, "user.a").unwrap(), b"y");
+    assert_eq!(table.list(1, false).unwrap(), b"user.a\0user.b\0");
+    assert!(table.list(2, false).unwrap().is_empty());
+    // trusted attributes are only listed for privileged processes
+    table.set(1, "trusted.c", b"z", XattrFlags::empty()).unwrap();
+    assert_eq!(table.list(1, false).unwrap(), b"user.a\0user.b\0");
+    assert_eq!(table.list(1, true).unwrap(), b"trusted.c\0user.a\0user.b\0");
+    table.remove(1, "trusted.c").unwrap();
+    table.remove(1, "user.a").unwrap();
+    assert!(matches!(table.remove(1, "user.a"), Err(LxError::ENODATA)));
+    table.clear(1);
+    assert!(table.list(1, true).unwrap().is_empty());
+    println!("test_xattr_flags pass");
+}
+
+pub fn test_xattr_on_disk() {
+    let fs = RamFS::new();
+    let root = fs.root_inode();
+    let table = XattrTable::on_disk(root.clone());
+    table
+        .set(5, "user.a", b"hello", XattrFlags::empty())
+        .unwrap();
+    table
+        .set(5, "user.b", b"world", XattrFlags::empty())
+        .unwrap();
+    assert!(root.find(XATTR_DIR).unwrap().find("5").is_ok());
+
+    // a new mount reads them from the filesystem
+    let table = XattrTable::on_disk(root.clone());
+    assert_eq!(table.get(5, "user.a").unwrap(), b"hello");
+    assert_eq!(table.list(5, true).unwrap(), b"user.a\0user.b\0");
+    table.remove(5, "user.a").unwrap();
+    let table = XattrTable::on_disk(root.clone());
+    assert!(matches!(table.get(5, "user.a"), Err(LxError::ENODATA)));
+    assert_eq!(table.get(5, "user.b").unwrap(), b"world");
+
+    // the file goes with the last attribute
+    table.clear(5);
+    assert!(root.find(XATTR_DIR).unwrap().find("5").is_err());
+    assert!(XattrTable::on_disk(root).list(5, true).unwrap().is_empty());
+    println!("test_xattr_on_disk pass");
+}
+
+/// Names in the directory `dir`
+fn entries(dir: &Arc<dyn INode>) -> Vec<String> {
+    let mut names = Vec::new();
+    while let Ok(name) = dir.get_entry(names.len()) {
+        names.push(name);
+    }
+    names
+}
+
+pub fn test_xattr_hidden() {
+    // an SFS on a disk of 64 blocks
+    let disk = Box::leak(vec![0u8; 64 * 4096].into_boxed_slice());
+    let sfs = SimpleFileSystem::create(Arc::new(MemBuf::new(disk)), 64 * 4096).unwrap();
+    let root = mount::mount_root(sfs);
+    let file = root.create("file", FileType::File, 0o644).unwrap();
+    let (table, ino) = mount::xattr_table(&file, true).unwrap();
+    table.set(ino, "user.a", b"x", XattrFlags::empty()).unwrap();
+
+    // the attributes are on the filesystem, but not in the tree
+    assert!(mount::fs_inode(&root).find(XATTR_DIR).is_ok());
+    assert!(matches!(root.find(XATTR_DIR), Err(FsError::EntryNotFound)));
+    assert!(matches!(root.unlink(XATTR_DIR), Err(FsError::EntryNotFound)));
+    let result = root.create(XATTR_DIR, FileType::File, 0o644);
+    assert!(matches!(result, Err(FsError::EntryExist)));
+    let result = root.move_("file", &root, XATTR_DIR);
+    assert!(matches!(result, Err(FsError::Busy)));
+    let names = entries(&root);
+    assert!(names.iter().any(|name| name == "file"));
+    assert!(!names.iter().any(|name| name == XATTR_DIR));
+    assert_eq!(table.get(ino, "user.a").unwrap(), b"x");
+
+    // they are not changed on a read-only mount
+    mount::remount(&root, MountFlags::RDONLY).unwrap();
+    let result = mount::xattr_table(&file, true);
+    assert!(matches!(result, Err(LxError::EROFS)));
+    let (table, ino) = mount::xattr_table(&file, false).unwrap();
+    assert_eq!(table.list(ino, false).unwrap(), b"user.a\0");
+    println!("test_xattr_hidden pass");
+}
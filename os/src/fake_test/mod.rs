@@ -1,12 +1,16 @@
 mod trapframe_test;
 mod alloc_test;
+mod drivers_test;
 mod kernel_hal_test;
 mod kernel_hal_bare_test;
+mod trace_test;
 
 pub use trapframe_test::*;
 pub use alloc_test::*;
+pub use drivers_test::*;
 pub use kernel_hal_test::*;
 pub use kernel_hal_bare_test::*;
+pub use trace_test::*;
 
 pub mod zircon_object_test;
 pub mod linux_object_test;
@@ -0,0 +1,55 @@
+use crate::linux_syscall::{self, SyscallType as LinuxSys};
+use crate::zircon_syscall::{self, SyscallType as ZirconSys};
+use crate::{print, println};
+use alloc::format;
+
+pub fn linux_trace_test() {
+    let path = b"/tmp/x\0";
+    let at_fdcwd = -100isize as usize;
+    let args = [at_fdcwd, path.as_ptr() as usize, 0x80041, 0o644, 0, 0];
+    assert_eq!(
+        linux_syscall::decode_args(&LinuxSys::OPENAT, &args),
+        "AT_FDCWD, \"/tmp/x\", WRONLY | CREATE | CLOEXEC, 0o644"
+    );
+    // unknown flags are shown in hex, a bad path as its pointer
+    let args = [3, 0, 0x10002, 0, 0, 0];
+    assert_eq!(
+        linux_syscall::decode_args(&LinuxSys::OPENAT, &args),
+        "3, 0x0, RDWR | 0x10000, 0o0"
+    );
+    // the calls which are not decoded show all their arguments
+    let args = [1, 2, 3, 4, 5, 6];
+    assert_eq!(
+        linux_syscall::decode_args(&LinuxSys::GETPID, &args),
+        "0x1, 0x2, 0x3, 0x4, 0x5, 0x6"
+    );
+    println!("linux_trace_test pass");
+}
+
+pub fn zircon_trace_test() {
+    let s = b"hello";
+    let args = [s.as_ptr() as usize, s.len(), 0, 0, 0, 0, 0, 0];
+    assert_eq!(
+        zircon_syscall::decode_args(&ZirconSys::DEBUG_WRITE, &args),
+        "\"hello\""
+    );
+    // long strings are cut, a bad string is shown as its pointer
+    let s = [b'a'; 40];
+    let args = [s.as_ptr() as usize, s.len(), 0, 0, 0, 0, 0, 0];
+    let expected = format!("{:?}...", "a".repeat(32));
+    assert_eq!(
+        zircon_syscall::decode_args(&ZirconSys::DEBUG_WRITE, &args),
+        expected
+    );
+    let args = [0, 5, 0, 0, 0, 0, 0, 0];
+    assert_eq!(
+        zircon_syscall::decode_args(&ZirconSys::DEBUG_WRITE, &args),
+        "0x0"
+    );
+    let args = [i64::max_value() as usize, 0, 0, 0, 0, 0, 0, 0];
+    assert_eq!(
+        zircon_syscall::decode_args(&ZirconSys::NANOSLEEP, &args),
+        "INFINITE"
+    );
+    println!("zircon_trace_test pass");
+}
@@ -67,7 +67,7 @@ mod tests {
                     futex.requeue(1, 1, 1, &requeue_futex, None),
                     Err(ZxError::BAD_STATE)
                 );
-                assert!(futex.requeue(2, 1, 1, &requeue_futex, None).is_ok());
+                assert_eq!(futex.requeue(2, 1, 1, &requeue_futex, None), Ok(2));
                 // 1 waiter waken, 1 waiter moved into `requeue_futex`.
                 assert_eq!(futex.inner.lock().waiter_queue.len(), 0);
                 assert_eq!(requeue_futex.inner.lock().waiter_queue.len(), 1);
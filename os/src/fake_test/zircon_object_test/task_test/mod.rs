@@ -29,6 +29,7 @@ pub fn test_all_in_task_test() {
     test_properties();
     test_process_exit();
     test_check_policy_process();
+    test_trace_syscalls();
     //test_create_thread();
     println!("all test in task_test pass");
 }
\ No newline at end of file
@@ -190,4 +190,23 @@ pub fn test_check_policy_process() {
         Some(ZxError::BAD_STATE)
     );
     println!("test_check_policy_process pass");
-}
\ No newline at end of file
+}
+pub fn test_trace_syscalls() {
+    let root_job = Job::root();
+    set_syscall_trace_filter(vec!["traced".into()]);
+    let traced = Process::create(&root_job, "traced").expect("failed to create process");
+    let proc = Process::create(&root_job, "proc").expect("failed to create process");
+    assert!(traced.trace_syscalls());
+    assert!(!proc.trace_syscalls());
+
+    set_syscall_trace_filter(vec!["*".into()]);
+    assert!(syscall_trace_filter_matches("proc"));
+    set_syscall_trace_filter(vec![]);
+    assert!(!syscall_trace_filter_matches("traced"));
+
+    proc.set_trace_syscalls(true);
+    assert!(proc.trace_syscalls());
+    traced.set_trace_syscalls(false);
+    assert!(!traced.trace_syscalls());
+    println!("test_trace_syscalls pass");
+}
@@ -23,6 +23,11 @@ pub fn test_all_in_vm_test() {
     test_unmap_vmar();
     test_destroy();
     test_unmap_mapping();
+    test_get_mappings();
+    test_aslr();
+    test_space_limit();
+    test_translate();
+    test_write_memory_private();
     println!("all test in vm_test pass");
 }
 
@@ -1,6 +1,8 @@
+use crate::zircon_object::object::KernelObject;
 use crate::zircon_object::vm::*;
 use crate::zircon_object::ZxError;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 
 use crate::{print, println};
 
@@ -184,4 +186,156 @@ pub fn test_unmap_mapping() {
     assert_eq!(vmar.count(), 1);
     assert_eq!(vmar.used_size(), 0x1000);
     println!("test_unmap_mapping pass");
-}
\ No newline at end of file
+}
+
+pub fn test_get_mappings() {
+    let s = Sample::new();
+    let base = s.root.addr();
+    let vmo = VmObject::new_paged(2);
+    let flags = MMUFlags::READ | MMUFlags::WRITE;
+    s.child2.map_at(0, vmo.clone(), 0, 0x1000, flags).unwrap();
+    s.grandson1
+        .map_at(0, vmo.clone(), 0x1000, 0x1000, MMUFlags::READ)
+        .unwrap();
+
+    let mappings = s.root.get_mappings();
+    assert_eq!(mappings.len(), 2);
+    assert_eq!(mappings[0].addr, base);
+    assert_eq!(mappings[0].flags, MMUFlags::READ);
+    assert_eq!(mappings[0].vmo_offset, 0x1000);
+    assert_eq!(mappings[1].addr, base + 0x2000);
+    assert_eq!(mappings[1].size, 0x1000);
+    assert_eq!(mappings[1].vmo_id, vmo.id());
+    assert_eq!(s.child1.get_mappings().len(), 1);
+    println!("test_get_mappings pass");
+}
+
+pub fn test_aslr() {
+    let flags = VmarFlags::CAN_MAP_RXW;
+
+    // first fit without ASLR
+    set_aslr(false, DEFAULT_ASLR_ENTROPY_BITS);
+    let root = VmAddressRegion::new_root();
+    let base = root.addr();
+    let child1 = root.allocate(None, 0x2000, flags, PAGE_SIZE).unwrap();
+    let child2 = root.allocate(None, 0x1000, flags, PAGE_SIZE).unwrap();
+    assert_eq!(child1.addr(), base);
+    assert_eq!(child2.addr(), base + 0x2000);
+
+    // random placement in the free areas
+    set_aslr(true, DEFAULT_ASLR_ENTROPY_BITS);
+    let root = VmAddressRegion::new_root();
+    let base = root.addr();
+    let mut addrs = Vec::new();
+    for _ in 0..8 {
+        let child = root.allocate(None, 0x10000, flags, 0x10000).unwrap();
+        assert_eq!(child.addr() % 0x10000, 0);
+        assert!(child.addr() >= base);
+        assert!(addrs.iter().all(|&addr| addr != child.addr()));
+        addrs.push(child.addr());
+    }
+    assert!(addrs.iter().any(|&addr| addr != base));
+
+    // children of a COMPACT region are placed next to each other
+    let compact = root
+        .allocate(None, 0x10000, flags | VmarFlags::COMPACT, PAGE_SIZE)
+        .unwrap();
+    let child1 = compact.allocate(None, 0x1000, flags, PAGE_SIZE).unwrap();
+    let child2 = compact.allocate(None, 0x1000, flags, PAGE_SIZE).unwrap();
+    assert_eq!(child1.addr(), compact.addr());
+    assert_eq!(child2.addr(), compact.addr() + 0x1000);
+    println!("test_aslr pass");
+}
+
+pub fn test_space_limit() {
+    let sample = Sample::new();
+    let vmo = VmObject::new_paged(2);
+    let flags = MMUFlags::READ | MMUFlags::WRITE;
+    assert_eq!(sample.root.space_limit(), usize::MAX);
+
+    // the limit is shared by the whole address space
+    sample.grandson1.set_space_limit(0x2000);
+    assert_eq!(sample.root.space_limit(), 0x2000);
+    sample
+        .grandson1
+        .map_at(0, vmo.clone(), 0, 0x1000, flags)
+        .unwrap();
+    sample
+        .child2
+        .map_at(0, vmo.clone(), 0, 0x1000, flags)
+        .unwrap();
+    assert_eq!(sample.root.mapped_size(), 0x2000);
+    assert_eq!(
+        sample.grandson2.map_at(0, vmo.clone(), 0, 0x1000, flags),
+        Err(ZxError::NO_MEMORY)
+    );
+
+    // unmapping makes room again
+    sample.child2.unmap(sample.child2.addr(), 0x1000).unwrap();
+    assert_eq!(sample.root.mapped_size(), 0x1000);
+    sample.grandson2.map_at(0, vmo, 0, 0x1000, flags).unwrap();
+    println!("test_space_limit pass");
+}
+
+pub fn test_translate() {
+    let sample = Sample::new();
+    let vmo = VmObject::new_paged(2);
+    let flags = MMUFlags::READ | MMUFlags::WRITE;
+    let addr1 = sample
+        .grandson1
+        .map_at(0, vmo.clone(), 0x1000, 0x1000, flags)
+        .unwrap();
+    let addr2 = sample
+        .grandson2
+        .map_at(0, vmo.clone(), 0, 0x1000, flags)
+        .unwrap();
+    let addr3 = sample
+        .child2
+        .map_at(0, vmo.clone(), 0x1000, 0x1000, flags)
+        .unwrap();
+
+    // the same page of the VMO is at the same physical address
+    let paddr = sample.root.translate(addr1 + 0x10).unwrap();
+    assert_eq!(paddr % PAGE_SIZE, 0x10);
+    assert_eq!(sample.root.translate(addr3 + 0x10), Ok(paddr));
+    assert_ne!(sample.root.translate(addr2 + 0x10), Ok(paddr));
+
+    // nothing is mapped after unmapping
+    sample.child2.unmap(addr3, 0x1000).unwrap();
+    assert_eq!(sample.root.translate(addr3), Err(ZxError::NOT_FOUND));
+    println!("test_translate pass");
+}
+
+pub fn test_write_memory_private() {
+    let vmo = VmObject::new_paged(1);
+    vmo.write(0, &[1, 2, 3, 4]).unwrap();
+    let flags = MMUFlags::READ | MMUFlags::EXECUTE | MMUFlags::USER;
+    let sample = Sample::new();
+    let other = VmAddressRegion::new_root();
+    let addr = sample.grandson1.map(None, vmo.clone(), 0, PAGE_SIZE, flags).unwrap();
+    let other_addr = other.map(None, vmo.clone(), 0, PAGE_SIZE, flags).unwrap();
+
+    // a read-only mapping gets a copy of the page
+    sample.root.write_memory_private(addr + 1, &[9]).unwrap();
+    let mut buf = [0u8; 4];
+    sample.root.read_memory(addr, &mut buf).unwrap();
+    assert_eq!(buf, [1, 9, 3, 4]);
+    other.read_memory(other_addr, &mut buf).unwrap();
+    assert_eq!(buf, [1, 2, 3, 4]);
+    vmo.read(0, &mut buf).unwrap();
+    assert_eq!(buf, [1, 2, 3, 4]);
+    assert_eq!(sample.root.get_mappings().len(), 1);
+
+    // a shared mapping is never written
+    let shared = VmObject::new_paged(1);
+    let flags = MMUFlags::READ | MMUFlags::WRITE | MMUFlags::USER;
+    let addr = sample
+        .child2
+        .map_shared(None, shared.clone(), 0, PAGE_SIZE, flags, false)
+        .unwrap();
+    let result = sample.root.write_memory_private(addr, &[9]);
+    assert_eq!(result, Err(ZxError::ACCESS_DENIED));
+    shared.read(0, &mut buf).unwrap();
+    assert_eq!(buf, [0; 4]);
+    println!("test_write_memory_private pass");
+}
@@ -24,6 +24,9 @@ pub struct Thread {
 #[allow(improper_ctypes)]
 extern "C" {
     fn hal_frame_dealloc(paddr: &PhysAddr);
+    fn hal_frame_alloc_contiguous(page_num: usize, align_log2: usize) -> Option<PhysAddr>;
+    fn hal_frame_total() -> usize;
+    fn hal_frame_free() -> usize;
 }
 
 impl Thread {
@@ -213,9 +216,8 @@ impl PhysFrame {
 
     #[linkage = "weak"]
     #[export_name = "hal_frame_alloc_contiguous_unimplemented"]
-    pub extern "C" fn alloc_contiguous_base(_size: usize, _align_log2: usize) -> Option<PhysAddr> {
-        println!("unimplemented in src/kernel_hal/dummy.rs impl PhysFrame");
-        unimplemented!()
+    pub extern "C" fn alloc_contiguous_base(size: usize, align_log2: usize) -> Option<PhysAddr> {
+        unsafe { hal_frame_alloc_contiguous(size, align_log2) }
     }
 
     pub fn alloc_contiguous(size: usize, align_log2: usize) -> Vec<Self> {
@@ -253,6 +255,25 @@ impl Drop for PhysFrame {
     }
 }
 
+/// Get the number of physical frames in total and not allocated.
+pub fn frame_stats() -> (usize, usize) {
+    unsafe { (hal_frame_total(), hal_frame_free()) }
+}
+
+/// Get the address of physical memory `paddr` seen by devices doing DMA.
+#[linkage = "weak"]
+#[export_name = "hal_dma_addr_unimplemented"]
+pub fn dma_addr(paddr: PhysAddr) -> usize {
+    crate::kernel_hal_bare::dma_addr(paddr)
+}
+
+/// Get the kernel virtual address at which physical memory `paddr` is mapped.
+#[linkage = "weak"]
+#[export_name = "hal_phys_to_virt_unimplemented"]
+pub fn phys_to_virt(paddr: PhysAddr) -> VirtAddr {
+    crate::kernel_hal_bare::phys_to_virt(paddr)
+}
+
 /// Read physical memory from `paddr` to `buf`.
 #[linkage = "weak"]
 #[export_name = "hal_pmem_read_unimplemented"]
@@ -349,37 +370,38 @@ impl InterruptManager {
     /// Handle IRQ.
     #[linkage = "weak"]
     #[export_name = "hal_irq_handle_unimplemented"]
-    pub fn handle(_irq: u8) {
-        println!("unimplemented in src/kernel_hal/dummy.rs impl InterruptManager");
-        unimplemented!()
+    pub fn handle(irq: u8) {
+        crate::kernel_hal_bare::arch::plic::handle(irq)
+    }
+    /// Wait for an interrupt and handle it, for drivers waiting in the kernel.
+    #[linkage = "weak"]
+    #[export_name = "hal_irq_wait_unimplemented"]
+    pub fn wait() {
+        crate::kernel_hal_bare::arch::plic::wait()
     }
     ///
     #[linkage = "weak"]
     #[export_name = "hal_ioapic_set_handle_unimplemented"]
-    pub fn set_ioapic_handle(_global_irq: u32, _handle: Box<dyn Fn() + Send + Sync>) -> Option<u8> {
-        println!("unimplemented in src/kernel_hal/dummy.rs impl InterruptManager");
-        unimplemented!()
+    pub fn set_ioapic_handle(global_irq: u32, handle: Box<dyn Fn() + Send + Sync>) -> Option<u8> {
+        crate::kernel_hal_bare::arch::plic::set_handle(global_irq, handle)
     }
     /// Add an interrupt handle to an irq
     #[linkage = "weak"]
     #[export_name = "hal_irq_add_handle_unimplemented"]
-    pub fn add_handle(_global_irq: u8, _handle: Box<dyn Fn() + Send + Sync>) -> Option<u8> {
-        println!("unimplemented in src/kernel_hal/dummy.rs impl InterruptManager");
-        unimplemented!()
+    pub fn add_handle(global_irq: u8, handle: Box<dyn Fn() + Send + Sync>) -> Option<u8> {
+        crate::kernel_hal_bare::arch::plic::set_handle(global_irq as u32, handle)
     }
     ///
     #[linkage = "weak"]
     #[export_name = "hal_ioapic_reset_handle_unimplemented"]
-    pub fn reset_ioapic_handle(_global_irq: u32) -> bool {
-        println!("unimplemented in src/kernel_hal/dummy.rs impl InterruptManager");
-        unimplemented!()
+    pub fn reset_ioapic_handle(global_irq: u32) -> bool {
+        crate::kernel_hal_bare::arch::plic::reset_handle(global_irq)
     }
     /// Remove the interrupt handle of an irq
     #[linkage = "weak"]
     #[export_name = "hal_irq_remove_handle_unimplemented"]
-    pub fn remove_handle(_irq: u8) -> bool {
-        println!("unimplemented in src/kernel_hal/dummy.rs impl InterruptManager");
-        unimplemented!()
+    pub fn remove_handle(irq: u8) -> bool {
+        crate::kernel_hal_bare::arch::plic::reset_handle(irq as u32)
     }
     /// Allocate contiguous positions for irq
     #[linkage = "weak"]
@@ -404,17 +426,15 @@ impl InterruptManager {
     /// Enable IRQ.
     #[linkage = "weak"]
     #[export_name = "hal_irq_enable_unimplemented"]
-    pub fn enable(_global_irq: u32) {
-        println!("unimplemented in src/kernel_hal/dummy.rs impl InterruptManager");
-        unimplemented!()
+    pub fn enable(global_irq: u32) {
+        crate::kernel_hal_bare::arch::plic::enable(global_irq)
     }
 
     /// Disable IRQ.
     #[linkage = "weak"]
     #[export_name = "hal_irq_disable_unimplemented"]
-    pub fn disable(_global_irq: u32) {
-        println!("unimplemented in src/kernel_hal/dummy.rs impl InterruptManager");
-        unimplemented!()
+    pub fn disable(global_irq: u32) {
+        crate::kernel_hal_bare::arch::plic::disable(global_irq)
     }
     /// Get IO APIC maxinstr
     #[linkage = "weak"]
@@ -437,9 +457,8 @@ impl InterruptManager {
     }
     #[linkage = "weak"]
     #[export_name = "hal_irq_isvalid_unimplemented"]
-    pub fn is_valid(_irq: u32) -> bool {
-        println!("unimplemented in src/kernel_hal/dummy.rs impl InterruptManager");
-        unimplemented!()
+    pub fn is_valid(irq: u32) -> bool {
+        crate::kernel_hal_bare::arch::plic::is_valid(irq)
     }
 }
 
@@ -506,13 +525,41 @@ pub fn fill_random(buf: &mut [u8]) {
 }
 
 #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+pub fn fill_random(buf: &mut [u8]) {
+    crate::kernel_hal_bare::random::fill_random(buf)
+}
+
+#[cfg(target_arch = "aarch64")]
 pub fn fill_random(_buf: &mut [u8]) {
     // TODO
-    println!("fill_random on riscv");
+}
+
+/// Get the hardware capabilities of the CPUs, as the `AT_HWCAP` of Linux
+#[cfg(target_arch = "x86_64")]
+pub fn hwcap() -> usize {
+    // the feature flags of CPUID leaf 1
+    unsafe { core::arch::x86_64::__cpuid(1).edx as usize }
+}
+
+#[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+pub fn hwcap() -> usize {
+    crate::kernel_hal_bare::arch::isa::hwcap()
 }
 
 #[cfg(target_arch = "aarch64")]
-pub fn fill_random(_buf: &mut [u8]) {
+pub fn hwcap() -> usize {
     // TODO
+    0
+}
+
+/// Mix entropy from the buffer into the random number generator
+#[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+pub fn add_entropy(buf: &[u8]) {
+    crate::kernel_hal_bare::random::add_entropy(buf)
+}
+
+#[cfg(not(any(target_arch = "riscv32", target_arch = "riscv64")))]
+pub fn add_entropy(_buf: &[u8]) {
+    // the hardware generator is used directly
 }
 
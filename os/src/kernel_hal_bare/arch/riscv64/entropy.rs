@@ -0,0 +1,101 @@
+//! Entropy sources for seeding the kernel CPRNG
+//!
+//! - the `seed` CSR of the Zkr extension, when the device tree lists it
+//! - the `rng-seed` property of `/chosen` in the device tree
+//! - jitter of the `time` CSR, always available but weak
+
+use super::fdt;
+use core::sync::atomic::spin_loop_hint;
+use lazy_static::lazy_static;
+use riscv::register::time;
+
+/// Attempts to poll the `seed` CSR before giving up on it
+const SEED_POLL_MAX: usize = 1000;
+
+/// `seed` CSR: 16 bits of entropy are valid
+const OPST_ES16: usize = 0b10;
+/// `seed` CSR: the source has failed
+const OPST_DEAD: usize = 0b11;
+
+lazy_static! {
+    /// Whether any hart lists Zkr in the device tree.
+    ///
+    /// The `seed` CSR traps unless it is implemented and the SBI has set
+    /// `mseccfg.SSEED`, which OpenSBI does when Zkr is present.
+    static ref HAS_ZKR: bool = {
+        let isa = fdt::properties("riscv,isa")
+            .into_iter()
+            .any(|value| value.split(|&b| b == b'_' || b == 0).any(|ext| ext == b"zkr"));
+        let extensions = fdt::properties("riscv,isa-extensions")
+            .into_iter()
+            .any(|value| value.split(|&b| b == 0).any(|ext| ext == b"zkr"));
+        isa || extensions
+    };
+}
+
+/// Get the current value of the `time` CSR.
+pub fn ticks() -> u64 {
+    time::read() as u64
+}
+
+/// Read 16 bits of entropy from the `seed` CSR.
+fn read_seed_csr() -> Option<u16> {
+    for _ in 0..SEED_POLL_MAX {
+        let value: usize;
+        // `seed` must be accessed with a read-write instruction
+        unsafe {
+            asm!("csrrw {0}, 0x015, x0", out(reg) value);
+        }
+        match value >> 30 & 0b11 {
+            OPST_ES16 => return Some(value as u16),
+            OPST_DEAD => return None,
+            // BIST or WAIT
+            _ => spin_loop_hint(),
+        }
+    }
+    None
+}
+
+/// Fill `buf` from the Zkr entropy source.
+///
+/// Return false if there is no such source or it has failed.
+pub fn hw_seed(buf: &mut [u8]) -> bool {
+    if !*HAS_ZKR {
+        return false;
+    }
+    for chunk in buf.chunks_mut(2) {
+        match read_seed_csr() {
+            Some(x) => chunk.copy_from_slice(&x.to_le_bytes()[..chunk.len()]),
+            None => {
+                warn!("entropy: Zkr seed source is dead");
+                return false;
+            }
+        }
+    }
+    true
+}
+
+/// Get the seed the bootloader put in the device tree.
+pub fn dtb_seed() -> Option<&'static [u8]> {
+    fdt::property("chosen", "rng-seed")
+}
+
+/// Fill `buf` with the jitter of the timer around busy loops.
+///
+/// Each byte folds in several samples, since only the low bits of a sample
+/// are unpredictable.
+pub fn timer_jitter(buf: &mut [u8]) {
+    for x in buf.iter_mut() {
+        let mut acc = 0u8;
+        for _ in 0..8 {
+            let start = ticks();
+            // spin for a time depending on the previous sample
+            for _ in 0..(16 + (start & 0x3f)) {
+                spin_loop_hint();
+            }
+            let end = ticks();
+            acc = acc.rotate_left(3) ^ (end.wrapping_sub(start) as u8) ^ (end as u8);
+        }
+        *x = acc;
+    }
+}
@@ -0,0 +1,102 @@
+//! Read-only access to the flattened device tree passed by the SBI
+//!
+//! The address of the tree is saved by `_start` from `a1`. Only what the
+//! kernel needs at boot is looked up, there is no parsed representation.
+
+use alloc::vec::Vec;
+use core::slice;
+
+/// Physical address of the device tree, identity-mapped, 0 if there is none
+#[no_mangle]
+static mut BOOT_DTB: usize = 0;
+
+const FDT_MAGIC: u32 = 0xd00d_feed;
+const FDT_BEGIN_NODE: u32 = 1;
+const FDT_END_NODE: u32 = 2;
+const FDT_PROP: u32 = 3;
+const FDT_NOP: u32 = 4;
+const FDT_END: u32 = 9;
+
+/// Get the device tree blob, if it is valid.
+fn blob() -> Option<&'static [u8]> {
+    let addr = unsafe { BOOT_DTB };
+    if addr == 0 || addr % 4 != 0 {
+        return None;
+    }
+    let header = unsafe { slice::from_raw_parts(addr as *const u8, 8) };
+    if be32(header, 0)? != FDT_MAGIC {
+        return None;
+    }
+    let size = be32(header, 4)? as usize;
+    Some(unsafe { slice::from_raw_parts(addr as *const u8, size) })
+}
+
+fn be32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 4)?;
+    Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// Get the NUL-terminated string at `offset`.
+fn cstr(data: &[u8], offset: usize) -> Option<&str> {
+    let data = data.get(offset..)?;
+    let len = data.iter().position(|&b| b == 0)?;
+    core::str::from_utf8(&data[..len]).ok()
+}
+
+/// Call `f` with the path, name and value of every property.
+///
+/// The path has the node names without the leading `/`, e.g. `cpus/cpu@0`.
+fn walk(f: &mut dyn FnMut(&[&str], &str, &'static [u8])) -> Option<()> {
+    let dtb = blob()?;
+    let struct_off = be32(dtb, 8)? as usize;
+    let strings_off = be32(dtb, 12)? as usize;
+    let mut path: Vec<&str> = Vec::new();
+    let mut pos = struct_off;
+    loop {
+        let token = be32(dtb, pos)?;
+        pos += 4;
+        match token {
+            FDT_BEGIN_NODE => {
+                let name = cstr(dtb, pos)?;
+                pos += (name.len() + 1 + 3) & !3;
+                path.push(name);
+            }
+            FDT_END_NODE => {
+                path.pop();
+            }
+            FDT_PROP => {
+                let len = be32(dtb, pos)? as usize;
+                let name = cstr(dtb, strings_off + be32(dtb, pos + 4)? as usize)?;
+                let value = dtb.get(pos + 8..pos + 8 + len)?;
+                // leave out the root node, which has an empty name
+                f(path.get(1..)?, name, value);
+                pos += 8 + ((len + 3) & !3);
+            }
+            FDT_NOP => {}
+            FDT_END => return Some(()),
+            _ => return None,
+        }
+    }
+}
+
+/// Get the value of the property `name` of the node at `path`, like `chosen`.
+pub fn property(path: &str, name: &str) -> Option<&'static [u8]> {
+    let mut found = None;
+    walk(&mut |node, prop, value| {
+        if found.is_none() && prop == name && node.join("/") == path {
+            found = Some(value);
+        }
+    });
+    found
+}
+
+/// Get the values of the property `name` in all nodes.
+pub fn properties(name: &str) -> Vec<&'static [u8]> {
+    let mut found = Vec::new();
+    walk(&mut |_, prop, value| {
+        if prop == name {
+            found.push(value);
+        }
+    });
+    found
+}
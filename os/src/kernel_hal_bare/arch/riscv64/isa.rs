@@ -0,0 +1,45 @@
+//! Instruction set extensions of the harts
+
+use super::fdt;
+
+/// Get the single-letter extensions implemented by all harts, with bit
+/// `c - 'a'` set for the extension `c`, as the `AT_HWCAP` of Linux.
+///
+/// They are read from the `riscv,isa` property of the CPUs in the device
+/// tree, like `rv64imafdc_zicsr`. Returns 0 if there is none.
+pub fn hwcap() -> usize {
+    fdt::properties("riscv,isa")
+        .into_iter()
+        .map(single_letter_extensions)
+        .fold(None, |all, hart| Some(all.unwrap_or(!0) & hart))
+        .unwrap_or(0)
+}
+
+/// Parse the single-letter extensions of an ISA string.
+fn single_letter_extensions(isa: &[u8]) -> usize {
+    let isa = isa.split(|&b| b == 0).next().unwrap_or(&[]);
+    let letters = match isa.get(..4) {
+        Some(b"rv64") | Some(b"rv32") => &isa[4..],
+        _ => return 0,
+    };
+    let bit = |c: u8| 1usize << (c - b'a');
+    let mut hwcap = 0;
+    let mut after_digit = false;
+    // multi-letter extensions follow the first `_` or a `z`, `s` or `x`
+    for &c in letters
+        .iter()
+        .take_while(|&&c| c != b'_' && !b"zsx".contains(&c))
+    {
+        match c {
+            // a version number, like `2p0`
+            b'0'..=b'9' => {}
+            b'p' if after_digit => {}
+            // `g` is short for `imafd` and the `zicsr` and `zifencei` extensions
+            b'g' => hwcap |= bit(b'i') | bit(b'm') | bit(b'a') | bit(b'f') | bit(b'd'),
+            b'a'..=b'z' => hwcap |= bit(c),
+            _ => break,
+        }
+        after_digit = c.is_ascii_digit();
+    }
+    hwcap
+}
@@ -6,6 +6,11 @@ use riscv::register::satp;
 use crate::{print, println};
 use core::fmt::{Arguments, Write};
 
+pub mod entropy;
+pub mod fdt;
+pub mod isa;
+pub mod plic;
+
 
 /// Page Table
 #[repr(C)]
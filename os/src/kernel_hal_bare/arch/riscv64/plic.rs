@@ -0,0 +1,112 @@
+//! Platform-Level Interrupt Controller of the QEMU virt machine
+//!
+//! Only the S-mode context of hart 0 is used. The kernel runs with
+//! `sstatus.SIE` cleared, so external interrupts are taken in user mode and
+//! handled by the thread which was running, holding no kernel locks.
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use core::ptr::{read_volatile, write_volatile};
+use lazy_static::lazy_static;
+use riscv::register::sie;
+use spin::Mutex;
+
+/// MMIO base of the PLIC, identity-mapped
+const PLIC_BASE: usize = 0x0c00_0000;
+/// S-mode context of hart 0
+const CONTEXT: usize = 1;
+/// Number of interrupt sources, source 0 is reserved
+const IRQ_MAX: u32 = 128;
+
+const PRIORITY: usize = PLIC_BASE;
+const ENABLE: usize = PLIC_BASE + 0x2000 + CONTEXT * 0x80;
+const THRESHOLD: usize = PLIC_BASE + 0x20_0000 + CONTEXT * 0x1000;
+const CLAIM: usize = THRESHOLD + 4;
+
+lazy_static! {
+    static ref HANDLERS: Mutex<BTreeMap<u32, Arc<dyn Fn() + Send + Sync>>> =
+        Mutex::new(BTreeMap::new());
+}
+
+fn read(addr: usize) -> u32 {
+    unsafe { read_volatile(addr as *const u32) }
+}
+
+fn write(addr: usize, value: u32) {
+    unsafe { write_volatile(addr as *mut u32, value) }
+}
+
+/// Whether `irq` is a valid interrupt source.
+pub fn is_valid(irq: u32) -> bool {
+    irq != 0 && irq < IRQ_MAX
+}
+
+/// Register the handler of `irq`, return `None` if it already has one.
+pub fn set_handle(irq: u32, handle: Box<dyn Fn() + Send + Sync>) -> Option<u8> {
+    if !is_valid(irq) {
+        return None;
+    }
+    let mut handlers = HANDLERS.lock();
+    if handlers.contains_key(&irq) {
+        return None;
+    }
+    handlers.insert(irq, Arc::from(handle));
+    Some(irq as u8)
+}
+
+/// Remove the handler of `irq`, return `false` if it has none.
+pub fn reset_handle(irq: u32) -> bool {
+    disable(irq);
+    HANDLERS.lock().remove(&irq).is_some()
+}
+
+/// Enable interrupts from `irq`.
+pub fn enable(irq: u32) {
+    if !is_valid(irq) {
+        return;
+    }
+    write(PRIORITY + irq as usize * 4, 1);
+    let reg = ENABLE + (irq / 32) as usize * 4;
+    write(reg, read(reg) | 1 << (irq % 32));
+    write(THRESHOLD, 0);
+    unsafe { sie::set_sext() };
+}
+
+/// Disable interrupts from `irq`.
+pub fn disable(irq: u32) {
+    if !is_valid(irq) {
+        return;
+    }
+    let reg = ENABLE + (irq / 32) as usize * 4;
+    write(reg, read(reg) & !(1 << (irq % 32)));
+}
+
+/// Handle a supervisor external interrupt.
+///
+/// The sources are claimed from the PLIC, so the trap number is ignored.
+pub fn handle(_trap_num: u8) {
+    loop {
+        let irq = read(CLAIM);
+        if irq == 0 {
+            break;
+        }
+        // call without the lock, the handler may register or remove handlers
+        let handle = HANDLERS.lock().get(&irq).cloned();
+        match handle {
+            Some(handle) => handle(),
+            None => warn!("plic: no handler for irq {}", irq),
+        }
+        write(CLAIM, irq);
+    }
+}
+
+/// Wait for an external interrupt and handle it.
+///
+/// `wfi` returns on a pending interrupt enabled in `sie` even with
+/// `sstatus.SIE` cleared, so a kernel thread can wait for a device without
+/// taking the trap.
+pub fn wait() {
+    unsafe { riscv::asm::wfi() };
+    handle(0);
+}
@@ -30,6 +30,7 @@ use crate::kernel_hal::{
 };
 pub mod arch;
 pub use arch::*;
+pub mod random;
 
 #[allow(improper_ctypes)]
 extern "C" {
@@ -133,10 +134,20 @@ impl Frame {
     }
 }
 
-fn phys_to_virt(paddr: PhysAddr) -> VirtAddr {
+/// Get the kernel virtual address at which physical memory `paddr` is mapped.
+#[export_name = "hal_phys_to_virt"]
+pub fn phys_to_virt(paddr: PhysAddr) -> VirtAddr {
     unsafe { PMEM_BASE + paddr }
 }
 
+/// Get the bus address of physical memory `paddr` for DMA.
+///
+/// The kernel runs identity-mapped, so devices see the kernel virtual address.
+#[export_name = "hal_dma_addr"]
+pub fn dma_addr(paddr: PhysAddr) -> usize {
+    phys_to_virt(paddr)
+}
+
 /// Read physical memory from `paddr` to `buf`.
 #[export_name = "hal_pmem_read"]
 pub fn pmem_read(paddr: PhysAddr, buf: &mut [u8]) {
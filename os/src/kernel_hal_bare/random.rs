@@ -0,0 +1,166 @@
+//! Kernel CPRNG based on ChaCha20
+//!
+//! The generator keeps a 256-bit key. Output is the ChaCha20 keystream under
+//! the key, and after each request the key is replaced by fresh keystream, so
+//! earlier output can not be recovered from the state ("fast key erasure").
+//!
+//! Entropy is absorbed by XORing it into the key and running the key through
+//! ChaCha20 again. The generator is seeded at boot and reseeded from the
+//! hardware sources after `RESEED_BYTES` of output or `RESEED_TICKS` of time,
+//! whichever comes first.
+
+use super::arch::entropy;
+use lazy_static::lazy_static;
+use spin::Mutex;
+
+/// Bytes of output between reseeds
+const RESEED_BYTES: usize = 1 << 20;
+/// Timer ticks between reseeds, 1s at the 10MHz timebase of QEMU virt
+const RESEED_TICKS: u64 = 10_000_000;
+/// Max output under one key
+const MAX_REQUEST: usize = 1 << 16;
+/// Bytes taken from each source when seeding
+const SEED_LEN: usize = 32;
+/// Max entropy added at once, the same as `ZX_CPRNG_ADD_ENTROPY_MAX_LEN`
+pub const MAX_ENTROPY: usize = 256;
+
+/// Nonces separating the uses of ChaCha20
+const NONCE_GENERATE: [u8; 12] = *b"zcore-output";
+const NONCE_ABSORB: [u8; 12] = *b"zcore-absorb";
+
+lazy_static! {
+    static ref CPRNG: Mutex<Cprng> = Mutex::new(Cprng::new());
+}
+
+struct Cprng {
+    key: [u8; 32],
+    seeded: bool,
+    /// output since the last reseed
+    output: usize,
+    /// timer ticks at the last reseed
+    last_reseed: u64,
+}
+
+impl Cprng {
+    fn new() -> Self {
+        Cprng {
+            key: [0; 32],
+            seeded: false,
+            output: 0,
+            last_reseed: 0,
+        }
+    }
+
+    /// Mix `data` into the key.
+    fn absorb(&mut self, data: &[u8]) {
+        for chunk in data.chunks(32) {
+            for (k, x) in self.key.iter_mut().zip(chunk) {
+                *k ^= x;
+            }
+            let block = chacha20_block(&self.key, 0, &NONCE_ABSORB);
+            self.key.copy_from_slice(&block[..32]);
+        }
+    }
+
+    /// Absorb entropy from the hardware sources.
+    fn reseed(&mut self) {
+        let mut seed = [0u8; SEED_LEN];
+        if entropy::hw_seed(&mut seed) {
+            self.absorb(&seed);
+        }
+        // always mixed in, in case the other sources are bad
+        entropy::timer_jitter(&mut seed);
+        self.absorb(&seed);
+        self.absorb(&entropy::ticks().to_le_bytes());
+        self.seeded = true;
+        self.output = 0;
+        self.last_reseed = entropy::ticks();
+    }
+
+    fn fill(&mut self, buf: &mut [u8]) {
+        if !self.seeded
+            || self.output >= RESEED_BYTES
+            || entropy::ticks().wrapping_sub(self.last_reseed) >= RESEED_TICKS
+        {
+            self.reseed();
+        }
+        for request in buf.chunks_mut(MAX_REQUEST) {
+            // block 0 is the next key
+            for (i, out) in request.chunks_mut(64).enumerate() {
+                let block = chacha20_block(&self.key, i as u32 + 1, &NONCE_GENERATE);
+                out.copy_from_slice(&block[..out.len()]);
+            }
+            let block = chacha20_block(&self.key, 0, &NONCE_GENERATE);
+            self.key.copy_from_slice(&block[..32]);
+            self.output += request.len();
+        }
+    }
+}
+
+/// Seed the CPRNG, call once at boot after the heap is ready.
+pub fn init() {
+    let mut cprng = CPRNG.lock();
+    if let Some(seed) = entropy::dtb_seed() {
+        info!(
+            "cprng: {} bytes of rng-seed from the device tree",
+            seed.len()
+        );
+        cprng.absorb(seed);
+    }
+    cprng.reseed();
+}
+
+/// Fill `buf` with random bytes from the kernel CPRNG.
+pub fn fill_random(buf: &mut [u8]) {
+    CPRNG.lock().fill(buf);
+}
+
+/// Mix `data` into the kernel CPRNG.
+///
+/// At most `MAX_ENTROPY` bytes are used.
+pub fn add_entropy(data: &[u8]) {
+    let len = data.len().min(MAX_ENTROPY);
+    CPRNG.lock().absorb(&data[..len]);
+}
+
+/// ChaCha20 block function of RFC 8439.
+pub fn chacha20_block(key: &[u8; 32], counter: u32, nonce: &[u8; 12]) -> [u8; 64] {
+    let mut state = [0u32; 16];
+    state[..4].copy_from_slice(&[0x6170_7865, 0x3320_646e, 0x7962_2d32, 0x6b20_6574]);
+    for (i, word) in key.chunks(4).enumerate() {
+        state[4 + i] = u32::from_le_bytes([word[0], word[1], word[2], word[3]]);
+    }
+    state[12] = counter;
+    for (i, word) in nonce.chunks(4).enumerate() {
+        state[13 + i] = u32::from_le_bytes([word[0], word[1], word[2], word[3]]);
+    }
+
+    let mut x = state;
+    for _ in 0..10 {
+        quarter_round(&mut x, 0, 4, 8, 12);
+        quarter_round(&mut x, 1, 5, 9, 13);
+        quarter_round(&mut x, 2, 6, 10, 14);
+        quarter_round(&mut x, 3, 7, 11, 15);
+        quarter_round(&mut x, 0, 5, 10, 15);
+        quarter_round(&mut x, 1, 6, 11, 12);
+        quarter_round(&mut x, 2, 7, 8, 13);
+        quarter_round(&mut x, 3, 4, 9, 14);
+    }
+
+    let mut out = [0u8; 64];
+    for (i, (a, b)) in x.iter().zip(state.iter()).enumerate() {
+        out[i * 4..i * 4 + 4].copy_from_slice(&a.wrapping_add(*b).to_le_bytes());
+    }
+    out
+}
+
+fn quarter_round(x: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    x[a] = x[a].wrapping_add(x[b]);
+    x[d] = (x[d] ^ x[a]).rotate_left(16);
+    x[c] = x[c].wrapping_add(x[d]);
+    x[b] = (x[b] ^ x[c]).rotate_left(12);
+    x[a] = x[a].wrapping_add(x[b]);
+    x[d] = (x[d] ^ x[a]).rotate_left(8);
+    x[c] = x[c].wrapping_add(x[d]);
+    x[b] = (x[b] ^ x[c]).rotate_left(7);
+}
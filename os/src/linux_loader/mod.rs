@@ -5,16 +5,17 @@
 use {
     alloc::{boxed::Box, string::String, sync::Arc, vec::Vec},
     core::{future::Future, pin::Pin},
-    crate::kernel_hal::{GeneralRegs, MMUFlags},
+    crate::kernel_hal::{MMUFlags, UserContext},
     crate::linux_object::{
         fs::{vfs::FileSystem, INodeExt},
         loader::LinuxElfLoader,
-        process::ProcessExt,
-        thread::ThreadExt,
+        process::{check_cpu_limit, fault_process, signal_process, ProcessExt},
+        ptrace,
+        signal::Signal as LinuxSignal,
+        thread::{CurrentThreadExt, ThreadExt},
     },
     crate::linux_syscall::Syscall,
     crate::zircon_object::task::*,
-    crate::println,
 };
 
 /// Create and run main Linux process
@@ -27,13 +28,22 @@ pub fn run(args: Vec<String>, envs: Vec<String>, rootfs: Arc<dyn FileSystem>) ->
         syscall_entry: kernel_hal_unix::syscall_entry as usize,
         #[cfg(not(feature = "std"))]
         syscall_entry: 0,
-        stack_pages: 8,
+        stack_pages: proc.linux().rlimits().stack_pages(),
         root_inode: rootfs.root_inode(),
+        cred: proc.linux().credentials(),
+        caller_cred: proc.linux().credentials(),
     };
     let inode = rootfs.root_inode().lookup(&args[0]).unwrap();
     let data = inode.read_as_vec().unwrap();
     let path = args[0].clone();
-    let (entry, sp) = loader.load(&proc.vmar(), &data, args, envs, path).unwrap();
+    proc.linux().set_execute_path(&path);
+    if syscall_trace_filter_matches(path.rsplit('/').next().unwrap()) {
+        proc.set_trace_syscalls(true);
+    }
+    proc.linux().set_args(args.clone(), envs.clone());
+    let vmar = proc.vmar();
+    let program = loader.prepare(&vmar, data, args, envs, path).unwrap();
+    let (entry, sp) = loader.load(&vmar, program).unwrap();
 
     thread
         .start(entry, sp, 0, 0, thread_fn)
@@ -54,21 +64,70 @@ async fn new_thread(thread: CurrentThread) {
         // wait
         let mut cx = thread.wait_for_run().await;
         if thread.state() == ThreadState::Dying {
+            thread.release_robust_futexes();
+            thread.proc().linux().add_exited_time(thread.get_time());
             break;
         }
         // run
         trace!("go to user: {:#x?}", cx);
+        let tmp_time = crate::kernel_hal::timer_now().as_nanos();
         crate::kernel_hal::context_run(&mut cx);
+        let time = crate::kernel_hal::timer_now().as_nanos() - tmp_time;
+        thread.time_add(time);
         trace!("back from user: {:#x?}", cx);
         // handle trap/interrupt/syscall
         #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
-        println!("should be handled in linux_loader/mod.rs async fn new_thread");
+        {
+            use riscv::register::{
+                scause::{self, Exception, Interrupt, Trap},
+                stval,
+            };
+            let scause = scause::read();
+            match scause.cause() {
+                Trap::Exception(Exception::UserEnvCall) => handle_syscall(&thread, &mut cx).await,
+                // a tracer is told of the breakpoint by the stop of SIGTRAP
+                Trap::Exception(Exception::Breakpoint) => {
+                    signal_process(thread.proc(), LinuxSignal::SIGTRAP)
+                }
+                Trap::Interrupt(Interrupt::SupervisorTimer) => {
+                    crate::kernel_hal::timer_tick();
+                    check_cpu_limit(thread.proc());
+                    crate::kernel_hal::yield_now().await;
+                }
+                // the PLIC tells the source, the trap number is ignored
+                Trap::Interrupt(Interrupt::SupervisorExternal) => {
+                    crate::kernel_hal::InterruptManager::handle(scause.code() as u8)
+                }
+                Trap::Exception(Exception::LoadPageFault) => {
+                    handle_page_fault(&thread, stval::read(), MMUFlags::READ)
+                }
+                Trap::Exception(Exception::StorePageFault) => {
+                    handle_page_fault(&thread, stval::read(), MMUFlags::WRITE)
+                }
+                Trap::Exception(Exception::InstructionPageFault) => {
+                    handle_page_fault(&thread, stval::read(), MMUFlags::EXECUTE)
+                }
+                Trap::Exception(Exception::IllegalInstruction) => {
+                    fault_process(thread.proc(), LinuxSignal::SIGILL)
+                }
+                Trap::Exception(cause) => {
+                    warn!("{:?} from user mode at {:#x}", cause, stval::read());
+                    fault_process(thread.proc(), LinuxSignal::SIGSEGV)
+                }
+                Trap::Interrupt(cause) => warn!("unhandled interrupt from user mode: {:?}", cause),
+            }
+        }
         #[cfg(target_arch = "x86_64")]
         match cx.trap_num {
-            0x100 => handle_syscall(&thread, &mut cx.general).await,
+            0x100 => handle_syscall(&thread, &mut cx).await,
+            // debug exception of the trap flag
+            0x1 => ptrace::single_step_stop(&thread, &mut cx).await,
+            // int3, a tracer is told of it by the stop of SIGTRAP
+            0x3 => signal_process(thread.proc(), LinuxSignal::SIGTRAP),
             0x20..=0x3f => {
                 crate::kernel_hal::InterruptManager::handle(cx.trap_num as u8);
                 if cx.trap_num == 0x20 {
+                    check_cpu_limit(thread.proc());
                     crate::kernel_hal::yield_now().await;
                 }
             }
@@ -79,36 +138,49 @@ async fn new_thread(thread: CurrentThread) {
                 } else {
                     MMUFlags::WRITE
                 };
-                error!("page fualt from user mode {:#x} {:#x?}", vaddr, flags);
-                let vmar = thread.proc().vmar();
-                match vmar.handle_page_fault(vaddr, flags) {
-                    Ok(()) => {}
-                    Err(_) => {
-                        panic!("Page Fault from user mode {:#x?}", cx);
-                    }
-                }
+                handle_page_fault(&thread, vaddr, flags);
             }
             _ => panic!("not supported interrupt from user mode. {:#x?}", cx),
         }
+        // a signal or an event for the tracer
+        ptrace::check_stop(&thread, &mut cx).await;
         thread.end_running(cx);
     }
 }
 
+/// Handle a page fault from user mode at `vaddr`, the process is sent
+/// `SIGSEGV` if the access is not allowed by its mappings.
+fn handle_page_fault(thread: &CurrentThread, vaddr: usize, flags: MMUFlags) {
+    debug!("page fault from user mode {:#x} {:#x?}", vaddr, flags);
+    if let Err(err) = thread.proc().vmar().handle_page_fault(vaddr, flags) {
+        info!("bad access {:#x?} to {:#x}: {:?}", flags, vaddr, err);
+        fault_process(thread.proc(), LinuxSignal::SIGSEGV);
+    }
+}
+
 fn thread_fn(thread: CurrentThread) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
     Box::pin(new_thread(thread))
 }
 
 /// syscall handler entry
-async fn handle_syscall(thread: &CurrentThread, regs: &mut GeneralRegs) {
-    trace!("syscall: {:#x?}", regs);
+async fn handle_syscall(thread: &CurrentThread, context: &mut UserContext) {
+    trace!("syscall: {:#x?}", context.general);
+    // return to the instruction after `ecall`
+    #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+    {
+        context.sepc += 4;
+    }
+    // the tracer may change the system call at its entry
+    ptrace::syscall_stop(thread, context).await;
+    let regs = &context.general;
     #[cfg(target_arch = "x86_64")]
     let num = regs.rax as u32;
     #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
-    let num = unimplemented!();
+    let num = regs.a7 as u32;
     #[cfg(target_arch = "x86_64")]
     let args = [regs.rdi, regs.rsi, regs.rdx, regs.r10, regs.r8, regs.r9];
     #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
-    let args = unimplemented!();
+    let args = [regs.a0, regs.a1, regs.a2, regs.a3, regs.a4, regs.a5];
     let mut syscall = Syscall {
         thread,
         #[cfg(feature = "std")]
@@ -116,15 +188,18 @@ async fn handle_syscall(thread: &CurrentThread, regs: &mut GeneralRegs) {
         #[cfg(not(feature = "std"))]
         syscall_entry: 0,
         thread_fn,
-        regs,
+        context,
     };
     let ret = syscall.syscall(num, args).await as usize;
     #[cfg(target_arch = "x86_64")]
     {
-        syscall.regs.rax = ret;
+        syscall.context.general.rax = ret;
     }
     #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
     {
-        unimplemented!();
+        syscall.context.general.a0 = ret;
     }
+    // the stop after `execve` comes before the exit of the system call
+    ptrace::check_stop(thread, context).await;
+    ptrace::syscall_stop(thread, context).await;
 }
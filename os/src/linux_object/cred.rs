@@ -0,0 +1,312 @@
+//! Process credentials and file permission checks
+//!
+//! A process has a real, effective, saved and filesystem ID for both its user
+//! and its group, and a list of supplementary groups. File access is checked
+//! with the filesystem IDs against the owner and mode bits in the metadata of
+//! the INode. There are no capabilities: an effective user ID of 0 grants all
+//! of them, and so does a filesystem user ID of 0 for file access.
+
+use super::error::*;
+use super::fs::xattr::XattrNamespace;
+use alloc::{sync::Arc, vec::Vec};
+use bitflags::bitflags;
+use rcore_fs::vfs::{FileType, FsError, INode, Metadata};
+
+/// Set-user-ID bit of the file mode
+pub const S_ISUID: u16 = 0o4000;
+/// Set-group-ID bit of the file mode
+pub const S_ISGID: u16 = 0o2000;
+/// Sticky bit of the file mode
+pub const S_ISVTX: u16 = 0o1000;
+
+/// Max number of supplementary groups
+pub const NGROUPS_MAX: usize = 65536;
+
+bitflags! {
+    /// Access to a file, as the `mode` of `access`
+    pub struct Access: u32 {
+        /// execute a file, or search a directory
+        const EXEC = 1;
+        /// write
+        const WRITE = 2;
+        /// read
+        const READ = 4;
+    }
+}
+
+/// The user or group IDs of a process
+#[derive(Debug, Default, Copy, Clone)]
+pub struct IdSet {
+    /// real ID, who owns the process
+    pub real: u32,
+    /// effective ID, used for permission checks
+    pub effective: u32,
+    /// saved ID, the effective ID to switch back to
+    pub saved: u32,
+    /// filesystem ID, used for file access, follows the effective ID
+    pub fs: u32,
+}
+
+impl IdSet {
+    fn contains(&self, id: u32) -> bool {
+        id == self.real || id == self.effective || id == self.saved
+    }
+
+    /// Set the IDs as by `setuid`.
+    ///
+    /// Privileged processes set all IDs, others can only set the effective ID
+    /// to the real or saved ID.
+    pub fn set(&mut self, id: u32, privileged: bool) -> LxResult {
+        if privileged {
+            self.real = id;
+            self.saved = id;
+        } else if id != self.real && id != self.saved {
+            return Err(LxError::EPERM);
+        }
+        self.effective = id;
+        self.fs = id;
+        Ok(())
+    }
+
+    /// Set the real and effective IDs as by `setreuid`, `None` for no change.
+    ///
+    /// The saved ID becomes the effective ID if the real ID is set or the
+    /// effective ID differs from the old real ID.
+    pub fn set_re(
+        &mut self,
+        real: Option<u32>,
+        effective: Option<u32>,
+        privileged: bool,
+    ) -> LxResult {
+        if !privileged {
+            if let Some(id) = real {
+                if id != self.real && id != self.effective {
+                    return Err(LxError::EPERM);
+                }
+            }
+            if let Some(id) = effective {
+                if !self.contains(id) {
+                    return Err(LxError::EPERM);
+                }
+            }
+        }
+        let old_real = self.real;
+        if let Some(id) = real {
+            self.real = id;
+        }
+        if let Some(id) = effective {
+            self.effective = id;
+        }
+        if real.is_some() || effective.map_or(false, |id| id != old_real) {
+            self.saved = self.effective;
+        }
+        self.fs = self.effective;
+        Ok(())
+    }
+
+    /// Set the real, effective and saved IDs as by `setresuid`, `None` for no
+    /// change.
+    ///
+    /// Unprivileged processes can set each to one of the current IDs.
+    pub fn set_res(
+        &mut self,
+        real: Option<u32>,
+        effective: Option<u32>,
+        saved: Option<u32>,
+        privileged: bool,
+    ) -> LxResult {
+        let ids = [real, effective, saved];
+        if !privileged && ids.iter().flatten().any(|&id| !self.contains(id)) {
+            return Err(LxError::EPERM);
+        }
+        if let Some(id) = real {
+            self.real = id;
+        }
+        if let Some(id) = effective {
+            self.effective = id;
+        }
+        if let Some(id) = saved {
+            self.saved = id;
+        }
+        self.fs = self.effective;
+        Ok(())
+    }
+
+    /// Set the filesystem ID as by `setfsuid`, return the old one.
+    ///
+    /// The ID is left unchanged if the process is not allowed to set it.
+    pub fn set_fs(&mut self, id: u32, privileged: bool) -> u32 {
+        let old = self.fs;
+        if privileged || self.contains(id) || id == self.fs {
+            self.fs = id;
+        }
+        old
+    }
+}
+
+/// Credentials of a process
+#[derive(Debug, Default, Clone)]
+pub struct Credentials {
+    /// user IDs
+    pub user: IdSet,
+    /// group IDs
+    pub group: IdSet,
+    /// supplementary groups
+    pub groups: Vec<u32>,
+}
+
+impl Credentials {
+    /// Whether the process may change its credentials and file owners.
+    pub fn is_privileged(&self) -> bool {
+        self.user.effective == 0
+    }
+
+    /// Whether the process is exempt from file permission checks.
+    pub fn overrides_permissions(&self) -> bool {
+        self.user.fs == 0
+    }
+
+    /// Whether the process is in the group `gid` for file access.
+    pub fn in_group(&self, gid: u32) -> bool {
+        self.group.fs == gid || self.groups.contains(&gid)
+    }
+
+    /// Whether the process owns the file, or may act as its owner.
+    pub fn is_owner(&self, metadata: &Metadata) -> bool {
+        self.overrides_permissions() || self.user.fs == metadata.uid as u32
+    }
+
+    /// Check `access` to a file with `metadata`.
+    pub fn check_access(&self, metadata: &Metadata, access: Access) -> LxResult {
+        let mode = metadata.mode as u32;
+        if self.overrides_permissions() {
+            // execution still needs an execute bit on files
+            let executable = metadata.type_ == FileType::Dir || mode & 0o111 != 0;
+            if access.contains(Access::EXEC) && !executable {
+                return Err(LxError::EACCES);
+            }
+            return Ok(());
+        }
+        let granted = if self.user.fs == metadata.uid as u32 {
+            mode >> 6
+        } else if self.in_group(metadata.gid as u32) {
+            mode >> 3
+        } else {
+            mode
+        } & 0o7;
+        if access.bits() & !granted != 0 {
+            return Err(LxError::EACCES);
+        }
+        Ok(())
+    }
+
+    /// Check `access` to `inode`.
+    pub fn check_inode(&self, inode: &Arc<dyn INode>, access: Access) -> LxResult {
+        self.check_access(&inode.metadata()?, access)
+    }
+
+    /// Check whether `inode` in the directory `dir` can be removed or renamed.
+    ///
+    /// In a sticky directory, only the owners of the file or the directory can.
+    pub fn check_delete(&self, dir: &Arc<dyn INode>, inode: &Arc<dyn INode>) -> LxResult {
+        let dir_metadata = dir.metadata()?;
+        self.check_access(&dir_metadata, Access::WRITE | Access::EXEC)?;
+        if dir_metadata.mode & S_ISVTX != 0
+            && !self.is_owner(&dir_metadata)
+            && !self.is_owner(&inode.metadata()?)
+        {
+            return Err(LxError::EPERM);
+        }
+        Ok(())
+    }
+
+    /// Check whether the extended attributes of `inode` in `namespace` can be
+    /// read, or changed if `write`.
+    ///
+    /// Everyone can read security attributes, but only privileged processes
+    /// can change them. Trusted attributes are only for privileged
+    /// processes. User attributes are only on files and directories and
+    /// follow the mode of the INode, and those of a sticky directory can only
+    /// be changed by its owner.
+    pub fn check_xattr(
+        &self,
+        inode: &Arc<dyn INode>,
+        namespace: XattrNamespace,
+        write: bool,
+    ) -> LxResult {
+        let metadata = inode.metadata()?;
+        match namespace {
+            XattrNamespace::Security if write && !self.is_privileged() => Err(LxError::EPERM),
+            XattrNamespace::Security => Ok(()),
+            XattrNamespace::Trusted if !self.is_privileged() => Err(LxError::EPERM),
+            XattrNamespace::Trusted => Ok(()),
+            XattrNamespace::User => {
+                match metadata.type_ {
+                    FileType::File | FileType::Dir => {}
+                    _ if write => return Err(LxError::EPERM),
+                    _ => return Err(LxError::ENODATA),
+                }
+                let sticky = metadata.type_ == FileType::Dir && metadata.mode & S_ISVTX != 0;
+                if write && sticky && !self.is_owner(&metadata) {
+                    return Err(LxError::EPERM);
+                }
+                let access = if write { Access::WRITE } else { Access::READ };
+                self.check_access(&metadata, access)
+            }
+        }
+    }
+
+    /// Whether the process may trace a process with the credentials `target`.
+    ///
+    /// The real IDs of the tracer must match all the user and group IDs of
+    /// the target, unless the tracer is privileged.
+    pub fn may_trace(&self, target: &Credentials) -> bool {
+        let same = |tracer: &IdSet, target: &IdSet| {
+            tracer.real == target.real
+                && tracer.real == target.effective
+                && tracer.real == target.saved
+        };
+        self.is_privileged() || (same(&self.user, &target.user) && same(&self.group, &target.group))
+    }
+
+    /// Whether a program runs with other effective IDs than its real ones,
+    /// as after executing a set-user-ID file, so that its libc must not trust
+    /// the environment.
+    pub fn secure_exec(&self) -> bool {
+        self.user.effective != self.user.real || self.group.effective != self.group.real
+    }
+
+    /// Make the process the owner of the newly created `inode`.
+    pub fn set_owner(&self, inode: &Arc<dyn INode>) -> LxResult {
+        let mut metadata = inode.metadata()?;
+        if metadata.uid == self.user.fs as usize && metadata.gid == self.group.fs as usize {
+            return Ok(());
+        }
+        metadata.uid = self.user.fs as usize;
+        metadata.gid = self.group.fs as usize;
+        match inode.set_metadata(&metadata) {
+            // the filesystem does not keep owners
+            Ok(()) | Err(FsError::NotSupported) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Update the credentials when executing the file with `metadata`.
+    ///
+    /// The set-user-ID and set-group-ID bits change the effective IDs, which
+    /// are then saved. They are ignored unless `set_ids`, as for a traced
+    /// process.
+    pub fn exec(&mut self, metadata: &Metadata, set_ids: bool) {
+        if set_ids && metadata.mode & S_ISUID != 0 {
+            self.user.effective = metadata.uid as u32;
+        }
+        // without group execute permission the bit marks mandatory locking
+        if set_ids && metadata.mode & S_ISGID != 0 && metadata.mode & 0o010 != 0 {
+            self.group.effective = metadata.gid as u32;
+        }
+        self.user.saved = self.user.effective;
+        self.user.fs = self.user.effective;
+        self.group.saved = self.group.effective;
+        self.group.fs = self.group.effective;
+    }
+}
@@ -95,24 +95,54 @@ pub enum LxError {
     ENOTEMPTY = 39,
     /// Too many symbolic links encountered
     ELOOP = 40,
+    /// No message of desired type
+    ENOMSG = 42,
     /// Identifier removed
     EIDRM = 43,
+    /// No data available
+    ENODATA = 61,
     /// Socket operation on non-socket
     ENOTSOCK = 88,
+    /// Destination address required
+    EDESTADDRREQ = 89,
+    /// Message too long
+    EMSGSIZE = 90,
+    /// Protocol wrong type for socket
+    EPROTOTYPE = 91,
     /// Protocol not available
     ENOPROTOOPT = 92,
+    /// Protocol not supported
+    EPROTONOSUPPORT = 93,
+    /// Operation not supported on transport endpoint
+    EOPNOTSUPP = 95,
     /// Protocol family not supported
     EPFNOSUPPORT = 96,
     /// Address family not supported by protocol
     EAFNOSUPPORT = 97,
+    /// Address already in use
+    EADDRINUSE = 98,
+    /// Cannot assign requested address
+    EADDRNOTAVAIL = 99,
+    /// Network is unreachable
+    ENETUNREACH = 101,
+    /// Software caused connection abort
+    ECONNABORTED = 103,
+    /// Connection reset by peer
+    ECONNRESET = 104,
     /// No buffer space available
     ENOBUFS = 105,
     /// Transport endpoint is already connected
     EISCONN = 106,
     /// Transport endpoint is not connected
     ENOTCONN = 107,
+    /// Connection timed out
+    ETIMEDOUT = 110,
     /// Connection refused
     ECONNREFUSED = 111,
+    /// Operation already in progress
+    EALREADY = 114,
+    /// Operation now in progress
+    EINPROGRESS = 115,
 }
 
 #[allow(non_snake_case)]
@@ -160,15 +190,30 @@ impl fmt::Display for LxError {
             ENOSYS => "Function not implemented",
             ENOTEMPTY => "Directory not empty",
             ELOOP => "Too many symbolic links encountered",
+            ENOMSG => "No message of desired type",
             EIDRM => "Identifier removed",
+            ENODATA => "No data available",
             ENOTSOCK => "Socket operation on non-socket",
+            EDESTADDRREQ => "Destination address required",
+            EMSGSIZE => "Message too long",
+            EPROTOTYPE => "Protocol wrong type for socket",
             ENOPROTOOPT => "Protocol not available",
+            EPROTONOSUPPORT => "Protocol not supported",
+            EOPNOTSUPP => "Operation not supported on transport endpoint",
             EPFNOSUPPORT => "Protocol family not supported",
             EAFNOSUPPORT => "Address family not supported by protocol",
+            EADDRINUSE => "Address already in use",
+            EADDRNOTAVAIL => "Cannot assign requested address",
+            ENETUNREACH => "Network is unreachable",
+            ECONNABORTED => "Software caused connection abort",
+            ECONNRESET => "Connection reset by peer",
             ENOBUFS => "No buffer space available",
             EISCONN => "Transport endpoint is already connected",
             ENOTCONN => "Transport endpoint is not connected",
+            ETIMEDOUT => "Connection timed out",
             ECONNREFUSED => "Connection refused",
+            EALREADY => "Operation already in progress",
+            EINPROGRESS => "Operation now in progress",
             _ => "Unknown error",
         };
         write!(f, "{}", explain)
@@ -184,6 +229,7 @@ impl From<ZxError> for LxError {
             ZxError::SHOULD_WAIT => LxError::EAGAIN,
             ZxError::PEER_CLOSED => LxError::EPIPE,
             ZxError::BAD_HANDLE => LxError::EBADF,
+            ZxError::NO_MEMORY => LxError::ENOMEM,
             _ => unimplemented!("unknown error type: {:?}", e),
         }
     }
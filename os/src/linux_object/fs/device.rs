@@ -1,6 +1,10 @@
 //! Implement Device
 
+use crate::drivers::{BlockDevice, BLOCK_SIZE};
+use alloc::sync::Arc;
+use core::any::Any;
 use rcore_fs::dev::*;
+use rcore_fs::vfs;
 use spin::RwLock;
 
 /// memory buffer for device
@@ -30,3 +34,150 @@ impl Device for MemBuf {
         Ok(())
     }
 }
+
+/// disk for filesystems and the INode of `/dev/vdX`
+///
+/// Accesses need not be aligned to blocks, partial blocks are read and
+/// written back as a whole.
+#[derive(Clone)]
+pub struct BlockDev {
+    device: Arc<dyn BlockDevice>,
+    minor: u32,
+}
+
+impl BlockDev {
+    /// create a BlockDev struct, `index` is the index of the disk: 0 for vda
+    pub fn new(device: Arc<dyn BlockDevice>, index: usize) -> Self {
+        BlockDev {
+            device,
+            // each disk has 16 minors for its partitions, like Linux
+            minor: index as u32 * 16,
+        }
+    }
+
+    fn size(&self) -> usize {
+        self.device.num_blocks() as usize * BLOCK_SIZE
+    }
+}
+
+impl Device for BlockDev {
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize> {
+        let size = self.size();
+        if offset >= size {
+            return Ok(0);
+        }
+        let len = buf.len().min(size - offset);
+        let mut done = 0;
+        while done < len {
+            let pos = offset + done;
+            let block = (pos / BLOCK_SIZE) as u64;
+            let begin = pos % BLOCK_SIZE;
+            if begin == 0 && len - done >= BLOCK_SIZE {
+                // whole blocks go straight to the buffer
+                let n = (len - done) / BLOCK_SIZE * BLOCK_SIZE;
+                let dst = &mut buf[done..done + n];
+                self.device.read_blocks(block, dst).map_err(|_| DevError)?;
+                done += n;
+            } else {
+                let mut tmp = [0u8; BLOCK_SIZE];
+                self.device
+                    .read_blocks(block, &mut tmp)
+                    .map_err(|_| DevError)?;
+                let n = (BLOCK_SIZE - begin).min(len - done);
+                buf[done..done + n].copy_from_slice(&tmp[begin..begin + n]);
+                done += n;
+            }
+        }
+        Ok(len)
+    }
+
+    fn write_at(&self, offset: usize, buf: &[u8]) -> Result<usize> {
+        if self.device.read_only() {
+            return Err(DevError);
+        }
+        let size = self.size();
+        if offset >= size {
+            return Ok(0);
+        }
+        let len = buf.len().min(size - offset);
+        let mut done = 0;
+        while done < len {
+            let pos = offset + done;
+            let block = (pos / BLOCK_SIZE) as u64;
+            let begin = pos % BLOCK_SIZE;
+            if begin == 0 && len - done >= BLOCK_SIZE {
+                let n = (len - done) / BLOCK_SIZE * BLOCK_SIZE;
+                let src = &buf[done..done + n];
+                self.device.write_blocks(block, src).map_err(|_| DevError)?;
+                done += n;
+            } else {
+                let mut tmp = [0u8; BLOCK_SIZE];
+                self.device
+                    .read_blocks(block, &mut tmp)
+                    .map_err(|_| DevError)?;
+                let n = (BLOCK_SIZE - begin).min(len - done);
+                tmp[begin..begin + n].copy_from_slice(&buf[done..done + n]);
+                self.device
+                    .write_blocks(block, &tmp)
+                    .map_err(|_| DevError)?;
+                done += n;
+            }
+        }
+        Ok(len)
+    }
+
+    fn sync(&self) -> Result<()> {
+        self.device.flush().map_err(|_| DevError)
+    }
+}
+
+impl vfs::INode for BlockDev {
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> vfs::Result<usize> {
+        Ok(Device::read_at(self, offset, buf)?)
+    }
+
+    fn write_at(&self, offset: usize, buf: &[u8]) -> vfs::Result<usize> {
+        Ok(Device::write_at(self, offset, buf)?)
+    }
+
+    fn poll(&self) -> vfs::Result<vfs::PollStatus> {
+        Ok(vfs::PollStatus {
+            read: true,
+            write: !self.device.read_only(),
+            error: false,
+        })
+    }
+
+    fn metadata(&self) -> vfs::Result<vfs::Metadata> {
+        let size = self.size();
+        Ok(vfs::Metadata {
+            dev: 1,
+            inode: 1,
+            size,
+            blk_size: BLOCK_SIZE,
+            blocks: size / BLOCK_SIZE,
+            atime: vfs::Timespec { sec: 0, nsec: 0 },
+            mtime: vfs::Timespec { sec: 0, nsec: 0 },
+            ctime: vfs::Timespec { sec: 0, nsec: 0 },
+            type_: vfs::FileType::BlockDevice,
+            mode: 0o660,
+            nlinks: 1,
+            uid: 0,
+            gid: 0,
+            // virtio-blk disks
+            rdev: vfs::make_rdev(254, self.minor as usize),
+        })
+    }
+
+    fn sync_all(&self) -> vfs::Result<()> {
+        Ok(Device::sync(self)?)
+    }
+
+    fn sync_data(&self) -> vfs::Result<()> {
+        Ok(Device::sync(self)?)
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+}
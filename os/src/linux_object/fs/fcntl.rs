@@ -30,6 +30,12 @@ bitflags! {
         const FD_CLOEXEC = 1;
         /// like F_DUPFD, but additionally set the close-on-exec flag
         const F_DUPFD_CLOEXEC = F_LINUX_SPECIFIC_BASE + 6;
+        /// Get open file description locking info.
+        const F_OFD_GETLK = 36;
+        /// Set open file description lock (non-blocking).
+        const F_OFD_SETLK = 37;
+        /// Set open file description lock (blocking).
+        const F_OFD_SETLKW = 38;
     }
 }
 
@@ -44,3 +50,28 @@ bitflags! {
         const O_CLOEXEC = 0o2000000;
     }
 }
+
+/// read lock, for the type of `Flock`
+pub const F_RDLCK: i16 = 0;
+/// write lock, for the type of `Flock`
+pub const F_WRLCK: i16 = 1;
+/// unlock, for the type of `Flock`
+pub const F_UNLCK: i16 = 2;
+
+/// record lock description for F_GETLK, F_SETLK and F_SETLKW
+///
+/// struct flock
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct Flock {
+    /// Type of lock: F_RDLCK, F_WRLCK, F_UNLCK
+    pub type_: i16,
+    /// How to interpret start: SEEK_SET, SEEK_CUR, SEEK_END
+    pub whence: i16,
+    /// Starting offset for lock
+    pub start: i64,
+    /// Number of bytes to lock, 0 for up to the end of the file
+    pub len: i64,
+    /// PID of process blocking our lock (F_GETLK only)
+    pub pid: i32,
+}
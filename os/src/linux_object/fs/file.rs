@@ -4,8 +4,11 @@
 
 use alloc::{boxed::Box, string::String, sync::Arc};
 
-use super::FileLike;
+use super::inotify::{notify_file, InotifyMask};
+use super::{FileLike, Pipe};
 use super::super::error::{LxError, LxResult};
+use super::lock::{flock, funlock, release_all_locks, LockKey, LockKind, LockOwner};
+use super::mount::inode_key;
 use async_trait::async_trait;
 use rcore_fs::vfs::{FsError, INode, Metadata, PollStatus};
 use spin::Mutex;
@@ -23,6 +26,8 @@ pub struct File {
     pub path: String,
     /// file inner mut data
     inner: Mutex<FileInner>,
+    /// the directory the file was opened in and its name there, for inotify
+    dir_entry: Mutex<Option<(Arc<dyn INode>, String)>>,
 }
 
 impl_kobject!(File);
@@ -46,6 +51,8 @@ pub struct OpenOptions {
     pub nonblock: bool,
     /// close on exec
     pub fd_cloexec: bool,
+    /// opened with `O_PATH`, only for the path
+    pub path_only: bool,
 }
 
 /// file seek type
@@ -68,6 +75,7 @@ impl File {
             options,
             path,
             inner: Mutex::new(FileInner::default()),
+            dir_entry: Mutex::new(None),
         })
     }
 
@@ -119,6 +127,7 @@ impl File {
             return Err(LxError::EBADF);
         }
         let len = self.inode.write_at(offset as usize, buf)?;
+        self.notify(InotifyMask::MODIFY);
         Ok(len)
     }
 
@@ -139,6 +148,7 @@ impl File {
             return Err(LxError::EBADF);
         }
         self.inode.resize(len as usize)?;
+        self.notify(InotifyMask::MODIFY);
         Ok(())
     }
 
@@ -190,7 +200,7 @@ impl File {
 
     /// manipulates the underlying device parameters of special files
     pub fn io_control(&self, cmd: u32, arg: usize) -> LxResult<usize> {
-        self.inode.io_control(cmd, arg)?;
+        self.operable_inode()?.io_control(cmd, arg)?;
         Ok(0)
     }
 
@@ -199,6 +209,46 @@ impl File {
         self.inode.clone()
     }
 
+    /// get INode of this file to operate on, which `O_PATH` does not allow
+    pub fn operable_inode(&self) -> LxResult<Arc<dyn INode>> {
+        if self.options.path_only {
+            return Err(LxError::EBADF);
+        }
+        Ok(self.inode.clone())
+    }
+
+    /// get the pipe if this file is an end of one
+    pub fn as_pipe(&self) -> Option<&Pipe> {
+        self.inode.as_any_ref().downcast_ref::<Pipe>()
+    }
+
+    /// set the directory the file was opened in and its name there
+    pub fn set_dir_entry(&self, dir: &Arc<dyn INode>, name: &str) {
+        *self.dir_entry.lock() = Some((dir.clone(), String::from(name)));
+    }
+
+    /// report inotify events on the file
+    pub fn notify(&self, mask: InotifyMask) {
+        notify_file(&self.inode, self.dir_entry.lock().as_ref(), mask);
+    }
+
+    /// key of the file in the lock table
+    pub fn lock_key(&self) -> LxResult<LockKey> {
+        inode_key(&self.inode)
+    }
+
+    /// apply a `flock` lock of `kind` on the file, or remove it if `None`
+    pub async fn flock(&self, kind: Option<LockKind>, nonblock: bool) -> LxResult {
+        let key = self.lock_key()?;
+        match kind {
+            Some(kind) => flock(key, self.id(), kind, nonblock).await,
+            None => {
+                funlock(key, self.id());
+                Ok(())
+            }
+        }
+    }
+
     /// manipulate file descriptor
     /// unimplemented
     pub fn fcntl(&self, cmd: usize, arg: usize) -> LxResult<usize> {
@@ -210,6 +260,18 @@ impl File {
     }
 }
 
+/// Release the locks of the open file description when it is closed
+impl Drop for File {
+    fn drop(&mut self) {
+        release_all_locks(LockOwner::File(self.id()));
+        if self.options.write {
+            self.notify(InotifyMask::CLOSE_WRITE);
+        } else if self.options.read {
+            self.notify(InotifyMask::CLOSE_NOWRITE);
+        }
+    }
+}
+
 #[async_trait]
 impl FileLike for File {
     async fn read(&self, buf: &mut [u8]) -> LxResult<usize> {
@@ -0,0 +1,577 @@
+//! File change notification
+//!
+//! An [`Inotify`] instance holds watches on INodes, found by their filesystem
+//! and inode number. The syscalls changing files report events with [`notify`],
+//! which queues them on every instance watching the INode. An event on an
+//! entry of a directory is reported to the watches on the directory with the
+//! name of the entry, and to the watches on the entry itself without it.
+#![deny(missing_docs)]
+
+use super::super::error::{LxError, LxResult};
+use super::super::sync::{Event, EventBus};
+use super::ioctl::FIONREAD;
+use super::mount::inode_key;
+use crate::kernel_hal::user::UserOutPtr;
+use alloc::{
+    boxed::Box,
+    collections::{BTreeMap, VecDeque},
+    string::String,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use bitflags::bitflags;
+use core::any::Any;
+use core::future::Future;
+use core::mem::size_of;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicU32, Ordering};
+use core::task::{Context, Poll};
+use lazy_static::lazy_static;
+use rcore_fs::vfs::*;
+use spin::Mutex;
+
+/// Max number of events queued on an instance
+const MAX_QUEUED_EVENTS: usize = 16384;
+/// Max number of watches of an instance
+const MAX_USER_WATCHES: usize = 8192;
+
+bitflags! {
+    /// events of inotify, and flags of `inotify_add_watch`
+    pub struct InotifyMask: u32 {
+        /// file was accessed
+        const ACCESS = 0x1;
+        /// file was modified
+        const MODIFY = 0x2;
+        /// metadata changed
+        const ATTRIB = 0x4;
+        /// file opened for writing was closed
+        const CLOSE_WRITE = 0x8;
+        /// file not opened for writing was closed
+        const CLOSE_NOWRITE = 0x10;
+        /// file was opened
+        const OPEN = 0x20;
+        /// file was moved out of the watched directory
+        const MOVED_FROM = 0x40;
+        /// file was moved into the watched directory
+        const MOVED_TO = 0x80;
+        /// file was created in the watched directory
+        const CREATE = 0x100;
+        /// file was deleted from the watched directory
+        const DELETE = 0x200;
+        /// the watched file was deleted
+        const DELETE_SELF = 0x400;
+        /// the watched file was moved
+        const MOVE_SELF = 0x800;
+        /// all the events above
+        const ALL_EVENTS = 0xfff;
+
+        /// filesystem of the watched file was unmounted
+        const UNMOUNT = 0x2000;
+        /// the event queue overflowed
+        const Q_OVERFLOW = 0x4000;
+        /// the watch was removed
+        const IGNORED = 0x8000;
+
+        /// only watch the path if it is a directory
+        const ONLYDIR = 0x0100_0000;
+        /// do not follow the path if it is a symbolic link
+        const DONT_FOLLOW = 0x0200_0000;
+        /// do not report events on entries after they are unlinked
+        const EXCL_UNLINK = 0x0400_0000;
+        /// fail if the path is already watched
+        const MASK_CREATE = 0x1000_0000;
+        /// add to the mask of an existing watch instead of replacing it
+        const MASK_ADD = 0x2000_0000;
+        /// subject of the event is a directory
+        const ISDIR = 0x4000_0000;
+        /// remove the watch after the first event
+        const ONESHOT = 0x8000_0000;
+    }
+}
+
+impl InotifyMask {
+    /// events which are reported to the watched INode itself as well as to
+    /// its directory
+    fn reported_to_self() -> Self {
+        Self::ACCESS
+            | Self::MODIFY
+            | Self::ATTRIB
+            | Self::CLOSE_WRITE
+            | Self::CLOSE_NOWRITE
+            | Self::OPEN
+    }
+}
+
+/// The key of an INode to watch, as given by `inode_key`
+pub type WatchKey = (usize, usize);
+
+/// Get the key of `inode` in the watch table
+pub fn watch_key(inode: &Arc<dyn INode>) -> LxResult<WatchKey> {
+    inode_key(inode)
+}
+
+lazy_static! {
+    /// the instances watching each INode
+    static ref WATCHERS: Mutex<BTreeMap<WatchKey, Vec<Weak<Mutex<InotifyInner>>>>> =
+        Mutex::new(BTreeMap::new());
+}
+
+/// Get a new cookie to connect `MOVED_FROM` and `MOVED_TO` events of a rename
+pub fn new_cookie() -> u32 {
+    static COOKIE: AtomicU32 = AtomicU32::new(1);
+    COOKIE.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Report the events in `mask` on the INode `key`.
+///
+/// `name` is the entry of the directory `key` the event is about, or empty if
+/// it is about the INode itself.
+pub fn notify(key: WatchKey, mask: InotifyMask, cookie: u32, name: &str) {
+    notify_watchers(key, mask, cookie, name, false);
+}
+
+/// Report the events in `mask` on the INode `key`, as `notify` does.
+///
+/// `unlinked` tells that the entry `name` is no longer in the directory, the
+/// watches with `EXCL_UNLINK` skip the events.
+fn notify_watchers(key: WatchKey, mask: InotifyMask, cookie: u32, name: &str, unlinked: bool) {
+    for instance in instances(key) {
+        let removed = instance.lock().report(key, mask, cookie, name, unlinked);
+        if removed {
+            unregister(key, &instance);
+        }
+    }
+}
+
+/// Get the instances watching the INode `key`.
+fn instances(key: WatchKey) -> Vec<Arc<Mutex<InotifyInner>>> {
+    match WATCHERS.lock().get(&key) {
+        Some(list) => list.iter().filter_map(Weak::upgrade).collect(),
+        None => Vec::new(),
+    }
+}
+
+/// Report the events in `mask` on `inode` itself.
+pub fn notify_inode(inode: &Arc<dyn INode>, mask: InotifyMask) {
+    if !watching() {
+        return;
+    }
+    if let (Ok(key), Ok(metadata)) = (watch_key(inode), inode.metadata()) {
+        let mask = if metadata.type_ == FileType::Dir {
+            mask | InotifyMask::ISDIR
+        } else {
+            mask
+        };
+        notify(key, mask, 0, "");
+    }
+}
+
+/// Report the events in `mask` on the entry `name` of the directory `dir`,
+/// where `is_dir` tells whether the entry is a directory.
+pub fn notify_entry(
+    dir: &Arc<dyn INode>,
+    name: &str,
+    is_dir: bool,
+    mask: InotifyMask,
+    cookie: u32,
+) {
+    if !watching() {
+        return;
+    }
+    if let Ok(key) = watch_key(dir) {
+        let mask = if is_dir {
+            mask | InotifyMask::ISDIR
+        } else {
+            mask
+        };
+        notify(key, mask, cookie, name);
+    }
+}
+
+/// Report the events in `mask` on the INode `inode` of an open file, and on
+/// its `entry` in a directory if known.
+pub fn notify_file(
+    inode: &Arc<dyn INode>,
+    entry: Option<&(Arc<dyn INode>, String)>,
+    mask: InotifyMask,
+) {
+    if !watching() {
+        return;
+    }
+    let (key, metadata) = match (watch_key(inode), inode.metadata()) {
+        (Ok(key), Ok(metadata)) => (key, metadata),
+        _ => return,
+    };
+    let mask = if metadata.type_ == FileType::Dir {
+        mask | InotifyMask::ISDIR
+    } else {
+        mask
+    };
+    if mask.intersects(InotifyMask::reported_to_self()) {
+        notify(key, mask, 0, "");
+    }
+    if let Some((dir, name)) = entry {
+        let dir_key = match watch_key(dir) {
+            Ok(dir_key) => dir_key,
+            Err(_) => return,
+        };
+        // only look the entry up again if a watch cares
+        let unlinked = excludes_unlinked(dir_key) && !is_entry(dir, name, key);
+        notify_watchers(dir_key, mask, 0, name, unlinked);
+    }
+}
+
+/// Whether the entry `name` of the directory `dir` is the INode `key`.
+fn is_entry(dir: &Arc<dyn INode>, name: &str, key: WatchKey) -> bool {
+    let found = dir.find(name).ok();
+    found.and_then(|inode| watch_key(&inode).ok()) == Some(key)
+}
+
+/// Whether a watch on the directory `key` has `EXCL_UNLINK`.
+fn excludes_unlinked(key: WatchKey) -> bool {
+    instances(key).iter().any(|instance| {
+        instance
+            .lock()
+            .watches
+            .values()
+            .any(|watch| watch.key == key && watch.mask.contains(InotifyMask::EXCL_UNLINK))
+    })
+}
+
+/// Whether anything is watched, to skip looking up the INodes of events
+fn watching() -> bool {
+    !WATCHERS.lock().is_empty()
+}
+
+/// Remove `instance` from the watchers of `key` if it no longer watches it.
+fn unregister(key: WatchKey, instance: &Arc<Mutex<InotifyInner>>) {
+    let mut watchers = WATCHERS.lock();
+    if let Some(list) = watchers.get_mut(&key) {
+        let watching = instance
+            .lock()
+            .watches
+            .values()
+            .any(|watch| watch.key == key);
+        if !watching {
+            list.retain(|weak| !core::ptr::eq(weak.as_ptr(), Arc::as_ptr(instance)));
+        }
+        list.retain(|weak| weak.strong_count() > 0);
+        if list.is_empty() {
+            watchers.remove(&key);
+        }
+    }
+}
+
+/// A watch of an instance
+struct Watch {
+    key: WatchKey,
+    /// events to report, with `ONESHOT` and `EXCL_UNLINK`
+    mask: InotifyMask,
+}
+
+/// A queued event
+#[derive(PartialEq)]
+struct InotifyEvent {
+    wd: i32,
+    mask: InotifyMask,
+    cookie: u32,
+    name: String,
+}
+
+/// The header of an event record
+///
+/// struct inotify_event
+#[repr(C)]
+struct EventHeader {
+    wd: i32,
+    mask: u32,
+    cookie: u32,
+    len: u32,
+}
+
+impl InotifyEvent {
+    /// length of the name, padded with NULs to a multiple of the header
+    fn name_len(&self) -> usize {
+        if self.name.is_empty() {
+            return 0;
+        }
+        let align = size_of::<EventHeader>();
+        (self.name.len() + align) / align * align
+    }
+
+    /// length of the record
+    fn len(&self) -> usize {
+        size_of::<EventHeader>() + self.name_len()
+    }
+
+    /// Write the record to the start of `buf`, which is long enough.
+    #[allow(unsafe_code)]
+    fn write_to(&self, buf: &mut [u8]) {
+        let header = EventHeader {
+            wd: self.wd,
+            mask: self.mask.bits(),
+            cookie: self.cookie,
+            len: self.name_len() as u32,
+        };
+        let header_len = size_of::<EventHeader>();
+        // the header is plain integers, with no padding
+        let header_bytes =
+            unsafe { core::slice::from_raw_parts(&header as *const _ as *const u8, header_len) };
+        buf[..header_len].copy_from_slice(header_bytes);
+        let name = &mut buf[header_len..self.len()];
+        for byte in name.iter_mut() {
+            *byte = 0;
+        }
+        name[..self.name.len()].copy_from_slice(self.name.as_bytes());
+    }
+}
+
+/// Inotify instance inner data
+#[derive(Default)]
+struct InotifyInner {
+    /// watches by watch descriptor
+    watches: BTreeMap<i32, Watch>,
+    /// the last watch descriptor
+    last_wd: i32,
+    /// events not read yet
+    events: VecDeque<InotifyEvent>,
+    /// event bus for the instance
+    eventbus: EventBus,
+}
+
+impl InotifyInner {
+    /// Queue an event, unless it is the same as the last one.
+    fn push_event(&mut self, wd: i32, mask: InotifyMask, cookie: u32, name: &str) {
+        let event = InotifyEvent {
+            wd,
+            mask,
+            cookie,
+            name: String::from(name),
+        };
+        if self.events.back() == Some(&event) {
+            return;
+        }
+        if self.events.len() >= MAX_QUEUED_EVENTS {
+            let overflow = InotifyEvent {
+                wd: -1,
+                mask: InotifyMask::Q_OVERFLOW,
+                cookie: 0,
+                name: String::new(),
+            };
+            if self.events.back() != Some(&overflow) {
+                self.events.push_back(overflow);
+            }
+        } else {
+            self.events.push_back(event);
+        }
+        self.eventbus.set(Event::READABLE);
+    }
+
+    /// Queue the events in `mask` on `key` for the watches interested in
+    /// them, and return whether any of the watches was removed.
+    ///
+    /// The watches with `EXCL_UNLINK` skip the events on an `unlinked` entry.
+    fn report(
+        &mut self,
+        key: WatchKey,
+        mask: InotifyMask,
+        cookie: u32,
+        name: &str,
+        unlinked: bool,
+    ) -> bool {
+        let watches: Vec<(i32, InotifyMask)> = self
+            .watches
+            .iter()
+            .filter(|(_, watch)| watch.key == key)
+            .filter(|(_, watch)| !(unlinked && watch.mask.contains(InotifyMask::EXCL_UNLINK)))
+            .map(|(&wd, watch)| (wd, watch.mask))
+            .collect();
+        let mut removed = false;
+        for (wd, watch_mask) in watches {
+            let events = mask & watch_mask & InotifyMask::ALL_EVENTS;
+            if !events.is_empty() {
+                self.push_event(wd, events | (mask & InotifyMask::ISDIR), cookie, name);
+            }
+            let deleted = name.is_empty() && mask.contains(InotifyMask::DELETE_SELF);
+            let oneshot = !events.is_empty() && watch_mask.contains(InotifyMask::ONESHOT);
+            if deleted || oneshot {
+                self.watches.remove(&wd);
+                self.push_event(wd, InotifyMask::IGNORED, 0, "");
+                removed = true;
+            }
+        }
+        removed
+    }
+}
+
+/// An inotify instance, the INode of the file returned by `inotify_init1`
+pub struct Inotify {
+    inner: Arc<Mutex<InotifyInner>>,
+}
+
+impl Default for Inotify {
+    fn default() -> Self {
+        Inotify {
+            inner: Arc::new(Mutex::new(InotifyInner::default())),
+        }
+    }
+}
+
+impl Inotify {
+    /// Watch `inode` for the events in `mask`, and return the watch
+    /// descriptor.
+    ///
+    /// An existing watch on `inode` is changed, its mask is replaced unless
+    /// `MASK_ADD` is in `mask`.
+    pub fn add_watch(&self, inode: &Arc<dyn INode>, mask: InotifyMask) -> LxResult<i32> {
+        if (mask & InotifyMask::ALL_EVENTS).is_empty()
+            || mask.contains(InotifyMask::MASK_ADD | InotifyMask::MASK_CREATE)
+        {
+            return Err(LxError::EINVAL);
+        }
+        let key = watch_key(inode)?;
+        let watch_mask =
+            mask & (InotifyMask::ALL_EVENTS | InotifyMask::ONESHOT | InotifyMask::EXCL_UNLINK);
+        let mut inner = self.inner.lock();
+        if let Some((&wd, watch)) = inner.watches.iter_mut().find(|(_, w)| w.key == key) {
+            if mask.contains(InotifyMask::MASK_CREATE) {
+                return Err(LxError::EEXIST);
+            }
+            if mask.contains(InotifyMask::MASK_ADD) {
+                watch.mask |= watch_mask;
+            } else {
+                watch.mask = watch_mask;
+            }
+            return Ok(wd);
+        }
+        if inner.watches.len() >= MAX_USER_WATCHES {
+            return Err(LxError::ENOSPC);
+        }
+        inner.last_wd += 1;
+        let wd = inner.last_wd;
+        inner.watches.insert(
+            wd,
+            Watch {
+                key,
+                mask: watch_mask,
+            },
+        );
+        drop(inner);
+        WATCHERS
+            .lock()
+            .entry(key)
+            .or_insert_with(Vec::new)
+            .push(Arc::downgrade(&self.inner));
+        Ok(wd)
+    }
+
+    /// Remove the watch `wd`, queueing an `IGNORED` event.
+    pub fn rm_watch(&self, wd: i32) -> LxResult {
+        let key = {
+            let mut inner = self.inner.lock();
+            let watch = inner.watches.remove(&wd).ok_or(LxError::EINVAL)?;
+            inner.push_event(wd, InotifyMask::IGNORED, 0, "");
+            watch.key
+        };
+        unregister(key, &self.inner);
+        Ok(())
+    }
+}
+
+impl Drop for Inotify {
+    fn drop(&mut self) {
+        let keys: Vec<WatchKey> = {
+            let mut inner = self.inner.lock();
+            let keys = inner.watches.values().map(|watch| watch.key).collect();
+            inner.watches.clear();
+            keys
+        };
+        for key in keys {
+            unregister(key, &self.inner);
+        }
+    }
+}
+
+impl INode for Inotify {
+    /// read as many whole events as fit in `buf`
+    fn read_at(&self, _offset: usize, buf: &mut [u8]) -> Result<usize> {
+        let mut inner = self.inner.lock();
+        match inner.events.front() {
+            None => return Err(FsError::Again),
+            Some(event) if event.len() > buf.len() => return Err(FsError::InvalidParam),
+            Some(_) => {}
+        }
+        let mut len = 0;
+        while let Some(event) = inner.events.front() {
+            if len + event.len() > buf.len() {
+                break;
+            }
+            event.write_to(&mut buf[len..]);
+            len += event.len();
+            inner.events.pop_front();
+        }
+        if inner.events.is_empty() {
+            inner.eventbus.clear(Event::READABLE);
+        }
+        Ok(len)
+    }
+
+    fn write_at(&self, _offset: usize, _buf: &[u8]) -> Result<usize> {
+        Err(FsError::InvalidParam)
+    }
+
+    fn poll(&self) -> Result<PollStatus> {
+        Ok(PollStatus {
+            read: !self.inner.lock().events.is_empty(),
+            write: false,
+            error: false,
+        })
+    }
+
+    fn async_poll<'a>(
+        &'a self,
+    ) -> Pin<Box<dyn Future<Output = Result<PollStatus>> + Send + Sync + 'a>> {
+        #[must_use = "future does nothing unless polled/`await`-ed"]
+        struct InotifyFuture<'a> {
+            inotify: &'a Inotify,
+        }
+
+        impl<'a> Future for InotifyFuture<'a> {
+            type Output = Result<PollStatus>;
+
+            fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+                let mut inner = self.inotify.inner.lock();
+                if !inner.events.is_empty() {
+                    drop(inner);
+                    return Poll::Ready(self.inotify.poll());
+                }
+                let waker = cx.waker().clone();
+                inner.eventbus.subscribe(Box::new(move |_| {
+                    waker.wake_by_ref();
+                    true
+                }));
+                Poll::Pending
+            }
+        }
+
+        Box::pin(InotifyFuture { inotify: self })
+    }
+
+    /// `FIONREAD` gets the bytes of the queued events
+    fn io_control(&self, cmd: u32, data: usize) -> Result<usize> {
+        match cmd as usize {
+            FIONREAD => {
+                let len: usize = self.inner.lock().events.iter().map(|e| e.len()).sum();
+                UserOutPtr::<u32>::from(data)
+                    .write(len as u32)
+                    .map_err(|_| FsError::InvalidParam)?;
+                Ok(0)
+            }
+            _ => Err(FsError::NotSupported),
+        }
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+}
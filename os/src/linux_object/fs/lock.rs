@@ -0,0 +1,395 @@
+//! Advisory file locks
+//!
+//! Locks are kept in a global table by the filesystem and inode number of
+//! the file, so they are shared by all opens of the file. A `flock` lock covers
+//! the whole file and is held by an open file description. A record lock
+//! covers a range of bytes and is held by a process, or by an open file
+//! description for an OFD lock. The two kinds of locks do not interact.
+
+use super::super::error::*;
+use crate::zircon_object::object::KoID;
+use alloc::{collections::BTreeMap, vec::Vec};
+use core::future::Future;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use core::task::{Context, Poll, Waker};
+use lazy_static::lazy_static;
+use spin::Mutex;
+
+/// The kind of a lock
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum LockKind {
+    /// a read lock, which many owners can hold
+    Shared,
+    /// a write lock, which only one owner can hold
+    Exclusive,
+}
+
+impl LockKind {
+    fn conflicts(self, other: LockKind) -> bool {
+        self == LockKind::Exclusive || other == LockKind::Exclusive
+    }
+}
+
+/// The holder of a lock
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum LockOwner {
+    /// an open file description, by the ID of the `File`
+    File(KoID),
+    /// a process, by PID
+    Process(KoID),
+}
+
+/// A record lock on the bytes from `start` to `end` (exclusive)
+#[derive(Debug, Copy, Clone)]
+pub struct RecordLock {
+    /// holder of the lock
+    pub owner: LockOwner,
+    /// kind of the lock
+    pub kind: LockKind,
+    /// first byte of the range
+    pub start: u64,
+    /// end of the range, `u64::MAX` for up to the end of the file
+    pub end: u64,
+}
+
+/// The key of a file in the lock table, as given by `inode_key`
+pub type LockKey = (usize, usize);
+
+/// The locks on a file
+#[derive(Default)]
+struct FileLocks {
+    /// `flock` locks by the ID of the open file description
+    flocks: Vec<(KoID, LockKind)>,
+    /// record locks
+    records: Vec<RecordLock>,
+    /// tasks waiting for a change of the locks, by the ID of their wait
+    waiters: BTreeMap<usize, Waker>,
+}
+
+impl FileLocks {
+    fn is_empty(&self) -> bool {
+        self.flocks.is_empty() && self.records.is_empty() && self.waiters.is_empty()
+    }
+
+    /// Find a record lock conflicting with a lock of `kind` by `owner`
+    fn conflict(
+        &self,
+        owner: LockOwner,
+        kind: LockKind,
+        start: u64,
+        end: u64,
+    ) -> Option<RecordLock> {
+        self.records
+            .iter()
+            .find(|lock| {
+                lock.owner != owner
+                    && lock.start < end
+                    && start < lock.end
+                    && lock.kind.conflicts(kind)
+            })
+            .copied()
+    }
+
+    /// Replace the locks of `owner` in the range with a lock of `kind`,
+    /// splitting the locks partly in the range.
+    ///
+    /// The new lock is merged with the locks of `owner` of the same kind
+    /// next to it, so each owner holds as few locks as possible.
+    fn replace(&mut self, owner: LockOwner, kind: Option<LockKind>, start: u64, end: u64) {
+        let mut remains = Vec::new();
+        self.records.retain(|lock| {
+            if lock.owner != owner || lock.end <= start || end <= lock.start {
+                return true;
+            }
+            if lock.start < start {
+                remains.push(RecordLock {
+                    end: start,
+                    ..*lock
+                });
+            }
+            if end < lock.end {
+                remains.push(RecordLock {
+                    start: end,
+                    ..*lock
+                });
+            }
+            false
+        });
+        self.records.extend(remains);
+        if let Some(kind) = kind {
+            // the locks of `owner` left do not overlap the range
+            let (mut start, mut end) = (start, end);
+            self.records.retain(|lock| {
+                if lock.owner != owner || lock.kind != kind {
+                    return true;
+                }
+                if lock.end == start {
+                    start = lock.start;
+                } else if lock.start == end {
+                    end = lock.end;
+                } else {
+                    return true;
+                }
+                false
+            });
+            self.records.push(RecordLock {
+                owner,
+                kind,
+                start,
+                end,
+            });
+        }
+        self.wake_all();
+    }
+
+    fn wake_all(&mut self) {
+        for (_, waker) in core::mem::take(&mut self.waiters) {
+            waker.wake();
+        }
+    }
+}
+
+/// The lock table
+#[derive(Default)]
+struct LockTable {
+    /// locks of the files
+    files: BTreeMap<LockKey, FileLocks>,
+    /// the owner of the lock each process is waiting for
+    blocked: BTreeMap<KoID, LockOwner>,
+}
+
+impl LockTable {
+    /// Change the locks of the file `key` with `f`
+    fn update(&mut self, key: LockKey, f: impl FnOnce(&mut FileLocks)) {
+        if let Some(locks) = self.files.get_mut(&key) {
+            f(locks);
+            if locks.is_empty() {
+                self.files.remove(&key);
+            }
+        }
+    }
+
+    /// Whether a process waiting for a lock held by `holder` would wait for
+    /// `owner` in the end
+    fn would_deadlock(&self, owner: LockOwner, holder: LockOwner) -> bool {
+        let mut holder = holder;
+        // a chain longer than the waiting processes has a cycle without us
+        for _ in 0..=self.blocked.len() {
+            if holder == owner {
+                return true;
+            }
+            holder = match holder {
+                LockOwner::Process(pid) => match self.blocked.get(&pid) {
+                    Some(&next) => next,
+                    None => return false,
+                },
+                LockOwner::File(_) => return false,
+            };
+        }
+        false
+    }
+}
+
+lazy_static! {
+    static ref LOCKS: Mutex<LockTable> = Mutex::new(LockTable::default());
+}
+
+/// Apply a `flock` lock of `kind` on the file `key` for the open file
+/// description `file`.
+///
+/// A lock of another kind held by the description is converted, which is not
+/// atomic. Waits for conflicting locks, unless `nonblock`.
+pub async fn flock(key: LockKey, file: KoID, kind: LockKind, nonblock: bool) -> LxResult {
+    let held = LOCKS
+        .lock()
+        .files
+        .get(&key)
+        .map_or(false, |locks| locks.flocks.contains(&(file, kind)));
+    if held {
+        return Ok(());
+    }
+    funlock(key, file);
+    wait_for(key, move |table| {
+        let locks = table.files.entry(key).or_default();
+        if locks.flocks.iter().any(|&(_, other)| other.conflicts(kind)) {
+            if nonblock {
+                return Some(Err(LxError::EAGAIN));
+            }
+            return None;
+        }
+        locks.flocks.push((file, kind));
+        Some(Ok(()))
+    })
+    .await
+}
+
+/// Remove the `flock` lock on the file `key` of the open file description
+/// `file`.
+pub fn funlock(key: LockKey, file: KoID) {
+    LOCKS.lock().update(key, |locks| {
+        locks.flocks.retain(|&(owner, _)| owner != file);
+        locks.wake_all();
+    });
+}
+
+/// Find a record lock on the file `key` which prevents `owner` from placing a
+/// lock of `kind` on the range, as by `F_GETLK`.
+pub fn test_record_lock(
+    key: LockKey,
+    owner: LockOwner,
+    kind: LockKind,
+    start: u64,
+    end: u64,
+) -> Option<RecordLock> {
+    let table = LOCKS.lock();
+    let locks = table.files.get(&key)?;
+    locks.conflict(owner, kind, start, end)
+}
+
+/// Set a record lock of `kind` on the range of the file `key` for `owner`,
+/// or remove the locks in the range if `kind` is `None`.
+///
+/// Waits for conflicting locks if `wait`, but fails with `EDEADLK` if the
+/// holder of a conflicting lock is waiting for `owner`.
+pub async fn set_record_lock(
+    key: LockKey,
+    owner: LockOwner,
+    kind: Option<LockKind>,
+    start: u64,
+    end: u64,
+    wait: bool,
+) -> LxResult {
+    let kind = match kind {
+        Some(kind) => kind,
+        None => {
+            LOCKS
+                .lock()
+                .update(key, |locks| locks.replace(owner, None, start, end));
+            return Ok(());
+        }
+    };
+
+    /// Forget what a process waits for when it stops waiting
+    struct Unblock(Option<KoID>);
+
+    impl Drop for Unblock {
+        fn drop(&mut self) {
+            if let Some(pid) = self.0 {
+                LOCKS.lock().blocked.remove(&pid);
+            }
+        }
+    }
+
+    let pid = match owner {
+        LockOwner::Process(pid) => Some(pid),
+        LockOwner::File(_) => None,
+    };
+    let _unblock = Unblock(pid);
+    wait_for(key, move |table| {
+        let locks = table.files.entry(key).or_default();
+        let holder = match locks.conflict(owner, kind, start, end) {
+            Some(lock) => lock.owner,
+            None => {
+                locks.replace(owner, Some(kind), start, end);
+                return Some(Ok(()));
+            }
+        };
+        if !wait {
+            return Some(Err(LxError::EAGAIN));
+        }
+        if let Some(pid) = pid {
+            if table.would_deadlock(owner, holder) {
+                return Some(Err(LxError::EDEADLK));
+            }
+            table.blocked.insert(pid, holder);
+        }
+        None
+    })
+    .await
+}
+
+/// Remove all record locks of `owner` on the file `key`, as when a process
+/// closes a file.
+pub fn release_record_locks(key: LockKey, owner: LockOwner) {
+    LOCKS
+        .lock()
+        .update(key, |locks| locks.replace(owner, None, 0, u64::MAX));
+}
+
+/// Remove all locks of `owner` on all files, as when a process exits or an
+/// open file description is closed.
+pub fn release_all_locks(owner: LockOwner) {
+    let mut table = LOCKS.lock();
+    let keys: Vec<LockKey> = table.files.keys().cloned().collect();
+    for key in keys {
+        table.update(key, |locks| {
+            if let LockOwner::File(file) = owner {
+                locks.flocks.retain(|&(holder, _)| holder != file);
+            }
+            locks.replace(owner, None, 0, u64::MAX);
+        });
+    }
+}
+
+/// Do `op` on the lock table, again after each change of the locks of the
+/// file `key` until it returns `Some` result.
+async fn wait_for<T, F>(key: LockKey, op: F) -> LxResult<T>
+where
+    F: FnMut(&mut LockTable) -> Option<LxResult<T>> + Unpin,
+{
+    #[must_use = "future does nothing unless polled/`await`-ed"]
+    struct LockFuture<F> {
+        key: LockKey,
+        op: F,
+        /// ID of the waker in the waiters of the file, once waiting
+        waiter: Option<usize>,
+    }
+
+    /// An interrupted or timed out wait takes its waker away
+    impl<F> Drop for LockFuture<F> {
+        fn drop(&mut self) {
+            if let Some(id) = self.waiter {
+                LOCKS.lock().update(self.key, |locks| {
+                    locks.waiters.remove(&id);
+                });
+            }
+        }
+    }
+
+    impl<T, F> Future for LockFuture<F>
+    where
+        F: FnMut(&mut LockTable) -> Option<LxResult<T>> + Unpin,
+    {
+        type Output = LxResult<T>;
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+            let mut table = LOCKS.lock();
+            let key = self.key;
+            let result = (self.op)(&mut table);
+            if result.is_none() {
+                static NEXT_WAITER: AtomicUsize = AtomicUsize::new(0);
+                let id = *self
+                    .waiter
+                    .get_or_insert_with(|| NEXT_WAITER.fetch_add(1, Ordering::Relaxed));
+                let locks = table.files.entry(key).or_default();
+                locks.waiters.insert(id, cx.waker().clone());
+                return Poll::Pending;
+            }
+            let waiter = self.waiter.take();
+            table.update(key, |locks| {
+                if let Some(id) = waiter {
+                    locks.waiters.remove(&id);
+                }
+            });
+            Poll::Ready(result.unwrap())
+        }
+    }
+
+    LockFuture {
+        key,
+        op,
+        waiter: None,
+    }
+    .await
+}
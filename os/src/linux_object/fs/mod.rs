@@ -1,22 +1,27 @@
 //! Linux file objects
 #![deny(missing_docs)]
-use alloc::{boxed::Box, sync::Arc, vec::Vec};
+use alloc::{boxed::Box, format, string::String, sync::Arc, vec::Vec};
 
 use rcore_fs::vfs::*;
 use rcore_fs_devfs::{special::*, DevFS};
-use rcore_fs_mountfs::MountFS;
 use rcore_fs_ramfs::RamFS;
 
 pub use self::device::*;
 pub use self::fcntl::*;
 pub use self::file::*;
+pub use self::lock::*;
 pub use self::pipe::*;
+pub use self::procfs::*;
 pub use self::pseudo::*;
+pub use self::pty::*;
 pub use self::random::*;
-pub use self::stdio::*;
+pub use self::splice::*;
+pub use self::tty::*;
 pub use rcore_fs::vfs;
 
+use super::cred::{Access, Credentials};
 use super::error::*;
+use super::net::Socket;
 use super::process::LinuxProcess;
 use async_trait::async_trait;
 use core::convert::TryFrom;
@@ -26,11 +31,18 @@ use crate::zircon_object::object::*;
 mod device;
 mod fcntl;
 mod file;
+pub mod inotify;
 mod ioctl;
+mod lock;
+pub mod mount;
 mod pipe;
+mod procfs;
 mod pseudo;
+mod pty;
 mod random;
-mod stdio;
+mod splice;
+mod tty;
+pub mod xattr;
 
 #[async_trait]
 /// Generic file interface
@@ -55,6 +67,10 @@ pub trait FileLike: KernelObject {
     fn ioctl(&self, request: usize, arg1: usize, arg2: usize, arg3: usize) -> LxResult<usize>;
     /// manipulate file descriptor
     fn fcntl(&self, cmd: usize, arg: usize) -> LxResult<usize>;
+    /// get the socket interface if the file is a socket
+    fn as_socket(&self) -> Option<&dyn Socket> {
+        None
+    }
 }
 
 impl_downcast!(sync FileLike);
@@ -100,12 +116,8 @@ impl Into<i32> for FileDesc {
     }
 }
 
-/// create root filesystem, mount DevFS and RamFS
-pub fn create_root_fs(rootfs: Arc<dyn FileSystem>) -> Arc<dyn INode> {
-    let rootfs = MountFS::new(rootfs);
-    let root = rootfs.root_inode();
-
-    // create DevFS
+/// create DevFS with the standard devices and the disks found
+pub fn create_devfs() -> Arc<DevFS> {
     let devfs = DevFS::new();
     devfs
         .add("null", Arc::new(NullINode::default()))
@@ -119,21 +131,74 @@ pub fn create_root_fs(rootfs: Arc<dyn FileSystem>) -> Arc<dyn INode> {
     devfs
         .add("urandom", Arc::new(RandomINode::new(true)))
         .expect("failed to mknod /dev/urandom");
+    devfs
+        .add("tty", Arc::new(DevTty::default()))
+        .expect("failed to mknod /dev/tty");
+    devfs
+        .add("console", CONSOLE.clone())
+        .expect("failed to mknod /dev/console");
+    devfs
+        .add("ptmx", Arc::new(Ptmx::default()))
+        .expect("failed to mknod /dev/ptmx");
+    devfs
+        .add("pts", Arc::new(PtsDir::new(&devfs)))
+        .expect("failed to mkdir /dev/pts");
+    for (i, (name, device)) in crate::drivers::block_devices().into_iter().enumerate() {
+        devfs
+            .add(&name, Arc::new(BlockDev::new(device, i)))
+            .expect("failed to mknod block device");
+    }
+    devfs
+}
+
+/// create root filesystem, mount DevFS, ProcFS and RamFS
+pub fn create_root_fs(rootfs: Arc<dyn FileSystem>) -> Arc<dyn INode> {
+    let root = mount::mount_root(rootfs);
 
     // mount DevFS at /dev
-    let dev = root.find(true, "dev").unwrap_or_else(|_| {
-        root.create("dev", FileType::Dir, 0o666)
-            .expect("failed to mkdir /dev")
-    });
-    dev.mount(devfs).expect("failed to mount DevFS");
+    let dev = root
+        .find("dev")
+        .or_else(|_| root.create("dev", FileType::Dir, 0o666))
+        .expect("failed to mkdir /dev");
+    mount::mount(
+        create_devfs(),
+        "devfs",
+        "devfs",
+        &dev,
+        "/dev".into(),
+        mount::MountFlags::empty(),
+    )
+    .expect("failed to mount DevFS");
+
+    // mount ProcFS at /proc
+    let proc = root
+        .find("proc")
+        .or_else(|_| root.create("proc", FileType::Dir, 0o555))
+        .expect("failed to mkdir /proc");
+    mount::mount(
+        ProcFS::new(),
+        "proc",
+        "proc",
+        &proc,
+        "/proc".into(),
+        mount::MountFlags::empty(),
+    )
+    .expect("failed to mount ProcFS");
 
     // mount RamFS at /tmp
-    let ramfs = RamFS::new();
-    let tmp = root.find(true, "tmp").unwrap_or_else(|_| {
-        root.create("tmp", FileType::Dir, 0o666)
-            .expect("failed to mkdir /tmp")
-    });
-    tmp.mount(ramfs).expect("failed to mount RamFS");
+    let tmp = root
+        .find("tmp")
+        .or_else(|_| root.create("tmp", FileType::Dir, 0o666))
+        .expect("failed to mkdir /tmp");
+    mount::mount(
+        RamFS::new(),
+        "tmpfs",
+        "tmpfs",
+        &tmp,
+        "/tmp".into(),
+        mount::MountFlags::empty(),
+    )
+    .expect("failed to mount RamFS");
 
     root
 }
@@ -182,30 +247,65 @@ impl LinuxProcess {
             path,
             follow
         );
-        // hard code special path
-        if path == "/proc/self/exe" {
-            return Ok(Arc::new(Pseudo::new(
-                &self.execute_path(),
-                FileType::SymLink,
-            )));
-        }
-        let (fd_dir_path, fd_name) = split_path(&path);
-        if fd_dir_path == "/proc/self/fd" {
-            let fd = FileDesc::try_from(fd_name)?;
-            let fd_path = &self.get_file(fd)?.path;
-            return Ok(Arc::new(Pseudo::new(fd_path, FileType::SymLink)));
-        }
-
-        let follow_max_depth = if follow { FOLLOW_MAX_DEPTH } else { 0 };
-        if dirfd == FileDesc::CWD {
-            Ok(self
-                .root_inode()
-                .lookup(&self.current_working_directory())?
-                .lookup_follow(path, follow_max_depth)?)
+        let root = self.root_inode();
+        let start = if path.starts_with('/') {
+            root.clone()
+        } else if dirfd == FileDesc::CWD {
+            self.current_working_directory_inode()
         } else {
-            let file = self.get_file(dirfd)?;
-            Ok(file.lookup_follow(path, follow_max_depth)?)
+            self.get_file(dirfd)?.inode()
+        };
+        let cred = self.credentials();
+        walk(root, start, path, follow, Some(&cred), Some(self.pid()))
+    }
+
+    /// Lookup the directory of the last name in `path` and the name.
+    ///
+    /// With `follow` a symbolic link named last is replaced by its target,
+    /// which is where a file is created for a dangling link.
+    pub fn lookup_parent_at(
+        &self,
+        dirfd: FileDesc,
+        path: &str,
+        follow: bool,
+    ) -> LxResult<(Arc<dyn INode>, String)> {
+        let (dir_path, file_name) = split_path(path);
+        let mut dir = self.lookup_inode_at(dirfd, dir_path, true)?;
+        let mut name = String::from(file_name);
+        let root = self.root_inode();
+        let cred = self.credentials();
+        let mut links = 0;
+        while follow {
+            let inode = match dir.find(&name) {
+                Ok(inode) if inode.metadata()?.type_ == FileType::SymLink => inode,
+                _ => break,
+            };
+            links += 1;
+            if links > MAXSYMLINKS {
+                return Err(LxError::ELOOP);
+            }
+            let target = self.read_link(&inode)?;
+            let target = core::str::from_utf8(&target).map_err(|_| LxError::ENOENT)?;
+            if target.is_empty() {
+                return Err(LxError::ENOENT);
+            }
+            let (dir_path, file_name) = split_path(target);
+            let start = if target.starts_with('/') {
+                root.clone()
+            } else {
+                dir
+            };
+            dir = walk(root, start, dir_path, true, Some(&cred), Some(self.pid()))?;
+            name = String::from(file_name);
         }
+        Ok((dir, name))
+    }
+
+    /// Read the target of the symbolic link `inode` for the process.
+    ///
+    /// see `read_link`
+    pub fn read_link(&self, inode: &Arc<dyn INode>) -> LxResult<Vec<u8>> {
+        read_link(inode, Some(self.pid()))
     }
 
     /// Lookup INode from the process.
@@ -227,5 +327,97 @@ pub fn split_path(path: &str) -> (&str, &str) {
     (dir_path, file_name)
 }
 
-/// the max depth for following a link
-const FOLLOW_MAX_DEPTH: usize = 1;
+/// Normalize `path` relative to `cwd` to an absolute path, resolving `.` and
+/// `..` without following symbolic links.
+pub fn absolute_path(cwd: &str, path: &str) -> String {
+    let base = if path.starts_with('/') { "" } else { cwd };
+    let mut segments: Vec<&str> = Vec::new();
+    for seg in base.split('/').chain(path.split('/')) {
+        match seg {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            _ => segments.push(seg),
+        }
+    }
+    String::from("/") + &segments.join("/")
+}
+
+/// the max number of symbolic links followed in a path lookup
+pub const MAXSYMLINKS: usize = 40;
+
+/// Look up `path` from the directory `start`, in the file tree of `root`.
+///
+/// Symbolic links are followed, absolute ones from `root`, except at the
+/// last component when not `follow` and the path has no trailing slash.
+/// Following more than `MAXSYMLINKS` links fails with `ELOOP`. Search
+/// permission on each directory on the way is checked for `cred` if given.
+/// `/proc/self` links to the directory of the process `pid`, if given.
+pub fn walk(
+    root: &Arc<dyn INode>,
+    start: Arc<dyn INode>,
+    path: &str,
+    follow: bool,
+    cred: Option<&Credentials>,
+    pid: Option<KoID>,
+) -> LxResult<Arc<dyn INode>> {
+    // a trailing slash asks for a directory
+    let dir_only = path.ends_with('/');
+    let follow = follow || dir_only;
+    // the names left to look up, the next one last
+    let mut names: Vec<String> = path
+        .rsplit('/')
+        .filter(|name| !name.is_empty())
+        .map(String::from)
+        .collect();
+    let mut links = 0;
+    let mut inode = start;
+    while let Some(name) = names.pop() {
+        let metadata = inode.metadata()?;
+        // other types fail in the lookup
+        if metadata.type_ == FileType::Dir {
+            if let Some(cred) = cred {
+                cred.check_access(&metadata, Access::EXEC)?;
+            }
+        }
+        let next = inode.find(&name)?;
+        if next.metadata()?.type_ != FileType::SymLink || (names.is_empty() && !follow) {
+            inode = next;
+            continue;
+        }
+        links += 1;
+        if links > MAXSYMLINKS {
+            return Err(LxError::ELOOP);
+        }
+        let target = read_link(&next, pid)?;
+        let target = core::str::from_utf8(&target).map_err(|_| LxError::ENOENT)?;
+        if target.is_empty() {
+            return Err(LxError::ENOENT);
+        }
+        if target.starts_with('/') {
+            inode = root.clone();
+        }
+        names.extend(
+            target
+                .rsplit('/')
+                .filter(|name| !name.is_empty())
+                .map(String::from),
+        );
+    }
+    if dir_only && inode.metadata()?.type_ != FileType::Dir {
+        return Err(LxError::ENOTDIR);
+    }
+    Ok(inode)
+}
+
+/// Read the target of the symbolic link `inode`.
+///
+/// `/proc/self` links to the directory of the process `pid`, and has no
+/// target without one.
+pub fn read_link(inode: &Arc<dyn INode>, pid: Option<KoID>) -> LxResult<Vec<u8>> {
+    match pid {
+        Some(pid) if is_self_link(inode) => Ok(format!("/proc/{}", pid).into_bytes()),
+        _ => Ok(inode.read_as_vec()?),
+    }
+}
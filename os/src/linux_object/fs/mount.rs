@@ -0,0 +1,850 @@
+//! Mount table
+//!
+//! Every INode reached from the root is wrapped in an [`MNode`], which knows
+//! the mount it belongs to. Looking up a directory that has a filesystem
+//! mounted on it returns the root of that filesystem instead, and `..` at the
+//! root of a mount goes back to the directory it is mounted on.
+//!
+//! There is one table for the whole kernel, `/proc/mounts` is generated from it.
+
+use super::xattr::{XattrTable, XATTR_DIR};
+use super::{create_devfs, BlockDev, File, FileLike, Pipe, ProcFS};
+use crate::linux_object::error::*;
+use crate::zircon_object::vm::PAGE_SIZE;
+use alloc::{boxed::Box, string::String, sync::Arc, vec::Vec};
+use bitflags::bitflags;
+use core::any::Any;
+use core::future::Future;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use lazy_static::lazy_static;
+use rcore_fs::vfs::*;
+use rcore_fs_ramfs::RamFS;
+use spin::RwLock;
+
+bitflags! {
+    /// flags of `mount`
+    pub struct MountFlags: usize {
+        /// mount read-only
+        const RDONLY = 1;
+        /// ignore suid and sgid bits
+        const NOSUID = 1 << 1;
+        /// disallow access to device special files
+        const NODEV = 1 << 2;
+        /// disallow program execution
+        const NOEXEC = 1 << 3;
+        /// writes are synced at once
+        const SYNCHRONOUS = 1 << 4;
+        /// alter flags of a mounted filesystem
+        const REMOUNT = 1 << 5;
+        /// do not update access times
+        const NOATIME = 1 << 10;
+        /// make a bind mount
+        const BIND = 1 << 12;
+        /// move a subtree
+        const MOVE = 1 << 13;
+        /// recursive bind mount
+        const REC = 1 << 14;
+        /// suppress some warnings
+        const SILENT = 1 << 15;
+        /// flags kept by the mount and shown in `/proc/mounts`
+        const PER_MOUNT = Self::RDONLY.bits | Self::NOSUID.bits | Self::NODEV.bits
+            | Self::NOEXEC.bits | Self::SYNCHRONOUS.bits | Self::NOATIME.bits;
+    }
+}
+
+bitflags! {
+    /// flags of `umount2`
+    pub struct UmountFlags: usize {
+        /// force unmounting
+        const FORCE = 1;
+        /// detach now, clean up when no longer busy
+        const DETACH = 2;
+        /// mark for expiry
+        const EXPIRE = 4;
+        /// do not follow the target if it is a symbolic link
+        const NOFOLLOW = 8;
+    }
+}
+
+/// `f_type` of `statfs` for a ramfs
+const RAMFS_MAGIC: usize = 0x8584_58f6;
+/// `f_type` of `statfs` for a tmpfs, also reported by devtmpfs
+const TMPFS_MAGIC: usize = 0x0102_1994;
+/// `f_type` of `statfs` for procfs
+const PROC_SUPER_MAGIC: usize = 0x9fa0;
+/// `f_type` of `statfs` for SFS, the magic number of its superblock
+const SFS_MAGIC: usize = 0x2f8d_be2a;
+/// `f_type` of `statfs` for pipes
+const PIPEFS_MAGIC: usize = 0x5049_5045;
+/// `f_type` of `statfs` for sockets
+const SOCKFS_MAGIC: usize = 0x534f_434b;
+/// `f_type` of `statfs` for other INodes not in the tree
+const ANON_INODE_FS_MAGIC: usize = 0x0904_1934;
+
+/// `f_type` of `statfs` for each type a filesystem can be mounted as
+const FS_MAGICS: &[(&str, usize)] = &[
+    ("ramfs", RAMFS_MAGIC),
+    ("tmpfs", TMPFS_MAGIC),
+    ("devfs", TMPFS_MAGIC),
+    ("devtmpfs", TMPFS_MAGIC),
+    ("proc", PROC_SUPER_MAGIC),
+    ("procfs", PROC_SUPER_MAGIC),
+    ("sfs", SFS_MAGIC),
+];
+
+/// A mounted filesystem
+pub struct Mount {
+    source: String,
+    fstype: String,
+    /// absolute path of the mount point
+    path: RwLock<String>,
+    fs: Arc<dyn FileSystem>,
+    /// ID of `fs`, shared by its bind mounts and never reused
+    dev: usize,
+    /// root of the mount, a subdirectory of `fs` for bind mounts
+    root: Arc<dyn INode>,
+    root_ino: usize,
+    flags: RwLock<MountFlags>,
+    /// the directory it is mounted on and its inode number, `None` for the root
+    mountpoint: RwLock<Option<(MNode, usize)>>,
+    /// extended attributes of `fs`, `None` if it cannot hold them
+    xattrs: Option<Arc<XattrTable>>,
+    /// number of [`MNode`]s on it, held by open files, working directories
+    /// and mounts on its directories
+    users: AtomicUsize,
+}
+
+lazy_static! {
+    /// mounts in the order they were made
+    static ref MOUNTS: RwLock<Vec<Arc<Mount>>> = RwLock::new(Vec::new());
+}
+
+impl Mount {
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        source: &str,
+        fstype: &str,
+        path: String,
+        fs: Arc<dyn FileSystem>,
+        dev: usize,
+        root: Arc<dyn INode>,
+        flags: MountFlags,
+        mountpoint: Option<(MNode, usize)>,
+        xattrs: Option<Arc<XattrTable>>,
+    ) -> LxResult<Arc<Self>> {
+        let root_ino = root.metadata()?.inode;
+        Ok(Arc::new(Mount {
+            source: String::from(source),
+            fstype: String::from(fstype),
+            path: RwLock::new(path),
+            fs,
+            dev,
+            root,
+            root_ino,
+            flags: RwLock::new(flags & MountFlags::PER_MOUNT),
+            mountpoint: RwLock::new(mountpoint),
+            xattrs,
+            users: AtomicUsize::new(0),
+        }))
+    }
+
+    /// A new ID for a filesystem being mounted
+    ///
+    /// 0 is left for the INodes which are not in the tree.
+    fn new_dev() -> usize {
+        static DEV: AtomicUsize = AtomicUsize::new(1);
+        DEV.fetch_add(1, Ordering::Relaxed)
+    }
+
+    fn read_only(&self) -> bool {
+        self.flags.read().contains(MountFlags::RDONLY)
+    }
+
+    /// `f_type` of `statfs`, by the type it was mounted as, 0 if unknown
+    fn magic(&self) -> usize {
+        let fstype = match self.fstype.as_str() {
+            // the root filesystem is given without its type
+            "rootfs" if self.root.as_any_ref().is::<rcore_fs_sfs::INodeImpl>() => "sfs",
+            "rootfs" if self.root.as_any_ref().is::<rcore_fs_ramfs::LockedINode>() => "ramfs",
+            fstype => fstype,
+        };
+        FS_MAGICS
+            .iter()
+            .find(|&&(name, _)| name == fstype)
+            .map_or(0, |&(_, magic)| magic)
+    }
+
+    /// A new table of extended attributes for a filesystem mounted as
+    /// `fstype`, if it is a RamFS or SFS
+    ///
+    /// The attributes on an SFS are stored on it.
+    fn new_xattrs(fstype: &str, root: &Arc<dyn INode>) -> Option<Arc<XattrTable>> {
+        match fstype {
+            "ramfs" | "tmpfs" => Some(Arc::new(XattrTable::default())),
+            // the root filesystem is given without its type
+            _ if root.as_any_ref().is::<rcore_fs_sfs::INodeImpl>() => {
+                Some(Arc::new(XattrTable::on_disk(root.clone())))
+            }
+            _ => None,
+        }
+    }
+
+    fn root_node(self: &Arc<Self>) -> MNode {
+        MNode::new(self.root.clone(), self.clone())
+    }
+
+    /// the directory it is mounted on and its inode number, `None` for the root
+    fn mounted_on(&self) -> Option<(MNode, usize)> {
+        self.mountpoint.read().clone()
+    }
+
+    /// whether `mount` is mounted on a directory of this mount
+    fn is_parent_of(self: &Arc<Self>, mount: &Mount) -> bool {
+        match &*mount.mountpoint.read() {
+            Some((node, _)) => Arc::ptr_eq(&node.mount, self),
+            None => false,
+        }
+    }
+}
+
+impl FileSystem for Mount {
+    fn sync(&self) -> Result<()> {
+        self.fs.sync()
+    }
+
+    /// The root of the whole tree, so that absolute symlinks resolve from there.
+    fn root_inode(&self) -> Arc<dyn INode> {
+        let root = MOUNTS.read()[0].clone();
+        Arc::new(root.root_node())
+    }
+
+    fn info(&self) -> FsInfo {
+        self.fs.info()
+    }
+}
+
+/// An INode with the mount it was reached through
+///
+/// The mount is busy while the node lives.
+pub struct MNode {
+    inode: Arc<dyn INode>,
+    mount: Arc<Mount>,
+}
+
+impl Clone for MNode {
+    fn clone(&self) -> Self {
+        MNode::new(self.inode.clone(), self.mount.clone())
+    }
+}
+
+impl Drop for MNode {
+    fn drop(&mut self) {
+        self.mount.users.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl MNode {
+    fn new(inode: Arc<dyn INode>, mount: Arc<Mount>) -> Self {
+        mount.users.fetch_add(1, Ordering::SeqCst);
+        MNode { inode, mount }
+    }
+
+    /// The INode of the underlying filesystem
+    pub fn inner(&self) -> &Arc<dyn INode> {
+        &self.inode
+    }
+
+    fn wrap(&self, inode: Arc<dyn INode>) -> MNode {
+        MNode::new(inode, self.mount.clone())
+    }
+
+    fn is_mount_root(&self) -> Result<bool> {
+        Ok(self.inode.metadata()?.inode == self.mount.root_ino)
+    }
+
+    /// Go down to the root of the last filesystem mounted here, if any.
+    fn overlaid(self) -> Result<MNode> {
+        let mut node = self;
+        loop {
+            let ino = node.inode.metadata()?.inode;
+            let top = MOUNTS
+                .read()
+                .iter()
+                .rev()
+                .find(|mount| match &*mount.mountpoint.read() {
+                    Some((mp, mp_ino)) => Arc::ptr_eq(&mp.mount, &node.mount) && *mp_ino == ino,
+                    None => false,
+                })
+                .cloned();
+            match top {
+                Some(mount) => node = mount.root_node(),
+                None => return Ok(node),
+            }
+        }
+    }
+
+    /// Whether a filesystem is mounted on the child `name`
+    fn is_mountpoint(&self, name: &str) -> Result<bool> {
+        let ino = self.inode.find(name)?.metadata()?.inode;
+        Ok(MOUNTS
+            .read()
+            .iter()
+            .any(|mount| match &*mount.mountpoint.read() {
+                Some((mp, mp_ino)) => Arc::ptr_eq(&mp.mount, &self.mount) && *mp_ino == ino,
+                None => false,
+            }))
+    }
+
+    /// Whether `name` is the directory holding the extended attributes of
+    /// the filesystem, which users must not reach
+    fn is_hidden(&self, name: &str) -> bool {
+        name == XATTR_DIR
+            && match &self.mount.xattrs {
+                Some(table) => table.is_store_root(&self.inode),
+                None => false,
+            }
+    }
+
+    fn check_writable(&self) -> Result<()> {
+        if self.mount.read_only() {
+            // vfs has no read-only error, syscalls check with `check_writable` first
+            return Err(FsError::NotSupported);
+        }
+        Ok(())
+    }
+}
+
+/// Get the INode of the underlying filesystem and the mount of `other`.
+fn unwrap_node(other: &Arc<dyn INode>) -> Option<&MNode> {
+    other.as_any_ref().downcast_ref::<MNode>()
+}
+
+/// Get the INode of the underlying filesystem of `inode`.
+pub fn fs_inode(inode: &Arc<dyn INode>) -> &Arc<dyn INode> {
+    unwrap_node(inode).map_or(inode, |node| node.inner())
+}
+
+impl INode for MNode {
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize> {
+        self.inode.read_at(offset, buf)
+    }
+
+    fn write_at(&self, offset: usize, buf: &[u8]) -> Result<usize> {
+        self.check_writable()?;
+        self.inode.write_at(offset, buf)
+    }
+
+    fn poll(&self) -> Result<PollStatus> {
+        self.inode.poll()
+    }
+
+    fn async_poll<'a>(
+        &'a self,
+    ) -> Pin<Box<dyn Future<Output = Result<PollStatus>> + Send + Sync + 'a>> {
+        self.inode.async_poll()
+    }
+
+    fn metadata(&self) -> Result<Metadata> {
+        self.inode.metadata()
+    }
+
+    fn set_metadata(&self, metadata: &Metadata) -> Result<()> {
+        self.check_writable()?;
+        self.inode.set_metadata(metadata)
+    }
+
+    fn sync_all(&self) -> Result<()> {
+        self.inode.sync_all()
+    }
+
+    fn sync_data(&self) -> Result<()> {
+        self.inode.sync_data()
+    }
+
+    fn resize(&self, len: usize) -> Result<()> {
+        self.check_writable()?;
+        self.inode.resize(len)
+    }
+
+    fn create(&self, name: &str, type_: FileType, mode: u32) -> Result<Arc<dyn INode>> {
+        self.check_writable()?;
+        if self.is_hidden(name) {
+            return Err(FsError::EntryExist);
+        }
+        let inode = self.inode.create(name, type_, mode)?;
+        Ok(Arc::new(self.wrap(inode)))
+    }
+
+    fn link(&self, name: &str, other: &Arc<dyn INode>) -> Result<()> {
+        self.check_writable()?;
+        let other = unwrap_node(other).ok_or(FsError::NotSameFs)?;
+        if !Arc::ptr_eq(&other.mount, &self.mount) {
+            return Err(FsError::NotSameFs);
+        }
+        if self.is_hidden(name) {
+            return Err(FsError::EntryExist);
+        }
+        self.inode.link(name, &other.inode)
+    }
+
+    fn unlink(&self, name: &str) -> Result<()> {
+        self.check_writable()?;
+        if self.is_hidden(name) {
+            return Err(FsError::EntryNotFound);
+        }
+        if self.is_mountpoint(name)? {
+            return Err(FsError::Busy);
+        }
+        self.inode.unlink(name)
+    }
+
+    fn move_(&self, old_name: &str, target: &Arc<dyn INode>, new_name: &str) -> Result<()> {
+        self.check_writable()?;
+        let target = unwrap_node(target).ok_or(FsError::NotSameFs)?;
+        if !Arc::ptr_eq(&target.mount, &self.mount) {
+            return Err(FsError::NotSameFs);
+        }
+        if self.is_hidden(old_name) {
+            return Err(FsError::EntryNotFound);
+        }
+        if target.is_hidden(new_name) {
+            return Err(FsError::Busy);
+        }
+        if self.is_mountpoint(old_name)? {
+            return Err(FsError::Busy);
+        }
+        self.inode.move_(old_name, &target.inode, new_name)
+    }
+
+    fn find(&self, name: &str) -> Result<Arc<dyn INode>> {
+        match name {
+            "" | "." => Ok(Arc::new(self.clone())),
+            ".." if self.is_mount_root()? => match self.mount.mounted_on() {
+                Some((mountpoint, _)) => mountpoint.find(".."),
+                // `..` of the root is itself
+                None => Ok(Arc::new(self.clone())),
+            },
+            _ if self.is_hidden(name) => Err(FsError::EntryNotFound),
+            _ => {
+                let inode = self.inode.find(name)?;
+                Ok(Arc::new(self.wrap(inode).overlaid()?))
+            }
+        }
+    }
+
+    fn get_entry(&self, id: usize) -> Result<String> {
+        if !self.is_hidden(XATTR_DIR) {
+            return self.inode.get_entry(id);
+        }
+        // the entries after the hidden one move up
+        let mut visible = 0;
+        let mut i = 0;
+        loop {
+            let name = self.inode.get_entry(i)?;
+            i += 1;
+            if name == XATTR_DIR {
+                continue;
+            }
+            if visible == id {
+                return Ok(name);
+            }
+            visible += 1;
+        }
+    }
+
+    fn io_control(&self, cmd: u32, data: usize) -> Result<usize> {
+        self.inode.io_control(cmd, data)
+    }
+
+    fn fs(&self) -> Arc<dyn FileSystem> {
+        self.mount.clone()
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Mount `rootfs` as the root of the tree, and return the root INode.
+///
+/// The old table is dropped.
+pub fn mount_root(rootfs: Arc<dyn FileSystem>) -> Arc<dyn INode> {
+    let root = rootfs.root_inode();
+    let xattrs = Mount::new_xattrs("rootfs", &root);
+    let mount = Mount::new(
+        "rootfs",
+        "rootfs",
+        String::from("/"),
+        rootfs,
+        Mount::new_dev(),
+        root,
+        MountFlags::empty(),
+        None,
+        xattrs,
+    )
+    .expect("failed to mount rootfs");
+    let node = mount.root_node();
+    let mut mounts = MOUNTS.write();
+    mounts.clear();
+    mounts.push(mount);
+    Arc::new(node)
+}
+
+/// Create a filesystem of type `fstype`.
+///
+/// `source` is needed by filesystems on a disk, and must be a block device.
+pub fn create_fs(fstype: &str, source: Option<&Arc<dyn INode>>) -> LxResult<Arc<dyn FileSystem>> {
+    match fstype {
+        "tmpfs" | "ramfs" => Ok(RamFS::new()),
+        "devfs" | "devtmpfs" => Ok(create_devfs()),
+        "proc" | "procfs" => Ok(ProcFS::new()),
+        "sfs" => {
+            let source = source.ok_or(LxError::EINVAL)?;
+            let source = unwrap_node(source).map_or(source, |node| &node.inode);
+            let device = source
+                .as_any_ref()
+                .downcast_ref::<BlockDev>()
+                .ok_or(LxError::ENOTBLK)?
+                .clone();
+            Ok(rcore_fs_sfs::SimpleFileSystem::open(Arc::new(device))?)
+        }
+        _ => Err(LxError::ENODEV),
+    }
+}
+
+/// Get the mount point at `target`, checking it can be mounted on.
+fn mountpoint(target: &Arc<dyn INode>) -> LxResult<(MNode, usize)> {
+    let node = unwrap_node(target).ok_or(LxError::EINVAL)?;
+    let metadata = node.metadata()?;
+    if metadata.type_ != FileType::Dir {
+        return Err(LxError::ENOTDIR);
+    }
+    Ok((node.clone(), metadata.inode))
+}
+
+/// Mount `fs` on the directory `target`, whose absolute path is `path`.
+pub fn mount(
+    fs: Arc<dyn FileSystem>,
+    source: &str,
+    fstype: &str,
+    target: &Arc<dyn INode>,
+    path: String,
+    flags: MountFlags,
+) -> LxResult {
+    let mountpoint = mountpoint(target)?;
+    let root = fs.root_inode();
+    let xattrs = Mount::new_xattrs(fstype, &root);
+    let mount = Mount::new(
+        source,
+        fstype,
+        path,
+        fs,
+        Mount::new_dev(),
+        root,
+        flags,
+        Some(mountpoint),
+        xattrs,
+    )?;
+    MOUNTS.write().push(mount);
+    Ok(())
+}
+
+/// Make the subtree at `source` visible at `target` as well.
+pub fn bind(
+    source: &Arc<dyn INode>,
+    source_path: &str,
+    target: &Arc<dyn INode>,
+    path: String,
+    flags: MountFlags,
+) -> LxResult {
+    let source = unwrap_node(source).ok_or(LxError::EINVAL)?;
+    let mountpoint = mountpoint(target)?;
+    let mount = Mount::new(
+        source_path,
+        &source.mount.fstype,
+        path,
+        source.mount.fs.clone(),
+        source.mount.dev,
+        source.inode.clone(),
+        flags,
+        Some(mountpoint),
+        source.mount.xattrs.clone(),
+    )?;
+    MOUNTS.write().push(mount);
+    Ok(())
+}
+
+/// Change the flags of the mount whose root is `target`.
+pub fn remount(target: &Arc<dyn INode>, flags: MountFlags) -> LxResult {
+    let node = unwrap_node(target).ok_or(LxError::EINVAL)?;
+    if !node.is_mount_root()? {
+        return Err(LxError::EINVAL);
+    }
+    *node.mount.flags.write() = flags & MountFlags::PER_MOUNT;
+    Ok(())
+}
+
+/// Move the mount whose root is `source`, with the mounts under it, to the
+/// directory `target`, whose absolute path is `path`.
+///
+/// Fails with `ELOOP` if `target` is under the mount.
+pub fn move_mount(source: &Arc<dyn INode>, target: &Arc<dyn INode>, path: String) -> LxResult {
+    let mount = {
+        let node = unwrap_node(source).ok_or(LxError::EINVAL)?;
+        if !node.is_mount_root()? {
+            return Err(LxError::EINVAL);
+        }
+        node.mount.clone()
+    };
+    if mount.mounted_on().is_none() {
+        return Err(LxError::EINVAL);
+    }
+    let mountpoint = mountpoint(target)?;
+    let mut ancestor = Some(mountpoint.0.mount.clone());
+    while let Some(parent) = ancestor {
+        if Arc::ptr_eq(&parent, &mount) {
+            return Err(LxError::ELOOP);
+        }
+        ancestor = parent.mounted_on().map(|(node, _)| node.mount.clone());
+    }
+
+    let mut mounts = MOUNTS.write();
+    let mut subtree = vec![mount.clone()];
+    let mut i = 0;
+    while i < subtree.len() {
+        let parent = subtree[i].clone();
+        subtree.extend(mounts.iter().filter(|m| parent.is_parent_of(m)).cloned());
+        i += 1;
+    }
+    let old_path = mount.path.read().clone();
+    for m in subtree.iter() {
+        let mut m_path = m.path.write();
+        if let Some(rest) = m_path.strip_prefix(old_path.as_str()) {
+            *m_path = match path.as_str() {
+                "/" if !rest.is_empty() => String::from(rest),
+                _ => path.clone() + rest,
+            };
+        }
+    }
+    *mount.mountpoint.write() = Some(mountpoint);
+    // on top of the mounts at `target`, parents still before their children
+    mounts.retain(|m| !subtree.iter().any(|moved| Arc::ptr_eq(m, moved)));
+    mounts.extend(subtree);
+    Ok(())
+}
+
+/// Unmount the filesystem whose root is `target`.
+///
+/// Fails with `EBUSY` if something is mounted under it or an INode of it is
+/// still in use, such as an open file or the working directory of a process,
+/// unless `DETACH` is given.
+pub fn umount(target: Arc<dyn INode>, flags: UmountFlags) -> LxResult {
+    let mount = {
+        let node = unwrap_node(&target).ok_or(LxError::EINVAL)?;
+        if !node.is_mount_root()? {
+            return Err(LxError::EINVAL);
+        }
+        node.mount.clone()
+    };
+    drop(target);
+    if mount.mounted_on().is_none() {
+        return Err(LxError::EBUSY);
+    }
+    let mut mounts = MOUNTS.write();
+    if flags.contains(UmountFlags::DETACH) {
+        // detach the whole subtree, the INodes in use keep their mounts alive
+        let mut detached = vec![mount.clone()];
+        while let Some(parent) = detached.pop() {
+            mounts.retain(|m| !Arc::ptr_eq(m, &parent));
+            detached.extend(mounts.iter().filter(|m| parent.is_parent_of(m)).cloned());
+        }
+        // leave the directory it was on, which may be unmounted then
+        mount.mountpoint.write().take();
+        return Ok(());
+    }
+    if mounts.iter().any(|m| mount.is_parent_of(m)) {
+        return Err(LxError::EBUSY);
+    }
+    if mount.users.load(Ordering::SeqCst) > 0 {
+        return Err(LxError::EBUSY);
+    }
+    mount.fs.sync()?;
+    mounts.retain(|m| !Arc::ptr_eq(m, &mount));
+    Ok(())
+}
+
+/// Return `EROFS` if `inode` is on a read-only mount.
+pub fn check_writable(inode: &Arc<dyn INode>) -> LxResult {
+    match unwrap_node(inode) {
+        Some(node) if node.mount.read_only() => Err(LxError::EROFS),
+        _ => Ok(()),
+    }
+}
+
+/// Get the flags of the mount `inode` is on, none if it is not in the tree.
+fn flags_of(inode: &Arc<dyn INode>) -> MountFlags {
+    unwrap_node(inode).map_or(MountFlags::empty(), |node| *node.mount.flags.read())
+}
+
+/// Return `EACCES` if `inode` is on a mount disallowing program execution.
+pub fn check_executable(inode: &Arc<dyn INode>) -> LxResult {
+    if flags_of(inode).contains(MountFlags::NOEXEC) {
+        return Err(LxError::EACCES);
+    }
+    Ok(())
+}
+
+/// Return `EACCES` if `inode` is a device special file on a mount
+/// disallowing access to them.
+pub fn check_device(inode: &Arc<dyn INode>) -> LxResult {
+    if flags_of(inode).contains(MountFlags::NODEV) {
+        let type_ = inode.metadata()?.type_;
+        if type_ == FileType::CharDevice || type_ == FileType::BlockDevice {
+            return Err(LxError::EACCES);
+        }
+    }
+    Ok(())
+}
+
+/// Whether the set-user-ID and set-group-ID bits of `inode` are ignored on
+/// its mount.
+pub fn ignores_set_ids(inode: &Arc<dyn INode>) -> bool {
+    flags_of(inode).contains(MountFlags::NOSUID)
+}
+
+/// Get the table of extended attributes of the filesystem `inode` is on, and
+/// its inode number there, to change them if `write`.
+///
+/// Fails with `EOPNOTSUPP` if the filesystem cannot hold them, and with
+/// `EROFS` for a change on a read-only mount.
+pub fn xattr_table(inode: &Arc<dyn INode>, write: bool) -> LxResult<(Arc<XattrTable>, usize)> {
+    let node = unwrap_node(inode).ok_or(LxError::EOPNOTSUPP)?;
+    let table = node.mount.xattrs.clone().ok_or(LxError::EOPNOTSUPP)?;
+    if write && node.mount.read_only() {
+        return Err(LxError::EROFS);
+    }
+    Ok((table, node.inode.metadata()?.inode))
+}
+
+/// Get a key telling `inode` apart from all other INodes: the ID of the
+/// filesystem it is on and its inode number there.
+///
+/// The device in the metadata is no use for this, most filesystems leave it
+/// 0. The IDs are given when mounting and never reused, so keys of unmounted
+/// filesystems are not given to new ones. INodes which are not in the tree,
+/// such as pipes, are keyed by their address.
+pub fn inode_key(inode: &Arc<dyn INode>) -> LxResult<(usize, usize)> {
+    match unwrap_node(inode) {
+        Some(node) => Ok((node.mount.dev, node.inode.metadata()?.inode)),
+        None => Ok((0, Arc::as_ptr(inode) as *const u8 as usize)),
+    }
+}
+
+/// Status of the filesystem an INode is on
+pub struct FsStat {
+    /// magic number of the filesystem type
+    pub magic: usize,
+    /// ID of the filesystem, the device of its root
+    pub fsid: usize,
+    /// flags of the mount
+    pub flags: MountFlags,
+    /// sizes and usage of the filesystem
+    pub info: FsInfo,
+}
+
+/// Get the status of the filesystem `inode` is on, as by `statfs`.
+pub fn fs_stat(inode: &Arc<dyn INode>) -> LxResult<FsStat> {
+    if let Some(node) = unwrap_node(inode) {
+        let mount = &node.mount;
+        return Ok(FsStat {
+            magic: mount.magic(),
+            fsid: mount.root.metadata()?.dev,
+            flags: *mount.flags.read(),
+            info: mount.fs.info(),
+        });
+    }
+    // pipes and other INodes which are not in the tree
+    let magic = if inode.as_any_ref().is::<Pipe>() {
+        PIPEFS_MAGIC
+    } else {
+        ANON_INODE_FS_MAGIC
+    };
+    Ok(pseudo_fs_stat(magic))
+}
+
+/// Get the status of the filesystem the file `file` is on, as by `fstatfs`.
+///
+/// Sockets are not in the tree, they are on a filesystem of their own.
+pub fn file_fs_stat(file: Arc<dyn FileLike>) -> LxResult<FsStat> {
+    if file.as_socket().is_some() {
+        return Ok(pseudo_fs_stat(SOCKFS_MAGIC));
+    }
+    match file.downcast_arc::<File>() {
+        Ok(file) => fs_stat(&file.inode()),
+        Err(_) => Ok(pseudo_fs_stat(ANON_INODE_FS_MAGIC)),
+    }
+}
+
+/// Status of a filesystem not in the tree, holding no blocks
+fn pseudo_fs_stat(magic: usize) -> FsStat {
+    FsStat {
+        magic,
+        fsid: 0,
+        flags: MountFlags::empty(),
+        info: FsInfo {
+            bsize: PAGE_SIZE,
+            frsize: PAGE_SIZE,
+            blocks: 0,
+            bfree: 0,
+            bavail: 0,
+            files: 0,
+            ffree: 0,
+            namemax: 255,
+        },
+    }
+}
+
+/// Sync all mounted filesystems.
+pub fn sync_all() -> LxResult {
+    let mounts = MOUNTS.read().clone();
+    for mount in mounts {
+        mount.fs.sync()?;
+    }
+    Ok(())
+}
+
+/// Content of `/proc/mounts`
+pub fn mounts_info() -> String {
+    let mut info = String::new();
+    for mount in MOUNTS.read().iter() {
+        let flags = *mount.flags.read();
+        let mut options = vec![if flags.contains(MountFlags::RDONLY) {
+            "ro"
+        } else {
+            "rw"
+        }];
+        for (flag, name) in [
+            (MountFlags::NOSUID, "nosuid"),
+            (MountFlags::NODEV, "nodev"),
+            (MountFlags::NOEXEC, "noexec"),
+            (MountFlags::SYNCHRONOUS, "sync"),
+            (MountFlags::NOATIME, "noatime"),
+        ]
+        .iter()
+        {
+            if flags.contains(*flag) {
+                options.push(*name);
+            }
+        }
+        info += &mount.source;
+        info += " ";
+        info += &mount.path.read();
+        info += " ";
+        info += &mount.fstype;
+        info += " ";
+        info += &options.join(",");
+        info += " 0 0\n";
+    }
+    info
+}
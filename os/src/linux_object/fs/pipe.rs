@@ -1,8 +1,14 @@
 //! Implement INode for Pipe
+//!
+//! The data in a pipe is kept in pages, which can be moved to another pipe by
+//! `splice` or shared with it by `tee` without copying. Pages of user memory
+//! are put in a pipe by `vmsplice` without copying them either, later writes
+//! to the memory show through the pipe until it is read.
 #![deny(missing_docs)]
 
 use super::super::{sync::Event, sync::EventBus};
-use alloc::{boxed::Box, collections::vec_deque::VecDeque, sync::Arc};
+use crate::zircon_object::vm::{VmObject, PAGE_SIZE};
+use alloc::{boxed::Box, collections::vec_deque::VecDeque, sync::Arc, vec::Vec};
 use core::{any::Any, cmp::min};
 use core::{
     future::Future,
@@ -12,6 +18,10 @@ use core::{
 use rcore_fs::vfs::*;
 use spin::Mutex;
 
+/// The most bytes moved into a pipe by one `splice`, as the default capacity
+/// of a pipe on Linux
+pub const PIPE_SIZE: usize = 16 * PAGE_SIZE;
+
 #[derive(Clone, PartialEq)]
 #[allow(dead_code)]
 /// Pipe end specify
@@ -22,16 +32,138 @@ pub enum PipeEnd {
     Write,
 }
 
+/// A page of data in a pipe
+enum Page {
+    /// a page of the pipe itself
+    Owned(Vec<u8>),
+    /// the page at the offset of a VMO, spliced from user memory
+    Vmo(Arc<VmObject>, usize),
+}
+
+/// A range of a page of data in a pipe
+///
+/// The page may be shared with other pipes, it is only appended to while it
+/// is not.
+#[derive(Clone)]
+struct PipeBuffer {
+    page: Arc<Page>,
+    start: usize,
+    end: usize,
+}
+
+impl PipeBuffer {
+    fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    /// Copy the data to the start of `buf`, which is long enough.
+    fn copy_to(&self, buf: &mut [u8]) -> Result<()> {
+        let buf = &mut buf[..self.len()];
+        match &*self.page {
+            Page::Owned(page) => buf.copy_from_slice(&page[self.start..self.end]),
+            Page::Vmo(vmo, offset) => vmo
+                .read(offset + self.start, buf)
+                .map_err(|_| FsError::DeviceError)?,
+        }
+        Ok(())
+    }
+
+    /// Call `f` with the data, which is copied to `scratch` first if it is in
+    /// a VMO.
+    fn with_data<T>(&self, scratch: &mut Vec<u8>, f: impl FnOnce(&[u8]) -> T) -> Result<T> {
+        if let Page::Owned(page) = &*self.page {
+            return Ok(f(&page[self.start..self.end]));
+        }
+        scratch.resize(self.len(), 0);
+        self.copy_to(scratch)?;
+        Ok(f(scratch))
+    }
+
+    /// Append as much of `data` as fits in the page, if it can be written.
+    fn append(&mut self, data: &[u8]) -> usize {
+        match Arc::get_mut(&mut self.page) {
+            Some(Page::Owned(page)) if self.end == page.len() => {
+                let len = min(data.len(), PAGE_SIZE.saturating_sub(page.len()));
+                page.extend_from_slice(&data[..len]);
+                self.end += len;
+                len
+            }
+            _ => 0,
+        }
+    }
+}
+
 /// Pipe inner data
 pub struct PipeData {
-    /// pipe buffer
-    buf: VecDeque<u8>,
+    /// pipe buffers
+    bufs: VecDeque<PipeBuffer>,
     /// event bus for pipe
     eventbus: EventBus,
     /// number of pipe ends
     end_cnt: i32,
 }
 
+impl PipeData {
+    /// Append a copy of `data`, in the last page while there is room.
+    fn push(&mut self, mut data: &[u8]) {
+        if let Some(last) = self.bufs.back_mut() {
+            let len = last.append(data);
+            data = &data[len..];
+        }
+        for chunk in data.chunks(PAGE_SIZE) {
+            let mut page = Vec::with_capacity(PAGE_SIZE);
+            page.extend_from_slice(chunk);
+            self.push_buffer(PipeBuffer {
+                page: Arc::new(Page::Owned(page)),
+                start: 0,
+                end: chunk.len(),
+            });
+        }
+    }
+
+    /// Append `buf` without copying.
+    fn push_buffer(&mut self, buf: PipeBuffer) {
+        if buf.len() != 0 {
+            self.bufs.push_back(buf);
+        }
+    }
+
+    /// The buffers of the first `len` bytes, sharing the pages.
+    fn peek(&self, len: usize) -> Vec<PipeBuffer> {
+        let mut bufs = Vec::new();
+        let mut remain = len;
+        for buf in self.bufs.iter() {
+            if remain == 0 {
+                break;
+            }
+            let len = min(remain, buf.len());
+            bufs.push(PipeBuffer {
+                end: buf.start + len,
+                ..buf.clone()
+            });
+            remain -= len;
+        }
+        bufs
+    }
+
+    /// Remove the first `len` bytes.
+    fn consume(&mut self, len: usize) {
+        let mut remain = len;
+        while remain > 0 {
+            let buf = self.bufs.front_mut().unwrap();
+            if buf.len() > remain {
+                buf.start += remain;
+                break;
+            }
+            remain -= buf.len();
+            self.bufs.pop_front();
+        }
+        if self.bufs.is_empty() {
+            self.eventbus.clear(Event::READABLE);
+        }
+    }
+}
+
 /// pipe struct
 #[derive(Clone)]
 pub struct Pipe {
@@ -53,7 +185,7 @@ impl Pipe {
     /// Create a pair of INode: (read, write)
     pub fn create_pair() -> (Pipe, Pipe) {
         let inner = PipeData {
-            buf: VecDeque::new(),
+            bufs: VecDeque::new(),
             eventbus: EventBus::default(),
             end_cnt: 2, // one read, one write
         };
@@ -74,7 +206,7 @@ impl Pipe {
         if let PipeEnd::Read = self.direction {
             // true
             let data = self.data.lock();
-            !data.buf.is_empty() || data.end_cnt < 2 // other end closed
+            !data.bufs.is_empty() || data.end_cnt < 2 // other end closed
         } else {
             false
         }
@@ -88,6 +220,120 @@ impl Pipe {
             false
         }
     }
+
+    /// Whether the pipes are ends of the same pipe
+    pub fn same_pipe(&self, other: &Pipe) -> bool {
+        Arc::ptr_eq(&self.data, &other.data)
+    }
+
+    /// Move up to `len` bytes from this read end to the write end `other`,
+    /// without copying them.
+    pub fn splice_to(&self, other: &Pipe, len: usize) -> Result<usize> {
+        let bufs = self.take_front(len, true)?;
+        Ok(other.push_buffers(bufs))
+    }
+
+    /// Share up to `len` bytes from this read end with the write end `other`,
+    /// without consuming them.
+    pub fn tee_to(&self, other: &Pipe, len: usize) -> Result<usize> {
+        let bufs = self.take_front(len, false)?;
+        Ok(other.push_buffers(bufs))
+    }
+
+    /// Read up to `len` bytes with `f`, which is given the data page by page
+    /// and returns how much of it was used. Stops at the first page not used
+    /// up, or the first error after some data was used.
+    ///
+    /// The pipe is locked during `f`, which must not use it.
+    pub fn read_with<E: From<FsError>>(
+        &self,
+        len: usize,
+        mut f: impl FnMut(&[u8]) -> core::result::Result<usize, E>,
+    ) -> core::result::Result<usize, E> {
+        if self.direction != PipeEnd::Read {
+            return Err(FsError::InvalidParam.into());
+        }
+        let mut data = self.data.lock();
+        if data.bufs.is_empty() && data.end_cnt == 2 {
+            return Err(FsError::Again.into());
+        }
+        let mut done = 0;
+        let mut scratch = Vec::new();
+        for buf in data.peek(len) {
+            let result = buf.with_data(&mut scratch, &mut f).map_err(E::from);
+            match result.and_then(|result| result) {
+                Ok(used) => {
+                    done += used;
+                    if used < buf.len() {
+                        break;
+                    }
+                }
+                Err(err) if done == 0 => return Err(err),
+                Err(_) => break,
+            }
+        }
+        data.consume(done);
+        Ok(done)
+    }
+
+    /// Write a page of data to the write end, without copying it.
+    pub fn write_page(&self, page: Vec<u8>) -> Result<usize> {
+        let len = page.len();
+        self.push_buffers(vec![PipeBuffer {
+            page: Arc::new(Page::Owned(page)),
+            start: 0,
+            end: len,
+        }]);
+        Ok(len)
+    }
+
+    /// Write `len` bytes at `offset` of `vmo` to the write end, sharing the
+    /// page instead of copying it. The bytes must be in one page.
+    pub fn write_vmo(&self, vmo: Arc<VmObject>, offset: usize, len: usize) -> Result<usize> {
+        let start = offset % PAGE_SIZE;
+        if start + len > PAGE_SIZE {
+            return Err(FsError::InvalidParam);
+        }
+        let len = self.push_buffers(vec![PipeBuffer {
+            page: Arc::new(Page::Vmo(vmo, offset - start)),
+            start,
+            end: start + len,
+        }]);
+        Ok(len)
+    }
+
+    /// Get the buffers of the first `len` bytes from the read end, removing
+    /// them if `consume`.
+    fn take_front(&self, len: usize, consume: bool) -> Result<Vec<PipeBuffer>> {
+        if self.direction != PipeEnd::Read {
+            return Err(FsError::InvalidParam);
+        }
+        let mut data = self.data.lock();
+        if data.bufs.is_empty() && data.end_cnt == 2 {
+            return Err(FsError::Again);
+        }
+        let bufs = data.peek(len);
+        if consume {
+            data.consume(bufs.iter().map(PipeBuffer::len).sum());
+        }
+        Ok(bufs)
+    }
+
+    /// Append `bufs` to the write end, returning the bytes in them.
+    fn push_buffers(&self, bufs: Vec<PipeBuffer>) -> usize {
+        if self.direction != PipeEnd::Write {
+            return 0;
+        }
+        let mut data = self.data.lock();
+        let len = bufs.iter().map(PipeBuffer::len).sum();
+        for buf in bufs {
+            data.push_buffer(buf);
+        }
+        if len != 0 {
+            data.eventbus.set(Event::READABLE);
+        }
+        len
+    }
 }
 
 impl INode for Pipe {
@@ -98,16 +344,15 @@ impl INode for Pipe {
         }
         if let PipeEnd::Read = self.direction {
             let mut data = self.data.lock();
-            if data.buf.is_empty() && data.end_cnt == 2 {
+            if data.bufs.is_empty() && data.end_cnt == 2 {
                 Err(FsError::Again)
             } else {
-                let len = min(buf.len(), data.buf.len());
-                for item in buf.iter_mut().take(len) {
-                    *item = data.buf.pop_front().unwrap();
-                }
-                if data.buf.is_empty() {
-                    data.eventbus.clear(Event::READABLE);
+                let mut len = 0;
+                for page in data.peek(buf.len()) {
+                    page.copy_to(&mut buf[len..])?;
+                    len += page.len();
                 }
+                data.consume(len);
                 Ok(len)
             }
         } else {
@@ -119,9 +364,7 @@ impl INode for Pipe {
     fn write_at(&self, _offset: usize, buf: &[u8]) -> Result<usize> {
         if let PipeEnd::Write = self.direction {
             let mut data = self.data.lock();
-            for c in buf {
-                data.buf.push_back(*c);
-            }
+            data.push(buf);
             data.eventbus.set(Event::READABLE);
             Ok(buf.len())
         } else {
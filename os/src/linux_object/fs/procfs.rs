@@ -0,0 +1,532 @@
+//! Process information pseudo-filesystem
+//!
+//! Directories are listed from the process table on each lookup. The content
+//! of a file is generated when its INode is found, so a file opened earlier
+//! shows the state at the time it was opened.
+//!
+//! `/proc/self` is a symbolic link without a target of its own, because an
+//! INode does not know which process is looking it up. [`walk`] and
+//! `readlink` read it with [`read_link`], which links it to the directory of
+//! the process.
+//!
+//! [`walk`]: super::walk
+//! [`read_link`]: super::read_link
+
+use super::{as_tty, mount, FileDesc};
+use crate::kernel_hal::{frame_stats, hwcap, timer_now};
+use crate::linux_object::cred::IdSet;
+use crate::linux_object::process::{pids, process_by_pid, ProcessExt};
+use crate::linux_object::ptrace;
+use crate::zircon_object::object::{KernelObject, KoID};
+use crate::zircon_object::task::{Process, Status, Thread, ThreadState};
+use crate::zircon_object::vm::{MMUFlags, PAGE_SIZE};
+use alloc::{
+    format,
+    string::String,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::any::Any;
+use core::convert::TryFrom;
+use rcore_fs::vfs::*;
+
+/// Files in the root directory
+const GLOBAL_FILES: [&str; 6] = ["cpuinfo", "loadavg", "meminfo", "mounts", "stat", "uptime"];
+/// Files and directories in the directory of a process
+const PROCESS_FILES: [&str; 7] = ["cmdline", "environ", "exe", "fd", "maps", "stat", "status"];
+
+/// Process information filesystem, usually mounted at `/proc`
+pub struct ProcFS {
+    /// reference to itself, returned by `fs()` of its INodes
+    self_ref: Weak<ProcFS>,
+}
+
+impl ProcFS {
+    /// create a ProcFS
+    pub fn new() -> Arc<Self> {
+        let fs = Arc::new(ProcFS {
+            self_ref: Weak::new(),
+        });
+        let weak = Arc::downgrade(&fs);
+        let ptr = Arc::into_raw(fs) as *mut ProcFS;
+        // SAFETY: there is no other reference to `fs` yet
+        unsafe {
+            (*ptr).self_ref = weak;
+            Arc::from_raw(ptr)
+        }
+    }
+
+    fn inode(&self, node: Node) -> Arc<ProcINode> {
+        Arc::new(ProcINode::new(self.self_ref.upgrade().unwrap(), node))
+    }
+}
+
+impl FileSystem for ProcFS {
+    fn sync(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn root_inode(&self) -> Arc<dyn INode> {
+        self.inode(Node::Root)
+    }
+
+    fn info(&self) -> FsInfo {
+        FsInfo {
+            bsize: PAGE_SIZE,
+            frsize: PAGE_SIZE,
+            blocks: 0,
+            bfree: 0,
+            bavail: 0,
+            files: 0,
+            ffree: 0,
+            namemax: 255,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Node {
+    Root,
+    /// index in `GLOBAL_FILES`
+    Global(usize),
+    /// `/proc/self`
+    SelfLink,
+    Process(KoID),
+    /// index in `PROCESS_FILES`
+    ProcessFile(KoID, usize),
+    FdDir(KoID),
+    Fd(KoID, FileDesc),
+}
+
+/// INode of ProcFS
+pub struct ProcINode {
+    fs: Arc<ProcFS>,
+    node: Node,
+    content: Vec<u8>,
+    /// user and group owning it, the effective IDs of its process
+    owner: (u32, u32),
+}
+
+impl ProcINode {
+    fn new(fs: Arc<ProcFS>, node: Node) -> Self {
+        let content = match node {
+            Node::Global(i) => global_file(GLOBAL_FILES[i]),
+            Node::ProcessFile(pid, i) => match process_by_pid(pid) {
+                Some(proc) => process_file(&proc, PROCESS_FILES[i]),
+                None => String::new(),
+            },
+            Node::Fd(pid, fd) => match process_by_pid(pid) {
+                Some(proc) => fd_path(&proc, fd),
+                None => String::new(),
+            },
+            _ => String::new(),
+        };
+        let owner = match node {
+            Node::Process(pid)
+            | Node::ProcessFile(pid, _)
+            | Node::FdDir(pid)
+            | Node::Fd(pid, _) => process_by_pid(pid).map_or((0, 0), |proc| {
+                let cred = proc.linux().credentials();
+                (cred.user.effective, cred.group.effective)
+            }),
+            _ => (0, 0),
+        };
+        ProcINode {
+            fs,
+            node,
+            content: content.into_bytes(),
+            owner,
+        }
+    }
+
+    fn type_(&self) -> FileType {
+        match self.node {
+            Node::Root | Node::Process(_) | Node::FdDir(_) => FileType::Dir,
+            Node::ProcessFile(_, i) if PROCESS_FILES[i] == "exe" => FileType::SymLink,
+            Node::SelfLink | Node::Fd(_, _) => FileType::SymLink,
+            _ => FileType::File,
+        }
+    }
+
+    /// inode numbers must be unique for mounts on ProcFS to work
+    ///
+    /// The INodes of a process are numbered from the pid, the kind of INode
+    /// and its index in bit fields which do not overlap.
+    fn ino(&self) -> usize {
+        const PID_SHIFT: usize = 32;
+        const KIND_SHIFT: usize = 28;
+        let process = |pid: KoID, kind: usize, index: usize| {
+            ((pid as usize + 1) << PID_SHIFT)
+                | (kind << KIND_SHIFT)
+                | (index & ((1 << KIND_SHIFT) - 1))
+        };
+        match self.node {
+            Node::Root => 1,
+            Node::Global(i) => 2 + i,
+            Node::SelfLink => 2 + GLOBAL_FILES.len(),
+            Node::Process(pid) => process(pid, 0, 0),
+            Node::ProcessFile(pid, i) => process(pid, 1, i),
+            Node::FdDir(pid) => process(pid, 2, 0),
+            Node::Fd(pid, fd) => process(pid, 3, Into::<i32>::into(fd) as usize),
+        }
+    }
+
+    /// names of the entries of a directory, without `.` and `..`
+    fn entries(&self) -> Result<Vec<String>> {
+        match self.node {
+            Node::Root => {
+                let mut entries: Vec<String> = GLOBAL_FILES.iter().map(|&s| s.into()).collect();
+                entries.push(String::from("self"));
+                entries.extend(pids().iter().map(|pid| format!("{}", pid)));
+                Ok(entries)
+            }
+            Node::Process(_) => Ok(PROCESS_FILES.iter().map(|&s| s.into()).collect()),
+            Node::FdDir(pid) => {
+                let proc = process_by_pid(pid).ok_or(FsError::DirRemoved)?;
+                let fds = proc.linux().file_descriptors();
+                Ok(fds
+                    .into_iter()
+                    .map(|fd| format!("{}", Into::<i32>::into(fd)))
+                    .collect())
+            }
+            _ => Err(FsError::NotDir),
+        }
+    }
+}
+
+impl INode for ProcINode {
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize> {
+        if self.type_() == FileType::Dir {
+            return Err(FsError::IsDir);
+        }
+        if offset >= self.content.len() {
+            return Ok(0);
+        }
+        let len = (self.content.len() - offset).min(buf.len());
+        buf[..len].copy_from_slice(&self.content[offset..offset + len]);
+        Ok(len)
+    }
+
+    fn write_at(&self, _offset: usize, _buf: &[u8]) -> Result<usize> {
+        Err(FsError::NotSupported)
+    }
+
+    fn poll(&self) -> Result<PollStatus> {
+        Ok(PollStatus {
+            read: true,
+            write: false,
+            error: false,
+        })
+    }
+
+    fn metadata(&self) -> Result<Metadata> {
+        let type_ = self.type_();
+        let mode = match (type_, self.node) {
+            (FileType::Dir, _) => 0o555,
+            (FileType::SymLink, _) => 0o777,
+            // the environment is private to its owner
+            (_, Node::ProcessFile(_, i)) if PROCESS_FILES[i] == "environ" => 0o400,
+            _ => 0o444,
+        };
+        Ok(Metadata {
+            dev: 0,
+            inode: self.ino(),
+            size: self.content.len(),
+            blk_size: 0,
+            blocks: 0,
+            atime: Timespec { sec: 0, nsec: 0 },
+            mtime: Timespec { sec: 0, nsec: 0 },
+            ctime: Timespec { sec: 0, nsec: 0 },
+            type_,
+            mode,
+            nlinks: if type_ == FileType::Dir { 2 } else { 1 },
+            uid: self.owner.0 as usize,
+            gid: self.owner.1 as usize,
+            rdev: 0,
+        })
+    }
+
+    fn find(&self, name: &str) -> Result<Arc<dyn INode>> {
+        let node = match (self.node, name) {
+            (_, ".") => self.node,
+            (Node::Root, "..") => Node::Root,
+            (Node::Process(_), "..") => Node::Root,
+            (Node::FdDir(pid), "..") => Node::Process(pid),
+            (Node::ProcessFile(pid, _), "..") => Node::Process(pid),
+            (Node::Root, "self") => Node::SelfLink,
+            (Node::Root, name) => match GLOBAL_FILES.iter().position(|&s| s == name) {
+                Some(i) => Node::Global(i),
+                None => {
+                    let pid = name.parse::<KoID>().map_err(|_| FsError::EntryNotFound)?;
+                    process_by_pid(pid).ok_or(FsError::EntryNotFound)?;
+                    Node::Process(pid)
+                }
+            },
+            (Node::Process(pid), "fd") => Node::FdDir(pid),
+            (Node::Process(pid), name) => match PROCESS_FILES.iter().position(|&s| s == name) {
+                Some(i) => Node::ProcessFile(pid, i),
+                None => return Err(FsError::EntryNotFound),
+            },
+            (Node::FdDir(pid), name) => {
+                let fd = FileDesc::try_from(name).map_err(|_| FsError::EntryNotFound)?;
+                let proc = process_by_pid(pid).ok_or(FsError::EntryNotFound)?;
+                proc.linux()
+                    .get_file_like(fd)
+                    .map_err(|_| FsError::EntryNotFound)?;
+                Node::Fd(pid, fd)
+            }
+            _ => return Err(FsError::NotDir),
+        };
+        Ok(self.fs.inode(node))
+    }
+
+    fn get_entry(&self, id: usize) -> Result<String> {
+        match id {
+            0 => Ok(String::from(".")),
+            1 => Ok(String::from("..")),
+            i => self
+                .entries()?
+                .into_iter()
+                .nth(i - 2)
+                .ok_or(FsError::EntryNotFound),
+        }
+    }
+
+    fn fs(&self) -> Arc<dyn FileSystem> {
+        self.fs.clone()
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Whether `inode` is `/proc/self`, which links to the directory of the
+/// process looking it up.
+pub fn is_self_link(inode: &Arc<dyn INode>) -> bool {
+    mount::fs_inode(inode)
+        .as_any_ref()
+        .downcast_ref::<ProcINode>()
+        .map_or(false, |inode| inode.node == Node::SelfLink)
+}
+
+/// The ISA string of the harts in `/proc/cpuinfo`, like `rv64imafdc`, from
+/// the single-letter extensions in `hwcap`, in the canonical order
+fn isa_string(hwcap: usize) -> String {
+    let mut isa = format!("rv{}", core::mem::size_of::<usize>() * 8);
+    for &c in b"imafdqcbkjpvh" {
+        if hwcap & (1 << (c - b'a')) != 0 {
+            isa.push(c as char);
+        }
+    }
+    isa
+}
+
+fn global_file(name: &str) -> String {
+    match name {
+        "cpuinfo" => format!(
+            "processor\t: 0\nhart\t\t: 0\nisa\t\t: {}\nmmu\t\t: sv39\n\n",
+            isa_string(hwcap())
+        ),
+        "loadavg" => {
+            let pids = pids();
+            let last = pids.iter().max().cloned().unwrap_or(0);
+            format!("0.00 0.00 0.00 1/{} {}\n", pids.len(), last)
+        }
+        "meminfo" => {
+            let (total, free) = frame_stats();
+            let kb = PAGE_SIZE / 1024;
+            format!(
+                "MemTotal:       {:8} kB\nMemFree:        {:8} kB\nMemAvailable:   {:8} kB\n\
+                 Buffers:        {:8} kB\nCached:         {:8} kB\n",
+                total * kb,
+                free * kb,
+                free * kb,
+                0,
+                0
+            )
+        }
+        "mounts" => mount::mounts_info(),
+        "stat" => {
+            // all time is idle time in clock ticks
+            let idle = timer_now().as_millis() / 10;
+            let processes = pids().len();
+            format!(
+                "cpu  0 0 0 {0} 0 0 0 0 0 0\ncpu0 0 0 0 {0} 0 0 0 0 0 0\n\
+                 btime 0\nprocesses {1}\nprocs_running 1\nprocs_blocked 0\n",
+                idle, processes
+            )
+        }
+        "uptime" => {
+            let now = timer_now();
+            format!("{}.{:02} 0.00\n", now.as_secs(), now.subsec_millis() / 10)
+        }
+        _ => unreachable!(),
+    }
+}
+
+fn process_file(proc: &Arc<Process>, name: &str) -> String {
+    let linux = proc.linux();
+    match name {
+        "cmdline" => join_nul(&linux.args().0),
+        "environ" => join_nul(&linux.args().1),
+        "exe" => linux.execute_path(),
+        "maps" => {
+            let mut maps = String::new();
+            for map in proc.vmar().get_mappings() {
+                let line = format!(
+                    "{:08x}-{:08x} {}{}{}p {:08x} 00:00 0",
+                    map.addr,
+                    map.addr + map.size,
+                    if map.flags.contains(MMUFlags::READ) {
+                        'r'
+                    } else {
+                        '-'
+                    },
+                    if map.flags.contains(MMUFlags::WRITE) {
+                        'w'
+                    } else {
+                        '-'
+                    },
+                    if map.flags.contains(MMUFlags::EXECUTE) {
+                        'x'
+                    } else {
+                        '-'
+                    },
+                    map.vmo_offset
+                );
+                if map.vmo_name.is_empty() {
+                    maps += &format!("{}\n", line);
+                } else {
+                    // the pathname is padded to column 74 like Linux
+                    maps += &format!("{:<72} {}\n", line, map.vmo_name);
+                }
+            }
+            maps
+        }
+        "stat" => {
+            let (state, _) = state(proc);
+            let vsize: usize = proc.vmar().get_mappings().iter().map(|m| m.size).sum();
+            let mut fields = vec![
+                format!("{}", proc.id()),
+                format!("({})", comm(proc)),
+                format!("{}", state),
+                format!("{}", ppid(proc)),
+                format!("{}", linux.pgid()),
+                format!("{}", linux.sid()),
+            ];
+            match linux.controlling_tty() {
+                Some(tty) => {
+                    let rdev = tty.metadata().map_or(0, |m| m.rdev);
+                    let tpgid = as_tty(&tty).map_or(0, |tty| tty.foreground());
+                    fields.push(format!("{}", rdev));
+                    fields.push(format!("{}", tpgid));
+                }
+                None => fields.extend(["0", "-1"].iter().map(|&s| String::from(s))),
+            }
+            // flags, faults, times, priority and nice
+            fields.extend(
+                ["0", "0", "0", "0", "0", "0", "0", "0", "0", "20", "0"]
+                    .iter()
+                    .map(|&s| String::from(s)),
+            );
+            fields.push(format!("{}", proc.thread_ids().len()));
+            // itrealvalue and starttime
+            fields.extend(["0", "0"].iter().map(|&s| String::from(s)));
+            fields.push(format!("{}", vsize));
+            fields.push(format!("{}", vsize / PAGE_SIZE));
+            // the remaining fields are not tracked
+            fields.resize(52, String::from("0"));
+            fields.join(" ") + "\n"
+        }
+        "status" => {
+            let (state, state_name) = state(proc);
+            let vsize: usize = proc.vmar().get_mappings().iter().map(|m| m.size).sum();
+            let cred = linux.credentials();
+            let ids =
+                |ids: &IdSet| format!("{}\t{}\t{}\t{}", ids.real, ids.effective, ids.saved, ids.fs);
+            format!(
+                "Name:\t{}\nState:\t{} ({})\nTgid:\t{}\nPid:\t{}\nPPid:\t{}\n\
+                 Uid:\t{}\nGid:\t{}\nFDSize:\t{}\n\
+                 VmSize:\t{:8} kB\nVmRSS:\t{:8} kB\nThreads:\t{}\n",
+                comm(proc),
+                state,
+                state_name,
+                proc.id(),
+                proc.id(),
+                ppid(proc),
+                ids(&cred.user),
+                ids(&cred.group),
+                linux.file_descriptors().len(),
+                vsize / 1024,
+                vsize / 1024,
+                proc.thread_ids().len()
+            )
+        }
+        _ => unreachable!(),
+    }
+}
+
+/// Target of `/proc/[pid]/fd/[fd]`
+fn fd_path(proc: &Arc<Process>, fd: FileDesc) -> String {
+    let linux = proc.linux();
+    if let Ok(file) = linux.get_file(fd) {
+        return file.path.clone();
+    }
+    let fd: i32 = fd.into();
+    match linux.get_file_like(fd.into()) {
+        Ok(file) if file.as_socket().is_some() => format!("socket:[{}]", fd),
+        _ => format!("anon_inode:[{}]", fd),
+    }
+}
+
+/// Name of the program, at most 15 characters like Linux
+fn comm(proc: &Arc<Process>) -> String {
+    let path = proc.linux().execute_path();
+    let name = path.rsplit('/').next().unwrap_or("");
+    name.chars().take(15).collect()
+}
+
+fn ppid(proc: &Arc<Process>) -> KoID {
+    proc.linux().parent().map_or(0, |parent| parent.id())
+}
+
+/// State of the process, from the states of its threads
+fn state(proc: &Arc<Process>) -> (char, &'static str) {
+    if let Status::Exited(_) = proc.status() {
+        return ('Z', "zombie");
+    }
+    if proc.linux().is_stopped() {
+        return ('T', "stopped");
+    }
+    if ptrace::is_stopped(proc) {
+        return ('t', "tracing stop");
+    }
+    let states: Vec<ThreadState> = proc
+        .thread_ids()
+        .into_iter()
+        .filter_map(|id| proc.get_child(id).ok())
+        .filter_map(|thread| thread.downcast_arc::<Thread>().ok())
+        .map(|thread| thread.state())
+        .collect();
+    let running = |state: &ThreadState| matches!(state, ThreadState::New | ThreadState::Running);
+    let dead = |state: &ThreadState| matches!(state, ThreadState::Dying | ThreadState::Dead);
+    if states.is_empty() || states.iter().any(running) {
+        ('R', "running")
+    } else if states.iter().all(dead) {
+        ('X', "dead")
+    } else if states.iter().any(|&state| state == ThreadState::Suspended) {
+        ('T', "stopped")
+    } else {
+        ('S', "sleeping")
+    }
+}
+
+fn join_nul(strings: &[String]) -> String {
+    let mut s = String::new();
+    for string in strings {
+        s += string;
+        s.push('\0');
+    }
+    s
+}
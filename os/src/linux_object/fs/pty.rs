@@ -0,0 +1,330 @@
+//! Pseudo-terminals
+//!
+//! Opening `/dev/ptmx` creates a pair: the master is returned, the slave is a
+//! [`Tty`] at `/dev/pts/N`. What is written to the master is the input of the
+//! slave, and the output of the slave is read from the master. The slave can
+//! only be opened after it is unlocked with `TIOCSPTLCK`, as by `unlockpt`.
+//!
+//! Closing the master hangs up the slave.
+
+use super::super::error::*;
+use super::super::process::LinuxProcess;
+use super::super::{sync::Event, sync::EventBus};
+use super::ioctl::*;
+use super::mount;
+use super::tty::{termios_ioctl, Tty};
+use crate::kernel_hal::user::{UserInPtr, UserOutPtr};
+use alloc::{
+    boxed::Box,
+    collections::{BTreeMap, VecDeque},
+    format,
+    string::String,
+    sync::{Arc, Weak},
+};
+use core::any::Any;
+use core::cmp::min;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+use lazy_static::lazy_static;
+use rcore_fs::vfs::*;
+use rcore_fs_devfs::DevFS;
+use spin::Mutex;
+
+/// Major device number of the slaves
+const PTS_MAJOR: usize = 136;
+/// Max number of pseudo-terminals
+const PTY_MAX: usize = 256;
+/// Inode number of `/dev/pts`, apart from those of the devices
+const PTS_INO: usize = 0x7074_7300;
+
+lazy_static! {
+    /// Slaves by index, and whether they are locked
+    static ref PTYS: Mutex<BTreeMap<usize, (Arc<Tty>, bool)>> = Mutex::new(BTreeMap::new());
+}
+
+/// Output of the slave waiting to be read from the master
+#[derive(Default)]
+struct MasterBuffer {
+    buf: VecDeque<u8>,
+    eventbus: EventBus,
+}
+
+/// The master side of a pseudo-terminal
+pub struct PtyMaster {
+    index: usize,
+    slave: Arc<Tty>,
+    output: Arc<Mutex<MasterBuffer>>,
+}
+
+impl PtyMaster {
+    /// Create a pseudo-terminal, the slave is locked.
+    fn new() -> Result<Self> {
+        let mut ptys = PTYS.lock();
+        let index = (0..PTY_MAX)
+            .find(|i| !ptys.contains_key(i))
+            .ok_or(FsError::NoDeviceSpace)?;
+        let output = Arc::new(Mutex::new(MasterBuffer::default()));
+        let slave = Tty::new(
+            make_rdev(PTS_MAJOR, index),
+            Box::new({
+                let output = output.clone();
+                move |data| {
+                    let mut output = output.lock();
+                    output.buf.extend(data);
+                    output.eventbus.set(Event::READABLE);
+                }
+            }),
+        );
+        ptys.insert(index, (slave.clone(), true));
+        Ok(PtyMaster {
+            index,
+            slave,
+            output,
+        })
+    }
+
+    fn can_read(&self) -> bool {
+        !self.output.lock().buf.is_empty()
+    }
+}
+
+impl Drop for PtyMaster {
+    fn drop(&mut self) {
+        PTYS.lock().remove(&self.index);
+        self.slave.hangup();
+    }
+}
+
+impl INode for PtyMaster {
+    fn read_at(&self, _offset: usize, buf: &mut [u8]) -> Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let mut output = self.output.lock();
+        if output.buf.is_empty() {
+            return Err(FsError::Again);
+        }
+        let len = min(buf.len(), output.buf.len());
+        for (dst, src) in buf.iter_mut().zip(output.buf.drain(..len)) {
+            *dst = src;
+        }
+        if output.buf.is_empty() {
+            output.eventbus.clear(Event::READABLE);
+        }
+        Ok(len)
+    }
+
+    fn write_at(&self, _offset: usize, buf: &[u8]) -> Result<usize> {
+        for &c in buf {
+            self.slave.push(c);
+        }
+        Ok(buf.len())
+    }
+
+    fn poll(&self) -> Result<PollStatus> {
+        Ok(PollStatus {
+            read: self.can_read(),
+            write: true,
+            error: false,
+        })
+    }
+
+    fn async_poll<'a>(
+        &'a self,
+    ) -> Pin<Box<dyn Future<Output = Result<PollStatus>> + Send + Sync + 'a>> {
+        #[must_use = "future does nothing unless polled/`await`-ed"]
+        struct MasterFuture<'a> {
+            master: &'a PtyMaster,
+        };
+
+        impl<'a> Future for MasterFuture<'a> {
+            type Output = Result<PollStatus>;
+
+            fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+                if self.master.can_read() {
+                    return Poll::Ready(self.master.poll());
+                }
+                let waker = cx.waker().clone();
+                self.master.output.lock().eventbus.subscribe(Box::new({
+                    move |_| {
+                        waker.wake_by_ref();
+                        true
+                    }
+                }));
+                Poll::Pending
+            }
+        }
+
+        Box::pin(MasterFuture { master: self })
+    }
+
+    fn metadata(&self) -> Result<Metadata> {
+        Ptmx.metadata()
+    }
+
+    /// ioctls copying from or to the caller are handled by `pty_master_ioctl`
+    fn io_control(&self, cmd: u32, data: usize) -> Result<usize> {
+        self.slave.io_control(cmd, data)
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Handle the ioctls of the master behind `inode` which copy from or to the
+/// caller, those of the terminal go to the slave.
+///
+/// Return `None` if `inode` is not a master or `request` is not one of them.
+pub fn pty_master_ioctl(
+    proc: &LinuxProcess,
+    inode: &Arc<dyn INode>,
+    request: usize,
+    arg: usize,
+) -> Option<LxResult<usize>> {
+    let master = mount::fs_inode(inode)
+        .as_any_ref()
+        .downcast_ref::<PtyMaster>()?;
+    let ret = match request {
+        TIOCGPTN => UserOutPtr::<u32>::from(arg).write(master.index as u32),
+        TIOCSPTLCK => UserInPtr::<i32>::from(arg).read().map(|lock| {
+            if let Some(entry) = PTYS.lock().get_mut(&master.index) {
+                entry.1 = lock != 0;
+            }
+        }),
+        _ => return termios_ioctl(proc, &master.slave, request, arg),
+    };
+    Some(ret.map(|_| 0).map_err(LxError::from))
+}
+
+/// `/dev/ptmx`, opened as the master of a new pseudo-terminal
+#[derive(Default)]
+pub struct Ptmx;
+
+impl INode for Ptmx {
+    fn read_at(&self, _offset: usize, _buf: &mut [u8]) -> Result<usize> {
+        Err(FsError::NoDevice)
+    }
+
+    fn write_at(&self, _offset: usize, _buf: &[u8]) -> Result<usize> {
+        Err(FsError::NoDevice)
+    }
+
+    fn poll(&self) -> Result<PollStatus> {
+        Ok(PollStatus {
+            read: false,
+            write: false,
+            error: true,
+        })
+    }
+
+    fn metadata(&self) -> Result<Metadata> {
+        Ok(Metadata {
+            dev: 1,
+            inode: make_rdev(5, 2),
+            size: 0,
+            blk_size: 0,
+            blocks: 0,
+            atime: Timespec { sec: 0, nsec: 0 },
+            mtime: Timespec { sec: 0, nsec: 0 },
+            ctime: Timespec { sec: 0, nsec: 0 },
+            type_: FileType::CharDevice,
+            mode: 0o666,
+            nlinks: 1,
+            uid: 0,
+            gid: 0,
+            rdev: make_rdev(5, 2),
+        })
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// If `inode` is `/dev/ptmx`, create a pseudo-terminal and return its master.
+pub fn open_ptmx(inode: &Arc<dyn INode>) -> Option<Result<Arc<dyn INode>>> {
+    if !mount::fs_inode(inode).as_any_ref().is::<Ptmx>() {
+        return None;
+    }
+    Some(PtyMaster::new().map(|master| Arc::new(master) as Arc<dyn INode>))
+}
+
+/// `/dev/pts`, the directory of the slaves
+pub struct PtsDir {
+    /// the DevFS of `/dev`
+    devfs: Weak<DevFS>,
+}
+
+impl PtsDir {
+    /// create the directory in `devfs`
+    pub fn new(devfs: &Arc<DevFS>) -> Self {
+        PtsDir {
+            devfs: Arc::downgrade(devfs),
+        }
+    }
+}
+
+impl INode for PtsDir {
+    fn read_at(&self, _offset: usize, _buf: &mut [u8]) -> Result<usize> {
+        Err(FsError::IsDir)
+    }
+
+    fn write_at(&self, _offset: usize, _buf: &[u8]) -> Result<usize> {
+        Err(FsError::IsDir)
+    }
+
+    fn poll(&self) -> Result<PollStatus> {
+        Err(FsError::IsDir)
+    }
+
+    fn metadata(&self) -> Result<Metadata> {
+        Ok(Metadata {
+            dev: 1,
+            inode: PTS_INO,
+            size: 0,
+            blk_size: 0,
+            blocks: 0,
+            atime: Timespec { sec: 0, nsec: 0 },
+            mtime: Timespec { sec: 0, nsec: 0 },
+            ctime: Timespec { sec: 0, nsec: 0 },
+            type_: FileType::Dir,
+            mode: 0o755,
+            nlinks: 2,
+            uid: 0,
+            gid: 0,
+            rdev: 0,
+        })
+    }
+
+    fn find(&self, name: &str) -> Result<Arc<dyn INode>> {
+        if name == ".." {
+            let devfs = self.devfs.upgrade().ok_or(FsError::EntryNotFound)?;
+            return Ok(devfs.root_inode());
+        }
+        let index = name.parse::<usize>().map_err(|_| FsError::EntryNotFound)?;
+        match PTYS.lock().get(&index) {
+            Some((_, true)) => Err(FsError::DeviceError),
+            Some((slave, false)) => Ok(slave.clone()),
+            None => Err(FsError::EntryNotFound),
+        }
+    }
+
+    fn get_entry(&self, id: usize) -> Result<String> {
+        match id {
+            0 => Ok(String::from(".")),
+            1 => Ok(String::from("..")),
+            i => PTYS
+                .lock()
+                .keys()
+                .nth(i - 2)
+                .map(|index| format!("{}", index))
+                .ok_or(FsError::EntryNotFound),
+        }
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+}
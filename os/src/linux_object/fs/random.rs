@@ -1,20 +1,13 @@
 //! Implement INode for RandomINode
 
-use alloc::sync::Arc;
 use core::any::Any;
 
+use crate::kernel_hal::{add_entropy, fill_random};
 use rcore_fs::vfs::*;
-use spin::Mutex;
 
-/// random INode data struct
-pub struct RandomINodeData {
-    seed: u32,
-}
-
-/// random INode struct
+/// random INode struct, reading from the kernel CPRNG
 #[derive(Clone)]
 pub struct RandomINode {
-    data: Arc<Mutex<RandomINodeData>>,
     secure: bool,
 }
 
@@ -23,32 +16,28 @@ impl RandomINode {
     /// - urandom -> secure = true
     /// - random -> secure = false
     pub fn new(secure: bool) -> RandomINode {
-        RandomINode {
-            secure,
-            data: Arc::new(Mutex::new(RandomINodeData { seed: 1 })),
-        }
+        RandomINode { secure }
     }
 }
 
 impl INode for RandomINode {
     fn read_at(&self, _offset: usize, buf: &mut [u8]) -> Result<usize> {
-        let mut data = self.data.lock();
-        // from K&R
-        for x in buf.iter_mut() {
-            data.seed = data.seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
-            *x = (data.seed / 65536) as u8;
-        }
+        fill_random(buf);
         Ok(buf.len())
     }
 
-    fn write_at(&self, _offset: usize, _buf: &[u8]) -> Result<usize> {
-        Err(FsError::NotSupported)
+    /// written data is mixed into the CPRNG, without crediting entropy
+    fn write_at(&self, _offset: usize, buf: &[u8]) -> Result<usize> {
+        for chunk in buf.chunks(256) {
+            add_entropy(chunk);
+        }
+        Ok(buf.len())
     }
 
     fn poll(&self) -> Result<PollStatus> {
         Ok(PollStatus {
             read: true,
-            write: false,
+            write: true,
             error: false,
         })
     }
@@ -0,0 +1,120 @@
+//! Moving data through pipes
+//!
+//! Pages move between pipes without being copied. The filesystems have no
+//! page cache to share pages with, so data between a file and a pipe is
+//! copied once, straight from or to the pages of the pipe.
+
+use super::*;
+use crate::zircon_object::vm::PAGE_SIZE;
+use core::cmp::min;
+
+/// Move up to `len` bytes between the files, one of which must be a pipe.
+///
+/// The offsets are for the file which is not a pipe: with one, it is read
+/// or written there and the offset advanced, instead of at the file offset.
+/// The offset of a pipe must be `None`.
+pub async fn splice(
+    in_file: &File,
+    off_in: Option<&mut u64>,
+    out_file: &File,
+    off_out: Option<&mut u64>,
+    len: usize,
+    nonblock: bool,
+) -> LxResult<usize> {
+    if !in_file.options.read || !out_file.options.write {
+        return Err(LxError::EBADF);
+    }
+    match (in_file.as_pipe(), out_file.as_pipe()) {
+        (Some(in_pipe), Some(out_pipe)) => {
+            if off_in.is_some() || off_out.is_some() {
+                return Err(LxError::ESPIPE);
+            }
+            if in_pipe.same_pipe(out_pipe) {
+                return Err(LxError::EINVAL);
+            }
+            wait_pipe(in_file, nonblock, || Ok(in_pipe.splice_to(out_pipe, len)?)).await
+        }
+        (Some(in_pipe), None) => {
+            if off_in.is_some() {
+                return Err(LxError::ESPIPE);
+            }
+            if off_out.is_some() && out_file.options.append {
+                return Err(LxError::EINVAL);
+            }
+            let mut offset = off_out;
+            wait_pipe(in_file, nonblock, || {
+                in_pipe.read_with(len, |data| match offset.as_mut() {
+                    Some(offset) => {
+                        let len = out_file.write_at(**offset, data)?;
+                        **offset += len as u64;
+                        Ok(len)
+                    }
+                    None => out_file.write(data),
+                })
+            })
+            .await
+        }
+        (None, Some(out_pipe)) => {
+            if off_out.is_some() {
+                return Err(LxError::ESPIPE);
+            }
+            let mut offset = off_in;
+            // pipes are not bounded, so do not move more than one could hold
+            let len = min(len, PIPE_SIZE);
+            let mut done = 0;
+            while done < len {
+                let mut page = vec![0u8; min(len - done, PAGE_SIZE)];
+                let result = match offset.as_mut() {
+                    Some(offset) => in_file.read_at(**offset, &mut page).await.map(|len| {
+                        **offset += len as u64;
+                        len
+                    }),
+                    None => in_file.read(&mut page).await,
+                };
+                let read_len = match result {
+                    Ok(read_len) => read_len,
+                    Err(err) if done == 0 => return Err(err),
+                    Err(_) => break,
+                };
+                let short = read_len < page.len();
+                page.truncate(read_len);
+                done += out_pipe.write_page(page)?;
+                if short {
+                    break;
+                }
+            }
+            Ok(done)
+        }
+        (None, None) => Err(LxError::EINVAL),
+    }
+}
+
+/// Duplicate up to `len` bytes from the pipe `in_file` to the pipe
+/// `out_file` without consuming them, the pages are shared by both pipes.
+pub async fn tee(in_file: &File, out_file: &File, len: usize, nonblock: bool) -> LxResult<usize> {
+    if !in_file.options.read || !out_file.options.write {
+        return Err(LxError::EBADF);
+    }
+    let (in_pipe, out_pipe) = match (in_file.as_pipe(), out_file.as_pipe()) {
+        (Some(in_pipe), Some(out_pipe)) if !in_pipe.same_pipe(out_pipe) => (in_pipe, out_pipe),
+        _ => return Err(LxError::EINVAL),
+    };
+    wait_pipe(in_file, nonblock, || Ok(in_pipe.tee_to(out_pipe, len)?)).await
+}
+
+/// Do `op` on the read end `file` of a pipe, again each time the pipe becomes
+/// readable while it fails with `EAGAIN`, unless not blocking.
+pub async fn wait_pipe<T>(
+    file: &File,
+    nonblock: bool,
+    mut op: impl FnMut() -> LxResult<T>,
+) -> LxResult<T> {
+    loop {
+        match op() {
+            Err(LxError::EAGAIN) if !nonblock && !file.options.nonblock => {
+                file.async_poll().await?;
+            }
+            result => return result,
+        }
+    }
+}
@@ -0,0 +1,835 @@
+//! Terminals and their line discipline
+//!
+//! A [`Tty`] turns the input of a terminal into what processes read, as set
+//! by termios: in canonical mode input is collected into lines that can be
+//! edited, with `ISIG` the interrupt characters become signals for the
+//! foreground process group, and input is echoed to the output.
+//!
+//! The console on the serial port and the slaves of pseudo-terminals are
+//! ttys. `/dev/tty` is the controlling terminal of the process opening it.
+#![allow(unsafe_code)]
+
+use super::super::error::*;
+use super::super::process::{process_group, signal_process_group, LinuxProcess, ProcessExt};
+use super::super::signal::{Signal, SIG_IGN};
+use super::super::{sync::Event, sync::EventBus};
+use super::ioctl::*;
+use super::mount;
+use super::pty::pty_master_ioctl;
+use crate::kernel_hal::user::{UserInPtr, UserOutPtr};
+use crate::zircon_object::object::KoID;
+use alloc::{boxed::Box, collections::VecDeque, sync::Arc, vec::Vec};
+use core::any::Any;
+use core::cmp::min;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+use lazy_static::lazy_static;
+use rcore_fs::vfs::*;
+use spin::Mutex;
+
+lazy_static! {
+    /// The console on the serial port
+    pub static ref CONSOLE: Arc<Tty> = Tty::new(
+        make_rdev(5, 1),
+        Box::new(|buf| {
+            // we do not care the utf-8 things, we just want to print it!
+            let s = unsafe { core::str::from_utf8_unchecked(buf) };
+            crate::kernel_hal::serial_write(s);
+        })
+    );
+}
+
+/// Number of control characters
+const NCCS: usize = 19;
+
+// indices of control characters
+const VINTR: usize = 0;
+const VQUIT: usize = 1;
+const VERASE: usize = 2;
+const VKILL: usize = 3;
+const VEOF: usize = 4;
+const VMIN: usize = 6;
+const VSUSP: usize = 10;
+const VEOL: usize = 11;
+const VREPRINT: usize = 12;
+const VWERASE: usize = 14;
+const VLNEXT: usize = 15;
+const VEOL2: usize = 16;
+
+// input modes
+const INLCR: u32 = 0o100;
+const IGNCR: u32 = 0o200;
+const ICRNL: u32 = 0o400;
+
+// output modes
+const OPOST: u32 = 0o1;
+const ONLCR: u32 = 0o4;
+
+// local modes
+const ISIG: u32 = 0o1;
+const ICANON: u32 = 0o2;
+const ECHO: u32 = 0o10;
+const ECHOE: u32 = 0o20;
+const ECHOK: u32 = 0o40;
+const ECHONL: u32 = 0o100;
+const NOFLSH: u32 = 0o200;
+const TOSTOP: u32 = 0o400;
+const ECHOCTL: u32 = 0o1000;
+const ECHOKE: u32 = 0o4000;
+const IEXTEN: u32 = 0o100000;
+
+// arguments of TCFLSH
+const TCIFLUSH: usize = 0;
+const TCIOFLUSH: usize = 2;
+
+/// Terminal settings, the `struct termios` of the kernel
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct Termios {
+    /// input modes
+    pub iflag: u32,
+    /// output modes
+    pub oflag: u32,
+    /// control modes
+    pub cflag: u32,
+    /// local modes
+    pub lflag: u32,
+    /// line discipline
+    pub line: u8,
+    /// control characters
+    pub cc: [u8; NCCS],
+}
+
+impl Default for Termios {
+    /// the settings of a newly opened terminal on Linux
+    fn default() -> Self {
+        Termios {
+            iflag: ICRNL | 0o2000, // IXON
+            oflag: OPOST | ONLCR,
+            cflag: 0o277, // B38400 | CS8 | CREAD
+            lflag: ISIG | ICANON | ECHO | ECHOE | ECHOK | ECHOCTL | ECHOKE | IEXTEN,
+            line: 0,
+            cc: [
+                3,    // VINTR: ^C
+                0x1c, // VQUIT: ^\
+                0x7f, // VERASE: DEL
+                0x15, // VKILL: ^U
+                4,    // VEOF: ^D
+                0,    // VTIME
+                1,    // VMIN
+                0,    // VSWTC
+                0x11, // VSTART: ^Q
+                0x13, // VSTOP: ^S
+                0x1a, // VSUSP: ^Z
+                0,    // VEOL
+                0x12, // VREPRINT: ^R
+                0xf,  // VDISCARD: ^O
+                0x17, // VWERASE: ^W
+                0x16, // VLNEXT: ^V
+                0,    // VEOL2
+                0, 0,
+            ],
+        }
+    }
+}
+
+/// Terminal window size
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct WinSize {
+    /// rows, in characters
+    pub row: u16,
+    /// columns, in characters
+    pub col: u16,
+    /// width, in pixels
+    pub xpixel: u16,
+    /// height, in pixels
+    pub ypixel: u16,
+}
+
+impl Default for WinSize {
+    fn default() -> Self {
+        WinSize {
+            row: 24,
+            col: 80,
+            xpixel: 0,
+            ypixel: 0,
+        }
+    }
+}
+
+/// A terminal with its line discipline
+pub struct Tty {
+    /// device number
+    rdev: usize,
+    inner: Mutex<TtyInner>,
+    eventbus: Mutex<EventBus>,
+    /// write to the device
+    output: Box<dyn Fn(&[u8]) + Send + Sync>,
+}
+
+#[derive(Default)]
+struct TtyInner {
+    termios: Termios,
+    winsize: WinSize,
+    /// the line being edited in canonical mode
+    line: Vec<u8>,
+    /// input to be read, a line each in canonical mode, an empty line is EOF
+    input: VecDeque<Vec<u8>>,
+    /// the next character is taken literally
+    literal: bool,
+    /// the session the terminal controls, 0 if none
+    session: KoID,
+    /// the foreground process group, 0 if none
+    foreground: KoID,
+    /// the other side has gone away
+    hangup: bool,
+}
+
+/// Whether `c` is the control character `cc`, which may be disabled.
+fn is_char(c: u8, cc: u8) -> bool {
+    cc != 0 && c == cc
+}
+
+/// Whether `c` is echoed as `^X` with `ECHOCTL`.
+fn is_ctl(c: u8) -> bool {
+    (c < 0x20 && c != b'\n' && c != b'\t') || c == 0x7f
+}
+
+impl TtyInner {
+    fn has(&self, lflag: u32) -> bool {
+        self.termios.lflag & lflag != 0
+    }
+
+    fn echo(&self, c: u8, echo: &mut Vec<u8>) {
+        if !self.has(ECHO) {
+            if c == b'\n' && self.has(ECHONL) && self.has(ICANON) {
+                echo.push(c);
+            }
+            return;
+        }
+        if self.has(ECHOCTL) && is_ctl(c) {
+            echo.extend_from_slice(&[b'^', c ^ 0x40]);
+        } else {
+            echo.push(c);
+        }
+    }
+
+    /// Erase the last character of the line, return false if it is empty.
+    fn erase(&mut self, echo: &mut Vec<u8>) -> bool {
+        let c = match self.line.pop() {
+            Some(c) => c,
+            None => return false,
+        };
+        if self.has(ECHO) && self.has(ECHOE) {
+            let width = if self.has(ECHOCTL) && is_ctl(c) { 2 } else { 1 };
+            for _ in 0..width {
+                echo.extend_from_slice(b"\x08 \x08");
+            }
+        }
+        true
+    }
+
+    /// Move the line being edited to the input.
+    fn commit_line(&mut self) {
+        let line = core::mem::take(&mut self.line);
+        self.input.push_back(line);
+    }
+
+    /// Process one character of input, return the signal it generates.
+    fn receive(&mut self, c: u8, echo: &mut Vec<u8>) -> Option<Signal> {
+        let cc = self.termios.cc;
+        let canonical = self.has(ICANON);
+        let mut c = c;
+        if self.literal {
+            self.literal = false;
+            if self.has(ECHO) && self.has(ECHOCTL) {
+                // remove the `^` put out for VLNEXT
+                echo.push(0x08);
+            }
+        } else {
+            match c {
+                b'\r' if self.termios.iflag & IGNCR != 0 => return None,
+                b'\r' if self.termios.iflag & ICRNL != 0 => c = b'\n',
+                b'\n' if self.termios.iflag & INLCR != 0 => c = b'\r',
+                _ => {}
+            }
+            if self.has(ISIG) {
+                let signal = if is_char(c, cc[VINTR]) {
+                    Some(Signal::SIGINT)
+                } else if is_char(c, cc[VQUIT]) {
+                    Some(Signal::SIGQUIT)
+                } else if is_char(c, cc[VSUSP]) {
+                    Some(Signal::SIGTSTP)
+                } else {
+                    None
+                };
+                if signal.is_some() {
+                    if !self.has(NOFLSH) {
+                        self.line.clear();
+                        self.input.clear();
+                    }
+                    self.echo(c, echo);
+                    return signal;
+                }
+            }
+            if self.has(IEXTEN) && is_char(c, cc[VLNEXT]) {
+                self.literal = true;
+                if self.has(ECHO) && self.has(ECHOCTL) {
+                    echo.push(b'^');
+                }
+                return None;
+            }
+            if canonical {
+                if is_char(c, cc[VERASE]) {
+                    self.erase(echo);
+                    return None;
+                }
+                if is_char(c, cc[VKILL]) {
+                    if self.has(ECHO) && self.has(ECHOKE) {
+                        while self.erase(echo) {}
+                    } else {
+                        self.line.clear();
+                        if self.has(ECHO) && self.has(ECHOK) {
+                            self.echo(c, echo);
+                            echo.push(b'\n');
+                        }
+                    }
+                    return None;
+                }
+                if self.has(IEXTEN) && is_char(c, cc[VWERASE]) {
+                    while self.line.last().map_or(false, |c| c.is_ascii_whitespace()) {
+                        self.erase(echo);
+                    }
+                    while self.line.last().map_or(false, |c| !c.is_ascii_whitespace()) {
+                        self.erase(echo);
+                    }
+                    return None;
+                }
+                if self.has(IEXTEN) && is_char(c, cc[VREPRINT]) {
+                    self.echo(c, echo);
+                    echo.push(b'\n');
+                    for &c in self.line.iter() {
+                        self.echo(c, echo);
+                    }
+                    return None;
+                }
+                if is_char(c, cc[VEOF]) {
+                    self.commit_line();
+                    return None;
+                }
+            }
+        }
+        self.echo(c, echo);
+        if !canonical {
+            self.input.push_back(vec![c]);
+            return None;
+        }
+        self.line.push(c);
+        if c == b'\n' || is_char(c, cc[VEOL]) || is_char(c, cc[VEOL2]) {
+            self.commit_line();
+        }
+        None
+    }
+
+    fn can_read(&self) -> bool {
+        !self.input.is_empty() || self.hangup || (!self.has(ICANON) && self.termios.cc[VMIN] == 0)
+    }
+
+    fn flush_input(&mut self) {
+        self.line.clear();
+        self.input.clear();
+    }
+}
+
+impl Tty {
+    /// Create a terminal writing its output with `output`.
+    pub fn new(rdev: usize, output: Box<dyn Fn(&[u8]) + Send + Sync>) -> Arc<Self> {
+        Arc::new(Tty {
+            rdev,
+            inner: Mutex::new(TtyInner::default()),
+            eventbus: Mutex::new(EventBus::default()),
+            output,
+        })
+    }
+
+    /// Process a character of input.
+    pub fn push(&self, c: u8) {
+        let mut echo = Vec::new();
+        let (signal, foreground, readable) = {
+            let mut inner = self.inner.lock();
+            let signal = inner.receive(c, &mut echo);
+            (signal, inner.foreground, inner.can_read())
+        };
+        if readable {
+            self.eventbus.lock().set(Event::READABLE);
+        }
+        self.write_output(&echo);
+        if let Some(signal) = signal {
+            if foreground != 0 {
+                signal_process_group(foreground, signal);
+            }
+        }
+    }
+
+    /// The other side of the terminal has gone away.
+    ///
+    /// Reads return end of file from now on, and the foreground process group
+    /// gets `SIGHUP`.
+    pub fn hangup(&self) {
+        let foreground = {
+            let mut inner = self.inner.lock();
+            inner.hangup = true;
+            inner.session = 0;
+            core::mem::replace(&mut inner.foreground, 0)
+        };
+        self.eventbus.lock().set(Event::READABLE | Event::CLOSED);
+        if foreground != 0 {
+            signal_process_group(foreground, Signal::SIGHUP);
+        }
+    }
+
+    /// Get the session the terminal controls, 0 if none.
+    pub fn session(&self) -> KoID {
+        self.inner.lock().session
+    }
+
+    /// Get the foreground process group, 0 if none.
+    pub fn foreground(&self) -> KoID {
+        self.inner.lock().foreground
+    }
+
+    /// Make the terminal control the session `sid`, with `pgid` in the foreground.
+    pub fn set_controlling(&self, sid: KoID, pgid: KoID) {
+        let mut inner = self.inner.lock();
+        inner.session = sid;
+        inner.foreground = pgid;
+    }
+
+    /// Detach the terminal from its session.
+    pub fn release_controlling(&self) {
+        let mut inner = self.inner.lock();
+        inner.session = 0;
+        inner.foreground = 0;
+    }
+
+    /// Get the terminal settings.
+    pub fn termios(&self) -> Termios {
+        self.inner.lock().termios
+    }
+
+    /// Get the window size.
+    pub fn winsize(&self) -> WinSize {
+        self.inner.lock().winsize
+    }
+
+    /// Set the window size.
+    pub fn set_winsize(&self, winsize: WinSize) {
+        self.inner.lock().winsize = winsize;
+    }
+
+    /// Get the number of bytes of input that can be read.
+    pub fn input_len(&self) -> usize {
+        let inner = self.inner.lock();
+        inner.input.iter().map(|line| line.len()).sum()
+    }
+
+    /// Write `buf` to the device, after output processing.
+    fn write_output(&self, buf: &[u8]) {
+        if buf.is_empty() {
+            return;
+        }
+        let oflag = self.inner.lock().termios.oflag;
+        if oflag & OPOST != 0 && oflag & ONLCR != 0 && buf.contains(&b'\n') {
+            let mut out = Vec::with_capacity(buf.len() * 2);
+            for &c in buf {
+                if c == b'\n' {
+                    out.push(b'\r');
+                }
+                out.push(c);
+            }
+            (self.output)(&out);
+        } else {
+            (self.output)(buf);
+        }
+    }
+
+    fn update_readable(&self, inner: &TtyInner) {
+        if inner.can_read() {
+            self.eventbus.lock().set(Event::READABLE);
+        } else {
+            self.eventbus.lock().clear(Event::READABLE);
+        }
+    }
+
+    /// Change the terminal settings, discarding the input with `flush`.
+    pub fn set_termios(&self, termios: Termios, flush: bool) {
+        let mut inner = self.inner.lock();
+        if flush {
+            inner.flush_input();
+        }
+        // a partial line becomes input when leaving canonical mode
+        if termios.lflag & ICANON == 0 && !inner.line.is_empty() {
+            inner.commit_line();
+        }
+        inner.termios = termios;
+        self.update_readable(&inner);
+    }
+}
+
+impl INode for Tty {
+    fn read_at(&self, _offset: usize, buf: &mut [u8]) -> Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let mut inner = self.inner.lock();
+        if inner.input.is_empty() {
+            return if inner.can_read() {
+                Ok(0)
+            } else {
+                Err(FsError::Again)
+            };
+        }
+        // a read returns at most one line in canonical mode
+        let canonical = inner.has(ICANON);
+        let mut len = 0;
+        while let Some(chunk) = inner.input.front_mut() {
+            let n = min(buf.len() - len, chunk.len());
+            buf[len..len + n].copy_from_slice(&chunk[..n]);
+            chunk.drain(..n);
+            len += n;
+            if chunk.is_empty() {
+                inner.input.pop_front();
+            }
+            if canonical || len == buf.len() {
+                break;
+            }
+        }
+        self.update_readable(&inner);
+        Ok(len)
+    }
+
+    fn write_at(&self, _offset: usize, buf: &[u8]) -> Result<usize> {
+        if self.inner.lock().hangup {
+            return Err(FsError::DeviceError);
+        }
+        self.write_output(buf);
+        Ok(buf.len())
+    }
+
+    fn poll(&self) -> Result<PollStatus> {
+        let inner = self.inner.lock();
+        Ok(PollStatus {
+            read: inner.can_read(),
+            write: !inner.hangup,
+            error: inner.hangup,
+        })
+    }
+
+    fn async_poll<'a>(
+        &'a self,
+    ) -> Pin<Box<dyn Future<Output = Result<PollStatus>> + Send + Sync + 'a>> {
+        #[must_use = "future does nothing unless polled/`await`-ed"]
+        struct TtyFuture<'a> {
+            tty: &'a Tty,
+        };
+
+        impl<'a> Future for TtyFuture<'a> {
+            type Output = Result<PollStatus>;
+
+            fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+                if self.tty.inner.lock().can_read() {
+                    return Poll::Ready(self.tty.poll());
+                }
+                let waker = cx.waker().clone();
+                self.tty.eventbus.lock().subscribe(Box::new({
+                    move |_| {
+                        waker.wake_by_ref();
+                        true
+                    }
+                }));
+                Poll::Pending
+            }
+        }
+
+        Box::pin(TtyFuture { tty: self })
+    }
+
+    fn metadata(&self) -> Result<Metadata> {
+        Ok(Metadata {
+            dev: 1,
+            inode: self.rdev,
+            size: 0,
+            blk_size: 0,
+            blocks: 0,
+            atime: Timespec { sec: 0, nsec: 0 },
+            mtime: Timespec { sec: 0, nsec: 0 },
+            ctime: Timespec { sec: 0, nsec: 0 },
+            type_: FileType::CharDevice,
+            mode: 0o620,
+            nlinks: 1,
+            uid: 0,
+            gid: 0,
+            rdev: self.rdev,
+        })
+    }
+
+    /// ioctls copying from or to the caller are handled by `tty_ioctl`
+    fn io_control(&self, cmd: u32, data: usize) -> Result<usize> {
+        match cmd as usize {
+            TCFLSH => {
+                if data == TCIFLUSH || data == TCIOFLUSH {
+                    let mut inner = self.inner.lock();
+                    inner.flush_input();
+                    self.update_readable(&inner);
+                }
+                Ok(0)
+            }
+            _ => Err(FsError::NotSupported),
+        }
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// `/dev/tty`, opened as the controlling terminal of the process
+#[derive(Default)]
+pub struct DevTty;
+
+impl INode for DevTty {
+    fn read_at(&self, _offset: usize, _buf: &mut [u8]) -> Result<usize> {
+        Err(FsError::NoDevice)
+    }
+
+    fn write_at(&self, _offset: usize, _buf: &[u8]) -> Result<usize> {
+        Err(FsError::NoDevice)
+    }
+
+    fn poll(&self) -> Result<PollStatus> {
+        Ok(PollStatus {
+            read: false,
+            write: false,
+            error: true,
+        })
+    }
+
+    fn metadata(&self) -> Result<Metadata> {
+        Ok(Metadata {
+            dev: 1,
+            inode: make_rdev(5, 0),
+            size: 0,
+            blk_size: 0,
+            blocks: 0,
+            atime: Timespec { sec: 0, nsec: 0 },
+            mtime: Timespec { sec: 0, nsec: 0 },
+            ctime: Timespec { sec: 0, nsec: 0 },
+            type_: FileType::CharDevice,
+            mode: 0o666,
+            nlinks: 1,
+            uid: 0,
+            gid: 0,
+            rdev: make_rdev(5, 0),
+        })
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Get the terminal behind `inode`, if it is one.
+pub fn as_tty(inode: &Arc<dyn INode>) -> Option<&Tty> {
+    mount::fs_inode(inode).as_any_ref().downcast_ref::<Tty>()
+}
+
+/// Whether `inode` is `/dev/tty`.
+pub fn is_dev_tty(inode: &Arc<dyn INode>) -> bool {
+    mount::fs_inode(inode).as_any_ref().is::<DevTty>()
+}
+
+/// Make the terminal behind `inode` the controlling terminal of `proc`.
+///
+/// Only a session leader without a controlling terminal can acquire one. A
+/// terminal controlling another session is taken over only with `force`.
+pub fn acquire_controlling_tty(
+    proc: &LinuxProcess,
+    inode: &Arc<dyn INode>,
+    force: bool,
+) -> LxResult {
+    let tty = as_tty(inode).ok_or(LxError::ENOTTY)?;
+    if proc.sid() != proc.pid() || proc.controlling_tty().is_some() {
+        return Err(LxError::EPERM);
+    }
+    if tty.session() != 0 && !force {
+        return Err(LxError::EPERM);
+    }
+    tty.set_controlling(proc.sid(), proc.pgid());
+    proc.set_controlling_tty(Some(mount::fs_inode(inode).clone()));
+    Ok(())
+}
+
+/// Whether `tty` is the controlling terminal of `proc`.
+fn is_controlling(proc: &LinuxProcess, tty: &Tty) -> bool {
+    proc.controlling_tty().map_or(false, |ctty| {
+        as_tty(&ctty).map_or(false, |t| core::ptr::eq(t, tty))
+    })
+}
+
+/// Check that `proc` is in the foreground of its controlling terminal `tty`.
+///
+/// A process in a background group is stopped with `signal`, as are the
+/// other processes of the group, and the call fails with `EINTR`. If the
+/// process ignores the signal, `SIGTTOU` lets the call go on and `SIGTTIN`
+/// fails it with `EIO`.
+fn check_foreground(proc: &LinuxProcess, tty: &Tty, signal: Signal) -> LxResult {
+    if !is_controlling(proc, tty) {
+        return Ok(());
+    }
+    let foreground = tty.foreground();
+    if foreground == 0 || proc.pgid() == foreground {
+        return Ok(());
+    }
+    if proc.signal_action(signal).handler == SIG_IGN {
+        return match signal {
+            Signal::SIGTTOU => Ok(()),
+            _ => Err(LxError::EIO),
+        };
+    }
+    signal_process_group(proc.pgid(), signal);
+    Err(LxError::EINTR)
+}
+
+/// Check that `proc` may read from, or write to with `write`, the file `inode`.
+///
+/// Only the foreground process group reads from a terminal, and writes too
+/// with `TOSTOP` set. Files other than terminals are always allowed.
+pub fn tty_check_access(proc: &LinuxProcess, inode: &Arc<dyn INode>, write: bool) -> LxResult {
+    let tty = match as_tty(inode) {
+        Some(tty) => tty,
+        None => return Ok(()),
+    };
+    if !write {
+        check_foreground(proc, tty, Signal::SIGTTIN)
+    } else if tty.termios().lflag & TOSTOP != 0 {
+        check_foreground(proc, tty, Signal::SIGTTOU)
+    } else {
+        Ok(())
+    }
+}
+
+/// Handle the ioctls of the terminal `tty` which copy from or to the caller.
+///
+/// Return `None` if `request` is not one of them.
+pub(super) fn termios_ioctl(
+    proc: &LinuxProcess,
+    tty: &Tty,
+    request: usize,
+    arg: usize,
+) -> Option<LxResult<usize>> {
+    let ret = match request {
+        TCGETS => UserOutPtr::<Termios>::from(arg)
+            .write(tty.termios())
+            .map_err(LxError::from),
+        TCSETS | TCSETSW | TCSETSF => UserInPtr::<Termios>::from(arg)
+            .read()
+            .map_err(LxError::from)
+            .and_then(|termios| {
+                check_foreground(proc, tty, Signal::SIGTTOU)?;
+                tty.set_termios(termios, request == TCSETSF);
+                Ok(())
+            }),
+        TIOCGPGRP => UserOutPtr::<i32>::from(arg)
+            .write(tty.foreground() as i32)
+            .map_err(LxError::from),
+        TIOCSPGRP => UserInPtr::<i32>::from(arg)
+            .read()
+            .map_err(LxError::from)
+            .and_then(|pgid| set_foreground(proc, tty, pgid)),
+        TIOCGWINSZ => UserOutPtr::<WinSize>::from(arg)
+            .write(tty.winsize())
+            .map_err(LxError::from),
+        TIOCSWINSZ => UserInPtr::<WinSize>::from(arg)
+            .read()
+            .map(|winsize| tty.set_winsize(winsize))
+            .map_err(LxError::from),
+        FIONREAD => UserOutPtr::<i32>::from(arg)
+            .write(tty.input_len() as i32)
+            .map_err(LxError::from),
+        _ => return None,
+    };
+    Some(ret.map(|_| 0))
+}
+
+/// Make the process group `pgid` the foreground one of `tty`, as `proc`.
+///
+/// The terminal must be the controlling one of the caller, and the group
+/// must be in its session.
+fn set_foreground(proc: &LinuxProcess, tty: &Tty, pgid: i32) -> LxResult {
+    if !is_controlling(proc, tty) || tty.session() != proc.sid() {
+        return Err(LxError::ENOTTY);
+    }
+    if pgid < 0 {
+        return Err(LxError::EINVAL);
+    }
+    let pgid = pgid as KoID;
+    let in_session = process_group(pgid)
+        .iter()
+        .any(|p| p.linux().sid() == proc.sid());
+    if !in_session {
+        return Err(LxError::EPERM);
+    }
+    check_foreground(proc, tty, Signal::SIGTTOU)?;
+    tty.inner.lock().foreground = pgid;
+    Ok(())
+}
+
+/// Handle the ioctls of a terminal that depend on the calling process.
+///
+/// Return `None` if `request` is not one of them.
+pub fn tty_ioctl(
+    proc: &LinuxProcess,
+    inode: &Arc<dyn INode>,
+    request: usize,
+    arg: usize,
+) -> Option<LxResult<usize>> {
+    if let Some(ret) = pty_master_ioctl(proc, inode, request, arg) {
+        return Some(ret);
+    }
+    let tty = as_tty(inode)?;
+    let is_controlling = is_controlling(proc, tty);
+    let ret = match request {
+        TIOCSCTTY => {
+            if is_controlling {
+                return Some(Ok(0));
+            }
+            acquire_controlling_tty(proc, inode, arg == 1).map(|_| 0)
+        }
+        TIOCNOTTY => {
+            if !is_controlling {
+                return Some(Err(LxError::ENOTTY));
+            }
+            // the session loses the terminal when its leader gives it up
+            if proc.sid() == proc.pid() {
+                let foreground = tty.foreground();
+                tty.release_controlling();
+                if foreground != 0 {
+                    signal_process_group(foreground, Signal::SIGHUP);
+                }
+            }
+            proc.set_controlling_tty(None);
+            Ok(0)
+        }
+        TIOCGSID => match tty.session() {
+            0 => Err(LxError::ENOTTY),
+            sid => UserOutPtr::<i32>::from(arg)
+                .write(sid as i32)
+                .map(|_| 0)
+                .map_err(LxError::from),
+        },
+        _ => return termios_ioctl(proc, tty, request, arg),
+    };
+    Some(ret)
+}
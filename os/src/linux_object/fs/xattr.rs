@@ -0,0 +1,303 @@
+//! Extended attributes
+//!
+//! Name and value pairs attached to INodes, in the `user`, `security` and
+//! `trusted` namespaces. rcore-fs has no room for them on the INodes, so every
+//! mount of a RamFS or SFS holds an [`XattrTable`], which is shared by its
+//! bind mounts. The attributes on an SFS are stored in a file for each INode,
+//! under the directory [`XATTR_DIR`] at its root, so they persist across
+//! mounts; those on a RamFS are only kept in memory and dropped with the
+//! filesystem. The attributes of an INode are dropped with its last link.
+//!
+//! The mounted tree hides [`XATTR_DIR`], so that it can not be reached,
+//! listed, created or removed by users.
+#![deny(missing_docs)]
+
+use super::super::error::{LxError, LxResult};
+use super::mount::xattr_table;
+use super::INodeExt;
+use alloc::{collections::BTreeMap, string::String, string::ToString, sync::Arc, vec::Vec};
+use bitflags::bitflags;
+use rcore_fs::vfs::{FileType, FsError, INode};
+use spin::Mutex;
+
+/// Max length of the name of an attribute
+pub const XATTR_NAME_MAX: usize = 255;
+/// Max size of the value of an attribute
+pub const XATTR_SIZE_MAX: usize = 65536;
+/// Max size of the list of the names of an INode
+pub const XATTR_LIST_MAX: usize = 65536;
+/// Directory at the root of a filesystem holding the attributes stored on it
+pub const XATTR_DIR: &str = ".xattr";
+
+/// Attributes of an INode by name
+type Attrs = BTreeMap<String, Vec<u8>>;
+
+bitflags! {
+    /// flags of `setxattr`
+    pub struct XattrFlags: usize {
+        /// fail if the attribute exists
+        const CREATE = 1;
+        /// fail if the attribute does not exist
+        const REPLACE = 2;
+    }
+}
+
+/// Namespace of an attribute, the prefix of its name
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum XattrNamespace {
+    /// `user.*`, for any data, checked against the mode of the INode
+    User,
+    /// `security.*`, for security labels and file capabilities, only set by
+    /// privileged processes
+    Security,
+    /// `trusted.*`, for data of privileged programs, only seen by them
+    Trusted,
+}
+
+impl XattrNamespace {
+    /// Get the namespace of the attribute `name`.
+    ///
+    /// Fails with `ERANGE` if the name is empty or too long, and with
+    /// `EOPNOTSUPP` if its namespace is not supported.
+    pub fn of(name: &str) -> LxResult<Self> {
+        if name.is_empty() || name.len() > XATTR_NAME_MAX {
+            return Err(LxError::ERANGE);
+        }
+        let (namespace, prefix) = if name.starts_with("user.") {
+            (XattrNamespace::User, "user.")
+        } else if name.starts_with("security.") {
+            (XattrNamespace::Security, "security.")
+        } else if name.starts_with("trusted.") {
+            (XattrNamespace::Trusted, "trusted.")
+        } else {
+            return Err(LxError::EOPNOTSUPP);
+        };
+        // the prefix alone does not name an attribute
+        if name.len() == prefix.len() {
+            return Err(LxError::EINVAL);
+        }
+        Ok(namespace)
+    }
+}
+
+/// The extended attributes of the INodes of a filesystem, by inode number
+#[derive(Default)]
+pub struct XattrTable {
+    /// attributes in memory, a cache of those on the filesystem if `store`
+    inodes: Mutex<BTreeMap<usize, Attrs>>,
+    /// root of the filesystem the attributes are stored on
+    store: Option<Arc<dyn INode>>,
+}
+
+impl XattrTable {
+    /// A table storing the attributes on the filesystem whose root is `root`.
+    pub fn on_disk(root: Arc<dyn INode>) -> Self {
+        XattrTable {
+            inodes: Mutex::new(BTreeMap::new()),
+            store: Some(root),
+        }
+    }
+
+    /// Whether `dir` holds the attributes in [`XATTR_DIR`], as the root of
+    /// the filesystem they are stored on.
+    pub fn is_store_root(&self, dir: &Arc<dyn INode>) -> bool {
+        match &self.store {
+            Some(root) => match (root.metadata(), dir.metadata()) {
+                (Ok(root), Ok(dir)) => root.inode == dir.inode,
+                _ => false,
+            },
+            None => false,
+        }
+    }
+
+    /// Get the value of the attribute `name` of the INode `ino`.
+    pub fn get(&self, ino: usize, name: &str) -> LxResult<Vec<u8>> {
+        self.with_attrs(ino, |attrs| {
+            attrs.get(name).cloned().ok_or(LxError::ENODATA)
+        })
+    }
+
+    /// Set the attribute `name` of the INode `ino` to `value`.
+    ///
+    /// With `CREATE` it must not exist, with `REPLACE` it must exist.
+    pub fn set(&self, ino: usize, name: &str, value: &[u8], flags: XattrFlags) -> LxResult {
+        if value.len() > XATTR_SIZE_MAX {
+            return Err(LxError::E2BIG);
+        }
+        self.with_attrs(ino, |attrs| {
+            let exists = attrs.contains_key(name);
+            if exists && flags.contains(XattrFlags::CREATE) {
+                return Err(LxError::EEXIST);
+            }
+            if !exists {
+                if flags.contains(XattrFlags::REPLACE) {
+                    return Err(LxError::ENODATA);
+                }
+                // the names must still fit in a list
+                if list_len(attrs) + name.len() + 1 > XATTR_LIST_MAX {
+                    return Err(LxError::ENOSPC);
+                }
+            }
+            let old = attrs.insert(String::from(name), value.to_vec());
+            if let Err(err) = self.save(ino, attrs) {
+                match old {
+                    Some(old) => attrs.insert(String::from(name), old),
+                    None => attrs.remove(name),
+                };
+                return Err(err);
+            }
+            Ok(())
+        })
+    }
+
+    /// Get the names of the attributes of the INode `ino`, each followed by
+    /// a null byte. Those in the `trusted` namespace are only listed with
+    /// `trusted`.
+    pub fn list(&self, ino: usize, trusted: bool) -> LxResult<Vec<u8>> {
+        self.with_attrs(ino, |attrs| {
+            let mut list = Vec::new();
+            for name in attrs.keys() {
+                if !trusted && name.starts_with("trusted.") {
+                    continue;
+                }
+                list.extend_from_slice(name.as_bytes());
+                list.push(0);
+            }
+            Ok(list)
+        })
+    }
+
+    /// Remove the attribute `name` of the INode `ino`.
+    pub fn remove(&self, ino: usize, name: &str) -> LxResult {
+        self.with_attrs(ino, |attrs| {
+            let value = attrs.remove(name).ok_or(LxError::ENODATA)?;
+            if let Err(err) = self.save(ino, attrs) {
+                attrs.insert(String::from(name), value);
+                return Err(err);
+            }
+            Ok(())
+        })
+    }
+
+    /// Remove all attributes of the INode `ino`.
+    pub fn clear(&self, ino: usize) {
+        let mut inodes = self.inodes.lock();
+        inodes.remove(&ino);
+        if let Err(err) = self.save(ino, &Attrs::new()) {
+            warn!(
+                "xattr: failed to remove the attributes of {}: {:?}",
+                ino, err
+            );
+        }
+    }
+
+    /// Do `op` on the attributes of the INode `ino`, loading them first.
+    fn with_attrs<T>(&self, ino: usize, op: impl FnOnce(&mut Attrs) -> LxResult<T>) -> LxResult<T> {
+        let mut inodes = self.inodes.lock();
+        if !inodes.contains_key(&ino) {
+            let attrs = self.load(ino)?;
+            inodes.insert(ino, attrs);
+        }
+        let attrs = inodes.get_mut(&ino).unwrap();
+        let result = op(attrs);
+        if attrs.is_empty() {
+            inodes.remove(&ino);
+        }
+        result
+    }
+
+    /// Read the attributes of the INode `ino` from the filesystem.
+    fn load(&self, ino: usize) -> LxResult<Attrs> {
+        let root = match &self.store {
+            Some(root) => root,
+            None => return Ok(Attrs::new()),
+        };
+        let file = match root
+            .find(XATTR_DIR)
+            .and_then(|dir| dir.find(&ino.to_string()))
+        {
+            Ok(file) => file,
+            Err(FsError::EntryNotFound) => return Ok(Attrs::new()),
+            Err(err) => return Err(err.into()),
+        };
+        decode(&file.read_as_vec()?)
+    }
+
+    /// Write the attributes of the INode `ino` to the filesystem, removing
+    /// its file if there are none.
+    fn save(&self, ino: usize, attrs: &Attrs) -> LxResult {
+        let root = match &self.store {
+            Some(root) => root,
+            None => return Ok(()),
+        };
+        let name = ino.to_string();
+        let dir = match root.find(XATTR_DIR) {
+            Ok(dir) => dir,
+            Err(FsError::EntryNotFound) if attrs.is_empty() => return Ok(()),
+            Err(FsError::EntryNotFound) => root.create(XATTR_DIR, FileType::Dir, 0o700)?,
+            Err(err) => return Err(err.into()),
+        };
+        if attrs.is_empty() {
+            return match dir.unlink(&name) {
+                Ok(()) | Err(FsError::EntryNotFound) => Ok(()),
+                Err(err) => Err(err.into()),
+            };
+        }
+        let file = match dir.find(&name) {
+            Ok(file) => file,
+            Err(FsError::EntryNotFound) => dir.create(&name, FileType::File, 0o600)?,
+            Err(err) => return Err(err.into()),
+        };
+        let data = encode(attrs);
+        file.resize(data.len())?;
+        file.write_at(0, &data)?;
+        Ok(())
+    }
+}
+
+/// Serialize `attrs` for the filesystem: for each attribute, the length of
+/// the name in a byte, the length of the value in 4 bytes, the name and the
+/// value.
+pub fn encode(attrs: &BTreeMap<String, Vec<u8>>) -> Vec<u8> {
+    let mut data = Vec::new();
+    for (name, value) in attrs {
+        data.push(name.len() as u8);
+        data.extend_from_slice(&(value.len() as u32).to_le_bytes());
+        data.extend_from_slice(name.as_bytes());
+        data.extend_from_slice(value);
+    }
+    data
+}
+
+/// Parse the attributes serialized by [`encode`], `EIO` if `data` is corrupt.
+pub fn decode(mut data: &[u8]) -> LxResult<BTreeMap<String, Vec<u8>>> {
+    let mut attrs = Attrs::new();
+    while !data.is_empty() {
+        if data.len() < 5 {
+            return Err(LxError::EIO);
+        }
+        let name_len = data[0] as usize;
+        let value_len = u32::from_le_bytes([data[1], data[2], data[3], data[4]]) as usize;
+        let data_len = 5 + name_len + value_len;
+        if name_len == 0 || value_len > XATTR_SIZE_MAX || data.len() < data_len {
+            return Err(LxError::EIO);
+        }
+        let name = core::str::from_utf8(&data[5..5 + name_len]).map_err(|_| LxError::EIO)?;
+        attrs.insert(String::from(name), data[5 + name_len..data_len].to_vec());
+        data = &data[data_len..];
+    }
+    Ok(attrs)
+}
+
+/// Length of the list of the names in `attrs`
+fn list_len(attrs: &Attrs) -> usize {
+    attrs.keys().map(|name| name.len() + 1).sum()
+}
+
+/// Drop the attributes of `inode` when its last link is removed, so that a
+/// new INode with its number does not get them.
+pub fn release(inode: &Arc<dyn INode>) {
+    if let Ok((table, ino)) = xattr_table(inode, true) {
+        table.clear(ino);
+    }
+}
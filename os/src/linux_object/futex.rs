@@ -0,0 +1,132 @@
+//! Linux futexes
+//!
+//! A private futex is keyed by the virtual address of the futex word in the
+//! process. A shared futex in a shared mapping is keyed by the VMO mapped and
+//! the offset of the word in it, so processes mapping the same page at
+//! different addresses wait on the same futex. The futex keeps the VMO and
+//! the page alive, and its key goes with it. Like on Linux, a shared futex in
+//! a private mapping is the same as a private one.
+
+use super::error::*;
+use super::process::ProcessExt;
+use crate::kernel_hal::user::UserInPtr;
+use crate::kernel_hal::VirtAddr;
+use crate::zircon_object::{
+    object::{KernelObject, KoID},
+    signal::Futex,
+    task::Process,
+};
+use alloc::collections::BTreeMap;
+use alloc::sync::{Arc, Weak};
+use core::sync::atomic::{AtomicI32, Ordering};
+use lazy_static::lazy_static;
+use spin::Mutex;
+
+/// The futex word has waiters
+pub const FUTEX_WAITERS: u32 = 0x8000_0000;
+/// The owner of the futex word has died
+pub const FUTEX_OWNER_DIED: u32 = 0x4000_0000;
+/// The TID of the owner in the futex word of a PI or robust futex
+pub const FUTEX_TID_MASK: u32 = 0x3fff_ffff;
+
+/// Max number of entries walked in a robust list
+const ROBUST_LIST_LIMIT: usize = 2048;
+
+lazy_static! {
+    /// Shared futexes by the ID of their VMO and their offset in it
+    static ref SHARED_FUTEXES: Mutex<BTreeMap<(KoID, usize), Weak<Futex>>> =
+        Mutex::new(BTreeMap::new());
+}
+
+/// Get the futex at `uaddr` in the process `proc`.
+///
+/// A `shared` futex in a shared mapping is the same for all processes mapping
+/// the page.
+pub fn get_futex(proc: &Process, uaddr: VirtAddr, shared: bool) -> LxResult<Arc<Futex>> {
+    futex_word(uaddr)?;
+    let vmar = proc.vmar();
+    let mapping = vmar.find_mapping(uaddr).ok_or(LxError::EFAULT)?;
+    if !shared || !mapping.is_shared() {
+        return Ok(proc.linux().get_futex(uaddr));
+    }
+    let (vmo, offset, _) = vmar.vmo_at(uaddr).ok_or(LxError::EFAULT)?;
+    let key = (vmo.id(), offset);
+    let mut futexes = SHARED_FUTEXES.lock();
+    if let Some(futex) = futexes.get(&key).and_then(Weak::upgrade) {
+        return Ok(futex);
+    }
+    futexes.retain(|_, futex| futex.strong_count() > 0);
+    let futex = Futex::new_in_vmo(vmo, offset).map_err(|_| LxError::EFAULT)?;
+    futexes.insert(key, Arc::downgrade(&futex));
+    Ok(futex)
+}
+
+/// Get the futex word at `uaddr` in the current process.
+pub fn futex_word(uaddr: VirtAddr) -> LxResult<&'static AtomicI32> {
+    let ptr = UserInPtr::<AtomicI32>::from(uaddr);
+    ptr.check().map_err(|_| LxError::EINVAL)?;
+    Ok(ptr.as_ref()?)
+}
+
+/// Linux struct robust_list_head
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct RobustListHead {
+    /// The first entry, or the head itself if the list is empty
+    pub list: usize,
+    /// Offset of the futex word from an entry
+    pub futex_offset: isize,
+    /// The entry being added or removed
+    pub list_op_pending: usize,
+}
+
+/// Release the robust futexes held by the exiting thread `tid` of `proc`.
+///
+/// Each futex word in the list still owned by the thread is marked with
+/// `FUTEX_OWNER_DIED`, and a waiter on it is woken up.
+pub fn exit_robust_list(proc: &Process, tid: u32, head: UserInPtr<RobustListHead>) -> LxResult {
+    // the low bit of an entry marks a PI futex
+    let head_addr = head.as_ptr() as usize;
+    let head = head.read()?;
+    let pending = head.list_op_pending & !1;
+    let mut entry = head.list & !1;
+    for _ in 0..ROBUST_LIST_LIMIT {
+        if entry == head_addr {
+            break;
+        }
+        let next = UserInPtr::<usize>::from(entry).read()? & !1;
+        if entry != pending {
+            handle_futex_death(proc, entry.wrapping_add(head.futex_offset as usize), tid)?;
+        }
+        entry = next;
+    }
+    if pending != 0 {
+        handle_futex_death(proc, pending.wrapping_add(head.futex_offset as usize), tid)?;
+    }
+    Ok(())
+}
+
+/// Mark the futex word at `uaddr` if it is owned by the dead thread `tid`.
+fn handle_futex_death(proc: &Process, uaddr: VirtAddr, tid: u32) -> LxResult {
+    let word = futex_word(uaddr)?;
+    let mut value = word.load(Ordering::SeqCst) as u32;
+    loop {
+        if value & FUTEX_TID_MASK != tid {
+            return Ok(());
+        }
+        let new_value = (value & FUTEX_WAITERS) | FUTEX_OWNER_DIED;
+        match word.compare_exchange(
+            value as i32,
+            new_value as i32,
+            Ordering::SeqCst,
+            Ordering::SeqCst,
+        ) {
+            Ok(_) => break,
+            Err(current) => value = current as u32,
+        }
+    }
+    if value & FUTEX_WAITERS != 0 {
+        get_futex(proc, uaddr, true)?.wake(1);
+    }
+    Ok(())
+}
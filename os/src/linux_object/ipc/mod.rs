@@ -1,10 +1,98 @@
 //! Linux Inter-Process Communication
+//!
+//! System V semaphore sets, shared memory segments and message queues share
+//! the key and permission model of [`IpcPerm`]. Shared memory segments and
+//! message queues are kept in a global [`IpcTable`] until removed, so their
+//! IDs are valid in every process. Semaphore sets are found by key in a
+//! global table, and a process refers to them by IDs in its own table.
 #![deny(missing_docs)]
+mod msg;
 mod semary;
+mod shm;
 
+pub use self::msg::*;
 pub use self::semary::*;
+pub use self::shm::*;
+use super::error::{LxError, LxResult};
+use crate::kernel_hal::MMUFlags;
+use crate::zircon_object::vm::VirtAddr;
 use alloc::collections::BTreeMap;
 use alloc::sync::Arc;
+use bitflags::*;
+
+bitflags! {
+    struct IpcGetFlags: usize {
+        const CREAT = 1 << 9;
+        const EXCLUSIVE = 1 << 10;
+    }
+}
+
+/// A global table of System V IPC objects of one kind
+///
+/// An object is found by the ID returned from its `*get` call, and by its key
+/// unless it is private. The table holds it until it is removed by IPC_RMID.
+pub struct IpcTable<T> {
+    /// objects by ID
+    objects: BTreeMap<usize, Arc<T>>,
+    /// IDs by key, except for private objects
+    keys: BTreeMap<u32, usize>,
+}
+
+impl<T> Default for IpcTable<T> {
+    fn default() -> Self {
+        IpcTable {
+            objects: BTreeMap::new(),
+            keys: BTreeMap::new(),
+        }
+    }
+}
+
+impl<T> IpcTable<T> {
+    /// Get the object with `key`, for the `flags` of a `*get` call. If not
+    /// exist, create a new one with `create`.
+    ///
+    /// Returns the ID, the object, and whether it is created. `IPC_PRIVATE`
+    /// always creates an object.
+    pub fn get_or_create(
+        &mut self,
+        key: u32,
+        flags: usize,
+        create: impl FnOnce(u32) -> LxResult<Arc<T>>,
+    ) -> LxResult<(usize, Arc<T>, bool)> {
+        let flag = IpcGetFlags::from_bits_truncate(flags);
+        // key 0 is IPC_PRIVATE
+        if key != 0 {
+            if let Some(&id) = self.keys.get(&key) {
+                if flag.contains(IpcGetFlags::CREAT | IpcGetFlags::EXCLUSIVE) {
+                    return Err(LxError::EEXIST);
+                }
+                return Ok((id, self.objects[&id].clone(), false));
+            }
+            if !flag.contains(IpcGetFlags::CREAT) {
+                return Err(LxError::ENOENT);
+            }
+        }
+        let object = create(key)?;
+        let id = (0..).find(|id| !self.objects.contains_key(id)).unwrap();
+        self.objects.insert(id, object.clone());
+        if key != 0 {
+            self.keys.insert(key, id);
+        }
+        Ok((id, object, true))
+    }
+
+    /// Get an object by `id`
+    pub fn get(&self, id: usize) -> Option<Arc<T>> {
+        self.objects.get(&id).cloned()
+    }
+
+    /// Remove an object by `id`, return it if it exists
+    pub fn remove(&mut self, id: usize) -> Option<Arc<T>> {
+        let object = self.objects.remove(&id)?;
+        self.keys.retain(|_, &mut other| other != id);
+        Some(object)
+    }
+}
 
 /// Semaphore table in a process
 #[derive(Default)]
@@ -80,3 +168,65 @@ impl Drop for SemProc {
         }
     }
 }
+
+/// Shared memory segments attached in a process
+#[derive(Default)]
+pub struct ShmProc {
+    /// Attached segments by address
+    attaches: BTreeMap<VirtAddr, ShmAttach>,
+}
+
+/// A shared memory segment attached to the address space
+#[derive(Clone)]
+pub struct ShmAttach {
+    /// The attached segment
+    pub segment: Arc<ShmSegment>,
+    /// Flags of the mapping
+    pub flags: MMUFlags,
+}
+
+impl ShmProc {
+    /// Record the `attach` of a segment at `addr`
+    pub fn attach(&mut self, addr: VirtAddr, attach: ShmAttach) {
+        attach.segment.attach();
+        self.attaches.insert(addr, attach);
+    }
+
+    /// Remove the attach at `addr`
+    pub fn detach(&mut self, addr: VirtAddr) -> Option<ShmAttach> {
+        let attach = self.attaches.remove(&addr)?;
+        attach.segment.detach();
+        Some(attach)
+    }
+
+    /// Remove all attaches, when the address space is gone
+    pub fn detach_all(&mut self) {
+        for (_, attach) in core::mem::take(&mut self.attaches) {
+            attach.segment.detach();
+        }
+    }
+
+    /// Attached segments by address
+    pub fn attaches(&self) -> impl Iterator<Item = (&VirtAddr, &ShmAttach)> {
+        self.attaches.iter()
+    }
+}
+
+/// Fork the shared memory table. Attached segments stay attached.
+impl Clone for ShmProc {
+    fn clone(&self) -> Self {
+        for attach in self.attaches.values() {
+            attach.segment.attach();
+        }
+        ShmProc {
+            attaches: self.attaches.clone(),
+        }
+    }
+}
+
+/// Auto detach segments on drop
+impl Drop for ShmProc {
+    fn drop(&mut self) {
+        self.detach_all();
+    }
+}
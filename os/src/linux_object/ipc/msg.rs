@@ -0,0 +1,297 @@
+//! Linux message queue ipc
+use super::super::cred::Credentials;
+use super::super::error::{LxError, LxResult};
+use super::super::time::*;
+use super::{IpcPerm, IpcTable};
+use alloc::{collections::VecDeque, sync::Arc, vec::Vec};
+use bitflags::*;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+use lazy_static::*;
+use spin::Mutex;
+use spin::RwLock;
+
+/// Max size of a message
+pub const MSGMAX: usize = 8192;
+
+/// Default max number of bytes in a queue
+const MSGMNB: usize = 16384;
+
+bitflags! {
+    /// for the flag argument of msgsnd() and msgrcv()
+    pub struct MsgFlags: usize {
+        /// Return immediately instead of waiting
+        const IPC_NOWAIT = 0o4000;
+        /// Truncate a message longer than the buffer
+        const MSG_NOERROR = 0o10000;
+        /// Receive the first message not of the given type
+        const MSG_EXCEPT = 0o20000;
+    }
+}
+
+/// msqid data structure
+///
+/// struct msqid_ds
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct MsqidDs {
+    /// Ownership and permissions
+    pub perm: IpcPerm,
+    /// Time of last msgsnd(2)
+    pub stime: usize,
+    /// Time of last msgrcv(2)
+    pub rtime: usize,
+    /// Time of last change
+    pub ctime: usize,
+    /// Current number of bytes in queue
+    pub cbytes: usize,
+    /// Current number of messages in queue
+    pub qnum: usize,
+    /// Maximum number of bytes allowed in queue
+    pub qbytes: usize,
+    /// PID of last msgsnd(2)
+    pub lspid: u32,
+    /// PID of last msgrcv(2)
+    pub lrpid: u32,
+    __unused4: usize,
+    __unused5: usize,
+}
+
+/// A System V message queue
+pub struct MsgQueue {
+    inner: Mutex<MsgQueueInner>,
+}
+
+/// Message queue inner data
+struct MsgQueueInner {
+    /// msqid data structure
+    msqid_ds: MsqidDs,
+    /// messages in the order they are sent
+    messages: VecDeque<Message>,
+    /// is removed
+    removed: bool,
+    /// tasks waiting for a change of the queue
+    waiters: Vec<Waker>,
+}
+
+/// A message in the queue
+struct Message {
+    mtype: isize,
+    data: Vec<u8>,
+}
+
+lazy_static! {
+    static ref MSG_TABLE: RwLock<IpcTable<MsgQueue>> = RwLock::new(IpcTable::default());
+}
+
+impl MsgQueue {
+    /// Get the ID of the message queue with `key`.
+    /// If not exist, create a new one.
+    pub fn get_or_create(key: u32, flags: usize, cred: &Credentials) -> LxResult<usize> {
+        let mut table = MSG_TABLE.write();
+        let (id, queue, created) = table.get_or_create(key, flags, |key| {
+            Ok(Arc::new(MsgQueue {
+                inner: Mutex::new(MsgQueueInner {
+                    msqid_ds: MsqidDs {
+                        perm: IpcPerm::new(key, flags, cred),
+                        stime: 0,
+                        rtime: 0,
+                        ctime: TimeSpec::now().sec,
+                        cbytes: 0,
+                        qnum: 0,
+                        qbytes: MSGMNB,
+                        lspid: 0,
+                        lrpid: 0,
+                        __unused4: 0,
+                        __unused5: 0,
+                    },
+                    messages: VecDeque::new(),
+                    removed: false,
+                    waiters: Vec::new(),
+                }),
+            }))
+        })?;
+        if !created {
+            queue.stat().perm.check(cred, IpcPerm::requested(flags))?;
+        }
+        Ok(id)
+    }
+
+    /// Get the message queue with `id`.
+    pub fn get(id: usize) -> Option<Arc<Self>> {
+        MSG_TABLE.read().get(id)
+    }
+
+    /// Remove the queue with `id`, awakening all processes waiting on it.
+    pub fn remove(id: usize) {
+        if let Some(queue) = MSG_TABLE.write().remove(id) {
+            let mut inner = queue.inner.lock();
+            inner.removed = true;
+            inner.wake_all();
+        }
+    }
+
+    /// msqid data structure, for IPC_STAT
+    pub fn stat(&self) -> MsqidDs {
+        self.inner.lock().msqid_ds
+    }
+
+    /// for IPC_SET
+    /// see man msgctl(2)
+    ///
+    /// Only a privileged process can raise the size above the default.
+    pub fn set(&self, new: &MsqidDs, cred: &Credentials) -> LxResult {
+        let mut inner = self.inner.lock();
+        if new.qbytes > MSGMNB && new.qbytes > inner.msqid_ds.qbytes && !cred.is_privileged() {
+            return Err(LxError::EPERM);
+        }
+        inner.msqid_ds.perm.set(&new.perm);
+        inner.msqid_ds.qbytes = new.qbytes;
+        inner.msqid_ds.ctime = TimeSpec::now().sec;
+        // there may be room for more messages
+        inner.wake_all();
+        Ok(())
+    }
+
+    /// Send a message of `mtype` with `data` from the process `pid`.
+    ///
+    /// Waits until there is room for the message in the queue, unless
+    /// `IPC_NOWAIT` is in `flags`.
+    pub async fn send(&self, mtype: isize, data: Vec<u8>, flags: MsgFlags, pid: u32) -> LxResult {
+        let mut message = Some(Message { mtype, data });
+        self.wait(|inner| {
+            let len = message.as_ref().unwrap().data.len();
+            let ds = &mut inner.msqid_ds;
+            if ds.cbytes + len > ds.qbytes || ds.qnum + 1 > ds.qbytes {
+                if flags.contains(MsgFlags::IPC_NOWAIT) {
+                    return Some(Err(LxError::EAGAIN));
+                }
+                return None;
+            }
+            ds.cbytes += len;
+            ds.qnum += 1;
+            ds.lspid = pid;
+            ds.stime = TimeSpec::now().sec;
+            inner.messages.push_back(message.take().unwrap());
+            inner.wake_all();
+            Some(Ok(()))
+        })
+        .await
+    }
+
+    /// Receive a message of at most `size` bytes to the process `pid`.
+    ///
+    /// The message is selected by `mtype`: the first message if it is 0, the
+    /// first message of `mtype` if it is positive, or the first message of
+    /// the lowest type not greater than `-mtype` if it is negative.
+    /// Waits until there is such a message, unless `IPC_NOWAIT` is in `flags`.
+    pub async fn receive(
+        &self,
+        mtype: isize,
+        size: usize,
+        flags: MsgFlags,
+        pid: u32,
+    ) -> LxResult<(isize, Vec<u8>)> {
+        self.wait(|inner| {
+            let index = match inner.find(mtype, flags.contains(MsgFlags::MSG_EXCEPT)) {
+                Some(index) => index,
+                None if flags.contains(MsgFlags::IPC_NOWAIT) => {
+                    return Some(Err(LxError::ENOMSG));
+                }
+                None => return None,
+            };
+            if inner.messages[index].data.len() > size && !flags.contains(MsgFlags::MSG_NOERROR) {
+                return Some(Err(LxError::E2BIG));
+            }
+            let mut message = inner.messages.remove(index).unwrap();
+            let ds = &mut inner.msqid_ds;
+            ds.cbytes -= message.data.len();
+            ds.qnum -= 1;
+            ds.lrpid = pid;
+            ds.rtime = TimeSpec::now().sec;
+            inner.wake_all();
+            message.data.truncate(size);
+            Some(Ok((message.mtype, message.data)))
+        })
+        .await
+    }
+
+    /// Do `op` on the queue, again after each change of the queue until it
+    /// returns `Some` result.
+    async fn wait<T, F>(&self, op: F) -> LxResult<T>
+    where
+        F: FnMut(&mut MsgQueueInner) -> Option<LxResult<T>> + Unpin,
+    {
+        #[must_use = "future does nothing unless polled/`await`-ed"]
+        struct MsgQueueFuture<'a, F> {
+            queue: &'a MsgQueue,
+            op: F,
+        }
+
+        impl<T, F> Future for MsgQueueFuture<'_, F>
+        where
+            F: FnMut(&mut MsgQueueInner) -> Option<LxResult<T>> + Unpin,
+        {
+            type Output = LxResult<T>;
+
+            fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+                let queue = self.queue;
+                let mut inner = queue.inner.lock();
+                if inner.removed {
+                    return Poll::Ready(Err(LxError::EIDRM));
+                }
+                match (self.op)(&mut inner) {
+                    Some(result) => Poll::Ready(result),
+                    None => {
+                        inner.waiters.push(cx.waker().clone());
+                        Poll::Pending
+                    }
+                }
+            }
+        }
+
+        MsgQueueFuture { queue: self, op }.await
+    }
+}
+
+impl MsgQueueInner {
+    /// Find the index of the message selected by `mtype`
+    fn find(&self, mtype: isize, except: bool) -> Option<usize> {
+        let types = self.messages.iter().map(|message| message.mtype);
+        select_message(types, mtype, except)
+    }
+
+    /// Wake up all tasks waiting on the queue
+    fn wake_all(&mut self) {
+        for waker in self.waiters.drain(..) {
+            waker.wake();
+        }
+    }
+}
+
+/// Select a message of a queue with messages of `types` for msgrcv(), return
+/// its index.
+///
+/// It is the first message if `mtype` is 0, the first message of `mtype` (or
+/// of another type if `except`) if it is positive, or the first message of the
+/// lowest type not greater than `-mtype` if it is negative.
+pub fn select_message(
+    types: impl IntoIterator<Item = isize>,
+    mtype: isize,
+    except: bool,
+) -> Option<usize> {
+    let mut types = types.into_iter().enumerate();
+    if mtype == 0 {
+        types.next().map(|(index, _)| index)
+    } else if mtype > 0 {
+        types
+            .find(|&(_, t)| (t == mtype) != except)
+            .map(|(index, _)| index)
+    } else {
+        types
+            .filter(|&(_, t)| t <= -mtype)
+            .min_by_key(|&(_, t)| t)
+            .map(|(index, _)| index)
+    }
+}
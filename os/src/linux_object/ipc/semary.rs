@@ -1,5 +1,6 @@
 //! Linux semaphore ipc
-use super::super::error::LxError;
+use super::super::cred::{Access, Credentials};
+use super::super::error::{LxError, LxResult};
 use super::super::sync::Semaphore;
 use super::super::time::*;
 use alloc::{collections::BTreeMap, sync::Arc, sync::Weak, vec::Vec};
@@ -17,7 +18,7 @@ bitflags! {
     }
 }
 
-/// structure specifies the access permissions on the semaphore set
+/// structure specifies the access permissions on an IPC object
 ///
 /// struct ipc_perm
 #[repr(C)]
@@ -43,6 +44,64 @@ pub struct IpcPerm {
     pub __pad2: usize,
 }
 
+impl IpcPerm {
+    /// Create the permissions of an object with `key` created by `cred`.
+    ///
+    /// The mode is the least significant 9 bits of `flags`.
+    pub fn new(key: u32, flags: usize, cred: &Credentials) -> Self {
+        IpcPerm {
+            key,
+            uid: cred.user.effective,
+            gid: cred.group.effective,
+            cuid: cred.user.effective,
+            cgid: cred.group.effective,
+            mode: (flags as u32) & 0x1ff,
+            __seq: 0,
+            __pad1: 0,
+            __pad2: 0,
+        }
+    }
+
+    /// The access requested by the mode bits in `flags` of a `*get` call.
+    pub fn requested(flags: usize) -> Access {
+        let flags = flags as u32;
+        Access::from_bits_truncate((flags >> 6) | (flags >> 3) | flags)
+    }
+
+    /// Check `access` to the object by a process with `cred`.
+    ///
+    /// Like file access, but with the effective IDs, and the creator has the
+    /// same permissions as the owner.
+    pub fn check(&self, cred: &Credentials, access: Access) -> LxResult {
+        let uid = cred.user.effective;
+        let in_group = |gid| cred.group.effective == gid || cred.groups.contains(&gid);
+        let granted = if uid == self.uid || uid == self.cuid {
+            self.mode >> 6
+        } else if in_group(self.gid) || in_group(self.cgid) {
+            self.mode >> 3
+        } else {
+            self.mode
+        } & 0o7;
+        if access.bits() & !granted != 0 && !cred.is_privileged() {
+            return Err(LxError::EACCES);
+        }
+        Ok(())
+    }
+
+    /// Whether a process with `cred` may change or remove the object.
+    pub fn is_owner(&self, cred: &Credentials) -> bool {
+        let uid = cred.user.effective;
+        cred.is_privileged() || uid == self.uid || uid == self.cuid
+    }
+
+    /// for IPC_SET, update the owner and the mode
+    pub fn set(&mut self, new: &IpcPerm) {
+        self.uid = new.uid;
+        self.gid = new.gid;
+        self.mode = new.mode & 0x1ff;
+    }
+}
+
 /// semid data structure
 ///
 /// struct semid_ds
@@ -103,10 +162,7 @@ impl SemArray {
     /// for IPC_SET
     /// see man semctl(2)
     pub fn set(&self, new: &SemidDs) {
-        let mut lock = self.semid_ds.lock();
-        lock.perm.uid = new.perm.uid;
-        lock.perm.gid = new.perm.gid;
-        lock.perm.mode = new.perm.mode & 0x1ff;
+        self.semid_ds.lock().perm.set(&new.perm);
     }
 
     /// Get the semaphore array with `key`.
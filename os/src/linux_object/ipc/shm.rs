@@ -0,0 +1,134 @@
+//! Linux shared memory ipc
+use super::super::cred::Credentials;
+use super::super::error::{LxError, LxResult};
+use super::super::time::*;
+use super::{IpcPerm, IpcTable};
+use crate::zircon_object::vm::{pages, VmObject};
+use alloc::sync::Arc;
+use lazy_static::*;
+use spin::Mutex;
+use spin::RwLock;
+
+/// Min size of a segment
+const SHMMIN: usize = 1;
+
+/// Max size of a segment
+const SHMMAX: usize = 64 * 1024 * 1024;
+
+/// Mode bit of a segment to be destroyed after the last detach
+const SHM_DEST: u32 = 0o1000;
+
+/// shmid data structure
+///
+/// struct shmid_ds
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct ShmidDs {
+    /// Ownership and permissions
+    pub perm: IpcPerm,
+    /// Size of segment (bytes)
+    pub segsz: usize,
+    /// Last attach time
+    pub atime: usize,
+    /// Last detach time
+    pub dtime: usize,
+    /// Last change time
+    pub ctime: usize,
+    /// PID of creator
+    pub cpid: u32,
+    /// PID of last shmat(2)/shmdt(2)
+    pub lpid: u32,
+    /// Number of current attaches
+    pub nattch: usize,
+    __unused4: usize,
+    __unused5: usize,
+}
+
+/// A System V shared memory segment
+pub struct ShmSegment {
+    /// shmid data structure
+    pub shmid_ds: Mutex<ShmidDs>,
+    /// memory of the segment, mapped by all attaches
+    vmo: Arc<VmObject>,
+}
+
+lazy_static! {
+    static ref SHM_TABLE: RwLock<IpcTable<ShmSegment>> = RwLock::new(IpcTable::default());
+}
+
+impl ShmSegment {
+    /// Get the ID of the segment with `key`.
+    /// If not exist, create a new one of `size` bytes by the process `pid`.
+    pub fn get_or_create(
+        key: u32,
+        size: usize,
+        flags: usize,
+        cred: &Credentials,
+        pid: u32,
+    ) -> LxResult<usize> {
+        let mut table = SHM_TABLE.write();
+        let (id, segment, created) = table.get_or_create(key, flags, |key| {
+            if size < SHMMIN || size > SHMMAX {
+                return Err(LxError::EINVAL);
+            }
+            Ok(Arc::new(ShmSegment {
+                shmid_ds: Mutex::new(ShmidDs {
+                    perm: IpcPerm::new(key, flags, cred),
+                    segsz: size,
+                    atime: 0,
+                    dtime: 0,
+                    ctime: TimeSpec::now().sec,
+                    cpid: pid,
+                    lpid: 0,
+                    nattch: 0,
+                    __unused4: 0,
+                    __unused5: 0,
+                }),
+                vmo: VmObject::new_paged(pages(size)),
+            }))
+        })?;
+        if !created {
+            let shmid_ds = segment.shmid_ds.lock();
+            shmid_ds.perm.check(cred, IpcPerm::requested(flags))?;
+            if size > shmid_ds.segsz {
+                return Err(LxError::EINVAL);
+            }
+        }
+        Ok(id)
+    }
+
+    /// Get the segment with `id`.
+    pub fn get(id: usize) -> Option<Arc<Self>> {
+        SHM_TABLE.read().get(id)
+    }
+
+    /// Remove the segment with `id`, it is destroyed after the last detach.
+    pub fn remove(id: usize) {
+        if let Some(segment) = SHM_TABLE.write().remove(id) {
+            segment.shmid_ds.lock().perm.mode |= SHM_DEST;
+        }
+    }
+
+    /// for IPC_SET
+    /// see man shmctl(2)
+    pub fn set(&self, new: &ShmidDs) {
+        let mut shmid_ds = self.shmid_ds.lock();
+        shmid_ds.perm.set(&new.perm);
+        shmid_ds.ctime = TimeSpec::now().sec;
+    }
+
+    /// memory of the segment
+    pub fn vmo(&self) -> Arc<VmObject> {
+        self.vmo.clone()
+    }
+
+    /// count an attach
+    pub fn attach(&self) {
+        self.shmid_ds.lock().nattch += 1;
+    }
+
+    /// count a detach
+    pub fn detach(&self) {
+        self.shmid_ds.lock().nattch -= 1;
+    }
+}
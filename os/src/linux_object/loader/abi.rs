@@ -7,6 +7,7 @@ use alloc::vec::Vec;
 use core::mem::{align_of, size_of};
 use core::ops::Deref;
 use core::ptr::null;
+use crate::kernel_hal::fill_random;
 
 /// process init information
 pub struct ProcInitInfo {
@@ -14,7 +15,9 @@ pub struct ProcInitInfo {
     pub args: Vec<String>,
     /// environment strings
     pub envs: Vec<String>,
-    /// auxiliary
+    /// path of the program, pointed to by `AT_EXECFN`
+    pub execfn: String,
+    /// auxiliary, `AT_EXECFN` and `AT_RANDOM` are added when pushed
     pub auxv: BTreeMap<u8, usize>,
 }
 
@@ -23,8 +26,14 @@ impl ProcInitInfo {
     pub fn push_at(&self, stack_top: usize) -> Stack {
         let mut writer = Stack::new(stack_top);
         // from stack_top:
-        // program name
-        writer.push_str(&self.args[0]);
+        // program path
+        writer.push_str(&self.execfn);
+        let execfn = writer.sp;
+        // random bytes for AT_RANDOM
+        let mut random = [0u8; 16];
+        fill_random(&mut random);
+        writer.push_slice(&random);
+        let random = writer.sp;
         // environment strings
         let envs: Vec<_> = self
             .envs
@@ -43,9 +52,18 @@ impl ProcInitInfo {
                 writer.sp
             })
             .collect();
+        // keep sp 16-byte aligned after argc
+        let mut auxv = self.auxv.clone();
+        auxv.insert(AT_EXECFN, execfn);
+        auxv.insert(AT_RANDOM, random);
+        let words = 2 + auxv.len() * 2 + envs.len() + 1 + argv.len() + 1 + 1;
+        writer.sp -= writer.sp % 16;
+        if words % 2 == 1 {
+            writer.push_slice(&[0usize]);
+        }
         // auxiliary vector entries
         writer.push_slice(&[null::<u8>(), null::<u8>()]);
-        for (&type_, &value) in self.auxv.iter() {
+        for (&type_, &value) in auxv.iter() {
             writer.push_slice(&[type_ as usize, value]);
         }
         // envionment pointers
@@ -115,4 +133,15 @@ pub const AT_PHENT: u8 = 4;
 pub const AT_PHNUM: u8 = 5;
 pub const AT_PAGESZ: u8 = 6;
 pub const AT_BASE: u8 = 7;
+pub const AT_FLAGS: u8 = 8;
 pub const AT_ENTRY: u8 = 9;
+pub const AT_UID: u8 = 11;
+pub const AT_EUID: u8 = 12;
+pub const AT_GID: u8 = 13;
+pub const AT_EGID: u8 = 14;
+pub const AT_HWCAP: u8 = 16;
+pub const AT_CLKTCK: u8 = 17;
+pub const AT_SECURE: u8 = 23;
+pub const AT_RANDOM: u8 = 25;
+pub const AT_EXECFN: u8 = 31;
+pub const AT_SYSINFO_EHDR: u8 = 33;
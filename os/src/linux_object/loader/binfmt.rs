@@ -0,0 +1,152 @@
+//! Executable format handlers
+//!
+//! `execve` tries the registered handlers in order, the first one which
+//! recognizes the file prepares it. ELF and `#!` scripts are built in.
+
+use super::{LinuxElfLoader, Program};
+use crate::linux_object::error::{LxError, LxResult};
+use crate::zircon_object::vm::VmAddressRegion;
+use alloc::{string::String, sync::Arc, vec::Vec};
+use lazy_static::lazy_static;
+use spin::RwLock;
+
+/// A handler of an executable format
+pub trait BinFmt: Send + Sync {
+    /// name of the format
+    fn name(&self) -> &str;
+
+    /// whether `data`, the content of the file, is in this format
+    fn probe(&self, data: &[u8]) -> bool;
+
+    /// check the program and read the ELF image it runs as
+    ///
+    /// This is called before the address space of the caller is cleared, so
+    /// it must not modify `vmar`. An interpreter found by the handler is
+    /// prepared with [`LinuxElfLoader::prepare_binary`] at `depth + 1`.
+    #[allow(clippy::too_many_arguments)]
+    fn prepare(
+        &self,
+        loader: &LinuxElfLoader,
+        vmar: &Arc<VmAddressRegion>,
+        data: Vec<u8>,
+        args: Vec<String>,
+        envs: Vec<String>,
+        path: String,
+        depth: usize,
+    ) -> LxResult<Program>;
+}
+
+lazy_static! {
+    static ref BINFMTS: RwLock<Vec<Arc<dyn BinFmt>>> =
+        RwLock::new(vec![Arc::new(ElfFormat), Arc::new(ScriptFormat)]);
+}
+
+/// Add a format handler, tried after the ones registered before.
+pub fn register_binfmt(binfmt: Arc<dyn BinFmt>) {
+    BINFMTS.write().push(binfmt);
+}
+
+/// Find the handler of the format of `data`.
+pub(super) fn find(data: &[u8]) -> Option<Arc<dyn BinFmt>> {
+    BINFMTS.read().iter().find(|fmt| fmt.probe(data)).cloned()
+}
+
+/// ELF executables and shared objects
+struct ElfFormat;
+
+impl BinFmt for ElfFormat {
+    fn name(&self) -> &str {
+        "elf"
+    }
+
+    fn probe(&self, data: &[u8]) -> bool {
+        data.starts_with(b"\x7fELF")
+    }
+
+    fn prepare(
+        &self,
+        loader: &LinuxElfLoader,
+        vmar: &Arc<VmAddressRegion>,
+        data: Vec<u8>,
+        args: Vec<String>,
+        envs: Vec<String>,
+        path: String,
+        _depth: usize,
+    ) -> LxResult<Program> {
+        loader.prepare_elf(vmar, data, args, envs, path)
+    }
+}
+
+/// Max length of the `#!` line, including the newline
+const SCRIPT_LINE_MAX: usize = 256;
+
+/// Parse the `#!interpreter [arg]` line of a script.
+///
+/// The rest of the line after the interpreter, spaces included, is the
+/// single optional argument.
+pub fn parse_script(data: &[u8]) -> LxResult<(&str, &str)> {
+    if !data.starts_with(b"#!") {
+        return Err(LxError::ENOEXEC);
+    }
+    let line = &data[2..data.len().min(SCRIPT_LINE_MAX)];
+    let line = match line.iter().position(|&b| b == b'\n') {
+        Some(end) => &line[..end],
+        // the interpreter might have been cut
+        None if data.len() > SCRIPT_LINE_MAX => return Err(LxError::ENOEXEC),
+        None => line,
+    };
+    let line = core::str::from_utf8(line).map_err(|_| LxError::ENOEXEC)?;
+    let line = line.trim_matches(|c| c == ' ' || c == '\t' || c == '\r');
+    let (interp, arg) = match line.find(|c| c == ' ' || c == '\t') {
+        Some(i) => (
+            &line[..i],
+            line[i..].trim_start_matches(|c| c == ' ' || c == '\t'),
+        ),
+        None => (line, ""),
+    };
+    if interp.is_empty() {
+        return Err(LxError::ENOEXEC);
+    }
+    Ok((interp, arg))
+}
+
+/// Scripts starting with `#!interpreter [arg]`
+///
+/// The interpreter is run with the arguments
+/// `interpreter [arg] path args[1..]`, like Linux.
+struct ScriptFormat;
+
+impl BinFmt for ScriptFormat {
+    fn name(&self) -> &str {
+        "script"
+    }
+
+    fn probe(&self, data: &[u8]) -> bool {
+        data.starts_with(b"#!")
+    }
+
+    fn prepare(
+        &self,
+        loader: &LinuxElfLoader,
+        vmar: &Arc<VmAddressRegion>,
+        data: Vec<u8>,
+        args: Vec<String>,
+        envs: Vec<String>,
+        path: String,
+        depth: usize,
+    ) -> LxResult<Program> {
+        let (interp, arg) = parse_script(&data)?;
+        info!("script: {:?}, interp: {:?} {:?}", path, interp, arg);
+
+        let mut new_args: Vec<String> = Vec::with_capacity(args.len() + 2);
+        new_args.push(interp.into());
+        if !arg.is_empty() {
+            new_args.push(arg.into());
+        }
+        new_args.push(path.clone());
+        new_args.extend(args.into_iter().skip(1));
+
+        let data = loader.read_interpreter(interp)?;
+        loader.prepare_binary(vmar, data, new_args, envs, path, depth + 1)
+    }
+}
@@ -1,16 +1,26 @@
-//! Linux ELF Program Loader
+//! Linux Program Loader
 #![deny(missing_docs)]
 
 use {
-    super::error::LxResult,
-    super::fs::INodeExt,
+    super::cred::{Access, Credentials},
+    super::error::{LxError, LxResult},
+    super::fs::{mount::check_executable, walk, INodeExt},
     alloc::{collections::BTreeMap, string::String, sync::Arc, vec::Vec},
-    rcore_fs::vfs::INode,
-    xmas_elf::ElfFile,
-    crate::zircon_object::{util::elf_loader::*, vm::*, ZxError},
+    rcore_fs::vfs::{FileType, INode},
+    xmas_elf::{header, program, ElfFile},
+    crate::kernel_hal::hwcap,
+    crate::zircon_object::{object::KernelObject, util::elf_loader::*, vm::*},
 };
 
 mod abi;
+mod binfmt;
+mod reloc;
+mod vdso;
+
+pub use self::binfmt::{parse_script, register_binfmt, BinFmt};
+
+/// Max number of interpreters nested in a script
+const MAX_BINFMT_DEPTH: usize = 4;
 
 /// Linux ELF Program Loader.
 pub struct LinuxElfLoader {
@@ -20,43 +30,144 @@ pub struct LinuxElfLoader {
     pub stack_pages: usize,
     /// root inode of LinuxElfLoader
     pub root_inode: Arc<dyn INode>,
+    /// credentials the program runs with
+    pub cred: Credentials,
+    /// credentials of the caller of `execve`, interpreters are opened with them
+    pub caller_cred: Credentials,
+}
+
+/// A program checked by [`LinuxElfLoader::prepare`], ready to be mapped by
+/// [`LinuxElfLoader::load`].
+///
+/// Everything which can make `execve` fail on the program itself is done
+/// while preparing it, so the caller can still return the error.
+pub struct Program {
+    /// content of the ELF image
+    data: Vec<u8>,
+    /// path and content of the interpreter of the image
+    interp: Option<(String, Vec<u8>)>,
+    /// arguments of the program
+    args: Vec<String>,
+    /// environment of the program
+    envs: Vec<String>,
+    /// path the program was executed as
+    path: String,
 }
 
 impl LinuxElfLoader {
-    /// load a Linux ElfFile and return a tuple of (entry,sp)
-    pub fn load(
+    /// check a program in any registered format and read its interpreters
+    pub fn prepare(
         &self,
         vmar: &Arc<VmAddressRegion>,
-        data: &[u8],
-        mut args: Vec<String>,
+        data: Vec<u8>,
+        args: Vec<String>,
         envs: Vec<String>,
         path: String,
-    ) -> LxResult<(VirtAddr, VirtAddr)> {
-        info!("load: vmar: {:?} args: {:?}, envs: {:?}", vmar, args, envs);
-        let elf = ElfFile::new(data).map_err(|_| ZxError::INVALID_ARGS)?;
-        if let Ok(interp) = elf.get_interpreter() {
-            info!("interp: {:?}", interp);
-            let inode = self.root_inode.lookup(interp)?;
-            let data = inode.read_as_vec()?;
-            args[0] = path.clone();
-            args.insert(0, interp.into());
-            return self.load(vmar, &data, args, envs, path);
+    ) -> LxResult<Program> {
+        self.prepare_binary(vmar, data, args, envs, path, 0)
+    }
+
+    /// check a program with the first format handler recognizing it
+    ///
+    /// `depth` is the number of interpreters the program was found through.
+    pub fn prepare_binary(
+        &self,
+        vmar: &Arc<VmAddressRegion>,
+        data: Vec<u8>,
+        args: Vec<String>,
+        envs: Vec<String>,
+        path: String,
+        depth: usize,
+    ) -> LxResult<Program> {
+        if depth > MAX_BINFMT_DEPTH {
+            return Err(LxError::ELOOP);
         }
+        let binfmt = binfmt::find(&data).ok_or(LxError::ENOEXEC)?;
+        debug!("prepare: {:?} as {}", path, binfmt.name());
+        binfmt.prepare(self, vmar, data, args, envs, path, depth)
+    }
 
-        let size = elf.load_segment_size();
-        let image_vmar = vmar.allocate(None, size, VmarFlags::CAN_MAP_RXW, PAGE_SIZE)?;
-        let base = image_vmar.addr();
-        let vmo = image_vmar.load_from_elf(&elf)?;
-        let entry = base + elf.header.pt2.entry_point() as usize;
+    /// check a Linux ElfFile and read its interpreter
+    ///
+    /// `vmar` is the address space the program will be loaded in, it is not
+    /// modified.
+    pub fn prepare_elf(
+        &self,
+        vmar: &Arc<VmAddressRegion>,
+        data: Vec<u8>,
+        args: Vec<String>,
+        envs: Vec<String>,
+        path: String,
+    ) -> LxResult<Program> {
+        let elf = ElfFile::new(&data).map_err(|_| LxError::ENOEXEC)?;
+        check_segments(vmar, &elf, data.len())?;
+        let has_interp = elf
+            .program_iter()
+            .any(|ph| ph.get_type() == Ok(program::Type::Interp));
+        let interp = if has_interp {
+            let interp = elf.get_interpreter().map_err(|_| LxError::ENOEXEC)?;
+            info!("interp: {:?}", interp);
+            let interp_data = self.read_interpreter(interp)?;
+            let interp_elf = ElfFile::new(&interp_data).map_err(|_| LxError::ENOEXEC)?;
+            check_segments(vmar, &interp_elf, interp_data.len())?;
+            Some((String::from(interp), interp_data))
+        } else {
+            if is_dynamic(&elf) {
+                reloc::check(&elf)?;
+            }
+            None
+        };
+        Ok(Program {
+            data,
+            interp,
+            args,
+            envs,
+            path,
+        })
+    }
 
-        // fill syscall entry
-        if let Some(offset) = elf.get_symbol_address("rcore_syscall_entry") {
-            vmo.write(offset as usize, &self.syscall_entry.to_ne_bytes())?;
-        }
+    /// load a prepared program and return a tuple of (entry,sp)
+    ///
+    /// If the program has an interpreter, both are mapped and the entry is
+    /// the one of the interpreter, which finds the program from the auxv.
+    pub fn load(
+        &self,
+        vmar: &Arc<VmAddressRegion>,
+        program: Program,
+    ) -> LxResult<(VirtAddr, VirtAddr)> {
+        let Program {
+            data,
+            interp,
+            args,
+            envs,
+            path,
+        } = program;
+        info!("load: vmar: {:?} args: {:?}, envs: {:?}", vmar, args, envs);
+        let elf = ElfFile::new(&data).map_err(|_| LxError::ENOEXEC)?;
+        let bias = self.map_image(vmar, &elf, &path)?;
+        let entry = bias + elf.header.pt2.entry_point() as usize;
 
-        elf.relocate(base).map_err(|_| ZxError::INVALID_ARGS)?;
+        let (interp_base, start) = match interp {
+            Some((interp, data)) => {
+                let interp_elf = ElfFile::new(&data).map_err(|_| LxError::ENOEXEC)?;
+                // the interpreter relocates itself
+                let interp_base = self.map_image(vmar, &interp_elf, &interp)?;
+                let start = interp_base + interp_elf.header.pt2.entry_point() as usize;
+                (interp_base, start)
+            }
+            None => {
+                // static PIE
+                if is_dynamic(&elf) {
+                    reloc::relocate(&elf, vmar, bias)?;
+                }
+                (0, entry)
+            }
+        };
+
+        let vdso = vdso::map(vmar)?;
 
         let stack_vmo = VmObject::new_paged(self.stack_pages);
+        stack_vmo.set_name("[stack]");
         let flags = MMUFlags::READ | MMUFlags::WRITE | MMUFlags::USER;
         let stack_bottom = vmar.map(None, stack_vmo.clone(), 0, stack_vmo.len(), flags)?;
         let mut sp = stack_bottom + stack_vmo.len();
@@ -64,14 +175,24 @@ impl LinuxElfLoader {
         let info = abi::ProcInitInfo {
             args,
             envs,
+            execfn: path,
             auxv: {
                 let mut map = BTreeMap::new();
-                map.insert(abi::AT_BASE, base);
-                map.insert(abi::AT_PHDR, base + elf.header.pt2.ph_offset() as usize);
+                map.insert(abi::AT_BASE, interp_base);
+                map.insert(abi::AT_PHDR, bias + phdr_addr(&elf));
                 map.insert(abi::AT_ENTRY, entry);
                 map.insert(abi::AT_PHENT, elf.header.pt2.ph_entry_size() as usize);
                 map.insert(abi::AT_PHNUM, elf.header.pt2.ph_count() as usize);
                 map.insert(abi::AT_PAGESZ, PAGE_SIZE);
+                map.insert(abi::AT_FLAGS, 0);
+                map.insert(abi::AT_HWCAP, hwcap());
+                map.insert(abi::AT_CLKTCK, 100);
+                map.insert(abi::AT_UID, self.cred.user.real as usize);
+                map.insert(abi::AT_EUID, self.cred.user.effective as usize);
+                map.insert(abi::AT_GID, self.cred.group.real as usize);
+                map.insert(abi::AT_EGID, self.cred.group.effective as usize);
+                map.insert(abi::AT_SECURE, self.cred.secure_exec() as usize);
+                map.insert(abi::AT_SYSINFO_EHDR, vdso);
                 map
             },
         };
@@ -79,6 +200,120 @@ impl LinuxElfLoader {
         stack_vmo.write(self.stack_pages * PAGE_SIZE - init_stack.len(), &init_stack)?;
         sp -= init_stack.len();
 
-        Ok((entry, sp))
+        Ok((start, sp))
+    }
+
+    /// Read the interpreter at `path`, which must be an executable file.
+    fn read_interpreter(&self, path: &str) -> LxResult<Vec<u8>> {
+        let root = &self.root_inode;
+        let cred = &self.caller_cred;
+        let inode = walk(root, root.clone(), path, true, Some(cred), None)?;
+        let metadata = inode.metadata()?;
+        if metadata.type_ != FileType::File {
+            return Err(LxError::EACCES);
+        }
+        cred.check_access(&metadata, Access::EXEC)?;
+        check_executable(&inode)?;
+        Ok(inode.read_as_vec()?)
+    }
+
+    /// Map the LOAD segments of `elf` and return the load bias.
+    ///
+    /// Position independent images are placed by `vmar`, executables at the
+    /// addresses they are linked at. The segments are named `path` in
+    /// `/proc/[pid]/maps`.
+    fn map_image(&self, vmar: &Arc<VmAddressRegion>, elf: &ElfFile, path: &str) -> LxResult<usize> {
+        let (start, end) = elf.load_segment_range();
+        let image_vmar = if is_dynamic(elf) {
+            vmar.allocate(None, end - start, VmarFlags::CAN_MAP_RXW, PAGE_SIZE)?
+        } else {
+            if start < vmar.addr() {
+                warn!(
+                    "load: executable at {:#x} is out of the address space",
+                    start
+                );
+                return Err(LxError::ENOEXEC);
+            }
+            vmar.allocate_at(
+                start - vmar.addr(),
+                end - start,
+                VmarFlags::CAN_MAP_RXW,
+                PAGE_SIZE,
+            )
+            .map_err(|_| LxError::ENOEXEC)?
+        };
+        let vmo = image_vmar.load_from_elf_at(elf, start)?;
+        let bias = image_vmar.addr() - start;
+        for map in image_vmar.get_mappings() {
+            if let Some(mapping) = image_vmar.find_mapping(map.addr) {
+                mapping.vmo().set_name(path);
+            }
+        }
+
+        // fill syscall entry
+        if let Some(addr) = elf.get_symbol_address("rcore_syscall_entry") {
+            vmo.write(addr as usize - start, &self.syscall_entry.to_ne_bytes())?;
+        }
+        Ok(bias)
+    }
+}
+
+/// Whether `elf` is position independent.
+fn is_dynamic(elf: &ElfFile) -> bool {
+    elf.header.pt2.type_().as_type() == header::Type::SharedObject
+}
+
+/// Check the LOAD segments of `elf`, `len` bytes long, can be mapped in `vmar`.
+///
+/// The segments must be in the file, and the image at its link address or,
+/// if it is position independent, anywhere in the address space.
+fn check_segments(vmar: &VmAddressRegion, elf: &ElfFile, len: usize) -> LxResult {
+    let mut loads = 0;
+    for ph in elf.program_iter() {
+        match ph.get_type() {
+            Ok(program::Type::Load) => loads += 1,
+            Ok(_) => continue,
+            Err(_) => return Err(LxError::ENOEXEC),
+        }
+        let file_end = ph.offset().checked_add(ph.file_size());
+        let mem_end = ph.virtual_addr().checked_add(ph.mem_size());
+        if file_end.map_or(true, |end| end > len as u64)
+            || mem_end.map_or(true, |end| end > vmar.end_addr() as u64)
+            || ph.file_size() > ph.mem_size()
+        {
+            warn!("load: segment at {:#x} is out of bounds", ph.virtual_addr());
+            return Err(LxError::ENOEXEC);
+        }
+    }
+    if loads == 0 {
+        warn!("load: no LOAD segment");
+        return Err(LxError::ENOEXEC);
+    }
+    let (start, end) = elf.load_segment_range();
+    let fits = if is_dynamic(elf) {
+        end - start <= vmar.end_addr() - vmar.addr()
+    } else {
+        start >= vmar.addr() && end <= vmar.end_addr()
+    };
+    if !fits {
+        warn!("load: image at {:#x} is out of the address space", start);
+        return Err(LxError::ENOEXEC);
+    }
+    Ok(())
+}
+
+/// Get the address of the program headers in the image, before relocation.
+fn phdr_addr(elf: &ElfFile) -> usize {
+    let phdrs = elf
+        .program_iter()
+        .find(|ph| ph.get_type() == Ok(program::Type::Phdr));
+    if let Some(ph) = phdrs {
+        return ph.virtual_addr() as usize;
     }
+    // the headers are in the first LOAD segment, at their file offset
+    let first_load = elf
+        .program_iter()
+        .find(|ph| ph.get_type() == Ok(program::Type::Load));
+    let load_addr = first_load.map_or(0, |ph| (ph.virtual_addr() - ph.offset()) as usize);
+    load_addr + elf.header.pt2.ph_offset() as usize
 }
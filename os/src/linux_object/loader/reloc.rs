@@ -0,0 +1,151 @@
+//! Relocation of static position independent executables
+//!
+//! The dynamic relocations of an image without an interpreter are applied at
+//! load time, writing through the address space the image is mapped in. Only
+//! the relocations a static-PIE can have are supported, and they are checked
+//! before the address space of the caller is cleared. The interpreter of a
+//! dynamically linked program relocates itself.
+
+use super::super::error::{LxError, LxResult};
+use crate::zircon_object::{util::elf_loader::ElfExt, vm::VmAddressRegion};
+use core::mem::size_of;
+use xmas_elf::{
+    program::Type,
+    sections::{Rela, SectionData},
+    symbol_table::{Binding, Entry},
+    ElfFile, P64,
+};
+
+/// How the value of a relocation is computed
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Kind {
+    /// nothing to do
+    None,
+    /// the load bias plus the addend
+    Relative,
+    /// the address of the symbol plus the addend
+    Absolute,
+    /// the address of the symbol, for GOT and PLT entries
+    Symbol,
+}
+
+#[cfg(target_arch = "x86_64")]
+fn kind(type_: u32) -> Option<Kind> {
+    match type_ {
+        0 => Some(Kind::None),
+        // R_X86_64_64
+        1 => Some(Kind::Absolute),
+        // R_X86_64_GLOB_DAT, R_X86_64_JUMP_SLOT
+        6 | 7 => Some(Kind::Symbol),
+        // R_X86_64_RELATIVE
+        8 => Some(Kind::Relative),
+        _ => None,
+    }
+}
+
+#[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+fn kind(type_: u32) -> Option<Kind> {
+    match type_ {
+        0 => Some(Kind::None),
+        // R_RISCV_64
+        2 => Some(Kind::Absolute),
+        // R_RISCV_RELATIVE
+        3 => Some(Kind::Relative),
+        // R_RISCV_JUMP_SLOT
+        5 => Some(Kind::Symbol),
+        _ => None,
+    }
+}
+
+/// Check the dynamic relocations of `elf` can be applied by [`relocate`].
+///
+/// Fails with `ENOEXEC` on an unsupported relocation, an undefined symbol
+/// which is not weak, or a relocation outside the LOAD segments.
+pub fn check(elf: &ElfFile) -> LxResult {
+    for_each_rela(elf, |entry| {
+        value(elf, entry, 0)?;
+        let offset = entry.get_offset();
+        let end = offset.checked_add(size_of::<usize>() as u64);
+        let in_segment = elf.program_iter().any(|ph| {
+            ph.get_type() == Ok(Type::Load)
+                && ph.virtual_addr() <= offset
+                && end.map_or(false, |end| end <= ph.virtual_addr() + ph.mem_size())
+        });
+        if !in_segment {
+            warn!("load: relocation at {:#x} is out of the image", offset);
+            return Err(LxError::ENOEXEC);
+        }
+        Ok(())
+    })
+}
+
+/// Apply the dynamic relocations of `elf`, mapped in `vmar` with `bias`.
+///
+/// An image without relocations is left as it is. The relocations must have
+/// been checked by [`check`].
+pub fn relocate(elf: &ElfFile, vmar: &VmAddressRegion, bias: usize) -> LxResult {
+    for_each_rela(elf, |entry| {
+        if let Some(value) = value(elf, entry, bias)? {
+            let addr = bias.wrapping_add(entry.get_offset() as usize);
+            vmar.write_memory(addr, &value.to_ne_bytes())
+                .map_err(|_| LxError::ENOEXEC)?;
+        }
+        Ok(())
+    })
+}
+
+/// Call `f` on the entries of the relocation sections of `elf`.
+fn for_each_rela(elf: &ElfFile, mut f: impl FnMut(&Rela<P64>) -> LxResult) -> LxResult {
+    for name in [".rela.dyn", ".rela.plt"].iter() {
+        let section = match elf.find_section_by_name(name) {
+            Some(section) => section,
+            None => continue,
+        };
+        let entries = match section.get_data(elf) {
+            Ok(SectionData::Rela64(entries)) => entries,
+            _ => return Err(LxError::ENOEXEC),
+        };
+        for entry in entries {
+            f(entry)?;
+        }
+    }
+    Ok(())
+}
+
+/// Compute the value of the relocation `entry` of an image loaded with
+/// `bias`, `None` if it has nothing to write.
+fn value(elf: &ElfFile, entry: &Rela<P64>, bias: usize) -> LxResult<Option<usize>> {
+    let type_ = entry.get_type();
+    let kind = kind(type_).ok_or_else(|| {
+        warn!("load: unsupported relocation type {}", type_);
+        LxError::ENOEXEC
+    })?;
+    let addend = entry.get_addend() as usize;
+    let value = match kind {
+        Kind::None => return Ok(None),
+        Kind::Relative => bias.wrapping_add(addend),
+        Kind::Absolute => {
+            symbol(elf, entry.get_symbol_table_index(), bias)?.wrapping_add(addend)
+        }
+        Kind::Symbol => symbol(elf, entry.get_symbol_table_index(), bias)?,
+    };
+    Ok(Some(value))
+}
+
+/// Get the address of the dynamic symbol `index`, 0 if it is weak and
+/// undefined.
+fn symbol(elf: &ElfFile, index: u32, bias: usize) -> LxResult<usize> {
+    let dynsym = elf.dynsym().map_err(|_| LxError::ENOEXEC)?;
+    let symbol = dynsym.get(index as usize).ok_or(LxError::ENOEXEC)?;
+    if symbol.shndx() != 0 {
+        return Ok(bias + symbol.value() as usize);
+    }
+    match symbol.get_binding() {
+        Ok(Binding::Weak) => Ok(0),
+        _ => {
+            let name = symbol.get_name(elf).unwrap_or("?");
+            warn!("load: undefined symbol {:?} in a static executable", name);
+            Err(LxError::ENOEXEC)
+        }
+    }
+}
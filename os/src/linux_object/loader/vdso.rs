@@ -0,0 +1,103 @@
+//! The vDSO of Linux processes
+//!
+//! The kernel provides no fast paths yet, so the vDSO is a valid shared
+//! object exporting no symbol. libc finds it from `AT_SYSINFO_EHDR`, looks
+//! up the functions it could use, and falls back to system calls. One page
+//! is built at boot and mapped read-only in every process.
+
+use super::super::error::LxResult;
+use crate::zircon_object::{object::KernelObject, vm::*};
+use alloc::{sync::Arc, vec::Vec};
+use lazy_static::lazy_static;
+
+#[cfg(target_arch = "x86_64")]
+const EM_CURRENT: u16 = 62;
+#[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+const EM_CURRENT: u16 = 243;
+#[cfg(target_arch = "aarch64")]
+const EM_CURRENT: u16 = 183;
+
+/// Name in `DT_SONAME`, after the leading NUL of the string table
+const SONAME: &[u8] = b"\0linux-vdso.so.1\0";
+
+// Layout of the image: the ELF header, the two program headers, the hash
+// table, the symbol table with only the null symbol, the string table and
+// the dynamic section.
+const PHDR: usize = 64;
+const PHDR_SIZE: usize = 56;
+const HASH: usize = PHDR + 2 * PHDR_SIZE;
+const DYNSYM: usize = HASH + 16;
+const SYM_SIZE: usize = 24;
+const DYNSTR: usize = DYNSYM + SYM_SIZE;
+const DYNAMIC: usize = (DYNSTR + SONAME.len() + 7) & !7;
+const DYNAMIC_SIZE: usize = 7 * 16;
+const END: usize = DYNAMIC + DYNAMIC_SIZE;
+
+lazy_static! {
+    static ref VDSO: Arc<VmObject> = {
+        let vmo = VmObject::new_paged(1);
+        vmo.set_name("[vdso]");
+        vmo.write(0, &image()).unwrap();
+        vmo
+    };
+}
+
+/// Map the vDSO in `vmar` and return its address.
+pub fn map(vmar: &Arc<VmAddressRegion>) -> LxResult<VirtAddr> {
+    let flags = MMUFlags::READ | MMUFlags::EXECUTE | MMUFlags::USER;
+    Ok(vmar.map(None, VDSO.clone(), 0, PAGE_SIZE, flags)?)
+}
+
+/// Build the ELF image of the vDSO.
+fn image() -> Vec<u8> {
+    let mut image = vec![0u8; END];
+    let mut put = |offset: usize, bytes: &[u8]| {
+        image[offset..offset + bytes.len()].copy_from_slice(bytes);
+    };
+    // ELF header: 64-bit, little endian, version 1, ET_DYN
+    put(0, b"\x7fELF\x02\x01\x01");
+    put(16, &3u16.to_le_bytes());
+    put(18, &EM_CURRENT.to_le_bytes());
+    put(20, &1u32.to_le_bytes());
+    put(32, &(PHDR as u64).to_le_bytes());
+    put(52, &64u16.to_le_bytes());
+    put(54, &(PHDR_SIZE as u16).to_le_bytes());
+    put(56, &2u16.to_le_bytes());
+    put(58, &64u16.to_le_bytes());
+    // program headers: PT_LOAD of the whole image, PT_DYNAMIC
+    let phdrs = [
+        (1u32, 5u32, 0, END, PAGE_SIZE),
+        (2, 4, DYNAMIC, DYNAMIC_SIZE, 8),
+    ];
+    for (i, &(type_, flags, offset, size, align)) in phdrs.iter().enumerate() {
+        let phdr = PHDR + i * PHDR_SIZE;
+        put(phdr, &type_.to_le_bytes());
+        put(phdr + 4, &flags.to_le_bytes());
+        // offset, vaddr, paddr, filesz, memsz, align
+        for (j, &value) in [offset, offset, offset, size, size, align]
+            .iter()
+            .enumerate()
+        {
+            put(phdr + 8 + j * 8, &(value as u64).to_le_bytes());
+        }
+    }
+    // hash table with one empty bucket and the null symbol in the chain
+    put(HASH, &1u32.to_le_bytes());
+    put(HASH + 4, &1u32.to_le_bytes());
+    put(DYNSTR, SONAME);
+    // DT_HASH, DT_STRTAB, DT_SYMTAB, DT_STRSZ, DT_SYMENT, DT_SONAME, DT_NULL
+    let dynamic = [
+        (4u64, HASH),
+        (5, DYNSTR),
+        (6, DYNSYM),
+        (10, SONAME.len()),
+        (11, SYM_SIZE),
+        (14, 1),
+        (0, 0),
+    ];
+    for (i, &(tag, value)) in dynamic.iter().enumerate() {
+        put(DYNAMIC + i * 16, &tag.to_le_bytes());
+        put(DYNAMIC + i * 16 + 8, &(value as u64).to_le_bytes());
+    }
+    image
+}
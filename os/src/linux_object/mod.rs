@@ -2,13 +2,18 @@
 pub mod error;
 
 // layer 1
+pub mod cred;
 pub mod fs;
+pub mod rlimit;
 
 // layer 2
 pub mod sync;
 pub mod process;
+pub mod futex;
 pub mod ipc;
+pub mod net;
 pub mod time;
 pub mod signal;
 pub mod loader;
 pub mod thread;
+pub mod ptrace;
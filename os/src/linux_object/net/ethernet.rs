@@ -0,0 +1,208 @@
+//! Ethernet interfaces
+//!
+//! IPv4 over a [`NetDevice`], with ARP to resolve neighbours. IPv6 neighbour
+//! discovery is not implemented, so these interfaces only carry IPv4.
+
+use super::iface::{self, NetInterface};
+use super::ip::IpAddress;
+use crate::drivers::NetDevice;
+use crate::linux_object::error::*;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::{boxed::Box, string::String, sync::Arc, vec::Vec};
+use spin::Mutex;
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_ARP: u16 = 0x0806;
+/// Size of the ethernet header
+const HEADER_LEN: usize = 14;
+/// Size of an ARP packet for IPv4 over ethernet
+const ARP_LEN: usize = 28;
+/// Hardware type ethernet, protocol type IPv4, and their address lengths
+const ARP_HEADER: [u8; 6] = [0, 1, 0x08, 0x00, 6, 4];
+const ARP_REQUEST: u16 = 1;
+const ARP_REPLY: u16 = 2;
+const BROADCAST_MAC: [u8; 6] = [0xff; 6];
+/// Max number of packets waiting for ARP replies
+const PENDING_MAX: usize = 16;
+
+/// An IPv4 interface on an ethernet card
+pub struct EthernetInterface {
+    name: String,
+    device: Arc<dyn NetDevice>,
+    addr: [u8; 4],
+    netmask: [u8; 4],
+    gateway: Option<[u8; 4]>,
+    arp: Mutex<ArpState>,
+}
+
+#[derive(Default)]
+struct ArpState {
+    /// IPv4 address to MAC address
+    cache: BTreeMap<[u8; 4], [u8; 6]>,
+    /// packets waiting for the MAC address of the next hop
+    pending: VecDeque<([u8; 4], Vec<u8>)>,
+}
+
+impl EthernetInterface {
+    /// Create an interface with a static address.
+    ///
+    /// `prefix_len` is the length of the subnet prefix, and packets out of the
+    /// subnet are sent to `gateway`.
+    pub fn new(
+        name: &str,
+        device: Arc<dyn NetDevice>,
+        addr: IpAddress,
+        prefix_len: u8,
+        gateway: Option<IpAddress>,
+    ) -> LxResult<Arc<Self>> {
+        let addr = match addr {
+            IpAddress::V4(addr) => addr,
+            IpAddress::V6(_) => return Err(LxError::EAFNOSUPPORT),
+        };
+        let gateway = match gateway {
+            Some(IpAddress::V4(gateway)) => Some(gateway),
+            Some(IpAddress::V6(_)) => return Err(LxError::EAFNOSUPPORT),
+            None => None,
+        };
+        if prefix_len > 32 {
+            return Err(LxError::EINVAL);
+        }
+        let mask = u32::max_value()
+            .checked_shl(32 - prefix_len as u32)
+            .unwrap_or(0);
+        let iface = Arc::new(EthernetInterface {
+            name: String::from(name),
+            device,
+            addr,
+            netmask: mask.to_be_bytes(),
+            gateway,
+            arp: Mutex::new(ArpState::default()),
+        });
+        // received frames are processed by the stack as soon as they arrive
+        iface.device.set_rx_callback(Box::new(iface::poll));
+        Ok(iface)
+    }
+
+    fn in_subnet(&self, addr: &[u8; 4]) -> bool {
+        (0..4).all(|i| addr[i] & self.netmask[i] == self.addr[i] & self.netmask[i])
+    }
+
+    fn send_frame(&self, dst: [u8; 6], ethertype: u16, payload: &[u8]) -> LxResult {
+        let mut frame = Vec::with_capacity(HEADER_LEN + payload.len());
+        frame.extend(&dst);
+        frame.extend(&self.device.mac());
+        frame.extend(&ethertype.to_be_bytes());
+        frame.extend(payload);
+        self.device.transmit(&frame)?;
+        Ok(())
+    }
+
+    fn send_arp(&self, op: u16, dst_mac: [u8; 6], target_mac: [u8; 6], target: [u8; 4]) {
+        let mut packet = Vec::with_capacity(ARP_LEN);
+        packet.extend(&ARP_HEADER);
+        packet.extend(&op.to_be_bytes());
+        packet.extend(&self.device.mac());
+        packet.extend(&self.addr);
+        packet.extend(&target_mac);
+        packet.extend(&target);
+        if let Err(err) = self.send_frame(dst_mac, ETHERTYPE_ARP, &packet) {
+            warn!("{}: failed to send ARP: {:?}", self.name, err);
+        }
+    }
+
+    fn handle_arp(&self, packet: &[u8]) {
+        if packet.len() < ARP_LEN || packet[0..6] != ARP_HEADER[..] {
+            return;
+        }
+        let op = u16::from_be_bytes([packet[6], packet[7]]);
+        let mut sender_mac = [0u8; 6];
+        let mut sender = [0u8; 4];
+        let mut target = [0u8; 4];
+        sender_mac.copy_from_slice(&packet[8..14]);
+        sender.copy_from_slice(&packet[14..18]);
+        target.copy_from_slice(&packet[24..28]);
+        if target != self.addr {
+            return;
+        }
+        let ready: Vec<Vec<u8>> = {
+            let mut arp = self.arp.lock();
+            arp.cache.insert(sender, sender_mac);
+            let (ready, waiting): (VecDeque<_>, VecDeque<_>) =
+                arp.pending.drain(..).partition(|(hop, _)| *hop == sender);
+            arp.pending = waiting;
+            ready.into_iter().map(|(_, packet)| packet).collect()
+        };
+        for packet in ready {
+            if let Err(err) = self.send_frame(sender_mac, ETHERTYPE_IPV4, &packet) {
+                warn!("{}: failed to send packet: {:?}", self.name, err);
+            }
+        }
+        if op == ARP_REQUEST {
+            self.send_arp(ARP_REPLY, sender_mac, sender_mac, sender);
+        }
+    }
+}
+
+impl NetInterface for EthernetInterface {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn mtu(&self) -> usize {
+        self.device.mtu()
+    }
+
+    fn addrs(&self) -> Vec<IpAddress> {
+        vec![IpAddress::V4(self.addr)]
+    }
+
+    fn transmit(&self, packet: Vec<u8>) -> LxResult {
+        if packet.len() < 20 || packet[0] >> 4 != 4 {
+            return Err(LxError::ENETUNREACH);
+        }
+        let mut dst = [0u8; 4];
+        dst.copy_from_slice(&packet[16..20]);
+        if dst == [0xff; 4] {
+            return self.send_frame(BROADCAST_MAC, ETHERTYPE_IPV4, &packet);
+        }
+        let next_hop = if self.in_subnet(&dst) {
+            dst
+        } else {
+            self.gateway.ok_or(LxError::ENETUNREACH)?
+        };
+        let mac = {
+            let mut arp = self.arp.lock();
+            match arp.cache.get(&next_hop) {
+                Some(mac) => *mac,
+                None => {
+                    if arp.pending.len() >= PENDING_MAX {
+                        arp.pending.pop_front();
+                    }
+                    arp.pending.push_back((next_hop, packet));
+                    drop(arp);
+                    self.send_arp(ARP_REQUEST, BROADCAST_MAC, [0; 6], next_hop);
+                    return Ok(());
+                }
+            }
+        };
+        self.send_frame(mac, ETHERTYPE_IPV4, &packet)
+    }
+
+    fn receive(&self) -> Option<Vec<u8>> {
+        loop {
+            let frame = self.device.receive()?;
+            if frame.len() < HEADER_LEN {
+                continue;
+            }
+            let dst = &frame[0..6];
+            if dst != &BROADCAST_MAC[..] && dst != &self.device.mac()[..] {
+                continue;
+            }
+            match u16::from_be_bytes([frame[12], frame[13]]) {
+                ETHERTYPE_IPV4 => return Some(frame[HEADER_LEN..].to_vec()),
+                ETHERTYPE_ARP => self.handle_arp(&frame[HEADER_LEN..]),
+                _ => {}
+            }
+        }
+    }
+}
@@ -0,0 +1,150 @@
+//! Network interfaces and IP routing
+//!
+//! The stack is driven by polling: interfaces queue received packets, and
+//! [`poll`] hands them to the transport protocols. Sockets call [`poll`] after
+//! each operation that may have sent something, so that traffic over the
+//! loopback interface is processed synchronously.
+
+use super::ip::*;
+use super::{tcp, udp};
+use crate::linux_object::error::*;
+use alloc::{collections::VecDeque, sync::Arc, vec::Vec};
+use lazy_static::lazy_static;
+use spin::{Mutex, RwLock};
+
+/// A network interface carrying IP packets
+pub trait NetInterface: Send + Sync {
+    /// interface name, e.g. `lo`
+    fn name(&self) -> &str;
+    /// maximum transmission unit
+    fn mtu(&self) -> usize;
+    /// addresses assigned to the interface
+    fn addrs(&self) -> Vec<IpAddress>;
+    /// send an IP packet
+    fn transmit(&self, packet: Vec<u8>) -> LxResult;
+    /// take a received IP packet
+    fn receive(&self) -> Option<Vec<u8>>;
+}
+
+/// The loopback interface
+#[derive(Default)]
+pub struct Loopback {
+    queue: Mutex<VecDeque<Vec<u8>>>,
+}
+
+impl NetInterface for Loopback {
+    fn name(&self) -> &str {
+        "lo"
+    }
+
+    fn mtu(&self) -> usize {
+        65536
+    }
+
+    fn addrs(&self) -> Vec<IpAddress> {
+        vec![IpAddress::LOOPBACK_V4, IpAddress::LOOPBACK_V6]
+    }
+
+    fn transmit(&self, packet: Vec<u8>) -> LxResult {
+        self.queue.lock().push_back(packet);
+        Ok(())
+    }
+
+    fn receive(&self) -> Option<Vec<u8>> {
+        self.queue.lock().pop_front()
+    }
+}
+
+lazy_static! {
+    static ref INTERFACES: RwLock<Vec<Arc<dyn NetInterface>>> =
+        RwLock::new(vec![Arc::new(Loopback::default()) as Arc<dyn NetInterface>]);
+    /// Serializes `poll`, packets are processed in order.
+    static ref POLL_LOCK: Mutex<()> = Mutex::new(());
+}
+
+/// Register a network interface to the stack.
+pub fn add_interface(iface: Arc<dyn NetInterface>) {
+    info!("net: add interface {}", iface.name());
+    INTERFACES.write().push(iface);
+}
+
+/// Whether `addr` is assigned to some local interface.
+pub fn is_local_addr(addr: &IpAddress) -> bool {
+    addr.is_loopback() || INTERFACES.read().iter().any(|i| i.addrs().contains(addr))
+}
+
+/// Find the interface to reach `dst`.
+///
+/// Local and loopback destinations go through the loopback interface,
+/// everything else through the first other interface.
+fn route(dst: &IpAddress) -> Option<Arc<dyn NetInterface>> {
+    if is_local_addr(dst) {
+        return INTERFACES.read().first().cloned();
+    }
+    INTERFACES
+        .read()
+        .iter()
+        .skip(1)
+        .find(|i| i.addrs().iter().any(|a| a.is_v4() == dst.is_v4()))
+        .cloned()
+}
+
+/// Choose the source address to reach `dst`.
+pub fn source_addr(dst: &IpAddress) -> LxResult<IpAddress> {
+    if is_local_addr(dst) {
+        return Ok(*dst);
+    }
+    route(dst)
+        .and_then(|i| i.addrs().into_iter().find(|a| a.is_v4() == dst.is_v4()))
+        .ok_or(LxError::ENETUNREACH)
+}
+
+/// Send a transport segment to `dst`.
+pub fn send_ip(src: IpAddress, dst: IpAddress, protocol: u8, payload: &[u8]) -> LxResult {
+    let iface = route(&dst).ok_or(LxError::ENETUNREACH)?;
+    if payload.len() + 60 > iface.mtu() {
+        return Err(LxError::EMSGSIZE);
+    }
+    iface.transmit(IpPacket::build(src, dst, protocol, payload)?)
+}
+
+/// Process all packets received by the interfaces.
+pub fn poll() {
+    let _guard = match POLL_LOCK.try_lock() {
+        Some(guard) => guard,
+        // someone is polling, it will also see our packets
+        None => return,
+    };
+    loop {
+        let ifaces = INTERFACES.read().clone();
+        let mut processed = false;
+        for iface in ifaces.iter() {
+            while let Some(packet) = iface.receive() {
+                processed = true;
+                dispatch(&packet);
+            }
+        }
+        if !processed {
+            break;
+        }
+    }
+}
+
+fn dispatch(buf: &[u8]) {
+    let packet = match IpPacket::parse(buf) {
+        Some(packet) => packet,
+        None => {
+            debug!("net: drop malformed packet");
+            return;
+        }
+    };
+    if !is_local_addr(&packet.dst) {
+        debug!("net: drop packet to {:?}", packet.dst);
+        return;
+    }
+    match packet.protocol {
+        IPPROTO_TCP => tcp::input(&packet),
+        IPPROTO_UDP => udp::input(&packet),
+        p => debug!("net: drop packet of protocol {}", p),
+    }
+}
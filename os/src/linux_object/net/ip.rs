@@ -0,0 +1,280 @@
+//! Internet protocol addresses and packets
+
+use crate::linux_object::error::*;
+use alloc::vec::Vec;
+use core::fmt;
+
+/// Protocol number of TCP
+pub const IPPROTO_TCP: u8 = 6;
+/// Protocol number of UDP
+pub const IPPROTO_UDP: u8 = 17;
+
+/// Default hop limit of sent packets
+const DEFAULT_TTL: u8 = 64;
+
+/// An IPv4 or IPv6 address
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+pub enum IpAddress {
+    /// IPv4 address
+    V4([u8; 4]),
+    /// IPv6 address
+    V6([u8; 16]),
+}
+
+impl IpAddress {
+    /// `0.0.0.0`
+    pub const UNSPECIFIED_V4: Self = IpAddress::V4([0; 4]);
+    /// `::`
+    pub const UNSPECIFIED_V6: Self = IpAddress::V6([0; 16]);
+    /// `127.0.0.1`
+    pub const LOOPBACK_V4: Self = IpAddress::V4([127, 0, 0, 1]);
+    /// `::1`
+    pub const LOOPBACK_V6: Self =
+        IpAddress::V6([0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+
+    /// Whether this is the wildcard address
+    pub fn is_unspecified(&self) -> bool {
+        match self {
+            IpAddress::V4(a) => *a == [0; 4],
+            IpAddress::V6(a) => *a == [0; 16],
+        }
+    }
+
+    /// Whether this address belongs to the loopback network
+    pub fn is_loopback(&self) -> bool {
+        match self {
+            IpAddress::V4(a) => a[0] == 127,
+            IpAddress::V6(_) => *self == Self::LOOPBACK_V6,
+        }
+    }
+
+    /// Whether this is an IPv4 address
+    pub fn is_v4(&self) -> bool {
+        matches!(self, IpAddress::V4(_))
+    }
+
+    /// The loopback address of the same family
+    pub fn loopback_like(&self) -> Self {
+        match self {
+            IpAddress::V4(_) => Self::LOOPBACK_V4,
+            IpAddress::V6(_) => Self::LOOPBACK_V6,
+        }
+    }
+
+    /// The wildcard address of the same family
+    pub fn unspecified_like(&self) -> Self {
+        match self {
+            IpAddress::V4(_) => Self::UNSPECIFIED_V4,
+            IpAddress::V6(_) => Self::UNSPECIFIED_V6,
+        }
+    }
+
+    /// Whether a socket bound to `self` accepts packets sent to `dst`
+    pub fn accepts(&self, dst: &IpAddress) -> bool {
+        self == dst || (self.is_unspecified() && self.is_v4() == dst.is_v4())
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        match self {
+            IpAddress::V4(a) => a,
+            IpAddress::V6(a) => a,
+        }
+    }
+}
+
+impl fmt::Debug for IpAddress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IpAddress::V4(a) => write!(f, "{}.{}.{}.{}", a[0], a[1], a[2], a[3]),
+            IpAddress::V6(a) => {
+                for i in 0..8 {
+                    if i != 0 {
+                        write!(f, ":")?;
+                    }
+                    write!(f, "{:x}", u16::from_be_bytes([a[i * 2], a[i * 2 + 1]]))?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// An address and port pair
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+pub struct IpEndpoint {
+    /// IP address
+    pub addr: IpAddress,
+    /// port number
+    pub port: u16,
+}
+
+impl IpEndpoint {
+    /// Create an endpoint.
+    pub fn new(addr: IpAddress, port: u16) -> Self {
+        IpEndpoint { addr, port }
+    }
+}
+
+impl fmt::Debug for IpEndpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.addr {
+            IpAddress::V4(_) => write!(f, "{:?}:{}", self.addr, self.port),
+            IpAddress::V6(_) => write!(f, "[{:?}]:{}", self.addr, self.port),
+        }
+    }
+}
+
+/// A parsed IPv4 or IPv6 packet
+pub struct IpPacket<'a> {
+    /// source address
+    pub src: IpAddress,
+    /// destination address
+    pub dst: IpAddress,
+    /// transport protocol number
+    pub protocol: u8,
+    /// transport payload
+    pub payload: &'a [u8],
+}
+
+impl<'a> IpPacket<'a> {
+    /// Parse an IP packet, return `None` if it is malformed.
+    ///
+    /// IPv4 options and IPv6 extension headers are not supported.
+    pub fn parse(buf: &'a [u8]) -> Option<Self> {
+        match buf.first()? >> 4 {
+            4 => {
+                let ihl = (buf[0] & 0xf) as usize * 4;
+                if buf.len() < 20 || ihl < 20 || buf.len() < ihl {
+                    return None;
+                }
+                let total_len = u16::from_be_bytes([buf[2], buf[3]]) as usize;
+                if total_len < ihl || total_len > buf.len() || checksum(&[&buf[..ihl]]) != 0 {
+                    return None;
+                }
+                let mut src = [0u8; 4];
+                let mut dst = [0u8; 4];
+                src.copy_from_slice(&buf[12..16]);
+                dst.copy_from_slice(&buf[16..20]);
+                Some(IpPacket {
+                    src: IpAddress::V4(src),
+                    dst: IpAddress::V4(dst),
+                    protocol: buf[9],
+                    payload: &buf[ihl..total_len],
+                })
+            }
+            6 => {
+                if buf.len() < 40 {
+                    return None;
+                }
+                let payload_len = u16::from_be_bytes([buf[4], buf[5]]) as usize;
+                if 40 + payload_len > buf.len() {
+                    return None;
+                }
+                let mut src = [0u8; 16];
+                let mut dst = [0u8; 16];
+                src.copy_from_slice(&buf[8..24]);
+                dst.copy_from_slice(&buf[24..40]);
+                Some(IpPacket {
+                    src: IpAddress::V6(src),
+                    dst: IpAddress::V6(dst),
+                    protocol: buf[6],
+                    payload: &buf[40..40 + payload_len],
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// Build an IP packet carrying `payload`.
+    ///
+    /// Fails with `EAFNOSUPPORT` if `src` and `dst` are of different families.
+    pub fn build(
+        src: IpAddress,
+        dst: IpAddress,
+        protocol: u8,
+        payload: &[u8],
+    ) -> LxResult<Vec<u8>> {
+        let mut buf = Vec::new();
+        match (src, dst) {
+            (IpAddress::V4(src), IpAddress::V4(dst)) => {
+                let total_len = (20 + payload.len()) as u16;
+                buf.extend(&[0x45, 0]);
+                buf.extend(&total_len.to_be_bytes());
+                // identification, flags: don't fragment
+                buf.extend(&[0, 0, 0x40, 0]);
+                buf.extend(&[DEFAULT_TTL, protocol, 0, 0]);
+                buf.extend(&src);
+                buf.extend(&dst);
+                let sum = checksum(&[&buf]);
+                buf[10..12].copy_from_slice(&sum.to_be_bytes());
+            }
+            (IpAddress::V6(src), IpAddress::V6(dst)) => {
+                buf.extend(&[0x60, 0, 0, 0]);
+                buf.extend(&(payload.len() as u16).to_be_bytes());
+                buf.extend(&[protocol, DEFAULT_TTL]);
+                buf.extend(&src);
+                buf.extend(&dst);
+            }
+            _ => return Err(LxError::EAFNOSUPPORT),
+        }
+        buf.extend(payload);
+        Ok(buf)
+    }
+}
+
+/// The internet checksum of the concatenated `chunks`.
+pub fn checksum(chunks: &[&[u8]]) -> u16 {
+    let mut sum = 0u32;
+    let mut odd: Option<u8> = None;
+    for chunk in chunks {
+        for &byte in chunk.iter() {
+            match odd.take() {
+                Some(high) => sum += u16::from_be_bytes([high, byte]) as u32,
+                None => odd = Some(byte),
+            }
+        }
+    }
+    if let Some(high) = odd {
+        sum += u16::from_be_bytes([high, 0]) as u32;
+    }
+    while sum >> 16 != 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+/// The TCP/UDP checksum of `segment` including the pseudo header.
+pub fn transport_checksum(src: &IpAddress, dst: &IpAddress, protocol: u8, segment: &[u8]) -> u16 {
+    let len = segment.len() as u32;
+    let pseudo = if src.is_v4() {
+        let mut pseudo = [0u8; 4];
+        pseudo[1] = protocol;
+        pseudo[2..4].copy_from_slice(&(len as u16).to_be_bytes());
+        Vec::from(&pseudo[..])
+    } else {
+        let mut pseudo = [0u8; 8];
+        pseudo[0..4].copy_from_slice(&len.to_be_bytes());
+        pseudo[7] = protocol;
+        Vec::from(&pseudo[..])
+    };
+    checksum(&[src.as_bytes(), dst.as_bytes(), &pseudo, segment])
+}
+
+/// First port of the ephemeral range
+const EPHEMERAL_START: u16 = 49152;
+
+/// Find a free ephemeral port, starting after the last one allocated.
+pub fn alloc_ephemeral_port(last: &mut u16, in_use: impl Fn(u16) -> bool) -> Option<u16> {
+    let count = (u16::max_value() - EPHEMERAL_START) as u32 + 1;
+    for _ in 0..count {
+        *last = if *last < EPHEMERAL_START || *last == u16::max_value() {
+            EPHEMERAL_START
+        } else {
+            *last + 1
+        };
+        if !in_use(*last) {
+            return Some(*last);
+        }
+    }
+    None
+}
@@ -0,0 +1,432 @@
+//! Linux socket objects
+#![deny(missing_docs)]
+
+use super::error::*;
+use super::fs::FileLike;
+use alloc::{boxed::Box, string::String, sync::Arc, vec::Vec};
+use async_trait::async_trait;
+use bitflags::bitflags;
+use core::convert::TryFrom;
+use numeric_enum_macro::numeric_enum;
+
+pub use self::ethernet::EthernetInterface;
+pub use self::iface::{add_interface, NetInterface};
+pub use self::ip::{IpAddress, IpEndpoint, IPPROTO_TCP, IPPROTO_UDP};
+pub use self::tcp::TcpSocket;
+pub use self::udp::UdpSocket;
+pub use self::unix::*;
+
+pub(crate) use self::tcp::Assembler;
+
+mod ethernet;
+mod iface;
+mod ip;
+mod tcp;
+mod udp;
+mod unix;
+
+numeric_enum! {
+    #[repr(u16)]
+    #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+    /// Address family of a socket
+    pub enum AddressFamily {
+        /// Local communication
+        Unix = 1,
+        /// IPv4 Internet protocols
+        Inet = 2,
+        /// IPv6 Internet protocols
+        Inet6 = 10,
+    }
+}
+
+numeric_enum! {
+    #[repr(usize)]
+    #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+    /// Communication semantics of a socket
+    pub enum SocketType {
+        /// Sequenced, reliable, two-way, connection-based byte streams
+        Stream = 1,
+        /// Connectionless, unreliable messages of a fixed maximum length
+        Datagram = 2,
+        /// Sequenced, reliable, two-way connection-based datagrams
+        SeqPacket = 5,
+    }
+}
+
+impl AddressFamily {
+    /// The wildcard endpoint `0.0.0.0:0` or `[::]:0` of an Internet family
+    pub fn unspecified_endpoint(self) -> IpEndpoint {
+        match self {
+            AddressFamily::Inet6 => IpEndpoint::new(IpAddress::UNSPECIFIED_V6, 0),
+            _ => IpEndpoint::new(IpAddress::UNSPECIFIED_V4, 0),
+        }
+    }
+}
+
+/// Mask of the type part in the `type` argument of `socket()`
+pub const SOCK_TYPE_MASK: usize = 0xf;
+/// Flag of `socket()`: set O_NONBLOCK on the new socket
+pub const SOCK_NONBLOCK: usize = 0o4000;
+/// Flag of `socket()`: set FD_CLOEXEC on the new socket
+pub const SOCK_CLOEXEC: usize = 0o2000000;
+
+/// Socket level of `setsockopt()` and control messages
+pub const SOL_SOCKET: usize = 1;
+/// TCP level of `setsockopt()`
+pub const SOL_TCP: usize = 6;
+/// Control message: pass file descriptors
+pub const SCM_RIGHTS: usize = 1;
+
+bitflags! {
+    /// Flags of `send()` and `recv()`
+    #[derive(Default)]
+    pub struct MsgFlags: usize {
+        /// Return data without removing it from the queue
+        const PEEK      = 0x2;
+        /// Control data was discarded due to lack of space
+        const CTRUNC    = 0x8;
+        /// Datagram was longer than the buffer
+        const TRUNC     = 0x20;
+        /// Nonblocking operation
+        const DONTWAIT  = 0x40;
+        /// Wait for the full request
+        const WAITALL   = 0x100;
+        /// Do not generate SIGPIPE
+        const NOSIGNAL  = 0x4000;
+        /// Set close-on-exec on the descriptors received by SCM_RIGHTS
+        const CMSG_CLOEXEC = 0x4000_0000;
+    }
+}
+
+/// How to shut down a full-duplex connection
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Shutdown {
+    /// Further receptions will be disallowed
+    Read,
+    /// Further transmissions will be disallowed
+    Write,
+    /// Both of the above
+    Both,
+}
+
+impl TryFrom<usize> for Shutdown {
+    type Error = LxError;
+    fn try_from(how: usize) -> LxResult<Self> {
+        match how {
+            0 => Ok(Shutdown::Read),
+            1 => Ok(Shutdown::Write),
+            2 => Ok(Shutdown::Both),
+            _ => Err(LxError::EINVAL),
+        }
+    }
+}
+
+impl Shutdown {
+    /// Whether the read half is shut down
+    pub fn read(self) -> bool {
+        self != Shutdown::Write
+    }
+    /// Whether the write half is shut down
+    pub fn write(self) -> bool {
+        self != Shutdown::Read
+    }
+}
+
+/// Address of a unix domain socket
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd)]
+pub enum UnixAddr {
+    /// Not bound to any name
+    Unnamed,
+    /// Bound to a filesystem pathname (absolute)
+    Path(String),
+    /// Bound to a name in the abstract namespace
+    Abstract(Vec<u8>),
+}
+
+impl Default for UnixAddr {
+    fn default() -> Self {
+        UnixAddr::Unnamed
+    }
+}
+
+/// Generic socket address
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum SockAddr {
+    /// `sockaddr_un`
+    Unix(UnixAddr),
+    /// `sockaddr_in` or `sockaddr_in6`
+    Inet(IpEndpoint),
+}
+
+/// Max length of `sun_path` in `sockaddr_un`
+const UNIX_PATH_MAX: usize = 108;
+/// Size of `sockaddr_in`
+const SOCKADDR_IN_LEN: usize = 16;
+/// Size of `sockaddr_in6`
+const SOCKADDR_IN6_LEN: usize = 28;
+
+impl SockAddr {
+    /// Parse a socket address from the raw `sockaddr` bytes of user.
+    pub fn from_bytes(buf: &[u8]) -> LxResult<Self> {
+        if buf.len() < 2 {
+            return Err(LxError::EINVAL);
+        }
+        let family = u16::from_ne_bytes([buf[0], buf[1]]);
+        match AddressFamily::try_from(family).map_err(|_| LxError::EAFNOSUPPORT)? {
+            AddressFamily::Unix => {
+                let path = &buf[2..];
+                if path.len() > UNIX_PATH_MAX {
+                    return Err(LxError::EINVAL);
+                }
+                let addr = match path.first() {
+                    None => UnixAddr::Unnamed,
+                    Some(0) => UnixAddr::Abstract(path[1..].to_vec()),
+                    Some(_) => {
+                        let len = path.iter().position(|&c| c == 0).unwrap_or(path.len());
+                        let path = core::str::from_utf8(&path[..len])
+                            .map_err(|_| LxError::EINVAL)?;
+                        UnixAddr::Path(String::from(path))
+                    }
+                };
+                Ok(SockAddr::Unix(addr))
+            }
+            AddressFamily::Inet => {
+                if buf.len() < SOCKADDR_IN_LEN {
+                    return Err(LxError::EINVAL);
+                }
+                let port = u16::from_be_bytes([buf[2], buf[3]]);
+                let mut addr = [0u8; 4];
+                addr.copy_from_slice(&buf[4..8]);
+                Ok(SockAddr::Inet(IpEndpoint::new(IpAddress::V4(addr), port)))
+            }
+            AddressFamily::Inet6 => {
+                if buf.len() < SOCKADDR_IN6_LEN {
+                    return Err(LxError::EINVAL);
+                }
+                let port = u16::from_be_bytes([buf[2], buf[3]]);
+                let mut addr = [0u8; 16];
+                addr.copy_from_slice(&buf[8..24]);
+                Ok(SockAddr::Inet(IpEndpoint::new(IpAddress::V6(addr), port)))
+            }
+        }
+    }
+
+    /// The address of a unix domain socket, `EINVAL` for other families.
+    pub fn unix(self) -> LxResult<UnixAddr> {
+        match self {
+            SockAddr::Unix(addr) => Ok(addr),
+            _ => Err(LxError::EINVAL),
+        }
+    }
+
+    /// The address of an Internet socket, `EAFNOSUPPORT` for other families.
+    pub fn inet(self) -> LxResult<IpEndpoint> {
+        match self {
+            SockAddr::Inet(endpoint) => Ok(endpoint),
+            _ => Err(LxError::EAFNOSUPPORT),
+        }
+    }
+
+    /// Serialize the address into raw `sockaddr` bytes.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            SockAddr::Unix(addr) => {
+                let mut buf = Vec::from((AddressFamily::Unix as u16).to_ne_bytes());
+                match addr {
+                    UnixAddr::Unnamed => {}
+                    UnixAddr::Path(path) => {
+                        buf.extend(path.as_bytes());
+                        buf.push(0);
+                    }
+                    UnixAddr::Abstract(name) => {
+                        buf.push(0);
+                        buf.extend(name);
+                    }
+                }
+                buf
+            }
+            SockAddr::Inet(endpoint) => match endpoint.addr {
+                IpAddress::V4(addr) => {
+                    let mut buf = Vec::from((AddressFamily::Inet as u16).to_ne_bytes());
+                    buf.extend(&endpoint.port.to_be_bytes());
+                    buf.extend(&addr);
+                    buf.resize(SOCKADDR_IN_LEN, 0);
+                    buf
+                }
+                IpAddress::V6(addr) => {
+                    let mut buf = Vec::from((AddressFamily::Inet6 as u16).to_ne_bytes());
+                    buf.extend(&endpoint.port.to_be_bytes());
+                    // flow info
+                    buf.extend(&[0; 4]);
+                    buf.extend(&addr);
+                    // scope id
+                    buf.extend(&[0; 4]);
+                    buf
+                }
+            },
+        }
+    }
+}
+
+/// Data and ancillary data received from a socket
+#[derive(Default)]
+pub struct RecvMsg {
+    /// Bytes copied into the buffer
+    pub len: usize,
+    /// Source address, if the socket type reports one
+    pub addr: Option<SockAddr>,
+    /// Files passed with SCM_RIGHTS
+    pub rights: Vec<Arc<dyn FileLike>>,
+    /// Result flags (MSG_TRUNC, ...)
+    pub flags: MsgFlags,
+}
+
+#[async_trait]
+/// Generic socket interface
+pub trait Socket: FileLike {
+    /// assign an address to the socket
+    fn bind(&self, addr: SockAddr) -> LxResult;
+    /// mark the socket as passive, accepting incoming connections
+    fn listen(&self, backlog: usize) -> LxResult;
+    /// wait for an incoming connection and return the new connected socket
+    async fn accept(&self) -> LxResult<(Arc<dyn FileLike>, SockAddr)>;
+    /// connect to the given address
+    async fn connect(&self, addr: SockAddr) -> LxResult;
+    /// send data and ancillary rights, optionally to the given address
+    async fn send(
+        &self,
+        data: &[u8],
+        addr: Option<SockAddr>,
+        rights: Vec<Arc<dyn FileLike>>,
+        flags: MsgFlags,
+    ) -> LxResult<usize>;
+    /// receive data and ancillary rights
+    async fn recv(&self, buf: &mut [u8], flags: MsgFlags) -> LxResult<RecvMsg>;
+    /// shut down part of a full-duplex connection
+    fn shutdown(&self, how: Shutdown) -> LxResult;
+    /// get the address the socket is bound to
+    fn local_addr(&self) -> LxResult<SockAddr>;
+    /// get the address of the connected peer
+    fn peer_addr(&self) -> LxResult<SockAddr>;
+    /// set a socket option
+    fn setsockopt(&self, level: usize, opt: usize, _data: &[u8]) -> LxResult {
+        warn!("setsockopt: unsupported level={} opt={}", level, opt);
+        Err(LxError::ENOPROTOOPT)
+    }
+    /// get a socket option
+    fn getsockopt(&self, level: usize, opt: usize) -> LxResult<Vec<u8>> {
+        warn!("getsockopt: unsupported level={} opt={}", level, opt);
+        Err(LxError::ENOPROTOOPT)
+    }
+}
+
+/// Handle `F_GETFL` and `F_SETFL` of a socket, whose only status flag is `O_NONBLOCK`.
+pub fn fcntl_nonblock(nonblock: &mut bool, cmd: usize, arg: usize) -> LxResult<usize> {
+    const F_GETFL: usize = 3;
+    const F_SETFL: usize = 4;
+    // O_RDWR
+    const ACCESS_MODE: usize = 2;
+    match cmd {
+        F_GETFL if *nonblock => Ok(SOCK_NONBLOCK | ACCESS_MODE),
+        F_GETFL => Ok(ACCESS_MODE),
+        F_SETFL => {
+            *nonblock = arg & SOCK_NONBLOCK != 0;
+            Ok(0)
+        }
+        _ => Ok(0),
+    }
+}
+
+/// Options of an Internet socket which are stored but have no effect
+#[derive(Debug, Clone)]
+pub struct InetOptions {
+    /// `SO_REUSEADDR`: allow binding to a port in use
+    pub reuse_addr: bool,
+    /// `SO_BROADCAST`
+    pub broadcast: bool,
+    /// `SO_KEEPALIVE`
+    pub keepalive: bool,
+    /// `SO_SNDBUF`
+    pub send_buf: u32,
+    /// `SO_RCVBUF`
+    pub recv_buf: u32,
+    /// `TCP_NODELAY`
+    pub nodelay: bool,
+}
+
+impl Default for InetOptions {
+    fn default() -> Self {
+        InetOptions {
+            reuse_addr: false,
+            broadcast: false,
+            keepalive: false,
+            send_buf: 65536,
+            recv_buf: 65536,
+            nodelay: false,
+        }
+    }
+}
+
+impl InetOptions {
+    /// Set an option from the raw `int` value of user.
+    pub fn set(&mut self, level: usize, opt: usize, data: &[u8]) -> LxResult {
+        if data.len() < 4 {
+            return Err(LxError::EINVAL);
+        }
+        let value = u32::from_ne_bytes([data[0], data[1], data[2], data[3]]);
+        match (level, opt) {
+            (SOL_SOCKET, sockopt::SO_REUSEADDR) => self.reuse_addr = value != 0,
+            (SOL_SOCKET, sockopt::SO_BROADCAST) => self.broadcast = value != 0,
+            (SOL_SOCKET, sockopt::SO_KEEPALIVE) => self.keepalive = value != 0,
+            (SOL_SOCKET, sockopt::SO_SNDBUF) => self.send_buf = value,
+            (SOL_SOCKET, sockopt::SO_RCVBUF) => self.recv_buf = value,
+            (SOL_TCP, sockopt::TCP_NODELAY) => self.nodelay = value != 0,
+            _ => {
+                warn!("setsockopt: unsupported level={} opt={}", level, opt);
+                return Err(LxError::ENOPROTOOPT);
+            }
+        }
+        Ok(())
+    }
+
+    /// Get an option as the raw `int` value.
+    pub fn get(&self, level: usize, opt: usize) -> LxResult<Vec<u8>> {
+        let value = match (level, opt) {
+            (SOL_SOCKET, sockopt::SO_REUSEADDR) => self.reuse_addr as u32,
+            (SOL_SOCKET, sockopt::SO_BROADCAST) => self.broadcast as u32,
+            (SOL_SOCKET, sockopt::SO_KEEPALIVE) => self.keepalive as u32,
+            (SOL_SOCKET, sockopt::SO_SNDBUF) => self.send_buf,
+            (SOL_SOCKET, sockopt::SO_RCVBUF) => self.recv_buf,
+            (SOL_TCP, sockopt::TCP_NODELAY) => self.nodelay as u32,
+            _ => {
+                warn!("getsockopt: unsupported level={} opt={}", level, opt);
+                return Err(LxError::ENOPROTOOPT);
+            }
+        };
+        Ok(Vec::from(value.to_ne_bytes()))
+    }
+}
+
+/// Socket options at level `SOL_SOCKET` and `SOL_TCP`
+pub mod sockopt {
+    /// Allow reuse of local addresses
+    pub const SO_REUSEADDR: usize = 2;
+    /// Get the socket type
+    pub const SO_TYPE: usize = 3;
+    /// Get and clear the pending socket error
+    pub const SO_ERROR: usize = 4;
+    /// Allow sending to broadcast addresses
+    pub const SO_BROADCAST: usize = 6;
+    /// Send buffer size
+    pub const SO_SNDBUF: usize = 7;
+    /// Receive buffer size
+    pub const SO_RCVBUF: usize = 8;
+    /// Send keep-alive probes
+    pub const SO_KEEPALIVE: usize = 9;
+    /// Credentials of the peer
+    pub const SO_PEERCRED: usize = 17;
+    /// Whether the socket is listening
+    pub const SO_ACCEPTCONN: usize = 30;
+    /// Disable Nagle's algorithm, at level `SOL_TCP`
+    pub const TCP_NODELAY: usize = 1;
+}
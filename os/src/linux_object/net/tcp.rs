@@ -0,0 +1,1325 @@
+//! TCP sockets
+//!
+//! A small TCP implementation: three-way handshake, data transfer with flow
+//! control by the receive window, and orderly release with FIN. Lost segments
+//! are sent again from the oldest unacknowledged byte with exponential
+//! backoff, out-of-order segments wait for the gap before them to be filled,
+//! and the side closing first stays in TIME-WAIT. There is no congestion
+//! control, selective acknowledgement or window scaling.
+//!
+//! The state of a connection lives in a [`Tcb`] owned by the connection
+//! table, so that it outlives its socket until the connection is over.
+//! Retransmission and TIME-WAIT timers are run by a kernel task.
+
+use super::iface::{self, poll};
+use super::ip::*;
+use super::*;
+use crate::impl_kobject;
+use crate::kernel_hal::{fill_random, sleep_until, timer_now, PageTable, PageTableTrait, Thread};
+use crate::linux_object::fs::vfs::PollStatus;
+use crate::linux_object::sync::{wait_for_event, Event, EventBus};
+use crate::zircon_object::object::*;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::sync::Weak;
+use core::mem;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::time::Duration;
+use futures::{future::FutureExt, select_biased};
+use lazy_static::lazy_static;
+use spin::Mutex;
+
+/// Size of the TCP header without options
+const HEADER_LEN: usize = 20;
+/// Maximum segment size
+const MSS: usize = 1460;
+/// Capacity of the receive buffer, also the largest window without scaling
+const RX_BUF_SIZE: usize = 65535;
+/// Capacity of the send buffer
+const TX_BUF_SIZE: usize = 65536;
+/// Backlog limit of `listen`
+const SOMAXCONN: usize = 4096;
+/// Initial retransmission timeout
+const RTO_INIT: Duration = Duration::from_secs(1);
+/// Upper bound of the retransmission timeout
+const RTO_MAX: Duration = Duration::from_secs(60);
+/// Retransmissions without progress before the connection is given up
+const MAX_RETRIES: u32 = 8;
+/// Time spent in TIME-WAIT, twice the maximum segment lifetime
+const TIME_WAIT_LEN: Duration = Duration::from_secs(60);
+/// How long a closed socket in FIN-WAIT-2 waits for the FIN of the peer
+const FIN_TIMEOUT: Duration = Duration::from_secs(60);
+
+bitflags! {
+    struct TcpFlags: u8 {
+        const FIN = 1 << 0;
+        const SYN = 1 << 1;
+        const RST = 1 << 2;
+        const PSH = 1 << 3;
+        const ACK = 1 << 4;
+    }
+}
+
+lazy_static! {
+    static ref TCP_TABLE: Mutex<TcpTable> = Mutex::new(TcpTable::default());
+    /// `READABLE` when a timer was started since the timer task last looked
+    static ref TIMER_BUS: Arc<Mutex<EventBus>> = EventBus::new();
+}
+
+/// Whether the timer task was spawned
+static TIMER_TASK_STARTED: AtomicBool = AtomicBool::new(false);
+
+/// A new initial sequence number, from the kernel CPRNG so that it can not be
+/// guessed by an off-path attacker.
+fn new_iss() -> u32 {
+    let mut iss = [0u8; 4];
+    fill_random(&mut iss);
+    u32::from_ne_bytes(iss)
+}
+
+#[derive(Default)]
+struct TcpTable {
+    /// Sockets bound to a port, by `bind` or `listen`
+    bound: BTreeMap<u16, Weak<Tcb>>,
+    /// Connections by (local, remote), until they are closed
+    connections: BTreeMap<(IpEndpoint, IpEndpoint), Arc<Tcb>>,
+    last_ephemeral: u16,
+}
+
+impl TcpTable {
+    /// Whether binding to `port` conflicts with another socket.
+    ///
+    /// With `SO_REUSEADDR` only a bound or listening socket conflicts, not
+    /// the connections left by a closed listener or in TIME-WAIT.
+    fn port_in_use(&self, port: u16, reuse_addr: bool) -> bool {
+        self.bound.get(&port).and_then(Weak::upgrade).is_some()
+            || (!reuse_addr && self.connections.keys().any(|(local, _)| local.port == port))
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum TcpState {
+    Closed,
+    Listen,
+    SynSent,
+    SynReceived,
+    Established,
+    FinWait1,
+    FinWait2,
+    CloseWait,
+    Closing,
+    LastAck,
+    TimeWait,
+}
+
+/// A TCP socket
+pub struct TcpSocket {
+    base: KObjectBase,
+    tcb: Arc<Tcb>,
+}
+
+impl_kobject!(TcpSocket);
+
+/// The state of a TCP socket and its connection
+struct Tcb {
+    self_ref: Weak<Tcb>,
+    /// `AF_INET` or `AF_INET6`
+    family: AddressFamily,
+    eventbus: Arc<Mutex<EventBus>>,
+    inner: Mutex<TcpInner>,
+}
+
+struct TcpInner {
+    state: TcpState,
+    local: Option<IpEndpoint>,
+    remote: Option<IpEndpoint>,
+    nonblock: bool,
+    options: InetOptions,
+    /// connection was reset, refused or timed out
+    error: Option<LxError>,
+    /// the socket was closed, the connection finishes on its own
+    orphan: bool,
+
+    // listener
+    backlog: usize,
+    /// connections in SYN-RECEIVED
+    syn_queue: Vec<Arc<Tcb>>,
+    /// established connections and their peers, not accepted yet
+    accept_queue: VecDeque<(Arc<Tcb>, IpEndpoint)>,
+    /// the listener of a connection in SYN-RECEIVED
+    listener: Weak<Tcb>,
+
+    // send sequence space
+    snd_una: u32,
+    snd_nxt: u32,
+    snd_wnd: usize,
+    /// bytes from `snd_una`, unacknowledged or unsent
+    tx_buf: VecDeque<u8>,
+    /// user closed the sending half, FIN follows the data
+    fin_queued: bool,
+    fin_sent: bool,
+
+    // receive sequence space
+    rcv_nxt: u32,
+    rx_buf: VecDeque<u8>,
+    /// data received after a gap
+    assembler: Assembler,
+    /// sequence number of the FIN of the peer, once it is in the window
+    fin_seq: Option<u32>,
+    /// FIN received, no more data
+    peer_fin: bool,
+    /// receiving half shut down, data is discarded
+    read_shut: bool,
+
+    // timer
+    /// retransmission timeout
+    rto: Duration,
+    /// retransmissions since the last acknowledgement of new data
+    retries: u32,
+    /// expiry of the retransmission, FIN-WAIT-2 or TIME-WAIT timer
+    deadline: Option<Duration>,
+}
+
+impl Default for TcpInner {
+    fn default() -> Self {
+        TcpInner {
+            state: TcpState::Closed,
+            local: None,
+            remote: None,
+            nonblock: false,
+            options: InetOptions::default(),
+            error: None,
+            orphan: false,
+            backlog: 0,
+            syn_queue: Vec::new(),
+            accept_queue: VecDeque::new(),
+            listener: Weak::new(),
+            snd_una: 0,
+            snd_nxt: 0,
+            snd_wnd: 0,
+            tx_buf: VecDeque::new(),
+            fin_queued: false,
+            fin_sent: false,
+            rcv_nxt: 0,
+            rx_buf: VecDeque::new(),
+            assembler: Assembler::default(),
+            fin_seq: None,
+            peer_fin: false,
+            read_shut: false,
+            rto: RTO_INIT,
+            retries: 0,
+            deadline: None,
+        }
+    }
+}
+
+/// Received data which waits for the gap before it to be filled
+#[derive(Default)]
+pub(crate) struct Assembler {
+    /// segments by sequence number
+    segments: BTreeMap<u32, Vec<u8>>,
+}
+
+impl Assembler {
+    /// Keep `data` starting at `seq`.
+    pub(crate) fn insert(&mut self, seq: u32, data: &[u8]) {
+        let segment = self.segments.entry(seq).or_insert_with(Vec::new);
+        if segment.len() < data.len() {
+            *segment = data.to_vec();
+        }
+    }
+
+    /// Take the data which continues at `next`.
+    ///
+    /// Segments ending before `next` are discarded on the way.
+    pub(crate) fn take(&mut self, next: u32) -> Option<Vec<u8>> {
+        loop {
+            let seq = *self.segments.keys().find(|&&seq| seq_le(seq, next))?;
+            let mut data = self.segments.remove(&seq).unwrap();
+            let skip = next.wrapping_sub(seq) as usize;
+            if skip < data.len() {
+                data.drain(..skip);
+                return Some(data);
+            }
+        }
+    }
+
+    /// Whether no data is kept
+    pub(crate) fn is_empty(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    fn clear(&mut self) {
+        self.segments.clear();
+    }
+}
+
+/// A parsed TCP segment
+struct Segment<'a> {
+    src: IpEndpoint,
+    dst: IpEndpoint,
+    seq: u32,
+    ack: u32,
+    flags: TcpFlags,
+    window: usize,
+    payload: &'a [u8],
+}
+
+impl<'a> Segment<'a> {
+    fn parse(packet: &IpPacket<'a>) -> Option<Self> {
+        let seg = packet.payload;
+        if seg.len() < HEADER_LEN {
+            return None;
+        }
+        let offset = (seg[12] >> 4) as usize * 4;
+        if offset < HEADER_LEN || offset > seg.len() {
+            return None;
+        }
+        if transport_checksum(&packet.src, &packet.dst, IPPROTO_TCP, seg) != 0 {
+            debug!("tcp: bad checksum");
+            return None;
+        }
+        let word = |i: usize| u32::from_be_bytes([seg[i], seg[i + 1], seg[i + 2], seg[i + 3]]);
+        Some(Segment {
+            src: IpEndpoint::new(packet.src, u16::from_be_bytes([seg[0], seg[1]])),
+            dst: IpEndpoint::new(packet.dst, u16::from_be_bytes([seg[2], seg[3]])),
+            seq: word(4),
+            ack: word(8),
+            flags: TcpFlags::from_bits_truncate(seg[13]),
+            window: u16::from_be_bytes([seg[14], seg[15]]) as usize,
+            payload: &seg[offset..],
+        })
+    }
+
+    /// length in sequence space
+    fn seq_len(&self) -> u32 {
+        let mut len = self.payload.len() as u32;
+        if self.flags.contains(TcpFlags::SYN) {
+            len += 1;
+        }
+        if self.flags.contains(TcpFlags::FIN) {
+            len += 1;
+        }
+        len
+    }
+}
+
+/// Send a segment, errors are dropped like a lost packet.
+fn send_segment(
+    local: IpEndpoint,
+    remote: IpEndpoint,
+    seq: u32,
+    ack: u32,
+    flags: TcpFlags,
+    window: usize,
+    payload: &[u8],
+) {
+    let mut seg = Vec::with_capacity(HEADER_LEN + payload.len());
+    seg.extend(&local.port.to_be_bytes());
+    seg.extend(&remote.port.to_be_bytes());
+    seg.extend(&seq.to_be_bytes());
+    seg.extend(&ack.to_be_bytes());
+    seg.extend(&[(HEADER_LEN as u8 / 4) << 4, flags.bits()]);
+    seg.extend(&(window.min(u16::max_value() as usize) as u16).to_be_bytes());
+    // checksum and urgent pointer
+    seg.extend(&[0, 0, 0, 0]);
+    seg.extend(payload);
+    let sum = transport_checksum(&local.addr, &remote.addr, IPPROTO_TCP, &seg);
+    seg[16..18].copy_from_slice(&sum.to_be_bytes());
+    if let Err(err) = iface::send_ip(local.addr, remote.addr, IPPROTO_TCP, &seg) {
+        warn!("tcp: failed to send segment to {:?}: {:?}", remote, err);
+    }
+}
+
+/// Reply RST to a segment which belongs to no connection.
+fn send_reset(seg: &Segment) {
+    if seg.flags.contains(TcpFlags::RST) {
+        return;
+    }
+    if seg.flags.contains(TcpFlags::ACK) {
+        send_segment(seg.dst, seg.src, seg.ack, 0, TcpFlags::RST, 0, &[]);
+    } else {
+        let ack = seg.seq.wrapping_add(seg.seq_len());
+        let flags = TcpFlags::RST | TcpFlags::ACK;
+        send_segment(seg.dst, seg.src, 0, ack, flags, 0, &[]);
+    }
+}
+
+/// `a < b` in sequence space
+fn seq_lt(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) < 0
+}
+
+/// `a <= b` in sequence space
+fn seq_le(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) <= 0
+}
+
+impl TcpInner {
+    fn endpoints(&self) -> (IpEndpoint, IpEndpoint) {
+        (self.local.unwrap(), self.remote.unwrap())
+    }
+
+    fn rcv_wnd(&self) -> usize {
+        RX_BUF_SIZE - self.rx_buf.len()
+    }
+
+    fn send_ack(&self) {
+        let (local, remote) = self.endpoints();
+        let window = self.rcv_wnd();
+        send_segment(local, remote, self.snd_nxt, self.rcv_nxt, TcpFlags::ACK, window, &[]);
+    }
+
+    fn send_syn(&self, ack: bool) {
+        let (local, remote) = self.endpoints();
+        let (flags, ack_num) = if ack {
+            (TcpFlags::SYN | TcpFlags::ACK, self.rcv_nxt)
+        } else {
+            (TcpFlags::SYN, 0)
+        };
+        send_segment(local, remote, self.snd_una, ack_num, flags, self.rcv_wnd(), &[]);
+    }
+
+    fn send_rst(&self) {
+        let (local, remote) = self.endpoints();
+        send_segment(local, remote, self.snd_nxt, 0, TcpFlags::RST, 0, &[]);
+    }
+
+    /// Send new data allowed by the peer's window, then FIN if queued.
+    fn output(&mut self) {
+        self.transmit(false);
+    }
+
+    /// Send everything after `snd_una` again.
+    fn retransmit(&mut self) {
+        match self.state {
+            TcpState::SynSent => self.send_syn(false),
+            TcpState::SynReceived => self.send_syn(true),
+            _ => {
+                self.snd_nxt = self.snd_una;
+                self.fin_sent = false;
+                // probe a zero window with one byte
+                self.transmit(true);
+            }
+        }
+    }
+
+    fn transmit(&mut self, probe: bool) {
+        if !matches!(
+            self.state,
+            TcpState::Established
+                | TcpState::CloseWait
+                | TcpState::FinWait1
+                | TcpState::Closing
+                | TcpState::LastAck
+        ) {
+            return;
+        }
+        let (local, remote) = self.endpoints();
+        let snd_wnd = self.snd_wnd.max(probe as usize);
+        loop {
+            let in_flight = self.snd_nxt.wrapping_sub(self.snd_una) as usize;
+            let unsent = self.tx_buf.len().saturating_sub(in_flight);
+            let window = snd_wnd.saturating_sub(in_flight);
+            let len = unsent.min(window).min(MSS);
+            if len == 0 {
+                break;
+            }
+            let payload: Vec<u8> = self.tx_buf.iter().skip(in_flight).take(len).cloned().collect();
+            let flags = TcpFlags::ACK | TcpFlags::PSH;
+            send_segment(local, remote, self.snd_nxt, self.rcv_nxt, flags, self.rcv_wnd(), &payload);
+            self.snd_nxt = self.snd_nxt.wrapping_add(len as u32);
+        }
+        let all_sent = self.snd_nxt.wrapping_sub(self.snd_una) as usize == self.tx_buf.len();
+        if self.fin_queued && !self.fin_sent && all_sent {
+            let flags = TcpFlags::FIN | TcpFlags::ACK;
+            send_segment(local, remote, self.snd_nxt, self.rcv_nxt, flags, self.rcv_wnd(), &[]);
+            self.snd_nxt = self.snd_nxt.wrapping_add(1);
+            self.fin_sent = true;
+            self.state = match self.state {
+                TcpState::Established => TcpState::FinWait1,
+                TcpState::CloseWait => TcpState::LastAck,
+                state => state,
+            };
+        }
+    }
+
+    /// Take the data at `seq` which fits in the receive window.
+    ///
+    /// Data after a gap is kept aside until the gap is filled.
+    fn receive(&mut self, seq: u32, data: &[u8]) {
+        let wnd_end = self.rcv_nxt.wrapping_add(self.rcv_wnd() as u32);
+        let (seq, data) = if seq_lt(seq, self.rcv_nxt) {
+            // trim what was received already
+            let skip = self.rcv_nxt.wrapping_sub(seq) as usize;
+            if skip >= data.len() {
+                return;
+            }
+            (self.rcv_nxt, &data[skip..])
+        } else {
+            (seq, data)
+        };
+        if !seq_lt(seq, wnd_end) {
+            return;
+        }
+        let data = &data[..data.len().min(wnd_end.wrapping_sub(seq) as usize)];
+        if seq != self.rcv_nxt {
+            self.assembler.insert(seq, data);
+            return;
+        }
+        self.append(data);
+        while let Some(data) = self.assembler.take(self.rcv_nxt) {
+            let len = data.len().min(self.rcv_wnd());
+            self.append(&data[..len]);
+        }
+    }
+
+    fn append(&mut self, data: &[u8]) {
+        if !self.read_shut {
+            self.rx_buf.extend(data);
+        }
+        self.rcv_nxt = self.rcv_nxt.wrapping_add(data.len() as u32);
+    }
+
+    /// Start the timer of the connection, expiring after `timeout`.
+    fn set_timer(&mut self, timeout: Duration) {
+        self.deadline = Some(timer_now() + timeout);
+        wake_timer_task();
+    }
+
+    /// Start the retransmission timer if something waits for an
+    /// acknowledgement, stop it otherwise.
+    fn update_timer(&mut self) {
+        let waiting = match self.state {
+            TcpState::SynSent | TcpState::SynReceived => true,
+            TcpState::Established
+            | TcpState::CloseWait
+            | TcpState::FinWait1
+            | TcpState::Closing
+            | TcpState::LastAck => !self.tx_buf.is_empty() || self.snd_una != self.snd_nxt,
+            // the timer is not for retransmission
+            TcpState::FinWait2 | TcpState::TimeWait => return,
+            TcpState::Closed | TcpState::Listen => false,
+        };
+        if !waiting {
+            self.deadline = None;
+        } else if self.deadline.is_none() {
+            self.set_timer(self.rto);
+        }
+    }
+
+    /// New data was acknowledged: restart the retransmission timer.
+    fn reset_timer(&mut self) {
+        self.rto = RTO_INIT;
+        self.retries = 0;
+        self.deadline = None;
+    }
+
+    /// Whether the user may still send data
+    fn can_send(&self) -> bool {
+        matches!(self.state, TcpState::Established | TcpState::CloseWait) && !self.fin_queued
+    }
+
+    /// Whether a receive would not block
+    fn can_recv(&self) -> bool {
+        !self.rx_buf.is_empty() || self.peer_fin || self.read_shut || self.error.is_some()
+    }
+}
+
+impl Tcb {
+    #[allow(unsafe_code)]
+    fn new(family: AddressFamily, nonblock: bool) -> Arc<Self> {
+        let mut tcb = Arc::new(Tcb {
+            self_ref: Weak::default(),
+            family,
+            eventbus: EventBus::new(),
+            inner: Mutex::new(TcpInner {
+                nonblock,
+                ..Default::default()
+            }),
+        });
+        let self_ref = Arc::downgrade(&tcb);
+        // no other reference of `tcb`
+        unsafe {
+            Arc::get_mut_unchecked(&mut tcb).self_ref = self_ref;
+        }
+        tcb
+    }
+
+    fn check_family(&self, endpoint: &IpEndpoint) -> LxResult {
+        match (self.family, endpoint.addr.is_v4()) {
+            (AddressFamily::Inet, true) | (AddressFamily::Inet6, false) => Ok(()),
+            _ => Err(LxError::EAFNOSUPPORT),
+        }
+    }
+
+    /// Update events of the socket from its state.
+    fn update_events(&self, inner: &TcpInner) {
+        let mut set = Event::empty();
+        let mut clear = Event::empty();
+        let readable = match inner.state {
+            TcpState::Listen => !inner.accept_queue.is_empty(),
+            _ => inner.can_recv(),
+        };
+        if readable {
+            set |= Event::READABLE;
+        } else {
+            clear |= Event::READABLE;
+        }
+        if inner.can_send() && inner.tx_buf.len() < TX_BUF_SIZE {
+            set |= Event::WRITABLE;
+        } else {
+            clear |= Event::WRITABLE;
+        }
+        if inner.error.is_some() {
+            set |= Event::ERROR;
+        }
+        if inner.state == TcpState::Closed {
+            set |= Event::CLOSED;
+        }
+        self.eventbus.lock().change(clear, set);
+    }
+
+    /// Leave the connection table once the connection is over.
+    fn close_connection(&self, inner: &mut TcpInner) {
+        inner.state = TcpState::Closed;
+        inner.deadline = None;
+        if let (Some(local), Some(remote)) = (inner.local, inner.remote) {
+            let mut table = TCP_TABLE.lock();
+            if let Some(tcb) = table.connections.get(&(local, remote)) {
+                if core::ptr::eq(&**tcb, self) {
+                    table.connections.remove(&(local, remote));
+                }
+            }
+        }
+    }
+
+    /// Reset the connection.
+    fn abort(&self) {
+        let mut inner = self.inner.lock();
+        if matches!(inner.state, TcpState::Closed | TcpState::Listen) {
+            return;
+        }
+        if inner.state != TcpState::SynSent {
+            inner.send_rst();
+        }
+        self.close_connection(&mut inner);
+        self.update_events(&inner);
+    }
+
+    /// Run the timer of the connection if it expired.
+    ///
+    /// Returns when the timer expires next.
+    fn on_timer(&self, now: Duration) -> Option<Duration> {
+        let mut inner = self.inner.lock();
+        match inner.deadline {
+            Some(deadline) if now >= deadline => inner.deadline = None,
+            deadline => return deadline,
+        }
+        match inner.state {
+            TcpState::Closed | TcpState::Listen => {}
+            TcpState::FinWait2 | TcpState::TimeWait => self.close_connection(&mut inner),
+            _ if inner.retries >= MAX_RETRIES => {
+                inner.error = Some(LxError::ETIMEDOUT);
+                self.close_connection(&mut inner);
+            }
+            _ => {
+                inner.retries += 1;
+                inner.rto = (inner.rto * 2).min(RTO_MAX);
+                inner.retransmit();
+                inner.update_timer();
+            }
+        }
+        self.update_events(&inner);
+        let deadline = inner.deadline;
+        drop(inner);
+        self.leave_syn_queue();
+        deadline
+    }
+
+    /// Handle a segment of this connection.
+    fn process(&self, seg: &Segment) {
+        let mut inner = self.inner.lock();
+        self.process_locked(&mut inner, seg);
+        self.update_events(&inner);
+        drop(inner);
+        self.leave_syn_queue();
+    }
+
+    fn process_locked(&self, inner: &mut TcpInner, seg: &Segment) {
+        if seg.flags.contains(TcpFlags::RST) {
+            inner.error = Some(match inner.state {
+                TcpState::SynSent => LxError::ECONNREFUSED,
+                _ => LxError::ECONNRESET,
+            });
+            self.close_connection(inner);
+            return;
+        }
+        match inner.state {
+            TcpState::SynSent => {
+                let expected = seg.flags.contains(TcpFlags::SYN | TcpFlags::ACK)
+                    && seg.ack == inner.snd_nxt;
+                if !expected {
+                    send_reset(seg);
+                    return;
+                }
+                inner.rcv_nxt = seg.seq.wrapping_add(1);
+                inner.snd_una = seg.ack;
+                inner.snd_wnd = seg.window;
+                inner.state = TcpState::Established;
+                inner.reset_timer();
+                inner.send_ack();
+                inner.output();
+                inner.update_timer();
+                return;
+            }
+            TcpState::SynReceived => {
+                if !seg.flags.contains(TcpFlags::ACK) || seg.ack != inner.snd_nxt {
+                    return;
+                }
+                inner.snd_una = seg.ack;
+                inner.snd_wnd = seg.window;
+                inner.state = TcpState::Established;
+                inner.reset_timer();
+                // the segment may carry data as well, go on
+            }
+            TcpState::Closed | TcpState::Listen => return,
+            _ => {}
+        }
+
+        // acknowledgement
+        if seg.flags.contains(TcpFlags::ACK)
+            && seq_lt(inner.snd_una, seg.ack)
+            && seq_le(seg.ack, inner.snd_nxt)
+        {
+            let mut acked = seg.ack.wrapping_sub(inner.snd_una) as usize;
+            let fin_acked = inner.fin_sent && seg.ack == inner.snd_nxt;
+            if fin_acked {
+                acked -= 1;
+            }
+            inner.tx_buf.drain(..acked.min(inner.tx_buf.len()));
+            inner.snd_una = seg.ack;
+            inner.reset_timer();
+            if fin_acked {
+                match inner.state {
+                    TcpState::FinWait1 => {
+                        inner.state = TcpState::FinWait2;
+                        if inner.orphan {
+                            inner.set_timer(FIN_TIMEOUT);
+                        }
+                    }
+                    TcpState::Closing => {
+                        inner.state = TcpState::TimeWait;
+                        inner.set_timer(TIME_WAIT_LEN);
+                    }
+                    TcpState::LastAck => {
+                        self.close_connection(inner);
+                        return;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        if seg.flags.contains(TcpFlags::ACK) {
+            inner.snd_wnd = seg.window;
+        }
+
+        // data
+        let receiving = matches!(
+            inner.state,
+            TcpState::Established | TcpState::FinWait1 | TcpState::FinWait2
+        );
+        if receiving {
+            if inner.orphan && !seg.payload.is_empty() {
+                // nobody will read the data: abort the connection
+                inner.send_rst();
+                self.close_connection(inner);
+                return;
+            }
+            inner.receive(seg.seq, seg.payload);
+            if seg.flags.contains(TcpFlags::FIN) {
+                let fin = seg.seq.wrapping_add(seg.payload.len() as u32);
+                let wnd_end = inner.rcv_nxt.wrapping_add(inner.rcv_wnd() as u32);
+                if seq_le(inner.rcv_nxt, fin) && seq_le(fin, wnd_end) {
+                    inner.fin_seq = Some(fin);
+                }
+            }
+        }
+
+        // end of data
+        if !inner.peer_fin && inner.fin_seq == Some(inner.rcv_nxt) {
+            inner.rcv_nxt = inner.rcv_nxt.wrapping_add(1);
+            inner.peer_fin = true;
+            match inner.state {
+                TcpState::Established => inner.state = TcpState::CloseWait,
+                TcpState::FinWait1 => inner.state = TcpState::Closing,
+                TcpState::FinWait2 => {
+                    inner.state = TcpState::TimeWait;
+                    inner.set_timer(TIME_WAIT_LEN);
+                }
+                _ => {}
+            }
+        } else if inner.state == TcpState::TimeWait && seg.flags.contains(TcpFlags::FIN) {
+            // our ACK of the FIN was lost, wait again after acknowledging it
+            inner.set_timer(TIME_WAIT_LEN);
+        }
+
+        // acknowledge anything occupying sequence space, even duplicates
+        if seg.seq_len() > 0 {
+            inner.send_ack();
+        }
+        inner.output();
+        inner.update_timer();
+    }
+
+    /// Move a connection which left SYN-RECEIVED out of the SYN queue of its
+    /// listener, into the accept queue if it is established.
+    ///
+    /// The connection must not be locked: the listener is locked first.
+    fn leave_syn_queue(&self) {
+        let (listener, remote, established) = {
+            let mut inner = self.inner.lock();
+            if inner.state == TcpState::SynReceived {
+                return;
+            }
+            let listener = match mem::replace(&mut inner.listener, Weak::new()).upgrade() {
+                Some(listener) => listener,
+                None => return,
+            };
+            (
+                listener,
+                inner.remote.unwrap(),
+                inner.state != TcpState::Closed,
+            )
+        };
+        let mut listener_inner = listener.inner.lock();
+        let me = self.self_ref.upgrade().unwrap();
+        let len = listener_inner.syn_queue.len();
+        listener_inner.syn_queue.retain(|s| !Arc::ptr_eq(s, &me));
+        if listener_inner.syn_queue.len() < len && established {
+            listener_inner.accept_queue.push_back((me, remote));
+            listener.update_events(&listener_inner);
+        }
+    }
+
+    /// Handle a SYN to this listening socket.
+    fn process_syn(&self, seg: &Segment) {
+        let mut inner = self.inner.lock();
+        if inner.state != TcpState::Listen {
+            return;
+        }
+        if inner.syn_queue.len() + inner.accept_queue.len() > inner.backlog {
+            debug!("tcp: backlog of {:?} is full", inner.local);
+            return;
+        }
+        let child = Tcb::new(self.family, false);
+        let iss = new_iss();
+        {
+            let mut child_inner = child.inner.lock();
+            child_inner.state = TcpState::SynReceived;
+            child_inner.local = Some(seg.dst);
+            child_inner.remote = Some(seg.src);
+            child_inner.options = inner.options.clone();
+            child_inner.listener = self.self_ref.clone();
+            child_inner.rcv_nxt = seg.seq.wrapping_add(1);
+            child_inner.snd_una = iss;
+            child_inner.snd_nxt = iss.wrapping_add(1);
+            child_inner.snd_wnd = seg.window;
+            TCP_TABLE
+                .lock()
+                .connections
+                .insert((seg.dst, seg.src), child.clone());
+            child_inner.send_syn(true);
+            child_inner.update_timer();
+        }
+        inner.syn_queue.push(child);
+    }
+
+    /// The socket is closed: reset the connection, or let it finish on its own.
+    fn close(&self) {
+        let mut inner = self.inner.lock();
+        inner.orphan = true;
+        let mut pending = Vec::new();
+        match inner.state {
+            TcpState::Listen => {
+                pending.extend(inner.syn_queue.drain(..));
+                pending.extend(inner.accept_queue.drain(..).map(|(tcb, _)| tcb));
+                inner.state = TcpState::Closed;
+            }
+            TcpState::Established | TcpState::CloseWait if !inner.rx_buf.is_empty() => {
+                // unread data is lost: abort the connection
+                inner.send_rst();
+                self.close_connection(&mut inner);
+            }
+            TcpState::Established | TcpState::CloseWait => {
+                inner.fin_queued = true;
+                inner.output();
+                inner.update_timer();
+            }
+            TcpState::SynSent => self.close_connection(&mut inner),
+            TcpState::FinWait2 => inner.set_timer(FIN_TIMEOUT),
+            _ => {}
+        }
+        if let Some(local) = inner.local {
+            let mut table = TCP_TABLE.lock();
+            if let Some(weak) = table.bound.get(&local.port) {
+                if Weak::ptr_eq(weak, &self.self_ref) {
+                    table.bound.remove(&local.port);
+                }
+            }
+        }
+        drop(inner);
+        // connections not accepted yet are reset
+        for tcb in pending {
+            tcb.abort();
+        }
+    }
+}
+
+/// Run the timers of all connections.
+///
+/// Timers run in a kernel task rather than from the timer interrupt, since
+/// they lock connections and send segments.
+async fn run_timers() {
+    loop {
+        TIMER_BUS.lock().clear(Event::READABLE);
+        let next = expire_timers(timer_now());
+        poll();
+        // without any timer, sleep until one is started
+        let next = next.unwrap_or_else(|| Duration::from_secs(u64::max_value()));
+        select_biased! {
+            _ = sleep_until(next).fuse() => {}
+            _ = wait_for_event(TIMER_BUS.clone(), Event::READABLE).fuse() => {}
+        }
+    }
+}
+
+/// Run the expired timers, returns the earliest deadline left.
+fn expire_timers(now: Duration) -> Option<Duration> {
+    let connections: Vec<Arc<Tcb>> = TCP_TABLE.lock().connections.values().cloned().collect();
+    connections.iter().filter_map(|tcb| tcb.on_timer(now)).min()
+}
+
+/// Let the timer task see a new deadline, starting the task at first.
+fn wake_timer_task() {
+    if !TIMER_TASK_STARTED.swap(true, Ordering::Relaxed) {
+        // the task does not access user memory, any page table will do
+        let vmtoken = PageTable::new().table_phys();
+        Thread::spawn(Box::pin(run_timers()), vmtoken);
+    }
+    TIMER_BUS.lock().set(Event::READABLE);
+}
+
+/// Handle a received TCP packet.
+pub(super) fn input(packet: &IpPacket) {
+    let seg = match Segment::parse(packet) {
+        Some(seg) => seg,
+        None => return,
+    };
+    let (connection, listener) = {
+        let table = TCP_TABLE.lock();
+        let connection = table.connections.get(&(seg.dst, seg.src)).cloned();
+        let listener = table.bound.get(&seg.dst.port).and_then(Weak::upgrade);
+        (connection, listener)
+    };
+    if let Some(tcb) = connection {
+        tcb.process(&seg);
+        return;
+    }
+    if let Some(listener) = listener {
+        let listening = {
+            let inner = listener.inner.lock();
+            inner.state == TcpState::Listen
+                && inner.local.map_or(false, |l| l.addr.accepts(&seg.dst.addr))
+        };
+        if listening && seg.flags.contains(TcpFlags::SYN) && !seg.flags.contains(TcpFlags::ACK)
+        {
+            listener.process_syn(&seg);
+            return;
+        }
+    }
+    // pure ACKs and FINs to a closed socket are ignored
+    if seg.flags.contains(TcpFlags::SYN) || !seg.payload.is_empty() {
+        send_reset(&seg);
+    }
+}
+
+impl TcpSocket {
+    /// Create a new TCP socket.
+    pub fn new(family: AddressFamily, nonblock: bool) -> Arc<Self> {
+        Self::with_tcb(Tcb::new(family, nonblock))
+    }
+
+    fn with_tcb(tcb: Arc<Tcb>) -> Arc<Self> {
+        Arc::new(TcpSocket {
+            base: KObjectBase::new(),
+            tcb,
+        })
+    }
+}
+
+impl Drop for TcpSocket {
+    fn drop(&mut self) {
+        self.tcb.close();
+        poll();
+    }
+}
+
+#[async_trait]
+impl Socket for TcpSocket {
+    fn bind(&self, addr: SockAddr) -> LxResult {
+        let mut local = addr.inet()?;
+        self.tcb.check_family(&local)?;
+        if !local.addr.is_unspecified() && !iface::is_local_addr(&local.addr) {
+            return Err(LxError::EADDRNOTAVAIL);
+        }
+        let mut inner = self.tcb.inner.lock();
+        if inner.local.is_some() {
+            return Err(LxError::EINVAL);
+        }
+        let mut table = TCP_TABLE.lock();
+        let table = &mut *table;
+        if local.port == 0 {
+            let (bound, connections) = (&table.bound, &table.connections);
+            local.port = alloc_ephemeral_port(&mut table.last_ephemeral, |port| {
+                bound.get(&port).and_then(Weak::upgrade).is_some()
+                    || connections.keys().any(|(l, _)| l.port == port)
+            })
+            .ok_or(LxError::EADDRINUSE)?;
+        } else if table.port_in_use(local.port, inner.options.reuse_addr) {
+            return Err(LxError::EADDRINUSE);
+        }
+        table.bound.insert(local.port, self.tcb.self_ref.clone());
+        inner.local = Some(local);
+        Ok(())
+    }
+
+    fn listen(&self, backlog: usize) -> LxResult {
+        if self.tcb.inner.lock().local.is_none() {
+            // listen on an ephemeral port
+            self.bind(SockAddr::Inet(self.tcb.family.unspecified_endpoint()))?;
+        }
+        let mut inner = self.tcb.inner.lock();
+        match inner.state {
+            TcpState::Closed | TcpState::Listen => {
+                inner.state = TcpState::Listen;
+                inner.backlog = backlog.min(SOMAXCONN);
+                Ok(())
+            }
+            _ => Err(LxError::EINVAL),
+        }
+    }
+
+    async fn accept(&self) -> LxResult<(Arc<dyn FileLike>, SockAddr)> {
+        loop {
+            let mut inner = self.tcb.inner.lock();
+            if inner.state != TcpState::Listen {
+                return Err(LxError::EINVAL);
+            }
+            if let Some((tcb, remote)) = inner.accept_queue.pop_front() {
+                self.tcb.update_events(&inner);
+                let socket: Arc<dyn FileLike> = TcpSocket::with_tcb(tcb);
+                return Ok((socket, SockAddr::Inet(remote)));
+            }
+            if inner.nonblock {
+                return Err(LxError::EAGAIN);
+            }
+            drop(inner);
+            wait_for_event(self.tcb.eventbus.clone(), Event::READABLE).await;
+        }
+    }
+
+    async fn connect(&self, addr: SockAddr) -> LxResult {
+        let mut remote = addr.inet()?;
+        self.tcb.check_family(&remote)?;
+        if remote.addr.is_unspecified() {
+            remote.addr = remote.addr.loopback_like();
+        }
+        {
+            let inner = self.tcb.inner.lock();
+            match inner.state {
+                TcpState::Closed => {}
+                TcpState::SynSent => return Err(LxError::EALREADY),
+                TcpState::Listen => return Err(LxError::EINVAL),
+                _ => return Err(LxError::EISCONN),
+            }
+        }
+        if self.tcb.inner.lock().local.is_none() {
+            self.bind(SockAddr::Inet(self.tcb.family.unspecified_endpoint()))?;
+        }
+        let nonblock = {
+            let mut inner = self.tcb.inner.lock();
+            let mut local = inner.local.unwrap();
+            if local.addr.is_unspecified() {
+                local.addr = iface::source_addr(&remote.addr)?;
+            }
+            let mut table = TCP_TABLE.lock();
+            if table.connections.contains_key(&(local, remote)) {
+                return Err(LxError::EADDRINUSE);
+            }
+            table.connections.insert((local, remote), self.tcb.clone());
+            // the port now belongs to the connection
+            if let Some(weak) = table.bound.get(&local.port) {
+                if Weak::ptr_eq(weak, &self.tcb.self_ref) {
+                    table.bound.remove(&local.port);
+                }
+            }
+            drop(table);
+            let iss = new_iss();
+            inner.local = Some(local);
+            inner.remote = Some(remote);
+            inner.snd_una = iss;
+            inner.snd_nxt = iss.wrapping_add(1);
+            inner.state = TcpState::SynSent;
+            inner.send_syn(false);
+            inner.update_timer();
+            inner.nonblock
+        };
+        poll();
+        loop {
+            let mut inner = self.tcb.inner.lock();
+            match inner.state {
+                TcpState::SynSent if nonblock => return Err(LxError::EINPROGRESS),
+                TcpState::SynSent => {}
+                TcpState::Closed => return Err(inner.error.take().unwrap_or(LxError::ECONNREFUSED)),
+                _ => return Ok(()),
+            }
+            drop(inner);
+            wait_for_event(self.tcb.eventbus.clone(), Event::WRITABLE | Event::CLOSED).await;
+        }
+    }
+
+    async fn send(
+        &self,
+        data: &[u8],
+        _addr: Option<SockAddr>,
+        _rights: Vec<Arc<dyn FileLike>>,
+        flags: MsgFlags,
+    ) -> LxResult<usize> {
+        let mut sent = 0;
+        loop {
+            {
+                let mut inner = self.tcb.inner.lock();
+                if let Some(err) = inner.error.take() {
+                    return Err(err);
+                }
+                if !inner.can_send() {
+                    return match inner.state {
+                        TcpState::Listen | TcpState::SynSent => Err(LxError::ENOTCONN),
+                        TcpState::Closed if inner.remote.is_none() => Err(LxError::ENOTCONN),
+                        _ => Err(LxError::EPIPE),
+                    };
+                }
+                let len = (TX_BUF_SIZE - inner.tx_buf.len()).min(data.len() - sent);
+                inner.tx_buf.extend(&data[sent..sent + len]);
+                sent += len;
+                inner.output();
+                inner.update_timer();
+                self.tcb.update_events(&inner);
+            }
+            poll();
+            if sent == data.len() {
+                return Ok(sent);
+            }
+            if self.tcb.inner.lock().nonblock || flags.contains(MsgFlags::DONTWAIT) {
+                return if sent > 0 { Ok(sent) } else { Err(LxError::EAGAIN) };
+            }
+            wait_for_event(
+                self.tcb.eventbus.clone(),
+                Event::WRITABLE | Event::ERROR | Event::CLOSED,
+            )
+            .await;
+        }
+    }
+
+    async fn recv(&self, buf: &mut [u8], flags: MsgFlags) -> LxResult<RecvMsg> {
+        loop {
+            {
+                let mut inner = self.tcb.inner.lock();
+                if !inner.rx_buf.is_empty() {
+                    let len = buf.len().min(inner.rx_buf.len());
+                    for (dst, src) in buf.iter_mut().zip(inner.rx_buf.iter()) {
+                        *dst = *src;
+                    }
+                    if !flags.contains(MsgFlags::PEEK) {
+                        let window_was_small = inner.rcv_wnd() < MSS;
+                        inner.rx_buf.drain(..len);
+                        if window_was_small && inner.state != TcpState::Closed {
+                            // window update
+                            inner.send_ack();
+                        }
+                        self.tcb.update_events(&inner);
+                    }
+                    drop(inner);
+                    poll();
+                    return Ok(RecvMsg {
+                        len,
+                        ..Default::default()
+                    });
+                }
+                if inner.peer_fin || inner.read_shut {
+                    return Ok(RecvMsg::default());
+                }
+                if let Some(err) = inner.error.take() {
+                    return Err(err);
+                }
+                match inner.state {
+                    TcpState::Closed | TcpState::Listen | TcpState::SynSent => {
+                        return Err(LxError::ENOTCONN)
+                    }
+                    _ => {}
+                }
+                if inner.nonblock || flags.contains(MsgFlags::DONTWAIT) {
+                    return Err(LxError::EAGAIN);
+                }
+            }
+            wait_for_event(
+                self.tcb.eventbus.clone(),
+                Event::READABLE | Event::ERROR | Event::CLOSED,
+            )
+            .await;
+        }
+    }
+
+    fn shutdown(&self, how: Shutdown) -> LxResult {
+        {
+            let mut inner = self.tcb.inner.lock();
+            match inner.state {
+                TcpState::Closed | TcpState::Listen | TcpState::SynSent => {
+                    return Err(LxError::ENOTCONN)
+                }
+                _ => {}
+            }
+            if how.read() {
+                // pending and future data is discarded
+                inner.rx_buf.clear();
+                inner.assembler.clear();
+                inner.read_shut = true;
+            }
+            if how.write() {
+                inner.fin_queued = true;
+                inner.output();
+                inner.update_timer();
+            }
+            self.tcb.update_events(&inner);
+        }
+        poll();
+        Ok(())
+    }
+
+    fn local_addr(&self) -> LxResult<SockAddr> {
+        let inner = self.tcb.inner.lock();
+        Ok(SockAddr::Inet(
+            inner
+                .local
+                .unwrap_or_else(|| self.tcb.family.unspecified_endpoint()),
+        ))
+    }
+
+    fn peer_addr(&self) -> LxResult<SockAddr> {
+        let inner = self.tcb.inner.lock();
+        match inner.state {
+            TcpState::Closed | TcpState::Listen | TcpState::SynSent => Err(LxError::ENOTCONN),
+            _ => Ok(SockAddr::Inet(inner.remote.unwrap())),
+        }
+    }
+
+    fn setsockopt(&self, level: usize, opt: usize, data: &[u8]) -> LxResult {
+        self.tcb.inner.lock().options.set(level, opt, data)
+    }
+
+    fn getsockopt(&self, level: usize, opt: usize) -> LxResult<Vec<u8>> {
+        let mut inner = self.tcb.inner.lock();
+        let value: u32 = match (level, opt) {
+            (SOL_SOCKET, sockopt::SO_TYPE) => SocketType::Stream as u32,
+            (SOL_SOCKET, sockopt::SO_ERROR) => inner.error.take().map_or(0, |e| e as u32),
+            (SOL_SOCKET, sockopt::SO_ACCEPTCONN) => (inner.state == TcpState::Listen) as u32,
+            _ => return inner.options.get(level, opt),
+        };
+        Ok(Vec::from(value.to_ne_bytes()))
+    }
+}
+
+#[async_trait]
+impl FileLike for TcpSocket {
+    async fn read(&self, buf: &mut [u8]) -> LxResult<usize> {
+        Ok(self.recv(buf, MsgFlags::empty()).await?.len)
+    }
+
+    fn write(&self, buf: &[u8]) -> LxResult<usize> {
+        // `write(2)` goes through `send`, this is for `splice` and `sendfile`:
+        // queue what fits
+        let len = {
+            let mut inner = self.tcb.inner.lock();
+            if let Some(err) = inner.error.take() {
+                return Err(err);
+            }
+            if !inner.can_send() {
+                return Err(LxError::EPIPE);
+            }
+            let len = (TX_BUF_SIZE - inner.tx_buf.len()).min(buf.len());
+            if len == 0 && !buf.is_empty() {
+                return Err(LxError::EAGAIN);
+            }
+            inner.tx_buf.extend(&buf[..len]);
+            inner.output();
+            inner.update_timer();
+            self.tcb.update_events(&inner);
+            len
+        };
+        poll();
+        Ok(len)
+    }
+
+    async fn read_at(&self, _offset: u64, _buf: &mut [u8]) -> LxResult<usize> {
+        Err(LxError::ESPIPE)
+    }
+
+    fn write_at(&self, _offset: u64, _buf: &[u8]) -> LxResult<usize> {
+        Err(LxError::ESPIPE)
+    }
+
+    fn poll(&self) -> LxResult<PollStatus> {
+        let inner = self.tcb.inner.lock();
+        let status = match inner.state {
+            TcpState::Listen => PollStatus {
+                read: !inner.accept_queue.is_empty(),
+                write: false,
+                error: false,
+            },
+            _ => PollStatus {
+                read: inner.can_recv(),
+                write: inner.can_send() && inner.tx_buf.len() < TX_BUF_SIZE,
+                error: inner.error.is_some(),
+            },
+        };
+        Ok(status)
+    }
+
+    async fn async_poll(&self) -> LxResult<PollStatus> {
+        loop {
+            let status = self.poll()?;
+            if status.read || status.write || status.error {
+                return Ok(status);
+            }
+            wait_for_event(
+                self.tcb.eventbus.clone(),
+                Event::READABLE | Event::WRITABLE | Event::ERROR,
+            )
+            .await;
+        }
+    }
+
+    fn ioctl(&self, request: usize, _arg1: usize, _arg2: usize, _arg3: usize) -> LxResult<usize> {
+        warn!("ioctl: unsupported request {:#x} on tcp socket", request);
+        Err(LxError::ENOTTY)
+    }
+
+    fn fcntl(&self, cmd: usize, arg: usize) -> LxResult<usize> {
+        let mut inner = self.tcb.inner.lock();
+        fcntl_nonblock(&mut inner.nonblock, cmd, arg)
+    }
+
+    fn as_socket(&self) -> Option<&dyn Socket> {
+        Some(self)
+    }
+}
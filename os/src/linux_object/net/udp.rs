@@ -0,0 +1,380 @@
+//! UDP sockets
+
+use super::iface::{self, poll};
+use super::ip::*;
+use super::*;
+use crate::impl_kobject;
+use crate::linux_object::fs::vfs::PollStatus;
+use crate::linux_object::sync::{wait_for_event, Event, EventBus};
+use crate::zircon_object::object::*;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::sync::Weak;
+use lazy_static::lazy_static;
+use spin::Mutex;
+
+/// Max number of datagrams queued on a socket
+const RX_QUEUE_MAX: usize = 64;
+/// Size of the UDP header
+const HEADER_LEN: usize = 8;
+
+lazy_static! {
+    /// Bound sockets by port
+    static ref UDP_PORTS: Mutex<UdpPorts> = Mutex::new(UdpPorts::default());
+}
+
+#[derive(Default)]
+struct UdpPorts {
+    bound: BTreeMap<u16, Weak<UdpSocket>>,
+    last_ephemeral: u16,
+}
+
+/// A UDP socket
+pub struct UdpSocket {
+    base: KObjectBase,
+    self_ref: Weak<UdpSocket>,
+    /// `AF_INET` or `AF_INET6`
+    family: AddressFamily,
+    eventbus: Arc<Mutex<EventBus>>,
+    inner: Mutex<UdpInner>,
+}
+
+impl_kobject!(UdpSocket);
+
+#[derive(Default)]
+struct UdpInner {
+    local: Option<IpEndpoint>,
+    remote: Option<IpEndpoint>,
+    nonblock: bool,
+    rx: VecDeque<(IpEndpoint, Vec<u8>)>,
+    shutdown_read: bool,
+    shutdown_write: bool,
+    options: InetOptions,
+}
+
+impl UdpSocket {
+    /// Create a new UDP socket.
+    #[allow(unsafe_code)]
+    pub fn new(family: AddressFamily, nonblock: bool) -> Arc<Self> {
+        let mut socket = Arc::new(UdpSocket {
+            base: KObjectBase::new(),
+            self_ref: Weak::default(),
+            family,
+            eventbus: EventBus::new(),
+            inner: Mutex::new(UdpInner {
+                nonblock,
+                ..Default::default()
+            }),
+        });
+        let self_ref = Arc::downgrade(&socket);
+        // no other reference of `socket`
+        unsafe {
+            Arc::get_mut_unchecked(&mut socket).self_ref = self_ref;
+        }
+        socket
+    }
+
+    /// Bind to `local`, allocating an ephemeral port if the port is 0.
+    fn bind_endpoint(&self, inner: &mut UdpInner, mut local: IpEndpoint) -> LxResult {
+        if inner.local.is_some() {
+            return Err(LxError::EINVAL);
+        }
+        if !local.addr.is_unspecified() && !iface::is_local_addr(&local.addr) {
+            return Err(LxError::EADDRNOTAVAIL);
+        }
+        let mut ports = UDP_PORTS.lock();
+        let UdpPorts {
+            bound,
+            last_ephemeral,
+        } = &mut *ports;
+        if local.port == 0 {
+            local.port = alloc_ephemeral_port(last_ephemeral, |port| {
+                bound.get(&port).and_then(Weak::upgrade).is_some()
+            })
+            .ok_or(LxError::EADDRINUSE)?;
+        } else if bound.get(&local.port).and_then(Weak::upgrade).is_some() {
+            return Err(LxError::EADDRINUSE);
+        }
+        bound.insert(local.port, self.self_ref.clone());
+        inner.local = Some(local);
+        Ok(())
+    }
+
+    /// Make sure the socket has a local port before sending to `remote`.
+    fn auto_bind(&self, inner: &mut UdpInner, remote: &IpEndpoint) -> LxResult<IpEndpoint> {
+        if inner.local.is_none() {
+            let unspecified = IpEndpoint::new(remote.addr.unspecified_like(), 0);
+            self.bind_endpoint(inner, unspecified)?;
+        }
+        Ok(inner.local.unwrap())
+    }
+
+    fn check_family(&self, endpoint: &IpEndpoint) -> LxResult {
+        match (self.family, endpoint.addr.is_v4()) {
+            (AddressFamily::Inet, true) | (AddressFamily::Inet6, false) => Ok(()),
+            _ => Err(LxError::EAFNOSUPPORT),
+        }
+    }
+
+    fn send_to(&self, data: &[u8], remote: Option<IpEndpoint>) -> LxResult<usize> {
+        let (src, dst) = {
+            let mut inner = self.inner.lock();
+            if inner.shutdown_write {
+                return Err(LxError::EPIPE);
+            }
+            let mut remote = match remote.or(inner.remote) {
+                Some(remote) => remote,
+                None => return Err(LxError::EDESTADDRREQ),
+            };
+            self.check_family(&remote)?;
+            if remote.addr.is_unspecified() {
+                remote.addr = remote.addr.loopback_like();
+            }
+            let local = self.auto_bind(&mut inner, &remote)?;
+            let src = if local.addr.is_unspecified() {
+                iface::source_addr(&remote.addr)?
+            } else {
+                local.addr
+            };
+            (IpEndpoint::new(src, local.port), remote)
+        };
+        if data.len() + HEADER_LEN > u16::max_value() as usize {
+            return Err(LxError::EMSGSIZE);
+        }
+        let mut segment = Vec::with_capacity(HEADER_LEN + data.len());
+        segment.extend(&src.port.to_be_bytes());
+        segment.extend(&dst.port.to_be_bytes());
+        segment.extend(&((HEADER_LEN + data.len()) as u16).to_be_bytes());
+        segment.extend(&[0, 0]);
+        segment.extend(data);
+        let sum = match transport_checksum(&src.addr, &dst.addr, IPPROTO_UDP, &segment) {
+            0 => 0xffff,
+            sum => sum,
+        };
+        segment[6..8].copy_from_slice(&sum.to_be_bytes());
+        iface::send_ip(src.addr, dst.addr, IPPROTO_UDP, &segment)?;
+        poll();
+        Ok(data.len())
+    }
+
+    /// Handle a received datagram.
+    fn deliver(&self, from: IpEndpoint, to: &IpAddress, data: &[u8]) {
+        let mut inner = self.inner.lock();
+        let accepted = match (inner.local, inner.remote) {
+            (Some(local), _) if !local.addr.accepts(to) => false,
+            (_, Some(remote)) if remote != from => false,
+            _ => !inner.shutdown_read && inner.rx.len() < RX_QUEUE_MAX,
+        };
+        if !accepted {
+            debug!("udp: drop datagram from {:?}", from);
+            return;
+        }
+        inner.rx.push_back((from, data.to_vec()));
+        self.eventbus.lock().set(Event::READABLE);
+    }
+}
+
+/// Handle a received UDP packet.
+pub(super) fn input(packet: &IpPacket) {
+    let seg = packet.payload;
+    if seg.len() < HEADER_LEN {
+        return;
+    }
+    let src_port = u16::from_be_bytes([seg[0], seg[1]]);
+    let dst_port = u16::from_be_bytes([seg[2], seg[3]]);
+    let len = u16::from_be_bytes([seg[4], seg[5]]) as usize;
+    let sum = u16::from_be_bytes([seg[6], seg[7]]);
+    if len < HEADER_LEN || len > seg.len() {
+        return;
+    }
+    let seg = &seg[..len];
+    if sum != 0 && transport_checksum(&packet.src, &packet.dst, IPPROTO_UDP, seg) != 0 {
+        debug!("udp: bad checksum");
+        return;
+    }
+    let socket = UDP_PORTS
+        .lock()
+        .bound
+        .get(&dst_port)
+        .and_then(Weak::upgrade);
+    match socket {
+        Some(socket) => socket.deliver(
+            IpEndpoint::new(packet.src, src_port),
+            &packet.dst,
+            &seg[HEADER_LEN..],
+        ),
+        None => debug!("udp: no socket on port {}", dst_port),
+    }
+}
+
+impl Drop for UdpSocket {
+    fn drop(&mut self) {
+        if let Some(local) = self.inner.get_mut().local {
+            let mut ports = UDP_PORTS.lock();
+            if let Some(weak) = ports.bound.get(&local.port) {
+                if Weak::ptr_eq(weak, &self.self_ref) {
+                    ports.bound.remove(&local.port);
+                }
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Socket for UdpSocket {
+    fn bind(&self, addr: SockAddr) -> LxResult {
+        let local = addr.inet()?;
+        self.check_family(&local)?;
+        let mut inner = self.inner.lock();
+        self.bind_endpoint(&mut inner, local)
+    }
+
+    fn listen(&self, _backlog: usize) -> LxResult {
+        Err(LxError::EOPNOTSUPP)
+    }
+
+    async fn accept(&self) -> LxResult<(Arc<dyn FileLike>, SockAddr)> {
+        Err(LxError::EOPNOTSUPP)
+    }
+
+    async fn connect(&self, addr: SockAddr) -> LxResult {
+        let mut remote = addr.inet()?;
+        self.check_family(&remote)?;
+        if remote.addr.is_unspecified() {
+            remote.addr = remote.addr.loopback_like();
+        }
+        let mut inner = self.inner.lock();
+        self.auto_bind(&mut inner, &remote)?;
+        inner.remote = Some(remote);
+        Ok(())
+    }
+
+    async fn send(
+        &self,
+        data: &[u8],
+        addr: Option<SockAddr>,
+        _rights: Vec<Arc<dyn FileLike>>,
+        _flags: MsgFlags,
+    ) -> LxResult<usize> {
+        let remote = match addr {
+            Some(addr) => Some(addr.inet()?),
+            None => None,
+        };
+        self.send_to(data, remote)
+    }
+
+    async fn recv(&self, buf: &mut [u8], flags: MsgFlags) -> LxResult<RecvMsg> {
+        loop {
+            let mut inner = self.inner.lock();
+            if let Some((from, data)) = inner.rx.front() {
+                let len = buf.len().min(data.len());
+                buf[..len].copy_from_slice(&data[..len]);
+                let mut msg = RecvMsg {
+                    len,
+                    addr: Some(SockAddr::Inet(*from)),
+                    ..Default::default()
+                };
+                if len < data.len() {
+                    msg.flags |= MsgFlags::TRUNC;
+                }
+                if !flags.contains(MsgFlags::PEEK) {
+                    inner.rx.pop_front();
+                    if inner.rx.is_empty() {
+                        self.eventbus.lock().clear(Event::READABLE);
+                    }
+                }
+                return Ok(msg);
+            }
+            if inner.shutdown_read {
+                return Ok(RecvMsg::default());
+            }
+            if inner.nonblock || flags.contains(MsgFlags::DONTWAIT) {
+                return Err(LxError::EAGAIN);
+            }
+            drop(inner);
+            wait_for_event(self.eventbus.clone(), Event::READABLE).await;
+        }
+    }
+
+    fn shutdown(&self, how: Shutdown) -> LxResult {
+        let mut inner = self.inner.lock();
+        if inner.remote.is_none() {
+            return Err(LxError::ENOTCONN);
+        }
+        inner.shutdown_read |= how.read();
+        inner.shutdown_write |= how.write();
+        if how.read() {
+            self.eventbus.lock().set(Event::READABLE);
+        }
+        Ok(())
+    }
+
+    fn local_addr(&self) -> LxResult<SockAddr> {
+        let inner = self.inner.lock();
+        Ok(SockAddr::Inet(
+            inner.local.unwrap_or_else(|| self.family.unspecified_endpoint()),
+        ))
+    }
+
+    fn peer_addr(&self) -> LxResult<SockAddr> {
+        let remote = self.inner.lock().remote.ok_or(LxError::ENOTCONN)?;
+        Ok(SockAddr::Inet(remote))
+    }
+
+    fn setsockopt(&self, level: usize, opt: usize, data: &[u8]) -> LxResult {
+        self.inner.lock().options.set(level, opt, data)
+    }
+
+    fn getsockopt(&self, level: usize, opt: usize) -> LxResult<Vec<u8>> {
+        if (level, opt) == (SOL_SOCKET, sockopt::SO_TYPE) {
+            return Ok(Vec::from((SocketType::Datagram as u32).to_ne_bytes()));
+        }
+        self.inner.lock().options.get(level, opt)
+    }
+}
+
+#[async_trait]
+impl FileLike for UdpSocket {
+    async fn read(&self, buf: &mut [u8]) -> LxResult<usize> {
+        Ok(self.recv(buf, MsgFlags::empty()).await?.len)
+    }
+
+    fn write(&self, buf: &[u8]) -> LxResult<usize> {
+        self.send_to(buf, None)
+    }
+
+    async fn read_at(&self, _offset: u64, _buf: &mut [u8]) -> LxResult<usize> {
+        Err(LxError::ESPIPE)
+    }
+
+    fn write_at(&self, _offset: u64, _buf: &[u8]) -> LxResult<usize> {
+        Err(LxError::ESPIPE)
+    }
+
+    fn poll(&self) -> LxResult<PollStatus> {
+        let inner = self.inner.lock();
+        Ok(PollStatus {
+            read: !inner.rx.is_empty() || inner.shutdown_read,
+            write: !inner.shutdown_write,
+            error: false,
+        })
+    }
+
+    async fn async_poll(&self) -> LxResult<PollStatus> {
+        // a UDP socket is always writable
+        self.poll()
+    }
+
+    fn ioctl(&self, request: usize, _arg1: usize, _arg2: usize, _arg3: usize) -> LxResult<usize> {
+        warn!("ioctl: unsupported request {:#x} on udp socket", request);
+        Err(LxError::ENOTTY)
+    }
+
+    fn fcntl(&self, cmd: usize, arg: usize) -> LxResult<usize> {
+        let mut inner = self.inner.lock();
+        fcntl_nonblock(&mut inner.nonblock, cmd, arg)
+    }
+
+    fn as_socket(&self) -> Option<&dyn Socket> {
+        Some(self)
+    }
+}
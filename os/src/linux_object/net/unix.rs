@@ -0,0 +1,761 @@
+//! Unix domain sockets
+//!
+//! Connected stream and seqpacket sockets are built on a pair of zircon
+//! [`Socket`](crate::zircon_object::ipc::Socket) endpoints, which already provide
+//! buffering, datagram boundaries and shutdown. Rights passed with SCM_RIGHTS
+//! travel beside the byte stream in a per-direction queue.
+//!
+//! Datagram sockets keep their own receive queue so that one bound socket can
+//! receive from many unconnected senders.
+
+use super::*;
+use crate::impl_kobject;
+use crate::linux_object::fs::vfs::PollStatus;
+use crate::linux_object::sync::{wait_for_event, Event, EventBus};
+use crate::zircon_object::ipc::{Socket as ZxSocket, SocketFlags};
+use crate::zircon_object::object::*;
+use crate::zircon_object::ZxError;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::sync::Weak;
+use lazy_static::lazy_static;
+use spin::Mutex;
+
+lazy_static! {
+    /// Sockets bound to a name, both pathname and abstract.
+    static ref UNIX_NAMES: Mutex<BTreeMap<Name, Weak<UnixSocket>>> = Default::default();
+}
+
+/// The key of a socket inode, as given by `inode_key`
+pub type SocketInode = (usize, usize);
+
+/// A name in the table of bound sockets
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd)]
+enum Name {
+    /// A pathname, by its socket inode: the binding moves with the inode when
+    /// it is renamed, and can not be reached any more once it is unlinked.
+    Inode(SocketInode),
+    /// A name in the abstract namespace
+    Abstract(Vec<u8>),
+}
+
+impl Name {
+    /// Get the name of an abstract address. A pathname is only known by its
+    /// socket inode, which the caller looks up in the filesystem.
+    fn of(addr: UnixAddr) -> LxResult<Name> {
+        match addr {
+            UnixAddr::Abstract(name) => Ok(Name::Abstract(name)),
+            _ => Err(LxError::EINVAL),
+        }
+    }
+}
+
+/// Max number of datagrams queued on a socket
+const DGRAM_QUEUE_MAX: usize = 256;
+/// Buffer size reported by SO_SNDBUF and SO_RCVBUF, same as the zircon socket
+const SOCKET_BUF_SIZE: u32 = 128 * 2048;
+
+type Rights = Vec<Arc<dyn FileLike>>;
+type RightsQueue = Arc<Mutex<VecDeque<Rights>>>;
+
+/// A unix domain socket
+pub struct UnixSocket {
+    base: KObjectBase,
+    /// weak reference to ourselves, registered in the name table on bind
+    self_ref: Weak<UnixSocket>,
+    type_: SocketType,
+    /// wakes up `accept` and datagram `recv`
+    eventbus: Arc<Mutex<EventBus>>,
+    inner: Mutex<UnixSocketInner>,
+}
+
+impl_kobject!(UnixSocket);
+
+#[derive(Default)]
+struct UnixSocketInner {
+    addr: UnixAddr,
+    /// the name `addr` is registered by in the name table
+    name: Option<Name>,
+    peer_addr: UnixAddr,
+    nonblock: bool,
+    state: State,
+    /// received datagrams (DGRAM only)
+    datagrams: VecDeque<Datagram>,
+    /// default destination (DGRAM only)
+    dgram_peer: Option<Weak<UnixSocket>>,
+    /// shutdown state (DGRAM only)
+    shutdown_read: bool,
+    shutdown_write: bool,
+}
+
+enum State {
+    Unconnected,
+    Listening {
+        backlog: usize,
+        pending: VecDeque<Arc<UnixSocket>>,
+    },
+    Connected(Endpoint),
+}
+
+impl Default for State {
+    fn default() -> Self {
+        State::Unconnected
+    }
+}
+
+struct Datagram {
+    data: Vec<u8>,
+    from: UnixAddr,
+    rights: Rights,
+}
+
+/// One end of a connected stream or seqpacket socket
+#[derive(Clone)]
+struct Endpoint {
+    socket: Arc<ZxSocket>,
+    /// rights in flight towards this end
+    rx_rights: RightsQueue,
+    /// rights in flight towards the peer
+    tx_rights: RightsQueue,
+}
+
+impl Endpoint {
+    fn pair(type_: SocketType) -> (Endpoint, Endpoint) {
+        let flags = match type_ {
+            SocketType::SeqPacket => SocketFlags::DATAGRAM,
+            _ => SocketFlags::empty(),
+        };
+        let (socket0, socket1) = ZxSocket::create(flags.bits()).unwrap();
+        let rights0 = RightsQueue::default();
+        let rights1 = RightsQueue::default();
+        (
+            Endpoint {
+                socket: socket0,
+                rx_rights: rights0.clone(),
+                tx_rights: rights1.clone(),
+            },
+            Endpoint {
+                socket: socket1,
+                rx_rights: rights1,
+                tx_rights: rights0,
+            },
+        )
+    }
+
+    /// whether a read would not block: data arrived or it will never arrive
+    fn readable(&self) -> bool {
+        self.socket.signal().intersects(
+            Signal::READABLE | Signal::PEER_CLOSED | Signal::SOCKET_PEER_WRITE_DISABLED,
+        )
+    }
+
+    fn writable(&self) -> bool {
+        self.socket.signal().contains(Signal::WRITABLE)
+    }
+}
+
+impl UnixSocket {
+    /// Create a new unconnected socket.
+    #[allow(unsafe_code)]
+    pub fn new(type_: SocketType, nonblock: bool) -> Arc<Self> {
+        let mut socket = Arc::new(UnixSocket {
+            base: KObjectBase::new(),
+            self_ref: Weak::default(),
+            type_,
+            eventbus: EventBus::new(),
+            inner: Mutex::new(UnixSocketInner {
+                nonblock,
+                ..Default::default()
+            }),
+        });
+        let self_ref = Arc::downgrade(&socket);
+        // no other reference of `socket`
+        unsafe {
+            Arc::get_mut_unchecked(&mut socket).self_ref = self_ref;
+        }
+        socket
+    }
+
+    /// Create a pair of connected sockets, as `socketpair()` does.
+    pub fn new_pair(type_: SocketType, nonblock: bool) -> (Arc<Self>, Arc<Self>) {
+        let socket0 = Self::new(type_, nonblock);
+        let socket1 = Self::new(type_, nonblock);
+        if type_ == SocketType::Datagram {
+            socket0.inner.lock().dgram_peer = Some(Arc::downgrade(&socket1));
+            socket1.inner.lock().dgram_peer = Some(Arc::downgrade(&socket0));
+        } else {
+            let (end0, end1) = Endpoint::pair(type_);
+            socket0.inner.lock().state = State::Connected(end0);
+            socket1.inner.lock().state = State::Connected(end1);
+        }
+        (socket0, socket1)
+    }
+
+    /// Get the type of this socket.
+    pub fn socket_type(&self) -> SocketType {
+        self.type_
+    }
+
+    /// Bind to the pathname `addr`, whose socket inode was just made for it.
+    pub fn bind_inode(&self, addr: SockAddr, inode: SocketInode) -> LxResult {
+        self.bind_name(addr.unix()?, Name::Inode(inode))
+    }
+
+    /// Connect to the socket bound to the socket inode of the pathname `addr`.
+    pub async fn connect_inode(&self, addr: SockAddr, inode: SocketInode) -> LxResult {
+        self.connect_name(addr.unix()?, Name::Inode(inode)).await
+    }
+
+    /// Send a datagram to the socket bound to the socket inode of a pathname.
+    pub fn send_to_inode(
+        &self,
+        data: &[u8],
+        inode: SocketInode,
+        rights: Rights,
+    ) -> LxResult<usize> {
+        match self.type_ {
+            SocketType::Datagram => self.send_datagram(data, Some(Name::Inode(inode)), rights),
+            _ => Err(LxError::EISCONN),
+        }
+    }
+
+    fn bind_name(&self, addr: UnixAddr, name: Name) -> LxResult {
+        let mut inner = self.inner.lock();
+        if inner.addr != UnixAddr::Unnamed {
+            return Err(LxError::EINVAL);
+        }
+        let mut names = UNIX_NAMES.lock();
+        // a socket inode was just made: another one with the same key is gone
+        if let Name::Abstract(_) = name {
+            if names.get(&name).and_then(Weak::upgrade).is_some() {
+                return Err(LxError::EADDRINUSE);
+            }
+        }
+        names.insert(name.clone(), self.self_ref.clone());
+        inner.addr = addr;
+        inner.name = Some(name);
+        Ok(())
+    }
+
+    async fn connect_name(&self, addr: UnixAddr, name: Name) -> LxResult {
+        let target = Self::lookup(&name)?;
+        if target.type_ != self.type_ {
+            return Err(LxError::EPROTOTYPE);
+        }
+        if self.type_ == SocketType::Datagram {
+            let mut inner = self.inner.lock();
+            inner.dgram_peer = Some(Arc::downgrade(&target));
+            inner.peer_addr = addr;
+            return Ok(());
+        }
+        if core::ptr::eq(&*target, self) {
+            // a stream socket connecting to itself is not listening
+            return Err(LxError::ECONNREFUSED);
+        }
+        let mut target = Some(target);
+        loop {
+            let target_ref = match target.take() {
+                Some(target) => target,
+                // the listener may have gone while waiting
+                None => Self::lookup(&name)?,
+            };
+            let mut inner = self.inner.lock();
+            match inner.state {
+                State::Unconnected => {}
+                State::Connected(_) => return Err(LxError::EISCONN),
+                State::Listening { .. } => return Err(LxError::EINVAL),
+            }
+            let mut target_guard = target_ref.inner.lock();
+            let target_inner = &mut *target_guard;
+            let target_addr = target_inner.addr.clone();
+            match &mut target_inner.state {
+                State::Listening { backlog, pending } if pending.len() > *backlog => {
+                    if inner.nonblock {
+                        return Err(LxError::EAGAIN);
+                    }
+                }
+                State::Listening { backlog, pending } => {
+                    let (end0, end1) = Endpoint::pair(self.type_);
+                    let server = Self::new(self.type_, false);
+                    {
+                        let mut server_inner = server.inner.lock();
+                        server_inner.addr = target_addr.clone();
+                        server_inner.peer_addr = inner.addr.clone();
+                        server_inner.state = State::Connected(end1);
+                    }
+                    pending.push_back(server);
+                    let full = pending.len() > *backlog;
+                    let mut eventbus = target_ref.eventbus.lock();
+                    eventbus.set(Event::READABLE);
+                    if full {
+                        eventbus.clear(Event::WRITABLE);
+                    }
+                    drop(eventbus);
+                    inner.state = State::Connected(end0);
+                    inner.peer_addr = target_addr;
+                    return Ok(());
+                }
+                _ => return Err(LxError::ECONNREFUSED),
+            }
+            // wait for `accept` to make room in the backlog, without keeping the listener alive
+            let eventbus = target_ref.eventbus.clone();
+            drop(target_guard);
+            drop(inner);
+            drop(target_ref);
+            wait_for_event(eventbus, Event::WRITABLE | Event::CLOSED).await;
+        }
+    }
+
+    /// Set or clear O_NONBLOCK.
+    pub fn set_nonblock(&self, nonblock: bool) {
+        self.inner.lock().nonblock = nonblock;
+    }
+
+    fn nonblock(&self, flags: MsgFlags) -> bool {
+        self.inner.lock().nonblock || flags.contains(MsgFlags::DONTWAIT)
+    }
+
+    fn endpoint(&self) -> LxResult<Endpoint> {
+        match &self.inner.lock().state {
+            State::Connected(end) => Ok(end.clone()),
+            _ => Err(LxError::ENOTCONN),
+        }
+    }
+
+    /// Find the socket bound to `name`.
+    fn lookup(name: &Name) -> LxResult<Arc<UnixSocket>> {
+        UNIX_NAMES
+            .lock()
+            .get(name)
+            .and_then(Weak::upgrade)
+            .ok_or(LxError::ECONNREFUSED)
+    }
+
+    /// Queue a datagram on this socket.
+    fn push_datagram(&self, datagram: Datagram) -> LxResult<usize> {
+        let mut inner = self.inner.lock();
+        if inner.shutdown_read {
+            return Err(LxError::ECONNREFUSED);
+        }
+        if inner.datagrams.len() >= DGRAM_QUEUE_MAX {
+            return Err(LxError::EAGAIN);
+        }
+        let len = datagram.data.len();
+        inner.datagrams.push_back(datagram);
+        drop(inner);
+        self.eventbus.lock().set(Event::READABLE);
+        Ok(len)
+    }
+
+    fn send_datagram(&self, data: &[u8], to: Option<Name>, rights: Rights) -> LxResult<usize> {
+        let (target, from) = {
+            let inner = self.inner.lock();
+            if inner.shutdown_write {
+                return Err(LxError::EPIPE);
+            }
+            let target = match to {
+                Some(name) => Self::lookup(&name)?,
+                None => inner
+                    .dgram_peer
+                    .as_ref()
+                    .ok_or(LxError::ENOTCONN)?
+                    .upgrade()
+                    .ok_or(LxError::ECONNREFUSED)?,
+            };
+            (target, inner.addr.clone())
+        };
+        target.push_datagram(Datagram {
+            data: data.to_vec(),
+            from,
+            rights,
+        })
+    }
+
+    async fn recv_datagram(&self, buf: &mut [u8], flags: MsgFlags) -> LxResult<RecvMsg> {
+        loop {
+            let mut inner = self.inner.lock();
+            if let Some(datagram) = inner.datagrams.front() {
+                let len = buf.len().min(datagram.data.len());
+                buf[..len].copy_from_slice(&datagram.data[..len]);
+                let mut msg = RecvMsg {
+                    len,
+                    addr: Some(SockAddr::Unix(datagram.from.clone())),
+                    ..Default::default()
+                };
+                if len < datagram.data.len() {
+                    msg.flags |= MsgFlags::TRUNC;
+                }
+                if !flags.contains(MsgFlags::PEEK) {
+                    msg.rights = inner.datagrams.pop_front().unwrap().rights;
+                    if inner.datagrams.is_empty() {
+                        self.eventbus.lock().clear(Event::READABLE);
+                    }
+                }
+                return Ok(msg);
+            }
+            if inner.shutdown_read {
+                return Ok(RecvMsg::default());
+            }
+            if inner.nonblock || flags.contains(MsgFlags::DONTWAIT) {
+                return Err(LxError::EAGAIN);
+            }
+            drop(inner);
+            wait_for_event(self.eventbus.clone(), Event::READABLE).await;
+        }
+    }
+
+    async fn send_stream(&self, data: &[u8], rights: Rights, flags: MsgFlags) -> LxResult<usize> {
+        let end = self.endpoint()?;
+        let nonblock = self.nonblock(flags);
+        let mut rights = Some(rights).filter(|r| !r.is_empty());
+        let mut sent = 0;
+        loop {
+            // rights are queued before their bytes, so the reader never misses them
+            let with_rights = match rights.take() {
+                Some(r) => {
+                    end.tx_rights.lock().push_back(r);
+                    true
+                }
+                None => false,
+            };
+            let result = end.socket.write(&data[sent..]);
+            if with_rights && result.is_err() {
+                rights = end.tx_rights.lock().pop_back();
+            }
+            match result {
+                Ok(len) => {
+                    sent += len;
+                    // seqpacket writes are never short
+                    if sent == data.len() || self.type_ == SocketType::SeqPacket {
+                        return Ok(sent);
+                    }
+                }
+                Err(ZxError::SHOULD_WAIT) if sent > 0 && nonblock => return Ok(sent),
+                Err(ZxError::SHOULD_WAIT) if nonblock => return Err(LxError::EAGAIN),
+                Err(ZxError::SHOULD_WAIT) => {}
+                Err(ZxError::BAD_STATE) | Err(ZxError::PEER_CLOSED) => {
+                    return Err(LxError::EPIPE)
+                }
+                Err(ZxError::OUT_OF_RANGE) => return Err(LxError::EMSGSIZE),
+                Err(e) => return Err(e.into()),
+            }
+            let object: Arc<dyn KernelObject> = end.socket.clone();
+            object
+                .wait_signal(Signal::WRITABLE | Signal::PEER_CLOSED)
+                .await;
+        }
+    }
+
+    async fn recv_stream(&self, buf: &mut [u8], flags: MsgFlags) -> LxResult<RecvMsg> {
+        let end = self.endpoint()?;
+        let nonblock = self.nonblock(flags);
+        let peek = flags.contains(MsgFlags::PEEK);
+        loop {
+            match end.socket.read(peek, buf) {
+                Ok(len) => {
+                    let mut msg = RecvMsg {
+                        len,
+                        ..Default::default()
+                    };
+                    if !peek {
+                        msg.rights = end.rx_rights.lock().pop_front().unwrap_or_default();
+                    }
+                    return Ok(msg);
+                }
+                // end of file
+                Err(ZxError::PEER_CLOSED) | Err(ZxError::BAD_STATE) => {
+                    return Ok(RecvMsg::default())
+                }
+                Err(ZxError::SHOULD_WAIT) => {
+                    if end
+                        .socket
+                        .signal()
+                        .contains(Signal::SOCKET_PEER_WRITE_DISABLED)
+                    {
+                        return Ok(RecvMsg::default());
+                    }
+                    if nonblock {
+                        return Err(LxError::EAGAIN);
+                    }
+                }
+                Err(e) => return Err(e.into()),
+            }
+            let object: Arc<dyn KernelObject> = end.socket.clone();
+            object
+                .wait_signal(
+                    Signal::READABLE | Signal::PEER_CLOSED | Signal::SOCKET_PEER_WRITE_DISABLED,
+                )
+                .await;
+        }
+    }
+}
+
+impl Drop for UnixSocket {
+    fn drop(&mut self) {
+        // fail connects waiting for room in our backlog
+        self.eventbus.lock().set(Event::CLOSED);
+        if let Some(name) = &self.inner.get_mut().name {
+            let mut names = UNIX_NAMES.lock();
+            if let Some(weak) = names.get(name) {
+                if Weak::ptr_eq(weak, &self.self_ref) {
+                    names.remove(name);
+                }
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Socket for UnixSocket {
+    fn bind(&self, addr: SockAddr) -> LxResult {
+        let addr = addr.unix()?;
+        let name = Name::of(addr.clone())?;
+        self.bind_name(addr, name)
+    }
+
+    fn listen(&self, backlog: usize) -> LxResult {
+        if self.type_ == SocketType::Datagram {
+            return Err(LxError::EOPNOTSUPP);
+        }
+        let mut guard = self.inner.lock();
+        let inner = &mut *guard;
+        if inner.addr == UnixAddr::Unnamed {
+            return Err(LxError::EINVAL);
+        }
+        let full = match &mut inner.state {
+            State::Unconnected => {
+                inner.state = State::Listening {
+                    backlog,
+                    pending: VecDeque::new(),
+                };
+                false
+            }
+            State::Listening {
+                backlog: old,
+                pending,
+            } => {
+                *old = backlog;
+                pending.len() > backlog
+            }
+            State::Connected(_) => return Err(LxError::EINVAL),
+        };
+        // WRITABLE: there is room in the backlog
+        let mut eventbus = self.eventbus.lock();
+        if full {
+            eventbus.clear(Event::WRITABLE);
+        } else {
+            eventbus.set(Event::WRITABLE);
+        }
+        Ok(())
+    }
+
+    async fn accept(&self) -> LxResult<(Arc<dyn FileLike>, SockAddr)> {
+        loop {
+            let mut guard = self.inner.lock();
+            let inner = &mut *guard;
+            match &mut inner.state {
+                State::Listening { pending, .. } => {
+                    if let Some(socket) = pending.pop_front() {
+                        let mut eventbus = self.eventbus.lock();
+                        if pending.is_empty() {
+                            eventbus.clear(Event::READABLE);
+                        }
+                        // wake up connects waiting for room in the backlog
+                        eventbus.set(Event::WRITABLE);
+                        drop(eventbus);
+                        let addr = SockAddr::Unix(socket.inner.lock().peer_addr.clone());
+                        let socket: Arc<dyn FileLike> = socket;
+                        return Ok((socket, addr));
+                    }
+                }
+                _ => return Err(LxError::EINVAL),
+            }
+            if inner.nonblock {
+                return Err(LxError::EAGAIN);
+            }
+            drop(guard);
+            wait_for_event(self.eventbus.clone(), Event::READABLE).await;
+        }
+    }
+
+    async fn connect(&self, addr: SockAddr) -> LxResult {
+        let addr = addr.unix()?;
+        let name = Name::of(addr.clone())?;
+        self.connect_name(addr, name).await
+    }
+
+    async fn send(
+        &self,
+        data: &[u8],
+        addr: Option<SockAddr>,
+        rights: Rights,
+        flags: MsgFlags,
+    ) -> LxResult<usize> {
+        match self.type_ {
+            SocketType::Datagram => {
+                let to = match addr {
+                    Some(addr) => Some(Name::of(addr.unix()?)?),
+                    None => None,
+                };
+                self.send_datagram(data, to, rights)
+            }
+            _ if addr.is_some() => Err(LxError::EISCONN),
+            _ => self.send_stream(data, rights, flags).await,
+        }
+    }
+
+    async fn recv(&self, buf: &mut [u8], flags: MsgFlags) -> LxResult<RecvMsg> {
+        match self.type_ {
+            SocketType::Datagram => self.recv_datagram(buf, flags).await,
+            _ => self.recv_stream(buf, flags).await,
+        }
+    }
+
+    fn shutdown(&self, how: Shutdown) -> LxResult {
+        let mut inner = self.inner.lock();
+        match &inner.state {
+            State::Connected(end) => {
+                end.socket.shutdown(how.read(), how.write())?;
+                Ok(())
+            }
+            _ if self.type_ == SocketType::Datagram => {
+                inner.shutdown_read |= how.read();
+                inner.shutdown_write |= how.write();
+                if how.read() {
+                    self.eventbus.lock().set(Event::READABLE);
+                }
+                Ok(())
+            }
+            _ => Err(LxError::ENOTCONN),
+        }
+    }
+
+    fn local_addr(&self) -> LxResult<SockAddr> {
+        Ok(SockAddr::Unix(self.inner.lock().addr.clone()))
+    }
+
+    fn peer_addr(&self) -> LxResult<SockAddr> {
+        let inner = self.inner.lock();
+        match (&inner.state, &inner.dgram_peer) {
+            (State::Connected(_), _) | (_, Some(_)) => {
+                Ok(SockAddr::Unix(inner.peer_addr.clone()))
+            }
+            _ => Err(LxError::ENOTCONN),
+        }
+    }
+
+    fn setsockopt(&self, level: usize, opt: usize, _data: &[u8]) -> LxResult {
+        match (level, opt) {
+            (SOL_SOCKET, sockopt::SO_SNDBUF) | (SOL_SOCKET, sockopt::SO_RCVBUF) => Ok(()),
+            _ => {
+                warn!("setsockopt: unsupported level={} opt={}", level, opt);
+                Err(LxError::ENOPROTOOPT)
+            }
+        }
+    }
+
+    fn getsockopt(&self, level: usize, opt: usize) -> LxResult<Vec<u8>> {
+        let value: u32 = match (level, opt) {
+            (SOL_SOCKET, sockopt::SO_TYPE) => self.type_ as u32,
+            (SOL_SOCKET, sockopt::SO_ERROR) => 0,
+            (SOL_SOCKET, sockopt::SO_SNDBUF) | (SOL_SOCKET, sockopt::SO_RCVBUF) => SOCKET_BUF_SIZE,
+            (SOL_SOCKET, sockopt::SO_ACCEPTCONN) => match self.inner.lock().state {
+                State::Listening { .. } => 1,
+                _ => 0,
+            },
+            _ => {
+                warn!("getsockopt: unsupported level={} opt={}", level, opt);
+                return Err(LxError::ENOPROTOOPT);
+            }
+        };
+        Ok(Vec::from(value.to_ne_bytes()))
+    }
+}
+
+#[async_trait]
+impl FileLike for UnixSocket {
+    async fn read(&self, buf: &mut [u8]) -> LxResult<usize> {
+        Ok(self.recv(buf, MsgFlags::empty()).await?.len)
+    }
+
+    fn write(&self, buf: &[u8]) -> LxResult<usize> {
+        // `write` is synchronous: fail with EAGAIN instead of blocking when full
+        match self.type_ {
+            SocketType::Datagram => self.send_datagram(buf, None, Vec::new()),
+            _ => match self.endpoint()?.socket.write(buf) {
+                Ok(len) => Ok(len),
+                Err(ZxError::SHOULD_WAIT) => Err(LxError::EAGAIN),
+                Err(ZxError::BAD_STATE) | Err(ZxError::PEER_CLOSED) => Err(LxError::EPIPE),
+                Err(ZxError::OUT_OF_RANGE) => Err(LxError::EMSGSIZE),
+                Err(e) => Err(e.into()),
+            },
+        }
+    }
+
+    async fn read_at(&self, _offset: u64, _buf: &mut [u8]) -> LxResult<usize> {
+        Err(LxError::ESPIPE)
+    }
+
+    fn write_at(&self, _offset: u64, _buf: &[u8]) -> LxResult<usize> {
+        Err(LxError::ESPIPE)
+    }
+
+    fn poll(&self) -> LxResult<PollStatus> {
+        let inner = self.inner.lock();
+        let status = match &inner.state {
+            State::Connected(end) => PollStatus {
+                read: end.readable(),
+                write: end.writable(),
+                error: false,
+            },
+            State::Listening { pending, .. } => PollStatus {
+                read: !pending.is_empty(),
+                write: false,
+                error: false,
+            },
+            State::Unconnected => PollStatus {
+                read: !inner.datagrams.is_empty() || inner.shutdown_read,
+                write: self.type_ == SocketType::Datagram,
+                error: false,
+            },
+        };
+        Ok(status)
+    }
+
+    async fn async_poll(&self) -> LxResult<PollStatus> {
+        loop {
+            let status = self.poll()?;
+            if status.read || status.write || status.error {
+                return Ok(status);
+            }
+            match self.endpoint() {
+                Ok(end) => {
+                    let object: Arc<dyn KernelObject> = end.socket.clone();
+                    object
+                        .wait_signal(
+                            Signal::READABLE
+                                | Signal::WRITABLE
+                                | Signal::PEER_CLOSED
+                                | Signal::SOCKET_PEER_WRITE_DISABLED,
+                        )
+                        .await;
+                }
+                Err(_) => {
+                    wait_for_event(self.eventbus.clone(), Event::READABLE).await;
+                }
+            }
+        }
+    }
+
+    fn ioctl(&self, request: usize, _arg1: usize, _arg2: usize, _arg3: usize) -> LxResult<usize> {
+        warn!("ioctl: unsupported request {:#x} on unix socket", request);
+        Err(LxError::ENOTTY)
+    }
+
+    fn fcntl(&self, cmd: usize, arg: usize) -> LxResult<usize> {
+        fcntl_nonblock(&mut self.inner.lock().nonblock, cmd, arg)
+    }
+
+    fn as_socket(&self) -> Option<&dyn Socket> {
+        Some(self)
+    }
+}
@@ -1,9 +1,14 @@
 //! Linux Process
 
+use super::cred::Credentials;
 use super::error::*;
 use super::fs::*;
 use super::ipc::*;
-use super::signal::{Signal as LinuxSignal, SignalAction};
+use super::ptrace::{self, Ptrace};
+use super::rlimit::*;
+use super::signal::{Signal as LinuxSignal, SignalAction, SignalCode, SIG_DFL, SIG_IGN};
+use alloc::collections::{BTreeMap, BTreeSet};
+use bitflags::bitflags;
 use alloc::vec::Vec;
 use alloc::{
     boxed::Box,
@@ -12,13 +17,15 @@ use alloc::{
 };
 use core::sync::atomic::AtomicI32;
 use hashbrown::HashMap;
+use lazy_static::lazy_static;
+use crate::kernel_hal::user::UserOutPtr;
 use crate::kernel_hal::VirtAddr;
 use rcore_fs::vfs::{FileSystem, INode};
 use spin::*;
 use crate::zircon_object::{
     object::{KernelObject, KoID, Signal},
     signal::Futex,
-    task::{Job, Process, Status},
+    task::{Job, Process, Status, Task},
     ZxResult,
 };
 
@@ -32,10 +39,206 @@ pub trait ProcessExt {
     fn fork_from(parent: &Arc<Self>, vfork: bool) -> ZxResult<Arc<Self>>;
 }
 
+lazy_static! {
+    /// All Linux processes by PID
+    static ref PROCESSES: RwLock<BTreeMap<KoID, Weak<Process>>> = RwLock::new(BTreeMap::new());
+}
+
+/// Add `proc` to the process table.
+fn register_process(proc: &Arc<Process>) {
+    proc.linux().inner.lock().pid = proc.id();
+    let mut processes = PROCESSES.write();
+    processes.retain(|_, proc| proc.strong_count() > 0);
+    processes.insert(proc.id(), Arc::downgrade(proc));
+    drop(processes);
+    // the address space and record locks are not used after exit
+    let pid = proc.id();
+    let weak = Arc::downgrade(proc);
+    proc.add_signal_callback(Box::new(move |signal| {
+        if !signal.contains(Signal::PROCESS_TERMINATED) {
+            return false;
+        }
+        release_all_locks(LockOwner::Process(pid));
+        if let Some(proc) = weak.upgrade() {
+            proc.linux().shm_detach_all();
+            ptrace::release(&proc);
+        }
+        true
+    }));
+}
+
+/// Get the Linux process with PID `pid`.
+pub fn process_by_pid(pid: KoID) -> Option<Arc<Process>> {
+    PROCESSES.read().get(&pid).and_then(|proc| proc.upgrade())
+}
+
+/// Get the processes in the process group `pgid`.
+pub fn process_group(pgid: KoID) -> Vec<Arc<Process>> {
+    PROCESSES
+        .read()
+        .values()
+        .filter_map(|proc| proc.upgrade())
+        .filter(|proc| proc.linux().pgid() == pgid)
+        .collect()
+}
+
+/// Send `signal` to all processes in the process group `pgid`.
+pub fn signal_process_group(pgid: KoID, signal: LinuxSignal) {
+    for proc in process_group(pgid) {
+        signal_process(&proc, signal);
+    }
+}
+
+/// Send `signal` to the process `proc`.
+///
+/// Signal handlers are not run yet: a signal whose action is the default one
+/// of terminating the process terminates it, with the shell convention of
+/// `128 + signal` as the exit code, one whose default action is stopping the
+/// process stops it, other signals are dropped. `SIGCONT` always resumes a
+/// stopped process, even if it is ignored. The parent is notified of the
+/// process stopping or resuming with `SIGCHLD`.
+///
+/// A signal other than `SIGKILL` to a traced process stops it instead, and is
+/// only delivered if the tracer resumes it with the signal.
+pub fn signal_process(proc: &Arc<Process>, signal: LinuxSignal) {
+    if ptrace::intercept_signal(proc, signal) {
+        return;
+    }
+    deliver_signal(proc, signal);
+}
+
+/// Send `signal` for a fault of the process `proc` in user mode.
+///
+/// The faulting instruction is run again on the return to user mode, so the
+/// signal can not be ignored: its action is reset to the default one first.
+pub fn fault_process(proc: &Arc<Process>, signal: LinuxSignal) {
+    proc.linux().set_signal_action(signal, SignalAction::default());
+    signal_process(proc, signal);
+}
+
+/// Send `signal` to the process `proc`, even if it is traced.
+pub fn deliver_signal(proc: &Arc<Process>, signal: LinuxSignal) {
+    if signal == LinuxSignal::SIGCONT {
+        continue_process(proc);
+    }
+    let action = proc.linux().signal_action(signal);
+    if action.handler == SIG_IGN {
+        return;
+    }
+    if action.handler != SIG_DFL {
+        warn!(
+            "signal {:?} to process {}: handlers are not supported",
+            signal,
+            proc.id()
+        );
+        return;
+    }
+    match signal {
+        LinuxSignal::SIGINT
+        | LinuxSignal::SIGQUIT
+        | LinuxSignal::SIGHUP
+        | LinuxSignal::SIGKILL
+        | LinuxSignal::SIGTERM
+        | LinuxSignal::SIGPIPE
+        | LinuxSignal::SIGILL
+        | LinuxSignal::SIGBUS
+        | LinuxSignal::SIGSEGV
+        | LinuxSignal::SIGXCPU => {
+            info!("signal {:?} terminates process {}", signal, proc.id());
+            proc.linux().inner.lock().term_signal = Some(signal);
+            proc.exit(128 + signal as i64);
+        }
+        LinuxSignal::SIGSTOP
+        | LinuxSignal::SIGTSTP
+        | LinuxSignal::SIGTTIN
+        | LinuxSignal::SIGTTOU => stop_process(proc, signal),
+        _ => debug!("signal {:?} to process {} is dropped", signal, proc.id()),
+    }
+}
+
+/// Stop the process `proc` by `signal`.
+fn stop_process(proc: &Arc<Process>, signal: LinuxSignal) {
+    let mut inner = proc.linux().inner.lock();
+    if inner.stopped {
+        return;
+    }
+    info!("signal {:?} stops process {}", signal, proc.id());
+    inner.stopped = true;
+    inner.stop_event = Some(ChildEvent::Stopped(signal));
+    drop(inner);
+    proc.suspend();
+    notify_parent(proc);
+}
+
+/// Resume the process `proc` if it is stopped.
+fn continue_process(proc: &Arc<Process>) {
+    let mut inner = proc.linux().inner.lock();
+    if !inner.stopped {
+        return;
+    }
+    info!("signal SIGCONT resumes process {}", proc.id());
+    inner.stopped = false;
+    inner.stop_event = Some(ChildEvent::Continued);
+    drop(inner);
+    proc.resume();
+    notify_parent(proc);
+}
+
+/// Notify the parent of `proc` of a state change with `SIGCHLD`.
+fn notify_parent(proc: &Arc<Process>) {
+    if let Some(parent) = proc.linux().parent() {
+        parent.signal_set(Signal::SIGCHLD);
+    }
+}
+
+/// Get the CPU time of the process `proc` in nanoseconds.
+///
+/// Unlike [`Process::get_time`], this includes the threads that have exited.
+pub fn cpu_time(proc: &Process) -> u64 {
+    proc.get_time() + proc.linux().inner.lock().exited_time
+}
+
+/// Enforce `RLIMIT_CPU` on the process `proc`.
+///
+/// The CPU time of a process is the time its threads have run. Beyond the soft
+/// limit the process gets `SIGXCPU` once every CPU second, beyond the hard
+/// limit `SIGKILL`. Called on the timer interrupts of the running threads.
+pub fn check_cpu_limit(proc: &Arc<Process>) {
+    let limit = proc.linux().rlimits().get(RLIMIT_CPU);
+    if limit.cur == RLIM_INFINITY {
+        return;
+    }
+    let time = cpu_time(proc) / 1_000_000_000;
+    if limit.max != RLIM_INFINITY && time >= limit.max {
+        signal_process(proc, LinuxSignal::SIGKILL);
+    } else if limit.exceeded_by(time) && proc.linux().xcpu_due(time) {
+        signal_process(proc, LinuxSignal::SIGXCPU);
+    }
+}
+
+/// Get PIDs of all Linux processes.
+pub fn pids() -> Vec<KoID> {
+    PROCESSES
+        .read()
+        .iter()
+        .filter(|(_, proc)| proc.strong_count() > 0)
+        .map(|(&pid, _)| pid)
+        .collect()
+}
+
 impl ProcessExt for Process {
     fn create_linux(job: &Arc<Job>, rootfs: Arc<dyn FileSystem>) -> ZxResult<Arc<Self>> {
         let linux_proc = LinuxProcess::new(rootfs);
-        Process::create_with_ext(job, "root", linux_proc)
+        let proc = Process::create_with_ext(job, "root", linux_proc)?;
+        register_process(&proc);
+        // the first process leads its own session, on the console
+        let mut inner = proc.linux().inner.lock();
+        inner.pgid = proc.id();
+        inner.sid = proc.id();
+        inner.tty = Some(CONSOLE.clone());
+        drop(inner);
+        CONSOLE.set_controlling(proc.id(), proc.id());
+        Ok(proc)
     }
 
     fn linux(&self) -> &LinuxProcess {
@@ -53,18 +256,42 @@ impl ProcessExt for Process {
             parent: Arc::downgrade(parent),
             inner: Mutex::new(LinuxProcessInner {
                 execute_path: linux_parent_inner.execute_path.clone(),
-                current_working_directory: linux_parent_inner.current_working_directory.clone(),
-                files: linux_parent_inner.files.clone(),
+                args: linux_parent_inner.args.clone(),
+                envs: linux_parent_inner.envs.clone(),
+                current_working_directory: Arc::new(Mutex::new(
+                    linux_parent_inner.current_working_directory.lock().clone(),
+                )),
+                files: Arc::new(Mutex::new(linux_parent_inner.files.lock().clone())),
                 signal_actions: linux_parent_inner.signal_actions.clone(),
+                pgid: linux_parent_inner.pgid,
+                sid: linux_parent_inner.sid,
+                tty: linux_parent_inner.tty.clone(),
+                cred: linux_parent_inner.cred.clone(),
+                rlimits: linux_parent_inner.rlimits,
                 ..Default::default()
             }),
+            ptrace: Mutex::new(Ptrace::default()),
         };
         let new_proc = Process::create_with_ext(&parent.job(), "", new_linux_proc)?;
+        new_proc.set_trace_syscalls(parent.trace_syscalls());
+        register_process(&new_proc);
+        new_proc
+            .vmar()
+            .set_space_limit(linux_parent_inner.rlimits.space_limit());
         linux_parent_inner
             .children
             .insert(new_proc.id(), new_proc.clone());
         if !vfork {
-            new_proc.vmar().fork_from(&parent.vmar())?;
+            let vmar = new_proc.vmar();
+            vmar.fork_from(&parent.vmar())?;
+            // attached shared memory is shared with the child, not copied
+            let shm = linux_parent_inner.shm.clone();
+            for (&addr, attach) in shm.attaches() {
+                let vmo = attach.segment.vmo();
+                let len = vmo.len();
+                vmar.map_at_ext(addr - vmar.addr(), vmo, 0, len, attach.flags, true, true)?;
+            }
+            new_proc.linux().inner.lock().shm = shm;
         }
 
         // notify parent on terminated
@@ -79,50 +306,317 @@ impl ProcessExt for Process {
     }
 }
 
-/// Wait for state changes in a child of the calling process, and obtain information about
-/// the child whose state has changed.
+/// The children to wait for
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum WaitTarget {
+    /// Any child
+    Any,
+    /// The child with the PID
+    Pid(KoID),
+    /// Any child in the process group
+    Group(KoID),
+}
+
+bitflags! {
+    /// Options of `wait4` and `waitid`
+    pub struct WaitOptions: u32 {
+        /// return immediately if no child has a state change
+        const NOHANG = 1;
+        /// report stopped children
+        const UNTRACED = 2;
+        /// report exited children
+        const EXITED = 4;
+        /// report resumed children
+        const CONTINUED = 8;
+        /// leave the child in a waitable state
+        const NOWAIT = 0x100_0000;
+    }
+}
+
+bitflags! {
+    /// Flags of `clone`
+    pub struct CloneFlags: usize {
+        ///
+        const CSIGNAL =         0xff;
+        /// the calling process and the child process run in the same memory space
+        const VM =              1 << 8;
+        /// the caller and the child process share the same filesystem information
+        const FS =              1 << 9;
+        /// the calling process and the child process share the same file descriptor table
+        const FILES =           1 << 10;
+        /// the calling process and the child process share the same table of signal handlers.
+        const SIGHAND =         1 << 11;
+        /// the calling process is being traced
+        const PTRACE =          1 << 13;
+        /// the execution of the calling process is suspended until the child releases its virtual memory resources
+        const VFORK =           1 << 14;
+        /// the parent of the new child will be the same as that of the call‐ing process.
+        const PARENT =          1 << 15;
+        /// the child is placed in the same thread group as the calling process.
+        const THREAD =          1 << 16;
+        /// cloned child is started in a new mount namespace
+        const NEWNS	=           1 << 17;
+        /// the child and the calling process share a single list of System V semaphore adjustment values.
+        const SYSVSEM =         1 << 18;
+        /// architecture dependent, The TLS (Thread Local Storage) descriptor is set to tls.
+        const SETTLS =          1 << 19;
+        /// Store the child thread ID at the location in the parent's memory.
+        const PARENT_SETTID =   1 << 20;
+        /// Clear (zero) the child thread ID
+        const CHILD_CLEARTID =  1 << 21;
+        /// the parent not to receive a signal when the child terminated
+        const DETACHED =        1 << 22;
+        /// a tracing process cannot force CLONE_PTRACE on this child process.
+        const UNTRACED =        1 << 23;
+        /// Store the child thread ID
+        const CHILD_SETTID =    1 << 24;
+        /// Create the process in a new cgroup namespace.
+        const NEWCGROUP =       1 << 25;
+        /// create the process in a new UTS namespace
+        const NEWUTS =          1 << 26;
+        /// create the process in a new IPC namespace.
+        const NEWIPC =          1 << 27;
+        /// create the process in a new user namespace
+        const NEWUSER =         1 << 28;
+        /// create the process in a new PID namespace
+        const NEWPID =          1 << 29;
+        /// create the process in a new net‐work namespace.
+        const NEWNET =          1 << 30;
+        /// the new process shares an I/O context with the calling process.
+        const IO =              1 << 31;
+
+        /// flags that are not supported: tracing, another parent and namespaces
+        const UNSUPPORTED =     Self::PTRACE.bits | Self::PARENT.bits | Self::NEWNS.bits
+            | Self::NEWCGROUP.bits | Self::NEWUTS.bits | Self::NEWIPC.bits
+            | Self::NEWUSER.bits | Self::NEWPID.bits | Self::NEWNET.bits;
+    }
+}
+
+impl CloneFlags {
+    /// Check the `flags` of `clone`.
+    ///
+    /// Fail with `EINVAL` on unknown or unsupported flags, on `CLONE_SIGHAND`
+    /// without `CLONE_VM`, and on threads which do not share everything with
+    /// the caller: they need `CLONE_VM`, `CLONE_FS`, `CLONE_FILES` and
+    /// `CLONE_SIGHAND`, and can not be created with `CLONE_VFORK`.
+    pub fn check(flags: usize) -> LxResult<Self> {
+        let flags = CloneFlags::from_bits(flags).ok_or(LxError::EINVAL)?;
+        if flags.intersects(CloneFlags::UNSUPPORTED) {
+            warn!(
+                "clone: unsupported flags {:?}",
+                flags & CloneFlags::UNSUPPORTED
+            );
+            return Err(LxError::EINVAL);
+        }
+        if flags.contains(CloneFlags::SIGHAND) && !flags.contains(CloneFlags::VM) {
+            return Err(LxError::EINVAL);
+        }
+        let thread_flags =
+            CloneFlags::VM | CloneFlags::FS | CloneFlags::FILES | CloneFlags::SIGHAND;
+        if flags.contains(CloneFlags::THREAD)
+            && (!flags.contains(thread_flags) || flags.contains(CloneFlags::VFORK))
+        {
+            return Err(LxError::EINVAL);
+        }
+        Ok(flags)
+    }
+}
+
+/// Store the ID `tid` of the thread or process created by `clone` in `child`.
 ///
-/// A state change is considered to be:
-/// - the child terminated.
-/// - the child was stopped by a signal. TODO
-/// - the child was resumed by a signal. TODO
-pub async fn wait_child(proc: &Arc<Process>, pid: KoID, nonblock: bool) -> LxResult<ExitCode> {
-    loop {
-        let mut inner = proc.linux().inner.lock();
-        let child = inner.children.get(&pid).ok_or(LxError::ECHILD)?;
-        if let Status::Exited(code) = child.status() {
-            inner.children.remove(&pid);
-            return Ok(code as ExitCode);
+/// With `CLONE_PARENT_SETTID` it is stored to `parent_tid` in the memory of
+/// `parent`, and with `CLONE_CHILD_SETTID` to `child_tid` in the memory of
+/// `child`, which is the caller's with `CLONE_VM`. The child exists already,
+/// so like Linux a bad pointer is ignored instead of failing the call.
+pub fn store_clone_tid(
+    flags: CloneFlags,
+    tid: KoID,
+    parent: &Process,
+    child: &Process,
+    parent_tid: &UserOutPtr<i32>,
+    child_tid: &UserOutPtr<i32>,
+) {
+    let bytes = (tid as i32).to_ne_bytes();
+    if flags.contains(CloneFlags::PARENT_SETTID) {
+        let addr = parent_tid.as_ptr() as usize;
+        if parent.vmar().write_memory(addr, &bytes).is_err() {
+            warn!("clone: bad parent_tid {:#x}", addr);
         }
-        if nonblock {
-            return Err(LxError::EAGAIN);
+    }
+    if flags.contains(CloneFlags::CHILD_SETTID) {
+        let addr = child_tid.as_ptr() as usize;
+        let vmar = if flags.contains(CloneFlags::VM) {
+            parent.vmar()
+        } else {
+            child.vmar()
+        };
+        if vmar.write_memory(addr, &bytes).is_err() {
+            warn!("clone: bad child_tid {:#x}", addr);
         }
-        let child: Arc<dyn KernelObject> = child.clone();
-        drop(inner);
-        child.signal_clear(Signal::PROCESS_TERMINATED);
-        child.wait_signal(Signal::PROCESS_TERMINATED).await;
     }
 }
 
-/// Wait for state changes in a child of the calling process.
-pub async fn wait_child_any(proc: &Arc<Process>, nonblock: bool) -> LxResult<(KoID, ExitCode)> {
+/// A state change of a child
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ChildEvent {
+    /// The child exited with the exit code.
+    Exited(ExitCode),
+    /// The child was terminated by the signal.
+    Killed(LinuxSignal),
+    /// The child was stopped by the signal.
+    Stopped(LinuxSignal),
+    /// The child was resumed by `SIGCONT`.
+    Continued,
+    /// The traced child stopped for its tracer, with the stop signal.
+    Traced(i32),
+}
+
+impl ChildEvent {
+    /// The status as reported by `wait4`.
+    pub fn wstatus(&self) -> i32 {
+        match *self {
+            ChildEvent::Exited(code) => (code & 0xff) << 8,
+            ChildEvent::Killed(signal) => signal as i32,
+            ChildEvent::Stopped(signal) => ((signal as i32) << 8) | 0x7f,
+            ChildEvent::Continued => 0xffff,
+            ChildEvent::Traced(status) => (status << 8) | 0x7f,
+        }
+    }
+
+    /// The `si_code` and `si_status` of `SIGCHLD` as reported by `waitid`.
+    pub fn siginfo(&self) -> (SignalCode, i32) {
+        match *self {
+            ChildEvent::Exited(code) => (SignalCode::CLD_EXITED, code),
+            ChildEvent::Killed(signal) => (SignalCode::CLD_KILLED, signal as i32),
+            ChildEvent::Stopped(signal) => (SignalCode::CLD_STOPPED, signal as i32),
+            ChildEvent::Continued => (SignalCode::CLD_CONTINUED, LinuxSignal::SIGCONT as i32),
+            ChildEvent::Traced(status) => (SignalCode::CLD_TRAPPED, status & 0xff),
+        }
+    }
+}
+
+/// A state change of a child reported by [`wait_child`]
+#[derive(Debug, Copy, Clone)]
+pub struct WaitResult {
+    /// PID of the child
+    pub pid: KoID,
+    /// Real user ID of the child
+    pub uid: u32,
+    /// The state change
+    pub event: ChildEvent,
+    /// CPU time of the child in nanoseconds
+    pub time: u64,
+}
+
+/// Wait for state changes in a child of the calling process, and obtain information about
+/// the child whose state has changed.
+///
+/// A state change is considered to be:
+/// - the child terminated, reported with `EXITED`.
+/// - the child was stopped by a signal, reported with `UNTRACED`.
+/// - the child was resumed by a signal, reported with `CONTINUED`.
+/// - a process traced by `proc` stopped, always reported.
+///
+/// A terminated child is reaped unless `NOWAIT` is given, and its CPU time is
+/// added to the children time of `proc`. If `proc` ignores `SIGCHLD`,
+/// terminated children are reaped without being reported.
+///
+/// Returns `None` if no child has a state change and `NOHANG` is given.
+pub async fn wait_child(
+    proc: &Arc<Process>,
+    target: WaitTarget,
+    options: WaitOptions,
+) -> LxResult<Option<WaitResult>> {
+    let linux = proc.linux();
     loop {
-        let mut inner = proc.linux().inner.lock();
-        if inner.children.is_empty() {
+        proc.signal_clear(Signal::SIGCHLD);
+        let is_target = |child: &Arc<Process>| match target {
+            WaitTarget::Any => true,
+            WaitTarget::Pid(pid) => child.id() == pid,
+            WaitTarget::Group(pgid) => child.linux().pgid() == pgid,
+        };
+        let children: Vec<Arc<Process>> = linux.inner.lock().children.values().cloned().collect();
+        let children: Vec<Arc<Process>> = children.into_iter().filter(is_target).collect();
+        let tracees: Vec<Arc<Process>> = ptrace::tracees(proc)
+            .into_iter()
+            .filter(is_target)
+            .collect();
+        if children.is_empty() && tracees.is_empty() {
             return Err(LxError::ECHILD);
         }
-        for (&pid, child) in inner.children.iter() {
+        for tracee in tracees.iter() {
+            let status = ptrace::stop_status(tracee, !options.contains(WaitOptions::NOWAIT));
+            if let Some(status) = status {
+                return Ok(Some(WaitResult {
+                    pid: tracee.id(),
+                    uid: tracee.linux().credentials().user.real,
+                    event: ChildEvent::Traced(status),
+                    time: cpu_time(tracee),
+                }));
+            }
+        }
+        let reap_silently = linux.signal_action(LinuxSignal::SIGCHLD).handler == SIG_IGN;
+        for child in children.iter() {
             if let Status::Exited(code) = child.status() {
-                inner.children.remove(&pid);
-                return Ok((pid, code as ExitCode));
+                // the threads may not have exited yet
+                if !options.contains(WaitOptions::EXITED)
+                    || !child.signal().contains(Signal::PROCESS_TERMINATED)
+                {
+                    continue;
+                }
+                let child_linux = child.linux();
+                let child_inner = child_linux.inner.lock();
+                let event = match child_inner.term_signal {
+                    Some(signal) => ChildEvent::Killed(signal),
+                    None => ChildEvent::Exited(code as ExitCode),
+                };
+                let time = child_inner.exited_time + child_inner.children_time;
+                let uid = child_inner.cred.user.real;
+                drop(child_inner);
+                if !options.contains(WaitOptions::NOWAIT) || reap_silently {
+                    let mut inner = linux.inner.lock();
+                    inner.children.remove(&child.id());
+                    inner.children_time += time;
+                }
+                if reap_silently {
+                    continue;
+                }
+                return Ok(Some(WaitResult {
+                    pid: child.id(),
+                    uid,
+                    event,
+                    time,
+                }));
             }
+            let mut child_inner = child.linux().inner.lock();
+            let event = match child_inner.stop_event {
+                Some(event @ ChildEvent::Stopped(_)) if options.contains(WaitOptions::UNTRACED) => {
+                    event
+                }
+                Some(ChildEvent::Continued) if options.contains(WaitOptions::CONTINUED) => {
+                    ChildEvent::Continued
+                }
+                _ => continue,
+            };
+            if !options.contains(WaitOptions::NOWAIT) {
+                child_inner.stop_event = None;
+            }
+            let uid = child_inner.cred.user.real;
+            drop(child_inner);
+            return Ok(Some(WaitResult {
+                pid: child.id(),
+                uid,
+                event,
+                time: cpu_time(child),
+            }));
         }
-        drop(inner);
-        if nonblock {
-            return Err(LxError::EAGAIN);
+        if reap_silently && tracees.is_empty() && linux.inner.lock().children.is_empty() {
+            return Err(LxError::ECHILD);
+        }
+        if options.contains(WaitOptions::NOHANG) {
+            return Ok(None);
         }
         let proc: Arc<dyn KernelObject> = proc.clone();
-        proc.signal_clear(Signal::SIGCHLD);
         proc.wait_signal(Signal::SIGCHLD).await;
     }
 }
@@ -135,27 +629,80 @@ pub struct LinuxProcess {
     parent: Weak<Process>,
     /// Inner
     inner: Mutex<LinuxProcessInner>,
+    /// Tracing state
+    ptrace: Mutex<Ptrace>,
 }
 
 /// Linux process mut inner data
+/// Working directory of a process
+#[derive(Clone)]
+struct WorkingDir {
+    /// Path of the directory
+    ///
+    /// Omit leading '/'.
+    path: String,
+    /// The directory, `None` for the root
+    ///
+    /// It keeps the mount it is on busy.
+    inode: Option<Arc<dyn INode>>,
+    /// File mode creation mask
+    umask: u32,
+}
+
+impl Default for WorkingDir {
+    fn default() -> Self {
+        WorkingDir {
+            path: String::new(),
+            inode: None,
+            umask: 0o022,
+        }
+    }
+}
+
 #[derive(Default)]
 struct LinuxProcessInner {
+    /// Process ID
+    pid: KoID,
+    /// Process group ID
+    pgid: KoID,
+    /// Session ID
+    sid: KoID,
+    /// Controlling terminal
+    tty: Option<Arc<dyn INode>>,
+    /// User and group IDs
+    cred: Credentials,
     /// Execute path
     execute_path: String,
-    /// Current Working Directory
-    ///
-    /// Omit leading '/'.
-    current_working_directory: String,
-    /// file open number limit
-    file_limit: RLimit,
-    /// Opened files
-    files: HashMap<FileDesc, Arc<dyn FileLike>>,
+    /// Arguments of the program
+    args: Vec<String>,
+    /// Environment of the program
+    envs: Vec<String>,
+    /// Current Working Directory, shared with processes cloned with `CLONE_FS`
+    current_working_directory: Arc<Mutex<WorkingDir>>,
+    /// Resource limits
+    rlimits: RLimits,
+    /// Opened files, shared with processes cloned with `CLONE_FILES`
+    files: Arc<Mutex<FileTable>>,
     /// Semaphore
     semaphores: SemProc,
+    /// Shared memory
+    shm: ShmProc,
     /// Futexes
     futexes: HashMap<VirtAddr, Arc<Futex>>,
     /// Child processes
     children: HashMap<KoID, Arc<Process>>,
+    /// CPU time of the exited threads in nanoseconds
+    exited_time: u64,
+    /// CPU time of the reaped children and their children in nanoseconds
+    children_time: u64,
+    /// The CPU second `SIGXCPU` was last sent at
+    xcpu_second: Option<u64>,
+    /// Whether the process is stopped by a signal
+    stopped: bool,
+    /// Stop or continue event not yet reported to the parent
+    stop_event: Option<ChildEvent>,
+    /// The signal which terminated the process
+    term_signal: Option<LinuxSignal>,
     /// Signal actions
     signal_actions: SignalActions,
 }
@@ -173,23 +720,12 @@ impl Default for SignalActions {
     }
 }
 
-/// resource limit
-#[repr(C)]
-#[derive(Debug, Copy, Clone)]
-pub struct RLimit {
-    /// soft limit
-    pub cur: u64,
-    /// hard limit
-    pub max: u64,
-}
-
-impl Default for RLimit {
-    fn default() -> Self {
-        RLimit {
-            cur: 1024,
-            max: 1024,
-        }
-    }
+/// File descriptor table
+#[derive(Clone, Default)]
+struct FileTable {
+    entries: HashMap<FileDesc, Arc<dyn FileLike>>,
+    /// descriptors closed on exec, besides files opened with `O_CLOEXEC`
+    cloexec: BTreeSet<FileDesc>,
 }
 
 /// The type of process exit code.
@@ -199,39 +735,42 @@ impl LinuxProcess {
     /// Create a new process.
     pub fn new(rootfs: Arc<dyn FileSystem>) -> Self {
         let stdin = File::new(
-            STDIN.clone(), // FIXME: stdin
+            CONSOLE.clone(),
             OpenOptions {
                 read: true,
                 write: false,
                 append: false,
                 nonblock: false,
                 fd_cloexec: false,
+                path_only: false,
             },
-            String::from("/dev/stdin"),
+            String::from("/dev/console"),
         ) as Arc<dyn FileLike>;
         let stdout = File::new(
-            STDOUT.clone(), // TODO: open from '/dev/stdout'
+            CONSOLE.clone(),
             OpenOptions {
                 read: false,
                 write: true,
                 append: false,
                 nonblock: false,
                 fd_cloexec: false,
+                path_only: false,
             },
-            String::from("/dev/stdout"),
+            String::from("/dev/console"),
         ) as Arc<dyn FileLike>;
-        let mut files = HashMap::new();
-        files.insert(0.into(), stdin);
-        files.insert(1.into(), stdout.clone());
-        files.insert(2.into(), stdout);
+        let mut files = FileTable::default();
+        files.entries.insert(0.into(), stdin);
+        files.entries.insert(1.into(), stdout.clone());
+        files.entries.insert(2.into(), stdout);
 
         LinuxProcess {
             root_inode: create_root_fs(rootfs),
             parent: Weak::default(),
             inner: Mutex::new(LinuxProcessInner {
-                files,
+                files: Arc::new(Mutex::new(files)),
                 ..Default::default()
             }),
+            ptrace: Mutex::new(Ptrace::default()),
         }
     }
 
@@ -252,40 +791,36 @@ impl LinuxProcess {
 
     /// Add a file to the file descriptor table.
     pub fn add_file(&self, file: Arc<dyn FileLike>) -> LxResult<FileDesc> {
-        let inner = self.inner.lock();
-        let fd = inner.get_free_fd();
-        self.insert_file(inner, fd, file)
-    }
-
-    /// Add a file to the file descriptor table at given `fd`.
-    pub fn add_file_at(&self, fd: FileDesc, file: Arc<dyn FileLike>) -> LxResult<FileDesc> {
-        let inner = self.inner.lock();
-        self.insert_file(inner, fd, file)
+        self.add_file_with_cloexec(file, false)
     }
 
-    /// insert a file and fd into the file descriptor table
-    fn insert_file(
+    /// Add a file to the file descriptor table, closed on exec if `cloexec`.
+    ///
+    /// For objects other than `File`, which keep the flag in their options.
+    pub fn add_file_with_cloexec(
         &self,
-        mut inner: MutexGuard<LinuxProcessInner>,
-        fd: FileDesc,
         file: Arc<dyn FileLike>,
+        cloexec: bool,
     ) -> LxResult<FileDesc> {
-        if inner.files.len() < inner.file_limit.cur as usize {
-            inner.files.insert(fd, file);
-            Ok(fd)
-        } else {
-            Err(LxError::EMFILE)
+        let inner = self.inner.lock();
+        let mut files = inner.files.lock();
+        let fd = get_free_fd(&files);
+        inner.insert_file(&mut files, fd, file)?;
+        if cloexec {
+            files.cloexec.insert(fd);
         }
+        Ok(fd)
     }
 
-    /// get and set file limit number
-    pub fn file_limit(&self, new_limit: Option<RLimit>) -> RLimit {
-        let mut inner = self.inner.lock();
-        let old = inner.file_limit;
-        if let Some(limit) = new_limit {
-            inner.file_limit = limit;
-        }
-        old
+    /// Add a file to the file descriptor table at given `fd`.
+    ///
+    /// Return `EBADF` if `fd` is beyond the limit.
+    pub fn add_file_at(&self, fd: FileDesc, file: Arc<dyn FileLike>) -> LxResult<FileDesc> {
+        let inner = self.inner.lock();
+        let mut files = inner.files.lock();
+        inner
+            .insert_file(&mut files, fd, file)
+            .map_err(|_| LxError::EBADF)
     }
 
     /// Get the `File` with given `fd`.
@@ -300,13 +835,43 @@ impl LinuxProcess {
     /// Get the `FileLike` with given `fd`.
     pub fn get_file_like(&self, fd: FileDesc) -> LxResult<Arc<dyn FileLike>> {
         let inner = self.inner.lock();
-        inner.files.get(&fd).cloned().ok_or(LxError::EBADF)
+        let files = inner.files.lock();
+        files.entries.get(&fd).cloned().ok_or(LxError::EBADF)
     }
 
     /// Close file descriptor `fd`.
     pub fn close_file(&self, fd: FileDesc) -> LxResult {
-        let mut inner = self.inner.lock();
-        inner.files.remove(&fd).map(|_| ()).ok_or(LxError::EBADF)
+        let files = self.inner.lock().files.clone();
+        // closing a terminal may signal processes, so drop it without the locks
+        let file = {
+            let mut files = files.lock();
+            files.cloexec.remove(&fd);
+            files.entries.remove(&fd).ok_or(LxError::EBADF)?
+        };
+        self.release_record_locks(&file);
+        Ok(())
+    }
+
+    /// Release the record locks of the process on the file of `file_like`,
+    /// as on closing any descriptor of the file.
+    fn release_record_locks(&self, file_like: &Arc<dyn FileLike>) {
+        if let Ok(file) = file_like.clone().downcast_arc::<File>() {
+            if let Ok(key) = file.lock_key() {
+                release_record_locks(key, LockOwner::Process(self.pid()));
+            }
+        }
+    }
+
+    /// Share the file descriptor table of `other`, as by `CLONE_FILES`.
+    pub fn share_files(&self, other: &LinuxProcess) {
+        let files = other.inner.lock().files.clone();
+        self.inner.lock().files = files;
+    }
+
+    /// Share the working directory of `other`, as by `CLONE_FS`.
+    pub fn share_fs(&self, other: &LinuxProcess) {
+        let cwd = other.inner.lock().current_working_directory.clone();
+        self.inner.lock().current_working_directory = cwd;
     }
 
     /// Get root INode of the process.
@@ -314,6 +879,16 @@ impl LinuxProcess {
         &self.root_inode
     }
 
+    /// Lock and get the tracing state.
+    pub fn ptrace(&self) -> MutexGuard<'_, Ptrace> {
+        self.ptrace.lock()
+    }
+
+    /// Whether the process is stopped by a signal.
+    pub fn is_stopped(&self) -> bool {
+        self.inner.lock().stopped
+    }
+
     /// Get parent process.
     pub fn parent(&self) -> Option<Arc<Process>> {
         self.parent.upgrade()
@@ -321,18 +896,42 @@ impl LinuxProcess {
 
     /// Get current working directory.
     pub fn current_working_directory(&self) -> String {
-        String::from("/") + &self.inner.lock().current_working_directory
+        let cwd = self.inner.lock().current_working_directory.clone();
+        let cwd = cwd.lock();
+        String::from("/") + &cwd.path
+    }
+
+    /// Get the INode of the current working directory.
+    pub fn current_working_directory_inode(&self) -> Arc<dyn INode> {
+        let cwd = self.inner.lock().current_working_directory.clone();
+        let cwd = cwd.lock();
+        cwd.inode.clone().unwrap_or_else(|| self.root_inode.clone())
     }
 
-    /// Change working directory.
-    pub fn change_directory(&self, path: &str) {
+    /// Get the file mode creation mask.
+    pub fn umask(&self) -> u32 {
+        let cwd = self.inner.lock().current_working_directory.clone();
+        let umask = cwd.lock().umask;
+        umask
+    }
+
+    /// Set the file mode creation mask, and return the old one.
+    pub fn set_umask(&self, umask: u32) -> u32 {
+        let cwd = self.inner.lock().current_working_directory.clone();
+        let mut cwd = cwd.lock();
+        core::mem::replace(&mut cwd.umask, umask & 0o777)
+    }
+
+    /// Change working directory to `inode`, found at `path`.
+    pub fn change_directory(&self, path: &str, inode: Arc<dyn INode>) {
         if path.is_empty() {
             return;
         }
-        let mut inner = self.inner.lock();
+        let current_working_directory = self.inner.lock().current_working_directory.clone();
+        let mut current_working_directory = current_working_directory.lock();
         let cwd = match path.as_bytes()[0] {
             b'/' => String::new(),
-            _ => inner.current_working_directory.clone(),
+            _ => current_working_directory.path.clone(),
         };
         let mut cwd_vec: Vec<_> = cwd.split('/').filter(|x| !x.is_empty()).collect();
         for seg in path.split('/') {
@@ -344,7 +943,8 @@ impl LinuxProcess {
                 _ => cwd_vec.push(seg),
             }
         }
-        inner.current_working_directory = cwd_vec.join("/");
+        current_working_directory.path = cwd_vec.join("/");
+        current_working_directory.inode = Some(inode);
     }
 
     /// Get execute path.
@@ -357,6 +957,155 @@ impl LinuxProcess {
         self.inner.lock().execute_path = String::from(path);
     }
 
+    /// Get process ID.
+    pub fn pid(&self) -> KoID {
+        self.inner.lock().pid
+    }
+
+    /// Get process group ID.
+    pub fn pgid(&self) -> KoID {
+        self.inner.lock().pgid
+    }
+
+    /// Move the process to the process group `pgid`.
+    ///
+    /// A session leader can not be moved, and the group must be in the same
+    /// session as the process.
+    pub fn set_pgid(&self, pgid: KoID) -> LxResult {
+        let (pid, sid) = {
+            let inner = self.inner.lock();
+            (inner.pid, inner.sid)
+        };
+        if sid == pid {
+            return Err(LxError::EPERM);
+        }
+        if pgid != pid {
+            let leader = process_group(pgid)
+                .into_iter()
+                .next()
+                .ok_or(LxError::EPERM)?;
+            if leader.linux().sid() != sid {
+                return Err(LxError::EPERM);
+            }
+        }
+        self.inner.lock().pgid = pgid;
+        Ok(())
+    }
+
+    /// Get session ID.
+    pub fn sid(&self) -> KoID {
+        self.inner.lock().sid
+    }
+
+    /// Create a new session led by the process, return the session ID.
+    ///
+    /// A process group leader can not create a session. The new session has
+    /// no controlling terminal.
+    pub fn set_sid(&self) -> LxResult<KoID> {
+        let pid = self.pid();
+        if !process_group(pid).is_empty() {
+            return Err(LxError::EPERM);
+        }
+        let mut inner = self.inner.lock();
+        inner.pgid = pid;
+        inner.sid = pid;
+        inner.tty = None;
+        Ok(pid)
+    }
+
+    /// Get the controlling terminal.
+    pub fn controlling_tty(&self) -> Option<Arc<dyn INode>> {
+        self.inner.lock().tty.clone()
+    }
+
+    /// Set the controlling terminal.
+    pub fn set_controlling_tty(&self, tty: Option<Arc<dyn INode>>) {
+        self.inner.lock().tty = tty;
+    }
+
+    /// Get the credentials.
+    pub fn credentials(&self) -> Credentials {
+        self.inner.lock().cred.clone()
+    }
+
+    /// Set the credentials.
+    pub fn set_credentials(&self, cred: Credentials) {
+        self.inner.lock().cred = cred;
+    }
+
+    /// Get the resource limits.
+    pub fn rlimits(&self) -> RLimits {
+        self.inner.lock().rlimits
+    }
+
+    /// Set the resource limits.
+    pub fn set_rlimits(&self, rlimits: RLimits) {
+        self.inner.lock().rlimits = rlimits;
+    }
+
+    /// Check whether the real user of the process may create another thread.
+    ///
+    /// The threads of all processes of the user count against `RLIMIT_NPROC`.
+    /// There are no capabilities to bypass it, so it also applies to root.
+    pub fn check_nproc(&self) -> LxResult {
+        let (uid, limit) = {
+            let inner = self.inner.lock();
+            (inner.cred.user.real, inner.rlimits.get(RLIMIT_NPROC))
+        };
+        let threads: usize = PROCESSES
+            .read()
+            .values()
+            .filter_map(|proc| proc.upgrade())
+            .filter(|proc| proc.linux().credentials().user.real == uid)
+            .map(|proc| proc.thread_ids().len())
+            .sum();
+        if limit.exceeded_by(threads as u64) {
+            return Err(LxError::EAGAIN);
+        }
+        Ok(())
+    }
+
+    /// Get arguments and environment of the program.
+    pub fn args(&self) -> (Vec<String>, Vec<String>) {
+        let inner = self.inner.lock();
+        (inner.args.clone(), inner.envs.clone())
+    }
+
+    /// Set arguments and environment of the program.
+    pub fn set_args(&self, args: Vec<String>, envs: Vec<String>) {
+        let mut inner = self.inner.lock();
+        inner.args = args;
+        inner.envs = envs;
+    }
+
+    /// Get all opened file descriptors.
+    pub fn file_descriptors(&self) -> Vec<FileDesc> {
+        let files = self.inner.lock().files.clone();
+        let mut fds: Vec<FileDesc> = files.lock().entries.keys().cloned().collect();
+        fds.sort();
+        fds
+    }
+
+    /// Add the CPU time of an exited thread.
+    pub fn add_exited_time(&self, time: u64) {
+        self.inner.lock().exited_time += time;
+    }
+
+    /// Whether `SIGXCPU` is due at `second` of CPU time, not sent for it yet.
+    fn xcpu_due(&self, second: u64) -> bool {
+        let mut inner = self.inner.lock();
+        if inner.xcpu_second.map_or(false, |last| last >= second) {
+            return false;
+        }
+        inner.xcpu_second = Some(second);
+        true
+    }
+
+    /// Get the CPU time of the reaped children in nanoseconds.
+    pub fn children_time(&self) -> u64 {
+        self.inner.lock().children_time
+    }
+
     /// Get signal action.
     pub fn signal_action(&self, signal: LinuxSignal) -> SignalAction {
         self.inner.lock().signal_actions.table[signal as u8 as usize]
@@ -369,9 +1118,10 @@ impl LinuxProcess {
 
     /// Close file that FD_CLOEXEC is set
     pub fn remove_cloexec_files(&self) {
-        let mut inner = self.inner.lock();
-        let close_fds = inner
-            .files
+        let files = self.inner.lock().files.clone();
+        let mut files = files.lock();
+        let mut close_fds = files
+            .entries
             .iter()
             .filter_map(|(fd, file_like)| {
                 if let Ok(file) = file_like.clone().downcast_arc::<File>() {
@@ -385,8 +1135,11 @@ impl LinuxProcess {
                 }
             })
             .collect::<Vec<_>>();
+        close_fds.extend(core::mem::take(&mut files.cloexec));
         for fd in close_fds {
-            inner.files.remove(&fd).map(|_| ()).unwrap();
+            if let Some(file) = files.entries.remove(&fd) {
+                self.release_record_locks(&file);
+            }
         }
     }
 
@@ -409,13 +1162,44 @@ impl LinuxProcess {
     pub fn semaphores_remove(&self, id: usize) {
         self.inner.lock().semaphores.remove(id)
     }
+
+    /// Record the `attach` of a segment at `addr`
+    pub fn shm_attach(&self, addr: VirtAddr, attach: ShmAttach) {
+        self.inner.lock().shm.attach(addr, attach)
+    }
+
+    /// Remove the attach of a segment at `addr`
+    pub fn shm_detach(&self, addr: VirtAddr) -> Option<ShmAttach> {
+        self.inner.lock().shm.detach(addr)
+    }
+
+    /// Remove all attaches of segments, on exec or exit
+    pub fn shm_detach_all(&self) {
+        self.inner.lock().shm.detach_all()
+    }
 }
 
 impl LinuxProcessInner {
-    fn get_free_fd(&self) -> FileDesc {
-        (0usize..)
-            .map(|i| i.into())
-            .find(|fd| !self.files.contains_key(fd))
-            .unwrap()
+    /// insert a file and fd into the file descriptor table `files`
+    fn insert_file(
+        &self,
+        files: &mut FileTable,
+        fd: FileDesc,
+        file: Arc<dyn FileLike>,
+    ) -> LxResult<FileDesc> {
+        let fd_num: usize = fd.into();
+        if self.rlimits.get(RLIMIT_NOFILE).exceeded_by(fd_num as u64) {
+            return Err(LxError::EMFILE);
+        }
+        files.cloexec.remove(&fd);
+        files.entries.insert(fd, file);
+        Ok(fd)
     }
 }
+
+fn get_free_fd(files: &FileTable) -> FileDesc {
+    (0usize..)
+        .map(|i| i.into())
+        .find(|fd| !files.entries.contains_key(fd))
+        .unwrap()
+}
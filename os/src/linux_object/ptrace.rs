@@ -0,0 +1,516 @@
+//! Process tracing
+//!
+//! A tracer attaches to a process, then stops it, reads and changes its
+//! memory and registers, and resumes it. The stops reuse the Zircon debugger
+//! machinery: a thread of a traced process stops by raising a synthetic
+//! exception on the debug exceptionate of its process, and blocks until the
+//! exception is handled. The tracer holds the user end of the exception
+//! channel and receives the exceptions from it, and handling an exception
+//! resumes its thread.
+//!
+//! Tracing is per process: any thread of the tracee may stop, and requests act
+//! on the first stopped one. A thread only notices a signal or an interrupt
+//! when it next enters the kernel, not while it is blocked in a system call.
+//! Children of a tracee are not traced, and only the parent of a tracee is
+//! told of its exit.
+#![deny(missing_docs)]
+
+use super::error::{LxError, LxResult};
+use super::process::{deliver_signal, ProcessExt};
+use super::signal::Signal as LinuxSignal;
+use crate::kernel_hal::UserContext;
+use crate::zircon_object::{
+    ipc::Channel,
+    object::{KernelObject, KoID, Rights, Signal},
+    task::{CurrentThread, ExceptionObject, ExceptionType, Process},
+};
+use alloc::{
+    boxed::Box,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use bitflags::bitflags;
+
+/// Event stop after `execve`, the message is the PID
+pub const PTRACE_EVENT_EXEC: i32 = 4;
+/// Event stop of `PTRACE_INTERRUPT` or of a signal to a seized tracee
+pub const PTRACE_EVENT_STOP: i32 = 128;
+
+bitflags! {
+    /// Options of `PTRACE_SETOPTIONS` and `PTRACE_SEIZE`
+    pub struct PtraceOptions: usize {
+        /// mark system call stops with bit 7 of the signal
+        const TRACESYSGOOD = 1;
+        /// trace the children of `fork`
+        const TRACEFORK = 2;
+        /// trace the children of `vfork`
+        const TRACEVFORK = 4;
+        /// trace the children of `clone`
+        const TRACECLONE = 8;
+        /// stop after `execve` with `PTRACE_EVENT_EXEC`
+        const TRACEEXEC = 0x10;
+        /// stop when the child of `vfork` releases the parent
+        const TRACEVFORKDONE = 0x20;
+        /// stop before exiting
+        const TRACEEXIT = 0x40;
+        /// stop on seccomp filters
+        const TRACESECCOMP = 0x80;
+        /// kill the tracee when the tracer exits
+        const EXITKILL = 0x10_0000;
+        /// the options implemented here
+        const SUPPORTED = Self::TRACESYSGOOD.bits | Self::TRACEEXEC.bits | Self::EXITKILL.bits;
+    }
+}
+
+/// How a stopped tracee is resumed
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Resume {
+    /// run until a signal or an event, `PTRACE_CONT`
+    Continue,
+    /// also stop at the entry and the exit of system calls, `PTRACE_SYSCALL`
+    Syscall,
+    /// also stop after each instruction, `PTRACE_SINGLESTEP`
+    SingleStep,
+}
+
+/// Why a tracee stopped
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum PtraceStop {
+    /// the signal was sent to it, and is delivered only if the tracer says so
+    Signal(LinuxSignal),
+    /// at the entry or the exit of a system call
+    Syscall,
+    /// after an instruction
+    SingleStep,
+    /// on the `PTRACE_EVENT_*` event
+    Event(i32),
+}
+
+impl PtraceStop {
+    /// The stop signal, as in the status reported by `wait4`.
+    fn status(&self, options: PtraceOptions) -> i32 {
+        let sigtrap = LinuxSignal::SIGTRAP as i32;
+        match *self {
+            PtraceStop::Signal(signal) => signal as i32,
+            PtraceStop::Syscall if options.contains(PtraceOptions::TRACESYSGOOD) => sigtrap | 0x80,
+            PtraceStop::Syscall | PtraceStop::SingleStep => sigtrap,
+            PtraceStop::Event(event) => sigtrap | (event << 8),
+        }
+    }
+
+    /// The `si_code` of `PTRACE_GETSIGINFO`.
+    fn code(&self, options: PtraceOptions) -> i32 {
+        match *self {
+            // SI_KERNEL
+            PtraceStop::Signal(_) => 0x80,
+            // TRAP_TRACE
+            PtraceStop::SingleStep => 2,
+            _ => self.status(options),
+        }
+    }
+}
+
+/// The tracing state of a Linux process
+#[derive(Default)]
+pub struct Ptrace {
+    /// The tracer, if the process is traced
+    tracee: Option<Tracee>,
+    /// Threads stopped for the tracer
+    stops: Vec<Stop>,
+    /// Processes traced by this process
+    tracees: Vec<Weak<Process>>,
+}
+
+/// The state of a traced process
+struct Tracee {
+    tracer: Weak<Process>,
+    /// User end of the debug exception channel of the process
+    channel: Arc<Channel>,
+    options: PtraceOptions,
+    /// Attached with `PTRACE_SEIZE`
+    seized: bool,
+    resume: Resume,
+    /// Stop to enter on the next trap
+    pending: Option<PtraceStop>,
+    /// Message of `PTRACE_GETEVENTMSG`
+    event_msg: usize,
+}
+
+/// A thread stopping or stopped for the tracer
+struct Stop {
+    tid: KoID,
+    reason: PtraceStop,
+    /// Registers of the thread, restored when it resumes
+    context: UserContext,
+    /// The exception the thread waits on, once received by the tracer
+    exception: Option<Arc<ExceptionObject>>,
+    /// Reported by `wait4`
+    reported: bool,
+}
+
+impl Ptrace {
+    /// Receive the exceptions of the stopped threads.
+    fn receive(&mut self) {
+        let channel = match &self.tracee {
+            Some(tracee) => tracee.channel.clone(),
+            None => return,
+        };
+        while let Ok(msg) = channel.read() {
+            for handle in msg.handles {
+                let exception = match handle.object.downcast_arc::<ExceptionObject>() {
+                    Ok(exception) => exception,
+                    Err(_) => continue,
+                };
+                let tid = exception.get_thread_handle().object.id();
+                if let Some(stop) = self
+                    .stops
+                    .iter_mut()
+                    .find(|stop| stop.tid == tid && stop.exception.is_none())
+                {
+                    stop.exception = Some(exception);
+                }
+            }
+        }
+    }
+
+    /// Get the first stopped thread.
+    fn stopped(&mut self) -> LxResult<&mut Stop> {
+        self.receive();
+        self.stops
+            .iter_mut()
+            .find(|stop| stop.exception.is_some())
+            .ok_or(LxError::ESRCH)
+    }
+
+    /// Get the tracing state, if the process is traced.
+    fn state(&mut self) -> LxResult<&mut Tracee> {
+        self.tracee.as_mut().ok_or(LxError::ESRCH)
+    }
+}
+
+/// Make `tracer` trace `tracee`, whose permission is checked by the caller.
+///
+/// A tracee attached with `seize` is not stopped by `execve`.
+pub fn attach(
+    tracer: &Arc<Process>,
+    tracee: &Arc<Process>,
+    seize: bool,
+    options: PtraceOptions,
+) -> LxResult {
+    if Arc::ptr_eq(tracer, tracee) {
+        return Err(LxError::EPERM);
+    }
+    if !PtraceOptions::SUPPORTED.contains(options) {
+        return Err(LxError::EINVAL);
+    }
+    let mut ptrace = tracee.linux().ptrace();
+    if ptrace.tracee.is_some() {
+        return Err(LxError::EPERM);
+    }
+    // fails if a Zircon debugger holds the channel
+    let channel = tracee
+        .debug_exceptionate()
+        .create_channel(Rights::empty())
+        .map_err(|_| LxError::EPERM)?;
+    let weak = Arc::downgrade(tracer);
+    channel.add_signal_callback(Box::new(move |signal| {
+        let tracer = match weak.upgrade() {
+            Some(tracer) => tracer,
+            None => return true,
+        };
+        if signal.contains(Signal::READABLE) {
+            tracer.signal_set(Signal::SIGCHLD);
+        }
+        false
+    }));
+    ptrace.tracee = Some(Tracee {
+        tracer: Arc::downgrade(tracer),
+        channel,
+        options,
+        seized: seize,
+        resume: Resume::Continue,
+        pending: None,
+        event_msg: 0,
+    });
+    drop(ptrace);
+    // wake up the tracer waiting for a tracee which is not its child
+    let weak = Arc::downgrade(tracer);
+    tracee.add_signal_callback(Box::new(move |signal| {
+        if !signal.contains(Signal::PROCESS_TERMINATED) {
+            return false;
+        }
+        if let Some(tracer) = weak.upgrade() {
+            tracer.signal_set(Signal::SIGCHLD);
+        }
+        true
+    }));
+    tracer.linux().ptrace().tracees.push(Arc::downgrade(tracee));
+    Ok(())
+}
+
+/// Stop tracing `tracee`, resuming its stopped threads.
+pub fn detach(tracee: &Arc<Process>) {
+    let mut ptrace = tracee.linux().ptrace();
+    let state = match ptrace.tracee.take() {
+        Some(state) => state,
+        None => return,
+    };
+    let exceptions: Vec<_> = ptrace
+        .stops
+        .iter_mut()
+        .filter_map(|stop| stop.exception.take())
+        .collect();
+    drop(ptrace);
+    for exception in exceptions {
+        exception.set_state(1).unwrap();
+    }
+    if let Some(tracer) = state.tracer.upgrade() {
+        tracer.linux().ptrace().tracees.retain(|proc| {
+            proc.upgrade()
+                .map_or(false, |proc| proc.id() != tracee.id())
+        });
+    }
+    // closing the channel resumes the threads whose stop was not received
+    drop(state);
+}
+
+/// Stop tracing the processes traced by `tracer` as it exits, or kill them
+/// if they asked for `EXITKILL`.
+pub fn release(tracer: &Process) {
+    let tracees: Vec<Arc<Process>> = tracer
+        .linux()
+        .ptrace()
+        .tracees
+        .drain(..)
+        .filter_map(|proc| proc.upgrade())
+        .collect();
+    for tracee in tracees.iter() {
+        let kill = tracee
+            .linux()
+            .ptrace()
+            .tracee
+            .as_ref()
+            .map_or(false, |state| {
+                state.options.contains(PtraceOptions::EXITKILL)
+            });
+        detach(tracee);
+        if kill {
+            deliver_signal(tracee, LinuxSignal::SIGKILL);
+        }
+    }
+}
+
+/// Get the process `pid` if it is traced by `tracer`.
+pub fn tracee_of(tracer: &Process, pid: KoID) -> LxResult<Arc<Process>> {
+    tracees(tracer)
+        .into_iter()
+        .find(|proc| proc.id() == pid)
+        .ok_or(LxError::ESRCH)
+}
+
+/// Get the live processes traced by `tracer`.
+pub fn tracees(tracer: &Process) -> Vec<Arc<Process>> {
+    let mut ptrace = tracer.linux().ptrace();
+    ptrace.tracees.retain(|proc| match proc.upgrade() {
+        Some(proc) => !proc.signal().contains(Signal::PROCESS_TERMINATED),
+        None => false,
+    });
+    ptrace
+        .tracees
+        .iter()
+        .filter_map(|proc| proc.upgrade())
+        .collect()
+}
+
+/// Get the status of a stop of `tracee` not yet reported by `wait4`, and
+/// mark it reported if `consume`.
+pub fn stop_status(tracee: &Process, consume: bool) -> Option<i32> {
+    let mut ptrace = tracee.linux().ptrace();
+    ptrace.receive();
+    let options = ptrace.tracee.as_ref()?.options;
+    let stop = ptrace
+        .stops
+        .iter_mut()
+        .find(|stop| stop.exception.is_some() && !stop.reported)?;
+    if consume {
+        stop.reported = true;
+    }
+    Some(stop.reason.status(options))
+}
+
+/// Whether a thread of `tracee` is stopped or stopping for the tracer.
+pub fn is_stopped(tracee: &Process) -> bool {
+    !tracee.linux().ptrace().stops.is_empty()
+}
+
+/// Get the `si_signo` and `si_code` of the stop of `tracee`.
+pub fn stop_siginfo(tracee: &Process) -> LxResult<(LinuxSignal, i32)> {
+    let mut ptrace = tracee.linux().ptrace();
+    let options = ptrace.state()?.options;
+    let reason = ptrace.stopped()?.reason;
+    let signal = match reason {
+        PtraceStop::Signal(signal) => signal,
+        _ => LinuxSignal::SIGTRAP,
+    };
+    Ok((signal, reason.code(options)))
+}
+
+/// Get the registers of the stopped thread of `tracee`.
+pub fn stopped_context(tracee: &Process) -> LxResult<UserContext> {
+    Ok(tracee.linux().ptrace().stopped()?.context)
+}
+
+/// Set the registers of the stopped thread of `tracee`.
+pub fn set_stopped_context(tracee: &Process, context: UserContext) -> LxResult {
+    tracee.linux().ptrace().stopped()?.context = context;
+    Ok(())
+}
+
+/// Check that a thread of `tracee` is stopped.
+pub fn check_stopped(tracee: &Process) -> LxResult {
+    tracee.linux().ptrace().stopped().map(|_| ())
+}
+
+/// Set the options of `tracee`.
+pub fn set_options(tracee: &Process, options: PtraceOptions) -> LxResult {
+    if !PtraceOptions::SUPPORTED.contains(options) {
+        return Err(LxError::EINVAL);
+    }
+    tracee.linux().ptrace().state()?.options = options;
+    Ok(())
+}
+
+/// Get the message of the last event stop of `tracee`.
+pub fn event_msg(tracee: &Process) -> LxResult<usize> {
+    Ok(tracee.linux().ptrace().state()?.event_msg)
+}
+
+/// Resume the stopped thread of `tracee`, delivering `signal` to it.
+pub fn resume(tracee: &Arc<Process>, resume: Resume, signal: Option<LinuxSignal>) -> LxResult {
+    let mut ptrace = tracee.linux().ptrace();
+    ptrace.state()?.resume = resume;
+    let exception = ptrace.stopped()?.exception.take().unwrap();
+    drop(ptrace);
+    exception.set_state(1).unwrap();
+    drop(exception);
+    if let Some(signal) = signal {
+        deliver_signal(tracee, signal);
+    }
+    Ok(())
+}
+
+/// Stop a seized `tracee` on its next trap, with `PTRACE_EVENT_STOP`.
+pub fn interrupt(tracee: &Process) -> LxResult {
+    let mut ptrace = tracee.linux().ptrace();
+    let state = ptrace.state()?;
+    if !state.seized {
+        return Err(LxError::EIO);
+    }
+    state.pending = Some(PtraceStop::Event(PTRACE_EVENT_STOP));
+    Ok(())
+}
+
+/// Turn `signal` to the traced `proc` into a stop, so that the tracer decides
+/// whether to deliver it.
+///
+/// Returns `false` if `proc` is not traced or the signal is `SIGKILL`.
+pub fn intercept_signal(proc: &Process, signal: LinuxSignal) -> bool {
+    if signal == LinuxSignal::SIGKILL {
+        return false;
+    }
+    match proc.linux().ptrace().tracee.as_mut() {
+        Some(state) => {
+            state.pending = Some(PtraceStop::Signal(signal));
+            true
+        }
+        None => false,
+    }
+}
+
+/// Whether `execve` in `proc` may take the IDs of a set-user-ID or
+/// set-group-ID file.
+///
+/// A traced process may not, unless its tracer is privileged: the tracer
+/// would keep control of a process it has no permission to trace.
+pub fn exec_may_set_ids(proc: &Process) -> bool {
+    let tracer = match proc.linux().ptrace().tracee.as_ref() {
+        Some(state) => state.tracer.upgrade(),
+        None => return true,
+    };
+    tracer.map_or(true, |tracer| tracer.linux().credentials().is_privileged())
+}
+
+/// Stop the traced `proc` after a successful `execve`.
+pub fn exec_event(proc: &Process) {
+    let mut ptrace = proc.linux().ptrace();
+    let state = match ptrace.tracee.as_mut() {
+        Some(state) => state,
+        None => return,
+    };
+    if state.options.contains(PtraceOptions::TRACEEXEC) {
+        state.event_msg = proc.id() as usize;
+        state.pending = Some(PtraceStop::Event(PTRACE_EVENT_EXEC));
+    } else if !state.seized {
+        state.pending = Some(PtraceStop::Signal(LinuxSignal::SIGTRAP));
+    }
+}
+
+/// Enter the stop pending for the process of `thread`, if any.
+pub async fn check_stop(thread: &CurrentThread, cx: &mut UserContext) {
+    let pending = match thread.proc().linux().ptrace().tracee.as_mut() {
+        Some(state) => state.pending.take(),
+        None => return,
+    };
+    if let Some(reason) = pending {
+        stop(thread, cx, reason).await;
+    }
+}
+
+/// Stop at the entry or the exit of a system call if the tracer asked to.
+pub async fn syscall_stop(thread: &CurrentThread, cx: &mut UserContext) {
+    if resumed_with(thread, Resume::Syscall) {
+        stop(thread, cx, PtraceStop::Syscall).await;
+    }
+}
+
+/// Stop after an instruction if the tracer asked to.
+pub async fn single_step_stop(thread: &CurrentThread, cx: &mut UserContext) {
+    if resumed_with(thread, Resume::SingleStep) {
+        stop(thread, cx, PtraceStop::SingleStep).await;
+    }
+}
+
+fn resumed_with(thread: &CurrentThread, resume: Resume) -> bool {
+    let ptrace = thread.proc().linux().ptrace();
+    matches!(&ptrace.tracee, Some(state) if state.resume == resume)
+}
+
+/// Stop `thread` with the registers `cx` until the tracer resumes it.
+async fn stop(thread: &CurrentThread, cx: &mut UserContext, reason: PtraceStop) {
+    let proc = thread.proc();
+    info!("ptrace: thread {} stops on {:?}", thread.id(), reason);
+    proc.linux().ptrace().stops.push(Stop {
+        tid: thread.id(),
+        reason,
+        context: *cx,
+        exception: None,
+        reported: false,
+    });
+    thread.handle_exception(ExceptionType::Synth, None).await;
+    let mut ptrace = proc.linux().ptrace();
+    if let Some(index) = ptrace.stops.iter().position(|stop| stop.tid == thread.id()) {
+        *cx = ptrace.stops.remove(index).context;
+    }
+    // the trap flag makes the CPU trap after the next instruction
+    #[cfg(target_arch = "x86_64")]
+    {
+        const TF: usize = 0x100;
+        let single_step =
+            matches!(&ptrace.tracee, Some(state) if state.resume == Resume::SingleStep);
+        if single_step {
+            cx.general.rflags |= TF;
+        } else {
+            cx.general.rflags &= !TF;
+        }
+    }
+}
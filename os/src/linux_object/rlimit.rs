@@ -0,0 +1,148 @@
+//! Resource limits
+//!
+//! Each process has a soft and a hard limit for every resource, inherited on
+//! fork. The soft limit is the one enforced, and it can be raised up to the
+//! hard limit. Only a privileged process can raise a hard limit.
+
+use super::error::*;
+use crate::zircon_object::vm::{pages, PAGE_SIZE};
+use core::cmp::{max, min};
+
+/// CPU time in seconds
+pub const RLIMIT_CPU: usize = 0;
+/// Max size of a file
+pub const RLIMIT_FSIZE: usize = 1;
+/// Max size of the data segment
+pub const RLIMIT_DATA: usize = 2;
+/// Max size of the stack
+pub const RLIMIT_STACK: usize = 3;
+/// Max size of a core file
+pub const RLIMIT_CORE: usize = 4;
+/// Max resident set size
+pub const RLIMIT_RSS: usize = 5;
+/// Max number of threads of the real user ID
+pub const RLIMIT_NPROC: usize = 6;
+/// Max file descriptor number plus one
+pub const RLIMIT_NOFILE: usize = 7;
+/// Max size of locked memory
+pub const RLIMIT_MEMLOCK: usize = 8;
+/// Max size of the address space
+pub const RLIMIT_AS: usize = 9;
+/// Max number of file locks
+pub const RLIMIT_LOCKS: usize = 10;
+/// Max number of pending signals
+pub const RLIMIT_SIGPENDING: usize = 11;
+/// Max size of POSIX message queues
+pub const RLIMIT_MSGQUEUE: usize = 12;
+/// Ceiling of the nice value
+pub const RLIMIT_NICE: usize = 13;
+/// Ceiling of the real-time priority
+pub const RLIMIT_RTPRIO: usize = 14;
+/// CPU time in microseconds of a real-time task without blocking
+pub const RLIMIT_RTTIME: usize = 15;
+/// Number of resources
+pub const RLIM_NLIMITS: usize = 16;
+
+/// No limit
+pub const RLIM_INFINITY: u64 = u64::MAX;
+
+/// Max number of file descriptors a limit can be raised to
+const NR_OPEN: u64 = 1024 * 1024;
+
+/// Min number of pages of the user stack
+const USER_STACK_PAGES_MIN: usize = 8;
+/// Max number of pages of the user stack
+///
+/// The stack is committed when it is mapped, so it is not as large as the
+/// default limit of 8 MB.
+const USER_STACK_PAGES_MAX: usize = 256;
+
+/// resource limit
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct RLimit {
+    /// soft limit
+    pub cur: u64,
+    /// hard limit
+    pub max: u64,
+}
+
+impl RLimit {
+    /// Create a limit with soft limit `cur` and hard limit `max`.
+    pub const fn new(cur: u64, max: u64) -> Self {
+        RLimit { cur, max }
+    }
+
+    /// No limit.
+    pub const fn infinity() -> Self {
+        RLimit::new(RLIM_INFINITY, RLIM_INFINITY)
+    }
+
+    /// Whether `value` is beyond the soft limit.
+    pub fn exceeded_by(&self, value: u64) -> bool {
+        self.cur != RLIM_INFINITY && value >= self.cur
+    }
+}
+
+/// The resource limits of a process
+#[derive(Debug, Copy, Clone)]
+pub struct RLimits {
+    table: [RLimit; RLIM_NLIMITS],
+}
+
+impl Default for RLimits {
+    /// the defaults of Linux
+    fn default() -> Self {
+        let mut table = [RLimit::infinity(); RLIM_NLIMITS];
+        table[RLIMIT_STACK] = RLimit::new(8 * 1024 * 1024, RLIM_INFINITY);
+        table[RLIMIT_CORE] = RLimit::new(0, RLIM_INFINITY);
+        table[RLIMIT_NPROC] = RLimit::new(4096, 4096);
+        table[RLIMIT_NOFILE] = RLimit::new(1024, 1024);
+        table[RLIMIT_MEMLOCK] = RLimit::new(64 * 1024, 64 * 1024);
+        table[RLIMIT_SIGPENDING] = RLimit::new(4096, 4096);
+        table[RLIMIT_MSGQUEUE] = RLimit::new(819_200, 819_200);
+        table[RLIMIT_NICE] = RLimit::new(0, 0);
+        table[RLIMIT_RTPRIO] = RLimit::new(0, 0);
+        RLimits { table }
+    }
+}
+
+impl RLimits {
+    /// Get the limit of `resource`.
+    ///
+    /// Panics if `resource` is not one of `RLIMIT_*`.
+    pub fn get(&self, resource: usize) -> RLimit {
+        self.table[resource]
+    }
+
+    /// Set the limit of `resource`.
+    ///
+    /// The soft limit can not be beyond the hard limit, which can only be
+    /// raised by a `privileged` process.
+    pub fn set(&mut self, resource: usize, limit: RLimit, privileged: bool) -> LxResult {
+        let old = self.table.get(resource).ok_or(LxError::EINVAL)?;
+        if limit.cur > limit.max {
+            return Err(LxError::EINVAL);
+        }
+        if limit.max > old.max && !privileged {
+            return Err(LxError::EPERM);
+        }
+        if resource == RLIMIT_NOFILE && limit.max > NR_OPEN {
+            return Err(LxError::EPERM);
+        }
+        self.table[resource] = limit;
+        Ok(())
+    }
+
+    /// Get the number of pages of the user stack.
+    pub fn stack_pages(&self) -> usize {
+        let size_max = (USER_STACK_PAGES_MAX * PAGE_SIZE) as u64;
+        let size = min(self.table[RLIMIT_STACK].cur, size_max) as usize;
+        max(pages(size), USER_STACK_PAGES_MIN)
+    }
+
+    /// Get the max total size of mappings in the address space.
+    pub fn space_limit(&self) -> usize {
+        min(self.table[RLIMIT_AS].cur, usize::MAX as u64) as usize
+    }
+}
@@ -48,7 +48,7 @@ pub struct SignalAction {
 #[derive(Copy, Clone)]
 pub union SiginfoFields {
     pad: [u8; Self::PAD_SIZE],
-    // TODO: fill this union
+    pub sigchld: SigChldFields,
 }
 
 impl SiginfoFields {
@@ -63,6 +63,20 @@ impl Default for SiginfoFields {
     }
 }
 
+/// The fields of `SigInfo` for `SIGCHLD`
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct SigChldFields {
+    pub pid: i32,
+    pub uid: u32,
+    /// exit code or signal
+    pub status: i32,
+    /// user time in clock ticks
+    pub utime: isize,
+    /// system time in clock ticks
+    pub stime: isize,
+}
+
 #[repr(C)]
 #[derive(Copy, Clone)]
 pub struct SigInfo {
@@ -75,6 +89,7 @@ pub struct SigInfo {
 /// A code identifying the cause of the signal.
 #[repr(i32)]
 #[derive(Debug, Copy, Clone)]
+#[allow(non_camel_case_types)]
 pub enum SignalCode {
     ASYNCNL = -60,
     TKILL = -6,
@@ -87,6 +102,18 @@ pub enum SignalCode {
     USER = 0,
     /// from kernel
     KERNEL = 128,
+    /// `SIGCHLD`: child has exited
+    CLD_EXITED = 1,
+    /// `SIGCHLD`: child was killed
+    CLD_KILLED = 2,
+    /// `SIGCHLD`: child terminated abnormally
+    CLD_DUMPED = 3,
+    /// `SIGCHLD`: traced child has trapped
+    CLD_TRAPPED = 4,
+    /// `SIGCHLD`: child has stopped
+    CLD_STOPPED = 5,
+    /// `SIGCHLD`: stopped child has continued
+    CLD_CONTINUED = 6,
 }
 
 bitflags! {
@@ -1,11 +1,13 @@
 //! Linux Thread
 
-use super::process::ProcessExt;
+use super::futex::{exit_robust_list, RobustListHead};
+use super::process::{CloneFlags, ProcessExt};
 use super::signal::{SignalStack, Sigset};
 use alloc::sync::Arc;
-use crate::kernel_hal::user::{Out, UserOutPtr, UserPtr};
-use crate::kernel_hal::VirtAddr;
+use crate::kernel_hal::user::{Out, UserInPtr, UserOutPtr, UserPtr};
+use crate::kernel_hal::{GeneralRegs, UserContext, VirtAddr};
 use spin::{Mutex, MutexGuard};
+use crate::zircon_object::object::KernelObject;
 use crate::zircon_object::task::{CurrentThread, Process, Thread};
 use crate::zircon_object::ZxResult;
 
@@ -17,18 +19,25 @@ pub trait ThreadExt {
     fn lock_linux(&self) -> MutexGuard<'_, LinuxThread>;
     /// Set pointer to thread ID.
     fn set_tid_address(&self, tidptr: UserOutPtr<i32>);
+    /// Set pointer to the head of the robust futex list.
+    fn set_robust_list(&self, head: UserInPtr<RobustListHead>);
+    /// Get address of the head of the robust futex list.
+    fn robust_list(&self) -> VirtAddr;
 }
 
 /// CurrentThread extension for linux
 pub trait CurrentThreadExt {
     /// exit linux thread
     fn exit_linux(&self, exit_code: i32);
+    /// release the robust futexes held by the thread
+    fn release_robust_futexes(&self);
 }
 
 impl ThreadExt for Thread {
     fn create_linux(proc: &Arc<Process>) -> ZxResult<Arc<Self>> {
         let linux_thread = Mutex::new(LinuxThread {
             clear_child_tid: 0.into(),
+            robust_list: 0.into(),
             signal_mask: Sigset::default(),
             signal_alternate_stack: SignalStack::default(),
         });
@@ -46,11 +55,20 @@ impl ThreadExt for Thread {
     fn set_tid_address(&self, tidptr: UserPtr<i32, Out>) {
         self.lock_linux().clear_child_tid = tidptr;
     }
+
+    fn set_robust_list(&self, head: UserInPtr<RobustListHead>) {
+        self.lock_linux().robust_list = head;
+    }
+
+    fn robust_list(&self) -> VirtAddr {
+        self.lock_linux().robust_list.as_ptr() as VirtAddr
+    }
 }
 
 impl CurrentThreadExt for CurrentThread {
     /// Exit current thread for Linux.
     fn exit_linux(&self, _exit_code: i32) {
+        self.release_robust_futexes();
         let mut linux_thread = self.lock_linux();
         let clear_child_tid = &mut linux_thread.clear_child_tid;
         // perform futex wake 1
@@ -64,6 +82,74 @@ impl CurrentThreadExt for CurrentThread {
         }
         self.exit();
     }
+
+    /// Release the robust futexes held by the thread, once.
+    ///
+    /// Ref: [http://man7.org/linux/man-pages/man2/set_robust_list.2.html]
+    fn release_robust_futexes(&self) {
+        let head = core::mem::replace(&mut self.lock_linux().robust_list, 0.into());
+        if head.is_null() {
+            return;
+        }
+        if let Err(err) = exit_robust_list(self.proc(), self.id() as u32, head) {
+            warn!("exit: bad robust futex list: {:?}", err);
+        }
+    }
+}
+
+/// Build the context of the thread created by `clone` from the caller's `context`.
+///
+/// The child returns 0 and runs on the stack `newsp`, or the caller's if 0.
+/// With `CLONE_SETTLS` its thread pointer is set to `newtls`.
+#[cfg(target_arch = "x86_64")]
+pub fn clone_context(
+    context: &UserContext,
+    flags: CloneFlags,
+    newsp: usize,
+    newtls: usize,
+) -> UserContext {
+    let regs = &context.general;
+    UserContext {
+        general: GeneralRegs {
+            rax: 0,
+            rsp: if newsp != 0 { newsp } else { regs.rsp },
+            fsbase: if flags.contains(CloneFlags::SETTLS) {
+                newtls
+            } else {
+                regs.fsbase
+            },
+            ..*regs
+        },
+        ..*context
+    }
+}
+
+/// Build the context of the thread created by `clone` from the caller's `context`.
+///
+/// The child returns 0 and runs on the stack `newsp`, or the caller's if 0.
+/// With `CLONE_SETTLS` its thread pointer is set to `newtls`.
+#[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+pub fn clone_context(
+    context: &UserContext,
+    flags: CloneFlags,
+    newsp: usize,
+    newtls: usize,
+) -> UserContext {
+    let regs = &context.general;
+    UserContext {
+        general: GeneralRegs {
+            a0: 0,
+            sp: if newsp != 0 { newsp } else { regs.sp },
+            // `tp` is the thread pointer
+            tp: if flags.contains(CloneFlags::SETTLS) {
+                newtls
+            } else {
+                regs.tp
+            },
+            ..*regs
+        },
+        ..*context
+    }
 }
 
 /// Linux specific thread information.
@@ -71,6 +157,8 @@ pub struct LinuxThread {
     /// Kernel performs futex wake when thread exits.
     /// Ref: [http://man7.org/linux/man-pages/man2/set_tid_address.2.html]
     clear_child_tid: UserOutPtr<i32>,
+    /// Kernel releases the robust futexes in the list when thread exits.
+    robust_list: UserInPtr<RobustListHead>,
     /// Signal mask
     pub signal_mask: Sigset,
     /// signal alternate stack
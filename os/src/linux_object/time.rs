@@ -80,6 +80,15 @@ impl Into<TimeVal> for TimeSpec {
     }
 }
 
+impl From<Duration> for TimeVal {
+    fn from(duration: Duration) -> Self {
+        TimeVal {
+            sec: duration.as_secs() as usize,
+            usec: duration.subsec_micros() as usize,
+        }
+    }
+}
+
 impl Default for TimeVal {
     fn default() -> Self {
         TimeVal { sec: 0, usec: 0 }
@@ -93,13 +102,36 @@ impl Default for TimeSpec {
 }
 
 /// RUsage for sys_getrusage()
-/// ignore other fields for now
+/// only CPU times are kept track of
 #[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
 pub struct RUsage {
     /// user CPU time used
     pub utime: TimeVal,
     /// system CPU time used
     pub stime: TimeVal,
+    /// max RSS, page faults, block I/O, IPC and context switches, always 0
+    pub other: [isize; 14],
+}
+
+impl RUsage {
+    /// Create a RUsage with `time` nanoseconds of CPU time.
+    ///
+    /// The time in kernel mode is not told apart, all of it is user time.
+    pub fn from_cpu_time(time: u64) -> Self {
+        RUsage {
+            utime: Duration::from_nanos(time).into(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Clock ticks per second, as `sysconf(_SC_CLK_TCK)`
+pub const CLOCK_TICKS_PER_SEC: u64 = 100;
+
+/// Convert `time` nanoseconds of CPU time to clock ticks.
+pub fn clock_ticks(time: u64) -> u64 {
+    time / (1_000_000_000 / CLOCK_TICKS_PER_SEC)
 }
 
 /// Tms for times()
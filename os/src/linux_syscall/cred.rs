@@ -0,0 +1,197 @@
+//! Syscalls for credentials
+//!
+//! - getuid, geteuid, getgid, getegid
+//! - setuid, setgid, setreuid, setregid
+//! - getresuid, getresgid, setresuid, setresgid
+//! - setfsuid, setfsgid
+//! - getgroups, setgroups
+
+use super::*;
+use crate::linux_object::cred::{IdSet, NGROUPS_MAX};
+use alloc::vec::Vec;
+
+/// Convert an ID argument, where -1 means no change.
+fn optional_id(id: usize) -> Option<u32> {
+    Some(id as u32).filter(|&id| id != u32::MAX)
+}
+
+impl Syscall<'_> {
+    /// get the real user ID
+    pub fn sys_getuid(&self) -> SysResult {
+        info!("getuid:");
+        Ok(self.linux_process().credentials().user.real as usize)
+    }
+
+    /// get the effective user ID
+    pub fn sys_geteuid(&self) -> SysResult {
+        info!("geteuid:");
+        Ok(self.linux_process().credentials().user.effective as usize)
+    }
+
+    /// get the real group ID
+    pub fn sys_getgid(&self) -> SysResult {
+        info!("getgid:");
+        Ok(self.linux_process().credentials().group.real as usize)
+    }
+
+    /// get the effective group ID
+    pub fn sys_getegid(&self) -> SysResult {
+        info!("getegid:");
+        Ok(self.linux_process().credentials().group.effective as usize)
+    }
+
+    /// Change the user or group IDs of the calling process with `f`.
+    fn change_ids(&self, group: bool, f: impl FnOnce(&mut IdSet, bool) -> LxResult) -> SysResult {
+        let proc = self.linux_process();
+        let mut cred = proc.credentials();
+        let privileged = cred.is_privileged();
+        let ids = if group {
+            &mut cred.group
+        } else {
+            &mut cred.user
+        };
+        f(ids, privileged)?;
+        proc.set_credentials(cred);
+        Ok(0)
+    }
+
+    /// set the user ID
+    pub fn sys_setuid(&self, uid: usize) -> SysResult {
+        info!("setuid: uid={}", uid);
+        self.change_ids(false, |ids, privileged| ids.set(uid as u32, privileged))
+    }
+
+    /// set the group ID
+    pub fn sys_setgid(&self, gid: usize) -> SysResult {
+        info!("setgid: gid={}", gid);
+        self.change_ids(true, |ids, privileged| ids.set(gid as u32, privileged))
+    }
+
+    /// set the real and effective user IDs, -1 for no change
+    pub fn sys_setreuid(&self, ruid: usize, euid: usize) -> SysResult {
+        info!("setreuid: ruid={}, euid={}", ruid as i32, euid as i32);
+        self.change_ids(false, |ids, privileged| {
+            ids.set_re(optional_id(ruid), optional_id(euid), privileged)
+        })
+    }
+
+    /// set the real and effective group IDs, -1 for no change
+    pub fn sys_setregid(&self, rgid: usize, egid: usize) -> SysResult {
+        info!("setregid: rgid={}, egid={}", rgid as i32, egid as i32);
+        self.change_ids(true, |ids, privileged| {
+            ids.set_re(optional_id(rgid), optional_id(egid), privileged)
+        })
+    }
+
+    /// set the real, effective and saved user IDs, -1 for no change
+    pub fn sys_setresuid(&self, ruid: usize, euid: usize, suid: usize) -> SysResult {
+        info!(
+            "setresuid: ruid={}, euid={}, suid={}",
+            ruid as i32, euid as i32, suid as i32
+        );
+        self.change_ids(false, |ids, privileged| {
+            let (r, e, s) = (optional_id(ruid), optional_id(euid), optional_id(suid));
+            ids.set_res(r, e, s, privileged)
+        })
+    }
+
+    /// set the real, effective and saved group IDs, -1 for no change
+    pub fn sys_setresgid(&self, rgid: usize, egid: usize, sgid: usize) -> SysResult {
+        info!(
+            "setresgid: rgid={}, egid={}, sgid={}",
+            rgid as i32, egid as i32, sgid as i32
+        );
+        self.change_ids(true, |ids, privileged| {
+            let (r, e, s) = (optional_id(rgid), optional_id(egid), optional_id(sgid));
+            ids.set_res(r, e, s, privileged)
+        })
+    }
+
+    /// get the real, effective and saved user IDs
+    pub fn sys_getresuid(
+        &self,
+        mut ruid: UserOutPtr<u32>,
+        mut euid: UserOutPtr<u32>,
+        mut suid: UserOutPtr<u32>,
+    ) -> SysResult {
+        info!("getresuid:");
+        let ids = self.linux_process().credentials().user;
+        ruid.write(ids.real)?;
+        euid.write(ids.effective)?;
+        suid.write(ids.saved)?;
+        Ok(0)
+    }
+
+    /// get the real, effective and saved group IDs
+    pub fn sys_getresgid(
+        &self,
+        mut rgid: UserOutPtr<u32>,
+        mut egid: UserOutPtr<u32>,
+        mut sgid: UserOutPtr<u32>,
+    ) -> SysResult {
+        info!("getresgid:");
+        let ids = self.linux_process().credentials().group;
+        rgid.write(ids.real)?;
+        egid.write(ids.effective)?;
+        sgid.write(ids.saved)?;
+        Ok(0)
+    }
+
+    /// set the user ID for file access, return the old one
+    pub fn sys_setfsuid(&self, fsuid: usize) -> SysResult {
+        info!("setfsuid: fsuid={}", fsuid as i32);
+        let proc = self.linux_process();
+        let mut cred = proc.credentials();
+        let privileged = cred.is_privileged();
+        let old = cred.user.set_fs(fsuid as u32, privileged);
+        proc.set_credentials(cred);
+        Ok(old as usize)
+    }
+
+    /// set the group ID for file access, return the old one
+    pub fn sys_setfsgid(&self, fsgid: usize) -> SysResult {
+        info!("setfsgid: fsgid={}", fsgid as i32);
+        let proc = self.linux_process();
+        let mut cred = proc.credentials();
+        let privileged = cred.is_privileged();
+        let old = cred.group.set_fs(fsgid as u32, privileged);
+        proc.set_credentials(cred);
+        Ok(old as usize)
+    }
+
+    /// get the supplementary group IDs
+    ///
+    /// With `size` 0 only the number of groups is returned.
+    pub fn sys_getgroups(&self, size: usize, mut list: UserOutPtr<u32>) -> SysResult {
+        info!("getgroups: size={}, list={:?}", size, list);
+        let groups = self.linux_process().credentials().groups;
+        if size == 0 {
+            return Ok(groups.len());
+        }
+        if size < groups.len() {
+            return Err(LxError::EINVAL);
+        }
+        list.write_array(&groups)?;
+        Ok(groups.len())
+    }
+
+    /// set the supplementary group IDs
+    pub fn sys_setgroups(&self, size: usize, list: UserInPtr<u32>) -> SysResult {
+        info!("setgroups: size={}, list={:?}", size, list);
+        if size > NGROUPS_MAX {
+            return Err(LxError::EINVAL);
+        }
+        let proc = self.linux_process();
+        let mut cred = proc.credentials();
+        if !cred.is_privileged() {
+            return Err(LxError::EPERM);
+        }
+        cred.groups = if size == 0 {
+            Vec::new()
+        } else {
+            list.read_array(size)?
+        };
+        proc.set_credentials(cred);
+        Ok(0)
+    }
+}
@@ -2,10 +2,12 @@
 //!
 //! - getcwd
 //! - chdir
+//! - umask
 //! - mkdir(at)
 //! - rmdir(at)
 //! - getdents64
 //! - link(at)
+//! - symlink(at)
 //! - unlink(at)
 //! - rename(at)
 //! - readlink(at)
@@ -13,7 +15,11 @@
 use super::*;
 use bitflags::bitflags;
 use crate::kernel_hal::user::UserOutPtr;
+use crate::linux_object::cred::Access;
+use crate::linux_object::fs::inotify::{new_cookie, notify_entry, notify_inode, InotifyMask};
+use crate::linux_object::fs::mount::check_writable;
 use crate::linux_object::fs::vfs::FileType;
+use crate::linux_object::fs::xattr;
 
 impl Syscall<'_> {
     /// return a null-terminated string containing an absolute pathname
@@ -43,10 +49,17 @@ impl Syscall<'_> {
         if info.type_ != FileType::Dir {
             return Err(LxError::ENOTDIR);
         }
-        proc.change_directory(&path);
+        proc.credentials().check_access(&info, Access::EXEC)?;
+        proc.change_directory(&path, inode);
         Ok(0)
     }
 
+    /// Set the file mode creation mask, and return the old one.
+    pub fn sys_umask(&self, mask: usize) -> SysResult {
+        info!("umask: mask={:#o}", mask);
+        Ok(self.linux_process().set_umask(mask as u32) as usize)
+    }
+
     /// Make a directory.
     /// - path – pointer to string with directory name
     /// - mode – file system permissions mode
@@ -69,7 +82,13 @@ impl Syscall<'_> {
         if inode.find(file_name).is_ok() {
             return Err(LxError::EEXIST);
         }
-        inode.create(file_name, FileType::Dir, mode as u32)?;
+        check_writable(&inode)?;
+        let cred = proc.credentials();
+        cred.check_inode(&inode, Access::WRITE | Access::EXEC)?;
+        let mode = mode as u32 & !proc.umask();
+        let dir_inode = inode.create(file_name, FileType::Dir, mode)?;
+        cred.set_owner(&dir_inode)?;
+        notify_entry(&inode, file_name, true, InotifyMask::CREATE, 0);
         Ok(0)
     }
     /// Remove a directory.
@@ -85,7 +104,12 @@ impl Syscall<'_> {
         if file_inode.metadata()?.type_ != FileType::Dir {
             return Err(LxError::ENOTDIR);
         }
+        check_writable(&dir_inode)?;
+        proc.credentials().check_delete(&dir_inode, &file_inode)?;
         dir_inode.unlink(file_name)?;
+        notify_entry(&dir_inode, file_name, true, InotifyMask::DELETE, 0);
+        notify_inode(&file_inode, InotifyMask::DELETE_SELF);
+        xattr::release(&file_inode);
         Ok(0)
     }
 
@@ -151,9 +175,63 @@ impl Syscall<'_> {
 
         let proc = self.linux_process();
         let (new_dir_path, new_file_name) = split_path(&newpath);
-        let inode = proc.lookup_inode_at(olddirfd, &oldpath, true)?;
+        let follow = flags.contains(AtFlags::SYMLINK_FOLLOW);
+        let inode = proc.lookup_inode_at(olddirfd, &oldpath, follow)?;
         let new_dir_inode = proc.lookup_inode_at(newdirfd, new_dir_path, true)?;
+        check_writable(&new_dir_inode)?;
+        proc.credentials()
+            .check_inode(&new_dir_inode, Access::WRITE | Access::EXEC)?;
         new_dir_inode.link(new_file_name, &inode)?;
+        notify_inode(&inode, InotifyMask::ATTRIB);
+        notify_entry(&new_dir_inode, new_file_name, false, InotifyMask::CREATE, 0);
+        Ok(0)
+    }
+
+    /// make a new name for a file, as a symbolic link
+    pub fn sys_symlink(&self, target: UserInPtr<u8>, linkpath: UserInPtr<u8>) -> SysResult {
+        self.sys_symlinkat(target, FileDesc::CWD, linkpath)
+    }
+
+    /// create a symbolic link relative to directory file descriptor
+    /// The link `linkpath` contains the string `target`, which is not checked.
+    pub fn sys_symlinkat(
+        &self,
+        target: UserInPtr<u8>,
+        newdirfd: FileDesc,
+        linkpath: UserInPtr<u8>,
+    ) -> SysResult {
+        let target = target.read_cstring()?;
+        let linkpath = linkpath.read_cstring()?;
+        info!(
+            "symlinkat: target={:?}, newdirfd={:?}, linkpath={:?}",
+            target, newdirfd, linkpath
+        );
+        if target.is_empty() {
+            return Err(LxError::ENOENT);
+        }
+
+        let proc = self.linux_process();
+        let (dir_path, file_name) = split_path(&linkpath);
+        let dir_inode = proc.lookup_inode_at(newdirfd, dir_path, true)?;
+        check_writable(&dir_inode)?;
+        let cred = proc.credentials();
+        cred.check_inode(&dir_inode, Access::WRITE | Access::EXEC)?;
+        if dir_inode.find(file_name).is_ok() {
+            return Err(LxError::EEXIST);
+        }
+        let inode = dir_inode
+            .create(file_name, FileType::SymLink, 0o777)
+            .map_err(|err| match err {
+                // the filesystem does not support symbolic links
+                FsError::NotSupported => LxError::EPERM,
+                err => err.into(),
+            })?;
+        if let Err(err) = inode.write_at(0, target.as_bytes()) {
+            dir_inode.unlink(file_name).ok();
+            return Err(err.into());
+        }
+        cred.set_owner(&inode)?;
+        notify_entry(&dir_inode, file_name, false, InotifyMask::CREATE, 0);
         Ok(0)
     }
 
@@ -182,7 +260,18 @@ impl Syscall<'_> {
         if file_inode.metadata()?.type_ == FileType::Dir {
             return Err(LxError::EISDIR);
         }
+        check_writable(&dir_inode)?;
+        proc.credentials().check_delete(&dir_inode, &file_inode)?;
         dir_inode.unlink(file_name)?;
+        notify_entry(&dir_inode, file_name, false, InotifyMask::DELETE, 0);
+        // the file is deleted with its last link
+        let nlinks = file_inode.metadata().map_or(0, |metadata| metadata.nlinks);
+        if nlinks == 0 {
+            notify_inode(&file_inode, InotifyMask::DELETE_SELF);
+            xattr::release(&file_inode);
+        } else {
+            notify_inode(&file_inode, InotifyMask::ATTRIB);
+        }
         Ok(0)
     }
 
@@ -211,7 +300,48 @@ impl Syscall<'_> {
         let (new_dir_path, new_file_name) = split_path(&newpath);
         let old_dir_inode = proc.lookup_inode_at(olddirfd, old_dir_path, false)?;
         let new_dir_inode = proc.lookup_inode_at(newdirfd, new_dir_path, false)?;
+        check_writable(&old_dir_inode)?;
+        check_writable(&new_dir_inode)?;
+        let cred = proc.credentials();
+        let inode = old_dir_inode.find(old_file_name)?;
+        cred.check_delete(&old_dir_inode, &inode)?;
+        let target = new_dir_inode.find(new_file_name).ok();
+        match &target {
+            Some(target) => cred.check_delete(&new_dir_inode, target)?,
+            None => cred.check_inode(&new_dir_inode, Access::WRITE | Access::EXEC)?,
+        }
+        let metadata = inode.metadata()?;
+        // the file replaced by the rename, if not another link to the same one
+        let replaced = match target {
+            Some(target) if target.metadata()?.inode != metadata.inode => Some(target),
+            _ => None,
+        };
         old_dir_inode.move_(old_file_name, &new_dir_inode, new_file_name)?;
+
+        let is_dir = metadata.type_ == FileType::Dir;
+        let cookie = new_cookie();
+        notify_entry(
+            &old_dir_inode,
+            old_file_name,
+            is_dir,
+            InotifyMask::MOVED_FROM,
+            cookie,
+        );
+        notify_entry(
+            &new_dir_inode,
+            new_file_name,
+            is_dir,
+            InotifyMask::MOVED_TO,
+            cookie,
+        );
+        notify_inode(&inode, InotifyMask::MOVE_SELF);
+        if let Some(target) = replaced {
+            notify_inode(&target, InotifyMask::DELETE_SELF);
+            let nlinks = target.metadata().map_or(0, |metadata| metadata.nlinks);
+            if is_dir || nlinks == 0 {
+                xattr::release(&target);
+            }
+        }
         Ok(0)
     }
 
@@ -222,7 +352,7 @@ impl Syscall<'_> {
 
     /// read value of symbolic link relative to directory file descriptor
     /// readlink() places the contents of the symbolic link path in the buffer base, which has size len
-    /// TODO: recursive link resolution and loop detection
+    /// An empty `path` refers to the link opened as `dirfd` with `O_PATH | O_NOFOLLOW`.
     pub fn sys_readlinkat(
         &self,
         dirfd: FileDesc,
@@ -237,14 +367,17 @@ impl Syscall<'_> {
         );
 
         let proc = self.linux_process();
-        let inode = proc.lookup_inode_at(dirfd, &path, false)?;
+        let inode = if path.is_empty() && dirfd != FileDesc::CWD {
+            proc.get_file(dirfd)?.inode()
+        } else {
+            proc.lookup_inode_at(dirfd, &path, false)?
+        };
         if inode.metadata()?.type_ != FileType::SymLink {
             return Err(LxError::EINVAL);
         }
-        // TODO: recursive link resolution and loop detection
-        let mut buf = vec![0; len];
-        let len = inode.read_at(0, &mut buf)?;
-        base.write_array(&buf[..len])?;
+        let target = proc.read_link(&inode)?;
+        let len = target.len().min(len);
+        base.write_array(&target[..len])?;
         Ok(len)
     }
 }
@@ -354,5 +487,7 @@ bitflags! {
     pub struct AtFlags: usize {
         const EMPTY_PATH = 0x1000;
         const SYMLINK_NOFOLLOW = 0x100;
+        const SYMLINK_FOLLOW = 0x400;
+        const EACCESS = 0x200;
     }
 }
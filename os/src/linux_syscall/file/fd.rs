@@ -6,6 +6,9 @@
 //! - pipe
 
 use super::*;
+use crate::linux_object::cred::Access;
+use crate::linux_object::fs::inotify::{notify_entry, InotifyMask};
+use crate::linux_object::fs::mount::{check_device, check_writable};
 use alloc::string::String;
 
 impl Syscall<'_> {
@@ -30,27 +33,98 @@ impl Syscall<'_> {
             dir_fd, path, flags, mode
         );
 
-        let inode = if flags.contains(OpenFlags::CREATE) {
-            let (dir_path, file_name) = split_path(&path);
-            // relative to cwd
-            let dir_inode = proc.lookup_inode_at(dir_fd, dir_path, true)?;
-            match dir_inode.find(file_name) {
-                Ok(file_inode) => {
+        let cred = proc.credentials();
+        let follow = !flags.contains(OpenFlags::NOFOLLOW);
+        let mut created = false;
+        let mut parent = None;
+        let inode = if flags.contains(OpenFlags::CREATE) && !flags.contains(OpenFlags::PATH) {
+            // a directory is not created
+            if path.ends_with('/') {
+                return Err(LxError::EISDIR);
+            }
+            // with O_EXCL a link is an existing file, not followed
+            let follow = follow && !flags.contains(OpenFlags::EXCLUSIVE);
+            // relative to cwd, a dangling link is replaced by its target
+            let (dir_inode, file_name) = proc.lookup_parent_at(dir_fd, &path, follow)?;
+            cred.check_inode(&dir_inode, Access::EXEC)?;
+            match dir_inode.find(&file_name) {
+                Ok(inode) => {
                     if flags.contains(OpenFlags::EXCLUSIVE) {
                         return Err(LxError::EEXIST);
                     }
-                    file_inode
+                    inode
                 }
                 Err(FsError::EntryNotFound) => {
-                    dir_inode.create(file_name, FileType::File, mode as u32)?
+                    check_writable(&dir_inode)?;
+                    cred.check_inode(&dir_inode, Access::WRITE)?;
+                    let mode = mode as u32 & !proc.umask();
+                    let file_inode = dir_inode.create(&file_name, FileType::File, mode)?;
+                    cred.set_owner(&file_inode)?;
+                    notify_entry(&dir_inode, &file_name, false, InotifyMask::CREATE, 0);
+                    created = true;
+                    parent = Some((dir_inode, file_name));
+                    file_inode
                 }
                 Err(e) => return Err(LxError::from(e)),
             }
         } else {
-            proc.lookup_inode_at(dir_fd, &path, true)?
+            proc.lookup_inode_at(dir_fd, &path, follow)?
+        };
+        if flags.contains(OpenFlags::PATH) {
+            // only for the path, the file can not be read or written
+            let file = File::new(inode, flags.to_path_options(), path);
+            let fd = proc.add_file(file)?;
+            return Ok(fd.into());
+        }
+        if inode.metadata()?.type_ == FileType::SymLink {
+            return Err(LxError::ELOOP);
+        }
+        check_device(&inode)?;
+        if flags.writable() || flags.contains(OpenFlags::TRUNCATE) {
+            check_writable(&inode)?;
+        }
+        // a file is opened as asked for by its creator, whatever its mode
+        if !created {
+            let mut access = Access::empty();
+            if flags.readable() {
+                access |= Access::READ;
+            }
+            if flags.writable() || flags.contains(OpenFlags::TRUNCATE) {
+                access |= Access::WRITE;
+            }
+            cred.check_inode(&inode, access)?;
+        }
+        let inode = if is_dev_tty(&inode) {
+            proc.controlling_tty().ok_or(LxError::ENXIO)?
+        } else if let Some(master) = open_ptmx(&inode) {
+            master?
+        } else {
+            inode
         };
+        // a session leader opening a free terminal acquires it
+        if !flags.contains(OpenFlags::NOCTTY) {
+            if let Some(tty) = as_tty(&inode) {
+                if tty.session() == 0 && proc.controlling_tty().is_none() {
+                    acquire_controlling_tty(proc, &inode, false).ok();
+                }
+            }
+        }
 
+        // the entry of the file in its directory, for inotify
+        let (dir_path, file_name) = split_path(&path);
+        let dir_entry = match parent {
+            Some(entry) => Some(entry),
+            None if file_name.is_empty() || file_name == "." || file_name == ".." => None,
+            None => proc
+                .lookup_inode_at(dir_fd, dir_path, true)
+                .ok()
+                .map(|dir| (dir, String::from(file_name))),
+        };
         let file = File::new(inode, flags.to_options(), path);
+        if let Some((dir, name)) = dir_entry {
+            file.set_dir_entry(&dir, &name);
+        }
+        file.notify(InotifyMask::OPEN);
         let fd = proc.add_file(file)?;
         Ok(fd.into())
     }
@@ -104,6 +178,7 @@ impl Syscall<'_> {
                 append: false,
                 nonblock: (flags & O_NONBLOCK) != 0,
                 fd_cloexec: (flags & O_CLOEXEC) != 0,
+                path_only: false,
             },
             String::from("pipe_r:[]"),
         ))?;
@@ -116,6 +191,7 @@ impl Syscall<'_> {
                 append: false,
                 nonblock: false,
                 fd_cloexec: (flags & O_CLOEXEC) != 0,
+                path_only: false,
             },
             String::from("pipe_w:[]"),
         ))?;
@@ -130,8 +206,10 @@ impl Syscall<'_> {
     }
 
     /// apply or remove an advisory lock on an open file
-    /// TODO: handle operation
-    pub fn sys_flock(&mut self, fd: FileDesc, operation: usize) -> SysResult {
+    ///
+    /// The lock is held by the open file description, and released when it
+    /// is closed.
+    pub async fn sys_flock(&mut self, fd: FileDesc, operation: usize) -> SysResult {
         bitflags! {
             struct Operation: u8 {
                 const LOCK_SH = 1;
@@ -140,17 +218,27 @@ impl Syscall<'_> {
                 const LOCK_UN = 8;
             }
         }
-        let operation = Operation::from_bits(operation as u8).unwrap();
+        let operation = Operation::from_bits(operation as u8).ok_or(LxError::EINVAL)?;
         info!("flock: fd: {:?}, operation: {:?}", fd, operation);
         let proc = self.linux_process();
 
-        proc.get_file(fd)?;
+        let file = proc.get_file(fd)?;
+        let kind = if operation.contains(Operation::LOCK_UN) {
+            None
+        } else if operation.contains(Operation::LOCK_EX) {
+            Some(LockKind::Exclusive)
+        } else if operation.contains(Operation::LOCK_SH) {
+            Some(LockKind::Shared)
+        } else {
+            return Err(LxError::EINVAL);
+        };
+        file.flock(kind, operation.contains(Operation::LOCK_NB)).await?;
         Ok(0)
     }
 }
 
 bitflags! {
-    struct OpenFlags: usize {
+    pub struct OpenFlags: usize {
         /// read only
         const RDONLY = 0;
         /// write only
@@ -161,12 +249,18 @@ bitflags! {
         const CREATE = 1 << 6;
         /// error if CREATE and the file exists
         const EXCLUSIVE = 1 << 7;
+        /// do not make a terminal the controlling terminal
+        const NOCTTY = 1 << 8;
         /// truncate file upon open
         const TRUNCATE = 1 << 9;
         /// append on each write
         const APPEND = 1 << 10;
+        /// fail if the last component of the path is a symbolic link
+        const NOFOLLOW = 1 << 17;
         /// close on exec
         const CLOEXEC = 1 << 19;
+        /// open only for the path, not the file contents
+        const PATH = 1 << 21;
     }
 }
 
@@ -189,6 +283,18 @@ impl OpenFlags {
             append: self.contains(Self::APPEND),
             nonblock: false,
             fd_cloexec: self.contains(Self::CLOEXEC),
+            path_only: false,
+        }
+    }
+    /// convert OpenFlags to OpenOptions for `O_PATH`, without read or write
+    fn to_path_options(self) -> OpenOptions {
+        OpenOptions {
+            read: false,
+            write: false,
+            append: false,
+            nonblock: false,
+            fd_cloexec: self.contains(Self::CLOEXEC),
+            path_only: true,
         }
     }
 }
@@ -6,11 +6,19 @@
 //! - truncate, ftruncate
 //! - sendfile, copy_file_range
 //! - sync, fsync, fdatasync
-//! - ioctl, fcntl
+//! - ioctl, fcntl, record locks
 //! - access, faccessat
+//! - chmod, fchmod, fchmodat
+//! - chown, fchown, fchownat, lchown
 
 use super::*;
+use crate::linux_object::cred::{Access, Credentials, S_ISGID, S_ISUID};
+use crate::linux_object::fs::mount::{check_writable, inode_key, sync_all};
+use crate::linux_object::fs::vfs::INode;
+use crate::linux_object::net::MsgFlags;
 use crate::linux_object::time::TimeSpec;
+use crate::zircon_object::vm::PAGE_SIZE;
+use alloc::vec::Vec;
 
 impl Syscall<'_> {
     /// Reads from a specified file using a file descriptor. Before using this call,
@@ -22,6 +30,7 @@ impl Syscall<'_> {
         info!("read: fd={:?}, base={:?}, len={:#x}", fd, base, len);
         let proc = self.linux_process();
         let file_like = proc.get_file_like(fd)?;
+        check_tty_access(proc, &file_like, false)?;
         let mut buf = vec![0u8; len];
         let len = file_like.read(&mut buf).await?;
         base.write_array(&buf[..len])?;
@@ -33,13 +42,13 @@ impl Syscall<'_> {
     /// - fd – file descriptor
     /// - base – pointer to the buffer write
     /// - len – number of bytes to write
-    pub fn sys_write(&self, fd: FileDesc, base: UserInPtr<u8>, len: usize) -> SysResult {
+    pub async fn sys_write(&self, fd: FileDesc, base: UserInPtr<u8>, len: usize) -> SysResult {
         info!("write: fd={:?}, base={:?}, len={:#x}", fd, base, len);
         let proc = self.linux_process();
         let buf = base.read_array(len)?;
         let file_like = proc.get_file_like(fd)?;
-        let len = file_like.write(&buf)?;
-        Ok(len)
+        check_tty_access(proc, &file_like, true)?;
+        self.write_file_like(&file_like, &buf).await
     }
 
     /// read from or write to a file descriptor at a given offset
@@ -97,6 +106,7 @@ impl Syscall<'_> {
         let mut iovs = iov_ptr.read_iovecs(iov_count)?;
         let proc = self.linux_process();
         let file_like = proc.get_file_like(fd)?;
+        check_tty_access(proc, &file_like, false)?;
         let mut buf = vec![0u8; iovs.total_len()];
         let len = file_like.read(&mut buf).await?;
         iovs.write_from_buf(&buf)?;
@@ -106,7 +116,7 @@ impl Syscall<'_> {
     /// works just like write except that multiple buffers are written out.
     /// writes iov_count buffers of data described
     /// by iov to the file associated with the file descriptor fd ("gather output").
-    pub fn sys_writev(
+    pub async fn sys_writev(
         &self,
         fd: FileDesc,
         iov_ptr: UserInPtr<IoVecIn>,
@@ -120,8 +130,20 @@ impl Syscall<'_> {
         let buf = iovs.read_to_vec()?;
         let proc = self.linux_process();
         let file_like = proc.get_file_like(fd)?;
-        let len = file_like.write(&buf)?;
-        Ok(len)
+        check_tty_access(proc, &file_like, true)?;
+        self.write_file_like(&file_like, &buf).await
+    }
+
+    /// Write `buf` to `file_like`. A socket is written as `send` does, so
+    /// that it blocks for room unless it is nonblocking.
+    async fn write_file_like(&self, file_like: &Arc<dyn FileLike>, buf: &[u8]) -> SysResult {
+        match file_like.as_socket() {
+            Some(_) => {
+                self.send_socket(&**file_like, buf, None, Vec::new(), MsgFlags::empty())
+                    .await
+            }
+            None => file_like.write(buf),
+        }
     }
 
     /// repositions the offset of the open file associated with the file descriptor fd
@@ -150,7 +172,9 @@ impl Syscall<'_> {
         let path = path.read_cstring()?;
         info!("truncate: path={:?}, len={}", path, len);
         let proc = self.linux_process();
-        proc.lookup_inode(&path)?.resize(len)?;
+        let inode = proc.lookup_inode(&path)?;
+        check_writable(&inode)?;
+        inode.resize(len)?;
         Ok(0)
     }
 
@@ -163,19 +187,73 @@ impl Syscall<'_> {
     }
 
     /// copies data between one file descriptor and another.
+    ///
+    /// Reads from `offset_ptr` and writes the new offset back if it is not null,
+    /// otherwise from the offset of `in_fd`, which is updated.
     pub async fn sys_sendfile(
         &self,
         out_fd: FileDesc,
         in_fd: FileDesc,
-        offset_ptr: UserInOutPtr<u64>,
+        mut offset_ptr: UserInOutPtr<u64>,
         count: usize,
     ) -> SysResult {
-        self.sys_copy_file_range(in_fd, offset_ptr, out_fd, 0.into(), count, 0)
-            .await
+        info!(
+            "sendfile: out={:?}, in={:?}, offset={:?}, count={}",
+            out_fd, in_fd, offset_ptr, count
+        );
+        let proc = self.linux_process();
+        let in_file = proc.get_file(in_fd)?;
+        let out_file = proc.get_file_like(out_fd)?;
+        let mut offset = offset_ptr.read_if_not_null()?;
+        let mut buffer = vec![0u8; count.min(PAGE_SIZE)];
+        let mut total_written = 0;
+        while total_written < count {
+            let len = buffer.len().min(count - total_written);
+            let result = match offset {
+                Some(offset) => in_file.read_at(offset, &mut buffer[..len]).await,
+                None => in_file.read(&mut buffer[..len]).await,
+            };
+            let read_len = match result {
+                Ok(0) => break,
+                Ok(read_len) => read_len,
+                Err(err) if total_written == 0 => return Err(err),
+                Err(_) => break,
+            };
+            let result = out_file.write(&buffer[..read_len]);
+            let write_len = *result.as_ref().unwrap_or(&0);
+            match offset.as_mut() {
+                Some(offset) => *offset += write_len as u64,
+                // give back what was read but not written, also when the write failed
+                None if write_len < read_len => {
+                    in_file.seek(SeekFrom::Current(write_len as i64 - read_len as i64))?;
+                }
+                None => {}
+            }
+            match result {
+                Err(err) if total_written == 0 => return Err(err),
+                Err(_) => break,
+                Ok(_) => {}
+            }
+            total_written += write_len;
+            if write_len < len {
+                break;
+            }
+        }
+        if let Some(offset) = offset {
+            offset_ptr.write(offset)?;
+        }
+        Ok(total_written)
     }
 
-    /// copies data between one file descriptor and anothe, read from specified offset and write new offset back
-    pub async fn sys_copy_file_range(
+    /// copies data between one regular file and another, read from specified offset and write new offset back
+    ///
+    /// For `in_offset` and `out_offset`, null means to use and update the file offset,
+    /// non-null means to update the offset pointed to instead.
+    /// The filesystems can not share extents between files, and pages can not
+    /// be shared either: files are not backed by VMOs, there is no page cache
+    /// and `mmap` of a file copies it into a new VMO. So the data is copied
+    /// page by page from one INode to the other.
+    pub fn sys_copy_file_range(
         &self,
         in_fd: FileDesc,
         mut in_offset: UserInOutPtr<u64>,
@@ -188,66 +266,73 @@ impl Syscall<'_> {
             "copy_file_range: in={:?}, out={:?}, in_offset={:?}, out_offset={:?}, count={}, flags={}",
             in_fd, out_fd, in_offset, out_offset, count, flags
         );
+        if flags != 0 {
+            return Err(LxError::EINVAL);
+        }
         let proc = self.linux_process();
         let in_file = proc.get_file(in_fd)?;
         let out_file = proc.get_file(out_fd)?;
-        let mut buffer = [0u8; 1024];
-
-        // for in_offset and out_offset
-        // null means update file offset
-        // non-null means update {in,out}_offset instead
+        if !in_file.options.read || !out_file.options.write || out_file.options.append {
+            return Err(LxError::EBADF);
+        }
+        let in_meta = in_file.metadata()?;
+        let out_meta = out_file.metadata()?;
+        if in_meta.type_ == FileType::Dir || out_meta.type_ == FileType::Dir {
+            return Err(LxError::EISDIR);
+        }
+        if in_meta.type_ != FileType::File || out_meta.type_ != FileType::File {
+            return Err(LxError::EINVAL);
+        }
 
-        let mut read_offset = if !in_offset.is_null() {
-            in_offset.read()?
-        } else {
-            in_file.seek(SeekFrom::Current(0))?
+        let mut read_offset = match in_offset.read_if_not_null()? {
+            Some(offset) => offset,
+            None => in_file.seek(SeekFrom::Current(0))?,
         };
-
-        let orig_out_file_offset = out_file.seek(SeekFrom::Current(0))?;
-        let write_offset = if !out_offset.is_null() {
-            let offset = out_offset.read()?;
-            out_file.seek(SeekFrom::Start(offset))?
-        } else {
-            0
+        let mut write_offset = match out_offset.read_if_not_null()? {
+            Some(offset) => offset,
+            None => out_file.seek(SeekFrom::Current(0))?,
         };
+        let same_file = inode_key(&in_file.inode())? == inode_key(&out_file.inode())?;
+        if same_file
+            && read_offset < write_offset.saturating_add(count as u64)
+            && write_offset < read_offset.saturating_add(count as u64)
+        {
+            return Err(LxError::EINVAL);
+        }
 
-        // read from specified offset and write new offset back
-        let mut bytes_read = 0;
+        let inode = in_file.inode();
+        let mut buffer = vec![0u8; count.min(PAGE_SIZE)];
         let mut total_written = 0;
-        while bytes_read < count {
-            let len = buffer.len().min(count - bytes_read);
-            let read_len = in_file.read_at(read_offset, &mut buffer[..len]).await?;
-            if read_len == 0 {
+        while total_written < count {
+            let len = buffer.len().min(count - total_written);
+            let result = inode.read_at(read_offset as usize, &mut buffer[..len]);
+            let result = result.map_err(LxError::from).and_then(|read_len| {
+                let write_len = out_file.write_at(write_offset, &buffer[..read_len])?;
+                Ok((read_len, write_len))
+            });
+            let write_len = match result {
+                Ok((0, _)) => break,
+                Ok((_, write_len)) => write_len,
+                Err(err) if total_written == 0 => return Err(err),
+                Err(_) => break,
+            };
+            read_offset += write_len as u64;
+            write_offset += write_len as u64;
+            total_written += write_len;
+            if write_len < len {
                 break;
             }
-            bytes_read += read_len;
-            read_offset += read_len as u64;
-
-            let mut bytes_written = 0;
-            let mut rlen = read_len;
-            while bytes_written < read_len {
-                let write_len = out_file.write(&buffer[bytes_written..(bytes_written + rlen)])?;
-                if write_len == 0 {
-                    info!(
-                        "copy_file_range:END_ERR in={:?}, out={:?}, in_offset={:?}, out_offset={:?}, count={} = bytes_read {}, bytes_written {}, write_len {}",
-                        in_fd, out_fd, in_offset, out_offset, count, bytes_read, bytes_written, write_len
-                    );
-                    return Err(LxError::EBADF);
-                }
-                bytes_written += write_len;
-                rlen -= write_len;
-            }
-            total_written += bytes_written;
         }
 
-        if !in_offset.is_null() {
-            in_offset.write(read_offset)?;
+        if in_offset.is_null() {
+            in_file.seek(SeekFrom::Start(read_offset))?;
         } else {
-            in_file.seek(SeekFrom::Current(bytes_read as i64))?;
+            in_offset.write(read_offset)?;
         }
-        out_offset.write_if_not_null(write_offset + total_written as u64)?;
-        if !out_offset.is_null() {
-            out_file.seek(SeekFrom::Start(orig_out_file_offset))?;
+        if out_offset.is_null() {
+            out_file.seek(SeekFrom::Start(write_offset))?;
+        } else {
+            out_offset.write(write_offset)?;
         }
         Ok(total_written)
     }
@@ -255,8 +340,7 @@ impl Syscall<'_> {
     /// causes all buffered modifications to file metadata and data to be written to the underlying file systems.
     pub fn sys_sync(&self) -> SysResult {
         info!("sync:");
-        let proc = self.linux_process();
-        proc.root_inode().fs().sync()?;
+        sync_all()?;
         Ok(0)
     }
 
@@ -292,26 +376,135 @@ impl Syscall<'_> {
         );
         let proc = self.linux_process();
         let file_like = proc.get_file_like(fd)?;
+        if let Ok(file) = file_like.clone().downcast_arc::<File>() {
+            if let Some(ret) = tty_ioctl(proc, &file.operable_inode()?, request, arg1) {
+                return ret;
+            }
+        }
         file_like.ioctl(request, arg1, arg2, arg3)
     }
 
     /// Manipulate a file descriptor.
     /// - cmd – cmd flag
     /// - arg – additional parameters based on cmd
-    pub fn sys_fcntl(&self, fd: FileDesc, cmd: usize, arg: usize) -> SysResult {
+    pub async fn sys_fcntl(&self, fd: FileDesc, cmd: usize, arg: usize) -> SysResult {
         info!("fcntl: fd={:?}, cmd={:x}, arg={}", fd, cmd, arg);
         let proc = self.linux_process();
+        let lock_cmds = [
+            FcntlFlags::F_GETLK,
+            FcntlFlags::F_SETLK,
+            FcntlFlags::F_SETLKW,
+            FcntlFlags::F_OFD_GETLK,
+            FcntlFlags::F_OFD_SETLK,
+            FcntlFlags::F_OFD_SETLKW,
+        ];
+        if lock_cmds.iter().any(|lock_cmd| lock_cmd.bits() == cmd) {
+            let file = proc.get_file(fd)?;
+            return self.fcntl_lock(&file, cmd, arg.into()).await;
+        }
         let file_like = proc.get_file_like(fd)?;
         file_like.fcntl(cmd, arg)
     }
 
+    /// get, set or remove a record lock on `file` for `fcntl`
+    ///
+    /// A lock is held by the process, or by the open file description for the
+    /// `F_OFD_*` commands.
+    async fn fcntl_lock(
+        &self,
+        file: &Arc<File>,
+        cmd: usize,
+        mut arg: UserInOutPtr<Flock>,
+    ) -> SysResult {
+        const SEEK_SET: i16 = 0;
+        const SEEK_CUR: i16 = 1;
+        const SEEK_END: i16 = 2;
+
+        let mut flock = arg.read()?;
+        info!("fcntl_lock: cmd={}, flock={:?}", cmd, flock);
+        let ofd = cmd >= FcntlFlags::F_OFD_GETLK.bits();
+        let owner = if ofd {
+            if flock.pid != 0 {
+                return Err(LxError::EINVAL);
+            }
+            LockOwner::File(file.id())
+        } else {
+            LockOwner::Process(self.linux_process().pid())
+        };
+        let base = match flock.whence {
+            SEEK_SET => 0,
+            SEEK_CUR => file.seek(SeekFrom::Current(0))? as i64,
+            SEEK_END => file.metadata()?.size as i64,
+            _ => return Err(LxError::EINVAL),
+        };
+        let start = base.checked_add(flock.start).ok_or(LxError::EINVAL)?;
+        let (start, end) = match flock.len {
+            0 => (start, i64::MAX),
+            len if len > 0 => (start, start.saturating_add(len)),
+            len => (start.checked_add(len).ok_or(LxError::EINVAL)?, start),
+        };
+        if start < 0 {
+            return Err(LxError::EINVAL);
+        }
+        let start = start as u64;
+        let end = if end == i64::MAX {
+            u64::MAX
+        } else {
+            end as u64
+        };
+        let kind = match flock.type_ {
+            F_RDLCK => Some(LockKind::Shared),
+            F_WRLCK => Some(LockKind::Exclusive),
+            F_UNLCK => None,
+            _ => return Err(LxError::EINVAL),
+        };
+        let key = file.lock_key()?;
+
+        if cmd == FcntlFlags::F_GETLK.bits() || cmd == FcntlFlags::F_OFD_GETLK.bits() {
+            let kind = kind.ok_or(LxError::EINVAL)?;
+            match test_record_lock(key, owner, kind, start, end) {
+                Some(lock) => {
+                    flock.type_ = match lock.kind {
+                        LockKind::Shared => F_RDLCK,
+                        LockKind::Exclusive => F_WRLCK,
+                    };
+                    flock.whence = SEEK_SET;
+                    flock.start = lock.start as i64;
+                    flock.len = if lock.end == u64::MAX {
+                        0
+                    } else {
+                        (lock.end - lock.start) as i64
+                    };
+                    flock.pid = match lock.owner {
+                        LockOwner::Process(pid) => pid as i32,
+                        LockOwner::File(_) => -1,
+                    };
+                }
+                None => flock.type_ = F_UNLCK,
+            }
+            arg.write(flock)?;
+            return Ok(0);
+        }
+        // a lock needs the file opened for the access
+        match kind {
+            Some(LockKind::Shared) if !file.options.read => return Err(LxError::EBADF),
+            Some(LockKind::Exclusive) if !file.options.write => return Err(LxError::EBADF),
+            _ => {}
+        }
+        let wait = cmd == FcntlFlags::F_SETLKW.bits() || cmd == FcntlFlags::F_OFD_SETLKW.bits();
+        set_record_lock(key, owner, kind, start, end, wait).await?;
+        Ok(0)
+    }
+
     /// Checks whether the calling process can access the file pathname
     pub fn sys_access(&self, path: UserInPtr<u8>, mode: usize) -> SysResult {
         self.sys_faccessat(FileDesc::CWD, path, mode, 0)
     }
 
     /// Check user's permissions of a file relative to a directory file descriptor
-    /// TODO: check permissions based on uid/gid
+    ///
+    /// The real user and group IDs are checked, or the effective ones with
+    /// `AT_EACCESS`.
     pub fn sys_faccessat(
         &self,
         dirfd: FileDesc,
@@ -319,7 +512,6 @@ impl Syscall<'_> {
         mode: usize,
         flags: usize,
     ) -> SysResult {
-        // TODO: check permissions based on uid/gid
         let path = path.read_cstring()?;
         let flags = AtFlags::from_bits_truncate(flags);
         info!(
@@ -328,7 +520,17 @@ impl Syscall<'_> {
         );
         let proc = self.linux_process();
         let follow = !flags.contains(AtFlags::SYMLINK_NOFOLLOW);
-        let _inode = proc.lookup_inode_at(dirfd, &path, follow)?;
+        let inode = proc.lookup_inode_at(dirfd, &path, follow)?;
+        let access = Access::from_bits(mode as u32).ok_or(LxError::EINVAL)?;
+        if access.contains(Access::WRITE) {
+            check_writable(&inode)?;
+        }
+        let mut cred = proc.credentials();
+        if !flags.contains(AtFlags::EACCESS) {
+            cred.user.fs = cred.user.real;
+            cred.group.fs = cred.group.real;
+        }
+        cred.check_inode(&inode, access)?;
         Ok(0)
     }
 
@@ -357,7 +559,7 @@ impl Syscall<'_> {
         let inode = if pathname.is_null() {
             let fd = dirfd;
             info!("futimens: fd: {:?}, times: {:?}", fd, times);
-            proc.get_file(fd)?.inode()
+            proc.get_file(fd)?.operable_inode()?
         } else {
             let pathname = pathname.read_cstring()?;
             info!(
@@ -395,4 +597,147 @@ impl Syscall<'_> {
         inode.set_metadata(&metadata)?;
         Ok(0)
     }
+
+    /// change the mode of a file
+    pub fn sys_chmod(&self, path: UserInPtr<u8>, mode: usize) -> SysResult {
+        self.sys_fchmodat(FileDesc::CWD, path, mode, 0)
+    }
+
+    /// change the mode of an open file
+    pub fn sys_fchmod(&self, fd: FileDesc, mode: usize) -> SysResult {
+        info!("fchmod: fd={:?}, mode={:#o}", fd, mode);
+        let proc = self.linux_process();
+        let inode = proc.get_file(fd)?.operable_inode()?;
+        chmod(&proc.credentials(), &inode, mode)
+    }
+
+    /// change the mode of a file relative to a directory file descriptor
+    pub fn sys_fchmodat(
+        &self,
+        dirfd: FileDesc,
+        path: UserInPtr<u8>,
+        mode: usize,
+        flags: usize,
+    ) -> SysResult {
+        let path = path.read_cstring()?;
+        let flags = AtFlags::from_bits_truncate(flags);
+        info!(
+            "fchmodat: dirfd={:?}, path={:?}, mode={:#o}, flags={:?}",
+            dirfd, path, mode, flags
+        );
+        let proc = self.linux_process();
+        let follow = !flags.contains(AtFlags::SYMLINK_NOFOLLOW);
+        let inode = proc.lookup_inode_at(dirfd, &path, follow)?;
+        chmod(&proc.credentials(), &inode, mode)
+    }
+
+    /// change the owner and group of a file
+    pub fn sys_chown(&self, path: UserInPtr<u8>, uid: usize, gid: usize) -> SysResult {
+        self.sys_fchownat(FileDesc::CWD, path, uid, gid, 0)
+    }
+
+    /// change the owner and group of a file, not following a symbolic link
+    pub fn sys_lchown(&self, path: UserInPtr<u8>, uid: usize, gid: usize) -> SysResult {
+        let flags = AtFlags::SYMLINK_NOFOLLOW.bits();
+        self.sys_fchownat(FileDesc::CWD, path, uid, gid, flags)
+    }
+
+    /// change the owner and group of an open file
+    pub fn sys_fchown(&self, fd: FileDesc, uid: usize, gid: usize) -> SysResult {
+        info!(
+            "fchown: fd={:?}, uid={}, gid={}",
+            fd, uid as i32, gid as i32
+        );
+        let proc = self.linux_process();
+        let inode = proc.get_file(fd)?.operable_inode()?;
+        chown(&proc.credentials(), &inode, uid, gid)
+    }
+
+    /// change the owner and group of a file relative to a directory file descriptor
+    ///
+    /// An ID of -1 is left unchanged.
+    pub fn sys_fchownat(
+        &self,
+        dirfd: FileDesc,
+        path: UserInPtr<u8>,
+        uid: usize,
+        gid: usize,
+        flags: usize,
+    ) -> SysResult {
+        let path = path.read_cstring()?;
+        let flags = AtFlags::from_bits_truncate(flags);
+        info!(
+            "fchownat: dirfd={:?}, path={:?}, uid={}, gid={}, flags={:?}",
+            dirfd, path, uid as i32, gid as i32, flags
+        );
+        let proc = self.linux_process();
+        let inode = if path.is_empty() && flags.contains(AtFlags::EMPTY_PATH) {
+            proc.get_file(dirfd)?.inode()
+        } else {
+            let follow = !flags.contains(AtFlags::SYMLINK_NOFOLLOW);
+            proc.lookup_inode_at(dirfd, &path, follow)?
+        };
+        chown(&proc.credentials(), &inode, uid, gid)
+    }
+}
+
+/// Change the mode of `inode` on behalf of a process with `cred`.
+fn chmod(cred: &Credentials, inode: &Arc<dyn INode>, mode: usize) -> SysResult {
+    check_writable(inode)?;
+    let mut metadata = inode.metadata()?;
+    if !cred.is_owner(&metadata) {
+        return Err(LxError::EPERM);
+    }
+    let mut mode = mode as u16 & 0o7777;
+    // only members of the group can make files set-group-ID
+    if !cred.overrides_permissions() && !cred.in_group(metadata.gid as u32) {
+        mode &= !S_ISGID;
+    }
+    metadata.mode = mode;
+    inode.set_metadata(&metadata)?;
+    Ok(0)
+}
+
+/// Change the owner and group of `inode` on behalf of a process with `cred`.
+///
+/// Only a privileged process can change the owner, the owner can change the
+/// group to one it is in.
+fn chown(cred: &Credentials, inode: &Arc<dyn INode>, uid: usize, gid: usize) -> SysResult {
+    check_writable(inode)?;
+    let mut metadata = inode.metadata()?;
+    let uid = Some(uid as u32).filter(|&uid| uid != u32::MAX && uid as usize != metadata.uid);
+    let gid = Some(gid as u32).filter(|&gid| gid != u32::MAX && gid as usize != metadata.gid);
+    if !cred.overrides_permissions() {
+        let owner = cred.user.fs as usize == metadata.uid;
+        if uid.is_some() || gid.map_or(false, |gid| !owner || !cred.in_group(gid)) {
+            return Err(LxError::EPERM);
+        }
+    }
+    if uid.is_none() && gid.is_none() {
+        return Ok(0);
+    }
+    if let Some(uid) = uid {
+        metadata.uid = uid as usize;
+    }
+    if let Some(gid) = gid {
+        metadata.gid = gid as usize;
+    }
+    // a new owner does not get the privileges of the old one
+    if metadata.type_ != FileType::Dir {
+        metadata.mode &= !S_ISUID;
+        if metadata.mode & 0o010 != 0 {
+            metadata.mode &= !S_ISGID;
+        }
+    }
+    inode.set_metadata(&metadata)?;
+    Ok(0)
+}
+
+/// Check that `proc` may read from, or write to with `write`, `file_like`,
+/// which is only limited for terminals.
+fn check_tty_access(proc: &LinuxProcess, file_like: &Arc<dyn FileLike>, write: bool) -> LxResult {
+    match file_like.clone().downcast_arc::<File>() {
+        Ok(file) => tty_check_access(proc, &file.inode(), write),
+        Err(_) => Ok(()),
+    }
 }
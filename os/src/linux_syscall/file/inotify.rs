@@ -0,0 +1,84 @@
+//! File change notification
+//!
+//! - inotify_init(1)
+//! - inotify_add_watch
+//! - inotify_rm_watch
+
+use super::*;
+use crate::linux_object::cred::Access;
+use crate::linux_object::fs::inotify::{Inotify, InotifyMask};
+use alloc::string::String;
+
+impl Syscall<'_> {
+    /// Creates an inotify instance, returns a file descriptor to read its events from.
+    pub fn sys_inotify_init(&self) -> SysResult {
+        self.sys_inotify_init1(0)
+    }
+
+    /// Creates an inotify instance, `IN_NONBLOCK` and `IN_CLOEXEC` can be set in `flags`.
+    pub fn sys_inotify_init1(&self, flags: usize) -> SysResult {
+        info!("inotify_init1: flags={:#x}", flags);
+        let flags = FileFlags::from_bits(flags).ok_or(LxError::EINVAL)?;
+        if flags.contains(FileFlags::O_APPEND) {
+            return Err(LxError::EINVAL);
+        }
+        let file = File::new(
+            Arc::new(Inotify::default()),
+            OpenOptions {
+                read: true,
+                write: false,
+                append: false,
+                nonblock: flags.contains(FileFlags::O_NONBLOCK),
+                fd_cloexec: flags.contains(FileFlags::O_CLOEXEC),
+                path_only: false,
+            },
+            String::from("anon_inode:inotify"),
+        );
+        let fd = self.linux_process().add_file(file)?;
+        Ok(fd.into())
+    }
+
+    /// Watches the file `path` for the events in `mask`, and returns the watch descriptor.
+    ///
+    /// The file must be readable, a watch on it of the instance `fd` is modified.
+    pub fn sys_inotify_add_watch(
+        &self,
+        fd: FileDesc,
+        path: UserInPtr<u8>,
+        mask: usize,
+    ) -> SysResult {
+        let path = path.read_cstring()?;
+        let mask = InotifyMask::from_bits_truncate(mask as u32);
+        info!(
+            "inotify_add_watch: fd={:?}, path={:?}, mask={:?}",
+            fd, path, mask
+        );
+        let proc = self.linux_process();
+        let inode = proc.get_file(fd)?.inode();
+        let inotify = inode
+            .as_any_ref()
+            .downcast_ref::<Inotify>()
+            .ok_or(LxError::EINVAL)?;
+        let follow = !mask.contains(InotifyMask::DONT_FOLLOW);
+        let target = proc.lookup_inode_at(FileDesc::CWD, &path, follow)?;
+        proc.credentials().check_inode(&target, Access::READ)?;
+        if mask.contains(InotifyMask::ONLYDIR) && target.metadata()?.type_ != FileType::Dir {
+            return Err(LxError::ENOTDIR);
+        }
+        let wd = inotify.add_watch(&target, mask)?;
+        Ok(wd as usize)
+    }
+
+    /// Removes the watch `wd` from the inotify instance `fd`.
+    pub fn sys_inotify_rm_watch(&self, fd: FileDesc, wd: usize) -> SysResult {
+        info!("inotify_rm_watch: fd={:?}, wd={}", fd, wd);
+        let proc = self.linux_process();
+        let inode = proc.get_file(fd)?.inode();
+        let inotify = inode
+            .as_any_ref()
+            .downcast_ref::<Inotify>()
+            .ok_or(LxError::EINVAL)?;
+        inotify.rm_watch(wd as i32)?;
+        Ok(0)
+    }
+}
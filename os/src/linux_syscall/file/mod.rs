@@ -7,9 +7,13 @@ use crate::linux_object::fs::*;
 
 mod dir;
 mod fd;
+mod mount;
 #[allow(clippy::module_inception)]
 mod file;
+mod inotify;
 mod poll;
+mod splice;
 mod stat;
+mod xattr;
 
-use self::dir::AtFlags;
+pub(super) use self::{dir::AtFlags, fd::OpenFlags};
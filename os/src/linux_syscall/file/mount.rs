@@ -0,0 +1,79 @@
+//! Mount operations
+//!
+//! - mount
+//! - umount2
+
+use super::*;
+use crate::linux_object::fs::absolute_path;
+use crate::linux_object::fs::mount::{self, MountFlags, UmountFlags};
+use alloc::string::String;
+
+impl Syscall<'_> {
+    /// attach the filesystem `fstype` from `source` to the directory `target`
+    ///
+    /// With `MS_REMOUNT` the flags of the mount at `target` are changed, with
+    /// `MS_MOVE` the mount at `source` is moved to `target`, with `MS_BIND`
+    /// the subtree at `source` is made visible at `target` as well.
+    pub fn sys_mount(
+        &self,
+        source: UserInPtr<u8>,
+        target: UserInPtr<u8>,
+        fstype: UserInPtr<u8>,
+        flags: usize,
+        _data: UserInPtr<u8>,
+    ) -> SysResult {
+        let source = if source.is_null() {
+            String::new()
+        } else {
+            source.read_cstring()?
+        };
+        let target = target.read_cstring()?;
+        let fstype = if fstype.is_null() {
+            String::new()
+        } else {
+            fstype.read_cstring()?
+        };
+        let flags = MountFlags::from_bits_truncate(flags);
+        info!(
+            "mount: source={:?}, target={:?}, fstype={:?}, flags={:?}",
+            source, target, fstype, flags
+        );
+
+        let proc = self.linux_process();
+        let target_inode = proc.lookup_inode(&target)?;
+        if flags.contains(MountFlags::REMOUNT) {
+            mount::remount(&target_inode, flags)?;
+            return Ok(0);
+        }
+        let path = absolute_path(&proc.current_working_directory(), &target);
+        if flags.contains(MountFlags::MOVE) {
+            let source_inode = proc.lookup_inode(&source)?;
+            mount::move_mount(&source_inode, &target_inode, path)?;
+            return Ok(0);
+        }
+        if flags.contains(MountFlags::BIND) {
+            let source_inode = proc.lookup_inode(&source)?;
+            mount::bind(&source_inode, &source, &target_inode, path, flags)?;
+            return Ok(0);
+        }
+        // only filesystems on disks need a source
+        let source_inode = proc.lookup_inode(&source).ok();
+        let fs = mount::create_fs(&fstype, source_inode.as_ref())?;
+        let source = if source.is_empty() { &fstype } else { &source };
+        mount::mount(fs, source, &fstype, &target_inode, path, flags)?;
+        Ok(0)
+    }
+
+    /// detach the filesystem mounted at `target`
+    pub fn sys_umount2(&self, target: UserInPtr<u8>, flags: usize) -> SysResult {
+        let target = target.read_cstring()?;
+        let flags = UmountFlags::from_bits(flags).ok_or(LxError::EINVAL)?;
+        info!("umount2: target={:?}, flags={:?}", target, flags);
+
+        let proc = self.linux_process();
+        let follow = !flags.contains(UmountFlags::NOFOLLOW);
+        let inode = proc.lookup_inode_at(FileDesc::CWD, &target, follow)?;
+        mount::umount(inode, flags)?;
+        Ok(0)
+    }
+}
@@ -0,0 +1,166 @@
+//! Moving data through pipes
+//!
+//! - splice
+//! - tee
+//! - vmsplice
+//!
+//! Pages of user memory are put in a pipe by `vmsplice` without being
+//! copied, the rest is done in `linux_object::fs`.
+
+use super::*;
+use crate::linux_object::fs::vfs::INode;
+use crate::zircon_object::vm::{MMUFlags, PAGE_SIZE};
+use core::cmp::min;
+
+impl Syscall<'_> {
+    /// move data between a pipe and a file descriptor
+    ///
+    /// One of `fd_in` and `fd_out` must be a pipe, whose offset must be null.
+    /// Pages are moved between pipes without copying, data read from a file
+    /// is put in a pipe as new pages.
+    pub async fn sys_splice(
+        &self,
+        fd_in: FileDesc,
+        mut off_in: UserInOutPtr<u64>,
+        fd_out: FileDesc,
+        mut off_out: UserInOutPtr<u64>,
+        len: usize,
+        flags: usize,
+    ) -> SysResult {
+        let flags = SpliceFlags::from_bits_truncate(flags);
+        info!(
+            "splice: fd_in={:?}, off_in={:?}, fd_out={:?}, off_out={:?}, len={}, flags={:?}",
+            fd_in, off_in, fd_out, off_out, len, flags
+        );
+        let proc = self.linux_process();
+        let in_file = proc.get_file(fd_in)?;
+        let out_file = proc.get_file(fd_out)?;
+        let mut offset_in = off_in.read_if_not_null()?;
+        let mut offset_out = off_out.read_if_not_null()?;
+        let nonblock = flags.contains(SpliceFlags::NONBLOCK);
+        let len = splice(
+            &in_file,
+            offset_in.as_mut(),
+            &out_file,
+            offset_out.as_mut(),
+            len,
+            nonblock,
+        )
+        .await?;
+        if let Some(offset) = offset_in {
+            off_in.write(offset)?;
+        }
+        if let Some(offset) = offset_out {
+            off_out.write(offset)?;
+        }
+        Ok(len)
+    }
+
+    /// duplicate up to `len` bytes from the pipe `fd_in` to the pipe `fd_out`
+    /// without consuming them
+    ///
+    /// The pages are shared by both pipes.
+    pub async fn sys_tee(
+        &self,
+        fd_in: FileDesc,
+        fd_out: FileDesc,
+        len: usize,
+        flags: usize,
+    ) -> SysResult {
+        let flags = SpliceFlags::from_bits_truncate(flags);
+        info!(
+            "tee: fd_in={:?}, fd_out={:?}, len={}, flags={:?}",
+            fd_in, fd_out, len, flags
+        );
+        let proc = self.linux_process();
+        let in_file = proc.get_file(fd_in)?;
+        let out_file = proc.get_file(fd_out)?;
+        let nonblock = flags.contains(SpliceFlags::NONBLOCK);
+        tee(&in_file, &out_file, len, nonblock).await
+    }
+
+    /// splice user pages to or from the pipe `fd`
+    ///
+    /// The pages of the buffers of `iov` are put in the write end of a pipe
+    /// without copying, so later changes to them show in the pipe. The
+    /// buffers are filled from the read end in place.
+    pub async fn sys_vmsplice(
+        &self,
+        fd: FileDesc,
+        iov: usize,
+        nr_segs: usize,
+        flags: usize,
+    ) -> SysResult {
+        let flags = SpliceFlags::from_bits_truncate(flags);
+        info!(
+            "vmsplice: fd={:?}, iov={:#x}, nr_segs={}, flags={:?}",
+            fd, iov, nr_segs, flags
+        );
+        let proc = self.linux_process();
+        let file = proc.get_file(fd)?;
+        let pipe = file.as_pipe().ok_or(LxError::EBADF)?;
+
+        if file.options.write {
+            let iovs = UserInPtr::<IoVecIn>::from(iov).read_iovecs(nr_segs)?;
+            let vmar = self.zircon_process().vmar();
+            let mut done = 0;
+            for iov in iovs.iter().filter(|iov| !iov.is_empty()) {
+                let mut addr = iov.as_slice()?.as_ptr() as usize;
+                let end = addr.checked_add(iov.len()).ok_or(LxError::EFAULT)?;
+                while addr < end {
+                    // pipes are not bounded, so do not move more than one could hold
+                    if done == PIPE_SIZE {
+                        return Ok(done);
+                    }
+                    let (vmo, offset, flags) = match vmar.vmo_at(addr) {
+                        Some(found) if found.2.contains(MMUFlags::READ | MMUFlags::USER) => found,
+                        _ if done == 0 => return Err(LxError::EFAULT),
+                        _ => return Ok(done),
+                    };
+                    let len = min(end - addr, PAGE_SIZE - addr % PAGE_SIZE);
+                    let len = min(len, PIPE_SIZE - done);
+                    done += pipe.write_vmo(vmo, offset, len)?;
+                    addr += len;
+                }
+            }
+            Ok(done)
+        } else {
+            let mut iovs = UserInPtr::<IoVecOut>::from(iov).read_iovecs(nr_segs)?;
+            let nonblock = flags.contains(SpliceFlags::NONBLOCK);
+            let mut done = 0;
+            for iov in iovs.iter_mut().filter(|iov| !iov.is_empty()) {
+                let buf = iov.as_mut_slice()?;
+                // only wait for the pipe before anything is read
+                let result = if done == 0 {
+                    wait_pipe(&file, nonblock, || Ok(pipe.read_at(0, buf)?)).await
+                } else {
+                    pipe.read_at(0, buf).map_err(LxError::from)
+                };
+                let len = match result {
+                    Ok(len) => len,
+                    Err(err) if done == 0 => return Err(err),
+                    Err(_) => break,
+                };
+                done += len;
+                if len < buf.len() {
+                    break;
+                }
+            }
+            Ok(done)
+        }
+    }
+}
+
+bitflags! {
+    /// flags of `splice`, `tee` and `vmsplice`
+    pub struct SpliceFlags: usize {
+        /// move pages instead of copying, only a hint
+        const MOVE = 1;
+        /// do not block on the pipes
+        const NONBLOCK = 2;
+        /// more data will be coming in a subsequent splice
+        const MORE = 4;
+        /// the user pages are a gift to the kernel
+        const GIFT = 8;
+    }
+}
@@ -3,8 +3,10 @@
 //! - stat
 //! - lstat
 //! - fstat(at)
+//! - statfs, fstatfs
 
 use super::*;
+use crate::linux_object::fs::mount::{file_fs_stat, fs_stat, FsStat};
 use crate::linux_object::fs::vfs::{FileType, Metadata};
 
 impl Syscall<'_> {
@@ -62,6 +64,29 @@ impl Syscall<'_> {
     pub fn sys_stat(&self, path: UserInPtr<u8>, stat_ptr: UserOutPtr<Stat>) -> SysResult {
         self.sys_fstatat(FileDesc::CWD, path, stat_ptr, 0)
     }
+
+    /// Returns information about the mounted filesystem which contains the file `path`.
+    /// - `path` – pointer to the name of any file within the filesystem
+    /// - `buf` – pointer to the structure to receive filesystem information
+    pub fn sys_statfs(&self, path: UserInPtr<u8>, mut buf: UserOutPtr<StatFs>) -> SysResult {
+        let path = path.read_cstring()?;
+        info!("statfs: path={:?}, buf={:?}", path, buf);
+        let proc = self.linux_process();
+        let inode = proc.lookup_inode(&path)?;
+        buf.write(StatFs::from(fs_stat(&inode)?))?;
+        Ok(0)
+    }
+
+    /// Works exactly like the statfs syscall except a file descriptor (fd) is provided instead of a path.
+    /// - `fd` – file descriptor of any file within the filesystem
+    /// - `buf` – pointer to the structure to receive filesystem information
+    pub fn sys_fstatfs(&self, fd: FileDesc, mut buf: UserOutPtr<StatFs>) -> SysResult {
+        info!("fstatfs: fd={:?}, buf={:?}", fd, buf);
+        let proc = self.linux_process();
+        let file = proc.get_file_like(fd)?;
+        buf.write(StatFs::from(file_fs_stat(file)?))?;
+        Ok(0)
+    }
 }
 
 #[cfg(not(target_arch = "mips"))]
@@ -225,6 +250,90 @@ impl From<Metadata> for Stat {
     }
 }
 
+/// `f_flags` of `statfs` is valid
+const ST_VALID: usize = 0x20;
+
+#[cfg(not(target_arch = "mips"))]
+#[repr(C)]
+#[derive(Debug)]
+pub struct StatFs {
+    /// type of filesystem
+    type_: i64,
+    /// optimal transfer block size
+    bsize: i64,
+    /// total data blocks in filesystem
+    blocks: u64,
+    /// free blocks in filesystem
+    bfree: u64,
+    /// free blocks available to unprivileged user
+    bavail: u64,
+    /// total inodes in filesystem
+    files: u64,
+    /// free inodes in filesystem
+    ffree: u64,
+    /// filesystem ID
+    fsid: [i32; 2],
+    /// maximum length of filenames
+    namelen: i64,
+    /// fragment size
+    frsize: i64,
+    /// mount flags of filesystem
+    flags: i64,
+    /// padding
+    _spare: [i64; 4],
+}
+
+#[cfg(target_arch = "mips")]
+#[repr(C)]
+#[derive(Debug)]
+pub struct StatFs {
+    /// type of filesystem
+    type_: u32,
+    /// optimal transfer block size
+    bsize: u32,
+    /// fragment size
+    frsize: u32,
+    /// total data blocks in filesystem
+    blocks: u32,
+    /// free blocks in filesystem
+    bfree: u32,
+    /// total inodes in filesystem
+    files: u32,
+    /// free inodes in filesystem
+    ffree: u32,
+    /// free blocks available to unprivileged user
+    bavail: u32,
+    /// filesystem ID
+    fsid: [i32; 2],
+    /// maximum length of filenames
+    namelen: u32,
+    /// mount flags of filesystem
+    flags: u32,
+    /// padding
+    _spare: [u32; 5],
+}
+
+impl From<FsStat> for StatFs {
+    fn from(stat: FsStat) -> Self {
+        let info = stat.info;
+        StatFs {
+            type_: stat.magic as _,
+            bsize: info.bsize as _,
+            blocks: info.blocks as _,
+            bfree: info.bfree as _,
+            bavail: info.bavail as _,
+            files: info.files as _,
+            ffree: info.ffree as _,
+            fsid: [stat.fsid as i32, (stat.fsid as u64 >> 32) as i32],
+            namelen: info.namemax as _,
+            frsize: info.frsize as _,
+            // `MountFlags` kept by a mount are the same bits as `ST_*`
+            flags: (stat.flags.bits() | ST_VALID) as _,
+            _spare: Default::default(),
+        }
+    }
+}
+
 bitflags! {
     pub struct StatMode: u32 {
         /// Type
@@ -0,0 +1,296 @@
+//! Extended attributes
+//!
+//! - getxattr, lgetxattr, fgetxattr
+//! - setxattr, lsetxattr, fsetxattr
+//! - listxattr, llistxattr, flistxattr
+//! - removexattr, lremovexattr, fremovexattr
+
+use super::*;
+use crate::linux_object::cred::Credentials;
+use crate::linux_object::fs::inotify::{notify_inode, InotifyMask};
+use crate::linux_object::fs::mount::{check_writable, xattr_table};
+use crate::linux_object::fs::vfs::INode;
+use crate::linux_object::fs::xattr::{XattrFlags, XattrNamespace, XATTR_SIZE_MAX};
+
+impl Syscall<'_> {
+    /// get the value of the extended attribute `name` of a file
+    ///
+    /// With a `size` of 0, only the size of the value is returned.
+    pub fn sys_getxattr(
+        &self,
+        path: UserInPtr<u8>,
+        name: UserInPtr<u8>,
+        value: UserOutPtr<u8>,
+        size: usize,
+    ) -> SysResult {
+        self.getxattr_at(path, name, value, size, true)
+    }
+
+    /// get the value of an extended attribute of a file, not following a
+    /// symbolic link
+    pub fn sys_lgetxattr(
+        &self,
+        path: UserInPtr<u8>,
+        name: UserInPtr<u8>,
+        value: UserOutPtr<u8>,
+        size: usize,
+    ) -> SysResult {
+        self.getxattr_at(path, name, value, size, false)
+    }
+
+    /// get the value of an extended attribute of an open file
+    pub fn sys_fgetxattr(
+        &self,
+        fd: FileDesc,
+        name: UserInPtr<u8>,
+        value: UserOutPtr<u8>,
+        size: usize,
+    ) -> SysResult {
+        let name = name.read_cstring()?;
+        info!("fgetxattr: fd={:?}, name={:?}, size={}", fd, name, size);
+        let proc = self.linux_process();
+        let inode = proc.get_file(fd)?.operable_inode()?;
+        getxattr(&proc.credentials(), &inode, &name, value, size)
+    }
+
+    fn getxattr_at(
+        &self,
+        path: UserInPtr<u8>,
+        name: UserInPtr<u8>,
+        value: UserOutPtr<u8>,
+        size: usize,
+        follow: bool,
+    ) -> SysResult {
+        let path = path.read_cstring()?;
+        let name = name.read_cstring()?;
+        info!(
+            "getxattr: path={:?}, name={:?}, size={}, follow={}",
+            path, name, size, follow
+        );
+        let proc = self.linux_process();
+        let inode = proc.lookup_inode_at(FileDesc::CWD, &path, follow)?;
+        getxattr(&proc.credentials(), &inode, &name, value, size)
+    }
+
+    /// set the extended attribute `name` of a file to `size` bytes of `value`
+    ///
+    /// With `XATTR_CREATE` the attribute must not exist, with `XATTR_REPLACE`
+    /// it must exist.
+    pub fn sys_setxattr(
+        &self,
+        path: UserInPtr<u8>,
+        name: UserInPtr<u8>,
+        value: UserInPtr<u8>,
+        size: usize,
+        flags: usize,
+    ) -> SysResult {
+        self.setxattr_at(path, name, value, size, flags, true)
+    }
+
+    /// set an extended attribute of a file, not following a symbolic link
+    pub fn sys_lsetxattr(
+        &self,
+        path: UserInPtr<u8>,
+        name: UserInPtr<u8>,
+        value: UserInPtr<u8>,
+        size: usize,
+        flags: usize,
+    ) -> SysResult {
+        self.setxattr_at(path, name, value, size, flags, false)
+    }
+
+    /// set an extended attribute of an open file
+    pub fn sys_fsetxattr(
+        &self,
+        fd: FileDesc,
+        name: UserInPtr<u8>,
+        value: UserInPtr<u8>,
+        size: usize,
+        flags: usize,
+    ) -> SysResult {
+        let name = name.read_cstring()?;
+        info!(
+            "fsetxattr: fd={:?}, name={:?}, size={}, flags={:#x}",
+            fd, name, size, flags
+        );
+        let proc = self.linux_process();
+        let inode = proc.get_file(fd)?.operable_inode()?;
+        setxattr(&proc.credentials(), &inode, &name, value, size, flags)
+    }
+
+    fn setxattr_at(
+        &self,
+        path: UserInPtr<u8>,
+        name: UserInPtr<u8>,
+        value: UserInPtr<u8>,
+        size: usize,
+        flags: usize,
+        follow: bool,
+    ) -> SysResult {
+        let path = path.read_cstring()?;
+        let name = name.read_cstring()?;
+        info!(
+            "setxattr: path={:?}, name={:?}, size={}, flags={:#x}, follow={}",
+            path, name, size, flags, follow
+        );
+        let proc = self.linux_process();
+        let inode = proc.lookup_inode_at(FileDesc::CWD, &path, follow)?;
+        setxattr(&proc.credentials(), &inode, &name, value, size, flags)
+    }
+
+    /// list the names of the extended attributes of a file, each followed by
+    /// a null byte
+    ///
+    /// With a `size` of 0, only the size of the list is returned.
+    pub fn sys_listxattr(
+        &self,
+        path: UserInPtr<u8>,
+        list: UserOutPtr<u8>,
+        size: usize,
+    ) -> SysResult {
+        self.listxattr_at(path, list, size, true)
+    }
+
+    /// list the extended attributes of a file, not following a symbolic link
+    pub fn sys_llistxattr(
+        &self,
+        path: UserInPtr<u8>,
+        list: UserOutPtr<u8>,
+        size: usize,
+    ) -> SysResult {
+        self.listxattr_at(path, list, size, false)
+    }
+
+    /// list the extended attributes of an open file
+    pub fn sys_flistxattr(&self, fd: FileDesc, list: UserOutPtr<u8>, size: usize) -> SysResult {
+        info!("flistxattr: fd={:?}, size={}", fd, size);
+        let proc = self.linux_process();
+        let inode = proc.get_file(fd)?.operable_inode()?;
+        listxattr(&proc.credentials(), &inode, list, size)
+    }
+
+    fn listxattr_at(
+        &self,
+        path: UserInPtr<u8>,
+        list: UserOutPtr<u8>,
+        size: usize,
+        follow: bool,
+    ) -> SysResult {
+        let path = path.read_cstring()?;
+        info!(
+            "listxattr: path={:?}, size={}, follow={}",
+            path, size, follow
+        );
+        let proc = self.linux_process();
+        let inode = proc.lookup_inode_at(FileDesc::CWD, &path, follow)?;
+        listxattr(&proc.credentials(), &inode, list, size)
+    }
+
+    /// remove the extended attribute `name` of a file
+    pub fn sys_removexattr(&self, path: UserInPtr<u8>, name: UserInPtr<u8>) -> SysResult {
+        self.removexattr_at(path, name, true)
+    }
+
+    /// remove an extended attribute of a file, not following a symbolic link
+    pub fn sys_lremovexattr(&self, path: UserInPtr<u8>, name: UserInPtr<u8>) -> SysResult {
+        self.removexattr_at(path, name, false)
+    }
+
+    /// remove an extended attribute of an open file
+    pub fn sys_fremovexattr(&self, fd: FileDesc, name: UserInPtr<u8>) -> SysResult {
+        let name = name.read_cstring()?;
+        info!("fremovexattr: fd={:?}, name={:?}", fd, name);
+        let proc = self.linux_process();
+        let inode = proc.get_file(fd)?.operable_inode()?;
+        removexattr(&proc.credentials(), &inode, &name)
+    }
+
+    fn removexattr_at(&self, path: UserInPtr<u8>, name: UserInPtr<u8>, follow: bool) -> SysResult {
+        let path = path.read_cstring()?;
+        let name = name.read_cstring()?;
+        info!(
+            "removexattr: path={:?}, name={:?}, follow={}",
+            path, name, follow
+        );
+        let proc = self.linux_process();
+        let inode = proc.lookup_inode_at(FileDesc::CWD, &path, follow)?;
+        removexattr(&proc.credentials(), &inode, &name)
+    }
+}
+
+/// Get the value of the attribute `name` of `inode` on behalf of a process
+/// with `cred`, or only its size if `size` is 0.
+fn getxattr(
+    cred: &Credentials,
+    inode: &Arc<dyn INode>,
+    name: &str,
+    mut value: UserOutPtr<u8>,
+    size: usize,
+) -> SysResult {
+    let namespace = XattrNamespace::of(name)?;
+    cred.check_xattr(inode, namespace, false)?;
+    let (table, ino) = xattr_table(inode, false)?;
+    let data = table.get(ino, name)?;
+    if size == 0 {
+        return Ok(data.len());
+    }
+    if data.len() > size {
+        return Err(LxError::ERANGE);
+    }
+    value.write_array(&data)?;
+    Ok(data.len())
+}
+
+/// Set the attribute `name` of `inode` on behalf of a process with `cred`.
+fn setxattr(
+    cred: &Credentials,
+    inode: &Arc<dyn INode>,
+    name: &str,
+    value: UserInPtr<u8>,
+    size: usize,
+    flags: usize,
+) -> SysResult {
+    let flags = XattrFlags::from_bits(flags).ok_or(LxError::EINVAL)?;
+    let namespace = XattrNamespace::of(name)?;
+    if size > XATTR_SIZE_MAX {
+        return Err(LxError::E2BIG);
+    }
+    let value = value.read_array(size)?;
+    check_writable(inode)?;
+    cred.check_xattr(inode, namespace, true)?;
+    let (table, ino) = xattr_table(inode, true)?;
+    table.set(ino, name, &value, flags)?;
+    notify_inode(inode, InotifyMask::ATTRIB);
+    Ok(0)
+}
+
+/// List the names of the attributes of `inode` seen by a process with `cred`,
+/// or only get the size of the list if `size` is 0.
+fn listxattr(
+    cred: &Credentials,
+    inode: &Arc<dyn INode>,
+    mut list: UserOutPtr<u8>,
+    size: usize,
+) -> SysResult {
+    let (table, ino) = xattr_table(inode, false)?;
+    let names = table.list(ino, cred.is_privileged())?;
+    if size == 0 {
+        return Ok(names.len());
+    }
+    if names.len() > size {
+        return Err(LxError::ERANGE);
+    }
+    list.write_array(&names)?;
+    Ok(names.len())
+}
+
+/// Remove the attribute `name` of `inode` on behalf of a process with `cred`.
+fn removexattr(cred: &Credentials, inode: &Arc<dyn INode>, name: &str) -> SysResult {
+    let namespace = XattrNamespace::of(name)?;
+    check_writable(inode)?;
+    cred.check_xattr(inode, namespace, true)?;
+    let (table, ino) = xattr_table(inode, true)?;
+    table.remove(ino, name)?;
+    notify_inode(inode, InotifyMask::ATTRIB);
+    Ok(0)
+}
@@ -2,11 +2,15 @@
 #![allow(dead_code)]
 
 use bitflags::*;
+use core::mem::size_of;
 use numeric_enum_macro::numeric_enum;
 
 pub use crate::linux_object::ipc::*;
 
 use super::*;
+use crate::linux_object::cred::Access;
+use crate::linux_object::time::TimeSpec;
+use crate::zircon_object::vm::*;
 
 impl Syscall<'_> {
     /// returns the semaphore set identifier associated with the argument key
@@ -117,6 +121,242 @@ impl Syscall<'_> {
             }
         }
     }
+
+    /// returns the identifier of the shared memory segment associated with the argument key
+    pub fn sys_shmget(&self, key: usize, size: usize, flags: usize) -> SysResult {
+        info!("shmget: key: {} size: {} flags: {:#x}", key, size, flags);
+        let proc = self.linux_process();
+        let cred = proc.credentials();
+        let id = ShmSegment::get_or_create(key as u32, size, flags, &cred, proc.pid() as u32)?;
+        Ok(id)
+    }
+
+    /// attaches the shared memory segment identified by shmid to the address space
+    ///
+    /// The segment is attached at `addr`, or at an address chosen by the kernel if it is 0.
+    pub fn sys_shmat(&self, id: usize, addr: usize, flags: usize) -> SysResult {
+        info!("shmat: id: {}, addr: {:#x}, flags: {:#x}", id, addr, flags);
+        let flags = ShmFlags::from_bits_truncate(flags);
+        let proc = self.linux_process();
+        let segment = ShmSegment::get(id).ok_or(LxError::EINVAL)?;
+
+        let mut access = Access::READ;
+        let mut mmu_flags = MMUFlags::USER | MMUFlags::READ;
+        if !flags.contains(ShmFlags::SHM_RDONLY) {
+            access |= Access::WRITE;
+            mmu_flags |= MMUFlags::WRITE;
+        }
+        if flags.contains(ShmFlags::SHM_EXEC) {
+            access |= Access::EXEC;
+            mmu_flags |= MMUFlags::EXECUTE;
+        }
+        segment
+            .shmid_ds
+            .lock()
+            .perm
+            .check(&proc.credentials(), access)?;
+
+        let vmar = self.zircon_process().vmar();
+        let vmo = segment.vmo();
+        let len = vmo.len();
+        let addr = if addr == 0 {
+            vmar.map_shared(None, vmo, 0, len, mmu_flags, false)?
+        } else {
+            let addr = if flags.contains(ShmFlags::SHM_RND) {
+                round_down_pages(addr)
+            } else {
+                addr
+            };
+            if !page_aligned(addr) || addr < vmar.addr() {
+                return Err(LxError::EINVAL);
+            }
+            let remap = flags.contains(ShmFlags::SHM_REMAP);
+            vmar.map_shared(Some(addr - vmar.addr()), vmo, 0, len, mmu_flags, remap)
+                .map_err(|_| LxError::EINVAL)?
+        };
+        {
+            let mut shmid_ds = segment.shmid_ds.lock();
+            shmid_ds.atime = TimeSpec::now().sec;
+            shmid_ds.lpid = proc.pid() as u32;
+        }
+        let attach = ShmAttach {
+            segment,
+            flags: mmu_flags,
+        };
+        proc.shm_attach(addr, attach);
+        Ok(addr)
+    }
+
+    /// detaches the shared memory segment located at the address specified by shmaddr
+    pub fn sys_shmdt(&self, addr: usize) -> SysResult {
+        info!("shmdt: addr: {:#x}", addr);
+        let proc = self.linux_process();
+        let attach = proc.shm_detach(addr).ok_or(LxError::EINVAL)?;
+        let len = attach.segment.vmo().len();
+        self.zircon_process().vmar().unmap(addr, len)?;
+        let mut shmid_ds = attach.segment.shmid_ds.lock();
+        shmid_ds.dtime = TimeSpec::now().sec;
+        shmid_ds.lpid = proc.pid() as u32;
+        Ok(0)
+    }
+
+    /// shared memory control operations
+    ///
+    /// performs the control operation specified by cmd on the shared memory segment identified by shmid
+    pub fn sys_shmctl(&self, id: usize, cmd: usize, buf: usize) -> SysResult {
+        info!("shmctl: id: {}, cmd: {}, buf: {:#x}", id, cmd, buf);
+        let proc = self.linux_process();
+        let segment = ShmSegment::get(id).ok_or(LxError::EINVAL)?;
+        let cred = proc.credentials();
+        let perm = segment.shmid_ds.lock().perm;
+        match IpcctlCmds::try_from(cmd & !IPC_64).map_err(|_| LxError::EINVAL)? {
+            IpcctlCmds::IPC_RMID => {
+                if !perm.is_owner(&cred) {
+                    return Err(LxError::EPERM);
+                }
+                ShmSegment::remove(id);
+            }
+            IpcctlCmds::IPC_SET => {
+                if !perm.is_owner(&cred) {
+                    return Err(LxError::EPERM);
+                }
+                let ds: ShmidDs = UserInPtr::from(buf).read()?;
+                segment.set(&ds);
+            }
+            IpcctlCmds::IPC_STAT => {
+                perm.check(&cred, Access::READ)?;
+                let mut ptr = UserOutPtr::from(buf);
+                ptr.write(*segment.shmid_ds.lock())?;
+            }
+        }
+        Ok(0)
+    }
+
+    /// returns the message queue identifier associated with the value of the key argument
+    pub fn sys_msgget(&self, key: usize, flags: usize) -> SysResult {
+        info!("msgget: key: {} flags: {:#x}", key, flags);
+        let proc = self.linux_process();
+        let id = MsgQueue::get_or_create(key as u32, flags, &proc.credentials())?;
+        Ok(id)
+    }
+
+    /// appends a copy of the message pointed to by msgp to the message queue
+    ///
+    /// The message is a `long` type followed by `size` bytes of text.
+    pub async fn sys_msgsnd(&self, id: usize, msgp: usize, size: usize, flags: usize) -> SysResult {
+        info!(
+            "msgsnd: id: {}, msgp: {:#x}, size: {}, flags: {:#x}",
+            id, msgp, size, flags
+        );
+        let proc = self.linux_process();
+        let queue = MsgQueue::get(id).ok_or(LxError::EINVAL)?;
+        if size > MSGMAX {
+            return Err(LxError::EINVAL);
+        }
+        queue
+            .stat()
+            .perm
+            .check(&proc.credentials(), Access::WRITE)?;
+        let mtype = UserInPtr::<isize>::from(msgp).read()?;
+        if mtype < 1 {
+            return Err(LxError::EINVAL);
+        }
+        let data = UserInPtr::<u8>::from(msgp + size_of::<isize>()).read_array(size)?;
+        let flags = MsgFlags::from_bits_truncate(flags);
+        queue.send(mtype, data, flags, proc.pid() as u32).await?;
+        Ok(0)
+    }
+
+    /// removes a message selected by msgtyp from the message queue and places it in the buffer pointed to by msgp
+    ///
+    /// returns the number of bytes copied into the text of the buffer
+    pub async fn sys_msgrcv(
+        &self,
+        id: usize,
+        msgp: usize,
+        size: usize,
+        mtype: isize,
+        flags: usize,
+    ) -> SysResult {
+        info!(
+            "msgrcv: id: {}, msgp: {:#x}, size: {}, mtype: {}, flags: {:#x}",
+            id, msgp, size, mtype, flags
+        );
+        let proc = self.linux_process();
+        let queue = MsgQueue::get(id).ok_or(LxError::EINVAL)?;
+        if (size as isize) < 0 {
+            return Err(LxError::EINVAL);
+        }
+        queue.stat().perm.check(&proc.credentials(), Access::READ)?;
+        let flags = MsgFlags::from_bits_truncate(flags);
+        let (mtype, data) = queue.receive(mtype, size, flags, proc.pid() as u32).await?;
+        UserOutPtr::<isize>::from(msgp).write(mtype)?;
+        UserOutPtr::<u8>::from(msgp + size_of::<isize>()).write_array(&data)?;
+        Ok(data.len())
+    }
+
+    /// message control operations
+    ///
+    /// performs the control operation specified by cmd on the message queue identified by msqid
+    pub fn sys_msgctl(&self, id: usize, cmd: usize, buf: usize) -> SysResult {
+        info!("msgctl: id: {}, cmd: {}, buf: {:#x}", id, cmd, buf);
+        let proc = self.linux_process();
+        let queue = MsgQueue::get(id).ok_or(LxError::EINVAL)?;
+        let cred = proc.credentials();
+        let perm = queue.stat().perm;
+        match IpcctlCmds::try_from(cmd & !IPC_64).map_err(|_| LxError::EINVAL)? {
+            IpcctlCmds::IPC_RMID => {
+                if !perm.is_owner(&cred) {
+                    return Err(LxError::EPERM);
+                }
+                MsgQueue::remove(id);
+            }
+            IpcctlCmds::IPC_SET => {
+                if !perm.is_owner(&cred) {
+                    return Err(LxError::EPERM);
+                }
+                let ds: MsqidDs = UserInPtr::from(buf).read()?;
+                queue.set(&ds, &cred)?;
+            }
+            IpcctlCmds::IPC_STAT => {
+                perm.check(&cred, Access::READ)?;
+                let mut ptr = UserOutPtr::from(buf);
+                ptr.write(queue.stat())?;
+            }
+        }
+        Ok(0)
+    }
+}
+
+/// Flag of the version of the structures in the cmd of shmctl() and msgctl()
+const IPC_64: usize = 0x100;
+
+numeric_enum! {
+    #[repr(usize)]
+    #[derive(Debug, Eq, PartialEq)]
+    #[allow(non_camel_case_types)]
+    /// for the second argument of shmctl() and msgctl(), specified the control operation
+    pub enum IpcctlCmds {
+        /// Remove the object
+        IPC_RMID = 0,
+        /// Write the values of some members of the data structure pointed to by buf
+        IPC_SET = 1,
+        /// Copy information from the kernel data structure into the one pointed to by buf
+        IPC_STAT = 2,
+    }
+}
+
+bitflags! {
+    pub struct ShmFlags: usize {
+        /// Attach the segment for read-only access
+        const SHM_RDONLY = 0o10000;
+        /// Round the attach address down to a multiple of SHMLBA
+        const SHM_RND = 0o20000;
+        /// Replace any existing mapping in the range of the segment
+        const SHM_REMAP = 0o40000;
+        /// Allow the contents of the segment to be executed
+        const SHM_EXEC = 0o100000;
+    }
 }
 
 numeric_enum! {
@@ -1,6 +1,13 @@
 use super::*;
 use bitflags::bitflags;
+use core::sync::atomic::Ordering;
+use core::time::Duration;
+use crate::kernel_hal::timer_now;
+use crate::linux_object::cred::IdSet;
+use crate::linux_object::futex::*;
+use crate::linux_object::rlimit::*;
 use crate::linux_object::time::*;
+use crate::zircon_object::signal::Futex;
 
 impl Syscall<'_> {
     #[cfg(target_arch = "x86_64")]
@@ -11,7 +18,7 @@ impl Syscall<'_> {
         match code {
             ARCH_SET_FS => {
                 info!("sys_arch_prctl: set FSBASE to {:#x}", addr);
-                self.regs.fsbase = addr;
+                self.context.general.fsbase = addr;
                 Ok(0)
             }
             _ => Err(LxError::EINVAL),
@@ -48,46 +55,232 @@ impl Syscall<'_> {
     /// - `uaddr` - points to the futex word.
     /// - `op` -  the operation to perform on the futex
     /// - `val` -  a value whose meaning and purpose depends on op
-    /// - `timeout` - not support now
-    /// TODO: support timeout
+    /// - `timeout` - the timeout of a wait, or the number of waiters to requeue
+    /// - `uaddr2` - points to the futex word to requeue waiters to
+    /// - `val3` - the value to compare for `FUTEX_CMP_REQUEUE`, or the bitset
+    ///
+    /// A waiter is only woken up by a bitset with a bit in common with its own,
+    /// `FUTEX_WAIT` and `FUTEX_WAKE` use all bits.
+    /// `CLOCK_REALTIME` and `CLOCK_MONOTONIC` are the same clock here.
     pub async fn sys_futex(
         &self,
         uaddr: usize,
         op: u32,
         val: i32,
-        timeout: UserInPtr<TimeSpec>,
+        timeout: usize,
+        uaddr2: usize,
+        val3: u32,
     ) -> SysResult {
-        let op = FutexFlags::from_bits_truncate(op);
+        let flags = FutexFlags::from_bits_truncate(op);
+        let cmd = op & !FutexFlags::all().bits();
         info!(
-            "futex: uaddr: {:#x}, op: {:?}, val: {}, timeout_ptr: {:?}",
-            uaddr, op, val, timeout
+            "futex: uaddr: {:#x}, op: {:#x}, val: {}, timeout: {:#x}, uaddr2: {:#x}, val3: {:#x}",
+            uaddr, op, val, timeout, uaddr2, val3
         );
-        if op.contains(FutexFlags::PRIVATE) {
-            warn!("process-shared futex is unimplemented");
-        }
-        let futex = self.linux_process().get_futex(uaddr);
-        match op.bits & 0xf {
-            0 => {
-                // FIXME: support timeout
-                let _timeout = timeout.read_if_not_null()?;
-                match futex.wait(val).await {
-                    Ok(_) => Ok(0),
-                    Err(ZxError::BAD_STATE) => Err(LxError::EAGAIN),
-                    Err(e) => Err(e.into()),
+        let proc = self.zircon_process();
+        let shared = !flags.contains(FutexFlags::PRIVATE);
+        match cmd {
+            FUTEX_WAIT | FUTEX_WAIT_BITSET => {
+                let bitset = match cmd {
+                    FUTEX_WAIT => FUTEX_BITSET_MATCH_ANY,
+                    _ => val3,
+                };
+                if bitset == 0 {
+                    return Err(LxError::EINVAL);
+                }
+                // the timeout of FUTEX_WAIT is relative, the others are absolute
+                let deadline = futex_deadline(timeout.into(), cmd == FUTEX_WAIT)?;
+                let futex = get_futex(proc, uaddr, shared)?;
+                self.futex_wait(&futex, val, None, bitset, deadline).await?;
+                Ok(0)
+            }
+            FUTEX_WAKE | FUTEX_WAKE_BITSET => {
+                let bitset = match cmd {
+                    FUTEX_WAKE => FUTEX_BITSET_MATCH_ANY,
+                    _ => val3,
+                };
+                if bitset == 0 {
+                    return Err(LxError::EINVAL);
+                }
+                let futex = get_futex(proc, uaddr, shared)?;
+                Ok(futex.wake_bitset(val as usize, bitset))
+            }
+            FUTEX_REQUEUE | FUTEX_CMP_REQUEUE => {
+                let requeue_count = timeout as i32;
+                if val < 0 || requeue_count < 0 {
+                    return Err(LxError::EINVAL);
+                }
+                let futex = get_futex(proc, uaddr, shared)?;
+                let requeue_futex = get_futex(proc, uaddr2, shared)?;
+                if Arc::ptr_eq(&futex, &requeue_futex) {
+                    return Err(LxError::EINVAL);
                 }
+                // FUTEX_REQUEUE does not compare the value
+                let current_value = match cmd {
+                    FUTEX_CMP_REQUEUE => val3 as i32,
+                    _ => futex_word(uaddr)?.load(Ordering::SeqCst),
+                };
+                futex
+                    .requeue(
+                        current_value,
+                        val as usize,
+                        requeue_count as usize,
+                        &requeue_futex,
+                        None,
+                    )
+                    .map_err(|err| match err {
+                        ZxError::BAD_STATE => LxError::EAGAIN,
+                        err => err.into(),
+                    })
             }
-            1 => {
-                let woken_up_count = futex.wake(val as usize);
-                Ok(woken_up_count)
+            FUTEX_LOCK_PI => {
+                let deadline = futex_deadline(timeout.into(), false)?;
+                self.futex_lock_pi(uaddr, shared, deadline).await
             }
+            FUTEX_TRYLOCK_PI => self.futex_trylock_pi(uaddr),
+            FUTEX_UNLOCK_PI => self.futex_unlock_pi(uaddr, shared),
             _ => {
-                warn!("unsupported futex operation: {:?}", op);
+                warn!("unsupported futex operation: {:#x}", op);
                 Err(LxError::ENOSYS)
             }
         }
     }
 
+    /// Wait on `futex` while its value is `val`, until `deadline`, to be woken
+    /// up by a bitset with a bit in common with `bitset`.
+    ///
+    /// On success the futex is owned by `new_owner`.
+    async fn futex_wait(
+        &self,
+        futex: &Arc<Futex>,
+        val: i32,
+        new_owner: Option<Arc<Thread>>,
+        bitset: u32,
+        deadline: Duration,
+    ) -> LxResult {
+        let thread = Some((*self.thread).clone());
+        let future = futex.wait_bitset(val, thread, new_owner, bitset);
+        let ret = self
+            .thread
+            .blocking_run(future, ThreadState::BlockedFutex, deadline, None)
+            .await;
+        match ret {
+            Ok(()) => Ok(()),
+            Err(ZxError::BAD_STATE) => Err(LxError::EAGAIN),
+            Err(ZxError::TIMED_OUT) => Err(LxError::ETIMEDOUT),
+            Err(ZxError::STOP) => Err(LxError::EINTR),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Lock the PI futex at `uaddr`, waiting for its owner to unlock it.
+    ///
+    /// The futex word holds the TID of the owner. A waiter sets
+    /// `FUTEX_WAITERS`, and the owner hands the futex over on unlock.
+    async fn futex_lock_pi(&self, uaddr: VirtAddr, shared: bool, deadline: Duration) -> SysResult {
+        let word = futex_word(uaddr)?;
+        let tid = self.thread.id() as u32;
+        let mut woken = false;
+        loop {
+            let value = word.load(Ordering::SeqCst) as u32;
+            let owner = value & FUTEX_TID_MASK;
+            if owner == tid {
+                // handed over by the owner
+                return if woken { Ok(0) } else { Err(LxError::EDEADLK) };
+            }
+            // take it if it is free, keeping `FUTEX_WAITERS` and `FUTEX_OWNER_DIED`
+            // or announce the waiter
+            let new_value = match owner {
+                0 => value | tid,
+                _ => value | FUTEX_WAITERS,
+            };
+            if word
+                .compare_exchange(
+                    value as i32,
+                    new_value as i32,
+                    Ordering::SeqCst,
+                    Ordering::SeqCst,
+                )
+                .is_err()
+            {
+                continue;
+            }
+            if owner == 0 {
+                return Ok(0);
+            }
+            let futex = get_futex(self.zircon_process(), uaddr, shared)?;
+            let thread = (*self.thread).clone();
+            match self
+                .futex_wait(
+                    &futex,
+                    new_value as i32,
+                    Some(thread),
+                    FUTEX_BITSET_MATCH_ANY,
+                    deadline,
+                )
+                .await
+            {
+                Ok(()) => woken = true,
+                Err(LxError::EAGAIN) => {}
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Lock the PI futex at `uaddr` if it is free.
+    fn futex_trylock_pi(&self, uaddr: VirtAddr) -> SysResult {
+        let word = futex_word(uaddr)?;
+        let tid = self.thread.id() as u32;
+        let value = word.load(Ordering::SeqCst) as u32;
+        match value & FUTEX_TID_MASK {
+            0 => {
+                let new_value = value | tid;
+                word.compare_exchange(
+                    value as i32,
+                    new_value as i32,
+                    Ordering::SeqCst,
+                    Ordering::SeqCst,
+                )
+                .map_err(|_| LxError::EAGAIN)?;
+                Ok(0)
+            }
+            owner if owner == tid => Err(LxError::EDEADLK),
+            _ => Err(LxError::EAGAIN),
+        }
+    }
+
+    /// Unlock the PI futex at `uaddr`, handing it over to the first waiter.
+    fn futex_unlock_pi(&self, uaddr: VirtAddr, shared: bool) -> SysResult {
+        let word = futex_word(uaddr)?;
+        let tid = self.thread.id() as u32;
+        if word.load(Ordering::SeqCst) as u32 & FUTEX_TID_MASK != tid {
+            return Err(LxError::EPERM);
+        }
+        let futex = get_futex(self.zircon_process(), uaddr, shared)?;
+        futex.wake_single_owner();
+        // more waiters may be left, the new owner will unlock in the kernel
+        let new_value = match futex.owner() {
+            Some(owner) => owner.id() as u32 | FUTEX_WAITERS,
+            None => 0,
+        };
+        word.store(new_value as i32, Ordering::SeqCst);
+        Ok(0)
+    }
+
+    /// get the resource limit of `resource`
+    pub fn sys_getrlimit(&mut self, resource: usize, old_limit: UserOutPtr<RLimit>) -> SysResult {
+        self.sys_prlimit64(0, resource, 0.into(), old_limit)
+    }
+
+    /// set the resource limit of `resource`
+    pub fn sys_setrlimit(&mut self, resource: usize, new_limit: UserInPtr<RLimit>) -> SysResult {
+        self.sys_prlimit64(0, resource, new_limit, 0.into())
+    }
+
     /// Combines and extends the functionality of setrlimit() and getrlimit()
+    ///
+    /// The process `pid`, or the calling process if 0, must have the same
+    /// user and group IDs as the caller, unless the caller is privileged.
     pub fn sys_prlimit64(
         &mut self,
         pid: usize,
@@ -99,29 +292,36 @@ impl Syscall<'_> {
             "prlimit64: pid: {}, resource: {}, new_limit: {:x?}, old_limit: {:x?}",
             pid, resource, new_limit, old_limit
         );
-        let proc = self.linux_process();
-        match resource {
-            RLIMIT_STACK => {
-                old_limit.write_if_not_null(RLimit {
-                    cur: USER_STACK_SIZE as u64,
-                    max: USER_STACK_SIZE as u64,
-                })?;
-                Ok(0)
-            }
-            RLIMIT_NOFILE => {
-                let new_limit = new_limit.read_if_not_null()?;
-                old_limit.write_if_not_null(proc.file_limit(new_limit))?;
-                Ok(0)
-            }
-            RLIMIT_RSS | RLIMIT_AS => {
-                old_limit.write_if_not_null(RLimit {
-                    cur: 1024 * 1024 * 1024,
-                    max: 1024 * 1024 * 1024,
-                })?;
-                Ok(0)
+        if resource >= RLIM_NLIMITS {
+            return Err(LxError::EINVAL);
+        }
+        let proc = match pid {
+            0 => self.zircon_process().clone(),
+            _ => process_by_pid(pid as KoID).ok_or(LxError::ESRCH)?,
+        };
+        let cred = self.linux_process().credentials();
+        let target = proc.linux().credentials();
+        let same_ids = |caller: &IdSet, target: &IdSet| {
+            [target.real, target.effective, target.saved]
+                .iter()
+                .all(|&id| id == caller.real)
+        };
+        if !cred.is_privileged()
+            && !(same_ids(&cred.user, &target.user) && same_ids(&cred.group, &target.group))
+        {
+            return Err(LxError::EPERM);
+        }
+        let new_limit = new_limit.read_if_not_null()?;
+        let mut rlimits = proc.linux().rlimits();
+        old_limit.write_if_not_null(rlimits.get(resource))?;
+        if let Some(limit) = new_limit {
+            rlimits.set(resource, limit, cred.is_privileged())?;
+            proc.linux().set_rlimits(rlimits);
+            if resource == RLIMIT_AS {
+                proc.vmar().set_space_limit(rlimits.space_limit());
             }
-            _ => Err(LxError::ENOSYS),
         }
+        Ok(0)
     }
 
     #[allow(unsafe_code)]
@@ -141,26 +341,48 @@ impl Syscall<'_> {
     }
 }
 
+/// tests that the value at the futex word pointed
+/// to by the address uaddr still contains the expected value val,
+/// and if so, then sleeps waiting for a FUTEX_WAKE operation on the futex word.
+const FUTEX_WAIT: u32 = 0;
+/// wakes at most val of the waiters that are waiting on the futex word at the address uaddr.
+const FUTEX_WAKE: u32 = 1;
+/// wakes at most val waiters, and moves at most val2 of the others to the futex word at uaddr2.
+const FUTEX_REQUEUE: u32 = 3;
+/// like FUTEX_REQUEUE, if the value at uaddr is still val3.
+const FUTEX_CMP_REQUEUE: u32 = 4;
+/// locks the PI futex at uaddr.
+const FUTEX_LOCK_PI: u32 = 6;
+/// unlocks the PI futex at uaddr.
+const FUTEX_UNLOCK_PI: u32 = 7;
+/// locks the PI futex at uaddr if it is free.
+const FUTEX_TRYLOCK_PI: u32 = 8;
+/// like FUTEX_WAIT, with a bitset in val3 and an absolute timeout.
+const FUTEX_WAIT_BITSET: u32 = 9;
+/// like FUTEX_WAKE, with a bitset in val3.
+const FUTEX_WAKE_BITSET: u32 = 10;
+/// the bitset of FUTEX_WAIT and FUTEX_WAKE, matching any other.
+const FUTEX_BITSET_MATCH_ANY: u32 = u32::MAX;
+
 bitflags! {
     /// for op argument in futex()
     struct FutexFlags: u32 {
-        /// tests that the value at the futex word pointed
-        /// to by the address uaddr still contains the expected value val,
-        /// and if so, then sleeps waiting for a FUTEX_WAKE operation on the futex word.
-        const WAIT      = 0;
-        /// wakes at most val of the waiters that are waiting on the futex word at the address uaddr.
-        const WAKE      = 1;
         /// can be employed with all futex operations, tells the kernel that the futex is process-private and not shared with another process
         const PRIVATE   = 0x80;
+        /// the timeout is measured against CLOCK_REALTIME instead of CLOCK_MONOTONIC
+        const CLOCK_REALTIME = 0x100;
     }
 }
 
-const USER_STACK_SIZE: usize = 8 * 1024 * 1024; // 8 MB, the default config of Linux
-
-const RLIMIT_STACK: usize = 3;
-const RLIMIT_RSS: usize = 5;
-const RLIMIT_NOFILE: usize = 7;
-const RLIMIT_AS: usize = 9;
+/// Get the deadline of a futex wait with `timeout`, which is `relative` or absolute.
+fn futex_deadline(timeout: UserInPtr<TimeSpec>, relative: bool) -> LxResult<Duration> {
+    let deadline = match timeout.read_if_not_null()? {
+        Some(timeout) if relative => timer_now() + timeout.into(),
+        Some(timeout) => timeout.into(),
+        None => Duration::from_nanos(u64::max_value()),
+    };
+    Ok(deadline)
+}
 
 /// sysinfo() return information sturct
 #[repr(C)]
@@ -3,6 +3,7 @@
 //! ## Example
 //! the syscall is called like this in the linux-loader:
 //! ```ignore
+//! let regs = &context.general;
 //! let num = regs.rax as u32;
 //! let args = [regs.rdi, regs.rsi, regs.rdx, regs.r10, regs.r8, regs.r9];
 //! let mut syscall = Syscall {
@@ -12,7 +13,7 @@
 //!     #[cfg(not(feature = "std"))]
 //!     syscall_entry: 0,
 //!     thread_fn,
-//!     regs,
+//!     context,
 //! };
 //! let ret = syscall.syscall(num, args).await;
 //! ```
@@ -22,29 +23,35 @@ use {
     consts::SyscallType as Sys,
     alloc::sync::Arc,
     core::convert::TryFrom,
-    crate::kernel_hal::{user::*, GeneralRegs},
+    crate::kernel_hal::{user::*, GeneralRegs, UserContext},
     crate::linux_object::{error::*, fs::FileDesc, process::*},
     crate::zircon_object::{object::*, task::*, vm::VirtAddr},
     crate::println,
 };
 
 mod consts;
+mod cred;
 mod file;
 mod ipc;
 mod misc;
+mod net;
+mod ptrace;
 mod signal;
 mod task;
 mod time;
+mod trace;
 mod vm;
 
+pub(crate) use self::{consts::SyscallType, trace::decode_args};
+
 /// The struct of Syscall which stores the information about making a syscall
 pub struct Syscall<'a> {
     /// the thread making a syscall
     pub thread: &'a CurrentThread,
     /// the entry of current syscall
     pub syscall_entry: VirtAddr,
-    /// store the regs statues
-    pub regs: &'a mut GeneralRegs,
+    /// the user context of the thread, with the registers
+    pub context: &'a mut UserContext,
     /// new thread function
     pub thread_fn: ThreadFn,
 }
@@ -60,10 +67,16 @@ impl Syscall<'_> {
                 return LxError::EINVAL as _;
             }
         };
+        // paths are read before the call may unmap them
+        let trace = if self.zircon_process().trace_syscalls() {
+            Some(decode_args(&sys_type, &args))
+        } else {
+            None
+        };
         let [a0, a1, a2, a3, a4, a5] = args;
         let ret = match sys_type {
             Sys::READ => self.sys_read(a0.into(), a1.into(), a2).await,
-            Sys::WRITE => self.sys_write(a0.into(), a1.into(), a2),
+            Sys::WRITE => self.sys_write(a0.into(), a1.into(), a2).await,
             Sys::OPENAT => self.sys_openat(a0.into(), a1.into(), a2, a3),
             Sys::CLOSE => self.sys_close(a0.into()),
             Sys::FSTAT => self.sys_fstat(a0.into(), a1.into()),
@@ -73,10 +86,10 @@ impl Syscall<'_> {
             Sys::PREAD64 => self.sys_pread(a0.into(), a1.into(), a2, a3 as _).await,
             Sys::PWRITE64 => self.sys_pwrite(a0.into(), a1.into(), a2, a3 as _),
             Sys::READV => self.sys_readv(a0.into(), a1.into(), a2).await,
-            Sys::WRITEV => self.sys_writev(a0.into(), a1.into(), a2),
+            Sys::WRITEV => self.sys_writev(a0.into(), a1.into(), a2).await,
             Sys::SENDFILE => self.sys_sendfile(a0.into(), a1.into(), a2.into(), a3).await,
-            Sys::FCNTL => self.sys_fcntl(a0.into(), a1, a2),
-            Sys::FLOCK => self.sys_flock(a0.into(), a1),
+            Sys::FCNTL => self.sys_fcntl(a0.into(), a1, a2).await,
+            Sys::FLOCK => self.sys_flock(a0.into(), a1).await,
             Sys::FSYNC => self.sys_fsync(a0.into()),
             Sys::FDATASYNC => self.sys_fdatasync(a0.into()),
             Sys::TRUNCATE => self.sys_truncate(a0.into(), a1),
@@ -88,21 +101,41 @@ impl Syscall<'_> {
             Sys::MKDIRAT => self.sys_mkdirat(a0.into(), a1.into(), a2),
             Sys::LINKAT => self.sys_linkat(a0.into(), a1.into(), a2.into(), a3.into(), a4),
             Sys::UNLINKAT => self.sys_unlinkat(a0.into(), a1.into(), a2),
-            Sys::SYMLINKAT => self.unimplemented("symlinkat", Err(LxError::EACCES)),
+            Sys::SYMLINKAT => self.sys_symlinkat(a0.into(), a1.into(), a2.into()),
             Sys::READLINKAT => self.sys_readlinkat(a0.into(), a1.into(), a2.into(), a3),
-            Sys::FCHMOD => self.unimplemented("fchmod", Ok(0)),
-            Sys::FCHMODAT => self.unimplemented("fchmodat", Ok(0)),
-            Sys::FCHOWN => self.unimplemented("fchown", Ok(0)),
-            Sys::FCHOWNAT => self.unimplemented("fchownat", Ok(0)),
+            Sys::FCHMOD => self.sys_fchmod(a0.into(), a1),
+            Sys::FCHMODAT => self.sys_fchmodat(a0.into(), a1.into(), a2, a3),
+            Sys::FCHOWN => self.sys_fchown(a0.into(), a1, a2),
+            Sys::FCHOWNAT => self.sys_fchownat(a0.into(), a1.into(), a2, a3, a4),
             Sys::FACCESSAT => self.sys_faccessat(a0.into(), a1.into(), a2, a3),
             Sys::DUP => self.sys_dup(a0.into()),
             Sys::DUP3 => self.sys_dup2(a0.into(), a1.into()), // TODO: handle `flags`
             Sys::PIPE2 => self.sys_pipe2(a0.into(), a1),      // TODO: handle `flags`
+            Sys::INOTIFY_INIT1 => self.sys_inotify_init1(a0),
+            Sys::INOTIFY_ADD_WATCH => self.sys_inotify_add_watch(a0.into(), a1.into(), a2),
+            Sys::INOTIFY_RM_WATCH => self.sys_inotify_rm_watch(a0.into(), a1),
             Sys::UTIMENSAT => self.sys_utimensat(a0.into(), a1.into(), a2.into(), a3),
             Sys::COPY_FILE_RANGE => {
                 self.sys_copy_file_range(a0.into(), a1.into(), a2.into(), a3.into(), a4, a5)
+            }
+            Sys::SPLICE => {
+                self.sys_splice(a0.into(), a1.into(), a2.into(), a3.into(), a4, a5)
                     .await
             }
+            Sys::TEE => self.sys_tee(a0.into(), a1.into(), a2, a3).await,
+            Sys::VMSPLICE => self.sys_vmsplice(a0.into(), a1, a2, a3).await,
+            Sys::SETXATTR => self.sys_setxattr(a0.into(), a1.into(), a2.into(), a3, a4),
+            Sys::LSETXATTR => self.sys_lsetxattr(a0.into(), a1.into(), a2.into(), a3, a4),
+            Sys::FSETXATTR => self.sys_fsetxattr(a0.into(), a1.into(), a2.into(), a3, a4),
+            Sys::GETXATTR => self.sys_getxattr(a0.into(), a1.into(), a2.into(), a3),
+            Sys::LGETXATTR => self.sys_lgetxattr(a0.into(), a1.into(), a2.into(), a3),
+            Sys::FGETXATTR => self.sys_fgetxattr(a0.into(), a1.into(), a2.into(), a3),
+            Sys::LISTXATTR => self.sys_listxattr(a0.into(), a1.into(), a2),
+            Sys::LLISTXATTR => self.sys_llistxattr(a0.into(), a1.into(), a2),
+            Sys::FLISTXATTR => self.sys_flistxattr(a0.into(), a1.into(), a2),
+            Sys::REMOVEXATTR => self.sys_removexattr(a0.into(), a1.into()),
+            Sys::LREMOVEXATTR => self.sys_lremovexattr(a0.into(), a1.into()),
+            Sys::FREMOVEXATTR => self.sys_fremovexattr(a0.into(), a1.into()),
 
             // io multiplexing
             //            Sys::PSELECT6 => self.sys_pselect6(a0, a1.into(), a2.into(), a3.into(), a4.into(), a5.into()),
@@ -112,13 +145,12 @@ impl Syscall<'_> {
             //            Sys::EPOLL_PWAIT => self.sys_epoll_pwait(a0, a1.into(), a2, a3, a4),
             //            Sys::EVENTFD2 => self.unimplemented("eventfd2", Err(LxError::EACCES)),
 
-            //            Sys::SOCKETPAIR => self.unimplemented("socketpair", Err(LxError::EACCES)),
             // file system
-            Sys::STATFS => self.unimplemented("statfs", Err(LxError::EACCES)),
-            Sys::FSTATFS => self.unimplemented("fstatfs", Err(LxError::EACCES)),
+            Sys::STATFS => self.sys_statfs(a0.into(), a1.into()),
+            Sys::FSTATFS => self.sys_fstatfs(a0.into(), a1.into()),
             Sys::SYNC => self.sys_sync(),
-            Sys::MOUNT => self.unimplemented("mount", Err(LxError::EACCES)),
-            Sys::UMOUNT2 => self.unimplemented("umount2", Err(LxError::EACCES)),
+            Sys::MOUNT => self.sys_mount(a0.into(), a1.into(), a2.into(), a3, a4.into()),
+            Sys::UMOUNT2 => self.sys_umount2(a0.into(), a1),
 
             // memory
             Sys::BRK => self.unimplemented("brk", Err(LxError::ENOMEM)),
@@ -139,30 +171,45 @@ impl Syscall<'_> {
             Sys::SCHED_GETAFFINITY => self.unimplemented("sched_getaffinity", Ok(0)),
 
             // socket
-            //            Sys::SOCKET => self.sys_socket(a0, a1, a2),
-            //            Sys::CONNECT => self.sys_connect(a0, a1.into(), a2),
-            //            Sys::ACCEPT => self.sys_accept(a0, a1.into(), a2.into()),
-            //            Sys::ACCEPT4 => self.sys_accept(a0, a1.into(), a2.into()), // use accept for accept4
-            //            Sys::SENDTO => self.sys_sendto(a0, a1.into(), a2, a3, a4.into(), a5),
-            //            Sys::RECVFROM => self.sys_recvfrom(a0, a1.into(), a2, a3, a4.into(), a5.into()),
-            //            Sys::SENDMSG => self.sys_sendmsg(),
-            //            Sys::RECVMSG => self.sys_recvmsg(a0, a1.into(), a2),
-            //            Sys::SHUTDOWN => self.sys_shutdown(a0, a1),
-            //            Sys::BIND => self.sys_bind(a0, a1.into(), a2),
-            //            Sys::LISTEN => self.sys_listen(a0, a1),
-            //            Sys::GETSOCKNAME => self.sys_getsockname(a0, a1.into(), a2.into()),
-            //            Sys::GETPEERNAME => self.sys_getpeername(a0, a1.into(), a2.into()),
-            //            Sys::SETSOCKOPT => self.sys_setsockopt(a0, a1, a2, a3.into(), a4),
-            //            Sys::GETSOCKOPT => self.sys_getsockopt(a0, a1, a2, a3.into(), a4.into()),
+            Sys::SOCKET => self.sys_socket(a0, a1, a2),
+            Sys::SOCKETPAIR => self.sys_socketpair(a0, a1, a2, a3.into()),
+            Sys::CONNECT => self.sys_connect(a0.into(), a1.into(), a2).await,
+            Sys::ACCEPT => self.sys_accept(a0.into(), a1.into(), a2.into(), 0).await,
+            Sys::ACCEPT4 => self.sys_accept(a0.into(), a1.into(), a2.into(), a3).await,
+            Sys::SENDTO => {
+                self.sys_sendto(a0.into(), a1.into(), a2, a3, a4.into(), a5)
+                    .await
+            }
+            Sys::RECVFROM => {
+                self.sys_recvfrom(a0.into(), a1.into(), a2, a3, a4.into(), a5.into())
+                    .await
+            }
+            Sys::SENDMSG => self.sys_sendmsg(a0.into(), a1.into(), a2).await,
+            Sys::RECVMSG => self.sys_recvmsg(a0.into(), a1.into(), a2).await,
+            Sys::SHUTDOWN => self.sys_shutdown(a0.into(), a1),
+            Sys::BIND => self.sys_bind(a0.into(), a1.into(), a2),
+            Sys::LISTEN => self.sys_listen(a0.into(), a1),
+            Sys::GETSOCKNAME => self.sys_getsockname(a0.into(), a1.into(), a2.into()),
+            Sys::GETPEERNAME => self.sys_getpeername(a0.into(), a1.into(), a2.into()),
+            Sys::SETSOCKOPT => self.sys_setsockopt(a0.into(), a1, a2, a3.into(), a4),
+            Sys::GETSOCKOPT => self.sys_getsockopt(a0.into(), a1, a2, a3.into(), a4.into()),
 
             // process
-            Sys::CLONE => self.sys_clone(a0, a1, a2.into(), a3.into(), a4),
+            #[cfg(target_arch = "x86_64")]
+            Sys::CLONE => self.sys_clone(a0, a1, a2.into(), a3.into(), a4).await,
+            // the TLS comes before the child TID on riscv
+            #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+            Sys::CLONE => self.sys_clone(a0, a1, a2.into(), a4.into(), a3).await,
             Sys::EXECVE => self.sys_execve(a0.into(), a1.into(), a2.into()),
             Sys::EXIT => self.sys_exit(a0 as _),
             Sys::EXIT_GROUP => self.sys_exit_group(a0 as _),
-            Sys::WAIT4 => self.sys_wait4(a0 as _, a1.into(), a2 as _).await,
+            Sys::WAIT4 => self.sys_wait4(a0 as _, a1.into(), a2 as _, a3.into()).await,
+            Sys::WAITID => self.sys_waitid(a0, a1, a2.into(), a3 as _, a4.into()).await,
+            Sys::PTRACE => self.sys_ptrace(a0, a1, a2, a3),
             Sys::SET_TID_ADDRESS => self.sys_set_tid_address(a0.into()),
-            Sys::FUTEX => self.sys_futex(a0, a1 as _, a2 as _, a3.into()).await,
+            Sys::FUTEX => self.sys_futex(a0, a1 as _, a2 as _, a3, a4, a5 as _).await,
+            Sys::SET_ROBUST_LIST => self.sys_set_robust_list(a0.into(), a1),
+            Sys::GET_ROBUST_LIST => self.sys_get_robust_list(a0 as _, a1.into(), a2.into()),
             Sys::TKILL => self.unimplemented("tkill", Ok(0)),
 
             // time
@@ -179,27 +226,58 @@ impl Syscall<'_> {
             #[cfg(not(target_arch = "mips"))]
             Sys::SEMCTL => self.sys_semctl(a0, a1, a2, a3),
 
+            // shm
+            #[cfg(not(target_arch = "mips"))]
+            Sys::SHMGET => self.sys_shmget(a0, a1, a2),
+            #[cfg(not(target_arch = "mips"))]
+            Sys::SHMAT => self.sys_shmat(a0, a1, a2),
+            #[cfg(not(target_arch = "mips"))]
+            Sys::SHMDT => self.sys_shmdt(a0),
+            #[cfg(not(target_arch = "mips"))]
+            Sys::SHMCTL => self.sys_shmctl(a0, a1, a2),
+
+            // msg
+            #[cfg(not(target_arch = "mips"))]
+            Sys::MSGGET => self.sys_msgget(a0, a1),
+            #[cfg(not(target_arch = "mips"))]
+            Sys::MSGSND => self.sys_msgsnd(a0, a1, a2, a3).await,
+            #[cfg(not(target_arch = "mips"))]
+            Sys::MSGRCV => self.sys_msgrcv(a0, a1, a2, a3 as _, a4).await,
+            #[cfg(not(target_arch = "mips"))]
+            Sys::MSGCTL => self.sys_msgctl(a0, a1, a2),
+
             // system
             Sys::GETPID => self.sys_getpid(),
             Sys::GETTID => self.sys_gettid(),
             Sys::UNAME => self.sys_uname(a0.into()),
-            Sys::UMASK => self.unimplemented("umask", Ok(0o777)),
-            //            Sys::GETRLIMIT => self.sys_getrlimit(),
-            //            Sys::SETRLIMIT => self.sys_setrlimit(),
-            Sys::GETRUSAGE => self.sys_getrusage(a0, a1.into()),
+            Sys::UMASK => self.sys_umask(a0),
+            Sys::GETRLIMIT => self.sys_getrlimit(a0, a1.into()),
+            Sys::SETRLIMIT => self.sys_setrlimit(a0, a1.into()),
+            Sys::GETRUSAGE => self.sys_getrusage(a0 as _, a1.into()),
             Sys::SYSINFO => self.sys_sysinfo(a0.into()),
             Sys::TIMES => self.sys_times(a0.into()),
-            Sys::GETUID => self.unimplemented("getuid", Ok(0)),
-            Sys::GETGID => self.unimplemented("getgid", Ok(0)),
-            Sys::SETUID => self.unimplemented("setuid", Ok(0)),
-            Sys::GETEUID => self.unimplemented("geteuid", Ok(0)),
-            Sys::GETEGID => self.unimplemented("getegid", Ok(0)),
-            Sys::SETPGID => self.unimplemented("setpgid", Ok(0)),
+            Sys::GETUID => self.sys_getuid(),
+            Sys::GETGID => self.sys_getgid(),
+            Sys::SETUID => self.sys_setuid(a0),
+            Sys::SETGID => self.sys_setgid(a0),
+            Sys::GETEUID => self.sys_geteuid(),
+            Sys::GETEGID => self.sys_getegid(),
+            Sys::SETREUID => self.sys_setreuid(a0, a1),
+            Sys::SETREGID => self.sys_setregid(a0, a1),
+            Sys::SETRESUID => self.sys_setresuid(a0, a1, a2),
+            Sys::GETRESUID => self.sys_getresuid(a0.into(), a1.into(), a2.into()),
+            Sys::SETRESGID => self.sys_setresgid(a0, a1, a2),
+            Sys::GETRESGID => self.sys_getresgid(a0.into(), a1.into(), a2.into()),
+            Sys::SETFSUID => self.sys_setfsuid(a0),
+            Sys::SETFSGID => self.sys_setfsgid(a0),
+            Sys::SETPGID => self.sys_setpgid(a0, a1),
             Sys::GETPPID => self.sys_getppid(),
-            Sys::SETSID => self.unimplemented("setsid", Ok(0)),
-            Sys::GETPGID => self.unimplemented("getpgid", Ok(0)),
-            Sys::GETGROUPS => self.unimplemented("getgroups", Ok(0)),
-            Sys::SETGROUPS => self.unimplemented("setgroups", Ok(0)),
+            Sys::GETPGRP => self.sys_getpgrp(),
+            Sys::SETSID => self.sys_setsid(),
+            Sys::GETPGID => self.sys_getpgid(a0),
+            Sys::GETSID => self.sys_getsid(a0),
+            Sys::GETGROUPS => self.sys_getgroups(a0, a1.into()),
+            Sys::SETGROUPS => self.sys_setgroups(a0, a1.into()),
             //            Sys::SETPRIORITY => self.sys_set_priority(a0),
             Sys::PRCTL => self.unimplemented("prctl", Ok(0)),
             Sys::MEMBARRIER => self.unimplemented("membarrier", Ok(0)),
@@ -216,6 +294,9 @@ impl Syscall<'_> {
             _ => self.riscv_syscall(sys_type, args).await,
         };
         info!("<= {:x?}", ret);
+        if let Some(args) = trace {
+            self.trace_result(&sys_type, &args, &ret);
+        }
         match ret {
             Ok(value) => value as isize,
             Err(err) => -(err as isize),
@@ -233,6 +314,7 @@ impl Syscall<'_> {
             Sys::POLL => self.sys_poll(a0.into(), a1, a2).await,
             Sys::ACCESS => self.sys_access(a0.into(), a1),
             Sys::PIPE => self.sys_pipe(a0.into()),
+            Sys::INOTIFY_INIT => self.sys_inotify_init(),
             //            Sys::SELECT => self.sys_select(a0, a1.into(), a2.into(), a3.into(), a4.into()),
             Sys::DUP2 => self.sys_dup2(a0.into(), a1.into()),
             //            Sys::ALARM => self.unimplemented("alarm", Ok(0)),
@@ -242,10 +324,12 @@ impl Syscall<'_> {
             Sys::MKDIR => self.sys_mkdir(a0.into(), a1),
             Sys::RMDIR => self.sys_rmdir(a0.into()),
             Sys::LINK => self.sys_link(a0.into(), a1.into()),
+            Sys::SYMLINK => self.sys_symlink(a0.into(), a1.into()),
             Sys::UNLINK => self.sys_unlink(a0.into()),
             Sys::READLINK => self.sys_readlink(a0.into(), a1.into(), a2),
-            Sys::CHMOD => self.unimplemented("chmod", Ok(0)),
-            Sys::CHOWN => self.unimplemented("chown", Ok(0)),
+            Sys::CHMOD => self.sys_chmod(a0.into(), a1),
+            Sys::CHOWN => self.sys_chown(a0.into(), a1, a2),
+            Sys::LCHOWN => self.sys_lchown(a0.into(), a1, a2),
             Sys::ARCH_PRCTL => self.sys_arch_prctl(a0 as _, a1),
             Sys::TIME => self.sys_time(a0.into()),
             //            Sys::EPOLL_CREATE => self.sys_epoll_create(a0),
@@ -266,6 +350,7 @@ impl Syscall<'_> {
             Sys::POLL => self.sys_poll(a0.into(), a1, a2).await,
             Sys::ACCESS => self.sys_access(a0.into(), a1),
             Sys::PIPE => self.sys_pipe(a0.into()),
+            Sys::INOTIFY_INIT => self.sys_inotify_init(),
             //            Sys::SELECT => self.sys_select(a0, a1.into(), a2.into(), a3.into(), a4.into()),
             Sys::DUP2 => self.sys_dup2(a0.into(), a1.into()),
             //            Sys::ALARM => self.unimplemented("alarm", Ok(0)),
@@ -275,10 +360,12 @@ impl Syscall<'_> {
             Sys::MKDIR => self.sys_mkdir(a0.into(), a1),
             Sys::RMDIR => self.sys_rmdir(a0.into()),
             Sys::LINK => self.sys_link(a0.into(), a1.into()),
+            Sys::SYMLINK => self.sys_symlink(a0.into(), a1.into()),
             Sys::UNLINK => self.sys_unlink(a0.into()),
             Sys::READLINK => self.sys_readlink(a0.into(), a1.into(), a2),
-            Sys::CHMOD => self.unimplemented("chmod", Ok(0)),
-            Sys::CHOWN => self.unimplemented("chown", Ok(0)),
+            Sys::CHMOD => self.sys_chmod(a0.into(), a1),
+            Sys::CHOWN => self.sys_chown(a0.into(), a1, a2),
+            Sys::LCHOWN => self.sys_lchown(a0.into(), a1, a2),
             Sys::ARCH_PRCTL => self.sys_arch_prctl(a0 as _, a1),
             Sys::TIME => self.sys_time(a0.into()),
             //            Sys::EPOLL_CREATE => self.sys_epoll_create(a0),
@@ -0,0 +1,576 @@
+//! Syscalls for sockets
+//!
+//! - socket, socketpair
+//! - bind, listen, accept(4), connect
+//! - sendto, recvfrom, sendmsg, recvmsg
+//! - shutdown, getsockname, getpeername
+//! - setsockopt, getsockopt
+
+use super::*;
+use crate::linux_object::cred::Access;
+use crate::linux_object::fs::inotify::{notify_entry, InotifyMask};
+use crate::linux_object::fs::mount::{check_writable, inode_key};
+use crate::linux_object::fs::vfs::{FileType, FsError};
+use crate::linux_object::fs::{absolute_path, split_path, FileLike};
+use crate::linux_object::net::*;
+use crate::linux_object::signal::Signal as LinuxSignal;
+use alloc::vec::Vec;
+
+impl Syscall<'_> {
+    /// create an endpoint for communication
+    pub fn sys_socket(&self, domain: usize, type_: usize, protocol: usize) -> SysResult {
+        info!(
+            "socket: domain={}, type={:#x}, protocol={}",
+            domain, type_, protocol
+        );
+        let socket = self.create_socket(domain, type_, protocol)?;
+        let cloexec = type_ & SOCK_CLOEXEC != 0;
+        let fd = self
+            .linux_process()
+            .add_file_with_cloexec(socket, cloexec)?;
+        Ok(fd.into())
+    }
+
+    /// create a pair of connected sockets
+    pub fn sys_socketpair(
+        &self,
+        domain: usize,
+        type_: usize,
+        protocol: usize,
+        mut sv: UserOutPtr<[i32; 2]>,
+    ) -> SysResult {
+        info!(
+            "socketpair: domain={}, type={:#x}, protocol={}, sv={:?}",
+            domain, type_, protocol, sv
+        );
+        if domain != AddressFamily::Unix as usize {
+            return Err(LxError::EOPNOTSUPP);
+        }
+        if protocol != 0 {
+            return Err(LxError::EPROTONOSUPPORT);
+        }
+        let nonblock = type_ & SOCK_NONBLOCK != 0;
+        let cloexec = type_ & SOCK_CLOEXEC != 0;
+        let type_ = SocketType::try_from(type_ & SOCK_TYPE_MASK).map_err(|_| LxError::EINVAL)?;
+        let (socket0, socket1) = UnixSocket::new_pair(type_, nonblock);
+        let proc = self.linux_process();
+        let fd0 = proc.add_file_with_cloexec(socket0, cloexec)?;
+        let fd1 = match proc.add_file_with_cloexec(socket1, cloexec) {
+            Ok(fd) => fd,
+            Err(err) => {
+                proc.close_file(fd0)?;
+                return Err(err);
+            }
+        };
+        sv.write([fd0.into(), fd1.into()])?;
+        Ok(0)
+    }
+
+    /// bind a name to a socket
+    pub fn sys_bind(&self, fd: FileDesc, addr: UserInPtr<u8>, addr_len: usize) -> SysResult {
+        info!("bind: fd={:?}, addr={:?}, addr_len={}", fd, addr, addr_len);
+        let proc = self.linux_process();
+        let file_like = proc.get_file_like(fd)?;
+        let socket = file_like.as_socket().ok_or(LxError::ENOTSOCK)?;
+        let addr = self.read_sockaddr(addr, addr_len)?;
+        let path = match &addr {
+            SockAddr::Unix(UnixAddr::Path(path)) => path.clone(),
+            _ => {
+                socket.bind(addr)?;
+                return Ok(0);
+            }
+        };
+        let unix = unix_socket(&*file_like)?;
+        // a pathname socket is visible in the filesystem, by a new socket inode
+        let (dir_path, file_name) = split_path(&path);
+        let dir = proc.lookup_inode(dir_path)?;
+        check_writable(&dir)?;
+        let cred = proc.credentials();
+        cred.check_inode(&dir, Access::WRITE | Access::EXEC)?;
+        let mode = 0o777 & !proc.umask();
+        let inode = match dir.create(file_name, FileType::Socket, mode) {
+            Ok(inode) => inode,
+            Err(FsError::EntryExist) => return Err(LxError::EADDRINUSE),
+            Err(err) => return Err(err.into()),
+        };
+        let result = cred
+            .set_owner(&inode)
+            .and_then(|_| unix.bind_inode(addr, inode_key(&inode)?));
+        if let Err(err) = result {
+            // don't leave a file no socket is bound to
+            if let Err(err) = dir.unlink(file_name) {
+                warn!("bind: failed to remove {:?}: {:?}", path, err);
+            }
+            return Err(err);
+        }
+        notify_entry(&dir, file_name, false, InotifyMask::CREATE, 0);
+        Ok(0)
+    }
+
+    /// listen for connections on a socket
+    pub fn sys_listen(&self, fd: FileDesc, backlog: usize) -> SysResult {
+        info!("listen: fd={:?}, backlog={}", fd, backlog);
+        let file_like = self.linux_process().get_file_like(fd)?;
+        let socket = file_like.as_socket().ok_or(LxError::ENOTSOCK)?;
+        socket.listen(backlog)?;
+        Ok(0)
+    }
+
+    /// accept a connection on a socket
+    pub async fn sys_accept(
+        &self,
+        fd: FileDesc,
+        addr: UserOutPtr<u8>,
+        addr_len: UserInOutPtr<u32>,
+        flags: usize,
+    ) -> SysResult {
+        info!(
+            "accept: fd={:?}, addr={:?}, addr_len={:?}, flags={:#x}",
+            fd, addr, addr_len, flags
+        );
+        let proc = self.linux_process();
+        let file_like = proc.get_file_like(fd)?;
+        let socket = file_like.as_socket().ok_or(LxError::ENOTSOCK)?;
+        let (new_socket, remote) = socket.accept().await?;
+        if flags & SOCK_NONBLOCK != 0 {
+            new_socket.fcntl(F_SETFL, SOCK_NONBLOCK)?;
+        }
+        let new_fd = proc.add_file_with_cloexec(new_socket, flags & SOCK_CLOEXEC != 0)?;
+        write_sockaddr(&remote, addr, addr_len)?;
+        Ok(new_fd.into())
+    }
+
+    /// initiate a connection on a socket
+    pub async fn sys_connect(&self, fd: FileDesc, addr: UserInPtr<u8>, addr_len: usize) -> SysResult {
+        info!("connect: fd={:?}, addr={:?}, addr_len={}", fd, addr, addr_len);
+        let file_like = self.linux_process().get_file_like(fd)?;
+        let socket = file_like.as_socket().ok_or(LxError::ENOTSOCK)?;
+        let addr = self.read_sockaddr(addr, addr_len)?;
+        match self.socket_inode(&addr)? {
+            Some(inode) => unix_socket(&*file_like)?.connect_inode(addr, inode).await?,
+            None => socket.connect(addr).await?,
+        }
+        Ok(0)
+    }
+
+    /// send a message on a socket
+    pub async fn sys_sendto(
+        &self,
+        fd: FileDesc,
+        base: UserInPtr<u8>,
+        len: usize,
+        flags: usize,
+        addr: UserInPtr<u8>,
+        addr_len: usize,
+    ) -> SysResult {
+        info!(
+            "sendto: fd={:?}, base={:?}, len={}, flags={:#x}, addr={:?}, addr_len={}",
+            fd, base, len, flags, addr, addr_len
+        );
+        let file_like = self.linux_process().get_file_like(fd)?;
+        file_like.as_socket().ok_or(LxError::ENOTSOCK)?;
+        let data = base.read_array(len)?;
+        let addr = if addr.is_null() {
+            None
+        } else {
+            Some(self.read_sockaddr(addr, addr_len)?)
+        };
+        let flags = MsgFlags::from_bits_truncate(flags);
+        self.send_socket(&*file_like, &data, addr, Vec::new(), flags)
+            .await
+    }
+
+    /// Send `data` on the socket `file_like`, raising `SIGPIPE` when the
+    /// connection is broken unless `MSG_NOSIGNAL` is given.
+    pub(super) async fn send_socket(
+        &self,
+        file_like: &dyn FileLike,
+        data: &[u8],
+        addr: Option<SockAddr>,
+        rights: Vec<Arc<dyn FileLike>>,
+        flags: MsgFlags,
+    ) -> SysResult {
+        let socket = file_like.as_socket().ok_or(LxError::ENOTSOCK)?;
+        let inode = match &addr {
+            Some(addr) => self.socket_inode(addr)?,
+            None => None,
+        };
+        let result = match inode {
+            Some(inode) => unix_socket(file_like)?.send_to_inode(data, inode, rights),
+            None => socket.send(data, addr, rights, flags).await,
+        };
+        if let Err(LxError::EPIPE) = result {
+            if !flags.contains(MsgFlags::NOSIGNAL) {
+                signal_process(self.zircon_process(), LinuxSignal::SIGPIPE);
+            }
+        }
+        result
+    }
+
+    /// receive a message from a socket
+    pub async fn sys_recvfrom(
+        &self,
+        fd: FileDesc,
+        mut base: UserOutPtr<u8>,
+        len: usize,
+        flags: usize,
+        addr: UserOutPtr<u8>,
+        addr_len: UserInOutPtr<u32>,
+    ) -> SysResult {
+        info!(
+            "recvfrom: fd={:?}, base={:?}, len={}, flags={:#x}, addr={:?}, addr_len={:?}",
+            fd, base, len, flags, addr, addr_len
+        );
+        let file_like = self.linux_process().get_file_like(fd)?;
+        let socket = file_like.as_socket().ok_or(LxError::ENOTSOCK)?;
+        let mut buf = vec![0u8; len];
+        let msg = socket
+            .recv(&mut buf, MsgFlags::from_bits_truncate(flags))
+            .await?;
+        base.write_array(&buf[..msg.len])?;
+        if let Some(remote) = msg.addr {
+            if !addr.is_null() {
+                write_sockaddr(&remote, addr, addr_len)?;
+            }
+        }
+        // rights are only delivered through `recvmsg`
+        Ok(msg.len)
+    }
+
+    /// send a message on a socket, with ancillary data
+    pub async fn sys_sendmsg(&self, fd: FileDesc, msg: UserInPtr<MsgHdr>, flags: usize) -> SysResult {
+        info!("sendmsg: fd={:?}, msg={:?}, flags={:#x}", fd, msg, flags);
+        let proc = self.linux_process();
+        let file_like = proc.get_file_like(fd)?;
+        file_like.as_socket().ok_or(LxError::ENOTSOCK)?;
+        let hdr = msg.read()?;
+        let iovs = UserInPtr::<IoVecIn>::from(hdr.iov).read_iovecs(hdr.iov_len)?;
+        let data = iovs.read_to_vec()?;
+        let addr = if hdr.name == 0 {
+            None
+        } else {
+            Some(self.read_sockaddr(hdr.name.into(), hdr.name_len as usize)?)
+        };
+        let control = UserInPtr::<u8>::from(hdr.control).read_array(hdr.control_len)?;
+        let mut rights: Vec<Arc<dyn FileLike>> = Vec::new();
+        for (level, type_, data) in CmsgIter::new(&control) {
+            if level != SOL_SOCKET || type_ != SCM_RIGHTS {
+                warn!("sendmsg: unsupported control message {}:{}", level, type_);
+                return Err(LxError::EINVAL);
+            }
+            for fd in data.chunks_exact(4) {
+                let fd = i32::from_ne_bytes([fd[0], fd[1], fd[2], fd[3]]);
+                rights.push(proc.get_file_like(fd.into())?);
+            }
+        }
+        let flags = MsgFlags::from_bits_truncate(flags);
+        self.send_socket(&*file_like, &data, addr, rights, flags)
+            .await
+    }
+
+    /// receive a message from a socket, with ancillary data
+    pub async fn sys_recvmsg(
+        &self,
+        fd: FileDesc,
+        mut msg: UserInOutPtr<MsgHdr>,
+        flags: usize,
+    ) -> SysResult {
+        info!("recvmsg: fd={:?}, msg={:?}, flags={:#x}", fd, msg, flags);
+        let proc = self.linux_process();
+        let file_like = proc.get_file_like(fd)?;
+        let socket = file_like.as_socket().ok_or(LxError::ENOTSOCK)?;
+        let mut hdr = msg.read()?;
+        let mut iovs = UserInPtr::<IoVecOut>::from(hdr.iov).read_iovecs(hdr.iov_len)?;
+        let mut buf = vec![0u8; iovs.total_len()];
+        let flags = MsgFlags::from_bits_truncate(flags);
+        let received = socket.recv(&mut buf, flags).await?;
+        iovs.write_from_buf(&buf[..received.len])?;
+
+        let mut out_flags = received.flags;
+        if hdr.name != 0 {
+            match received.addr {
+                Some(remote) => {
+                    let bytes = remote.to_bytes();
+                    let len = bytes.len().min(hdr.name_len as usize);
+                    UserOutPtr::<u8>::from(hdr.name).write_array(&bytes[..len])?;
+                    hdr.name_len = bytes.len() as u32;
+                }
+                None => hdr.name_len = 0,
+            }
+        }
+        let mut control_len = 0;
+        let mut fds = Vec::new();
+        if !received.rights.is_empty() {
+            // install the received files, as many as fit in the control buffer
+            let room = hdr.control_len.saturating_sub(CMSG_HDR_LEN) / 4;
+            if room < received.rights.len() {
+                out_flags |= MsgFlags::CTRUNC;
+            }
+            let cloexec = flags.contains(MsgFlags::CMSG_CLOEXEC);
+            for file in received.rights.into_iter().take(room) {
+                match proc.add_file_with_cloexec(file, cloexec) {
+                    Ok(fd) => fds.push(fd),
+                    Err(err) => {
+                        close_files(proc, &fds);
+                        return Err(err);
+                    }
+                }
+            }
+        }
+        if !fds.is_empty() {
+            let mut cmsg = Vec::new();
+            cmsg.extend(&(CMSG_HDR_LEN + fds.len() * 4).to_ne_bytes());
+            cmsg.extend(&(SOL_SOCKET as i32).to_ne_bytes());
+            cmsg.extend(&(SCM_RIGHTS as i32).to_ne_bytes());
+            for &fd in fds.iter() {
+                cmsg.extend(&i32::from(fd).to_ne_bytes());
+            }
+            // the descriptors are only received if the caller learns of them
+            if let Err(err) = UserOutPtr::<u8>::from(hdr.control).write_array(&cmsg) {
+                close_files(proc, &fds);
+                return Err(err.into());
+            }
+            control_len = cmsg_align(cmsg.len()).min(hdr.control_len);
+        }
+        hdr.control_len = control_len;
+        hdr.flags = out_flags.bits() as i32;
+        if let Err(err) = msg.write(hdr) {
+            close_files(proc, &fds);
+            return Err(err.into());
+        }
+        Ok(received.len)
+    }
+
+    /// shut down part of a full-duplex connection
+    pub fn sys_shutdown(&self, fd: FileDesc, how: usize) -> SysResult {
+        info!("shutdown: fd={:?}, how={}", fd, how);
+        let file_like = self.linux_process().get_file_like(fd)?;
+        let socket = file_like.as_socket().ok_or(LxError::ENOTSOCK)?;
+        socket.shutdown(Shutdown::try_from(how)?)?;
+        Ok(0)
+    }
+
+    /// get socket name
+    pub fn sys_getsockname(
+        &self,
+        fd: FileDesc,
+        addr: UserOutPtr<u8>,
+        addr_len: UserInOutPtr<u32>,
+    ) -> SysResult {
+        info!("getsockname: fd={:?}, addr={:?}, addr_len={:?}", fd, addr, addr_len);
+        let file_like = self.linux_process().get_file_like(fd)?;
+        let socket = file_like.as_socket().ok_or(LxError::ENOTSOCK)?;
+        write_sockaddr(&socket.local_addr()?, addr, addr_len)?;
+        Ok(0)
+    }
+
+    /// get name of connected peer socket
+    pub fn sys_getpeername(
+        &self,
+        fd: FileDesc,
+        addr: UserOutPtr<u8>,
+        addr_len: UserInOutPtr<u32>,
+    ) -> SysResult {
+        info!("getpeername: fd={:?}, addr={:?}, addr_len={:?}", fd, addr, addr_len);
+        let file_like = self.linux_process().get_file_like(fd)?;
+        let socket = file_like.as_socket().ok_or(LxError::ENOTSOCK)?;
+        write_sockaddr(&socket.peer_addr()?, addr, addr_len)?;
+        Ok(0)
+    }
+
+    /// set options on sockets
+    pub fn sys_setsockopt(
+        &self,
+        fd: FileDesc,
+        level: usize,
+        optname: usize,
+        optval: UserInPtr<u8>,
+        optlen: usize,
+    ) -> SysResult {
+        info!(
+            "setsockopt: fd={:?}, level={}, optname={}, optval={:?}, optlen={}",
+            fd, level, optname, optval, optlen
+        );
+        let file_like = self.linux_process().get_file_like(fd)?;
+        let socket = file_like.as_socket().ok_or(LxError::ENOTSOCK)?;
+        let data = optval.read_array(optlen)?;
+        socket.setsockopt(level, optname, &data)?;
+        Ok(0)
+    }
+
+    /// get options on sockets
+    pub fn sys_getsockopt(
+        &self,
+        fd: FileDesc,
+        level: usize,
+        optname: usize,
+        mut optval: UserOutPtr<u8>,
+        mut optlen: UserInOutPtr<u32>,
+    ) -> SysResult {
+        info!(
+            "getsockopt: fd={:?}, level={}, optname={}, optval={:?}, optlen={:?}",
+            fd, level, optname, optval, optlen
+        );
+        let file_like = self.linux_process().get_file_like(fd)?;
+        let socket = file_like.as_socket().ok_or(LxError::ENOTSOCK)?;
+        let data = socket.getsockopt(level, optname)?;
+        let len = data.len().min(optlen.read()? as usize);
+        optval.write_array(&data[..len])?;
+        optlen.write(len as u32)?;
+        Ok(0)
+    }
+
+    /// create a socket object of the given domain and type
+    fn create_socket(
+        &self,
+        domain: usize,
+        type_: usize,
+        protocol: usize,
+    ) -> LxResult<Arc<dyn FileLike>> {
+        let nonblock = type_ & SOCK_NONBLOCK != 0;
+        let type_ = SocketType::try_from(type_ & SOCK_TYPE_MASK).map_err(|_| LxError::EINVAL)?;
+        let domain = AddressFamily::try_from(domain as u16).map_err(|_| LxError::EAFNOSUPPORT)?;
+        match domain {
+            AddressFamily::Unix => {
+                if protocol != 0 {
+                    return Err(LxError::EPROTONOSUPPORT);
+                }
+                Ok(UnixSocket::new(type_, nonblock))
+            }
+            AddressFamily::Inet | AddressFamily::Inet6 => match type_ {
+                SocketType::Stream => {
+                    if protocol != 0 && protocol != IPPROTO_TCP as usize {
+                        return Err(LxError::EPROTONOSUPPORT);
+                    }
+                    Ok(TcpSocket::new(domain, nonblock))
+                }
+                SocketType::Datagram => {
+                    if protocol != 0 && protocol != IPPROTO_UDP as usize {
+                        return Err(LxError::EPROTONOSUPPORT);
+                    }
+                    Ok(UdpSocket::new(domain, nonblock))
+                }
+                SocketType::SeqPacket => Err(LxError::EPROTONOSUPPORT),
+            },
+        }
+    }
+
+    /// read a socket address from user, making pathnames absolute
+    fn read_sockaddr(&self, addr: UserInPtr<u8>, len: usize) -> LxResult<SockAddr> {
+        let addr = SockAddr::from_bytes(&addr.read_array(len)?)?;
+        if let SockAddr::Unix(UnixAddr::Path(path)) = &addr {
+            if !path.starts_with('/') {
+                let cwd = self.linux_process().current_working_directory();
+                return Ok(SockAddr::Unix(UnixAddr::Path(absolute_path(&cwd, path))));
+            }
+        }
+        Ok(addr)
+    }
+
+    /// Look up the socket inode of a pathname address: a pathname socket is
+    /// reached by its inode, which needs write permission.
+    fn socket_inode(&self, addr: &SockAddr) -> LxResult<Option<SocketInode>> {
+        if let SockAddr::Unix(UnixAddr::Path(path)) = addr {
+            let proc = self.linux_process();
+            let inode = proc.lookup_inode(path)?;
+            if inode.metadata()?.type_ != FileType::Socket {
+                return Err(LxError::ECONNREFUSED);
+            }
+            proc.credentials().check_inode(&inode, Access::WRITE)?;
+            return Ok(Some(inode_key(&inode)?));
+        }
+        Ok(None)
+    }
+}
+
+/// Get the unix domain socket `file_like` for a pathname address.
+fn unix_socket(file_like: &dyn FileLike) -> LxResult<&UnixSocket> {
+    file_like
+        .downcast_ref::<UnixSocket>()
+        .ok_or(LxError::EAFNOSUPPORT)
+}
+
+/// `struct msghdr`
+#[repr(C)]
+#[derive(Debug)]
+pub struct MsgHdr {
+    /// optional address
+    name: usize,
+    /// size of address
+    name_len: u32,
+    /// scatter/gather array
+    iov: usize,
+    /// number of elements in `iov`
+    iov_len: usize,
+    /// ancillary data
+    control: usize,
+    /// ancillary data buffer length
+    control_len: usize,
+    /// flags on received message
+    flags: i32,
+}
+
+/// size of `struct cmsghdr`
+const CMSG_HDR_LEN: usize = 16;
+
+/// `F_SETFL` command of `fcntl`
+const F_SETFL: usize = 4;
+
+fn cmsg_align(len: usize) -> usize {
+    (len + 7) & !7
+}
+
+/// Iterator over the `(level, type, data)` of control messages in a buffer
+struct CmsgIter<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> CmsgIter<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        CmsgIter { buf }
+    }
+}
+
+impl<'a> Iterator for CmsgIter<'a> {
+    type Item = (usize, usize, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.buf.len() < CMSG_HDR_LEN {
+            return None;
+        }
+        let mut len = [0u8; 8];
+        len.copy_from_slice(&self.buf[..8]);
+        let len = usize::from_ne_bytes(len);
+        if len < CMSG_HDR_LEN || len > self.buf.len() {
+            return None;
+        }
+        let level = i32::from_ne_bytes([self.buf[8], self.buf[9], self.buf[10], self.buf[11]]);
+        let type_ = i32::from_ne_bytes([self.buf[12], self.buf[13], self.buf[14], self.buf[15]]);
+        let data = &self.buf[CMSG_HDR_LEN..len];
+        self.buf = &self.buf[cmsg_align(len).min(self.buf.len())..];
+        Some((level as usize, type_ as usize, data))
+    }
+}
+
+/// write a socket address to user, truncated to the given buffer length
+fn write_sockaddr(
+    addr: &SockAddr,
+    mut ptr: UserOutPtr<u8>,
+    mut len_ptr: UserInOutPtr<u32>,
+) -> LxResult {
+    if ptr.is_null() {
+        return Ok(());
+    }
+    let bytes = addr.to_bytes();
+    let len = bytes.len().min(len_ptr.read()? as usize);
+    ptr.write_array(&bytes[..len])?;
+    len_ptr.write(bytes.len() as u32)?;
+    Ok(())
+}
+
+/// close the descriptors installed for a message which can't be delivered
+fn close_files(proc: &LinuxProcess, fds: &[FileDesc]) {
+    for &fd in fds {
+        proc.close_file(fd).ok();
+    }
+}
@@ -0,0 +1,362 @@
+//! Syscalls for process tracing
+//!
+//! - ptrace
+
+use super::*;
+use crate::linux_object::ptrace::{self, PtraceOptions, Resume};
+use crate::linux_object::signal::Signal as LinuxSignal;
+use core::mem::size_of;
+use numeric_enum_macro::numeric_enum;
+
+numeric_enum! {
+    #[repr(usize)]
+    #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+    #[allow(non_camel_case_types)]
+    /// Requests of `ptrace`
+    enum PtraceRequest {
+        TRACEME = 0,
+        PEEKTEXT = 1,
+        PEEKDATA = 2,
+        PEEKUSER = 3,
+        POKETEXT = 4,
+        POKEDATA = 5,
+        POKEUSER = 6,
+        CONT = 7,
+        KILL = 8,
+        SINGLESTEP = 9,
+        GETREGS = 12,
+        SETREGS = 13,
+        ATTACH = 16,
+        DETACH = 17,
+        SYSCALL = 24,
+        SETOPTIONS = 0x4200,
+        GETEVENTMSG = 0x4201,
+        GETSIGINFO = 0x4202,
+        GETREGSET = 0x4204,
+        SETREGSET = 0x4205,
+        SEIZE = 0x4206,
+        INTERRUPT = 0x4207,
+    }
+}
+
+/// The general purpose registers in `PTRACE_GETREGSET`
+const NT_PRSTATUS: usize = 1;
+
+impl Syscall<'_> {
+    /// trace the process `pid`: stop it, inspect and change its memory and
+    /// registers, and resume it
+    ///
+    /// Stops of the tracee are reported to the tracer by `wait4`. `pid` is the
+    /// PID of a process, and requests act on its first stopped thread.
+    pub fn sys_ptrace(&self, request: usize, pid: usize, addr: usize, data: usize) -> SysResult {
+        let request = PtraceRequest::try_from(request).map_err(|_| LxError::EIO)?;
+        info!(
+            "ptrace: request={:?}, pid={}, addr={:#x}, data={:#x}",
+            request, pid, addr, data
+        );
+        let proc = self.zircon_process();
+        match request {
+            PtraceRequest::TRACEME => {
+                let parent = self.linux_process().parent().ok_or(LxError::EPERM)?;
+                ptrace::attach(&parent, proc, false, PtraceOptions::empty())?;
+                Ok(0)
+            }
+            PtraceRequest::ATTACH | PtraceRequest::SEIZE => {
+                let tracee = process_by_pid(pid as KoID).ok_or(LxError::ESRCH)?;
+                let cred = self.linux_process().credentials();
+                if !cred.may_trace(&tracee.linux().credentials()) {
+                    return Err(LxError::EPERM);
+                }
+                let seize = request == PtraceRequest::SEIZE;
+                let options = if seize {
+                    PtraceOptions::from_bits(data).ok_or(LxError::EINVAL)?
+                } else {
+                    PtraceOptions::empty()
+                };
+                ptrace::attach(proc, &tracee, seize, options)?;
+                if !seize {
+                    ptrace::intercept_signal(&tracee, LinuxSignal::SIGSTOP);
+                }
+                Ok(0)
+            }
+            _ => {
+                let tracee = ptrace::tracee_of(proc, pid as KoID)?;
+                ptrace_tracee(&tracee, request, addr, data)
+            }
+        }
+    }
+}
+
+/// Carry out `request` on `tracee`, which is traced by the caller.
+fn ptrace_tracee(
+    tracee: &Arc<Process>,
+    request: PtraceRequest,
+    addr: usize,
+    data: usize,
+) -> SysResult {
+    match request {
+        PtraceRequest::PEEKTEXT | PtraceRequest::PEEKDATA => {
+            ptrace::check_stopped(tracee)?;
+            let mut word = [0u8; size_of::<usize>()];
+            tracee
+                .vmar()
+                .read_memory(addr, &mut word)
+                .map_err(|_| LxError::EIO)?;
+            UserOutPtr::<usize>::from(data).write(usize::from_ne_bytes(word))?;
+            Ok(0)
+        }
+        PtraceRequest::POKETEXT | PtraceRequest::POKEDATA => {
+            ptrace::check_stopped(tracee)?;
+            // the page is copied if other processes may map it, like the vDSO
+            tracee
+                .vmar()
+                .write_memory_private(addr, &data.to_ne_bytes())
+                .map_err(|_| LxError::EIO)?;
+            Ok(0)
+        }
+        // there is no `struct user` area
+        PtraceRequest::PEEKUSER | PtraceRequest::POKEUSER => Err(LxError::EIO),
+        PtraceRequest::CONT => ptrace_resume(tracee, Resume::Continue, data),
+        PtraceRequest::SYSCALL => ptrace_resume(tracee, Resume::Syscall, data),
+        // riscv has no hardware single-stepping, debuggers place breakpoints
+        #[cfg(target_arch = "x86_64")]
+        PtraceRequest::SINGLESTEP => ptrace_resume(tracee, Resume::SingleStep, data),
+        #[cfg(not(target_arch = "x86_64"))]
+        PtraceRequest::SINGLESTEP => Err(LxError::EIO),
+        PtraceRequest::KILL => {
+            signal_process(tracee, LinuxSignal::SIGKILL);
+            Ok(0)
+        }
+        PtraceRequest::DETACH => {
+            let signal = resume_signal(data)?;
+            ptrace::detach(tracee);
+            if let Some(signal) = signal {
+                deliver_signal(tracee, signal);
+            }
+            Ok(0)
+        }
+        PtraceRequest::GETREGS => {
+            let context = ptrace::stopped_context(tracee)?;
+            UserOutPtr::<UserRegs>::from(data).write(UserRegs::from_context(&context))?;
+            Ok(0)
+        }
+        PtraceRequest::SETREGS => {
+            let regs = UserInPtr::<UserRegs>::from(data).read()?;
+            let mut context = ptrace::stopped_context(tracee)?;
+            regs.apply(&mut context);
+            ptrace::set_stopped_context(tracee, context)?;
+            Ok(0)
+        }
+        PtraceRequest::GETREGSET | PtraceRequest::SETREGSET => {
+            if addr != NT_PRSTATUS {
+                return Err(LxError::EINVAL);
+            }
+            let mut iov = UserInOutPtr::<RegSetIoVec>::from(data);
+            let mut regset = iov.read()?;
+            if regset.len < size_of::<UserRegs>() {
+                return Err(LxError::EINVAL);
+            }
+            let mut context = ptrace::stopped_context(tracee)?;
+            if request == PtraceRequest::GETREGSET {
+                UserOutPtr::<UserRegs>::from(regset.base)
+                    .write(UserRegs::from_context(&context))?;
+            } else {
+                let regs = UserInPtr::<UserRegs>::from(regset.base).read()?;
+                regs.apply(&mut context);
+                ptrace::set_stopped_context(tracee, context)?;
+            }
+            regset.len = size_of::<UserRegs>();
+            iov.write(regset)?;
+            Ok(0)
+        }
+        PtraceRequest::SETOPTIONS => {
+            let options = PtraceOptions::from_bits(data).ok_or(LxError::EINVAL)?;
+            ptrace::set_options(tracee, options)?;
+            Ok(0)
+        }
+        PtraceRequest::GETEVENTMSG => {
+            UserOutPtr::<usize>::from(data).write(ptrace::event_msg(tracee)?)?;
+            Ok(0)
+        }
+        PtraceRequest::GETSIGINFO => {
+            let (signal, code) = ptrace::stop_siginfo(tracee)?;
+            UserOutPtr::<StopSigInfo>::from(data).write(StopSigInfo {
+                signo: signal as i32,
+                errno: 0,
+                code,
+                padding: [0; 29],
+            })?;
+            Ok(0)
+        }
+        PtraceRequest::INTERRUPT => {
+            ptrace::interrupt(tracee)?;
+            Ok(0)
+        }
+        PtraceRequest::TRACEME | PtraceRequest::ATTACH | PtraceRequest::SEIZE => unreachable!(),
+    }
+}
+
+/// Resume the stopped thread of `tracee`, delivering the signal `data`.
+fn ptrace_resume(tracee: &Arc<Process>, resume: Resume, data: usize) -> SysResult {
+    let signal = resume_signal(data)?;
+    ptrace::resume(tracee, resume, signal)?;
+    Ok(0)
+}
+
+/// Get the signal to deliver on resuming, none if `data` is 0.
+fn resume_signal(data: usize) -> LxResult<Option<LinuxSignal>> {
+    if data == 0 {
+        return Ok(None);
+    }
+    let signal = LinuxSignal::try_from(data as u8).map_err(|_| LxError::EIO)?;
+    Ok(Some(signal))
+}
+
+/// `struct iovec` of `PTRACE_GETREGSET`, whose length is updated
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+struct RegSetIoVec {
+    base: usize,
+    len: usize,
+}
+
+/// `siginfo_t` of `PTRACE_GETSIGINFO`
+///
+/// The `si_code` values of `SIGTRAP` are not in `SignalCode`.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+struct StopSigInfo {
+    signo: i32,
+    errno: i32,
+    code: i32,
+    padding: [i32; 29],
+}
+
+/// `user_regs_struct`, the registers of `PTRACE_GETREGS`
+#[cfg(target_arch = "x86_64")]
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+struct UserRegs {
+    r15: usize,
+    r14: usize,
+    r13: usize,
+    r12: usize,
+    rbp: usize,
+    rbx: usize,
+    r11: usize,
+    r10: usize,
+    r9: usize,
+    r8: usize,
+    rax: usize,
+    rcx: usize,
+    rdx: usize,
+    rsi: usize,
+    rdi: usize,
+    orig_rax: usize,
+    rip: usize,
+    cs: usize,
+    eflags: usize,
+    rsp: usize,
+    ss: usize,
+    fs_base: usize,
+    gs_base: usize,
+    ds: usize,
+    es: usize,
+    fs: usize,
+    gs: usize,
+}
+
+#[cfg(target_arch = "x86_64")]
+impl UserRegs {
+    fn from_context(context: &UserContext) -> Self {
+        let regs = &context.general;
+        UserRegs {
+            r15: regs.r15,
+            r14: regs.r14,
+            r13: regs.r13,
+            r12: regs.r12,
+            rbp: regs.rbp,
+            rbx: regs.rbx,
+            r11: regs.r11,
+            r10: regs.r10,
+            r9: regs.r9,
+            r8: regs.r8,
+            rax: regs.rax,
+            rcx: regs.rcx,
+            rdx: regs.rdx,
+            rsi: regs.rsi,
+            rdi: regs.rdi,
+            // the system call number is still in `rax` at its entry
+            orig_rax: regs.rax,
+            rip: regs.rip,
+            cs: 0x33,
+            eflags: regs.rflags,
+            rsp: regs.rsp,
+            ss: 0x2b,
+            fs_base: regs.fsbase,
+            gs_base: regs.gsbase,
+            ..Default::default()
+        }
+    }
+
+    /// Set the registers of `context`, `orig_rax` and the segments are
+    /// ignored.
+    fn apply(&self, context: &mut UserContext) {
+        let regs = &mut context.general;
+        regs.r15 = self.r15;
+        regs.r14 = self.r14;
+        regs.r13 = self.r13;
+        regs.r12 = self.r12;
+        regs.rbp = self.rbp;
+        regs.rbx = self.rbx;
+        regs.r11 = self.r11;
+        regs.r10 = self.r10;
+        regs.r9 = self.r9;
+        regs.r8 = self.r8;
+        regs.rax = self.rax;
+        regs.rcx = self.rcx;
+        regs.rdx = self.rdx;
+        regs.rsi = self.rsi;
+        regs.rdi = self.rdi;
+        regs.rip = self.rip;
+        regs.rflags = self.eflags;
+        regs.rsp = self.rsp;
+        regs.fsbase = self.fs_base;
+        regs.gsbase = self.gs_base;
+    }
+}
+
+/// Define `user_regs_struct` as the `pc` and then the registers `x1` to
+/// `x31`, by their ABI names.
+#[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+macro_rules! user_regs {
+    ($($reg:ident),*) => {
+        /// `user_regs_struct`, the registers of `PTRACE_GETREGS`
+        #[repr(C)]
+        #[derive(Debug, Default, Copy, Clone)]
+        struct UserRegs {
+            pc: usize,
+            $($reg: usize,)*
+        }
+
+        impl UserRegs {
+            fn from_context(context: &UserContext) -> Self {
+                UserRegs {
+                    pc: context.sepc,
+                    $($reg: context.general.$reg,)*
+                }
+            }
+
+            fn apply(&self, context: &mut UserContext) {
+                context.sepc = self.pc;
+                $(context.general.$reg = self.$reg;)*
+            }
+        }
+    };
+}
+
+#[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+user_regs!(
+    ra, sp, gp, tp, t0, t1, t2, s0, s1, a0, a1, a2, a3, a4, a5, a6, a7, s2, s3, s4, s5, s6, s7, s8,
+    s9, s10, s11, t3, t4, t5, t6
+);
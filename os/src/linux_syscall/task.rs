@@ -3,27 +3,38 @@
 //! - fork
 //! - vfork
 //! - clone
-//! - wait4
+//! - wait4, waitid
 //! - execve
 //! - gettid
 //! - getpid
 //! - getppid
+//! - setpgid, getpgid, getpgrp
+//! - setsid, getsid
+//! - set_tid_address, set_robust_list, get_robust_list
 
 use super::*;
-use bitflags::bitflags;
 use core::fmt::Debug;
+use crate::linux_object::cred::Access;
+use crate::linux_object::fs::vfs::FileType;
+use crate::linux_object::fs::mount::{check_executable, ignores_set_ids};
 use crate::linux_object::fs::INodeExt;
+use crate::linux_object::futex::RobustListHead;
 use crate::linux_object::loader::LinuxElfLoader;
-use crate::linux_object::thread::{CurrentThreadExt, ThreadExt};
+use crate::linux_object::ptrace;
+use crate::linux_object::signal::{
+    SigChldFields, SigInfo, SiginfoFields, Signal as LinuxSignal, SignalCode,
+};
+use crate::linux_object::thread::{clone_context, CurrentThreadExt, ThreadExt};
 use crate::linux_object::time::*;
 
 impl Syscall<'_> {
     /// Fork the current process. Return the child's PID.
     pub fn sys_fork(&self) -> SysResult {
         info!("fork:");
+        self.linux_process().check_nproc()?;
         let new_proc = Process::fork_from(self.zircon_process(), false)?;
         let new_thread = Thread::create_linux(&new_proc)?;
-        new_thread.start_with_regs(GeneralRegs::new_fork(self.regs), self.thread_fn)?;
+        new_thread.start_with_context(UserContext::new_fork(self.context), self.thread_fn)?;
 
         info!("fork: {} -> {}", self.zircon_process().id(), new_proc.id());
         Ok(new_proc.id() as usize)
@@ -32,9 +43,10 @@ impl Syscall<'_> {
     /// creates a child process of the calling process, similar to fork but wait for execve
     pub async fn sys_vfork(&self) -> SysResult {
         info!("vfork:");
+        self.linux_process().check_nproc()?;
         let new_proc = Process::fork_from(self.zircon_process(), true)?;
         let new_thread = Thread::create_linux(&new_proc)?;
-        new_thread.start_with_regs(GeneralRegs::new_fork(self.regs), self.thread_fn)?;
+        new_thread.start_with_context(UserContext::new_fork(self.context), self.thread_fn)?;
 
         let new_proc: Arc<dyn KernelObject> = new_proc;
         info!("vfork: {} -> {}", self.zircon_process().id(), new_proc.id());
@@ -42,91 +54,181 @@ impl Syscall<'_> {
         Ok(new_proc.id() as usize)
     }
 
-    /// Create a new thread in the current process.
-    /// The new thread's stack pointer will be set to `newsp`,
-    /// and thread pointer will be set to `newtls`.
-    /// The child tid will be stored at both `parent_tid` and `child_tid`.
-    /// This is partially implemented for musl only.
-    pub fn sys_clone(
+    /// Create a child process, or a thread in the current process with `CLONE_THREAD`.
+    ///
+    /// The stack pointer of the child is set to `newsp`, or the caller's if 0,
+    /// and with `CLONE_SETTLS` the thread pointer is set to `newtls`.
+    /// Return the TID of the new thread, or the PID of the new process.
+    ///
+    /// Threads share everything with the caller, so they must be created with
+    /// `CLONE_VM`, `CLONE_FS`, `CLONE_FILES` and `CLONE_SIGHAND`. A child
+    /// process shares its file descriptor table with `CLONE_FILES` and its
+    /// working directory with `CLONE_FS`. Namespaces are not supported.
+    pub async fn sys_clone(
         &self,
         flags: usize,
         newsp: usize,
-        mut parent_tid: UserOutPtr<i32>,
-        mut child_tid: UserOutPtr<i32>,
+        parent_tid: UserOutPtr<i32>,
+        child_tid: UserOutPtr<i32>,
         newtls: usize,
     ) -> SysResult {
-        let _flags = CloneFlags::from_bits_truncate(flags);
         info!(
             "clone: flags={:#x}, newsp={:#x}, parent_tid={:?}, child_tid={:?}, newtls={:#x}",
             flags, newsp, parent_tid, child_tid, newtls
         );
-        if flags == 0x4111 || flags == 0x11 {
-            warn!("sys_clone is calling sys_fork instead, ignoring other args");
-            unimplemented!()
-            //            return self.sys_fork();
+        let flags = CloneFlags::check(flags)?;
+        let context = clone_context(self.context, flags, newsp, newtls);
+        self.linux_process().check_nproc()?;
+
+        if flags.contains(CloneFlags::THREAD) {
+            let new_thread = Thread::create_linux(self.zircon_process())?;
+            let tid = new_thread.id();
+            let proc = self.zircon_process();
+            store_clone_tid(flags, tid, proc, proc, &parent_tid, &child_tid);
+            if flags.contains(CloneFlags::CHILD_CLEARTID) {
+                new_thread.set_tid_address(child_tid);
+            }
+            new_thread.start_with_context(context, self.thread_fn)?;
+            info!("clone: {} -> {}", self.thread.id(), tid);
+            return Ok(tid as usize);
         }
-        if flags != 0x7d_0f00 && flags != 0x5d_0f00 {
-            // 0x5d0f00: gcc of alpine linux
-            // 0x7d0f00: pthread_create of alpine linux
-            // warn!("sys_clone only support musl pthread_create");
-            panic!("unsupported sys_clone flags: {:#x}", flags);
+
+        let new_proc = Process::fork_from(self.zircon_process(), flags.contains(CloneFlags::VM))?;
+        if flags.contains(CloneFlags::FILES) {
+            new_proc.linux().share_files(self.linux_process());
         }
-        let new_thread = Thread::create_linux(self.zircon_process())?;
-        let regs = GeneralRegs::new_clone(self.regs, newsp, newtls);
-        new_thread.start_with_regs(regs, self.thread_fn)?;
-
-        let tid = new_thread.id();
-        info!("clone: {} -> {}", self.thread.id(), tid);
-        parent_tid.write(tid as i32)?;
-        child_tid.write(tid as i32)?;
-        new_thread.set_tid_address(child_tid);
-        Ok(tid as usize)
+        if flags.contains(CloneFlags::FS) {
+            new_proc.linux().share_fs(self.linux_process());
+        }
+        let new_thread = Thread::create_linux(&new_proc)?;
+        let pid = new_proc.id();
+        let proc = self.zircon_process();
+        store_clone_tid(flags, pid, proc, &new_proc, &parent_tid, &child_tid);
+        if flags.contains(CloneFlags::CHILD_CLEARTID) {
+            new_thread.set_tid_address(child_tid);
+        }
+        new_thread.start_with_context(context, self.thread_fn)?;
+        info!("clone: {} -> {}", self.zircon_process().id(), pid);
+
+        if flags.contains(CloneFlags::VFORK) {
+            // wait for the child to execve or exit
+            let new_proc: Arc<dyn KernelObject> = new_proc;
+            new_proc
+                .wait_signal(Signal::SIGNALED | Signal::PROCESS_TERMINATED)
+                .await;
+        }
+        Ok(pid as usize)
     }
 
-    /// Wait for a child process exited.
+    /// Wait for a child process to change state.
+    ///
+    /// `pid` selects the children to wait for:
+    /// - `< -1`: any child in the process group `-pid`
+    /// - `-1`: any child
+    /// - `0`: any child in the process group of the caller
+    /// - `> 0`: the child with PID `pid`
     ///
-    /// Return the PID. Store exit code to `wstatus` if it's not null.
+    /// Return the PID, or 0 if `WNOHANG` is given and no child has changed
+    /// state. Store the status to `wstatus` and the resource usage of the
+    /// child to `rusage` if they are not null.
     pub async fn sys_wait4(
         &self,
         pid: i32,
         mut wstatus: UserOutPtr<i32>,
         options: u32,
+        mut rusage: UserOutPtr<RUsage>,
     ) -> SysResult {
-        #[derive(Debug)]
-        enum WaitTarget {
-            AnyChild,
-            AnyChildInGroup,
-            Pid(KoID),
+        let target = match pid {
+            -1 => WaitTarget::Any,
+            0 => WaitTarget::Group(self.linux_process().pgid()),
+            p if p > 0 => WaitTarget::Pid(p as KoID),
+            p => WaitTarget::Group(p.checked_neg().ok_or(LxError::ESRCH)? as KoID),
+        };
+        let options = WaitOptions::from_bits_truncate(options);
+        if options.intersects(WaitOptions::EXITED | WaitOptions::NOWAIT) {
+            return Err(LxError::EINVAL);
         }
-        bitflags! {
-            struct WaitFlags: u32 {
-                const NOHANG    = 1;
-                const STOPPED   = 2;
-                const EXITED    = 4;
-                const CONTINUED = 8;
-                const NOWAIT    = 0x100_0000;
+        info!(
+            "wait4: target={:?}, wstatus={:?}, options={:?}, rusage={:?}",
+            target, wstatus, options, rusage,
+        );
+        let options = options | WaitOptions::EXITED;
+        match wait_child(self.zircon_process(), target, options).await? {
+            Some(result) => {
+                wstatus.write_if_not_null(result.event.wstatus())?;
+                rusage.write_if_not_null(RUsage::from_cpu_time(result.time))?;
+                Ok(result.pid as usize)
             }
+            None => Ok(0),
         }
-        let target = match pid {
-            -1 => WaitTarget::AnyChild,
-            0 => WaitTarget::AnyChildInGroup,
-            p if p > 0 => WaitTarget::Pid(p as KoID),
-            _ => unimplemented!(),
+    }
+
+    /// Wait for a child process to change state, like `wait4`.
+    ///
+    /// `idtype` and `id` select the children to wait for:
+    /// - `P_ALL`: any child
+    /// - `P_PID`: the child with PID `id`
+    /// - `P_PGID`: any child in the process group `id`, or of the caller if
+    ///   `id` is 0
+    ///
+    /// At least one of `WEXITED`, `WSTOPPED` and `WCONTINUED` must be given.
+    /// Store the child state to `infop` if it is not null. If `WNOHANG` is
+    /// given and no child has changed state, `si_pid` is 0.
+    pub async fn sys_waitid(
+        &self,
+        idtype: usize,
+        id: usize,
+        mut infop: UserOutPtr<SigInfo>,
+        options: u32,
+        mut rusage: UserOutPtr<RUsage>,
+    ) -> SysResult {
+        const P_ALL: usize = 0;
+        const P_PID: usize = 1;
+        const P_PGID: usize = 2;
+        let target = match idtype {
+            P_ALL => WaitTarget::Any,
+            P_PID => WaitTarget::Pid(id as KoID),
+            P_PGID if id == 0 => WaitTarget::Group(self.linux_process().pgid()),
+            P_PGID => WaitTarget::Group(id as KoID),
+            _ => return Err(LxError::EINVAL),
         };
-        let flags = WaitFlags::from_bits_truncate(options);
-        let nohang = flags.contains(WaitFlags::NOHANG);
+        let options = WaitOptions::from_bits_truncate(options);
+        let events = WaitOptions::EXITED | WaitOptions::UNTRACED | WaitOptions::CONTINUED;
+        if !options.intersects(events) {
+            return Err(LxError::EINVAL);
+        }
         info!(
-            "wait4: target={:?}, wstatus={:?}, options={:?}",
-            target, wstatus, flags,
+            "waitid: target={:?}, infop={:?}, options={:?}, rusage={:?}",
+            target, infop, options, rusage,
         );
-        let (pid, code) = match target {
-            WaitTarget::AnyChild | WaitTarget::AnyChildInGroup => {
-                wait_child_any(self.zircon_process(), nohang).await?
+        let info = match wait_child(self.zircon_process(), target, options).await? {
+            Some(result) => {
+                rusage.write_if_not_null(RUsage::from_cpu_time(result.time))?;
+                let (code, status) = result.event.siginfo();
+                SigInfo {
+                    signo: LinuxSignal::SIGCHLD as i32,
+                    errno: 0,
+                    code,
+                    field: SiginfoFields {
+                        sigchld: SigChldFields {
+                            pid: result.pid as i32,
+                            uid: result.uid,
+                            status,
+                            utime: clock_ticks(result.time) as isize,
+                            stime: 0,
+                        },
+                    },
+                }
             }
-            WaitTarget::Pid(pid) => (pid, wait_child(self.zircon_process(), pid, nohang).await?),
+            None => SigInfo {
+                signo: 0,
+                errno: 0,
+                code: SignalCode::USER,
+                field: SiginfoFields::default(),
+            },
         };
-        wstatus.write_if_not_null(code)?;
-        Ok(pid as usize)
+        infop.write_if_not_null(info)?;
+        Ok(0)
     }
 
     /// Replaces the current ** process ** with a new process image
@@ -164,26 +266,47 @@ impl Syscall<'_> {
         // Read program file
         let proc = self.linux_process();
         let inode = proc.lookup_inode(&path)?;
+        let metadata = inode.metadata()?;
+        if metadata.type_ != FileType::File {
+            return Err(LxError::EACCES);
+        }
+        let mut cred = proc.credentials();
+        cred.check_access(&metadata, Access::EXEC)?;
+        check_executable(&inode)?;
         let data = inode.read_as_vec()?;
-
-        proc.remove_cloexec_files();
+        // the credentials are needed by the loader for the auxv
+        let set_ids = ptrace::exec_may_set_ids(self.zircon_process()) && !ignores_set_ids(&inode);
+        cred.exec(&metadata, set_ids);
 
         let vmar = self.zircon_process().vmar();
-        vmar.clear()?;
         let loader = LinuxElfLoader {
             syscall_entry: self.syscall_entry,
-            stack_pages: 8,
+            stack_pages: proc.rlimits().stack_pages(),
             root_inode: proc.root_inode().clone(),
+            cred: cred.clone(),
+            caller_cred: proc.credentials(),
         };
-        let (entry, sp) = loader.load(&vmar, &data, args, envs, path.clone())?;
+        // errors after the address space is cleared can't be returned
+        let program = loader.prepare(&vmar, data, args.clone(), envs.clone(), path.clone())?;
+
+        proc.remove_cloexec_files();
+        proc.shm_detach_all();
+        vmar.clear()?;
+        let (entry, sp) = loader.load(&vmar, program)?;
 
         // Modify exec path
         proc.set_execute_path(&path);
+        if syscall_trace_filter_matches(path.rsplit('/').next().unwrap()) {
+            self.zircon_process().set_trace_syscalls(true);
+        }
+        proc.set_args(args, envs);
+        proc.set_credentials(cred);
 
         // TODO: use right signal
         self.zircon_process().signal_set(Signal::SIGNALED);
+        ptrace::exec_event(self.zircon_process());
 
-        *self.regs = GeneralRegs::new_fn(entry, sp, 0, 0);
+        *self.context = UserContext::new_fn(entry, sp, 0, 0);
         Ok(0)
     }
     //
@@ -238,6 +361,65 @@ impl Syscall<'_> {
         Ok(ppid as usize)
     }
 
+    /// Get the process `pid`, the calling process if 0.
+    fn process_or_self(&self, pid: usize) -> LxResult<Arc<Process>> {
+        if pid == 0 {
+            return Ok(self.zircon_process().clone());
+        }
+        process_by_pid(pid as KoID).ok_or(LxError::ESRCH)
+    }
+
+    /// Set the process group ID of the process `pid`, the calling process if 0.
+    ///
+    /// The process must be the caller or a child of it in the same session.
+    /// With `pgid` 0 the process becomes the leader of a new group.
+    pub fn sys_setpgid(&self, pid: usize, pgid: usize) -> SysResult {
+        info!("setpgid: pid={}, pgid={}", pid, pgid);
+        let proc = self.zircon_process();
+        let target = self.process_or_self(pid)?;
+        if target.id() != proc.id() {
+            let parent = target.linux().parent().map(|p| p.id());
+            if parent != Some(proc.id()) {
+                return Err(LxError::ESRCH);
+            }
+            if target.linux().sid() != proc.linux().sid() {
+                return Err(LxError::EPERM);
+            }
+        }
+        let pgid = if pgid == 0 { target.id() } else { pgid as KoID };
+        target.linux().set_pgid(pgid)?;
+        Ok(0)
+    }
+
+    /// Get the process group ID of the process `pid`, the calling process if 0.
+    pub fn sys_getpgid(&self, pid: usize) -> SysResult {
+        info!("getpgid: pid={}", pid);
+        let proc = self.process_or_self(pid)?;
+        Ok(proc.linux().pgid() as usize)
+    }
+
+    /// Get the process group ID of the calling process.
+    pub fn sys_getpgrp(&self) -> SysResult {
+        info!("getpgrp:");
+        Ok(self.linux_process().pgid() as usize)
+    }
+
+    /// Create a new session with the calling process as its leader.
+    ///
+    /// Return the session ID, which is the PID of the caller.
+    pub fn sys_setsid(&self) -> SysResult {
+        info!("setsid:");
+        let sid = self.linux_process().set_sid()?;
+        Ok(sid as usize)
+    }
+
+    /// Get the session ID of the process `pid`, the calling process if 0.
+    pub fn sys_getsid(&self, pid: usize) -> SysResult {
+        info!("getsid: pid={}", pid);
+        let proc = self.process_or_self(pid)?;
+        Ok(proc.linux().sid() as usize)
+    }
+
     /// Exit the current thread
     pub fn sys_exit(&mut self, exit_code: i32) -> SysResult {
         info!("exit: code={}", exit_code);
@@ -276,104 +458,100 @@ impl Syscall<'_> {
         let tid = self.thread.id();
         Ok(tid as usize)
     }
-}
 
-bitflags! {
-    pub struct CloneFlags: usize {
-        ///
-        const CSIGNAL =         0xff;
-        /// the calling process and the child process run in the same memory space
-        const VM =              1 << 8;
-        /// the caller and the child process share the same filesystem information
-        const FS =              1 << 9;
-        /// the calling process and the child process share the same file descriptor table
-        const FILES =           1 << 10;
-        /// the calling process and the child process share the same table of signal handlers.
-        const SIGHAND =         1 << 11;
-        /// the calling process is being traced
-        const PTRACE =          1 << 13;
-        /// the execution of the calling process is suspended until the child releases its virtual memory resources
-        const VFORK =           1 << 14;
-        /// the parent of the new child will be the same as that of the call‐ing process.
-        const PARENT =          1 << 15;
-        /// the child is placed in the same thread group as the calling process.
-        const THREAD =          1 << 16;
-        /// cloned child is started in a new mount namespace
-        const NEWNS	=           1 << 17;
-        /// the child and the calling process share a single list of System V semaphore adjustment values.
-        const SYSVSEM =         1 << 18;
-        /// architecture dependent, The TLS (Thread Local Storage) descriptor is set to tls.
-        const SETTLS =          1 << 19;
-        /// Store the child thread ID at the location in the parent's memory.
-        const PARENT_SETTID =   1 << 20;
-        /// Clear (zero) the child thread ID
-        const CHILD_CLEARTID =  1 << 21;
-        /// the parent not to receive a signal when the child terminated
-        const DETACHED =        1 << 22;
-        /// a tracing process cannot force CLONE_PTRACE on this child process.
-        const UNTRACED =        1 << 23;
-        /// Store the child thread ID
-        const CHILD_SETTID =    1 << 24;
-        /// Create the process in a new cgroup namespace.
-        const NEWCGROUP =       1 << 25;
-        /// create the process in a new UTS namespace
-        const NEWUTS =          1 << 26;
-        /// create the process in a new IPC namespace.
-        const NEWIPC =          1 << 27;
-        /// create the process in a new user namespace
-        const NEWUSER =         1 << 28;
-        /// create the process in a new PID namespace
-        const NEWPID =          1 << 29;
-        /// create the process in a new net‐work namespace.
-        const NEWNET =          1 << 30;
-        /// the new process shares an I/O context with the calling process.
-        const IO =              1 << 31;
+    /// Set the head of the robust futex list of the calling thread.
+    ///
+    /// The futexes in the list are released when the thread exits.
+    pub fn sys_set_robust_list(&self, head: UserInPtr<RobustListHead>, len: usize) -> SysResult {
+        info!("set_robust_list: head: {:?}, len: {}", head, len);
+        if len != core::mem::size_of::<RobustListHead>() {
+            return Err(LxError::EINVAL);
+        }
+        self.thread.set_robust_list(head);
+        Ok(0)
+    }
+
+    /// Get the head of the robust futex list of the thread `tid`, or the calling thread if 0.
+    pub fn sys_get_robust_list(
+        &self,
+        tid: usize,
+        mut head_ptr: UserOutPtr<usize>,
+        mut len_ptr: UserOutPtr<usize>,
+    ) -> SysResult {
+        info!(
+            "get_robust_list: tid: {}, head_ptr: {:?}, len_ptr: {:?}",
+            tid, head_ptr, len_ptr
+        );
+        let head = if tid == 0 {
+            self.thread.robust_list()
+        } else {
+            let thread = self
+                .zircon_process()
+                .get_child(tid as KoID)
+                .ok()
+                .and_then(|thread| thread.downcast_arc::<Thread>().ok())
+                .ok_or(LxError::ESRCH)?;
+            thread.robust_list()
+        };
+        head_ptr.write(head)?;
+        len_ptr.write(core::mem::size_of::<RobustListHead>())?;
+        Ok(0)
     }
 }
 
 trait RegExt {
     fn new_fn(entry: usize, sp: usize, arg1: usize, arg2: usize) -> Self;
-    fn new_clone(regs: &Self, newsp: usize, newtls: usize) -> Self;
-    fn new_fork(regs: &Self) -> Self;
+    fn new_fork(context: &Self) -> Self;
 }
 
 #[cfg(target_arch = "x86_64")]
-impl RegExt for GeneralRegs {
+impl RegExt for UserContext {
     fn new_fn(entry: usize, sp: usize, arg1: usize, arg2: usize) -> Self {
-        GeneralRegs {
-            rip: entry,
-            rsp: sp,
-            rdi: arg1,
-            rsi: arg2,
+        UserContext {
+            general: GeneralRegs {
+                rip: entry,
+                rsp: sp,
+                rdi: arg1,
+                rsi: arg2,
+                ..Default::default()
+            },
             ..Default::default()
         }
     }
 
-    fn new_clone(regs: &Self, newsp: usize, newtls: usize) -> Self {
-        GeneralRegs {
-            rax: 0,
-            rsp: newsp,
-            fsbase: newtls,
-            ..*regs
+    fn new_fork(context: &Self) -> Self {
+        UserContext {
+            general: GeneralRegs {
+                rax: 0,
+                ..context.general
+            },
+            ..*context
         }
     }
-
-    fn new_fork(regs: &Self) -> Self {
-        GeneralRegs { rax: 0, ..*regs }
-    }
 }
 
 #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
-impl RegExt for GeneralRegs {
+impl RegExt for UserContext {
     fn new_fn(entry: usize, sp: usize, arg1: usize, arg2: usize) -> Self {
-        unimplemented!()
-    }
-
-    fn new_clone(regs: &Self, newsp: usize, newtls: usize) -> Self {
-        unimplemented!()
+        UserContext {
+            general: GeneralRegs {
+                sp,
+                a0: arg1,
+                a1: arg2,
+                ..Default::default()
+            },
+            sepc: entry,
+            ..Default::default()
+        }
     }
 
-    fn new_fork(regs: &Self) -> Self {
-        unimplemented!()
+    fn new_fork(context: &Self) -> Self {
+        UserContext {
+            general: GeneralRegs {
+                a0: 0,
+                ..context.general
+            },
+            ..*context
+        }
     }
-}
\ No newline at end of file
+}
@@ -5,6 +5,7 @@ use super::Syscall;
 use crate::kernel_hal::{user::UserInPtr, user::UserOutPtr};
 use crate::linux_object::error::LxError;
 use crate::linux_object::error::SysResult;
+use crate::linux_object::process::cpu_time;
 use crate::linux_object::time::*;
 
 const USEC_PER_TICK: usize = 10000;
@@ -61,18 +62,30 @@ impl Syscall<'_> {
     /// currently only support ru_utime and ru_stime:
     /// - `ru_utime`: user CPU time used
     /// - `ru_stime`: system CPU time used
-    pub fn sys_getrusage(&mut self, who: usize, mut rusage: UserOutPtr<RUsage>) -> SysResult {
+    ///
+    /// `who` is one of:
+    /// - `RUSAGE_SELF`: the calling process
+    /// - `RUSAGE_CHILDREN`: the children that have been waited for
+    /// - `RUSAGE_THREAD`: the calling thread
+    pub fn sys_getrusage(&mut self, who: i32, mut rusage: UserOutPtr<RUsage>) -> SysResult {
+        const RUSAGE_SELF: i32 = 0;
+        const RUSAGE_CHILDREN: i32 = -1;
+        const RUSAGE_THREAD: i32 = 1;
         info!("getrusage: who: {}, rusage: {:?}", who, rusage);
 
-        let new_rusage = RUsage {
-            utime: TimeVal::now(),
-            stime: TimeVal::now(),
+        let time = match who {
+            RUSAGE_SELF => cpu_time(self.zircon_process()),
+            RUSAGE_CHILDREN => self.linux_process().children_time(),
+            RUSAGE_THREAD => self.thread.get_time(),
+            _ => return Err(LxError::EINVAL),
         };
-        rusage.write(new_rusage)?;
+        rusage.write(RUsage::from_cpu_time(time))?;
         Ok(0)
     }
 
     /// stores the current process times in the struct tms that buf points to
+    ///
+    /// The times of children are those of the children that have been waited for.
     pub fn sys_times(&mut self, mut buf: UserOutPtr<Tms>) -> SysResult {
         info!("times: buf: {:?}", buf);
 
@@ -81,9 +94,9 @@ impl Syscall<'_> {
         let tick = (tv.sec * 1_000_000 + tv.usec) / USEC_PER_TICK;
 
         let new_buf = Tms {
-            tms_utime: 0,
+            tms_utime: clock_ticks(cpu_time(self.zircon_process())),
             tms_stime: 0,
-            tms_cutime: 0,
+            tms_cutime: clock_ticks(self.linux_process().children_time()),
             tms_cstime: 0,
         };
 
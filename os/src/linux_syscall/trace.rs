@@ -0,0 +1,188 @@
+//! Tracing of Linux system calls, as set by [`Process::set_trace_syscalls`]
+//!
+//! Paths, file descriptors and flags are decoded.
+
+use super::file::{AtFlags, OpenFlags};
+use super::vm::{MmapFlags, MmapProt};
+use super::*;
+use crate::linux_object::signal::Signal;
+use crate::zircon_object::debuglog::{write_kernel_log, Severity};
+use alloc::{format, string::String, vec::Vec};
+use core::fmt::Debug;
+
+/// How an argument is shown
+#[derive(Debug, Copy, Clone)]
+enum Arg {
+    /// in hex, for pointers and addresses
+    Hex,
+    /// in signed decimal, for sizes, counts and IDs
+    Int,
+    /// a file descriptor, or `AT_FDCWD`
+    Fd,
+    /// a path, as a quoted string
+    Path,
+    /// in octal, for file modes
+    Octal,
+    /// bits of `OpenFlags`
+    OpenFlags,
+    /// bits of `AtFlags`
+    AtFlags,
+    /// bits of `MmapProt`
+    MmapProt,
+    /// bits of `MmapFlags`
+    MmapFlags,
+    /// bits of `CloneFlags`, with the exit signal in the low byte
+    CloneFlags,
+    /// bits of `WaitOptions`
+    WaitOptions,
+    /// a signal number
+    Signal,
+}
+
+/// Get how the arguments of `sys` are shown, empty for the system calls
+/// which are not decoded.
+fn signature(sys: &Sys) -> &'static [Arg] {
+    use Arg::*;
+    match sys {
+        Sys::READ | Sys::WRITE => &[Fd, Hex, Int],
+        Sys::PREAD64 | Sys::PWRITE64 => &[Fd, Hex, Int, Int],
+        Sys::READV | Sys::WRITEV => &[Fd, Hex, Int],
+        Sys::OPENAT => &[Fd, Path, OpenFlags, Octal],
+        Sys::OPEN => &[Path, OpenFlags, Octal],
+        Sys::CLOSE | Sys::DUP | Sys::FSYNC | Sys::FDATASYNC => &[Fd],
+        Sys::DUP2 => &[Fd, Fd],
+        Sys::DUP3 => &[Fd, Fd, OpenFlags],
+        Sys::PIPE => &[Hex],
+        Sys::PIPE2 => &[Hex, OpenFlags],
+        Sys::FSTAT | Sys::FSTATFS => &[Fd, Hex],
+        Sys::NEWFSTATAT => &[Fd, Path, Hex, AtFlags],
+        Sys::STAT | Sys::LSTAT | Sys::STATFS => &[Path, Hex],
+        Sys::LSEEK => &[Fd, Int, Int],
+        Sys::IOCTL => &[Fd, Hex, Hex],
+        Sys::FCNTL => &[Fd, Int, Hex],
+        Sys::GETDENTS64 => &[Fd, Hex, Int],
+        Sys::TRUNCATE => &[Path, Int],
+        Sys::FTRUNCATE => &[Fd, Int],
+        Sys::CHDIR | Sys::RMDIR | Sys::UNLINK => &[Path],
+        Sys::MKDIRAT => &[Fd, Path, Octal],
+        Sys::MKDIR => &[Path, Octal],
+        Sys::UNLINKAT => &[Fd, Path, AtFlags],
+        Sys::RENAMEAT => &[Fd, Path, Fd, Path],
+        Sys::RENAME | Sys::LINK | Sys::SYMLINK => &[Path, Path],
+        Sys::LINKAT => &[Fd, Path, Fd, Path, AtFlags],
+        Sys::SYMLINKAT => &[Path, Fd, Path],
+        Sys::READLINKAT => &[Fd, Path, Hex, Int],
+        Sys::READLINK => &[Path, Hex, Int],
+        Sys::FACCESSAT => &[Fd, Path, Octal, AtFlags],
+        Sys::ACCESS => &[Path, Octal],
+        Sys::FCHMOD => &[Fd, Octal],
+        Sys::FCHMODAT => &[Fd, Path, Octal, AtFlags],
+        Sys::CHMOD => &[Path, Octal],
+        Sys::MMAP => &[Hex, Int, MmapProt, MmapFlags, Fd, Hex],
+        Sys::MPROTECT => &[Hex, Int, MmapProt],
+        Sys::MUNMAP => &[Hex, Int],
+        Sys::BRK => &[Hex],
+        Sys::CLONE => &[CloneFlags, Hex, Hex, Hex, Hex],
+        Sys::EXECVE => &[Path, Hex, Hex],
+        Sys::EXIT | Sys::EXIT_GROUP => &[Int],
+        Sys::WAIT4 => &[Int, Hex, WaitOptions, Hex],
+        Sys::PTRACE => &[Int, Int, Hex, Hex],
+        Sys::RT_SIGACTION => &[Signal, Hex, Hex, Int],
+        _ => &[],
+    }
+}
+
+/// Decode the arguments of the system call `sys_type`.
+pub(crate) fn decode_args(sys_type: &Sys, args: &[usize; 6]) -> String {
+    let signature = signature(sys_type);
+    if signature.is_empty() {
+        let args: Vec<String> = args.iter().map(|arg| format!("{:#x}", arg)).collect();
+        return args.join(", ");
+    }
+    let decoded: Vec<String> = signature
+        .iter()
+        .zip(args.iter())
+        .map(|(&arg, &value)| decode(arg, value))
+        .collect();
+    decoded.join(", ")
+}
+
+impl Syscall<'_> {
+    /// Write the trace of a call of `sys_type` with the decoded `args`.
+    pub(super) fn trace_result(&self, sys_type: &Sys, args: &str, ret: &SysResult) {
+        let result = match (sys_type, ret) {
+            // the process is gone, there is no result
+            (Sys::EXIT, _) | (Sys::EXIT_GROUP, _) => String::from("?"),
+            (Sys::MMAP, Ok(value)) | (Sys::BRK, Ok(value)) => format!("{:#x}", value),
+            (_, Ok(value)) => format!("{}", value),
+            (_, Err(err)) => format!("-1 {:?} ({})", err, err),
+        };
+        let proc = self.zircon_process();
+        let name = format!("{:?}", sys_type).to_lowercase();
+        let line = format!(
+            "[{}:{}] {}({}) = {}",
+            proc.id(),
+            self.thread.id(),
+            name,
+            args,
+            result
+        );
+        write_kernel_log(Severity::Info, self.thread.id(), proc.id(), &line);
+    }
+}
+
+/// Show the argument `value` as `arg`.
+fn decode(arg: Arg, value: usize) -> String {
+    match arg {
+        Arg::Hex => format!("{:#x}", value),
+        Arg::Int => format!("{}", value as isize),
+        Arg::Fd if value as i32 == -100 => String::from("AT_FDCWD"),
+        Arg::Fd => format!("{}", value as i32),
+        Arg::Path => match UserInPtr::<u8>::from(value).read_cstring() {
+            Ok(path) => format!("{:?}", path),
+            Err(_) => format!("{:#x}", value),
+        },
+        Arg::Octal => format!("{:#o}", value),
+        Arg::OpenFlags => {
+            let flags = OpenFlags::from_bits_truncate(value);
+            bits(flags, value & !flags.bits())
+        }
+        Arg::AtFlags => {
+            let flags = AtFlags::from_bits_truncate(value);
+            bits(flags, value & !flags.bits())
+        }
+        Arg::MmapProt => {
+            let prot = MmapProt::from_bits_truncate(value);
+            bits(prot, value & !prot.bits())
+        }
+        Arg::MmapFlags => {
+            let flags = MmapFlags::from_bits_truncate(value);
+            bits(flags, value & !flags.bits())
+        }
+        Arg::CloneFlags => {
+            let flags = CloneFlags::from_bits_truncate(value & !0xff);
+            let flags = bits(flags, value & !0xff & !flags.bits());
+            match Signal::try_from(value as u8) {
+                Ok(signal) => format!("{} | {:?}", flags, signal),
+                Err(_) => flags,
+            }
+        }
+        Arg::WaitOptions => {
+            let options = WaitOptions::from_bits_truncate(value as u32);
+            bits(options, value & !options.bits() as usize)
+        }
+        Arg::Signal => match Signal::try_from(value as u8) {
+            Ok(signal) if value <= 0xff => format!("{:?}", signal),
+            _ => format!("{}", value),
+        },
+    }
+}
+
+/// Show the known bits in `flags`, followed by the `unknown` bits if any.
+fn bits(flags: impl Debug, unknown: usize) -> String {
+    if unknown == 0 {
+        format!("{:?}", flags)
+    } else {
+        format!("{:?} | {:#x}", flags, unknown)
+    }
+}
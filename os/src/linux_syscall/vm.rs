@@ -1,5 +1,6 @@
 use super::*;
 use bitflags::bitflags;
+use crate::linux_object::fs::mount::check_executable;
 use crate::zircon_object::vm::*;
 
 impl Syscall<'_> {
@@ -30,27 +31,39 @@ impl Syscall<'_> {
         let proc = self.zircon_process();
         let vmar = proc.vmar();
 
+        let file = if flags.contains(MmapFlags::ANONYMOUS) {
+            None
+        } else {
+            Some(self.linux_process().get_file(fd)?)
+        };
+        if let Some(file) = &file {
+            if prot.contains(MmapProt::EXEC) {
+                check_executable(&file.inode())?;
+            }
+        }
         if flags.contains(MmapFlags::FIXED) {
             // unmap first
             vmar.unmap(addr, len)?;
         }
         let vmar_offset = flags.contains(MmapFlags::FIXED).then(|| addr - vmar.addr());
-        if flags.contains(MmapFlags::ANONYMOUS) {
-            if flags.contains(MmapFlags::SHARED) {
-                return Err(LxError::EINVAL);
-            }
-            let vmo = VmObject::new_paged(pages(len));
-            let addr = vmar.map(vmar_offset, vmo.clone(), 0, vmo.len(), prot.to_flags())?;
-            Ok(addr)
-        } else {
-            let file = self.linux_process().get_file(fd)?;
+        let vmo = if let Some(file) = file {
             let mut buf = vec![0; len];
             let len = file.read_at(offset, &mut buf).await?;
             let vmo = VmObject::new_paged(pages(len));
+            vmo.set_name(&file.path);
             vmo.write(0, &buf[..len])?;
-            let addr = vmar.map(vmar_offset, vmo.clone(), 0, vmo.len(), prot.to_flags())?;
-            Ok(addr)
-        }
+            vmo
+        } else {
+            VmObject::new_paged(pages(len))
+        };
+        let (len, mmu_flags) = (vmo.len(), prot.to_flags());
+        // a shared mapping stays shared with the children
+        let addr = if flags.contains(MmapFlags::SHARED) {
+            vmar.map_shared(vmar_offset, vmo, 0, len, mmu_flags, false)?
+        } else {
+            vmar.map(vmar_offset, vmo, 0, len, mmu_flags)?
+        };
+        Ok(addr)
     }
 
     /// changes the access protections for the calling process's memory pages
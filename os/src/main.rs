@@ -37,6 +37,7 @@ mod panic;
 mod sbi;
 mod zircon_object;
 mod memory;
+mod drivers;
 mod kernel_hal;
 mod kernel_hal_bare;
 mod fake_test;
@@ -66,14 +67,22 @@ use fake_test::{
     trapframe_test,
     alloc_test,
     fill_random_test,
+    chacha20_test,
     frame_test,
+    contiguous_frame_test,
+    frame_stats_test,
     pmem_test,
     page_table_test,
+    virtio_blk_test,
+    block_dev_test,
+    linux_trace_test,
+    zircon_trace_test,
     zircon_object_test::object_test::test_all_in_object_test,
     zircon_object_test::signal_test::test_all_in_signal_test,
     zircon_object_test::task_test::test_all_in_task_test,
     zircon_object_test::ipc_test::test_all_in_ipc_test,
     zircon_object_test::vm_test::test_all_in_vm_test,
+    linux_object_test::test_all_in_linux_object_test,
 };
 
 use crate::zircon_loader::{simple_run_userboot_zircon, Images};
@@ -86,6 +95,7 @@ global_asm!(include_str!("asm/entry.asm"));
 pub extern "C" fn rust_main(ramfs_data: &'static mut [u8], cmdline: &str) -> ! {
     println!("Welcome to zCore on riscv64");
     memory::init();
+    kernel_hal_bare::random::init();
 
     #[cfg(test)]
     test_main();
@@ -93,20 +103,30 @@ pub extern "C" fn rust_main(ramfs_data: &'static mut [u8], cmdline: &str) -> ! {
     alloc_test();
     trapframe_test();
     fill_random_test();
+    chacha20_test();
     frame_test();
+    contiguous_frame_test();
+    frame_stats_test();
     pmem_test();
+    virtio_blk_test();
+    block_dev_test();
+    linux_trace_test();
+    zircon_trace_test();
     //page_table_test();
     test_all_in_object_test();
     test_all_in_signal_test();
     test_all_in_ipc_test();
     test_all_in_task_test();
     test_all_in_vm_test();
+    test_all_in_linux_object_test();
     //run_with_zircon_loader(ramfs_data, cmdline);
     //run_with_linux_loader(ramfs_data, cmdline);
     unreachable!();
 }
 
 fn run_with_zircon_loader(ramfs_data: &[u8], cmdline: &str) {
+    configure_aslr(cmdline);
+    configure_syscall_trace(cmdline);
     let images = Images::<&[u8]> {
         userboot: include_bytes!("./hello"),
         vdso: include_bytes!("./hello_world"),
@@ -117,20 +137,20 @@ fn run_with_zircon_loader(ramfs_data: &[u8], cmdline: &str) {
     run_loop();
 }
 
-fn run_with_linux_loader(ramfs_data: &'static mut [u8], _cmdline: &str) {
+fn run_with_linux_loader(ramfs_data: &'static mut [u8], cmdline: &str) {
     use alloc::boxed::Box;
     use alloc::sync::Arc;
     use alloc::vec;
-    use linux_object::fs::MemBuf;
-    use linux_object::fs::STDIN;
+    use linux_object::fs::{MemBuf, CONSOLE};
     println!("run with linux loader");
+    configure_aslr(cmdline);
+    configure_syscall_trace(cmdline);
     crate::kernel_hal_bare::serial_set_callback(Box::new({
         move || {
             let mut buffer = [0; 255];
             let len = kernel_hal_bare::serial_read(&mut buffer);
-            for c in &buffer[..len] {
-                STDIN.push((*c).into());
-                kernel_hal_bare::serial_write(alloc::format!("{}", *c as char).as_str());
+            for &c in &buffer[..len] {
+                CONSOLE.push(c);
             }
             false
         }
@@ -139,12 +159,88 @@ fn run_with_linux_loader(ramfs_data: &'static mut [u8], _cmdline: &str) {
     let args = vec!["/bin/busybox".into(), "sh".into()];
     let envs = vec!["PATH=/usr/sbin:/usr/bin:/sbin:/bin:/usr/x86_64-alpine-linux-musl/bin".into()];
 
-    let device = Arc::new(MemBuf::new(ramfs_data));
-    let rootfs = rcore_fs_sfs::SimpleFileSystem::open(device).unwrap();
+    drivers::init();
+    if let Some(device) = drivers::net_devices().into_iter().next() {
+        // static configuration of QEMU user-mode networking
+        use linux_object::net::{add_interface, EthernetInterface, IpAddress};
+        let addr = IpAddress::V4([10, 0, 2, 15]);
+        let gateway = IpAddress::V4([10, 0, 2, 2]);
+        let eth0 = EthernetInterface::new("eth0", device, addr, 24, Some(gateway)).unwrap();
+        add_interface(eth0);
+    }
+
+    // `root=/dev/vdX` on the command line selects a disk, otherwise the ramdisk
+    let root = cmdline
+        .split_whitespace()
+        .find_map(|arg| arg.strip_prefix("root="));
+    let rootfs = match root {
+        // writes to a disk asked for must not go to the volatile ramdisk
+        Some(root) => open_disk(root).unwrap_or_else(|err| panic!("root={}: {}", root, err)),
+        None => rcore_fs_sfs::SimpleFileSystem::open(Arc::new(MemBuf::new(ramfs_data))).unwrap(),
+    };
     let _proc = linux_loader::run(args, envs, rootfs);
     run_loop();
 }
 
+/// Open the file system on the disk at `path`, such as `/dev/vda`.
+fn open_disk(
+    path: &str,
+) -> Result<alloc::sync::Arc<rcore_fs_sfs::SimpleFileSystem>, alloc::string::String> {
+    use alloc::{format, sync::Arc};
+    use linux_object::fs::BlockDev;
+    let name = path
+        .strip_prefix("/dev/")
+        .ok_or_else(|| format!("{} is not a device", path))?;
+    let disk = drivers::block_devices()
+        .into_iter()
+        .enumerate()
+        .find(|(_, (n, _))| n == name);
+    let (index, disk) = match disk {
+        Some((index, (_, disk))) => (index, disk),
+        None => return Err(format!("device {} not found", path)),
+    };
+    rcore_fs_sfs::SimpleFileSystem::open(Arc::new(BlockDev::new(disk, index)))
+        .map_err(|err| format!("failed to open {}: {:?}", path, err))
+}
+
+/// Configure ASLR from the `aslr.disable` and `aslr.entropy_bits=N` options.
+fn configure_aslr(cmdline: &str) {
+    use zircon_object::vm::{set_aslr, DEFAULT_ASLR_ENTROPY_BITS};
+    let mut enabled = true;
+    let mut entropy_bits = DEFAULT_ASLR_ENTROPY_BITS;
+    for arg in cmdline.split_whitespace() {
+        if arg == "aslr.disable" {
+            enabled = false;
+        } else if let Some(bits) = arg.strip_prefix("aslr.entropy_bits=") {
+            match bits.parse() {
+                Ok(bits) => entropy_bits = bits,
+                Err(_) => warn!("invalid aslr.entropy_bits: {:?}", bits),
+            }
+        }
+    }
+    if !enabled {
+        warn!("ASLR is disabled");
+    }
+    set_aslr(enabled, entropy_bits);
+}
+
+/// Trace the system calls of the processes named in the
+/// `syscall_trace=name,...` option, or of all processes with `*`.
+fn configure_syscall_trace(cmdline: &str) {
+    use alloc::{string::String, vec::Vec};
+    let names: Vec<String> = cmdline
+        .split_whitespace()
+        .filter_map(|arg| arg.strip_prefix("syscall_trace="))
+        .flat_map(|names| names.split(','))
+        .filter(|name| !name.is_empty())
+        .map(String::from)
+        .collect();
+    if !names.is_empty() {
+        warn!("tracing system calls of {:?}", names);
+    }
+    zircon_object::task::set_syscall_trace_filter(names);
+}
+
 fn run_loop() -> ! {
     let mut counter = 0;
     loop {
@@ -3,6 +3,7 @@ use {
     buddy_system_allocator::LockedHeap,
     spin::Mutex,
     riscv::paging::{PageTable, PageTableFlags as EF},
+    core::sync::atomic::{AtomicUsize, Ordering},
 };
 
 use crate::println;
@@ -13,6 +14,11 @@ type FrameAlloc = bitmap_allocator::BitAlloc16M;
 
 static FRAME_ALLOCATOR: Mutex<FrameAlloc> = Mutex::new(FrameAlloc::DEFAULT);
 
+/// Number of frames managed by the allocator
+static TOTAL_FRAMES: AtomicUsize = AtomicUsize::new(0);
+/// Number of frames not allocated
+static FREE_FRAMES: AtomicUsize = AtomicUsize::new(0);
+
 #[used]
 #[export_name = "hal_pmem_base"]
 static PMEM_BASE: usize = PHYSICAL_MEMORY_OFFSET;
@@ -27,6 +33,8 @@ static HEAP_ALLOCATOR: LockedHeap = LockedHeap::new();
 pub fn init_frame_allocator(start_frame: usize, end_frame: usize) {
     let mut ba = FRAME_ALLOCATOR.lock();
     ba.insert(start_frame..end_frame);
+    TOTAL_FRAMES.fetch_add(end_frame - start_frame, Ordering::Relaxed);
+    FREE_FRAMES.fetch_add(end_frame - start_frame, Ordering::Relaxed);
     info!("Frame allocator init end");
 }
 
@@ -50,6 +58,9 @@ pub extern "C" fn hal_frame_alloc() -> Option<usize> {
         .lock()
         .alloc()
         .map(|id| id * PAGE_SIZE + MEMORY_OFFSET);
+    if ret.is_some() {
+        FREE_FRAMES.fetch_sub(1, Ordering::Relaxed);
+    }
     trace!("Allocate frame: {:x?}", ret);
     ret
 }
@@ -60,6 +71,9 @@ pub extern "C" fn hal_frame_alloc_contiguous(page_num: usize, align_log2: usize)
         .lock()
         .alloc_contiguous(page_num, align_log2)
         .map(|id| id * PAGE_SIZE + MEMORY_OFFSET);
+    if ret.is_some() {
+        FREE_FRAMES.fetch_sub(page_num, Ordering::Relaxed);
+    }
     trace!(
         "Allocate contiguous frames: {:x?} ~ {:x?}",
         ret,
@@ -74,6 +88,17 @@ pub extern "C" fn hal_frame_dealloc(target: &usize) {
     FRAME_ALLOCATOR
         .lock()
         .dealloc((*target - MEMORY_OFFSET) / PAGE_SIZE);
+    FREE_FRAMES.fetch_add(1, Ordering::Relaxed);
+}
+
+#[no_mangle]
+pub extern "C" fn hal_frame_total() -> usize {
+    TOTAL_FRAMES.load(Ordering::Relaxed)
+}
+
+#[no_mangle]
+pub extern "C" fn hal_frame_free() -> usize {
+    FREE_FRAMES.load(Ordering::Relaxed)
 }
 
 #[no_mangle]
@@ -44,27 +44,45 @@ pub fn run_userboot(images: &Images<impl AsRef<[u8]>>, cmdline: &str) -> Arc<Pro
     );
     let vmar = proc.vmar();
 
+    // userboot is linked with the vDSO right after it, so one region at a
+    // random address holds both
+    let userboot_elf = ElfFile::new(images.userboot.as_ref()).unwrap();
+    let vdso_elf = ElfFile::new(images.vdso.as_ref()).unwrap();
+    let userboot_size = userboot_elf.load_segment_size();
+    let vdso_size = vdso_elf.load_segment_size();
+    let image_vmar = vmar
+        .allocate(
+            None,
+            userboot_size + vdso_size,
+            VmarFlags::CAN_MAP_RXW,
+            PAGE_SIZE,
+        )
+        .unwrap();
+
     // userboot
-    let (entry, userboot_size) = {
-        let elf = ElfFile::new(images.userboot.as_ref()).unwrap();
-        let size = elf.load_segment_size();
-        let vmar = vmar
-            .allocate(None, size, VmarFlags::CAN_MAP_RXW, PAGE_SIZE)
+    let entry = {
+        let elf = &userboot_elf;
+        let vmar = image_vmar
+            .allocate_at(
+                0,
+                userboot_size,
+                VmarFlags::CAN_MAP_RXW | VmarFlags::SPECIFIC,
+                PAGE_SIZE,
+            )
             .unwrap();
-        vmar.load_from_elf(&elf).unwrap();
-        (vmar.addr() + elf.header.pt2.entry_point() as usize, size)
+        vmar.load_from_elf(elf).unwrap();
+        vmar.addr() + elf.header.pt2.entry_point() as usize
     };
 
     // vdso
     let vdso_vmo = {
-        let elf = ElfFile::new(images.vdso.as_ref()).unwrap();
+        let elf = &vdso_elf;
         let vdso_vmo = VmObject::new_paged(images.vdso.as_ref().len() / PAGE_SIZE + 1);
         vdso_vmo.write(0, images.vdso.as_ref()).unwrap();
-        let size = elf.load_segment_size();
-        let vmar = vmar
+        let vmar = image_vmar
             .allocate_at(
                 userboot_size,
-                size,
+                vdso_size,
                 VmarFlags::CAN_MAP_RXW | VmarFlags::SPECIFIC,
                 PAGE_SIZE,
             )
@@ -202,6 +220,18 @@ async fn new_thread(thread: CurrentThread) {
             0 => handle_syscall(&thread, &mut cx.general).await,
             _ => unimplemented!(),
         }
+        #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+        {
+            use riscv::register::scause::{self, Interrupt, Trap};
+            let scause = scause::read();
+            match scause.cause() {
+                // the PLIC tells the source, the trap number is ignored
+                Trap::Interrupt(Interrupt::SupervisorExternal) => {
+                    crate::kernel_hal::InterruptManager::handle(scause.code() as u8)
+                }
+                cause => error!("unhandled trap from user mode: {:?}", cause),
+            }
+        }
         #[cfg(target_arch = "x86_64")]
         match cx.trap_num {
             0x100 => handle_syscall(&thread, &mut cx.general).await,
@@ -50,6 +50,23 @@ impl DebugLog {
     }
 }
 
+/// Write a log of the kernel on behalf of the thread `tid` of the process
+/// `pid`, and print it to the console.
+///
+/// The data is cut to fit in a record.
+pub fn write_kernel_log(severity: Severity, tid: u64, pid: u64, data: &str) {
+    let mut len = data.len().min(DLOG_MAX_LEN - HEADER_SIZE);
+    while !data.is_char_boundary(len) {
+        len -= 1;
+    }
+    let data = &data[..len];
+    DLOG.lock().write(severity, 0, tid, pid, data.as_bytes());
+    crate::kernel_hal::serial_write(data);
+    if !data.ends_with('\n') {
+        crate::kernel_hal::serial_write("\n");
+    }
+}
+
 #[repr(C)]
 #[derive(Debug)]
 struct DlogHeader {
@@ -52,7 +52,7 @@ impl Iommu {
         if perms.contains(IommuPerms::PERM_EXECUTE) {
             flags |= MMUFlags::EXECUTE;
         }
-        let p_addr = vmo.commit_page(offset / PAGE_SIZE, flags)?;
+        let p_addr = crate::kernel_hal::dma_addr(vmo.commit_page(offset / PAGE_SIZE, flags)?);
         if vmo.is_paged() {
             Ok((p_addr, PAGE_SIZE))
         } else {
@@ -73,7 +73,8 @@ impl Iommu {
         if offset + size > vmo.len() {
             return Err(ZxError::INVALID_ARGS);
         }
-        let p_addr = vmo.commit_page(offset, MMUFlags::empty())?;
+        let p_addr = vmo.commit_page(offset / PAGE_SIZE, MMUFlags::empty())?;
+        let p_addr = crate::kernel_hal::dma_addr(p_addr);
         if vmo.is_paged() {
             Ok((p_addr, PAGE_SIZE))
         } else {
@@ -1,5 +1,7 @@
 use super::*;
 use super::{object::*, task::Thread};
+use crate::kernel_hal::phys_to_virt;
+use crate::zircon_object::vm::{MMUFlags, VmObject, PAGE_SIZE};
 use alloc::collections::VecDeque;
 use alloc::sync::Arc;
 use core::future::Future;
@@ -19,9 +21,20 @@ use spin::Mutex;
 pub struct Futex {
     base: KObjectBase,
     value: &'static AtomicI32,
+    /// the VMO holding `value`, if the futex is made in one
+    backing: Option<Backing>,
     inner: Mutex<FutexInner>,
 }
 
+/// The VMO holding the value of a futex, kept alive with the page of the value
+/// pinned, so that the value is not freed or moved while the futex lives.
+struct Backing {
+    vmo: Arc<VmObject>,
+    /// offset of the page
+    page: usize,
+    pinned: bool,
+}
+
 impl_kobject!(Futex);
 
 #[derive(Default)]
@@ -44,10 +57,36 @@ impl Futex {
         Arc::new(Futex {
             base: KObjectBase::default(),
             value,
+            backing: None,
             inner: Mutex::new(FutexInner::default()),
         })
     }
 
+    /// Create a new Futex on the value at `offset` in `vmo`.
+    ///
+    /// The page of the value is committed and pinned, and the VMO is kept
+    /// alive until the futex is dropped.
+    pub fn new_in_vmo(vmo: Arc<VmObject>, offset: usize) -> ZxResult<Arc<Self>> {
+        if offset % core::mem::align_of::<AtomicI32>() != 0 {
+            return Err(ZxError::INVALID_ARGS);
+        }
+        let page = offset - offset % PAGE_SIZE;
+        let paddr = vmo.commit_page(page / PAGE_SIZE, MMUFlags::WRITE)?;
+        // the memory of other VMOs is not freed while they live
+        let pinned = vmo.is_paged();
+        if pinned {
+            vmo.pin(page, PAGE_SIZE)?;
+        }
+        #[allow(unsafe_code)]
+        let value = unsafe { &*(phys_to_virt(paddr + offset % PAGE_SIZE) as *const AtomicI32) };
+        Ok(Arc::new(Futex {
+            base: KObjectBase::default(),
+            value,
+            backing: Some(Backing { vmo, page, pinned }),
+            inner: Mutex::new(FutexInner::default()),
+        }))
+    }
+
     /// Wait on a futex.
     ///
     /// This atomically verifies that `value_ptr` still contains the value `current_value`
@@ -82,6 +121,48 @@ impl Futex {
         wake_count
     }
 
+    // ------ Linux bitset APIs ------
+
+    /// Wait on a futex like [`wait_with_owner`], to be woken only by a
+    /// [`wake_bitset`] whose bitset has a bit in common with `bitset`.
+    ///
+    /// [`wait_with_owner`]: Futex::wait_with_owner
+    /// [`wake_bitset`]: Futex::wake_bitset
+    pub fn wait_bitset(
+        self: &Arc<Self>,
+        current_value: i32,
+        thread: Option<Arc<Thread>>,
+        new_owner: Option<Arc<Thread>>,
+        bitset: u32,
+    ) -> impl Future<Output = ZxResult> {
+        self.wait_inner(current_value, thread, new_owner, bitset)
+    }
+
+    /// Wake at most `wake_count` of the waiters whose bitset has a bit in
+    /// common with `bitset`, in the order they started waiting.
+    ///
+    /// Return the number of waiters that were woken up.
+    ///
+    /// # Ownership
+    ///
+    /// The owner of the futex is set to nothing, regardless of the wake count.
+    pub fn wake_bitset(&self, wake_count: usize, bitset: u32) -> usize {
+        let mut inner = self.inner.lock();
+        inner.set_owner(None);
+        let mut woken_count = 0;
+        let mut i = 0;
+        while woken_count < wake_count && i < inner.waiter_queue.len() {
+            if inner.waiter_queue[i].bitset & bitset == 0 {
+                i += 1;
+                continue;
+            }
+            let waiter = inner.waiter_queue.remove(i).unwrap();
+            waiter.wake();
+            woken_count += 1;
+        }
+        woken_count
+    }
+
     // ------ Advanced APIs on Zircon ------
 
     /// Get the owner of the futex.
@@ -117,6 +198,16 @@ impl Futex {
         current_value: i32,
         thread: Option<Arc<Thread>>,
         new_owner: Option<Arc<Thread>>,
+    ) -> impl Future<Output = ZxResult> {
+        self.wait_inner(current_value, thread, new_owner, u32::MAX)
+    }
+
+    fn wait_inner(
+        self: &Arc<Self>,
+        current_value: i32,
+        thread: Option<Arc<Thread>>,
+        new_owner: Option<Arc<Thread>>,
+        bitset: u32,
     ) -> impl Future<Output = ZxResult> {
         #[must_use = "wait does nothing unless polled/`await`-ed"]
         struct FutexFuture {
@@ -172,6 +263,7 @@ impl Futex {
         FutexFuture {
             waiter: Arc::new(Waiter {
                 thread,
+                bitset,
                 inner: Mutex::new(WaiterInner {
                     waker: None,
                     woken: false,
@@ -215,6 +307,8 @@ impl Futex {
     ///
     /// The owner of this futex is set to nothing, regardless of the wake count.
     /// The owner of the `requeue_futex` is set to the thread `new_requeue_owner`.
+    ///
+    /// Return the number of waiters that were woken up or requeued.
     pub fn requeue(
         &self,
         current_value: i32,
@@ -222,16 +316,18 @@ impl Futex {
         requeue_count: usize,
         requeue_futex: &Arc<Futex>,
         new_requeue_owner: Option<Arc<Thread>>,
-    ) -> ZxResult {
+    ) -> ZxResult<usize> {
         let mut inner = self.inner.lock();
         // check value
         if self.value.load(Ordering::SeqCst) != current_value {
             return Err(ZxError::BAD_STATE);
         }
         // wake
-        for _ in 0..wake_count {
+        let mut woken_count = 0;
+        while woken_count < wake_count {
             if let Some(waiter) = inner.waiter_queue.pop_front() {
                 waiter.wake();
+                woken_count += 1;
             } else {
                 break;
             }
@@ -246,7 +342,7 @@ impl Futex {
         // set owner
         inner.set_owner(None);
         new_inner.set_owner(new_requeue_owner);
-        Ok(())
+        Ok(woken_count + requeue_count)
     }
 }
 
@@ -275,6 +371,8 @@ impl FutexInner {
 struct Waiter {
     /// The thread waiting on the futex.
     thread: Option<Arc<Thread>>,
+    /// Woken by `wake_bitset` with a bit in common, all bits otherwise.
+    bitset: u32,
     inner: Mutex<WaiterInner>,
 }
 
@@ -298,3 +396,15 @@ impl Waiter {
         self.inner.lock().futex = futex;
     }
 }
+
+impl Drop for Futex {
+    fn drop(&mut self) {
+        if let Some(backing) = &self.backing {
+            if backing.pinned {
+                if let Err(err) = backing.vmo.unpin(backing.page, PAGE_SIZE) {
+                    warn!("futex: failed to unpin its page: {:?}", err);
+                }
+            }
+        }
+    }
+}
@@ -1,11 +1,15 @@
 use {
     super::{exception::*, job::Job, job_policy::*, thread::Thread, *},
     super::{object::*, signal::Futex, vm::*},
-    alloc::{boxed::Box, sync::Arc, vec::Vec},
-    core::{any::Any, sync::atomic::AtomicI32},
+    alloc::{boxed::Box, string::String, sync::Arc, vec::Vec},
+    core::{
+        any::Any,
+        sync::atomic::{AtomicBool, AtomicI32, Ordering},
+    },
     futures::channel::oneshot::{self, Receiver, Sender},
     hashbrown::HashMap,
-    spin::Mutex,
+    lazy_static::lazy_static,
+    spin::{Mutex, RwLock},
 };
 
 /// Process abstraction
@@ -57,6 +61,8 @@ pub struct Process {
     ext: Box<dyn Any + Send + Sync>,
     exceptionate: Arc<Exceptionate>,
     debug_exceptionate: Arc<Exceptionate>,
+    /// Whether the system calls are traced, read on every system call.
+    trace_syscalls: AtomicBool,
     inner: Mutex<ProcessInner>,
 }
 
@@ -86,6 +92,26 @@ struct ProcessInner {
     critical_to_job: Option<(Arc<Job>, bool)>,
 }
 
+lazy_static! {
+    /// Names of the processes whose system calls are traced from their creation
+    static ref SYSCALL_TRACE_FILTER: RwLock<Vec<String>> = RwLock::new(Vec::new());
+}
+
+/// Trace the system calls of the processes created with a name in `names`,
+/// or of all processes if it has `*`.
+pub fn set_syscall_trace_filter(names: Vec<String>) {
+    *SYSCALL_TRACE_FILTER.write() = names;
+}
+
+/// Whether the system calls of a process named `name` are traced from its
+/// creation.
+pub fn syscall_trace_filter_matches(name: &str) -> bool {
+    SYSCALL_TRACE_FILTER
+        .read()
+        .iter()
+        .any(|filter| filter == "*" || filter == name)
+}
+
 /// Status of a process.
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum Status {
@@ -124,6 +150,7 @@ impl Process {
             ext: Box::new(ext),
             exceptionate: Exceptionate::new(ExceptionChannelType::Process),
             debug_exceptionate: Exceptionate::new(ExceptionChannelType::Debugger),
+            trace_syscalls: AtomicBool::new(syscall_trace_filter_matches(name)),
             inner: Mutex::new(ProcessInner::default()),
         });
         job.add_process(proc.clone())?;
@@ -517,6 +544,21 @@ impl Process {
         self.inner.lock().dyn_break_on_load
     }
 
+    /// Set whether the system calls of the process are traced.
+    ///
+    /// Each traced call is written to the debuglog as one line, with its
+    /// arguments decoded and its result, like `strace` does. The arguments
+    /// are decoded before the call is made, as the call may unmap or change
+    /// the memory they point to.
+    pub fn set_trace_syscalls(&self, enabled: bool) {
+        self.trace_syscalls.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Whether the system calls of the process are traced.
+    pub fn trace_syscalls(&self) -> bool {
+        self.trace_syscalls.load(Ordering::Relaxed)
+    }
+
     /// Get an one-shot `Receiver` for receiving cancel message of the given handle.
     pub fn get_cancel_token(&self, handle_value: HandleValue) -> ZxResult<Receiver<()>> {
         self.inner.lock().get_cancel_token(handle_value)
@@ -527,6 +569,13 @@ impl Process {
         self.inner.lock().threads.iter().map(|t| t.id()).collect()
     }
 
+    /// Get the time the threads of this process have run on cpu.
+    ///
+    /// Threads that have exited no longer count.
+    pub fn get_time(&self) -> u64 {
+        self.inner.lock().threads.iter().map(|t| t.get_time()).sum()
+    }
+
     /// Wait for process exit and get return code.
     pub async fn wait_for_exit(self: &Arc<Self>) -> i64 {
         let object: Arc<dyn KernelObject> = self.clone();
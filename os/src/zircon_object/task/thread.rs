@@ -22,6 +22,10 @@ pub use self::thread_state::*;
 
 mod thread_state;
 
+/// `sstatus.SPIE`, interrupts are enabled after returning to user mode
+#[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+const SSTATUS_SPIE: usize = 1 << 5;
+
 /// Runnable / computation entity
 ///
 /// ## SYNOPSIS
@@ -260,7 +264,11 @@ impl Thread {
             }
             #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
             {
-                unimplemented!();
+                context.sepc = entry;
+                context.general.sp = stack;
+                context.general.a0 = arg1;
+                context.general.a1 = arg2;
+                context.sstatus |= SSTATUS_SPIE;
             }
             inner.change_state(ThreadState::Running, &self.base);
         }
@@ -270,18 +278,36 @@ impl Thread {
     }
 
     /// Start execution with given registers.
+    ///
+    /// On riscv the program counter is not one of the registers, use
+    /// [`start_with_context`] to set it.
+    ///
+    /// [`start_with_context`]: Thread::start_with_context
     pub fn start_with_regs(self: &Arc<Self>, regs: GeneralRegs, thread_fn: ThreadFn) -> ZxResult {
+        let context = UserContext {
+            general: regs,
+            ..Default::default()
+        };
+        self.start_with_context(context, thread_fn)
+    }
+
+    /// Start execution with given user context.
+    pub fn start_with_context(
+        self: &Arc<Self>,
+        context: UserContext,
+        thread_fn: ThreadFn,
+    ) -> ZxResult {
         {
             let mut inner = self.inner.lock();
-            let context = inner.context.as_mut().ok_or(ZxError::BAD_STATE)?;
-            context.general = regs;
+            let ctx = inner.context.as_mut().ok_or(ZxError::BAD_STATE)?;
+            **ctx = context;
             #[cfg(target_arch = "x86_64")]
             {
-                context.general.rflags |= 0x3202;
+                ctx.general.rflags |= 0x3202;
             }
             #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
             {
-                unimplemented!();
+                ctx.sstatus |= SSTATUS_SPIE;
             }
             inner.change_state(ThreadState::Running, &self.base);
         }
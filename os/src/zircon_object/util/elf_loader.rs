@@ -9,6 +9,7 @@ use xmas_elf::{
 use crate::println;
 pub trait VmarExt {
     fn load_from_elf(&self, elf: &ElfFile) -> ZxResult<Arc<VmObject>>;
+    fn load_from_elf_at(&self, elf: &ElfFile, start: usize) -> ZxResult<Arc<VmObject>>;
     fn map_from_elf(&self, elf: &ElfFile, vmo: Arc<VmObject>) -> ZxResult;
 }
 
@@ -16,13 +17,19 @@ impl VmarExt for VmAddressRegion {
     /// Create `VMObject` from all LOAD segments of `elf` and map them to this VMAR.
     /// Return the first `VMObject`.
     fn load_from_elf(&self, elf: &ElfFile) -> ZxResult<Arc<VmObject>> {
+        self.load_from_elf_at(elf, 0)
+    }
+
+    /// Like `load_from_elf`, but map the segments at their virtual address
+    /// minus `start`, for images linked at a fixed address.
+    fn load_from_elf_at(&self, elf: &ElfFile, start: usize) -> ZxResult<Arc<VmObject>> {
         let mut first_vmo = None;
         for ph in elf.program_iter() {
             if ph.get_type().unwrap() != Type::Load {
                 continue;
             }
             let vmo = make_vmo(&elf, ph)?;
-            let offset = ph.virtual_addr() as usize / PAGE_SIZE * PAGE_SIZE;
+            let offset = ph.virtual_addr() as usize / PAGE_SIZE * PAGE_SIZE - start;
             let flags = ph.flags().to_mmu_flags();
             self.map_at(offset, vmo.clone(), 0, vmo.len(), flags)?;
             first_vmo.get_or_insert(vmo);
@@ -81,6 +88,7 @@ fn make_vmo(elf: &ElfFile, ph: ProgramHeader) -> ZxResult<Arc<VmObject>> {
 
 pub trait ElfExt {
     fn load_segment_size(&self) -> usize;
+    fn load_segment_range(&self) -> (usize, usize);
     fn get_symbol_address(&self, symbol: &str) -> Option<u64>;
     fn get_interpreter(&self) -> Result<&str, &str>;
     fn dynsym(&self) -> Result<&[DynEntry64], &'static str>;
@@ -98,6 +106,17 @@ impl ElfExt for ElfFile<'_> {
             * PAGE_SIZE
     }
 
+    /// Get the page-aligned range of addresses covered by LOAD segments.
+    fn load_segment_range(&self) -> (usize, usize) {
+        let start = self
+            .program_iter()
+            .filter(|ph| ph.get_type().unwrap() == Type::Load)
+            .map(|ph| ph.virtual_addr() as usize / PAGE_SIZE * PAGE_SIZE)
+            .min()
+            .unwrap_or(0);
+        (start, self.load_segment_size())
+    }
+
     /// Get address of the given `symbol`.
     fn get_symbol_address(&self, symbol: &str) -> Option<u64> {
         for section in self.section_iter() {
@@ -121,7 +140,7 @@ impl ElfExt for ElfFile<'_> {
             SegmentData::Undefined(data) => data,
             _ => return Err("bad interp"),
         };
-        let len = (0..).find(|&i| data[i] == 0).unwrap();
+        let len = data.iter().position(|&b| b == 0).ok_or("bad interp")?;
         let path = core::str::from_utf8(&data[..len]).map_err(|_| "failed to convert to utf8")?;
         Ok(path)
     }
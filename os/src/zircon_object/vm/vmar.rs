@@ -1,6 +1,7 @@
 use core::sync::atomic::*;
 use {
-    super::*, crate::zircon_object::object::*, alloc::sync::Arc, alloc::vec::Vec, bitflags::bitflags,
+    super::*, crate::zircon_object::object::*, alloc::string::String, alloc::sync::Arc,
+    alloc::vec::Vec, bitflags::bitflags,
     crate::kernel_hal::PageTableTrait, spin::Mutex,
 };
 
@@ -37,6 +38,22 @@ bitflags! {
     }
 }
 
+/// Default bits of entropy in the address of randomly placed regions
+pub const DEFAULT_ASLR_ENTROPY_BITS: u32 = 20;
+
+/// Whether regions without a specific offset are placed randomly
+static ASLR_ENABLED: AtomicBool = AtomicBool::new(true);
+/// Bits of entropy in the address of randomly placed regions
+static ASLR_ENTROPY_BITS: AtomicU32 = AtomicU32::new(DEFAULT_ASLR_ENTROPY_BITS);
+
+/// Configure address space layout randomization.
+///
+/// When disabled, regions without a specific offset take the first free area.
+pub fn set_aslr(enabled: bool, entropy_bits: u32) {
+    ASLR_ENABLED.store(enabled, Ordering::Relaxed);
+    ASLR_ENTROPY_BITS.store(entropy_bits.min(usize::MAX.count_ones() - 1), Ordering::Relaxed);
+}
+
 /// Virtual Memory Address Regions
 pub struct VmAddressRegion {
     flags: VmarFlags,
@@ -46,6 +63,8 @@ pub struct VmAddressRegion {
     size: usize,
     parent: Option<Arc<VmAddressRegion>>,
     page_table: Arc<Mutex<dyn PageTableTrait>>,
+    /// Max total size of mappings in the address space, only used in the root.
+    space_limit: AtomicUsize,
     /// If inner is None, this region is destroyed, all operations are invalid.
     inner: Mutex<Option<VmarInner>>,
 }
@@ -75,6 +94,7 @@ impl VmAddressRegion {
             size: 0x100_00000000,
             parent: None,
             page_table: Arc::new(Mutex::new(crate::kernel_hal::PageTable::new())),
+            space_limit: AtomicUsize::new(usize::MAX),
             inner: Mutex::new(Some(VmarInner::default())),
         })
     }
@@ -91,6 +111,7 @@ impl VmAddressRegion {
             size: kernel_vmar_size,
             parent: None,
             page_table: Arc::new(Mutex::new(crate::kernel_hal::PageTable::new())),
+            space_limit: AtomicUsize::new(usize::MAX),
             inner: Mutex::new(Some(VmarInner::default())),
         })
     }
@@ -108,6 +129,7 @@ impl VmAddressRegion {
             size: guest_vmar_size,
             parent: None,
             page_table: Arc::new(Mutex::new(crate::hypervisor::VmmPageTable::new())),
+            space_limit: AtomicUsize::new(usize::MAX),
             inner: Mutex::new(Some(VmarInner::default())),
         })
     }
@@ -142,6 +164,7 @@ impl VmAddressRegion {
             size: len,
             parent: Some(self.clone()),
             page_table: self.page_table.clone(),
+            space_limit: AtomicUsize::new(usize::MAX),
             inner: Mutex::new(Some(VmarInner::default())),
         });
         inner.children.push(child.clone());
@@ -206,6 +229,34 @@ impl VmAddressRegion {
         flags: MMUFlags,
         overwrite: bool,
         _map_range: bool,
+    ) -> ZxResult<VirtAddr> {
+        self.map_inner(vmar_offset, vmo, vmo_offset, len, flags, overwrite, false)
+    }
+
+    /// Map the `vmo` into this VMAR, shared with the VMARs forked from this one
+    /// instead of copied on write. (For Linux `MAP_SHARED`)
+    pub fn map_shared(
+        &self,
+        vmar_offset: Option<usize>,
+        vmo: Arc<VmObject>,
+        vmo_offset: usize,
+        len: usize,
+        flags: MMUFlags,
+        overwrite: bool,
+    ) -> ZxResult<VirtAddr> {
+        self.map_inner(vmar_offset, vmo, vmo_offset, len, flags, overwrite, true)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn map_inner(
+        &self,
+        vmar_offset: Option<usize>,
+        vmo: Arc<VmObject>,
+        vmo_offset: usize,
+        len: usize,
+        flags: MMUFlags,
+        overwrite: bool,
+        shared: bool,
     ) -> ZxResult<VirtAddr> {
         if !page_aligned(vmo_offset) || !page_aligned(len) || vmo_offset.overflowing_add(len).1 {
             return Err(ZxError::INVALID_ARGS);
@@ -214,6 +265,10 @@ impl VmAddressRegion {
         if vmo_offset > vmo.len() || len > vmo.len() - vmo_offset {
             return Err(ZxError::INVALID_ARGS);
         }
+        let limit = self.space_limit();
+        if limit != usize::MAX && self.root().mapped_size().saturating_add(len) > limit {
+            return Err(ZxError::NO_MEMORY);
+        }
         let mut guard = self.inner.lock();
         let inner = guard.as_mut().ok_or(ZxError::BAD_STATE)?;
         let offset = self.determine_offset(inner, vmar_offset, len, PAGE_SIZE)?;
@@ -231,7 +286,15 @@ impl VmAddressRegion {
                 return Err(ZxError::NO_MEMORY);
             }
         }
-        let mapping = VmMapping::new(addr, len, vmo, vmo_offset, flags, self.page_table.clone());
+        let mapping = VmMapping::new(
+            addr,
+            len,
+            vmo,
+            vmo_offset,
+            flags,
+            shared,
+            self.page_table.clone(),
+        );
         let map_range = true;
         if map_range {
             mapping.map()?;
@@ -370,6 +433,11 @@ impl VmAddressRegion {
         self.addr
     }
 
+    /// Get end address of this VMAR.
+    pub fn end_addr(&self) -> VirtAddr {
+        self.addr + self.size
+    }
+
     /// Whether this VMAR is dead.
     pub fn is_dead(&self) -> bool {
         self.inner.lock().is_none()
@@ -424,6 +492,9 @@ impl VmAddressRegion {
     }
 
     /// Find a free area with `len`.
+    ///
+    /// The area is chosen randomly if ASLR is enabled, unless this VMAR is
+    /// `COMPACT`, then the first free area is used.
     fn find_free_area(
         &self,
         inner: &VmarInner,
@@ -431,7 +502,9 @@ impl VmAddressRegion {
         len: usize,
         align: usize,
     ) -> Option<usize> {
-        // TODO: randomize
+        if ASLR_ENABLED.load(Ordering::Relaxed) && !self.flags.contains(VmarFlags::COMPACT) {
+            return self.find_random_area(inner, len, align);
+        }
         debug_assert!(check_aligned(offset_hint, align));
         debug_assert!(check_aligned(len, align));
         // brute force:
@@ -442,8 +515,46 @@ impl VmAddressRegion {
             .find(|&offset| self.test_map(inner, offset, len, align))
     }
 
-    fn end_addr(&self) -> VirtAddr {
-        self.addr + self.size
+    /// Find a free area with `len` at random.
+    ///
+    /// The start is one of at most `2^ASLR_ENTROPY_BITS` aligned offsets,
+    /// spread evenly over all the free areas.
+    fn find_random_area(&self, inner: &VmarInner, len: usize, align: usize) -> Option<usize> {
+        let mut used: Vec<(usize, usize)> = inner
+            .children
+            .iter()
+            .map(|vmar| (vmar.addr, vmar.end_addr()))
+            .chain(inner.mappings.iter().map(|map| (map.addr(), map.end_addr())))
+            .map(|(begin, end)| (begin - self.addr, end - self.addr))
+            .collect();
+        used.sort_unstable();
+        used.push((self.size, self.size));
+
+        // free areas as (first offset, number of possible offsets)
+        let mut areas = Vec::new();
+        let mut free_begin = 0;
+        for (begin, end) in used {
+            let first = (free_begin + align - 1) / align * align;
+            if first + len <= begin {
+                areas.push((first, (begin - first - len) / align + 1));
+            }
+            free_begin = free_begin.max(end);
+        }
+        let total: usize = areas.iter().map(|&(_, count)| count).sum();
+        if total == 0 {
+            return None;
+        }
+        let slots = total.min(1 << ASLR_ENTROPY_BITS.load(Ordering::Relaxed));
+        let mut random = [0u8; core::mem::size_of::<usize>()];
+        crate::kernel_hal::fill_random(&mut random);
+        let mut index = usize::from_ne_bytes(random) % slots * (total / slots);
+        for (first, count) in areas {
+            if index < count {
+                return Some(first + index * align);
+            }
+            index -= count;
+        }
+        unreachable!()
     }
 
     fn overlap(&self, begin: VirtAddr, end: VirtAddr) -> bool {
@@ -533,6 +644,39 @@ impl VmAddressRegion {
         }
     }
 
+    /// The root region of the address space.
+    fn root(&self) -> &VmAddressRegion {
+        let mut vmar = self;
+        while let Some(parent) = vmar.parent.as_ref() {
+            vmar = parent;
+        }
+        vmar
+    }
+
+    /// Limit the total size of mappings in the address space of this region.
+    ///
+    /// Mappings beyond the limit fail with `NO_MEMORY`, existing ones are kept.
+    pub fn set_space_limit(&self, limit: usize) {
+        self.root().space_limit.store(limit, Ordering::Relaxed);
+    }
+
+    /// Get the limit of the total size of mappings in the address space.
+    pub fn space_limit(&self) -> usize {
+        self.root().space_limit.load(Ordering::Relaxed)
+    }
+
+    /// Get the total size of mappings in this region and its children.
+    pub fn mapped_size(&self) -> usize {
+        let guard = self.inner.lock();
+        let inner = match guard.as_ref() {
+            Some(inner) => inner,
+            None => return 0,
+        };
+        let map_size: usize = inner.mappings.iter().map(|map| map.size()).sum();
+        let child_size: usize = inner.children.iter().map(|child| child.mapped_size()).sum();
+        map_size + child_size
+    }
+
     /// Clone the entire address space and VMOs from source VMAR. (For Linux fork)
     pub fn fork_from(&self, src: &Arc<Self>) -> ZxResult {
         let mut guard = self.inner.lock();
@@ -571,6 +715,71 @@ impl VmAddressRegion {
         Ok(buf.len())
     }
 
+    /// Write to address space as a debugger does, without changing what other
+    /// address spaces see.
+    ///
+    /// A mapping shared with other address spaces can not be written. A
+    /// read-only mapping, whose VMO may be mapped by other processes like the
+    /// vDSO, is first given a private copy-on-write child of its VMO.
+    ///
+    /// Return the actual number of bytes written.
+    pub fn write_memory_private(&self, vaddr: usize, buf: &[u8]) -> ZxResult<usize> {
+        let map = self.find_mapping(vaddr).ok_or(ZxError::NO_MEMORY)?;
+        if map.is_shared() {
+            return Err(ZxError::ACCESS_DENIED);
+        }
+        if !map.flags.contains(MMUFlags::WRITE) {
+            self.make_private(vaddr)?;
+        }
+        self.write_memory(vaddr, buf)
+    }
+
+    /// Replace the mapping at `vaddr` with one of a copy-on-write child of its VMO.
+    fn make_private(&self, vaddr: usize) -> ZxResult {
+        let mut guard = self.inner.lock();
+        let inner = guard.as_mut().ok_or(ZxError::BAD_STATE)?;
+        if let Some(map) = inner.mappings.iter_mut().find(|map| map.contains(vaddr)) {
+            let new_map = map.clone_map(self.page_table.clone())?;
+            new_map.vmo.set_name(&map.vmo.name());
+            map.cut(map.addr(), map.end_addr());
+            *map = new_map.clone();
+            return new_map.map();
+        }
+        let child = inner
+            .children
+            .iter()
+            .find(|ch| ch.contains(vaddr))
+            .cloned()
+            .ok_or(ZxError::NO_MEMORY)?;
+        drop(guard);
+        child.make_private(vaddr)
+    }
+
+    /// Get information of all mappings in this VMAR and its children, sorted by address.
+    pub fn get_mappings(&self) -> Vec<VmMappingInfo> {
+        let guard = self.inner.lock();
+        let inner = match guard.as_ref() {
+            Some(inner) => inner,
+            None => return Vec::new(),
+        };
+        let mut mappings: Vec<VmMappingInfo> =
+            inner.mappings.iter().map(|map| map.get_info()).collect();
+        for child in inner.children.iter() {
+            mappings.extend(child.get_mappings());
+        }
+        mappings.sort_by_key(|map| map.addr);
+        mappings
+    }
+
+    /// Get the physical address `vaddr` is mapped to.
+    ///
+    /// The page is committed if it is not yet. Addresses mapping the same page
+    /// of a VMO get the same physical address, until the page is copied on write.
+    pub fn translate(&self, vaddr: VirtAddr) -> ZxResult<PhysAddr> {
+        let mapping = self.find_mapping(vaddr).ok_or(ZxError::NOT_FOUND)?;
+        mapping.translate(vaddr)
+    }
+
     /// Find mapping of vaddr
     pub fn find_mapping(&self, vaddr: usize) -> Option<Arc<VmMapping>> {
         let guard = self.inner.lock();
@@ -584,6 +793,15 @@ impl VmAddressRegion {
         None
     }
 
+    /// Get the VMO mapped at `vaddr`, the offset of `vaddr` in it and the
+    /// flags of the mapping.
+    pub fn vmo_at(&self, vaddr: usize) -> Option<(Arc<VmObject>, usize, MMUFlags)> {
+        let map = self.find_mapping(vaddr)?;
+        let map_inner = map.inner.lock();
+        let vmo_offset = vaddr - map_inner.addr + map_inner.vmo_offset;
+        Some((map.vmo.clone(), vmo_offset, map.flags))
+    }
+
     
     pub fn count(&self) -> usize {
         let mut guard = self.inner.lock();
@@ -630,9 +848,28 @@ pub struct VmarInfo {
     len: usize,
 }
 
+/// Information of a VmMapping.
+#[derive(Debug, Clone)]
+pub struct VmMappingInfo {
+    /// Base address
+    pub addr: VirtAddr,
+    /// Size in bytes
+    pub size: usize,
+    /// Access flags
+    pub flags: MMUFlags,
+    /// KoID of the mapped VMO
+    pub vmo_id: KoID,
+    /// Offset of the mapping in the VMO
+    pub vmo_offset: usize,
+    /// Name of the mapped VMO
+    pub vmo_name: String,
+}
+
 /// Virtual Memory Mapping
 pub struct VmMapping {
     flags: MMUFlags,
+    /// shared with the forked VMARs instead of copied on write
+    shared: bool,
     vmo: Arc<VmObject>,
     page_table: Arc<Mutex<dyn PageTableTrait>>,
     inner: Mutex<VmMappingInner>,
@@ -675,6 +912,7 @@ impl VmMapping {
         vmo: Arc<VmObject>,
         vmo_offset: usize,
         flags: MMUFlags,
+        shared: bool,
         page_table: Arc<Mutex<dyn PageTableTrait>>,
     ) -> Arc<Self> {
         let mapping = Arc::new(VmMapping {
@@ -684,6 +922,7 @@ impl VmMapping {
                 vmo_offset,
             }),
             flags,
+            shared,
             page_table,
             vmo: vmo.clone(),
         });
@@ -691,6 +930,18 @@ impl VmMapping {
         mapping
     }
 
+    fn get_info(&self) -> VmMappingInfo {
+        let inner = self.inner.lock();
+        VmMappingInfo {
+            addr: inner.addr,
+            size: inner.size,
+            flags: self.flags,
+            vmo_id: self.vmo.id(),
+            vmo_offset: inner.vmo_offset,
+            vmo_name: self.vmo.name(),
+        }
+    }
+
     /// Map range and commit.
     /// Commit pages to vmo, and map those to frames in page_table.
     /// Temporarily used for development. A standard procedure for
@@ -788,6 +1039,7 @@ impl VmMapping {
                 self.vmo.clone(),
                 inner.vmo_offset + (end - inner.addr),
                 self.flags,
+                self.shared,
                 self.page_table.clone(),
             ))
         }
@@ -841,6 +1093,16 @@ impl VmMapping {
         self.flags
     }
 
+    /// Get the VMO mapped by this VmMapping.
+    pub fn vmo(&self) -> &Arc<VmObject> {
+        &self.vmo
+    }
+
+    /// Whether the mapping is shared with the forked VMARs.
+    pub fn is_shared(&self) -> bool {
+        self.shared
+    }
+
     /// Remove WRITE flag from the mappings for Copy-on-Write.
     pub(super) fn range_change(&self, offset: usize, len: usize, op: RangeChangeOp) {
         let inner = self.inner.lock();
@@ -877,12 +1139,29 @@ impl VmMapping {
         Ok(())
     }
 
+    /// Get the physical address `vaddr` in this VmMapping is mapped to.
+    fn translate(&self, vaddr: VirtAddr) -> ZxResult<PhysAddr> {
+        let offset = {
+            let inner = self.inner.lock();
+            vaddr - inner.addr + inner.vmo_offset
+        };
+        let paddr = self.vmo.commit_page(offset / PAGE_SIZE, MMUFlags::READ)?;
+        Ok(paddr + offset % PAGE_SIZE)
+    }
+
     /// Clone VMO and map it to a new page table. (For Linux)
+    ///
+    /// A shared mapping maps the same VMO.
     fn clone_map(&self, page_table: Arc<Mutex<dyn PageTableTrait>>) -> ZxResult<Arc<Self>> {
-        let new_vmo = self.vmo.create_child(false, 0, self.vmo.len())?;
+        let new_vmo = if self.shared {
+            self.vmo.clone()
+        } else {
+            self.vmo.create_child(false, 0, self.vmo.len())?
+        };
         let mapping = Arc::new(VmMapping {
             inner: Mutex::new(self.inner.lock().clone()),
             flags: self.flags,
+            shared: self.shared,
             page_table,
             vmo: new_vmo.clone(),
         });
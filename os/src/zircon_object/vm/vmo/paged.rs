@@ -305,6 +305,12 @@ impl VMObjectTrait for VMObjectPaged {
         }
         let start_page = offset / PAGE_SIZE;
         let pages = len / PAGE_SIZE;
+        // pinned pages are in use by the kernel
+        let pinned = (start_page..start_page + pages)
+            .any(|i| inner.frames.get(&i).map_or(false, |frame| frame.pin_count > 0));
+        if pinned {
+            return Err(ZxError::BAD_STATE);
+        }
         for i in 0..pages {
             inner.decommit(start_page + i);
         }
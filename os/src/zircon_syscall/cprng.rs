@@ -1,5 +1,7 @@
 use super::*;
 
+const CPRNG_ADD_ENTROPY_MAX_LEN: usize = 256;
+
 impl Syscall<'_> {
     /// Draw random bytes from the kernel CPRNG.   
     ///
@@ -14,4 +16,17 @@ impl Syscall<'_> {
         buf.write_array(&res)?;
         Ok(())
     }
+
+    /// Add entropy to the kernel CPRNG.
+    ///
+    /// At most `ZX_CPRNG_ADD_ENTROPY_MAX_LEN` (256) bytes can be added at once.
+    pub fn sys_cprng_add_entropy(&self, buf: UserInPtr<u8>, len: usize) -> ZxResult {
+        info!("cprng_add_entropy: buf=({:?}; {:?})", buf, len);
+        if len > CPRNG_ADD_ENTROPY_MAX_LEN {
+            return Err(ZxError::INVALID_ARGS);
+        }
+        let data = buf.read_array(len)?;
+        crate::kernel_hal::add_entropy(&data);
+        Ok(())
+    }
 }
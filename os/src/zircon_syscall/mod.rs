@@ -27,6 +27,7 @@ mod stream;
 mod system;
 
 mod task;
+mod trace;
 mod vmar;
 mod vmo;
 
@@ -46,6 +47,8 @@ use {
 
 use consts::SyscallType as Sys;
 
+pub(crate) use self::{consts::SyscallType, trace::decode_args};
+
 pub struct Syscall<'a> {
     pub regs: &'a mut GeneralRegs,
     pub thread: &'a CurrentThread,
@@ -67,6 +70,12 @@ impl Syscall<'_> {
             "{}|{} {:?} => args={:x?}",
             proc_name, thread_name, sys_type, args
         );
+        // strings are read before the call may overwrite them
+        let trace = if self.thread.proc().trace_syscalls() {
+            Some(decode_args(&sys_type, &args))
+        } else {
+            None
+        };
         let [a0, a1, a2, a3, a4, a5, a6, a7] = args;
         let ret = match sys_type {
             Sys::HANDLE_CLOSE => self.sys_handle_close(a0 as _),
@@ -248,6 +257,7 @@ impl Syscall<'_> {
             Sys::VMAR_PROTECT => self.sys_vmar_protect(a0 as _, a1 as _, a2 as _, a3 as _),
             Sys::VMAR_DESTROY => self.sys_vmar_destroy(a0 as _),
             Sys::CPRNG_DRAW_ONCE => self.sys_cprng_draw_once(a0.into(), a1 as _),
+            Sys::CPRNG_ADD_ENTROPY => self.sys_cprng_add_entropy(a0.into(), a1 as _),
             Sys::NANOSLEEP => self.sys_nanosleep(a0.into()).await,
             Sys::CLOCK_CREATE => self.sys_clock_create(a0 as _, a1.into(), a2.into()),
             Sys::CLOCK_GET => self.sys_clock_get(a0 as _, a1.into()),
@@ -379,6 +389,9 @@ impl Syscall<'_> {
             }
         };
         info!("{}|{} {:?} <= {:?}", proc_name, thread_name, sys_type, ret);
+        if let Some(args) = trace {
+            self.trace_result(&sys_type, &args, &ret);
+        }
         match ret {
             Ok(_) => 0,
             Err(err) => err as isize,
@@ -58,6 +58,14 @@ impl Syscall<'_> {
                 info_ptr.write(break_on_load)?;
                 Ok(())
             }
+            Property::ProcessTraceSyscalls => {
+                let mut info_ptr = UserOutPtr::<usize>::from_addr_size(buffer, buffer_size)?;
+                let enabled = proc
+                    .get_object_with_rights::<Process>(handle_value, Rights::GET_PROPERTY)?
+                    .trace_syscalls();
+                info_ptr.write(enabled as usize)?;
+                Ok(())
+            }
             Property::SocketRxThreshold => {
                 let mut info_ptr = UserOutPtr::<usize>::from_addr_size(buffer, buffer_size)?;
                 let rx = proc
@@ -153,6 +161,12 @@ impl Syscall<'_> {
                     .set_dyn_break_on_load(addr);
                 Ok(())
             }
+            Property::ProcessTraceSyscalls => {
+                let enabled = UserInPtr::<usize>::from_addr_size(buffer, buffer_size)?.read()?;
+                proc.get_object_with_rights::<Process>(handle_value, Rights::SET_PROPERTY)?
+                    .set_trace_syscalls(enabled != 0);
+                Ok(())
+            }
             Property::SocketRxThreshold => {
                 let threshold = UserInPtr::<usize>::from_addr_size(buffer, buffer_size)?.read()?;
                 proc.get_object::<Socket>(handle_value)?
@@ -540,6 +554,8 @@ numeric_enum! {
         ExceptionState = 16,
         VmoContentSize = 17,
         ExceptionStrategy = 18,
+        // not in Zircon: whether the system calls of a process are traced
+        ProcessTraceSyscalls = 0x1000,
     }
 }
 
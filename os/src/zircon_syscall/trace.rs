@@ -0,0 +1,119 @@
+//! Tracing of Zircon system calls, as set by [`Process::set_trace_syscalls`]
+//!
+//! Handles, rights, signals, deadlines and strings are decoded.
+//!
+//! [`Process::set_trace_syscalls`]: crate::zircon_object::task::Process::set_trace_syscalls
+
+use super::*;
+use crate::zircon_object::debuglog::{write_kernel_log, Severity};
+use alloc::{format, string::String, vec::Vec};
+
+/// How an argument is shown
+#[derive(Debug, Copy, Clone)]
+enum Arg {
+    /// in hex, for pointers, options and addresses
+    Hex,
+    /// in decimal, for sizes and counts
+    Int,
+    /// a handle value
+    Handle,
+    /// bits of `Rights`
+    Rights,
+    /// bits of `Signal`
+    Signals,
+    /// a deadline in nanoseconds
+    Deadline,
+    /// a string given by a pointer and a length, taking two arguments
+    Str,
+}
+
+/// Max length of a string shown in a trace
+const MAX_STR_LEN: usize = 32;
+
+/// Get how the arguments of `sys` are shown, empty for the system calls
+/// which are not decoded.
+fn signature(sys: &Sys) -> &'static [Arg] {
+    use Arg::*;
+    match sys {
+        Sys::HANDLE_CLOSE => &[Handle],
+        Sys::HANDLE_DUPLICATE | Sys::HANDLE_REPLACE => &[Handle, Rights, Hex],
+        Sys::OBJECT_GET_PROPERTY | Sys::OBJECT_SET_PROPERTY => &[Handle, Int, Hex, Int],
+        Sys::OBJECT_SIGNAL | Sys::OBJECT_SIGNAL_PEER => &[Handle, Signals, Signals],
+        Sys::OBJECT_WAIT_ONE => &[Handle, Signals, Deadline, Hex],
+        Sys::THREAD_CREATE => &[Handle, Str, Hex, Hex],
+        Sys::THREAD_START => &[Handle, Hex, Hex, Hex, Hex],
+        Sys::PROCESS_CREATE => &[Handle, Str, Hex, Hex, Hex],
+        Sys::PROCESS_START => &[Handle, Handle, Hex, Hex, Handle, Hex],
+        Sys::PROCESS_EXIT => &[Int],
+        Sys::TASK_KILL => &[Handle],
+        Sys::CHANNEL_CREATE => &[Hex, Hex, Hex],
+        Sys::CHANNEL_READ | Sys::CHANNEL_READ_ETC => &[Handle, Hex, Hex, Hex, Int, Int, Hex, Hex],
+        Sys::CHANNEL_WRITE | Sys::CHANNEL_WRITE_ETC => &[Handle, Hex, Hex, Int, Hex, Int],
+        Sys::VMO_CREATE => &[Int, Hex, Hex],
+        Sys::VMO_READ | Sys::VMO_WRITE => &[Handle, Hex, Hex, Int],
+        Sys::VMAR_MAP => &[Handle, Hex, Hex, Handle, Hex, Int, Hex],
+        Sys::VMAR_UNMAP => &[Handle, Hex, Int],
+        Sys::VMAR_PROTECT => &[Handle, Hex, Hex, Int],
+        Sys::NANOSLEEP => &[Deadline],
+        Sys::DEBUG_WRITE => &[Str],
+        Sys::DEBUGLOG_WRITE => &[Handle, Hex, Str],
+        _ => &[],
+    }
+}
+
+/// Decode the arguments of the system call `sys_type`.
+pub(crate) fn decode_args(sys_type: &Sys, args: &[usize; 8]) -> String {
+    let signature = signature(sys_type);
+    if signature.is_empty() {
+        let args: Vec<String> = args.iter().map(|arg| format!("{:#x}", arg)).collect();
+        return args.join(", ");
+    }
+    let mut args = args.iter().copied();
+    let mut decoded = Vec::new();
+    for arg in signature {
+        let value = args.next().unwrap_or_default();
+        decoded.push(match arg {
+            Arg::Hex | Arg::Handle => format!("{:#x}", value),
+            Arg::Int => format!("{}", value as isize),
+            Arg::Rights => format!("{:?}", Rights::from_bits_truncate(value as u32)),
+            Arg::Signals => format!("{:?}", Signal::from_bits_truncate(value as u32)),
+            Arg::Deadline if value as i64 == i64::max_value() => String::from("INFINITE"),
+            Arg::Deadline => format!("{}", value as i64),
+            Arg::Str => {
+                let len = args.next().unwrap_or_default();
+                string_arg(value, len)
+            }
+        });
+    }
+    decoded.join(", ")
+}
+
+impl Syscall<'_> {
+    /// Write the trace of a call of `sys_type` with the decoded `args`.
+    pub(super) fn trace_result(&self, sys_type: &Sys, args: &str, ret: &ZxResult) {
+        let status = match ret {
+            Ok(()) => String::from("ZX_OK"),
+            Err(err) => format!("ZX_ERR_{:?}", err),
+        };
+        let proc = self.thread.proc();
+        let name = format!("{:?}", sys_type).to_lowercase();
+        let line = format!(
+            "[{}:{}] zx_{}({}) = {}",
+            proc.id(),
+            self.thread.id(),
+            name,
+            args,
+            status
+        );
+        write_kernel_log(Severity::Info, self.thread.id(), proc.id(), &line);
+    }
+}
+
+/// Show the string of `len` bytes at `ptr`, quoted and cut to `MAX_STR_LEN`.
+fn string_arg(ptr: usize, len: usize) -> String {
+    match UserInPtr::<u8>::from(ptr).read_string(len.min(MAX_STR_LEN)) {
+        Ok(s) if len > MAX_STR_LEN => format!("{:?}...", s),
+        Ok(s) => format!("{:?}", s),
+        Err(_) => format!("{:#x}", ptr),
+    }
+}